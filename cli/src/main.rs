@@ -0,0 +1,440 @@
+// agent-hub: drive a running Agent Hub from the terminal.
+//
+// Requests go to the app's local API socket (<data dir>/agent-hub/agent-hub.sock,
+// or agent-hub-dev with --dev for a debug build), which needs no token. With
+// AGENT_HUB_URL set (http://host:port) they go over HTTP instead, using
+// AGENT_HUB_TOKEN, a paired device's token.
+
+use futures_util::StreamExt;
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use hyper::{Method, Request};
+use serde_json::{json, Value};
+use std::io::{Read, Write};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_tungstenite::tungstenite::Message;
+
+const USAGE: &str = "\
+Usage: agent-hub [--dev] <command>
+
+Commands:
+  list                          Sessions, with their state
+  send <session> [prompt]       Send a prompt (read from stdin when omitted)
+  tail <session> [--raw]        Follow a session's output until it stops
+  create [--dir DIR] [--agent TYPE] [--name NAME] [--prompt TEXT]
+                                Create and start a session (default: this directory)
+
+<session> is a session id, a unique id prefix, or a session name.
+
+Environment:
+  AGENT_HUB_SOCKET              Local API socket to use instead of the default
+  AGENT_HUB_URL                 http://host:port of a hub to use instead of the socket
+  AGENT_HUB_TOKEN               Device token for AGENT_HUB_URL
+";
+
+/// Stream types requests can run over
+trait Io: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Io for T {}
+
+enum Hub {
+    #[cfg(unix)]
+    Socket(std::path::PathBuf),
+    Http { addr: String, token: Option<String> },
+}
+
+impl Hub {
+    fn from_env(dev: bool) -> Result<Hub, String> {
+        if let Ok(url) = std::env::var("AGENT_HUB_URL") {
+            let addr = url
+                .strip_prefix("http://")
+                .ok_or("AGENT_HUB_URL must be an http:// URL")?
+                .split('/')
+                .next()
+                .unwrap_or_default()
+                .to_string();
+            return Ok(Hub::Http { addr, token: std::env::var("AGENT_HUB_TOKEN").ok() });
+        }
+        #[cfg(unix)]
+        {
+            let path = match std::env::var_os("AGENT_HUB_SOCKET") {
+                Some(path) => std::path::PathBuf::from(path),
+                None => dirs::data_dir()
+                    .ok_or("No data directory to find the Agent Hub socket in")?
+                    .join(if dev { "agent-hub-dev" } else { "agent-hub" })
+                    .join("agent-hub.sock"),
+            };
+            Ok(Hub::Socket(path))
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = dev;
+            Err("Set AGENT_HUB_URL and AGENT_HUB_TOKEN to reach Agent Hub".to_string())
+        }
+    }
+
+    async fn connect(&self) -> Result<Box<dyn Io>, String> {
+        match self {
+            #[cfg(unix)]
+            Hub::Socket(path) => tokio::net::UnixStream::connect(path)
+                .await
+                .map(|s| Box::new(s) as Box<dyn Io>)
+                .map_err(|e| format!("Can't reach Agent Hub at {} ({}); is the app running?", path.display(), e)),
+            Hub::Http { addr, .. } => tokio::net::TcpStream::connect(addr.as_str())
+                .await
+                .map(|s| Box::new(s) as Box<dyn Io>)
+                .map_err(|e| format!("Can't reach Agent Hub at {}: {}", addr, e)),
+        }
+    }
+
+    fn host(&self) -> &str {
+        match self {
+            #[cfg(unix)]
+            Hub::Socket(_) => "localhost",
+            Hub::Http { addr, .. } => addr,
+        }
+    }
+
+    fn token(&self) -> Option<&str> {
+        match self {
+            #[cfg(unix)]
+            Hub::Socket(_) => None,
+            Hub::Http { token, .. } => token.as_deref(),
+        }
+    }
+
+    async fn request(&self, method: Method, path: &str, body: Option<Value>) -> Result<Value, String> {
+        let io = hyper_util::rt::TokioIo::new(self.connect().await?);
+        let (mut sender, conn) = hyper::client::conn::http1::handshake(io).await.map_err(|e| e.to_string())?;
+        tokio::spawn(conn);
+
+        let mut req = Request::builder().method(method).uri(path).header("host", self.host());
+        if let Some(token) = self.token() {
+            req = req.header("authorization", format!("Bearer {}", token));
+        }
+        let req = match body {
+            Some(body) => req.header("content-type", "application/json").body(Full::new(Bytes::from(body.to_string()))),
+            None => req.body(Full::new(Bytes::new())),
+        }
+        .map_err(|e| e.to_string())?;
+
+        let resp = sender.send_request(req).await.map_err(|e| e.to_string())?;
+        let status = resp.status();
+        let bytes = resp.into_body().collect().await.map_err(|e| e.to_string())?.to_bytes();
+        let value = serde_json::from_slice(&bytes).unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&bytes).into_owned()));
+        if status.is_success() {
+            return Ok(value);
+        }
+        Err(match &value {
+            Value::String(text) if !text.is_empty() => text.clone(),
+            _ => value["error"].as_str().map(str::to_string).unwrap_or_else(|| status.to_string()),
+        })
+    }
+
+    async fn websocket(&self, path: &str) -> Result<tokio_tungstenite::WebSocketStream<Box<dyn Io>>, String> {
+        let io = self.connect().await?;
+        let mut url = format!("ws://{}{}", self.host(), path);
+        if let Some(token) = self.token() {
+            url.push_str(&format!("?token={}", token));
+        }
+        let (ws, _) = tokio_tungstenite::client_async(url, io).await.map_err(|e| e.to_string())?;
+        Ok(ws)
+    }
+}
+
+// =====================================================================
+//  Arguments
+// =====================================================================
+
+fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+    let found = args.iter().position(|a| a == flag);
+    if let Some(i) = found {
+        args.remove(i);
+    }
+    found.is_some()
+}
+
+fn take_option(args: &mut Vec<String>, name: &str) -> Result<Option<String>, String> {
+    let Some(i) = args.iter().position(|a| a == name) else { return Ok(None) };
+    if i + 1 >= args.len() {
+        return Err(format!("{} needs a value", name));
+    }
+    let value = args.remove(i + 1);
+    args.remove(i);
+    Ok(Some(value))
+}
+
+/// The session `query` names: an exact id, then a name, then an id prefix
+fn find_session<'a>(sessions: &'a [Value], query: &str) -> Result<&'a Value, String> {
+    if let Some(session) = sessions.iter().find(|s| s["id"] == query) {
+        return Ok(session);
+    }
+    let by_name: Vec<&Value> = sessions
+        .iter()
+        .filter(|s| s["name"].as_str().is_some_and(|n| n.eq_ignore_ascii_case(query)))
+        .collect();
+    let by_prefix: Vec<&Value> = sessions
+        .iter()
+        .filter(|s| s["id"].as_str().is_some_and(|id| id.starts_with(query)))
+        .collect();
+    match (by_name.as_slice(), by_prefix.as_slice()) {
+        ([session], _) => Ok(session),
+        ([], [session]) => Ok(session),
+        ([], []) => Err(format!("No session matches '{}'", query)),
+        _ => Err(format!("'{}' matches more than one session; use its id", query)),
+    }
+}
+
+async fn resolve(hub: &Hub, query: &str) -> Result<Value, String> {
+    let sessions = hub.request(Method::GET, "/api/sessions", None).await?;
+    let sessions = sessions.as_array().cloned().unwrap_or_default();
+    find_session(&sessions, query).cloned()
+}
+
+// =====================================================================
+//  Commands
+// =====================================================================
+
+async fn list(hub: &Hub) -> Result<(), String> {
+    let sessions = hub.request(Method::GET, "/api/sessions", None).await?;
+    for s in sessions.as_array().into_iter().flatten() {
+        let state = if s["processing"] == true {
+            "working"
+        } else if s["running"] == true {
+            "running"
+        } else {
+            "stopped"
+        };
+        let id = s["id"].as_str().unwrap_or_default();
+        println!(
+            "{:<10} {:<8} {:<12} {:<28} {}",
+            &id[..id.len().min(8)],
+            state,
+            s["agent_type"].as_str().unwrap_or_default(),
+            s["name"].as_str().unwrap_or_default(),
+            s["working_dir"].as_str().unwrap_or_default(),
+        );
+    }
+    Ok(())
+}
+
+async fn send(hub: &Hub, query: &str, prompt: String) -> Result<(), String> {
+    let prompt = if prompt.is_empty() {
+        let mut text = String::new();
+        std::io::stdin().read_to_string(&mut text).map_err(|e| e.to_string())?;
+        text
+    } else {
+        prompt
+    };
+    if prompt.trim().is_empty() {
+        return Err("Nothing to send".to_string());
+    }
+    let session = resolve(hub, query).await?;
+    let id = session["id"].as_str().unwrap_or_default();
+    hub.request(Method::POST, &format!("/api/sessions/{}/prompt", id), Some(json!({ "text": prompt })))
+        .await?;
+    Ok(())
+}
+
+async fn create(hub: &Hub, mut args: Vec<String>) -> Result<(), String> {
+    let dir = take_option(&mut args, "--dir")?.unwrap_or_else(|| ".".to_string());
+    let agent = take_option(&mut args, "--agent")?;
+    let name = take_option(&mut args, "--name")?;
+    let prompt = take_option(&mut args, "--prompt")?;
+    if !args.is_empty() {
+        return Err(USAGE.to_string());
+    }
+    let dir = std::fs::canonicalize(&dir).map_err(|e| format!("{}: {}", dir, e))?;
+    let agent = match agent {
+        Some(agent) => agent,
+        None => hub.request(Method::GET, "/api/settings", None).await?["default_agent_type"]
+            .as_str()
+            .unwrap_or("claude-json")
+            .to_string(),
+    };
+    let name = name.or_else(|| dir.file_name().map(|n| n.to_string_lossy().into_owned()));
+
+    let session = hub
+        .request(
+            Method::POST,
+            "/api/sessions",
+            Some(json!({ "name": name, "agent_type": agent, "working_dir": dir.to_string_lossy() })),
+        )
+        .await?;
+    let id = session["id"].as_str().ok_or("Agent Hub didn't return the new session's id")?;
+    hub.request(Method::POST, &format!("/api/sessions/{}/start", id), None).await?;
+    if let Some(prompt) = prompt {
+        hub.request(Method::POST, &format!("/api/sessions/{}/prompt", id), Some(json!({ "text": prompt })))
+            .await?;
+    }
+    eprintln!("Started {} in {}", session["name"].as_str().unwrap_or(id), dir.display());
+    println!("{}", id);
+    Ok(())
+}
+
+/// Messages from the agent itself, as opposed to hub status events
+fn is_agent_message(value: &Value) -> bool {
+    matches!(value["type"].as_str(), Some("assistant" | "user" | "result" | "system"))
+}
+
+/// One line for a tool call's input: its most telling field
+fn summarize_input(input: &Value) -> String {
+    let text = ["command", "file_path", "path", "pattern", "url", "description"]
+        .iter()
+        .find_map(|key| input[key].as_str().map(str::to_string))
+        .unwrap_or_else(|| input.to_string());
+    let line = text.lines().next().unwrap_or_default();
+    match line.char_indices().nth(100) {
+        Some((i, _)) => format!("{}…", &line[..i]),
+        None => line.to_string(),
+    }
+}
+
+fn render(value: &Value) {
+    let content = &value["message"]["content"];
+    let blocks = content.as_array().cloned().unwrap_or_default();
+    match value["type"].as_str() {
+        Some("assistant") => {
+            for block in &blocks {
+                match block["type"].as_str() {
+                    Some("text") => println!("{}", block["text"].as_str().unwrap_or_default()),
+                    Some("tool_use") => {
+                        println!("→ {} {}", block["name"].as_str().unwrap_or_default(), summarize_input(&block["input"]))
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Some("user") => {
+            if let Some(text) = content.as_str() {
+                println!("> {}", text);
+            }
+            for block in blocks.iter().filter(|b| b["type"] == "text") {
+                println!("> {}", block["text"].as_str().unwrap_or_default());
+            }
+        }
+        Some("result") => {
+            let mut details = Vec::new();
+            if let Some(ms) = value["duration_ms"].as_f64() {
+                details.push(format!("{:.0}s", ms / 1000.0));
+            }
+            if let Some(cost) = value["total_cost_usd"].as_f64() {
+                details.push(format!("${:.2}", cost));
+            }
+            let outcome = if value["is_error"] == true { "failed" } else { "done" };
+            println!("— {} ({})\n", outcome, details.join(", "));
+        }
+        _ => {}
+    }
+}
+
+async fn tail(hub: &Hub, query: &str, raw: bool) -> Result<(), String> {
+    let session = resolve(hub, query).await?;
+    let id = session["id"].as_str().unwrap_or_default().to_string();
+    let mut ws = hub.websocket(&format!("/api/ws/{}", id)).await?;
+    let mut stdout = std::io::stdout();
+    while let Some(msg) = ws.next().await {
+        match msg.map_err(|e| e.to_string())? {
+            // Terminal output
+            Message::Binary(data) => {
+                let _ = stdout.write_all(&data);
+                let _ = stdout.flush();
+            }
+            Message::Text(text) => {
+                let Ok(value) = serde_json::from_str::<Value>(&text) else {
+                    return Err(text);
+                };
+                if value["type"] == "session_status" && value["data"]["session_id"] == id.as_str() && value["data"]["running"] == false {
+                    eprintln!("[session stopped]");
+                    break;
+                }
+                // We fell behind and the hub skipped ahead
+                if value["type"] == "resync" {
+                    if value["session_id"] == id.as_str() {
+                        eprintln!("[fell behind; some output was skipped]");
+                    }
+                    let sessions = value["sessions"].as_array().map(Vec::as_slice).unwrap_or_default();
+                    if sessions.iter().any(|s| s["id"] == id.as_str() && s["running"] == false) {
+                        eprintln!("[session stopped]");
+                        break;
+                    }
+                    continue;
+                }
+                if !is_agent_message(&value) {
+                    continue;
+                }
+                if raw {
+                    println!("{}", text);
+                } else {
+                    render(&value);
+                }
+            }
+            Message::Close(_) => break,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+async fn run(mut args: Vec<String>) -> Result<(), String> {
+    let dev = take_flag(&mut args, "--dev");
+    let hub = Hub::from_env(dev)?;
+    let command = if args.is_empty() { String::new() } else { args.remove(0) };
+    match command.as_str() {
+        "list" | "ls" => list(&hub).await,
+        "send" if !args.is_empty() => {
+            let query = args.remove(0);
+            send(&hub, &query, args.join(" ")).await
+        }
+        "tail" => {
+            let raw = take_flag(&mut args, "--raw");
+            match args.as_slice() {
+                [query] => tail(&hub, query, raw).await,
+                _ => Err(USAGE.to_string()),
+            }
+        }
+        "create" => create(&hub, args).await,
+        "" | "help" | "--help" | "-h" => {
+            print!("{}", USAGE);
+            Ok(())
+        }
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    if let Err(e) = run(std::env::args().skip(1).collect()).await {
+        eprintln!("{}", e.trim_end());
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sessions_are_found_by_id_name_or_prefix() {
+        let sessions = vec![
+            json!({ "id": "a1b2c3", "name": "Backend" }),
+            json!({ "id": "a1ffff", "name": "Docs" }),
+            json!({ "id": "d0c5", "name": "a1" }),
+        ];
+        assert_eq!(find_session(&sessions, "a1b2c3").unwrap()["name"], "Backend");
+        assert_eq!(find_session(&sessions, "backend").unwrap()["id"], "a1b2c3");
+        assert_eq!(find_session(&sessions, "a1f").unwrap()["name"], "Docs");
+        // A name beats an id prefix
+        assert_eq!(find_session(&sessions, "a1").unwrap()["id"], "d0c5");
+        assert!(find_session(&sessions, "zz").is_err());
+        assert!(find_session(&sessions, "").is_err());
+    }
+
+    #[test]
+    fn options_are_taken_with_their_values() {
+        let mut args: Vec<String> = ["--dir", "/tmp", "--raw", "x"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(take_option(&mut args, "--dir").unwrap().as_deref(), Some("/tmp"));
+        assert!(take_flag(&mut args, "--raw"));
+        assert_eq!(args, ["x"]);
+        assert!(take_option(&mut vec!["--name".to_string()], "--name").is_err());
+    }
+}
@@ -4,16 +4,41 @@
 // Run the app separately with `npx tauri dev`, then use this as the MCP server
 
 const http = require('http');
+const os = require('os');
+const { join } = require('path');
 const readline = require('readline');
 
 const AGENT_HUB_PORT = process.env.AGENT_HUB_PORT || 3857;
+// Set by Agent Hub for the agents it spawns; proves we're on the same machine
+const LOCAL_TOKEN_HEADER = process.env.AGENT_HUB_LOCAL_TOKEN
+  ? { 'X-Agent-Hub-Local': process.env.AGENT_HUB_LOCAL_TOKEN }
+  : {};
+
+// The app's unix socket in its data directory: agent-hub for the prod port
+// (3847), agent-hub-dev otherwise
+function defaultSocketPath() {
+  const home = os.homedir();
+  const dataDir = process.platform === 'darwin'
+    ? join(home, 'Library', 'Application Support')
+    : process.env.XDG_DATA_HOME || join(home, '.local', 'share');
+  const app = String(AGENT_HUB_PORT) === '3847' ? 'agent-hub' : 'agent-hub-dev';
+  return join(dataDir, app, 'agent-hub.sock');
+}
+
+// Without the local token (started from .mcp.json rather than by the app)
+// the bridge goes through the unix socket, which needs no token: only this
+// user can open it. AGENT_HUB_SOCKET overrides where it is; Windows has no
+// socket, so there the token is needed.
+const SOCKET_PATH = process.env.AGENT_HUB_SOCKET
+  || (process.env.AGENT_HUB_LOCAL_TOKEN || process.platform === 'win32' ? null : defaultSocketPath());
+const CONNECTION = SOCKET_PATH ? { socketPath: SOCKET_PATH } : { hostname: 'localhost', port: AGENT_HUB_PORT };
 const PROTOCOL_VERSION = '2024-11-05';
 
 // Tool definitions
 const TOOLS = [
   {
     name: 'take_screenshot',
-    description: 'Get information about the current window state including page content',
+    description: 'Capture the Agent Hub window as a PNG image, with the page\'s size, title and text',
     inputSchema: { type: 'object', properties: {}, required: [] }
   },
   {
@@ -105,6 +130,20 @@ const TOOLS = [
     description: 'Return search index statistics: schema version, indexed file count, total messages, last ingest timestamp.',
     inputSchema: { type: 'object', properties: {}, required: [] }
   },
+  {
+    name: 'spawn_child_session',
+    description: 'Create a new Claude session managed by Agent Hub as a child of the calling session, in another working directory, and optionally start it with a prompt. Use this to delegate work to a sibling agent; the child shows up under its parent in the session list.',
+    inputSchema: {
+      type: 'object',
+      properties: {
+        working_dir: { type: 'string', description: 'Directory the child session runs in (~ is expanded).' },
+        prompt: { type: 'string', description: 'First prompt to send. Omit to create the session without starting it.' },
+        name: { type: 'string', description: 'Session name (default: "<parent> → <dir name>").' },
+        parent_session_id: { type: 'string', description: 'Parent Agent Hub session id. Defaults to the calling session (AGENT_HUB_SESSION_ID).' }
+      },
+      required: ['working_dir']
+    }
+  },
   {
     name: 'rebuild_search_index',
     description: 'Wipe and rebuild the search index from on-disk Claude JSONL files. Slow operation; use only when the index is suspected stale.',
@@ -112,11 +151,17 @@ const TOOLS = [
   }
 ];
 
-// HTTP helper for /api/search/* endpoints.
-function httpJson(method, path) {
+// HTTP helper for the JSON endpoints (/api/search/*, /api/sessions/*).
+function httpJson(method, path, payload) {
   return new Promise((resolve, reject) => {
+    const data = payload === undefined ? null : JSON.stringify(payload);
+    const headers = data === null ? { ...LOCAL_TOKEN_HEADER } : {
+      ...LOCAL_TOKEN_HEADER,
+      'Content-Type': 'application/json',
+      'Content-Length': Buffer.byteLength(data)
+    };
     const req = http.request(
-      { hostname: 'localhost', port: AGENT_HUB_PORT, path, method, timeout: 30000 },
+      { ...CONNECTION, path, method, headers, timeout: 30000 },
       (res) => {
         let body = '';
         res.on('data', (c) => (body += c));
@@ -134,6 +179,9 @@ function httpJson(method, path) {
       req.destroy();
       reject(new Error('Request timeout'));
     });
+    if (data !== null) {
+      req.write(data);
+    }
     req.end();
   });
 }
@@ -144,11 +192,11 @@ function executeJs(code, timeoutMs = 5000) {
     const data = JSON.stringify({ code, timeout_ms: timeoutMs });
 
     const req = http.request({
-      hostname: 'localhost',
-      port: AGENT_HUB_PORT,
+      ...CONNECTION,
       path: '/api/mcp/execute',
       method: 'POST',
       headers: {
+        ...LOCAL_TOKEN_HEADER,
         'Content-Type': 'application/json',
         'Content-Length': Buffer.byteLength(data)
       },
@@ -184,6 +232,8 @@ function executeJs(code, timeoutMs = 5000) {
 // Tool implementations
 const toolHandlers = {
   async take_screenshot() {
+    const shot = await httpJson('POST', '/api/mcp/screenshot');
+    if (!shot.success) throw new Error(shot.error || 'Screenshot failed');
     // Use parentheses to make object literal an expression
     const js = `({
       width: window.innerWidth,
@@ -192,7 +242,13 @@ const toolHandlers = {
       url: window.location.href,
       bodyText: document.body.innerText.substring(0, 8000)
     })`;
-    return executeJs(js);
+    const details = await executeJs(js).catch(() => null);
+    const content = [{ type: 'image', data: shot.data, mimeType: shot.mime_type }];
+    if (details !== null) {
+      content.push({ type: 'text', text: typeof details === 'string' ? details : JSON.stringify(details, null, 2) });
+    }
+    // Already MCP content blocks; passed through as is
+    return { content };
   },
 
   async execute_js({ code }) {
@@ -318,6 +374,23 @@ const toolHandlers = {
     return httpJson('POST', '/api/search/rebuild');
   },
 
+  async spawn_child_session({ working_dir, prompt, name, parent_session_id } = {}) {
+    const parentId = parent_session_id || process.env.AGENT_HUB_SESSION_ID;
+    if (!parentId) {
+      throw new Error('parent_session_id is required when not running inside an Agent Hub session');
+    }
+    if (!working_dir) {
+      throw new Error('working_dir is required');
+    }
+    const result = await httpJson('POST', `/api/sessions/${encodeURIComponent(parentId)}/children`, {
+      working_dir, prompt, name
+    });
+    if (result.error) {
+      throw new Error(result.error);
+    }
+    return result;
+  },
+
   async list_elements() {
     // Wrap in IIFE - return inside IIFE is valid
     const js = `(function() {
@@ -417,7 +490,9 @@ async function handleRequest(request) {
             jsonrpc: '2.0',
             id,
             result: {
-              content: [{ type: 'text', text: typeof result === 'string' ? result : JSON.stringify(result, null, 2) }],
+              content: Array.isArray(result?.content)
+                ? result.content
+                : [{ type: 'text', text: typeof result === 'string' ? result : JSON.stringify(result, null, 2) }],
               isError: false
             }
           };
@@ -0,0 +1,35 @@
+// Service worker: shows push notifications from the desktop (see push.rs)
+// and opens the session when one is tapped.
+
+self.addEventListener('push', (event) => {
+  let data = {};
+  try {
+    data = event.data ? event.data.json() : {};
+  } catch {
+    data = { title: 'Agent Hub', body: event.data ? event.data.text() : '' };
+  }
+  event.waitUntil(
+    self.registration.showNotification(data.title || 'Agent Hub', {
+      body: data.body || '',
+      // One notification per session; a newer one replaces it
+      tag: data.sessionId,
+      data: { sessionId: data.sessionId },
+    }),
+  );
+});
+
+self.addEventListener('notificationclick', (event) => {
+  event.notification.close();
+  const sessionId = event.notification.data && event.notification.data.sessionId;
+  const url = sessionId ? `/#/session/${sessionId}` : '/';
+  event.waitUntil(
+    self.clients.matchAll({ type: 'window', includeUncontrolled: true }).then((windows) => {
+      const open = windows[0];
+      if (open) {
+        open.postMessage({ type: 'open_session', sessionId });
+        return open.focus();
+      }
+      return self.clients.openWindow(url);
+    }),
+  );
+});
@@ -1,6 +1,8 @@
 import { useEffect } from 'react';
 import { useAuthStore, useGlobalStore } from './stores';
 import { websocketService } from './services/websocket';
+import { connectHubs, disconnectHubs, reconnectHubs } from './services/hubs';
+import { registerServiceWorker } from './services/push';
 
 // Views
 import { AuthFlow } from './components/auth/AuthFlow';
@@ -32,9 +34,11 @@ export default function App() {
   useEffect(() => {
     if (isAuthenticated) {
       websocketService.connect();
+      connectHubs();
     }
     return () => {
       websocketService.disconnect();
+      disconnectHubs();
     };
   }, [isAuthenticated]);
 
@@ -46,6 +50,7 @@ export default function App() {
       if (document.visibilityState === 'visible') {
         console.log('Tab visible, reconnecting WebSocket');
         websocketService.reconnect();
+        reconnectHubs();
       }
     };
 
@@ -71,6 +76,19 @@ export default function App() {
     }
   }, [isAuthenticated, activeSessionId]);
 
+  // Tapping a push notification while the app is open (see public/sw.js)
+  useEffect(() => {
+    if (!isAuthenticated) return;
+    registerServiceWorker();
+    const handleMessage = (event: MessageEvent) => {
+      if (event.data?.type === 'open_session' && event.data.sessionId) {
+        setActiveSession(event.data.sessionId);
+      }
+    };
+    navigator.serviceWorker?.addEventListener('message', handleMessage);
+    return () => navigator.serviceWorker?.removeEventListener('message', handleMessage);
+  }, [isAuthenticated, setActiveSession]);
+
   // Handle browser back/forward
   useEffect(() => {
     const handleHashChange = () => {
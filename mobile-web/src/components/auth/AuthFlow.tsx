@@ -1,4 +1,4 @@
-import { useState } from 'react';
+import { useEffect, useState } from 'react';
 import { useAuthStore } from '../../stores';
 import { api } from '../../services/api';
 
@@ -15,6 +15,23 @@ export function AuthFlow() {
 
   const { setServerUrl: saveServerUrl, setAuthToken, setPinEnabled } = useAuthStore();
 
+  // Opened from the desktop's pairing QR code: ?pair=<id>&code=<code>
+  useEffect(() => {
+    const params = new URLSearchParams(window.location.search);
+    const qrPairingId = params.get('pair');
+    const qrCode = params.get('code');
+    if (!qrPairingId || !qrCode) return;
+
+    window.history.replaceState(null, '', window.location.pathname);
+    saveServerUrl(window.location.origin);
+    setIsLoading(true);
+    api
+      .completePairing(qrPairingId, qrCode, 'Mobile Web')
+      .then(({ token, expires_at }) => setAuthToken(token, expires_at))
+      .catch((err) => setError(err instanceof Error ? err.message : 'Pairing failed'))
+      .finally(() => setIsLoading(false));
+  }, [saveServerUrl, setAuthToken]);
+
   const handleConnect = async () => {
     if (!serverUrl.trim()) return;
 
@@ -51,8 +68,8 @@ export function AuthFlow() {
     setError(null);
 
     try {
-      const { token } = await api.loginWithPin(pin, 'Mobile Web');
-      setAuthToken(token);
+      const { token, expires_at } = await api.loginWithPin(pin, 'Mobile Web');
+      setAuthToken(token, expires_at);
     } catch (err) {
       setError(err instanceof Error ? err.message : 'Invalid PIN');
     } finally {
@@ -67,8 +84,8 @@ export function AuthFlow() {
     setError(null);
 
     try {
-      const { token } = await api.completePairing(pairingId, pairingCode, 'Mobile Web');
-      setAuthToken(token);
+      const { token, expires_at } = await api.completePairing(pairingId, pairingCode, 'Mobile Web');
+      setAuthToken(token, expires_at);
     } catch (err) {
       setError(err instanceof Error ? err.message : 'Pairing failed');
     } finally {
@@ -174,7 +191,7 @@ export function AuthFlow() {
               disabled={isLoading || !pairingCode.trim()}
               className="w-full py-3 px-4 bg-[#0e639c] text-white rounded-lg font-medium disabled:opacity-50"
             >
-              {isLoading ? 'Pairing...' : 'Pair Device'}
+              {isLoading ? 'Waiting for approval on desktop...' : 'Pair Device'}
             </button>
 
             <button
@@ -0,0 +1,78 @@
+import { useState } from 'react';
+import { api } from '../../services/api';
+import { websocketService } from '../../services/websocket';
+import type { AgentAsk } from '../../types';
+
+interface AskCardProps {
+  ask: AgentAsk;
+  readOnly: boolean;
+}
+
+// A question or approval request from the agent; it goes away when the
+// desktop reports it settled (answered here, elsewhere, or given up on)
+export function AskCard({ ask, readOnly }: AskCardProps) {
+  const [text, setText] = useState('');
+  const [sending, setSending] = useState(false);
+  const [error, setError] = useState<string | null>(null);
+
+  const answer = async (body: { text?: string; approved?: boolean }) => {
+    setSending(true);
+    setError(null);
+    try {
+      if (!websocketService.answerQuestion(ask.id, body)) {
+        await api.answerAsk(ask.id, body);
+      }
+    } catch (e) {
+      setError(e instanceof Error ? e.message : String(e));
+      setSending(false);
+    }
+  };
+
+  const buttonClass = 'px-3 py-1.5 rounded-lg text-sm bg-[#2a2a2a] border border-[#3c3c3c] text-white disabled:opacity-50';
+
+  return (
+    <div className="mx-3 my-2 p-3 rounded-lg bg-[#222] border-l-4 border-[#0e9fd8]">
+      <div className="text-xs text-gray-400 mb-1">
+        {ask.kind === 'approval' ? 'Approval requested' : 'Question'}
+      </div>
+      <div className="text-white text-sm whitespace-pre-wrap mb-2">{ask.prompt}</div>
+      {!readOnly && ask.kind === 'approval' && (
+        <div className="flex gap-2">
+          <button className={buttonClass} disabled={sending} onClick={() => answer({ approved: true })}>
+            Approve
+          </button>
+          <button className={buttonClass} disabled={sending} onClick={() => answer({ approved: false })}>
+            Deny
+          </button>
+        </div>
+      )}
+      {!readOnly && ask.kind === 'question' && (
+        <div className="flex flex-wrap gap-2">
+          {ask.options.map((option) => (
+            <button key={option} className={buttonClass} disabled={sending} onClick={() => answer({ text: option })}>
+              {option}
+            </button>
+          ))}
+          <form
+            className="flex flex-1 gap-2 min-w-[200px]"
+            onSubmit={(e) => {
+              e.preventDefault();
+              if (text.trim()) answer({ text: text.trim() });
+            }}
+          >
+            <input
+              value={text}
+              onChange={(e) => setText(e.target.value)}
+              placeholder="Answer..."
+              className="flex-1 px-3 py-1.5 rounded-lg bg-[#1a1a1a] border border-[#3c3c3c] text-white text-sm"
+            />
+            <button type="submit" className={buttonClass} disabled={sending || !text.trim()}>
+              Send
+            </button>
+          </form>
+        </div>
+      )}
+      {error && <div className="text-xs text-red-400 mt-2">{error}</div>}
+    </div>
+  );
+}
@@ -1,7 +1,7 @@
-import { useRef, useEffect, useCallback } from 'react';
-import { useSessionStore, useGlobalStore } from '../../stores';
+import { useRef, useEffect, useCallback, useState } from 'react';
+import { useSessionStore, useGlobalStore, useAuthStore } from '../../stores';
 import { api } from '../../services/api';
-import { websocketService } from '../../services/websocket';
+import { socketFor } from '../../services/hubs';
 import type { Message } from '../../types';
 
 interface ChatInputProps {
@@ -10,9 +10,14 @@ interface ChatInputProps {
 
 export function ChatInput({ sessionId }: ChatInputProps) {
   const textareaRef = useRef<HTMLTextAreaElement>(null);
+  const recorderRef = useRef<MediaRecorder | null>(null);
+  const photoInputRef = useRef<HTMLInputElement>(null);
+  const [uploading, setUploading] = useState(false);
+  const [voiceState, setVoiceState] = useState<'idle' | 'recording' | 'transcribing'>('idle');
 
   const { inputText, pendingImages, setInputText, clearPendingImages, removePendingImage, addPendingImage, addMessage } = useSessionStore();
   const { sessionStatus, updateSessionStatus } = useGlobalStore();
+  const readOnly = useAuthStore((s) => s.scope === 'read_only');
 
   const text = inputText.get(sessionId) || '';
   const images = pendingImages.get(sessionId) || [];
@@ -79,15 +84,73 @@ export function ChatInput({ sessionId }: ChatInputProps) {
     }
 
     // Send via WebSocket
-    websocketService.sendMessage(sessionId, content);
+    socketFor(sessionId).sendMessage(sessionId, content);
     updateSessionStatus(sessionId, { isProcessing: true });
   }, [sessionId, text, images, hasContent, sessionStatus, setInputText, clearPendingImages, updateSessionStatus, addMessage]);
 
   const handleInterrupt = useCallback(() => {
-    websocketService.interrupt(sessionId);
+    socketFor(sessionId).interrupt(sessionId);
     updateSessionStatus(sessionId, { isProcessing: false });
   }, [sessionId, updateSessionStatus]);
 
+  // Voice prompt: record until tapped again, then the desktop transcribes
+  // it and sends the text (echoed back as a chat_message)
+  const handleVoice = useCallback(async () => {
+    if (voiceState === 'recording') {
+      recorderRef.current?.stop();
+      return;
+    }
+    if (voiceState !== 'idle') return;
+
+    let stream: MediaStream;
+    try {
+      stream = await navigator.mediaDevices.getUserMedia({ audio: true });
+    } catch (err) {
+      console.error('Microphone unavailable:', err);
+      return;
+    }
+    const recorder = new MediaRecorder(stream);
+    const chunks: Blob[] = [];
+    recorder.ondataavailable = (e) => {
+      if (e.data.size > 0) chunks.push(e.data);
+    };
+    recorder.onstop = async () => {
+      stream.getTracks().forEach((t) => t.stop());
+      recorderRef.current = null;
+      setVoiceState('transcribing');
+      try {
+        await api.sendAudio(sessionId, new Blob(chunks, { type: recorder.mimeType }));
+        updateSessionStatus(sessionId, { running: true, isProcessing: true });
+      } catch (err) {
+        addMessage(sessionId, { type: 'result', subtype: 'error', is_error: true, result: `Voice prompt failed: ${(err as Error).message}` });
+      } finally {
+        setVoiceState('idle');
+      }
+    };
+    recorderRef.current = recorder;
+    recorder.start();
+    setVoiceState('recording');
+  }, [sessionId, voiceState, updateSessionStatus, addMessage]);
+
+  // Photo: uploaded to the desktop with the typed text as its caption; the
+  // sent message comes back as a chat_message
+  const handlePhoto = useCallback(async (e: React.ChangeEvent<HTMLInputElement>) => {
+    const file = e.target.files?.[0];
+    e.target.value = '';
+    if (!file) return;
+
+    setUploading(true);
+    try {
+      await api.uploadAttachment(sessionId, file, text.trim() || undefined);
+      setInputText(sessionId, '');
+      updateSessionStatus(sessionId, { running: true, isProcessing: true });
+    } catch (err) {
+      addMessage(sessionId, { type: 'result', subtype: 'error', is_error: true, result: `Photo upload failed: ${(err as Error).message}` });
+    } finally {
+      setUploading(false);
+    }
+  }, [sessionId, text, setInputText, updateSessionStatus, addMessage]);
+
   const handlePaste = useCallback((e: React.ClipboardEvent) => {
     const items = e.clipboardData?.items;
     if (!items) return;
@@ -123,6 +186,14 @@ export function ChatInput({ sessionId }: ChatInputProps) {
     }
   };
 
+  if (readOnly) {
+    return (
+      <div className="border-t border-[#3c3c3c] bg-[#252526] pb-[env(safe-area-inset-bottom)] p-3 text-center text-sm text-gray-400">
+        This device can watch sessions but not send input.
+      </div>
+    );
+  }
+
   return (
     <div className="border-t border-[#3c3c3c] bg-[#252526] pb-[env(safe-area-inset-bottom)]">
       {/* Image previews */}
@@ -159,6 +230,34 @@ export function ChatInput({ sessionId }: ChatInputProps) {
           className="flex-1 px-4 py-2.5 bg-[#1a1a1a] border border-[#3c3c3c] rounded-full text-white resize-none min-h-[40px] max-h-[120px] focus:outline-none focus:border-[#0e9fd8]"
         />
 
+        <input
+          ref={photoInputRef}
+          type="file"
+          accept="image/*"
+          capture="environment"
+          onChange={handlePhoto}
+          className="hidden"
+        />
+        <button
+          onClick={() => photoInputRef.current?.click()}
+          disabled={uploading}
+          aria-label="Send photo"
+          className="w-10 h-10 rounded-full bg-[#3c3c3c] text-white flex-shrink-0 disabled:opacity-50"
+        >
+          {uploading ? '…' : '📷'}
+        </button>
+
+        <button
+          onClick={handleVoice}
+          disabled={voiceState === 'transcribing'}
+          aria-label={voiceState === 'recording' ? 'Stop recording' : 'Record voice prompt'}
+          className={`w-10 h-10 rounded-full text-white flex-shrink-0 disabled:opacity-50 ${
+            voiceState === 'recording' ? 'bg-red-500 animate-pulse' : 'bg-[#3c3c3c]'
+          }`}
+        >
+          {voiceState === 'transcribing' ? '…' : '🎤'}
+        </button>
+
         {isProcessing && (
           <button
             onClick={handleInterrupt}
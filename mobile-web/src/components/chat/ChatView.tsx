@@ -1,8 +1,11 @@
 import { useEffect } from 'react';
-import { useGlobalStore, useSessionStore } from '../../stores';
+import { useAuthStore, useGlobalStore, useSessionStore } from '../../stores';
+import { api } from '../../services/api';
+import { socketFor } from '../../services/hubs';
 import { websocketService } from '../../services/websocket';
 import { MessageList } from './MessageList';
 import { ChatInput } from './ChatInput';
+import { AskCard } from './AskCard';
 
 interface ChatViewProps {
   sessionId: string;
@@ -12,27 +15,52 @@ export function ChatView({ sessionId }: ChatViewProps) {
   const { sessions, sessionStatus, setActiveSession, isConnected } = useGlobalStore();
   const cameFromSearch = useGlobalStore((s) => s.cameFromSearch);
   const lastSearchQuery = useGlobalStore((s) => s.lastSearchQuery);
+  const notificationPrefs = useGlobalStore((s) => s.notificationPrefs);
   const triggerBackToSearch = useGlobalStore((s) => s.triggerBackToSearch);
   const { messages } = useSessionStore();
+  const asks = useGlobalStore((s) => s.asks.get(sessionId)) ?? [];
+  const setAsks = useGlobalStore((s) => s.setAsks);
+  const readOnly = useAuthStore((s) => s.scope === 'read_only');
 
   const session = sessions.get(sessionId);
   const status = sessionStatus.get(sessionId);
+  // Less this device, which is subscribed while the view is open
+  const otherViewers = Math.max((status?.viewerCount ?? 0) - 1, 0);
   const sessionMessages = messages.get(sessionId) || [];
 
   // Subscribe to session on mount and on reconnect, unsubscribe on unmount
   useEffect(() => {
     if (isConnected) {
-      websocketService.subscribe(sessionId);
+      socketFor(sessionId).subscribe(sessionId);
     }
     return () => {
-      websocketService.unsubscribe(sessionId);
+      socketFor(sessionId).unsubscribe(sessionId);
     };
   }, [sessionId, isConnected]);
 
+  // Asks raised before we connected; later ones arrive as session_ask. Only
+  // the desktop's own sessions can be answered from here.
+  const isHubSession = !!session?.hub_id;
+  useEffect(() => {
+    if (!isConnected || isHubSession) return;
+    api.getAsks(sessionId).then((pending) => setAsks(sessionId, pending)).catch(() => {});
+  }, [sessionId, isConnected, isHubSession, setAsks]);
+
   const handleBack = () => {
     setActiveSession(null);
   };
 
+  // Hub sessions notify through their own hub, so only the desktop's can be muted here
+  const muted = notificationPrefs?.muted_sessions.includes(sessionId) ?? false;
+  const handleToggleMute = () => {
+    if (!notificationPrefs) return;
+    const others = notificationPrefs.muted_sessions.filter((id) => id !== sessionId);
+    websocketService.setNotificationPrefs({
+      ...notificationPrefs,
+      muted_sessions: muted ? others : [...others, sessionId],
+    });
+  };
+
   if (!session) {
     return (
       <div className="h-full flex items-center justify-center text-gray-500">
@@ -53,10 +81,25 @@ export function ChatView({ sessionId }: ChatViewProps) {
         </button>
         <div className="flex-1 min-w-0">
           <div className="text-white font-medium truncate">{session.name}</div>
+          {otherViewers > 0 && (
+            <div className="text-xs text-gray-400 truncate">
+              Also open on {otherViewers === 1 ? '1 other device' : `${otherViewers} other devices`}
+            </div>
+          )}
         </div>
         {status?.isProcessing && (
           <div className="w-2.5 h-2.5 rounded-full bg-blue-500 animate-pulse" />
         )}
+        {notificationPrefs && !session.hub_id && (
+          <button
+            onClick={handleToggleMute}
+            className={`p-1 ${muted ? 'text-gray-500' : 'text-[#0e9fd8]'}`}
+            title={muted ? 'Notify me about this session' : "Don't notify me about this session"}
+            aria-label={muted ? 'Unmute notifications' : 'Mute notifications'}
+          >
+            {muted ? '🔕' : '🔔'}
+          </button>
+        )}
       </div>
 
       {/* Back-to-search pill: visible when the user landed here via a
@@ -78,6 +121,8 @@ export function ChatView({ sessionId }: ChatViewProps) {
         <MessageList sessionId={sessionId} messages={sessionMessages} />
       </div>
 
+      {!isHubSession && asks.map((ask) => <AskCard key={ask.id} ask={ask} readOnly={readOnly} />)}
+
       {/* Input */}
       <ChatInput sessionId={sessionId} />
     </div>
@@ -13,6 +13,7 @@ export function MessageList({ sessionId, messages }: MessageListProps) {
   const virtuosoRef = useRef<VirtuosoHandle>(null);
   const { getScrollPosition, setScrollPosition } = useSessionStore();
   const sessionStatus = useGlobalStore((s) => s.sessionStatus.get(sessionId));
+  const streamingText = useSessionStore((s) => s.streamingText.get(sessionId));
   const pendingScrollTargetUuid = useGlobalStore((s) => s.pendingScrollTargetUuid);
   const setPendingScrollTarget = useGlobalStore((s) => s.setPendingScrollTarget);
 
@@ -88,13 +89,16 @@ export function MessageList({ sessionId, messages }: MessageListProps) {
           Footer: () =>
             isProcessing ? (
               <div className="px-4 py-2">
+                {streamingText && (
+                  <div className="mb-2 text-gray-200 text-sm whitespace-pre-wrap break-words">{streamingText}</div>
+                )}
                 <div className="flex items-center gap-2 text-gray-400 text-sm">
                   <div className="flex gap-1">
                     <div className="w-2 h-2 bg-blue-500 rounded-full animate-bounce" style={{ animationDelay: '0ms' }} />
                     <div className="w-2 h-2 bg-blue-500 rounded-full animate-bounce" style={{ animationDelay: '150ms' }} />
                     <div className="w-2 h-2 bg-blue-500 rounded-full animate-bounce" style={{ animationDelay: '300ms' }} />
                   </div>
-                  <span>Thinking...</span>
+                  <span>{streamingText ? 'Typing...' : 'Thinking...'}</span>
                 </div>
               </div>
             ) : null,
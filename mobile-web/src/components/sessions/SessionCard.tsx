@@ -11,6 +11,7 @@ export function SessionCard({ session }: SessionCardProps) {
 
   const isRunning = status?.running ?? false;
   const isProcessing = status?.isProcessing ?? false;
+  const viewerNames = [...new Set((status?.viewers ?? []).map((v) => v.device_name ?? 'another device'))];
 
   return (
     <button
@@ -31,9 +32,16 @@ export function SessionCard({ session }: SessionCardProps) {
       {/* Session info */}
       <div className="flex-1 min-w-0">
         <div className="text-white font-medium truncate">{session.name}</div>
+        {session.summary && (
+          <div className="text-sm text-gray-400 line-clamp-2">{session.summary}</div>
+        )}
         <div className="text-sm text-gray-500 truncate">
+          {session.host && <>{session.host} • </>}
           {session.agent_type} • {session.working_dir.replace(/^~\//, '')}
         </div>
+        {viewerNames.length > 0 && (
+          <div className="text-xs text-gray-500 truncate">Open on {viewerNames.join(', ')}</div>
+        )}
       </div>
 
       {/* Chevron */}
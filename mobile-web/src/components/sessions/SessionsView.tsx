@@ -1,7 +1,9 @@
 import { useEffect, useState } from 'react';
 import { useGlobalStore, useAuthStore } from '../../stores';
 import { websocketService } from '../../services/websocket';
+import { connectHubs, disconnectHubs } from '../../services/hubs';
 import { api } from '../../services/api';
+import { disablePush, enablePush, pushEnabled, pushSupported } from '../../services/push';
 import { SessionCard } from './SessionCard';
 import { SearchPanel } from './SearchPanel';
 
@@ -25,6 +27,26 @@ export function SessionsView() {
       setShowSearch(true);
     }
   }, [pendingSearchOpen, consumePendingSearchOpen]);
+  const [notifications, setNotifications] = useState(false);
+
+  useEffect(() => {
+    pushEnabled().then(setNotifications).catch(() => setNotifications(false));
+  }, []);
+
+  const handleToggleNotifications = async () => {
+    try {
+      if (notifications) {
+        await disablePush();
+        setNotifications(false);
+      } else {
+        await enablePush();
+        setNotifications(true);
+      }
+    } catch (err) {
+      alert('Notifications: ' + (err as Error).message);
+    }
+  };
+
   const [newSessionName, setNewSessionName] = useState('');
   const [newSessionDir, setNewSessionDir] = useState('~/dev/');
   const [isCreating, setIsCreating] = useState(false);
@@ -33,10 +55,12 @@ export function SessionsView() {
     // Reconnect WebSocket to get fresh data
     websocketService.disconnect();
     websocketService.connect();
+    connectHubs();
   };
 
   const handleLogout = () => {
     websocketService.disconnect();
+    disconnectHubs();
     logout();
   };
 
@@ -46,7 +70,10 @@ export function SessionsView() {
     setIsCreating(true);
     try {
       const name = newSessionName.trim() || newSessionDir.split('/').filter(Boolean).pop() || 'New Session';
-      const session = await api.createSession(name, newSessionDir.trim() || '~/');
+      const workingDir = newSessionDir.trim() || '~/';
+      const session = websocketService.supportsSessionActions()
+        ? await websocketService.createSession(name, workingDir)
+        : await api.createSession(name, workingDir);
       addSession(session);
       setShowNewSessionModal(false);
       setNewSessionName('');
@@ -93,6 +120,16 @@ export function SessionsView() {
           >
             +
           </button>
+          {pushSupported() && (
+            <button
+              onClick={handleToggleNotifications}
+              className={`p-2 hover:text-white ${notifications ? 'text-[#0e9fd8]' : 'text-gray-400'}`}
+              title={notifications ? 'Turn off notifications' : 'Notify me when a session finishes'}
+              aria-label="Notifications"
+            >
+              🔔
+            </button>
+          )}
           <button
             onClick={handleRefresh}
             className="p-2 text-gray-400 hover:text-white"
@@ -187,8 +224,8 @@ export function SessionsView() {
               );
             })()}
             {(() => {
-              // Group sessions by folder_id for display
-              const hasFolders = sessionsOrder.some((id) => sessions.get(id)?.folder_id);
+              // Group sessions by folder_id for display, and remote hubs' by hub
+              const hasFolders = sessionsOrder.some((id) => sessions.get(id)?.folder_id || sessions.get(id)?.hub_id);
               if (!hasFolders) {
                 // No folders — flat list
                 return sessionsOrder.map((id) => {
@@ -205,7 +242,12 @@ export function SessionsView() {
               for (const id of sessionsOrder) {
                 const session = sessions.get(id);
                 if (!session) continue;
-                if (!session.folder_id) {
+                if (session.hub_id) {
+                  const key = `hub:${session.hub_id}`;
+                  const group = folderGroups.get(key) || { name: session.host || 'Remote hub', ids: [] };
+                  group.ids.push(id);
+                  folderGroups.set(key, group);
+                } else if (!session.folder_id) {
                   unfiled.push(id);
                 } else {
                   const folder = folders.get(session.folder_id);
@@ -1,5 +1,12 @@
 import { useAuthStore } from '../stores/authStore';
-import type { Session } from '../types';
+import { useGlobalStore } from '../stores/globalStore';
+import type { Session, Folder, RemoteSettings, DiscoveredInstance, PairedDevice, DeviceScope, AuditEntry, PushSubscriptionInfo, RemoteHub, AgentAsk } from '../types';
+
+interface AuthTokenResponse {
+  token: string;
+  device_id: string;
+  expires_at?: string | null;
+}
 
 class ApiService {
   private getHeaders(): HeadersInit {
@@ -24,12 +31,19 @@ class ApiService {
 
     if (!response.ok) {
       const error = await response.json().catch(() => ({ message: response.statusText }));
-      throw new Error(error.message || `Request failed: ${response.status}`);
+      throw new Error(error.message || error.error || `Request failed: ${response.status}`);
     }
 
     return response.json();
   }
 
+  // A session's endpoint; sessions on a remote hub go through the desktop's relay
+  private sessionPath(sessionId: string, rest = ''): string {
+    const hubId = useGlobalStore.getState().sessions.get(sessionId)?.hub_id;
+    const base = hubId ? `/api/hubs/${encodeURIComponent(hubId)}/api` : '/api';
+    return `${base}/sessions/${sessionId}${rest}`;
+  }
+
   // Auth endpoints
   async checkPinStatus(): Promise<{ pin_configured: boolean }> {
     return this.request('/api/auth/pin-status');
@@ -39,18 +53,28 @@ class ApiService {
     return this.request('/api/auth/request-pairing', { method: 'POST' });
   }
 
-  async completePairing(pairingId: string, code: string, deviceName: string): Promise<{ token: string; device_id: string }> {
-    return this.request('/api/auth/pair', {
-      method: 'POST',
-      body: JSON.stringify({
-        pairing_id: pairingId,
-        code,
-        device_name: deviceName,
-      }),
-    });
+  // Resolves once the desktop has approved this device
+  async completePairing(pairingId: string, code: string, deviceName: string): Promise<AuthTokenResponse> {
+    for (;;) {
+      const response = await this.request<AuthTokenResponse | { status: 'pending_approval'; expires_in: number }>(
+        '/api/auth/pair',
+        {
+          method: 'POST',
+          body: JSON.stringify({
+            pairing_id: pairingId,
+            code,
+            device_name: deviceName,
+          }),
+        }
+      );
+      if ('token' in response) {
+        return response;
+      }
+      await new Promise((resolve) => setTimeout(resolve, 2000));
+    }
   }
 
-  async loginWithPin(pin: string, deviceName: string): Promise<{ token: string; device_id: string }> {
+  async loginWithPin(pin: string, deviceName: string): Promise<AuthTokenResponse> {
     return this.request('/api/auth/pin-login', {
       method: 'POST',
       body: JSON.stringify({
@@ -60,15 +84,80 @@ class ApiService {
     });
   }
 
+  // Rotates the token: the one used for this request stops working
+  async refreshToken(): Promise<AuthTokenResponse> {
+    return this.request('/api/auth/refresh', { method: 'POST' });
+  }
+
   async checkAuth(): Promise<{ valid: boolean }> {
     return this.request('/api/auth/check');
   }
 
+  // Device endpoints
+  async getDevices(): Promise<PairedDevice[]> {
+    return this.request('/api/devices');
+  }
+
+  async setDeviceScope(deviceId: string, scope: DeviceScope): Promise<{ scope: DeviceScope }> {
+    return this.request(`/api/devices/${encodeURIComponent(deviceId)}`, {
+      method: 'PATCH',
+      body: JSON.stringify({ scope }),
+    });
+  }
+
+  async getAuditLog(params: { deviceId?: string; sessionId?: string; before?: number; limit?: number } = {}): Promise<AuditEntry[]> {
+    const query = new URLSearchParams();
+    if (params.deviceId) query.set('device_id', params.deviceId);
+    if (params.sessionId) query.set('session_id', params.sessionId);
+    if (params.before !== undefined) query.set('before', String(params.before));
+    if (params.limit !== undefined) query.set('limit', String(params.limit));
+    return this.request(`/api/audit?${query}`);
+  }
+
+  async revokeDevice(deviceId: string): Promise<void> {
+    return this.request(`/api/devices/${encodeURIComponent(deviceId)}`, { method: 'DELETE' });
+  }
+
+  // Push notification endpoints (for this device)
+  async getVapidKey(): Promise<{ public_key: string }> {
+    return this.request('/api/push/vapid-key');
+  }
+
+  async getPushSubscription(): Promise<PushSubscriptionInfo | null> {
+    return this.request('/api/push/subscription');
+  }
+
+  async setPushSubscription(subscription: PushSubscriptionJSON | { kind: 'ntfy' | 'webhook'; endpoint: string }): Promise<PushSubscriptionInfo> {
+    return this.request('/api/push/subscription', {
+      method: 'PUT',
+      body: JSON.stringify(subscription),
+    });
+  }
+
+  async deletePushSubscription(): Promise<void> {
+    await this.request('/api/push/subscription', { method: 'DELETE' });
+  }
+
   // Session endpoints
   async getSessions(): Promise<Session[]> {
     return this.request('/api/sessions');
   }
 
+  // Other Agent Hubs whose sessions the desktop relays
+  async getHubs(): Promise<RemoteHub[]> {
+    return this.request('/api/hubs');
+  }
+
+  // Unauthenticated; protocol_version changes when the desktop API breaks compatibility
+  async getVersion(): Promise<{ version: string; protocol_version: number; platform: string }> {
+    return this.request('/api/version');
+  }
+
+  // Hubs advertising on the desktop's LAN via mDNS (browsers can't browse mDNS themselves)
+  async discoverInstances(): Promise<{ instances: DiscoveredInstance[] }> {
+    return this.request('/api/instances');
+  }
+
   async createSession(name: string, workingDir: string): Promise<Session> {
     return this.request('/api/sessions', {
       method: 'POST',
@@ -80,20 +169,118 @@ class ApiService {
     });
   }
 
+  async updateSession(
+    sessionId: string,
+    changes: { name?: string; folder_id?: string | null; working_dir?: string; sort_order?: number },
+  ): Promise<Session> {
+    return this.request(this.sessionPath(sessionId), {
+      method: 'PATCH',
+      body: JSON.stringify(changes),
+    });
+  }
+
   async deleteSession(sessionId: string): Promise<void> {
-    await this.request(`/api/sessions/${sessionId}`, { method: 'DELETE' });
+    await this.request(this.sessionPath(sessionId), { method: 'DELETE' });
+  }
+
+  async getSettings(): Promise<RemoteSettings> {
+    return this.request('/api/settings');
+  }
+
+  async updateSettings(changes: Partial<RemoteSettings>): Promise<RemoteSettings> {
+    return this.request('/api/settings', {
+      method: 'PATCH',
+      body: JSON.stringify(changes),
+    });
+  }
+
+  async getFolders(): Promise<Folder[]> {
+    return this.request('/api/folders');
+  }
+
+  async createFolder(name: string): Promise<Folder> {
+    return this.request('/api/folders', {
+      method: 'POST',
+      body: JSON.stringify({ name }),
+    });
+  }
+
+  async updateFolder(
+    folderId: string,
+    changes: { name?: string; collapsed?: boolean; sort_order?: number },
+  ): Promise<Folder> {
+    return this.request(`/api/folders/${folderId}`, {
+      method: 'PATCH',
+      body: JSON.stringify(changes),
+    });
+  }
+
+  async reorderFolders(orders: [string, number][]): Promise<Folder[]> {
+    return this.request('/api/folders', {
+      method: 'PATCH',
+      body: JSON.stringify({ orders }),
+    });
+  }
+
+  // Sessions in the folder are moved out of it, not deleted
+  async deleteFolder(folderId: string): Promise<void> {
+    await this.request(`/api/folders/${folderId}`, { method: 'DELETE' });
   }
 
   async startSession(sessionId: string): Promise<{ status: string }> {
-    return this.request(`/api/sessions/${sessionId}/start`, { method: 'POST' });
+    return this.request(this.sessionPath(sessionId, '/start'), { method: 'POST' });
   }
 
   async interruptSession(sessionId: string): Promise<{ status: string }> {
-    return this.request(`/api/sessions/${sessionId}/interrupt`, { method: 'POST' });
+    return this.request(this.sessionPath(sessionId, '/interrupt'), { method: 'POST' });
+  }
+
+  async stopSession(sessionId: string): Promise<{ status: string }> {
+    return this.request(this.sessionPath(sessionId, '/stop'), { method: 'POST' });
+  }
+
+  // Questions and approval requests the desktop's agents are waiting on
+  async getAsks(sessionId: string): Promise<AgentAsk[]> {
+    return this.request(`/api/asks?${new URLSearchParams({ session_id: sessionId })}`);
+  }
+
+  // text answers a question; approved (with an optional text note) an approval
+  async answerAsk(askId: string, answer: { text?: string; approved?: boolean }): Promise<{ status: string }> {
+    return this.request(`/api/asks/${encodeURIComponent(askId)}/answer`, {
+      method: 'POST',
+      body: JSON.stringify(answer),
+    });
+  }
+
+  // Terminal sessions only; isControl sends Ctrl+<text> (e.g. 'c')
+  async sendTerminalInput(sessionId: string, text: string, isControl = false): Promise<{ status: string }> {
+    return this.request(this.sessionPath(sessionId, '/input'), {
+      method: 'POST',
+      body: JSON.stringify({ text, is_control: isControl }),
+    });
   }
 
   async getSessionBuffer(sessionId: string): Promise<{ buffer: string | null }> {
-    return this.request(`/api/sessions/${sessionId}/buffer`);
+    return this.request(this.sessionPath(sessionId, '/buffer'));
+  }
+
+  // Photo upload — stored on the desktop and sent to the session with an optional caption
+  async uploadAttachment(sessionId: string, image: Blob, text?: string): Promise<{ id: string; media_type: string }> {
+    const qs = text ? `?${new URLSearchParams({ text }).toString()}` : '';
+    return this.request(this.sessionPath(sessionId, `/attachments${qs}`), {
+      method: 'POST',
+      headers: { 'Content-Type': image.type || 'application/octet-stream' },
+      body: image,
+    });
+  }
+
+  // Voice prompt — the desktop transcribes it and sends the text to the session
+  async sendAudio(sessionId: string, audio: Blob): Promise<{ text: string }> {
+    return this.request(this.sessionPath(sessionId, '/audio'), {
+      method: 'POST',
+      headers: { 'Content-Type': audio.type || 'audio/webm' },
+      body: audio,
+    });
   }
 
   // Search context — hit's neighbors for the rich result card
@@ -0,0 +1,67 @@
+// Decoder for the CBOR (RFC 8949) frames the desktop sends once auth asks
+// for `encoding: 'cbor'` (protocol 9). Covers what the desktop encodes: the
+// JSON types plus byte strings, which become Uint8Arrays.
+export function decodeCbor(data: Uint8Array): unknown {
+  const view = new DataView(data.buffer, data.byteOffset, data.byteLength);
+  const text = new TextDecoder();
+  let pos = 0;
+
+  const length = (info: number): number => {
+    if (info < 24) return info;
+    pos += 1 << (info - 24);
+    switch (info) {
+      case 24: return view.getUint8(pos - 1);
+      case 25: return view.getUint16(pos - 2);
+      case 26: return view.getUint32(pos - 4);
+      case 27: return Number(view.getBigUint64(pos - 8));
+      default: throw new Error(`Unsupported CBOR length ${info}`);
+    }
+  };
+
+  const item = (): unknown => {
+    const initial = view.getUint8(pos++);
+    const major = initial >> 5;
+    const info = initial & 0x1f;
+    switch (major) {
+      case 0: return length(info);
+      case 1: return -1 - length(info);
+      case 2: {
+        const n = length(info);
+        pos += n;
+        return data.subarray(pos - n, pos);
+      }
+      case 3: {
+        const n = length(info);
+        pos += n;
+        return text.decode(data.subarray(pos - n, pos));
+      }
+      case 4: {
+        const n = length(info);
+        const items: unknown[] = [];
+        for (let i = 0; i < n; i++) items.push(item());
+        return items;
+      }
+      case 5: {
+        const n = length(info);
+        const map: Record<string, unknown> = {};
+        for (let i = 0; i < n; i++) {
+          const key = item() as string;
+          map[key] = item();
+        }
+        return map;
+      }
+      case 7:
+        switch (info) {
+          case 20: return false;
+          case 21: return true;
+          case 22: return null;
+          case 23: return undefined;
+          case 26: pos += 4; return view.getFloat32(pos - 4);
+          case 27: pos += 8; return view.getFloat64(pos - 8);
+        }
+    }
+    throw new Error(`Unsupported CBOR item 0x${initial.toString(16)}`);
+  };
+
+  return item();
+}
@@ -0,0 +1,50 @@
+import { api } from './api';
+import { WebSocketService, websocketService } from './websocket';
+import { useGlobalStore } from '../stores/globalStore';
+
+// Other Agent Hubs registered on the desktop. Each is followed over its own
+// WebSocket, relayed by the desktop, and its sessions are listed with the
+// desktop's own, tagged with the hub (hub_id, host).
+const hubSockets = new Map<string, WebSocketService>();
+
+// Connect to every registered hub; call again to pick up added or removed ones
+export async function connectHubs() {
+  let hubs;
+  try {
+    hubs = await api.getHubs();
+  } catch (err) {
+    // Desktops without hub support answer 404
+    console.warn('Could not load remote hubs:', err);
+    return;
+  }
+  const ids = new Set(hubs.map((hub) => hub.id));
+  for (const [id, socket] of hubSockets) {
+    if (!ids.has(id)) {
+      socket.disconnect();
+      hubSockets.delete(id);
+      useGlobalStore.getState().setSessions([], id);
+    }
+  }
+  for (const hub of hubs) {
+    let socket = hubSockets.get(hub.id);
+    if (!socket) {
+      socket = new WebSocketService(hub);
+      hubSockets.set(hub.id, socket);
+    }
+    socket.connect();
+  }
+}
+
+export function disconnectHubs() {
+  for (const socket of hubSockets.values()) socket.disconnect();
+}
+
+export function reconnectHubs() {
+  for (const socket of hubSockets.values()) socket.reconnect();
+}
+
+// The connection that reaches a session: its hub's, or the desktop's
+export function socketFor(sessionId: string): WebSocketService {
+  const hubId = useGlobalStore.getState().sessions.get(sessionId)?.hub_id;
+  return (hubId && hubSockets.get(hubId)) || websocketService;
+}
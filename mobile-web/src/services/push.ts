@@ -0,0 +1,54 @@
+import { api } from './api';
+
+// Web push needs a service worker and a secure context (https or localhost)
+export function pushSupported(): boolean {
+  return window.isSecureContext && 'serviceWorker' in navigator && 'PushManager' in window;
+}
+
+export function registerServiceWorker() {
+  if (!pushSupported()) return;
+  navigator.serviceWorker.register('/sw.js').catch((err) => console.warn('Service worker registration failed:', err));
+}
+
+function urlBase64ToUint8Array(base64: string): Uint8Array<ArrayBuffer> {
+  const padded = (base64 + '='.repeat((4 - (base64.length % 4)) % 4)).replace(/-/g, '+').replace(/_/g, '/');
+  return Uint8Array.from(atob(padded), (c) => c.charCodeAt(0));
+}
+
+// Whether this browser is subscribed and the desktop knows about it
+export async function pushEnabled(): Promise<boolean> {
+  if (!pushSupported() || Notification.permission !== 'granted') return false;
+  const registration = await navigator.serviceWorker.ready;
+  const subscription = await registration.pushManager.getSubscription();
+  if (!subscription) return false;
+  const stored = await api.getPushSubscription().catch(() => null);
+  return stored?.endpoint === subscription.endpoint;
+}
+
+// Ask for permission, subscribe with the desktop's VAPID key and register
+// the subscription for this device
+export async function enablePush(): Promise<void> {
+  if (!pushSupported()) {
+    throw new Error('Notifications need the app opened over https (or added to the home screen on iOS)');
+  }
+  if ((await Notification.requestPermission()) !== 'granted') {
+    throw new Error('Notification permission was denied');
+  }
+  const registration = await navigator.serviceWorker.register('/sw.js');
+  await navigator.serviceWorker.ready;
+  const { public_key } = await api.getVapidKey();
+  // A subscription made with another desktop's key can't be reused
+  await (await registration.pushManager.getSubscription())?.unsubscribe();
+  const subscription = await registration.pushManager.subscribe({
+    userVisibleOnly: true,
+    applicationServerKey: urlBase64ToUint8Array(public_key),
+  });
+  await api.setPushSubscription(subscription.toJSON());
+}
+
+export async function disablePush(): Promise<void> {
+  await api.deletePushSubscription();
+  if (!pushSupported()) return;
+  const registration = await navigator.serviceWorker.getRegistration();
+  await (await registration?.pushManager.getSubscription())?.unsubscribe();
+}
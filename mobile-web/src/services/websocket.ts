@@ -1,10 +1,46 @@
 import { useAuthStore } from '../stores/authStore';
+import { api } from './api';
 import { useGlobalStore } from '../stores/globalStore';
 import { useSessionStore } from '../stores/sessionStore';
+import { decodeCbor } from './cbor';
 import type { Message } from '../types/message';
-import type { ClientMessage, ServerMessage } from '../types/websocket';
+import type { NotificationPrefs, RemoteHub, Session } from '../types/session';
+import { PROTOCOL_VERSION, type ClientMessage, type ServerMessage } from '../types/websocket';
+
+// Receives a PTY session's output for a terminal view
+export interface TerminalListener {
+  onBuffer?: (buffer: string) => void;
+  onData: (data: Uint8Array) => void;
+  onClose?: () => void;
+}
+
+type DistributiveOmit<T, K extends PropertyKey> = T extends unknown ? Omit<T, K> : never;
+type SessionActionMessage = DistributiveOmit<
+  Extract<ClientMessage, { requestId: string }>,
+  'requestId'
+>;
+
+// A gzipped binary frame (protocol 6) back to its JSON text
+async function inflate(data: ArrayBuffer): Promise<ArrayBuffer> {
+  const stream = new Blob([data]).stream().pipeThrough(new DecompressionStream('gzip'));
+  return new Response(stream).arrayBuffer();
+}
 
-class WebSocketService {
+// A frame as a message: JSON text, or a binary frame holding CBOR
+// (protocol 9), gzipped JSON or gzipped CBOR
+async function decodeFrame(data: string | ArrayBuffer): Promise<ServerMessage> {
+  if (typeof data === 'string') return JSON.parse(data) as ServerMessage;
+  let bytes = new Uint8Array(data);
+  if (bytes[0] === 0x1f && bytes[1] === 0x8b) bytes = new Uint8Array(await inflate(data));
+  // A CBOR message is a map (0xa0-0xbf); '{' starts JSON
+  if (bytes[0] === 0x7b) return JSON.parse(new TextDecoder().decode(bytes)) as ServerMessage;
+  return decodeCbor(bytes) as ServerMessage;
+}
+
+// One connection to the desktop, or through it to a remote hub (see hubs.ts).
+// A hub's sessions are tagged with it; the desktop's own folders, settings
+// and this device's sign-in are only taken from the desktop's connection.
+export class WebSocketService {
   private ws: WebSocket | null = null;
   private reconnectAttempts = 0;
   private maxReconnectAttempts = 5;
@@ -12,6 +48,25 @@ class WebSocketService {
   private pingInterval: ReturnType<typeof setInterval> | null = null;
   private isAuthenticated = false;
   private pendingMessages: ClientMessage[] = [];
+  // Protocol agreed in auth_success; session management needs 2
+  private protocolVersion = 1;
+  private terminalListeners = new Map<string, TerminalListener>();
+  // Newest chat message seq seen per session, to resume from after a reconnect
+  private lastSeq = new Map<string, number>();
+  // Sessions with an ack waiting to be sent (batched, see scheduleAck)
+  private pendingAcks = new Set<string>();
+  private ackTimer: ReturnType<typeof setTimeout> | null = null;
+  // Incoming frames are handled in order even when one needs async inflating
+  private inbound: Promise<void> = Promise.resolve();
+  private pendingActions = new Map<
+    string,
+    { resolve: (session: Session | null) => void; reject: (err: Error) => void }
+  >();
+  // Chat subscriptions, resent when a hub connection comes back (ChatView
+  // resubscribes the desktop's own when isConnected changes)
+  private subscriptions = new Set<string>();
+
+  constructor(private readonly hub: RemoteHub | null = null) {}
 
   connect() {
     const token = useAuthStore.getState().authToken;
@@ -26,18 +81,28 @@ class WebSocketService {
     }
 
     const protocol = window.location.protocol === 'https:' ? 'wss:' : 'ws:';
-    const wsUrl = `${protocol}//${window.location.host}/api/ws/mobile`;
+    const path = this.hub ? `/api/hubs/${encodeURIComponent(this.hub.id)}/ws/mobile` : '/api/ws/mobile';
+    const wsUrl = `${protocol}//${window.location.host}${path}`;
 
     console.log('Connecting to WebSocket:', wsUrl);
     this.ws = new WebSocket(wsUrl);
+    this.ws.binaryType = 'arraybuffer';
 
     this.ws.onopen = () => {
       console.log('WebSocket connected');
       this.reconnectAttempts = 0;
-      useGlobalStore.getState().setConnected(true);
+      if (!this.hub) useGlobalStore.getState().setConnected(true);
 
       // Authenticate
-      this.send({ type: 'auth', token: token || '' });
+      this.send({
+        type: 'auth',
+        token: token || '',
+        protocolVersion: PROTOCOL_VERSION,
+        // Large messages (history, scrollback) come gzipped if we can inflate them
+        ...('DecompressionStream' in window ? { compression: 'gzip' as const } : {}),
+        // Chat and terminal traffic as CBOR, smaller than JSON on mobile data
+        encoding: 'cbor',
+      });
 
       // Start ping interval
       this.pingInterval = setInterval(() => {
@@ -50,7 +115,7 @@ class WebSocketService {
     this.ws.onclose = (event) => {
       console.log('WebSocket closed:', event.code, event.reason);
       this.isAuthenticated = false;
-      useGlobalStore.getState().setConnected(false);
+      if (!this.hub) useGlobalStore.getState().setConnected(false);
       this.cleanup();
 
       // Reconnect if not intentionally closed
@@ -67,12 +132,11 @@ class WebSocketService {
     };
 
     this.ws.onmessage = (event) => {
-      try {
-        const message = JSON.parse(event.data) as ServerMessage;
-        this.handleMessage(message);
-      } catch (e) {
-        console.error('Failed to parse WebSocket message:', e);
-      }
+      const data = event.data as string | ArrayBuffer;
+      this.inbound = this.inbound
+        .then(() => decodeFrame(data))
+        .then((message) => this.handleMessage(message))
+        .catch((e) => console.error('Failed to parse WebSocket message:', e));
     };
   }
 
@@ -90,18 +154,49 @@ class WebSocketService {
       case 'auth_success':
         console.log('WebSocket authenticated');
         this.isAuthenticated = true;
+        this.protocolVersion = message.protocolVersion ?? 1;
+        // Desktops that predate negotiation don't report a version
+        if ((message.serverProtocolVersion ?? 1) !== PROTOCOL_VERSION) {
+          console.warn(
+            `Desktop ${message.serverVersion ?? '(unknown version)'} speaks protocol ${message.serverProtocolVersion ?? 1}, ` +
+              `this client ${PROTOCOL_VERSION}; using ${message.protocolVersion ?? 1}`,
+          );
+        }
         // Flush any pending messages that were queued before auth
         this.flushPendingMessages();
+        // Reattach terminals after a reconnect
+        for (const sessionId of this.terminalListeners.keys()) {
+          this.send({ type: 'subscribe_terminal', sessionId });
+        }
+        if (this.hub) {
+          for (const sessionId of this.subscriptions) this.subscribe(sessionId);
+          break;
+        }
+        // Desktops without scopes give every device full access
+        useAuthStore.getState().setScope(message.scope ?? 'admin');
+        if (this.protocolVersion >= 12) this.send({ type: 'get_notification_prefs' });
+        this.refreshTokenIfExpiring();
         break;
 
       case 'auth_error':
         console.error('WebSocket auth error:', message.message);
-        useAuthStore.getState().logout();
+        // A hub rejecting the desktop's token doesn't sign this device out
+        if (!this.hub) useAuthStore.getState().logout();
+        break;
+
+      case 'auth_revoked':
+        console.warn('Desktop ended this device session:', message.reason);
+        // A scope change, server restart or desktop quit only needs a reconnect, which onclose does
+        const reconnect = ['scope_changed', 'server_restarting', 'server_shutting_down'];
+        if (!this.hub && !reconnect.includes(message.reason)) {
+          useAuthStore.getState().logout();
+        }
         break;
 
       case 'session_list':
         // Update the sessions in global store
-        globalStore.setSessions(message.sessions);
+        globalStore.setSessions(message.sessions.map((s) => this.tag(s)), this.hub?.id ?? null);
+        if (this.hub) break;
         if (message.folders) {
           globalStore.setFolders(message.folders);
         }
@@ -116,18 +211,47 @@ class WebSocketService {
         break;
 
       case 'session_created':
-        globalStore.addSession(message.session);
+        globalStore.addSession(this.tag(message.session));
         break;
 
       case 'session_updated':
-        globalStore.updateSession(message.session);
+        globalStore.updateSession(this.tag(message.session));
         break;
 
       case 'session_deleted':
         globalStore.removeSession(message.sessionId);
         break;
 
+      case 'folder_created':
+      case 'folder_updated':
+        if (!this.hub) globalStore.setFolder(message.folder);
+        break;
+
+      case 'folder_deleted':
+        if (!this.hub) globalStore.removeFolder(message.folderId);
+        break;
+
+      case 'notification_prefs':
+        if (!this.hub) globalStore.setNotificationPrefs(message.prefs);
+        break;
+
+      case 'settings_updated':
+        if (!this.hub) globalStore.setShowActiveSessionsGroup(message.settings.show_active_sessions_group);
+        break;
+
       case 'chat_message':
+        if (message.seq != null) {
+          const last = this.lastSeq.get(message.sessionId);
+          // A resend for a session we hold no history for loads with it on subscribe
+          const stale = message.retry && last === undefined;
+          // Catching up can repeat a message that arrived live
+          if (stale || message.seq <= (last ?? 0)) {
+            this.scheduleAck(message.sessionId, stale ? message.seq : undefined);
+            break;
+          }
+          this.lastSeq.set(message.sessionId, message.seq);
+          this.scheduleAck(message.sessionId);
+        }
         // Add message to session's message list
         this.handleChatMessage(message.sessionId, message.message);
         break;
@@ -135,8 +259,115 @@ class WebSocketService {
       case 'chat_history':
         // Set the chat history for a session
         sessionStore.setMessages(message.sessionId, message.messages);
+        if (message.seq != null) {
+          this.lastSeq.set(message.sessionId, message.seq);
+          this.scheduleAck(message.sessionId);
+        } else {
+          this.lastSeq.delete(message.sessionId);
+        }
+        break;
+
+      case 'agent_typing':
+        globalStore.updateSessionStatus(message.sessionId, { isProcessing: message.typing });
+        if (!message.typing) sessionStore.clearStreamingText(message.sessionId);
+        break;
+
+      case 'agent_delta':
+        sessionStore.appendStreamingText(message.sessionId, message.text);
+        break;
+
+      case 'presence':
+        globalStore.updateSessionStatus(message.sessionId, {
+          viewerCount: message.viewerCount,
+          viewers: message.viewers,
+        });
+        break;
+
+      case 'session_error':
+        // Show the failure inline so a session that won't start isn't just blank
+        sessionStore.addMessage(message.sessionId, {
+          type: 'result',
+          subtype: 'error',
+          is_error: true,
+          result: message.error.message,
+        });
+        break;
+
+      case 'session_ask':
+        globalStore.addAsk(message.ask);
+        break;
+
+      case 'session_ask_settled':
+        globalStore.removeAsk(message.sessionId, message.askId);
+        break;
+
+      case 'session_attention':
+        sessionStore.addMessage(message.sessionId, {
+          type: 'system',
+          result: `Needs your attention: ${message.message}`,
+        });
+        break;
+
+      case 'context_warning':
+        sessionStore.addMessage(message.sessionId, {
+          type: 'system',
+          result: `Context is ${message.contextUsedPct}% full. Consider /compact or starting a new session.`,
+        });
+        break;
+
+      case 'session_hung': {
+        const mins = Math.round(message.silentSecs / 60);
+        const action = message.policy === 'interrupt' ? ' Interrupting.' : message.policy === 'restart' ? ' Restarting.' : '';
+        sessionStore.addMessage(message.sessionId, {
+          type: 'system',
+          result: `No output for ${mins} min; the session may be stuck.${action}`,
+        });
+        break;
+      }
+
+      case 'session_recovered':
+      case 'session_starting':
+      case 'session_ready':
+        break;
+
+      case 'session_restarted':
+        sessionStore.addMessage(message.sessionId, {
+          type: 'system',
+          result: `Agent process crashed and was restarted (attempt ${message.attempt} of ${message.maxAttempts}).`,
+        });
+        break;
+
+      case 'terminal_subscribed':
+        if (message.buffer) this.terminalListeners.get(message.sessionId)?.onBuffer?.(message.buffer);
         break;
 
+      case 'terminal_output':
+        this.terminalListeners
+          .get(message.sessionId)
+          ?.onData(
+            typeof message.data === 'string'
+              ? Uint8Array.from(atob(message.data), (c) => c.charCodeAt(0))
+              : message.data,
+          );
+        break;
+
+      case 'terminal_closed':
+        this.terminalListeners.get(message.sessionId)?.onClose?.();
+        this.terminalListeners.delete(message.sessionId);
+        break;
+
+      case 'session_action_result': {
+        const pending = message.requestId ? this.pendingActions.get(message.requestId) : undefined;
+        if (!pending) break;
+        this.pendingActions.delete(message.requestId!);
+        if (message.ok) {
+          pending.resolve(message.session ?? null);
+        } else {
+          pending.reject(new Error(message.error || `${message.action} failed`));
+        }
+        break;
+      }
+
       case 'error':
         console.error('Server error:', message.message);
         break;
@@ -165,14 +396,54 @@ class WebSocketService {
       message = messageData;
     }
 
+    // The complete reply replaces its streamed preview
+    if (message.type === 'assistant') sessionStore.clearStreamingText(sessionId);
     sessionStore.addMessage(sessionId, message);
   }
 
+  // Tell the desktop which messages were shown (protocol 5), a moment after
+  // the last one so a streaming answer takes one ack. `seq` acks a message
+  // that wasn't added to lastSeq.
+  private scheduleAck(sessionId: string, seq?: number) {
+    if (this.protocolVersion < 5) return;
+    if (seq !== undefined) {
+      this.send({ type: 'ack', sessionId, seq });
+      return;
+    }
+    this.pendingAcks.add(sessionId);
+    if (this.ackTimer) return;
+    this.ackTimer = setTimeout(() => {
+      this.ackTimer = null;
+      for (const id of this.pendingAcks) {
+        const last = this.lastSeq.get(id);
+        if (last !== undefined) this.send({ type: 'ack', sessionId: id, seq: last });
+      }
+      this.pendingAcks.clear();
+    }, 250);
+  }
+
+  // Sessions from a hub carry its id and name; their folders are the hub's,
+  // so they're listed under the hub instead
+  private tag(session: Session): Session {
+    return this.hub ? { ...session, hub_id: this.hub.id, host: this.hub.name, folder_id: null } : session;
+  }
+
+  // Resubscribing to a session we've seen asks only for what was missed;
+  // desktops without seq support ignore since_seq and resend the history.
+  // Tool results aren't rendered, so they aren't downloaded either.
   subscribe(sessionId: string) {
-    this.send({ type: 'subscribe', sessionId });
+    this.subscriptions.add(sessionId);
+    const sinceSeq = this.lastSeq.get(sessionId);
+    const filters = { exclude_tool_results: true };
+    this.send(
+      sinceSeq === undefined
+        ? { type: 'subscribe', sessionId, filters }
+        : { type: 'subscribe', sessionId, since_seq: sinceSeq, filters },
+    );
   }
 
   unsubscribe(sessionId: string) {
+    this.subscriptions.delete(sessionId);
     this.send({ type: 'unsubscribe', sessionId });
   }
 
@@ -193,6 +464,85 @@ class WebSocketService {
     this.send({ type: 'interrupt', sessionId });
   }
 
+  // Replaces all of them; the desktop answers with what it saved
+  setNotificationPrefs(prefs: NotificationPrefs) {
+    this.send({ type: 'set_notification_prefs', prefs });
+  }
+
+  // Reply to an agent's question (text) or approval request (approved);
+  // desktops before protocol 13 only take answers over HTTP
+  answerQuestion(askId: string, answer: { text?: string; approved?: boolean }): boolean {
+    if (this.protocolVersion < 13) return false;
+    this.send({ type: 'answer_question', askId, ...answer });
+    return true;
+  }
+
+  // Whether the desktop streams terminals over this socket (protocol 3);
+  // older ones only offer /api/ws/:id
+  supportsTerminals(): boolean {
+    return this.isAuthenticated && this.protocolVersion >= 3;
+  }
+
+  // Attach a terminal view to a running PTY session; call the returned
+  // function to detach
+  subscribeTerminal(sessionId: string, listener: TerminalListener): () => void {
+    this.terminalListeners.set(sessionId, listener);
+    this.send({ type: 'subscribe_terminal', sessionId });
+    return () => {
+      if (this.terminalListeners.get(sessionId) !== listener) return;
+      this.terminalListeners.delete(sessionId);
+      this.send({ type: 'unsubscribe_terminal', sessionId });
+    };
+  }
+
+  sendTerminalInput(sessionId: string, data: string) {
+    this.send({ type: 'terminal_input', sessionId, data });
+  }
+
+  resizeTerminal(sessionId: string, cols: number, rows: number) {
+    this.send({ type: 'terminal_resize', sessionId, cols, rows });
+  }
+
+  // Whether the desktop handles session management over the socket; older
+  // ones need the REST endpoints
+  supportsSessionActions(): boolean {
+    return this.isAuthenticated && this.protocolVersion >= 2;
+  }
+
+  createSession(name: string, workingDir: string, folderId?: string | null): Promise<Session> {
+    return this.sessionAction({ type: 'create_session', name, agentType: 'claude-json', workingDir, folderId }) as Promise<Session>;
+  }
+
+  renameSession(sessionId: string, name: string): Promise<Session> {
+    return this.sessionAction({ type: 'rename_session', sessionId, name }) as Promise<Session>;
+  }
+
+  moveSessionToFolder(sessionId: string, folderId: string | null): Promise<Session> {
+    return this.sessionAction({ type: 'move_session_to_folder', sessionId, folderId }) as Promise<Session>;
+  }
+
+  deleteSession(sessionId: string): Promise<void> {
+    return this.sessionAction({ type: 'delete_session', sessionId }).then(() => undefined);
+  }
+
+  stopSession(sessionId: string): Promise<void> {
+    return this.sessionAction({ type: 'stop_session', sessionId }).then(() => undefined);
+  }
+
+  // Send a session management message and wait for its session_action_result
+  private sessionAction(message: SessionActionMessage): Promise<Session | null> {
+    const requestId = crypto.randomUUID();
+    return new Promise((resolve, reject) => {
+      this.pendingActions.set(requestId, { resolve, reject });
+      this.send({ ...message, requestId } as ClientMessage);
+      setTimeout(() => {
+        if (this.pendingActions.delete(requestId)) {
+          reject(new Error('Timed out waiting for the desktop'));
+        }
+      }, 15000);
+    });
+  }
+
   private send(message: ClientMessage) {
     // Auth messages should always go through immediately
     if (message.type === 'auth') {
@@ -216,6 +566,17 @@ class WebSocketService {
     }
   }
 
+  // Renew the token once it's within a week of expiring; the socket stays
+  // connected since it's tied to the device, not the token
+  private refreshTokenIfExpiring() {
+    const { tokenExpiresAt, setAuthToken } = useAuthStore.getState();
+    if (!tokenExpiresAt || Date.parse(tokenExpiresAt) - Date.now() > 7 * 24 * 60 * 60 * 1000) return;
+    api
+      .refreshToken()
+      .then(({ token, expires_at }) => setAuthToken(token, expires_at))
+      .catch((err) => console.warn('Token refresh failed:', err));
+  }
+
   private flushPendingMessages() {
     if (this.pendingMessages.length > 0) {
       console.log(`Flushing ${this.pendingMessages.length} pending messages`);
@@ -249,7 +610,7 @@ class WebSocketService {
   reconnect() {
     this.cleanup();
     this.isAuthenticated = false;
-    useGlobalStore.getState().setConnected(false);
+    if (!this.hub) useGlobalStore.getState().setConnected(false);
     if (this.ws) {
       this.ws.close(1000, 'Reconnecting');
       this.ws = null;
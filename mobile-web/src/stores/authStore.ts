@@ -1,9 +1,12 @@
 import { create } from 'zustand';
 import { persist } from 'zustand/middleware';
+import type { DeviceScope } from '../types';
 
 interface AuthState {
   serverUrl: string | null;
   authToken: string | null;
+  tokenExpiresAt: string | null; // null = never expires (or an older desktop)
+  scope: DeviceScope; // reported by the desktop on each WebSocket auth
   isAuthenticated: boolean;
   pinEnabled: boolean;
   isLoading: boolean;
@@ -11,8 +14,9 @@ interface AuthState {
 
   // Actions
   setServerUrl: (url: string) => void;
-  setAuthToken: (token: string) => void;
+  setAuthToken: (token: string, expiresAt?: string | null) => void;
   setPinEnabled: (enabled: boolean) => void;
+  setScope: (scope: DeviceScope) => void;
   setError: (error: string | null) => void;
   setLoading: (loading: boolean) => void;
   logout: () => void;
@@ -23,6 +27,8 @@ export const useAuthStore = create<AuthState>()(
     (set) => ({
       serverUrl: null,
       authToken: null,
+      tokenExpiresAt: null,
+      scope: 'admin',
       isAuthenticated: false,
       pinEnabled: false,
       isLoading: false,
@@ -30,20 +36,24 @@ export const useAuthStore = create<AuthState>()(
 
       setServerUrl: (url) => set({ serverUrl: url, error: null }),
 
-      setAuthToken: (token) => set({
+      setAuthToken: (token, expiresAt = null) => set({
         authToken: token,
+        tokenExpiresAt: expiresAt,
         isAuthenticated: true,
         error: null
       }),
 
       setPinEnabled: (enabled) => set({ pinEnabled: enabled }),
 
+      setScope: (scope) => set({ scope }),
+
       setError: (error) => set({ error, isLoading: false }),
 
       setLoading: (loading) => set({ isLoading: loading }),
 
       logout: () => set({
         authToken: null,
+        tokenExpiresAt: null,
         isAuthenticated: false,
         error: null,
       }),
@@ -53,6 +63,7 @@ export const useAuthStore = create<AuthState>()(
       partialize: (state) => ({
         serverUrl: state.serverUrl,
         authToken: state.authToken,
+        tokenExpiresAt: state.tokenExpiresAt,
         isAuthenticated: state.isAuthenticated,
       }),
     }
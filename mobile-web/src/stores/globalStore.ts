@@ -1,5 +1,5 @@
 import { create } from 'zustand';
-import type { Session, SessionStatus, Folder } from '../types';
+import type { Session, SessionStatus, Folder, NotificationPrefs, AgentAsk } from '../types';
 
 interface GlobalState {
   // Sessions
@@ -21,6 +21,16 @@ interface GlobalState {
   // Settings from desktop
   showActiveSessionsGroup: boolean;
 
+  // Questions and approval requests agents are waiting on, by session
+  asks: Map<string, AgentAsk[]>;
+  setAsks: (sessionId: string, asks: AgentAsk[]) => void;
+  addAsk: (ask: AgentAsk) => void;
+  removeAsk: (sessionId: string, askId: string) => void;
+
+  // This device's notification preferences; null until the desktop sends them
+  notificationPrefs: NotificationPrefs | null;
+  setNotificationPrefs: (prefs: NotificationPrefs) => void;
+
   // Pending scroll-to-message target (uuid). Set when a search hit is
   // tapped; consumed by MessageList once the messages render.
   pendingScrollTargetUuid: string | null;
@@ -41,8 +51,10 @@ interface GlobalState {
   clearSearchBackTrail: () => void;
 
   // Actions
-  setSessions: (sessions: Session[]) => void;
+  setSessions: (sessions: Session[], hubId?: string | null) => void;
   setFolders: (folders: Folder[]) => void;
+  setFolder: (folder: Folder) => void;
+  removeFolder: (id: string) => void;
   setShowActiveSessionsGroup: (value: boolean) => void;
   setSession: (session: Session) => void;
   addSession: (session: Session) => void;
@@ -62,6 +74,21 @@ export const useGlobalStore = create<GlobalState>((set, get) => ({
   activeSessionId: null,
   isConnected: false,
   showActiveSessionsGroup: true,
+  asks: new Map(),
+  setAsks: (sessionId, asks) => {
+    const asksMap = new Map(get().asks);
+    asksMap.set(sessionId, asks);
+    set({ asks: asksMap });
+  },
+  addAsk: (ask) => {
+    const existing = (get().asks.get(ask.session_id) || []).filter((a) => a.id !== ask.id);
+    get().setAsks(ask.session_id, [...existing, ask]);
+  },
+  removeAsk: (sessionId, askId) => {
+    get().setAsks(sessionId, (get().asks.get(sessionId) || []).filter((a) => a.id !== askId));
+  },
+  notificationPrefs: null,
+  setNotificationPrefs: (prefs) => set({ notificationPrefs: prefs }),
   pendingScrollTargetUuid: null,
   setPendingScrollTarget: (uuid) => set({ pendingScrollTargetUuid: uuid }),
 
@@ -78,10 +105,13 @@ export const useGlobalStore = create<GlobalState>((set, get) => ({
   },
   clearSearchBackTrail: () => set({ cameFromSearch: false }),
 
-  setSessions: (sessions) => {
+  // Replaces the sessions of one hub (null: the desktop's own), keeping the
+  // others; the desktop's are listed first
+  setSessions: (sessions, hubId = null) => {
     const sessionsMap = new Map<string, Session>();
     const order: string[] = [];
     const statusMap = new Map<string, SessionStatus>();
+    const kept = get().sessionsOrder.filter((id) => (get().sessions.get(id)?.hub_id ?? null) !== hubId);
 
     // Sort by sort_order
     const sorted = [...sessions].sort((a, b) => a.sort_order - b.sort_order);
@@ -89,16 +119,26 @@ export const useGlobalStore = create<GlobalState>((set, get) => ({
     for (const session of sorted) {
       sessionsMap.set(session.id, session);
       order.push(session.id);
-      // Use running status from server, preserve isProcessing if we have it
+      // Use running/processing status from server; desktops that don't
+      // report processing keep whatever we last heard
       const existing = get().sessionStatus.get(session.id);
-      const running = (session as Session & { running?: boolean }).running ?? false;
+      const { running, processing } = session as Session & { running?: boolean; processing?: boolean };
       statusMap.set(session.id, {
-        running,
-        isProcessing: existing?.isProcessing ?? false,
+        running: running ?? false,
+        isProcessing: processing ?? existing?.isProcessing ?? false,
       });
     }
+    for (const id of kept) {
+      sessionsMap.set(id, get().sessions.get(id)!);
+      const status = get().sessionStatus.get(id);
+      if (status) statusMap.set(id, status);
+    }
 
-    set({ sessions: sessionsMap, sessionsOrder: order, sessionStatus: statusMap });
+    set({
+      sessions: sessionsMap,
+      sessionsOrder: hubId === null ? [...order, ...kept] : [...kept, ...order],
+      sessionStatus: statusMap,
+    });
   },
 
   setFolders: (folders) => {
@@ -109,6 +149,25 @@ export const useGlobalStore = create<GlobalState>((set, get) => ({
     set({ folders: foldersMap });
   },
 
+  setFolder: (folder) => {
+    const folders = new Map(get().folders);
+    folders.set(folder.id, folder);
+    set({ folders });
+  },
+
+  // Sessions in a deleted folder fall back to unfiled, as on the desktop
+  removeFolder: (id) => {
+    const folders = new Map(get().folders);
+    folders.delete(id);
+    const sessions = new Map(get().sessions);
+    for (const [sid, session] of sessions) {
+      if (session.folder_id === id) {
+        sessions.set(sid, { ...session, folder_id: null });
+      }
+    }
+    set({ folders, sessions });
+  },
+
   setSession: (session) => {
     const sessions = new Map(get().sessions);
     sessions.set(session.id, session);
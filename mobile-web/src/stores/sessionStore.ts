@@ -19,11 +19,17 @@ interface SessionState {
   // Message deduplication
   seenMessageKeys: Map<string, Set<string>>;
 
+  // Reply text streamed so far, until the full message arrives
+  streamingText: Map<string, string>;
+
   // Actions
   setMessages: (sessionId: string, messages: Message[]) => void;
   addMessage: (sessionId: string, message: Message) => boolean; // returns false if duplicate
   clearMessages: (sessionId: string) => void;
 
+  appendStreamingText: (sessionId: string, text: string) => void;
+  clearStreamingText: (sessionId: string) => void;
+
   setInputText: (sessionId: string, text: string) => void;
   addPendingImage: (sessionId: string, image: PendingImage) => void;
   removePendingImage: (sessionId: string, index: number) => void;
@@ -60,6 +66,7 @@ export const useSessionStore = create<SessionState>((set, get) => ({
   pendingImages: new Map(),
   scrollPosition: new Map(),
   seenMessageKeys: new Map(),
+  streamingText: new Map(),
 
   setMessages: (sessionId, messages) => {
     const messagesMap = new Map(get().messages);
@@ -131,6 +138,19 @@ export const useSessionStore = create<SessionState>((set, get) => ({
     set({ messages: messagesMap, seenMessageKeys: seenMap });
   },
 
+  appendStreamingText: (sessionId, text) => {
+    const streamingMap = new Map(get().streamingText);
+    streamingMap.set(sessionId, (streamingMap.get(sessionId) || '') + text);
+    set({ streamingText: streamingMap });
+  },
+
+  clearStreamingText: (sessionId) => {
+    if (!get().streamingText.has(sessionId)) return;
+    const streamingMap = new Map(get().streamingText);
+    streamingMap.delete(sessionId);
+    set({ streamingText: streamingMap });
+  },
+
   setInputText: (sessionId, text) => {
     const inputMap = new Map(get().inputText);
     inputMap.set(sessionId, text);
@@ -8,11 +8,105 @@ export interface Session {
   claude_session_id?: string;
   sort_order: number;
   folder_id?: string | null;
+  parent_id?: string | null;
+  summary?: string | null; // Auto-generated when the session goes idle
+  // Set on sessions from a remote hub (see services/hubs.ts)
+  hub_id?: string | null;
+  host?: string | null;
 }
 
 export interface SessionStatus {
   running: boolean;
   isProcessing: boolean;
+  contextUsedPct?: number | null; // % of the usable context window (claude-json only)
+  // Connections with the session open, this one included if it's subscribed
+  viewerCount?: number;
+  viewers?: SessionViewer[];
+}
+
+// Which push notifications this device gets (see the desktop's notify_prefs)
+export interface NotificationPrefs {
+  events?: ('result' | 'error' | 'attention')[] | null; // null: all of them
+  muted_sessions: string[];
+  quiet_hours?: { start: string; end: string } | null; // local "HH:MM", may run overnight
+}
+
+// A device with a session open: chat or terminal on a phone, or a session WebSocket
+export interface SessionViewer {
+  device_id: string | null;
+  device_name: string | null;
+  view: 'chat' | 'terminal' | 'stream';
+}
+
+// Desktop settings that can be read and changed remotely (GET/PATCH /api/settings)
+export interface RemoteSettings {
+  theme: string;
+  default_working_dir: string;
+  default_agent_type: string;
+  default_model: string | null;
+  notifications_enabled: boolean;
+  bell_notifications_enabled: boolean;
+  bounce_dock_on_bell: boolean;
+  read_aloud_enabled: boolean;
+  tts_voice: string | null;
+  show_active_sessions_group: boolean;
+  context_warning_pct: number;
+  auto_summaries_enabled: boolean;
+  hang_timeout_mins: number;
+  auto_restart_max_attempts: number;
+}
+
+// Agent Hub found on the LAN via mDNS (GET /api/instances)
+export interface DiscoveredInstance {
+  name: string;
+  host: string;
+  port: number;
+  version: string | null;
+  protocol_version: number | null;
+  tls: boolean;
+  url: string;
+}
+
+// Another Agent Hub registered on the desktop (GET /api/hubs)
+export interface RemoteHub {
+  id: string;
+  name: string;
+  url: string;
+  created_at: number;
+}
+
+// What a paired device may do; each scope includes the ones before it
+export type DeviceScope = 'read_only' | 'interact' | 'admin';
+
+// Paired device (GET /api/devices); `current` is the device making the request
+export interface PairedDevice {
+  id: string;
+  name: string;
+  paired_at: string;
+  last_seen: string;
+  expires_at: string | null;
+  scope: DeviceScope;
+  current: boolean;
+  // Chat messages sent to the device that it hasn't acknowledged
+  undelivered_count?: number;
+}
+
+// This device's push notification target (GET /api/push/subscription)
+export interface PushSubscriptionInfo {
+  kind: 'webpush' | 'ntfy' | 'webhook';
+  endpoint: string;
+  created_at: number;
+}
+
+// Audit log entry (GET /api/audit); no device means this computer
+export interface AuditEntry {
+  id: number;
+  ts: string;
+  device_id: string | null;
+  device_name: string | null;
+  action: string;
+  session_id: string | null;
+  source_ip: string | null;
 }
 
 export interface Folder {
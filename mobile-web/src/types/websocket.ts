@@ -1,23 +1,105 @@
-import type { Session, SessionStatus, Folder } from './session';
+import type { Session, SessionStatus, SessionViewer, Folder, RemoteSettings, DeviceScope, NotificationPrefs } from './session';
 import type { Message } from './message';
 
+// Classified agent failure (CLI missing, logged out, rate limited, ...)
+export interface SessionError {
+  code: 'cli_not_found' | 'not_logged_in' | 'rate_limited' | 'context_overflow' | 'spawn_failed';
+  message: string;
+  detail: string;
+}
+
+// A question or approval request from a session's agent, waiting on the user
+export interface AgentAsk {
+  id: string;
+  session_id: string;
+  kind: 'question' | 'approval';
+  prompt: string;
+  options: string[]; // Suggested answers to a question
+  created_at: number;
+}
+
+// Protocol version this client speaks; sent with auth and negotiated down by
+// the desktop (see auth_success)
+//   2: session management messages (create_session, ...)
+//   3: terminal streaming (subscribe_terminal, ...)
+//   4: seq on chat messages, subscribe with since_seq
+//   5: ack for chat messages; unacked ones are resent on reconnect
+//   6: large messages arrive gzipped in binary frames (when auth asks for it)
+//   7: agent_typing and agent_delta (partial reply text)
+//   8: presence (who has each session open)
+//   9: chat and terminal messages in CBOR binary frames (when auth asks for it)
+//  10: after auth the session list only changes by session_* / folder_* events
+//  11: filters on subscribe, applied by the desktop before sending
+//  12: get_notification_prefs / set_notification_prefs
+//  13: session_ask / session_ask_settled and answer_question
+export const PROTOCOL_VERSION = 13;
+
+// Content left out of a subscription's chat messages
+export interface ChatFilters {
+  exclude_tool_results?: boolean;
+  text_only?: boolean;
+}
+
 // Client -> Server messages
 export type ClientMessage =
-  | { type: 'auth'; token: string }
-  | { type: 'subscribe'; sessionId: string }
+  | { type: 'auth'; token: string; protocolVersion?: number; compression?: 'gzip'; encoding?: 'cbor' }
+  | { type: 'subscribe'; sessionId: string; since_seq?: number; filters?: ChatFilters }
   | { type: 'unsubscribe'; sessionId: string }
+  | { type: 'ack'; sessionId: string; seq: number }
   | { type: 'send_message'; sessionId: string; content: unknown }
-  | { type: 'interrupt'; sessionId: string };
+  | { type: 'interrupt'; sessionId: string }
+  | { type: 'create_session'; requestId: string; name?: string; agentType?: string; workingDir?: string; folderId?: string | null }
+  | { type: 'rename_session'; requestId: string; sessionId: string; name: string }
+  | { type: 'move_session_to_folder'; requestId: string; sessionId: string; folderId: string | null }
+  | { type: 'delete_session'; requestId: string; sessionId: string }
+  | { type: 'stop_session'; requestId: string; sessionId: string }
+  | { type: 'subscribe_terminal'; sessionId: string }
+  | { type: 'unsubscribe_terminal'; sessionId: string }
+  | { type: 'terminal_input'; sessionId: string; data: string }
+  | { type: 'terminal_resize'; sessionId: string; cols: number; rows: number }
+  | { type: 'get_notification_prefs' }
+  | { type: 'set_notification_prefs'; prefs: NotificationPrefs }
+  | { type: 'answer_question'; askId: string; text?: string; approved?: boolean };
+
+export type SessionAction = 'create_session' | 'rename_session' | 'move_session_to_folder' | 'delete_session' | 'stop_session';
 
 // Server -> Client messages
 export type ServerMessage =
-  | { type: 'auth_success' }
+  | { type: 'auth_success'; protocolVersion?: number; serverProtocolVersion?: number; serverVersion?: string; scope?: DeviceScope }
   | { type: 'auth_error'; message: string }
+  | { type: 'auth_revoked'; reason: string }
   | { type: 'session_list'; sessions: Session[]; folders?: Folder[]; settings?: { show_active_sessions_group?: boolean } }
   | { type: 'session_status'; sessionId: string; status: SessionStatus }
   | { type: 'session_created'; session: Session }
   | { type: 'session_updated'; session: Session }
   | { type: 'session_deleted'; sessionId: string }
-  | { type: 'chat_message'; sessionId: string; message: Message }
-  | { type: 'chat_history'; sessionId: string; messages: Message[] }
+  | { type: 'folder_created'; folder: Folder }
+  | { type: 'folder_updated'; folder: Folder }
+  | { type: 'folder_deleted'; folderId: string }
+  | { type: 'settings_updated'; settings: RemoteSettings }
+  // seq: position in the session's message stream (absent from older desktops);
+  // retry: resent after a reconnect because it was never acked
+  | { type: 'chat_message'; sessionId: string; seq?: number | null; retry?: boolean; message: Message }
+  | { type: 'chat_history'; sessionId: string; seq?: number; messages: Message[] }
+  // text: the next piece of the reply being written, shown until the full message arrives
+  | { type: 'agent_typing'; sessionId: string; typing: boolean }
+  | { type: 'agent_delta'; sessionId: string; text: string }
+  | { type: 'presence'; sessionId: string; viewerCount: number; viewers: SessionViewer[] }
+  | { type: 'session_error'; sessionId: string; error: SessionError }
+  | { type: 'session_ask'; sessionId: string; ask: AgentAsk }
+  | { type: 'session_ask_settled'; sessionId: string; askId: string }
+  | { type: 'session_attention'; sessionId: string; message: string }
+  | { type: 'context_warning'; sessionId: string; contextUsedPct: number; thresholdPct: number }
+  | { type: 'session_hung'; sessionId: string; silentSecs: number; policy: string }
+  | { type: 'session_recovered'; sessionId: string }
+  | { type: 'session_starting'; sessionId: string }
+  | { type: 'session_ready'; sessionId: string }
+  | { type: 'session_restarted'; sessionId: string; attempt: number; maxAttempts: number }
+  | { type: 'session_action_result'; action: SessionAction; requestId?: string; ok: boolean; session?: Session | null; error?: string }
+  // buffer: last scrollback the desktop saved; data: PTY output, base64 in
+  // JSON and raw bytes in CBOR frames
+  | { type: 'terminal_subscribed'; sessionId: string; buffer: string | null }
+  | { type: 'terminal_output'; sessionId: string; data: string | Uint8Array }
+  | { type: 'terminal_closed'; sessionId: string }
+  | { type: 'notification_prefs'; prefs: NotificationPrefs }
   | { type: 'error'; message: string };
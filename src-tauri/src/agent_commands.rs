@@ -0,0 +1,77 @@
+// Launch command per agent type.
+//
+// Built-in defaults can be overridden with the `agent_commands` app setting
+// (agent type -> command template). Templates may use:
+//
+//   {model}  `--model '<default_model>'`, or nothing when no default is set
+//   $SHELL   the user's login shell
+//
+// The desktop frontend applies the same rules when it creates sessions.
+
+use std::collections::HashMap;
+
+pub const CLAUDE_JSON_COMMAND: &str =
+    "claude --print --verbose --input-format stream-json --output-format stream-json --dangerously-skip-permissions";
+
+fn builtin(agent_type: &str) -> Option<&'static str> {
+    match agent_type {
+        "claude" => Some("claude --dangerously-skip-permissions"),
+        "claude-json" => Some(CLAUDE_JSON_COMMAND),
+        "codex" => Some("codex --full-auto"),
+        "aider" => Some("aider"),
+        "shell" => Some("$SHELL"),
+        _ => None,
+    }
+}
+
+/// Command for a new session of `agent_type`, honoring the user's
+/// overrides. None for types without a default (custom / unknown).
+pub fn command_for(agent_type: &str) -> Option<String> {
+    let settings = crate::load_app_settings().unwrap_or_default();
+    resolve(&settings.agent_commands, settings.default_model.as_deref(), agent_type)
+}
+
+fn resolve(overrides: &HashMap<String, String>, default_model: Option<&str>, agent_type: &str) -> Option<String> {
+    let template = overrides
+        .get(agent_type)
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .or_else(|| builtin(agent_type))?;
+    Some(expand(template, default_model))
+}
+
+fn expand(template: &str, default_model: Option<&str>) -> String {
+    let model = default_model
+        .filter(|m| !m.is_empty())
+        .map(|m| format!("--model '{}'", m.replace('\'', "'\\''")))
+        .unwrap_or_default();
+    let shell = crate::platform::user_shell();
+    let template = if model.is_empty() {
+        template.replace(" {model}", "").replace("{model}", "")
+    } else {
+        template.replace("{model}", &model)
+    };
+    template.replace("$SHELL", &shell)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn override_replaces_builtin() {
+        let mut overrides = HashMap::new();
+        overrides.insert("claude".to_string(), "claude {model} --verbose".to_string());
+        assert_eq!(resolve(&overrides, None, "claude").as_deref(), Some("claude --verbose"));
+        assert_eq!(resolve(&overrides, None, "aider").as_deref(), Some("aider"));
+        assert_eq!(resolve(&overrides, None, "custom"), None);
+    }
+
+    #[test]
+    fn model_placeholder_is_quoted() {
+        assert_eq!(
+            expand("claude {model} --print", Some("claude-opus-4-7[1m]")),
+            "claude --model 'claude-opus-4-7[1m]' --print"
+        );
+    }
+}
@@ -0,0 +1,205 @@
+// The environment JSON sessions' agents run in, captured once from the
+// user's login shell.
+//
+// Sessions used to start as `$SHELL -i -l -c '<command>'`, which runs the
+// user's rc files (nvm, oh-my-zsh, prompt themes: often a second or more)
+// every time. Instead the shell is run once, in the background at startup,
+// to print its environment, and claude is resolved against that PATH. A
+// command that's only claude and its arguments is then exec'd by /bin/sh,
+// which reads no rc files, with that environment; one using shell syntax
+// (`&&`, pipes, variables) still goes through the login shell. Until the
+// capture finishes, starting a session waits for it; when it fails, or
+// claude isn't found (installed since, say), sessions go through the login
+// shell as before.
+
+use once_cell::sync::OnceCell;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+/// Printed before the environment, so whatever the rc files print is skipped
+const MARKER: &str = "__AGENT_HUB_ENV__";
+
+/// Longest the login shell gets to start; rc files that wait on something
+/// shouldn't hold sessions up for good
+const CAPTURE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Variables the shell sets for itself rather than for what it runs
+const SHELL_OWN: &[&str] = &["PWD", "OLDPWD", "SHLVL", "_"];
+
+pub struct AgentEnv {
+    pub vars: Vec<(String, String)>,
+    /// Absolute path of the claude binary, if it's on the shell's PATH
+    pub claude: Option<PathBuf>,
+}
+
+static ENV: OnceCell<Option<AgentEnv>> = OnceCell::new();
+
+/// Capture the environment in the background, so the first session doesn't
+/// wait for it
+pub fn prewarm() {
+    std::thread::spawn(|| {
+        get();
+    });
+}
+
+/// The captured environment, capturing it first if need be (blocking: call
+/// from a thread or spawn_blocking). None if the shell couldn't be run.
+pub fn get() -> Option<&'static AgentEnv> {
+    ENV.get_or_init(|| {
+        let started = std::time::Instant::now();
+        match capture() {
+            Ok(env) => {
+                tracing::info!(
+                    "[agent_env] captured {} vars in {:?}, claude at {:?}",
+                    env.vars.len(),
+                    started.elapsed(),
+                    env.claude
+                );
+                Some(env)
+            }
+            Err(e) => {
+                tracing::warn!("[agent_env] sessions will start through the login shell: {}", e);
+                None
+            }
+        }
+    })
+    .as_ref()
+}
+
+fn capture() -> Result<AgentEnv, String> {
+    let mut child = Command::new(crate::platform::user_shell())
+        .args(["-i", "-l", "-c", &format!("printf '\\n%s\\n' {}; env -0", MARKER)])
+        .env("PATH", crate::platform::agent_path())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("Failed to run login shell: {}", e))?;
+    let mut stdout = child.stdout.take().ok_or("No shell output")?;
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let mut output = Vec::new();
+        let _ = std::io::Read::read_to_end(&mut stdout, &mut output);
+        let _ = tx.send(output);
+    });
+    let output = match rx.recv_timeout(CAPTURE_TIMEOUT) {
+        Ok(output) => output,
+        Err(_) => {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(format!("Login shell didn't finish in {:?}", CAPTURE_TIMEOUT));
+        }
+    };
+    let _ = child.wait();
+    let vars = parse_env(&String::from_utf8_lossy(&output)).ok_or("Login shell printed no environment")?;
+    let claude = vars
+        .iter()
+        .find(|(key, _)| key == "PATH")
+        .and_then(|(_, path)| find_on_path("claude", path));
+    Ok(AgentEnv { vars, claude })
+}
+
+/// The NUL-separated `env -0` output following MARKER
+fn parse_env(output: &str) -> Option<Vec<(String, String)>> {
+    let (_, env) = output.split_once(&format!("\n{}\n", MARKER))?;
+    Some(
+        env.split('\0')
+            .filter_map(|entry| entry.split_once('='))
+            .filter(|(key, _)| !key.is_empty() && !SHELL_OWN.contains(key))
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect(),
+    )
+}
+
+fn find_on_path(program: &str, path: &str) -> Option<PathBuf> {
+    use std::os::unix::fs::PermissionsExt;
+    path.split(':')
+        .filter(|dir| !dir.is_empty())
+        .map(|dir| Path::new(dir).join(program))
+        .find(|candidate| candidate.metadata().is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0))
+}
+
+/// Characters that make a command more than one program with arguments
+const SHELL_CONTROL: &[char] = &[';', '&', '|', '<', '>', '$', '`', '\n'];
+
+/// `command` for /bin/sh to exec with claude as `claude`, or None if it
+/// doesn't just run claude (custom wrappers, `&&`, pipes, redirects,
+/// variables) and needs the login shell
+pub fn direct_command(command: &str, claude: &Path) -> Option<String> {
+    let rest = command.trim_start().strip_prefix("claude")?;
+    if !rest.is_empty() && !rest.starts_with(' ') {
+        return None;
+    }
+    // exec would replace the shell before the rest of a list ran
+    if rest.contains(SHELL_CONTROL) {
+        return None;
+    }
+    let quoted = claude.to_string_lossy().replace('\'', "'\\''");
+    Some(format!("exec '{}'{}", quoted, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn environment_is_read_after_the_marker() {
+        let output = format!(
+            "Welcome back!\n\n{}\nHOME=/home/me\0PATH=/home/me/.local/bin:/usr/bin\0NOTE=two\nlines\0SHLVL=2\0PWD=/tmp\0",
+            MARKER
+        );
+        let vars = parse_env(&output).unwrap();
+        assert_eq!(
+            vars,
+            vec![
+                ("HOME".to_string(), "/home/me".to_string()),
+                ("PATH".to_string(), "/home/me/.local/bin:/usr/bin".to_string()),
+                ("NOTE".to_string(), "two\nlines".to_string()),
+            ]
+        );
+        assert!(parse_env("no marker here").is_none());
+    }
+
+    #[test]
+    fn claude_commands_are_execd_directly() {
+        let claude = Path::new("/home/me/.local/bin/claude");
+        assert_eq!(
+            direct_command("claude --mcp-config '/tmp/it'\\''s.json' -p", claude).as_deref(),
+            Some("exec '/home/me/.local/bin/claude' --mcp-config '/tmp/it'\\''s.json' -p")
+        );
+        assert_eq!(direct_command("claude", claude).as_deref(), Some("exec '/home/me/.local/bin/claude'"));
+        assert_eq!(direct_command("claude-wrapper --json", claude), None);
+        assert_eq!(direct_command("npx claude", claude), None);
+    }
+
+    #[test]
+    fn shell_syntax_goes_through_the_login_shell() {
+        let claude = Path::new("/usr/bin/claude");
+        for command in [
+            "claude --foo && say done",
+            "claude; cleanup",
+            "claude -p | tee log",
+            "claude > out.txt",
+            "claude --model $MODEL",
+            "claude `cat args`",
+            "claude &",
+            "claude\nsay done",
+        ] {
+            assert_eq!(direct_command(command, claude), None, "{}", command);
+        }
+    }
+
+    #[test]
+    fn programs_are_found_on_the_path() {
+        let dir = std::env::temp_dir().join(format!("agent-hub-path-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let program = dir.join("claude");
+        std::fs::write(&program, "#!/bin/sh\n").unwrap();
+        assert_eq!(find_on_path("claude", &format!("/nonexistent:{}", dir.display())), None);
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&program, std::fs::Permissions::from_mode(0o755)).unwrap();
+        assert_eq!(find_on_path("claude", &format!("/nonexistent::{}", dir.display())), Some(program));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
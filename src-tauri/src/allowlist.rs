@@ -0,0 +1,94 @@
+// Client IP allowlist for the web server (`ip_allowlist` in settings).
+//
+// Entries are CIDRs ("192.168.10.0/24", "fd00::/8") or single addresses.
+// An empty list allows everyone. This computer is always allowed, so the MCP
+// bridge keeps working and a mistyped list can't lock out the desktop app.
+//
+// Tunnels connect over loopback too. Tailscale Funnel (like any local reverse
+// proxy) names the real client in X-Forwarded-For, so a loopback request
+// carrying that header is judged by the forwarded address instead. An SSH
+// reverse tunnel carries no such header: its clients look local and pass.
+
+use ipnet::IpNet;
+use std::net::IpAddr;
+
+#[derive(Debug, Clone, Default)]
+pub struct Allowlist(Vec<IpNet>);
+
+impl Allowlist {
+    pub fn parse(entries: &[String]) -> Result<Allowlist, String> {
+        entries
+            .iter()
+            .map(|e| e.trim())
+            .filter(|e| !e.is_empty())
+            .map(|e| {
+                e.parse::<IpNet>()
+                    .or_else(|_| e.parse::<IpAddr>().map(IpNet::from))
+                    .map_err(|_| format!("'{}' is not an IP address or CIDR range", e))
+            })
+            .collect::<Result<_, _>>()
+            .map(Allowlist)
+    }
+
+    /// Only this computer.
+    pub fn local_only() -> Allowlist {
+        Allowlist(vec![IpNet::from(IpAddr::from(std::net::Ipv4Addr::LOCALHOST))])
+    }
+
+    pub fn allows(&self, ip: IpAddr) -> bool {
+        // IPv4 clients of a dual-stack listener show up as ::ffff:a.b.c.d
+        let ip = ip.to_canonical();
+        self.0.is_empty() || ip.is_loopback() || self.0.iter().any(|net| net.contains(&ip))
+    }
+
+    /// `allows` for a connection from `peer` carrying `forwarded_for` (the
+    /// X-Forwarded-For header). Only loopback peers are trusted to forward;
+    /// the last entry is the one the local proxy added. A header that names
+    /// no address is refused rather than treated as local.
+    pub fn allows_forwarded(&self, peer: IpAddr, forwarded_for: Option<&str>) -> bool {
+        match forwarded_for {
+            Some(header) if peer.to_canonical().is_loopback() => {
+                let client = header.rsplit(',').next().and_then(|ip| ip.trim().parse::<IpAddr>().ok());
+                self.0.is_empty() || client.is_some_and(|ip| self.allows(ip))
+            }
+            _ => self.allows(peer),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_ranges_addresses_and_loopback() {
+        let list = Allowlist::parse(&["192.168.10.0/24".into(), " 10.0.0.7 ".into(), "".into(), "fd00::/8".into()]).unwrap();
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        assert!(list.allows(ip("192.168.10.42")));
+        assert!(list.allows(ip("::ffff:192.168.10.42")));
+        assert!(list.allows(ip("10.0.0.7")));
+        assert!(list.allows(ip("fd12::1")));
+        assert!(list.allows(ip("127.0.0.1")));
+        assert!(!list.allows(ip("192.168.20.5")));
+        assert!(!list.allows(ip("10.0.0.8")));
+
+        assert!(Allowlist::default().allows(ip("203.0.113.9")));
+        assert!(Allowlist::parse(&["192.168.10.0/33".into()]).is_err());
+        assert!(Allowlist::parse(&["office".into()]).is_err());
+    }
+
+    #[test]
+    fn loopback_peers_are_judged_by_the_forwarded_client() {
+        let list = Allowlist::parse(&["192.168.10.0/24".into()]).unwrap();
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        assert!(list.allows_forwarded(ip("127.0.0.1"), None));
+        assert!(list.allows_forwarded(ip("127.0.0.1"), Some("192.168.10.42")));
+        assert!(!list.allows_forwarded(ip("127.0.0.1"), Some("203.0.113.9")));
+        // Only the proxy's own (last) entry counts
+        assert!(!list.allows_forwarded(ip("::1"), Some("192.168.10.42, 203.0.113.9")));
+        assert!(!list.allows_forwarded(ip("127.0.0.1"), Some("unknown")));
+        // Remote peers can't vouch for themselves
+        assert!(!list.allows_forwarded(ip("203.0.113.9"), Some("192.168.10.42")));
+        assert!(Allowlist::default().allows_forwarded(ip("127.0.0.1"), Some("203.0.113.9")));
+    }
+}
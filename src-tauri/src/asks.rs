@@ -0,0 +1,210 @@
+// Questions and approval requests a session's agent puts to the user, through
+// the ask_user and request_approval MCP tools (see mcp::session_config).
+//
+// An ask waits here until someone answers it (POST /api/asks/{id}/answer)
+// or the tool call waiting on it gives up. Mobile clients get a
+// `session_ask` message when one is raised and `session_ask_settled` when
+// it's gone either way; the desktop gets the matching session-ask and
+// session-ask-settled events. Devices not watching the session are pushed
+// an attention notification.
+//
+// signal_attention is the fire-and-forget version: `session_attention` /
+// session-attention and the same push, with nothing to answer.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tauri::Emitter;
+use tokio::sync::oneshot;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AskKind {
+    /// Answered with text, or one of `options`
+    Question,
+    /// Answered with approve or deny
+    Approval,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Ask {
+    pub id: String,
+    pub session_id: String,
+    pub kind: AskKind,
+    pub prompt: String,
+    /// Suggested answers to a question; empty for free text
+    pub options: Vec<String>,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+pub struct Answer {
+    /// The reply to a question, or a note with an approval
+    #[serde(default)]
+    pub text: Option<String>,
+    /// Approvals only
+    #[serde(default)]
+    pub approved: Option<bool>,
+}
+
+type Waiting = (Ask, oneshot::Sender<Answer>);
+
+#[derive(Default)]
+pub(crate) struct Asks {
+    open: HashMap<String, Waiting>,
+}
+
+impl Asks {
+    fn pending(&self, session_id: Option<&str>) -> Vec<Ask> {
+        let mut asks: Vec<Ask> = self
+            .open
+            .values()
+            .map(|(ask, _)| ask.clone())
+            .filter(|ask| session_id.is_none_or(|id| ask.session_id == id))
+            .collect();
+        asks.sort_by_key(|ask| ask.created_at);
+        asks
+    }
+
+    /// Close the ask if `answer` answers it: an approval needs `approved`,
+    /// a question needs text
+    fn take(&mut self, ask_id: &str, answer: &Answer) -> Result<Waiting, String> {
+        let (ask, _) = self.open.get(ask_id).ok_or("No open ask with that id")?;
+        match ask.kind {
+            AskKind::Approval if answer.approved.is_none() => Err("An approval needs 'approved'".to_string()),
+            AskKind::Question if answer.text.as_deref().is_none_or(|t| t.trim().is_empty()) => {
+                Err("A question needs 'text'".to_string())
+            }
+            _ => self.open.remove(ask_id).ok_or_else(|| "No open ask with that id".to_string()),
+        }
+    }
+}
+
+/// Withdraws the ask when the waiting tool call finishes or is cancelled
+pub struct AskGuard(String);
+
+impl Drop for AskGuard {
+    fn drop(&mut self) {
+        let withdrawn = crate::state::get().asks.lock().open.remove(&self.0);
+        if let Some((ask, _)) = withdrawn {
+            announce_settled(&ask);
+        }
+    }
+}
+
+fn new_ask(session_id: &str, kind: AskKind, prompt: &str, options: Vec<String>) -> Ask {
+    Ask {
+        id: uuid::Uuid::new_v4().to_string(),
+        session_id: session_id.to_string(),
+        kind,
+        prompt: prompt.to_string(),
+        options,
+        created_at: chrono::Utc::now().timestamp(),
+    }
+}
+
+/// Put an ask to the user; the receiver gets the answer
+pub fn ask(session_id: &str, kind: AskKind, prompt: &str, options: Vec<String>) -> (Ask, AskGuard, oneshot::Receiver<Answer>) {
+    let ask = new_ask(session_id, kind, prompt, options);
+    let (tx, rx) = oneshot::channel();
+    crate::state::get().asks.lock().open.insert(ask.id.clone(), (ask.clone(), tx));
+    announce(&ask);
+    let guard = AskGuard(ask.id.clone());
+    (ask, guard, rx)
+}
+
+/// Open asks, oldest first; only the session's when one is given
+pub fn pending(session_id: Option<&str>) -> Vec<Ask> {
+    crate::state::get().asks.lock().pending(session_id)
+}
+
+/// The session an open ask belongs to
+pub fn session_of(ask_id: &str) -> Option<String> {
+    crate::state::get().asks.lock().open.get(ask_id).map(|(ask, _)| ask.session_id.clone())
+}
+
+/// Answer an open ask, handing the answer to the tool call waiting on it
+pub fn answer(ask_id: &str, answer: Answer) -> Result<(), String> {
+    let (ask, sender) = crate::state::get().asks.lock().take(ask_id, &answer)?;
+    let _ = sender.send(answer);
+    announce_settled(&ask);
+    Ok(())
+}
+
+/// The agent needs the user but isn't waiting on a reply
+pub fn signal_attention(session_id: &str, message: &str) {
+    crate::broadcast_to_mobile_clients(&serde_json::json!({
+        "type": "session_attention",
+        "sessionId": session_id,
+        "message": message,
+    }).to_string());
+    if let Some(app) = crate::state::get().app_handle.lock().as_ref() {
+        let _ = app.emit("session-attention", serde_json::json!({
+            "session_id": session_id,
+            "message": message,
+        }));
+    }
+    crate::attention::flag(session_id);
+    crate::push::on_attention(session_id, message);
+    crate::desktop_notify::on_attention(session_id, message);
+}
+
+fn announce(ask: &Ask) {
+    crate::broadcast_to_mobile_clients(&serde_json::json!({
+        "type": "session_ask",
+        "sessionId": ask.session_id,
+        "ask": ask,
+    }).to_string());
+    if let Some(app) = crate::state::get().app_handle.lock().as_ref() {
+        let _ = app.emit("session-ask", ask);
+    }
+    crate::attention::flag(&ask.session_id);
+    crate::push::on_ask(&ask.session_id, &ask.prompt);
+    crate::desktop_notify::on_ask(&ask.session_id, &ask.prompt);
+}
+
+fn announce_settled(ask: &Ask) {
+    if pending(Some(&ask.session_id)).is_empty() {
+        crate::attention::clear(&ask.session_id);
+    }
+    crate::broadcast_to_mobile_clients(&serde_json::json!({
+        "type": "session_ask_settled",
+        "sessionId": ask.session_id,
+        "askId": ask.id,
+    }).to_string());
+    if let Some(app) = crate::state::get().app_handle.lock().as_ref() {
+        let _ = app.emit("session-ask-settled", serde_json::json!({
+            "session_id": ask.session_id,
+            "ask_id": ask.id,
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open(asks: &mut Asks, session_id: &str, kind: AskKind) -> (Ask, oneshot::Receiver<Answer>) {
+        let ask = new_ask(session_id, kind, "Go ahead?", vec![]);
+        let (tx, rx) = oneshot::channel();
+        asks.open.insert(ask.id.clone(), (ask.clone(), tx));
+        (ask, rx)
+    }
+
+    #[test]
+    fn questions_need_text_and_approvals_a_decision() {
+        let mut asks = Asks::default();
+        let (question, _rx) = open(&mut asks, "s1", AskKind::Question);
+        let (approval, _rx2) = open(&mut asks, "s2", AskKind::Approval);
+
+        assert!(asks.take(&question.id, &Answer { text: Some(" ".into()), approved: None }).is_err());
+        assert!(asks.take(&approval.id, &Answer { text: Some("sure".into()), approved: None }).is_err());
+        assert_eq!(asks.pending(None).len(), 2);
+
+        let (taken, sender) = asks.take(&approval.id, &Answer { text: None, approved: Some(false) }).unwrap();
+        assert_eq!(taken.session_id, "s2");
+        sender.send(Answer { text: None, approved: Some(false) }).unwrap();
+        assert!(asks.take(&approval.id, &Answer::default()).is_err());
+        assert_eq!(asks.pending(Some("s1")).len(), 1);
+        assert!(asks.pending(Some("s2")).is_empty());
+    }
+}
@@ -0,0 +1,123 @@
+// Image attachments uploaded from remote clients (e.g. a phone photo of a
+// whiteboard) for claude-json sessions.
+//
+// Uploads are kept under <app data>/attachments/<session id>/ so the
+// conversation can be audited later, and sent to the session as a base64
+// image content block, optionally followed by a text caption.
+
+use serde::Serialize;
+use std::path::PathBuf;
+
+/// The API rejects larger images.
+const MAX_IMAGE_BYTES: usize = 5 * 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Attachment {
+    pub id: String,
+    pub media_type: String,
+    pub path: String,
+    pub size: usize,
+}
+
+fn attachments_dir(session_id: &str) -> PathBuf {
+    crate::platform::data_dir()
+        .join("attachments")
+        .join(session_id)
+}
+
+/// Identify an image from its magic bytes. Only the formats Claude accepts
+/// are recognized; anything else (HEIC, SVG, ...) is rejected.
+fn sniff_media_type(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if data.starts_with(b"\xff\xd8\xff") {
+        Some("image/jpeg")
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if data.len() >= 12 && &data[0..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else {
+        None
+    }
+}
+
+fn extension(media_type: &str) -> &'static str {
+    match media_type {
+        "image/png" => "png",
+        "image/gif" => "gif",
+        "image/webp" => "webp",
+        _ => "jpg",
+    }
+}
+
+/// Validate and store an uploaded image.
+pub fn save_attachment(session_id: &str, data: &[u8]) -> Result<Attachment, String> {
+    if data.len() > MAX_IMAGE_BYTES {
+        return Err(format!(
+            "Image is {:.1} MB; the limit is {} MB",
+            data.len() as f64 / (1024.0 * 1024.0),
+            MAX_IMAGE_BYTES / (1024 * 1024)
+        ));
+    }
+    let media_type = sniff_media_type(data)
+        .ok_or_else(|| "Unsupported image type (use JPEG, PNG, GIF or WebP)".to_string())?;
+
+    let dir = attachments_dir(session_id);
+    std::fs::create_dir_all(&dir).map_err(|e| format!("Failed to create attachments dir: {}", e))?;
+    let id = uuid::Uuid::new_v4().to_string();
+    let path = dir.join(format!("{}.{}", id, extension(media_type)));
+    std::fs::write(&path, data).map_err(|e| format!("Failed to save attachment: {}", e))?;
+
+    Ok(Attachment {
+        id,
+        media_type: media_type.to_string(),
+        path: path.to_string_lossy().to_string(),
+        size: data.len(),
+    })
+}
+
+/// User message content for an image plus optional caption, in the
+/// stream-json input format.
+pub fn content_blocks(data: &[u8], media_type: &str, caption: Option<&str>) -> serde_json::Value {
+    use base64::Engine;
+    let mut blocks = vec![serde_json::json!({
+        "type": "image",
+        "source": {
+            "type": "base64",
+            "media_type": media_type,
+            "data": base64::engine::general_purpose::STANDARD.encode(data),
+        }
+    })];
+    if let Some(text) = caption.map(str::trim).filter(|t| !t.is_empty()) {
+        blocks.push(serde_json::json!({ "type": "text", "text": text }));
+    }
+    serde_json::Value::Array(blocks)
+}
+
+/// Remove a deleted session's uploads.
+pub fn delete_for_session(session_id: &str) {
+    let _ = std::fs::remove_dir_all(attachments_dir(session_id));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognizes_supported_formats() {
+        assert_eq!(sniff_media_type(b"\x89PNG\r\n\x1a\n...."), Some("image/png"));
+        assert_eq!(sniff_media_type(b"\xff\xd8\xff\xe0JFIF"), Some("image/jpeg"));
+        assert_eq!(sniff_media_type(b"GIF89a...."), Some("image/gif"));
+        assert_eq!(sniff_media_type(b"RIFF\x00\x00\x00\x00WEBPVP8 "), Some("image/webp"));
+        assert_eq!(sniff_media_type(b"\x00\x00\x00\x18ftypheic"), None);
+    }
+
+    #[test]
+    fn image_block_precedes_caption() {
+        let blocks = content_blocks(b"abc", "image/png", Some("  what does this say? "));
+        assert_eq!(blocks[0]["source"]["media_type"], "image/png");
+        assert_eq!(blocks[0]["source"]["data"], "YWJj");
+        assert_eq!(blocks[1], serde_json::json!({"type": "text", "text": "what does this say?"}));
+        assert_eq!(content_blocks(b"abc", "image/png", Some(" ")).as_array().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,26 @@
+// Sessions waiting on the user: an open question or approval (asks.rs), an
+// agent's signal_attention, or the hang watchdog. A session stays flagged
+// until it's opened in the main window, its asks are all settled, or it's
+// deleted. The count is the tray's at-a-glance badge and the dock badge.
+
+pub fn flag(session_id: &str) {
+    if crate::state::get().attention_flagged.lock().insert(session_id.to_string()) {
+        crate::tray::refresh();
+        crate::dock::on_attention();
+    }
+}
+
+pub fn clear(session_id: &str) {
+    if crate::state::get().attention_flagged.lock().remove(session_id) {
+        crate::tray::refresh();
+        crate::dock::refresh();
+    }
+}
+
+pub fn is_flagged(session_id: &str) -> bool {
+    crate::state::get().attention_flagged.lock().contains(session_id)
+}
+
+pub fn count() -> usize {
+    crate::state::get().attention_flagged.lock().len()
+}
@@ -0,0 +1,171 @@
+// Audit log of what paired devices (and local tools) did through the web
+// API: every state-changing request, every WebSocket connection, and the
+// mobile WebSocket's send_message / interrupt messages. Reads are not
+// recorded. Entries older than RETENTION_DAYS are pruned at startup.
+
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+
+const RETENTION_DAYS: i64 = 90;
+const DEFAULT_LIMIT: u32 = 200;
+const MAX_LIMIT: u32 = 1000;
+
+// =====================================================================
+//  Schema
+// =====================================================================
+
+pub fn run_audit_migrations(conn: &Connection) {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS audit_log (
+            id          INTEGER PRIMARY KEY AUTOINCREMENT,
+            ts          TEXT NOT NULL,
+            device_id   TEXT,
+            device_name TEXT,
+            action      TEXT NOT NULL,
+            session_id  TEXT,
+            source_ip   TEXT
+        )",
+        [],
+    )
+    .expect("Failed to create audit_log table");
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_audit_log_session ON audit_log(session_id, id)", [])
+        .expect("Failed to create audit_log index");
+
+    let cutoff = (chrono::Utc::now() - chrono::Duration::days(RETENTION_DAYS)).to_rfc3339();
+    let _ = conn.execute("DELETE FROM audit_log WHERE ts < ?1", params![cutoff]);
+}
+
+// =====================================================================
+//  Recording
+// =====================================================================
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub ts: String,
+    /// None for requests from this machine (the MCP bridge, local scripts)
+    /// and before any device is paired.
+    pub device_id: Option<String>,
+    pub device_name: Option<String>,
+    /// "POST /api/sessions/:session_id/input", "ws /api/ws/mobile",
+    /// "ws:send_message", ...
+    pub action: String,
+    pub session_id: Option<String>,
+    pub source_ip: Option<String>,
+}
+
+fn insert(conn: &Connection, entry: &AuditEntry) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO audit_log (ts, device_id, device_name, action, session_id, source_ip)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![entry.ts, entry.device_id, entry.device_name, entry.action, entry.session_id, entry.source_ip],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Record an action by `device` (None = local or pre-pairing).
+pub fn record(
+    device: Option<&crate::PairedDevice>,
+    action: &str,
+    session_id: Option<&str>,
+    source_ip: Option<std::net::IpAddr>,
+) {
+    let entry = AuditEntry {
+        id: 0,
+        ts: chrono::Utc::now().to_rfc3339(),
+        device_id: device.map(|d| d.id.clone()),
+        device_name: device.map(|d| d.name.clone()),
+        action: action.to_string(),
+        session_id: session_id.map(str::to_string),
+        source_ip: source_ip.map(|ip| ip.to_string()),
+    };
+    if let Err(e) = insert(&crate::state::get().db().lock(), &entry) {
+        tracing::error!("failed to record {}: {}", action, e);
+    }
+}
+
+// =====================================================================
+//  Querying
+// =====================================================================
+
+#[derive(Debug, Default, Deserialize)]
+pub struct AuditQuery {
+    pub device_id: Option<String>,
+    pub session_id: Option<String>,
+    /// Only entries with a smaller id (for paging back).
+    pub before: Option<i64>,
+    pub limit: Option<u32>,
+}
+
+/// Newest first.
+fn query(conn: &Connection, q: &AuditQuery) -> Result<Vec<AuditEntry>, String> {
+    let limit = q.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, ts, device_id, device_name, action, session_id, source_ip FROM audit_log
+             WHERE (?1 IS NULL OR device_id = ?1)
+               AND (?2 IS NULL OR session_id = ?2)
+               AND (?3 IS NULL OR id < ?3)
+             ORDER BY id DESC LIMIT ?4",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![q.device_id, q.session_id, q.before, limit], |row| {
+            Ok(AuditEntry {
+                id: row.get(0)?,
+                ts: row.get(1)?,
+                device_id: row.get(2)?,
+                device_name: row.get(3)?,
+                action: row.get(4)?,
+                session_id: row.get(5)?,
+                source_ip: row.get(6)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+}
+
+pub fn list(q: &AuditQuery) -> Result<Vec<AuditEntry>, String> {
+    query(&crate::state::get().db().lock(), q)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(device: Option<&str>, action: &str, session: Option<&str>) -> AuditEntry {
+        AuditEntry {
+            id: 0,
+            ts: chrono::Utc::now().to_rfc3339(),
+            device_id: device.map(str::to_string),
+            device_name: device.map(|d| format!("{}'s phone", d)),
+            action: action.to_string(),
+            session_id: session.map(str::to_string),
+            source_ip: Some("192.168.1.20".to_string()),
+        }
+    }
+
+    #[test]
+    fn filters_and_pages_newest_first() {
+        let conn = Connection::open_in_memory().unwrap();
+        run_audit_migrations(&conn);
+        insert(&conn, &entry(Some("a"), "POST /api/sessions", None)).unwrap();
+        insert(&conn, &entry(Some("a"), "ws:send_message", Some("s1"))).unwrap();
+        insert(&conn, &entry(Some("b"), "ws:interrupt", Some("s1"))).unwrap();
+        insert(&conn, &entry(None, "POST /api/mcp/execute", None)).unwrap();
+
+        let all = query(&conn, &AuditQuery::default()).unwrap();
+        assert_eq!(all.len(), 4);
+        assert_eq!(all[0].action, "POST /api/mcp/execute");
+
+        let by_device = query(&conn, &AuditQuery { device_id: Some("a".into()), ..Default::default() }).unwrap();
+        assert_eq!(by_device.iter().map(|e| e.action.as_str()).collect::<Vec<_>>(), ["ws:send_message", "POST /api/sessions"]);
+
+        let by_session = query(&conn, &AuditQuery { session_id: Some("s1".into()), ..Default::default() }).unwrap();
+        assert_eq!(by_session.len(), 2);
+
+        let page = query(&conn, &AuditQuery { before: Some(all[1].id), limit: Some(1), ..Default::default() }).unwrap();
+        assert_eq!(page, vec![all[2].clone()]);
+    }
+}
@@ -0,0 +1,306 @@
+// Terminal buffers (PTY scrollback, and chat history for JSON sessions) in
+// the terminal_buffers table.
+//
+// A buffer is stored as a BLOB: a format byte, then the content compressed
+// with zstd. Rows written before the format byte hold base64'd gzip as TEXT
+// (a third bigger than the gzip itself); they still load, and are rewritten
+// in the current format the first time they do. The column was declared
+// TEXT then, which SQLite doesn't hold BLOBs to, so old databases keep their
+// table.
+//
+// Compression happens before the database is locked. A big buffer is written
+// CHUNK bytes at a time into a zeroblob of its compressed size, rather than
+// bound as one parameter for SQLite to copy.
+//
+// append() adds output to the end of a buffer as a zstd frame of its own;
+// frames one after another decode as one stream, so what's already stored
+// isn't decompressed or compressed again. appended_bytes counts what's been
+// added that way since the buffer was last written whole, for callers to
+// write it whole again (trimmed to their scrollback) once it passes
+// REWRITE_AFTER.
+
+use flate2::read::GzDecoder;
+use parking_lot::Mutex;
+use rusqlite::types::ValueRef;
+use rusqlite::{params, Connection, DatabaseName, OptionalExtension};
+use std::io::{Read, Write};
+
+/// zstd-compressed UTF-8
+const FORMAT_ZSTD: u8 = 1;
+
+/// zstd's default level: quick enough to save a large scrollback every few
+/// seconds, and terminal output compresses well at it
+const ZSTD_LEVEL: i32 = 3;
+
+/// Buffers bigger than this compressed are written to the blob in pieces
+/// of this size
+const CHUNK: usize = 256 * 1024;
+
+/// Appended bytes after which a buffer should be written whole again
+pub const REWRITE_AFTER: u64 = 4 * 1024 * 1024;
+
+pub fn run_buffer_migrations(conn: &Connection) {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS terminal_buffers (
+            session_id TEXT PRIMARY KEY,
+            buffer_data BLOB NOT NULL,
+            updated_at TEXT NOT NULL,
+            FOREIGN KEY (session_id) REFERENCES sessions(id) ON DELETE CASCADE
+        )",
+        [],
+    )
+    .expect("Failed to create terminal_buffers table");
+
+    let _ = conn.execute("ALTER TABLE terminal_buffers ADD COLUMN appended_bytes INTEGER NOT NULL DEFAULT 0", []);
+}
+
+/// A buffer as stored
+enum Stored {
+    Current(Vec<u8>),
+    /// base64'd gzip, from before the format byte
+    Legacy(String),
+}
+
+/// `content` in the current format
+pub fn encode(content: &str) -> Result<Vec<u8>, String> {
+    compress_onto(vec![FORMAT_ZSTD], content)
+}
+
+/// `content` as a zstd frame, after `out`
+fn compress_onto(out: Vec<u8>, content: &str) -> Result<Vec<u8>, String> {
+    let mut encoder = zstd::stream::write::Encoder::new(out, ZSTD_LEVEL)
+        .map_err(|e| format!("Failed to compress buffer: {}", e))?;
+    encoder.write_all(content.as_bytes()).map_err(|e| format!("Failed to compress buffer: {}", e))?;
+    encoder.finish().map_err(|e| format!("Failed to finish compression: {}", e))
+}
+
+fn decode(stored: &[u8]) -> Result<String, String> {
+    match stored.split_first() {
+        Some((&FORMAT_ZSTD, compressed)) => {
+            let mut content = String::new();
+            zstd::stream::read::Decoder::new(compressed)
+                .and_then(|mut decoder| decoder.read_to_string(&mut content))
+                .map_err(|e| format!("Failed to decompress buffer: {}", e))?;
+            Ok(content)
+        }
+        Some((format, _)) => Err(format!("Buffer is in format {}, from a newer version of the app", format)),
+        None => Err("Buffer is empty".to_string()),
+    }
+}
+
+fn decode_legacy(encoded: &str) -> Result<String, String> {
+    use base64::Engine;
+    let compressed = base64::engine::general_purpose::STANDARD
+        .decode(encoded)
+        .map_err(|e| format!("Failed to decode buffer: {}", e))?;
+    let mut content = String::new();
+    GzDecoder::new(&compressed[..])
+        .read_to_string(&mut content)
+        .map_err(|e| format!("Failed to decompress buffer: {}", e))?;
+    Ok(content)
+}
+
+fn read(conn: &Connection, session_id: &str) -> Result<Option<Stored>, String> {
+    conn.query_row(
+        "SELECT buffer_data FROM terminal_buffers WHERE session_id = ?1",
+        params![session_id],
+        |row| match row.get_ref(0)? {
+            ValueRef::Text(text) => Ok(Stored::Legacy(String::from_utf8_lossy(text).into_owned())),
+            _ => row.get(0).map(Stored::Current),
+        },
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+/// Save an encode()d buffer as the session's
+pub fn write(conn: &Connection, session_id: &str, encoded: &[u8]) -> Result<(), String> {
+    store(conn, session_id, encoded, 0)
+}
+
+fn store(conn: &Connection, session_id: &str, encoded: &[u8], appended: u64) -> Result<(), String> {
+    let now = chrono::Utc::now().to_rfc3339();
+    if encoded.len() <= CHUNK {
+        conn.execute(
+            "INSERT OR REPLACE INTO terminal_buffers (session_id, buffer_data, updated_at, appended_bytes)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![session_id, encoded, now, appended as i64],
+        )
+        .map_err(|e| e.to_string())?;
+        return Ok(());
+    }
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    tx.execute(
+        "INSERT OR REPLACE INTO terminal_buffers (session_id, buffer_data, updated_at, appended_bytes)
+         VALUES (?1, zeroblob(?2), ?3, ?4)",
+        params![session_id, encoded.len() as i64, now, appended as i64],
+    )
+    .map_err(|e| e.to_string())?;
+    {
+        let mut blob = tx
+            .blob_open(DatabaseName::Main, "terminal_buffers", "buffer_data", tx.last_insert_rowid(), false)
+            .map_err(|e| e.to_string())?;
+        for chunk in encoded.chunks(CHUNK) {
+            blob.write_all(chunk).map_err(|e| format!("Failed to write buffer: {}", e))?;
+        }
+    }
+    tx.commit().map_err(|e| e.to_string())
+}
+
+/// Add `chunk` to the end of the session's buffer (starting one if it has
+/// none). Returns the bytes appended since the buffer was last written whole.
+pub fn append(db: &Mutex<Connection>, session_id: &str, chunk: &str) -> Result<u64, String> {
+    let frame = compress_onto(Vec::new(), chunk)?;
+    let conn = db.lock();
+    let now = chrono::Utc::now().to_rfc3339();
+    // SQLite's || makes text of its operands; the bytes are unchanged, and
+    // the cast makes the result a BLOB again
+    let appended: Option<i64> = conn
+        .query_row(
+            "UPDATE terminal_buffers
+             SET buffer_data = CAST(buffer_data || ?2 AS BLOB), appended_bytes = appended_bytes + ?3, updated_at = ?4
+             WHERE session_id = ?1 AND typeof(buffer_data) = 'blob'
+             RETURNING appended_bytes",
+            params![session_id, frame, chunk.len() as i64, now],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    if let Some(appended) = appended {
+        return Ok(appended as u64);
+    }
+    // No buffer yet, or an old-format one to carry over
+    let content = match read(&conn, session_id)? {
+        Some(Stored::Legacy(encoded)) => decode_legacy(&encoded)? + chunk,
+        _ => chunk.to_string(),
+    };
+    store(&conn, session_id, &encode(&content)?, chunk.len() as u64)?;
+    Ok(chunk.len() as u64)
+}
+
+/// The session's buffer. The database is locked to read the row (and to
+/// rewrite an old-format one), not while decompressing.
+pub fn load(db: &Mutex<Connection>, session_id: &str) -> Result<Option<String>, String> {
+    let stored = read(&db.lock(), session_id)?;
+    match stored {
+        None => Ok(None),
+        Some(Stored::Current(bytes)) => decode(&bytes).map(Some),
+        Some(Stored::Legacy(encoded)) => {
+            let content = decode_legacy(&encoded)?;
+            // Unless it's been saved again in the meantime
+            let migrated = encode(&content).and_then(|encoded| {
+                db.lock()
+                    .execute(
+                        "UPDATE terminal_buffers SET buffer_data = ?2
+                         WHERE session_id = ?1 AND typeof(buffer_data) = 'text'",
+                        params![session_id, encoded],
+                    )
+                    .map_err(|e| e.to_string())
+            });
+            if let Err(e) = migrated {
+                tracing::warn!("[buffers] rewriting {} in the current format: {}", session_id, e);
+            }
+            Ok(Some(content))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> Mutex<Connection> {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("CREATE TABLE sessions (id TEXT PRIMARY KEY); INSERT INTO sessions VALUES ('s1');").unwrap();
+        run_buffer_migrations(&conn);
+        Mutex::new(conn)
+    }
+
+    /// Text that barely compresses, so it goes past CHUNK when stored
+    fn noisy(len: usize) -> String {
+        let mut seed: u32 = 7;
+        (0..len)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                char::from(b'!' + ((seed >> 16) % 90) as u8)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn buffers_round_trip_with_a_format_byte() {
+        let db = setup();
+        assert_eq!(load(&db, "s1").unwrap(), None);
+
+        let small = "$ ls\r\nsrc  Cargo.toml\r\n".repeat(100);
+        write(&db.lock(), "s1", &encode(&small).unwrap()).unwrap();
+        let stored: Vec<u8> = db.lock().query_row("SELECT buffer_data FROM terminal_buffers", [], |r| r.get(0)).unwrap();
+        assert_eq!(stored[0], FORMAT_ZSTD);
+        assert!(stored.len() < small.len() / 10);
+        assert_eq!(load(&db, "s1").unwrap().as_deref(), Some(small.as_str()));
+
+        // Written in pieces, and replacing what was there
+        let large = noisy(CHUNK * 3);
+        let encoded = encode(&large).unwrap();
+        assert!(encoded.len() > CHUNK * 2);
+        write(&db.lock(), "s1", &encoded).unwrap();
+        assert_eq!(load(&db, "s1").unwrap().as_deref(), Some(large.as_str()));
+        let rows: i64 = db.lock().query_row("SELECT COUNT(*) FROM terminal_buffers", [], |r| r.get(0)).unwrap();
+        assert_eq!(rows, 1);
+    }
+
+    #[test]
+    fn old_rows_load_and_are_rewritten() {
+        use base64::Engine;
+        use flate2::write::GzEncoder;
+
+        let db = setup();
+        let content = "old scrollback\r\n".repeat(50);
+        let mut gzip = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(content.as_bytes()).unwrap();
+        let legacy = base64::engine::general_purpose::STANDARD.encode(gzip.finish().unwrap());
+        db.lock()
+            .execute(
+                "INSERT INTO terminal_buffers (session_id, buffer_data, updated_at) VALUES ('s1', ?1, 'then')",
+                params![legacy],
+            )
+            .unwrap();
+
+        assert_eq!(load(&db, "s1").unwrap().as_deref(), Some(content.as_str()));
+        let kind: String = db.lock().query_row("SELECT typeof(buffer_data) FROM terminal_buffers", [], |r| r.get(0)).unwrap();
+        assert_eq!(kind, "blob");
+        assert_eq!(load(&db, "s1").unwrap().as_deref(), Some(content.as_str()));
+
+        // A format this version doesn't know is an error, not garbage
+        db.lock().execute("UPDATE terminal_buffers SET buffer_data = x'ff00'", []).unwrap();
+        assert!(load(&db, "s1").unwrap_err().contains("newer version"));
+    }
+
+    #[test]
+    fn appends_extend_the_buffer_until_it_is_written_whole() {
+        let db = setup();
+        let appended_bytes =
+            || -> i64 { db.lock().query_row("SELECT appended_bytes FROM terminal_buffers", [], |r| r.get(0)).unwrap() };
+
+        assert_eq!(append(&db, "s1", "first\r\n").unwrap(), 7);
+        write(&db.lock(), "s1", &encode("$ make\r\n").unwrap()).unwrap();
+        assert_eq!(appended_bytes(), 0);
+
+        let large = noisy(CHUNK * 2);
+        assert_eq!(append(&db, "s1", "building\r\n").unwrap(), 10);
+        assert_eq!(append(&db, "s1", &large).unwrap(), 10 + large.len() as u64);
+        let expected = format!("$ make\r\nbuilding\r\n{}", large);
+        assert_eq!(load(&db, "s1").unwrap(), Some(expected));
+        let kind: String = db.lock().query_row("SELECT typeof(buffer_data) FROM terminal_buffers", [], |r| r.get(0)).unwrap();
+        assert_eq!(kind, "blob");
+
+        write(&db.lock(), "s1", &encode("trimmed").unwrap()).unwrap();
+        assert_eq!(appended_bytes(), 0);
+        assert_eq!(load(&db, "s1").unwrap().as_deref(), Some("trimmed"));
+
+        // Onto an old-format row
+        db.lock().execute("UPDATE terminal_buffers SET buffer_data = 'H4sIAAAAAAACA8tIzcnJBwCGphA2BQAAAA=='", []).unwrap();
+        append(&db, "s1", " world").unwrap();
+        assert_eq!(load(&db, "s1").unwrap().as_deref(), Some("hello world"));
+    }
+}
@@ -0,0 +1,264 @@
+// File changes made by agents, extracted from Edit / Write / MultiEdit
+// tool_use blocks as they stream out of a claude-json session.
+//
+// Each edit becomes one `file_changes` row (a MultiEdit contributes one row
+// per entry in its `edits` array), so every file an agent touched can be
+// reviewed without scrolling back through the transcript. `old_text` is NULL
+// for Write, which replaces the whole file.
+
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use tauri::Emitter;
+
+// =====================================================================
+//  Schema
+// =====================================================================
+
+pub fn run_changes_migrations(conn: &Connection) {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS file_changes (
+            id          INTEGER PRIMARY KEY AUTOINCREMENT,
+            session_id  TEXT NOT NULL,
+            tool_use_id TEXT NOT NULL,
+            edit_index  INTEGER NOT NULL,
+            tool        TEXT NOT NULL,
+            file_path   TEXT NOT NULL,
+            old_text    TEXT,
+            new_text    TEXT NOT NULL,
+            replace_all INTEGER NOT NULL DEFAULT 0,
+            created_at  TEXT NOT NULL,
+            UNIQUE (tool_use_id, edit_index)
+        )",
+        [],
+    )
+    .expect("Failed to create file_changes table");
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_file_changes_session ON file_changes(session_id, id)",
+        [],
+    )
+    .expect("Failed to create file_changes index");
+}
+
+/// Remove a session's change records (called when the session is deleted).
+pub fn delete_changes_for_session(conn: &Connection, session_id: &str) {
+    let _ = conn.execute("DELETE FROM file_changes WHERE session_id = ?1", params![session_id]);
+}
+
+// =====================================================================
+//  Extraction
+// =====================================================================
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct FileChange {
+    /// Row id; 0 until stored.
+    pub id: i64,
+    pub tool_use_id: String,
+    pub edit_index: i64,
+    /// "Edit", "Write" or "MultiEdit".
+    pub tool: String,
+    pub file_path: String,
+    pub old_text: Option<String>,
+    pub new_text: String,
+    pub replace_all: bool,
+    pub created_at: String,
+}
+
+/// Pull file changes out of an assistant message's tool_use blocks.
+fn extract_file_changes(msg: &crate::ClaudeJsonMessage) -> Vec<FileChange> {
+    let Some(content) = msg.message.as_ref().and_then(|m| m.content.as_ref()) else {
+        return Vec::new();
+    };
+    let now = chrono::Utc::now().to_rfc3339();
+    let mut changes = Vec::new();
+
+    for item in content.iter().filter(|c| c.content_type == "tool_use") {
+        let (Some(tool), Some(tool_use_id), Some(input)) = (&item.name, &item.id, &item.input) else {
+            continue;
+        };
+        let Some(file_path) = input.get("file_path").and_then(|v| v.as_str()) else {
+            continue;
+        };
+        let str_field = |v: &serde_json::Value, key: &str| v.get(key).and_then(|s| s.as_str()).map(|s| s.to_string());
+        let change = |edit_index: usize, old_text: Option<String>, new_text: String, replace_all: bool| FileChange {
+            id: 0,
+            tool_use_id: tool_use_id.clone(),
+            edit_index: edit_index as i64,
+            tool: tool.clone(),
+            file_path: file_path.to_string(),
+            old_text,
+            new_text,
+            replace_all,
+            created_at: now.clone(),
+        };
+
+        match tool.as_str() {
+            "Write" => {
+                if let Some(content) = str_field(input, "content") {
+                    changes.push(change(0, None, content, false));
+                }
+            }
+            "Edit" => {
+                if let Some(new_text) = str_field(input, "new_string") {
+                    let replace_all = input.get("replace_all").and_then(|v| v.as_bool()).unwrap_or(false);
+                    changes.push(change(0, str_field(input, "old_string"), new_text, replace_all));
+                }
+            }
+            "MultiEdit" => {
+                let edits = input.get("edits").and_then(|v| v.as_array()).cloned().unwrap_or_default();
+                for (i, edit) in edits.iter().enumerate() {
+                    if let Some(new_text) = str_field(edit, "new_string") {
+                        let replace_all = edit.get("replace_all").and_then(|v| v.as_bool()).unwrap_or(false);
+                        changes.push(change(i, str_field(edit, "old_string"), new_text, replace_all));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    changes
+}
+
+// =====================================================================
+//  Storage
+// =====================================================================
+
+/// Called from the stdout reader for each assistant message. Stores any file
+/// changes it carries and pushes them to the desktop UI and mobile clients.
+pub fn record_file_changes(session_id: &str, msg: &crate::ClaudeJsonMessage) {
+    let mut changes = extract_file_changes(msg);
+    if changes.is_empty() {
+        return;
+    }
+    {
+        let conn = crate::state::get().db().lock();
+        for change in changes.iter_mut() {
+            let inserted = conn.execute(
+                "INSERT OR IGNORE INTO file_changes
+                    (session_id, tool_use_id, edit_index, tool, file_path, old_text, new_text, replace_all, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![
+                    session_id,
+                    change.tool_use_id,
+                    change.edit_index,
+                    change.tool,
+                    change.file_path,
+                    change.old_text,
+                    change.new_text,
+                    change.replace_all as i64,
+                    change.created_at,
+                ],
+            );
+            if let Ok(1) = inserted {
+                change.id = conn.last_insert_rowid();
+            }
+        }
+    }
+    // Rows already stored (same tool_use_id) aren't announced twice
+    changes.retain(|c| c.id != 0);
+    if changes.is_empty() {
+        return;
+    }
+
+    if let Some(app) = crate::state::get().app_handle.lock().as_ref() {
+        let _ = app.emit("file-changes", serde_json::json!({
+            "session_id": session_id,
+            "changes": changes,
+        }));
+    }
+    let msg = serde_json::json!({
+        "type": "file_changes",
+        "sessionId": session_id,
+        "changes": changes,
+    })
+    .to_string();
+    crate::broadcast_to_session_subscribers(session_id, &msg);
+}
+
+/// Every file change recorded for a session, oldest first.
+pub fn get_session_changes(session_id: &str) -> Result<Vec<FileChange>, String> {
+    let conn = crate::state::get().db().lock();
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, tool_use_id, edit_index, tool, file_path, old_text, new_text, replace_all, created_at
+             FROM file_changes WHERE session_id = ?1 ORDER BY id ASC",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![session_id], |r| {
+            Ok(FileChange {
+                id: r.get(0)?,
+                tool_use_id: r.get(1)?,
+                edit_index: r.get(2)?,
+                tool: r.get(3)?,
+                file_path: r.get(4)?,
+                old_text: r.get(5)?,
+                new_text: r.get(6)?,
+                replace_all: r.get::<_, i64>(7)? != 0,
+                created_at: r.get(8)?,
+            })
+        })
+        .map_err(|e| e.to_string())?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assistant(content: serde_json::Value) -> crate::ClaudeJsonMessage {
+        serde_json::from_value(serde_json::json!({
+            "type": "assistant",
+            "message": { "role": "assistant", "content": content }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn extracts_edit_and_write() {
+        let msg = assistant(serde_json::json!([
+            { "type": "text", "text": "Fixing it" },
+            { "type": "tool_use", "id": "tu1", "name": "Edit",
+              "input": { "file_path": "/a.rs", "old_string": "foo", "new_string": "bar", "replace_all": true } },
+            { "type": "tool_use", "id": "tu2", "name": "Write",
+              "input": { "file_path": "/b.rs", "content": "fn main() {}" } },
+        ]));
+        let changes = extract_file_changes(&msg);
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].tool, "Edit");
+        assert_eq!(changes[0].file_path, "/a.rs");
+        assert_eq!(changes[0].old_text.as_deref(), Some("foo"));
+        assert_eq!(changes[0].new_text, "bar");
+        assert!(changes[0].replace_all);
+        assert_eq!(changes[1].tool, "Write");
+        assert_eq!(changes[1].old_text, None);
+        assert_eq!(changes[1].new_text, "fn main() {}");
+    }
+
+    #[test]
+    fn multi_edit_yields_one_change_per_edit() {
+        let msg = assistant(serde_json::json!([
+            { "type": "tool_use", "id": "tu1", "name": "MultiEdit",
+              "input": { "file_path": "/c.rs", "edits": [
+                  { "old_string": "a", "new_string": "b" },
+                  { "old_string": "c", "new_string": "d" }
+              ] } },
+        ]));
+        let changes = extract_file_changes(&msg);
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].edit_index, 0);
+        assert_eq!(changes[1].edit_index, 1);
+        assert_eq!(changes[1].old_text.as_deref(), Some("c"));
+        assert!(changes.iter().all(|c| c.file_path == "/c.rs" && c.tool_use_id == "tu1"));
+    }
+
+    #[test]
+    fn ignores_other_tools() {
+        let msg = assistant(serde_json::json!([
+            { "type": "tool_use", "id": "tu1", "name": "Bash", "input": { "command": "ls" } },
+            { "type": "tool_use", "id": "tu2", "name": "Read", "input": { "file_path": "/a.rs" } },
+        ]));
+        assert!(extract_file_changes(&msg).is_empty());
+    }
+}
@@ -0,0 +1,85 @@
+// What a mobile chat subscription leaves out of the session's messages
+// (protocol 11). `subscribe` may carry `filters: {exclude_tool_results,
+// text_only}`; the messages sent for that subscription (history, catch-up and
+// live) have the matching content blocks removed. Filtered messages are still
+// sent, emptied if need be, so seq numbering and acks are unchanged.
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ChatFilter {
+    /// Drop tool_result blocks and the CLI's copy of the tool output
+    pub exclude_tool_results: bool,
+    /// Keep only text blocks: no tool calls, results or thinking
+    pub text_only: bool,
+}
+
+impl ChatFilter {
+    /// The filters of a `subscribe` message; none if it has none
+    pub fn from_subscribe(msg: &serde_json::Value) -> Self {
+        let filters = &msg["filters"];
+        ChatFilter {
+            exclude_tool_results: filters["exclude_tool_results"].as_bool().unwrap_or(false),
+            text_only: filters["text_only"].as_bool().unwrap_or(false),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == ChatFilter::default()
+    }
+
+    fn keeps(&self, block: &serde_json::Value) -> bool {
+        let kind = block["type"].as_str().unwrap_or("");
+        if self.text_only {
+            return kind == "text";
+        }
+        !(self.exclude_tool_results && kind == "tool_result")
+    }
+
+    /// A stream-json message with what the filter excludes taken out
+    pub fn apply(&self, message: &serde_json::Value) -> serde_json::Value {
+        let mut message = message.clone();
+        if self.is_empty() {
+            return message;
+        }
+        if let Some(content) = message.pointer_mut("/message/content").and_then(|c| c.as_array_mut()) {
+            content.retain(|block| self.keeps(block));
+        }
+        if let Some(fields) = message.as_object_mut() {
+            fields.remove("tool_use_result");
+        }
+        message
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters_remove_only_what_they_name() {
+        let message = serde_json::json!({
+            "type": "user",
+            "message": { "role": "user", "content": [
+                { "type": "tool_result", "tool_use_id": "t1", "content": "a large file" },
+                { "type": "text", "text": "and then" },
+                { "type": "thinking", "thinking": "hmm" },
+            ]},
+            "tool_use_result": { "stdout": "a large file" },
+        });
+
+        let none = ChatFilter::from_subscribe(&serde_json::json!({ "type": "subscribe", "sessionId": "s" }));
+        assert!(none.is_empty());
+        assert_eq!(none.apply(&message), message);
+
+        let no_results = ChatFilter::from_subscribe(&serde_json::json!({ "filters": { "exclude_tool_results": true } }));
+        let filtered = no_results.apply(&message);
+        let kinds: Vec<&str> = filtered["message"]["content"].as_array().unwrap().iter().map(|b| b["type"].as_str().unwrap()).collect();
+        assert_eq!(kinds, ["text", "thinking"]);
+        assert!(filtered.get("tool_use_result").is_none());
+
+        let text = ChatFilter { text_only: true, ..Default::default() }.apply(&message);
+        assert_eq!(text["message"]["content"], serde_json::json!([{ "type": "text", "text": "and then" }]));
+        // Messages without content blocks pass through
+        let result = serde_json::json!({ "type": "result", "result": "done" });
+        assert_eq!(ChatFilter { text_only: true, ..Default::default() }.apply(&result), result);
+    }
+}
@@ -0,0 +1,212 @@
+// Slack and Discord messages when a selected session finishes a turn.
+//
+// The incoming-webhook URLs live in settings (`slack_webhook_url`,
+// `discord_webhook_url`); sessions opt in through the `chat_notify_sessions`
+// table. Each message carries the session name, how long the turn took, its
+// cost, the start of the result and an agenthub://session/<id> link back.
+// Raw event delivery is webhooks.rs; this is the formatted, human version.
+
+use rusqlite::{params, Connection};
+use std::time::Duration;
+
+const MAX_SNIPPET_CHARS: usize = 500;
+
+pub fn run_chat_notify_migrations(conn: &Connection) {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS chat_notify_sessions (
+            session_id TEXT PRIMARY KEY
+        )",
+        [],
+    )
+    .expect("Failed to create chat_notify_sessions table");
+}
+
+pub fn delete_chat_notify_for_session(conn: &Connection, session_id: &str) {
+    let _ = conn.execute("DELETE FROM chat_notify_sessions WHERE session_id = ?1", params![session_id]);
+}
+
+pub fn enabled_for(session_id: &str) -> bool {
+    let conn = crate::state::get().db().lock();
+    conn.query_row(
+        "SELECT COUNT(*) FROM chat_notify_sessions WHERE session_id = ?1",
+        params![session_id],
+        |r| r.get::<_, i64>(0),
+    )
+    .map(|n| n > 0)
+    .unwrap_or(false)
+}
+
+pub fn set_enabled(session_id: &str, enabled: bool) -> Result<(), String> {
+    let conn = crate::state::get().db().lock();
+    if enabled {
+        conn.execute("INSERT OR IGNORE INTO chat_notify_sessions (session_id) VALUES (?1)", params![session_id])
+    } else {
+        conn.execute("DELETE FROM chat_notify_sessions WHERE session_id = ?1", params![session_id])
+    }
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// =====================================================================
+//  Messages
+// =====================================================================
+
+/// What a finished turn is reported with
+#[derive(Debug, Clone, Default)]
+pub struct TurnSummary {
+    pub session_id: String,
+    pub session_name: String,
+    pub is_error: bool,
+    pub duration_ms: Option<f64>,
+    pub cost_usd: Option<f64>,
+    pub result: Option<String>,
+}
+
+impl TurnSummary {
+    fn headline(&self) -> String {
+        let outcome = if self.is_error { "failed" } else { "finished" };
+        let mut details = Vec::new();
+        if let Some(ms) = self.duration_ms {
+            details.push(format_duration(ms));
+        }
+        if let Some(cost) = self.cost_usd {
+            details.push(format!("${:.2}", cost));
+        }
+        if details.is_empty() {
+            format!("{} {}", self.session_name, outcome)
+        } else {
+            format!("{} {} ({})", self.session_name, outcome, details.join(", "))
+        }
+    }
+
+    fn link(&self) -> String {
+        format!("agenthub://session/{}", self.session_id)
+    }
+
+    fn snippet(&self) -> String {
+        let text = self.result.as_deref().unwrap_or("").trim();
+        match text.char_indices().nth(MAX_SNIPPET_CHARS) {
+            Some((i, _)) => format!("{}…", &text[..i]),
+            None => text.to_string(),
+        }
+    }
+}
+
+fn format_duration(ms: f64) -> String {
+    let secs = (ms / 1000.0).round() as u64;
+    if secs < 60 {
+        format!("{}s", secs)
+    } else if secs < 3600 {
+        format!("{}m {}s", secs / 60, secs % 60)
+    } else {
+        format!("{}h {}m", secs / 3600, secs % 3600 / 60)
+    }
+}
+
+/// Slack incoming-webhook body (mrkdwn)
+fn slack_message(turn: &TurnSummary) -> serde_json::Value {
+    let mut text = format!("*{}*", turn.headline());
+    let snippet = turn.snippet();
+    if !snippet.is_empty() {
+        text.push_str(&format!("\n>{}", snippet.replace('\n', "\n>")));
+    }
+    text.push_str(&format!("\n<{}|Open in Agent Hub>", turn.link()));
+    serde_json::json!({ "text": text })
+}
+
+/// Discord webhook body, one embed per turn
+fn discord_message(turn: &TurnSummary) -> serde_json::Value {
+    serde_json::json!({
+        "embeds": [{
+            "title": turn.headline(),
+            "description": format!("{}\n\n{}", turn.snippet(), turn.link()).trim_start(),
+            "color": if turn.is_error { 0xd9534f } else { 0x0e9fd8 },
+        }],
+    })
+}
+
+/// Called when a turn completes; posts it for opted-in sessions.
+pub fn on_turn_complete(
+    session_id: &str,
+    is_error: bool,
+    duration_ms: Option<f64>,
+    cost_usd: Option<f64>,
+    result: Option<&str>,
+) {
+    let settings = crate::load_app_settings().unwrap_or_default();
+    let slack = settings.slack_webhook_url.filter(|u| !u.trim().is_empty());
+    let discord = settings.discord_webhook_url.filter(|u| !u.trim().is_empty());
+    if (slack.is_none() && discord.is_none()) || !enabled_for(session_id) {
+        return;
+    }
+    let turn = TurnSummary {
+        session_id: session_id.to_string(),
+        session_name: crate::load_sessions()
+            .ok()
+            .and_then(|sessions| sessions.into_iter().find(|s| s.id == session_id))
+            .map(|s| s.name)
+            .unwrap_or_else(|| "Session".to_string()),
+        is_error,
+        duration_ms,
+        cost_usd,
+        result: result.map(str::to_string),
+    };
+    let mut targets: Vec<(String, serde_json::Value)> = Vec::new();
+    if let Some(url) = slack {
+        targets.push((url, slack_message(&turn)));
+    }
+    if let Some(url) = discord {
+        targets.push((url, discord_message(&turn)));
+    }
+    tauri::async_runtime::spawn(async move {
+        let client = reqwest::Client::new();
+        for (url, body) in targets {
+            if let Err(e) = post(&client, &url, &body).await {
+                tracing::warn!("{}: {}", turn.session_id, e);
+            }
+        }
+    });
+}
+
+async fn post(client: &reqwest::Client, url: &str, body: &serde_json::Value) -> Result<(), String> {
+    let resp = client
+        .post(url)
+        .json(body)
+        .timeout(Duration::from_secs(15))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !resp.status().is_success() {
+        return Err(format!("webhook returned {}", resp.status()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_carry_name_duration_cost_and_link() {
+        let turn = TurnSummary {
+            session_id: "abc".into(),
+            session_name: "Refactor".into(),
+            is_error: false,
+            duration_ms: Some(125_400.0),
+            cost_usd: Some(0.4213),
+            result: Some("Done.\nAll tests pass.".into()),
+        };
+        assert_eq!(turn.headline(), "Refactor finished (2m 5s, $0.42)");
+
+        let slack = slack_message(&turn)["text"].as_str().unwrap().to_string();
+        assert!(slack.contains(">Done.\n>All tests pass."));
+        assert!(slack.ends_with("<agenthub://session/abc|Open in Agent Hub>"));
+
+        let discord = discord_message(&turn);
+        assert_eq!(discord["embeds"][0]["title"], "Refactor finished (2m 5s, $0.42)");
+        assert!(discord["embeds"][0]["description"].as_str().unwrap().ends_with("agenthub://session/abc"));
+
+        let bare = TurnSummary { session_name: "Build".into(), is_error: true, ..Default::default() };
+        assert_eq!(bare.headline(), "Build failed");
+    }
+}
@@ -0,0 +1,181 @@
+// Per-session context window tracking for claude-json sessions.
+//
+// Mirrors the desktop chat's context indicator on the backend so every
+// client gets the same number: the current context size is the prompt of
+// the latest top-level assistant message (input + cache read + cache
+// creation tokens), measured against the model's window minus the CLI's
+// auto-compact reserve. The window comes from the result event's
+// `modelUsage` when the CLI reports it, else a per-model table.
+//
+// Crossing `context_warning_pct` (app setting) emits a one-shot
+// `context_warning` until usage drops back below it (e.g. after /compact).
+
+use serde::Serialize;
+use tauri::Emitter;
+
+/// Space the CLI keeps free for auto-compaction (same as the desktop UI).
+const AUTOCOMPACT_BUFFER: u64 = 45_000;
+const DEFAULT_CONTEXT_WINDOW: u64 = 200_000;
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ContextUsage {
+    pub model: Option<String>,
+    /// Window reported by the CLI, if any.
+    pub context_window: Option<u64>,
+    /// Tokens in the most recent prompt.
+    pub used_tokens: u64,
+    #[serde(skip)]
+    warned: bool,
+}
+
+impl ContextUsage {
+    fn window(&self) -> u64 {
+        self.context_window
+            .or_else(|| self.model.as_deref().map(model_context_window))
+            .unwrap_or(DEFAULT_CONTEXT_WINDOW)
+    }
+
+    /// Percent of the usable (pre-auto-compact) window in use.
+    pub fn used_pct(&self) -> u32 {
+        let usable = self.window().saturating_sub(AUTOCOMPACT_BUFFER).max(1);
+        ((self.used_tokens as f64 / usable as f64) * 100.0).round() as u32
+    }
+}
+
+/// Known windows; the `[1m]` suffix marks the 1M-token variants.
+fn model_context_window(model: &str) -> u64 {
+    if model.ends_with("[1m]") || model == "claude-mythos-5" {
+        1_000_000
+    } else {
+        DEFAULT_CONTEXT_WINDOW
+    }
+}
+
+/// Feed a parsed stdout message. Returns the new usage percentage when the
+/// message changed it.
+pub fn on_message(session_id: &str, msg: &crate::ClaudeJsonMessage) -> Option<u32> {
+    let mut all = crate::state::get().context_usage.lock();
+    let usage = all.entry(session_id.to_string()).or_default();
+
+    match msg.msg_type.as_str() {
+        "system" if msg.subtype.as_deref() == Some("init") => {
+            if msg.model.is_some() {
+                usage.model = msg.model.clone();
+            }
+            None
+        }
+        // Subagent (Task tool) messages carry the subagent's own context
+        "assistant" if msg.parent_tool_use_id.is_none() => {
+            let u = msg.message.as_ref()?.usage.as_ref()?;
+            let used = u.input_tokens.unwrap_or(0)
+                + u.cache_read_input_tokens.unwrap_or(0)
+                + u.cache_creation_input_tokens.unwrap_or(0);
+            if used == 0 {
+                return None;
+            }
+            usage.used_tokens = used;
+            Some(usage.used_pct())
+        }
+        "result" => {
+            let window = msg.model_usage.as_ref().and_then(|m| reported_window(m, usage.model.as_deref()))?;
+            usage.context_window = Some(window);
+            Some(usage.used_pct())
+        }
+        _ => None,
+    }
+}
+
+/// Pick the context window out of a result's `modelUsage`, preferring the
+/// session's own model over any helper models the CLI used.
+fn reported_window(model_usage: &serde_json::Value, model: Option<&str>) -> Option<u64> {
+    let window = |v: &serde_json::Value| v.get("contextWindow").and_then(|w| w.as_u64()).filter(|w| *w > 0);
+    model
+        .and_then(|m| model_usage.get(m))
+        .and_then(window)
+        .or_else(|| model_usage.as_object()?.values().filter_map(window).max())
+}
+
+pub fn used_pct(session_id: &str) -> Option<u32> {
+    crate::state::get().context_usage.lock().get(session_id).filter(|u| u.used_tokens > 0).map(|u| u.used_pct())
+}
+
+pub fn get_context_usage(session_id: &str) -> Option<ContextUsage> {
+    crate::state::get().context_usage.lock().get(session_id).cloned()
+}
+
+/// Emit `context_warning` the first time usage reaches `threshold_pct`;
+/// re-arms once usage falls back under it.
+pub fn check_threshold(session_id: &str, threshold_pct: u32) {
+    let (pct, used_tokens) = {
+        let mut all = crate::state::get().context_usage.lock();
+        let Some(usage) = all.get_mut(session_id) else { return };
+        let pct = usage.used_pct();
+        if pct < threshold_pct {
+            usage.warned = false;
+            return;
+        }
+        if usage.warned {
+            return;
+        }
+        usage.warned = true;
+        (pct, usage.used_tokens)
+    };
+
+    let event = crate::events::SessionEvent::ContextWarning {
+        session_id: session_id.to_string(),
+        context_used_pct: pct,
+        used_tokens,
+        threshold_pct,
+    };
+    if let Some(app) = crate::state::get().app_handle.lock().as_ref() {
+        let _ = app.emit("context-warning", event.data());
+    }
+    crate::broadcast_session_event(event);
+    let msg = serde_json::json!({
+        "type": "context_warning",
+        "sessionId": session_id,
+        "contextUsedPct": pct,
+        "thresholdPct": threshold_pct,
+    })
+    .to_string();
+    crate::broadcast_to_session_subscribers(session_id, &msg);
+}
+
+/// Drop tracked usage (session deleted).
+pub fn forget(session_id: &str) {
+    crate::state::get().context_usage.lock().remove(session_id);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pct_is_against_usable_window() {
+        let usage = ContextUsage { used_tokens: 77_500, ..Default::default() };
+        // 200k - 45k reserve = 155k usable
+        assert_eq!(usage.used_pct(), 50);
+    }
+
+    #[test]
+    fn reported_window_wins_over_model_table() {
+        let usage = ContextUsage {
+            model: Some("claude-opus-4-7".to_string()),
+            context_window: Some(1_000_000),
+            used_tokens: 477_500,
+            ..Default::default()
+        };
+        assert_eq!(usage.used_pct(), 50);
+    }
+
+    #[test]
+    fn prefers_session_model_in_model_usage() {
+        let mu = serde_json::json!({
+            "claude-haiku-4-5": { "contextWindow": 200000 },
+            "claude-opus-4-7[1m]": { "contextWindow": 1000000 },
+        });
+        assert_eq!(reported_window(&mu, Some("claude-haiku-4-5")), Some(200_000));
+        assert_eq!(reported_window(&mu, None), Some(1_000_000));
+        assert_eq!(reported_window(&serde_json::json!({}), None), None);
+    }
+}
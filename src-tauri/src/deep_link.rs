@@ -0,0 +1,155 @@
+// The agenthub:// URL scheme, for notifications, scripts and launchers:
+//
+//   agenthub://session/<id>                    open that session
+//   agenthub://new?dir=~/dev/app&agent=claude-json&name=App
+//                                              the new-session dialog, filled
+//                                              in (all optional); any page can
+//                                              open a link, so the user still
+//                                              has to press Create
+//   agenthub://pair                            show the pairing QR code
+//
+// The scheme is registered by tauri-plugin-deep-link; on Windows and Linux a
+// second launch with a URL is folded into the running instance by
+// tauri-plugin-single-instance. URLs are handed to the frontend as events
+// (open-session, deep-link-new-session, deep-link-pair). Ones that arrive
+// before the frontend is listening (a cold start from a link) are held until
+// it calls deep_links_ready.
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager};
+
+pub const SCHEME: &str = "agenthub";
+
+const AGENT_TYPES: &[&str] = &["claude", "claude-json", "codex", "aider", "shell"];
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Action {
+    OpenSession { session_id: String },
+    NewSession { agent_type: Option<String>, name: Option<String>, working_dir: Option<String> },
+    Pair,
+}
+
+/// Links received before the frontend was ready, or None once it is
+static PENDING: Lazy<Mutex<Option<Vec<Action>>>> = Lazy::new(|| Mutex::new(Some(Vec::new())));
+
+/// Percent-decode a query value ('+' is a space)
+fn decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+                match hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                    Some(b) => {
+                        out.push(b);
+                        i += 2;
+                    }
+                    None => out.push(b'%'),
+                }
+            }
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+pub fn parse(url: &str) -> Result<Action, String> {
+    let rest = url
+        .strip_prefix(SCHEME)
+        .and_then(|r| r.strip_prefix("://"))
+        .ok_or_else(|| format!("Not an {}:// link: {}", SCHEME, url))?;
+    let (path, query) = rest.split_once('?').unwrap_or((rest, ""));
+    let params: Vec<(String, String)> = query
+        .split('&')
+        .filter_map(|pair| pair.split_once('=').or(Some((pair, ""))))
+        .filter(|(k, _)| !k.is_empty())
+        .map(|(k, v)| (k.to_string(), decode(v)))
+        .collect();
+    let param = |name: &str| {
+        params.iter().find(|(k, _)| k == name).map(|(_, v)| v.trim().to_string()).filter(|v| !v.is_empty())
+    };
+    let segments: Vec<&str> = path.trim_matches('/').split('/').filter(|s| !s.is_empty()).collect();
+    match segments.as_slice() {
+        ["session", id] => Ok(Action::OpenSession { session_id: decode(id) }),
+        ["new"] => {
+            let agent_type = param("agent");
+            if let Some(agent) = agent_type.as_deref().filter(|a| !AGENT_TYPES.contains(a)) {
+                return Err(format!("Unknown agent type '{}'", agent));
+            }
+            Ok(Action::NewSession { agent_type, name: param("name"), working_dir: param("dir") })
+        }
+        ["pair"] => Ok(Action::Pair),
+        _ => Err(format!("Unrecognized link: {}", url)),
+    }
+}
+
+/// Handle links the OS opened the app with
+pub fn handle_urls(app: &AppHandle, urls: Vec<String>) {
+    for url in urls {
+        match parse(&url) {
+            Ok(action) => dispatch(app, action),
+            Err(e) => tracing::warn!("{}", e),
+        }
+    }
+}
+
+fn dispatch(app: &AppHandle, action: Action) {
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.unminimize();
+        let _ = window.show();
+        let _ = window.set_focus();
+    }
+    if let Some(pending) = PENDING.lock().as_mut() {
+        pending.push(action);
+        return;
+    }
+    emit(app, &action);
+}
+
+fn emit(app: &AppHandle, action: &Action) {
+    let _ = match action {
+        Action::OpenSession { session_id } => app.emit("open-session", session_id),
+        Action::NewSession { .. } => app.emit("deep-link-new-session", action),
+        Action::Pair => app.emit("deep-link-pair", ()),
+    };
+}
+
+/// The frontend is listening: deliver anything held back, then emit directly
+pub fn ready(app: &AppHandle) {
+    let held = PENDING.lock().take().unwrap_or_default();
+    for action in held {
+        emit(app, &action);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn links_parse_into_actions() {
+        assert_eq!(
+            parse("agenthub://session/abc-123").unwrap(),
+            Action::OpenSession { session_id: "abc-123".into() }
+        );
+        assert_eq!(
+            parse("agenthub://new?dir=~%2Fdev%2Fmy+app&agent=claude-json").unwrap(),
+            Action::NewSession {
+                agent_type: Some("claude-json".into()),
+                name: None,
+                working_dir: Some("~/dev/my app".into()),
+            }
+        );
+        assert_eq!(parse("agenthub://pair/").unwrap(), Action::Pair);
+        assert!(parse("agenthub://new?agent=rm").is_err());
+        assert!(parse("agenthub://settings").is_err());
+        assert!(parse("https://session/abc").is_err());
+    }
+}
@@ -0,0 +1,136 @@
+// Native notifications on this machine, raised by the backend so they fire
+// whether or not the webview is listening (it misses events while it
+// reloads).
+//
+// Turn results and errors follow `notifications_enabled`; attention (the
+// hang watchdog, agents' signal_attention and questions) follows
+// `bell_notifications_enabled`, as the terminal bell does. Nothing is shown
+// while the main window has focus.
+//
+// Desktop notifications can't carry a click action, but clicking one brings
+// the app forward. When the window gains focus shortly after a notification,
+// the frontend is told to open that notification's session (open-session).
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager};
+use tauri_plugin_notification::NotificationExt;
+
+/// Focus this soon after a notification counts as clicking it
+const CLICK_WINDOW: Duration = Duration::from_secs(30);
+const MAX_BODY_CHARS: usize = 200;
+
+/// Session of the last notification shown, and when
+static LAST_SHOWN: Lazy<Mutex<Option<(String, Instant)>>> = Lazy::new(|| Mutex::new(None));
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Result,
+    Error,
+    Attention,
+}
+
+/// A turn finished; the notification carries the start of its answer.
+pub fn on_turn_complete(session_id: &str, result: Option<&str>, is_error: bool) {
+    if is_error {
+        notify(session_id, Kind::Error, "failed", result.unwrap_or("The turn ended with an error"));
+    } else {
+        notify(session_id, Kind::Result, "finished", result.unwrap_or(""));
+    }
+}
+
+/// The session hit a classified error (see session_errors).
+pub fn on_error(session_id: &str, message: &str) {
+    notify(session_id, Kind::Error, "failed", message);
+}
+
+/// The watchdog found the session silent mid-turn.
+pub fn on_hung(session_id: &str, silent_secs: u64) {
+    let body = format!("No output for {} min; it may be stuck.", silent_secs.div_ceil(60));
+    notify(session_id, Kind::Attention, "needs attention", &body);
+}
+
+/// The agent asked the user something (see asks).
+pub fn on_ask(session_id: &str, prompt: &str) {
+    notify(session_id, Kind::Attention, "has a question", prompt);
+}
+
+/// The agent flagged that it needs the user (signal_attention).
+pub fn on_attention(session_id: &str, message: &str) {
+    notify(session_id, Kind::Attention, "needs attention", message);
+}
+
+/// The main window gained focus: open the session of a notification shown
+/// just before, which is most likely what was clicked.
+pub fn on_focus(app: &AppHandle) {
+    let Some((session_id, shown_at)) = LAST_SHOWN.lock().take() else { return };
+    if shown_at.elapsed() < CLICK_WINDOW {
+        let _ = app.emit("open-session", session_id);
+    }
+}
+
+fn enabled(kind: Kind, settings: &crate::AppSettings) -> bool {
+    match kind {
+        Kind::Attention => settings.bell_notifications_enabled,
+        Kind::Result | Kind::Error => settings.notifications_enabled,
+    }
+}
+
+fn snippet(text: &str) -> String {
+    let text = text.trim();
+    match text.char_indices().nth(MAX_BODY_CHARS) {
+        Some((i, _)) => format!("{}…", &text[..i]),
+        None => text.to_string(),
+    }
+}
+
+fn notify(session_id: &str, kind: Kind, what: &str, body: &str) {
+    if !enabled(kind, &crate::load_app_settings().unwrap_or_default()) {
+        return;
+    }
+    let Some(app) = crate::state::get().app_handle.lock().clone() else { return };
+    let focused = app.get_webview_window("main").and_then(|w| w.is_focused().ok()).unwrap_or(false);
+    if focused {
+        return;
+    }
+    let name = crate::load_sessions()
+        .ok()
+        .and_then(|sessions| sessions.into_iter().find(|s| s.id == session_id))
+        .map(|s| s.name)
+        .unwrap_or_else(|| "Session".to_string());
+    let shown = app
+        .notification()
+        .builder()
+        .title(format!("{} {}", name, what))
+        .body(snippet(body))
+        .show();
+    match shown {
+        Ok(()) => *LAST_SHOWN.lock() = Some((session_id.to_string(), Instant::now())),
+        Err(e) => tracing::warn!("{}: {}", session_id, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_kind_follows_its_setting() {
+        let mut settings = crate::AppSettings { notifications_enabled: false, bell_notifications_enabled: true, ..Default::default() };
+        assert!(!enabled(Kind::Result, &settings));
+        assert!(!enabled(Kind::Error, &settings));
+        assert!(enabled(Kind::Attention, &settings));
+        settings.notifications_enabled = true;
+        settings.bell_notifications_enabled = false;
+        assert!(enabled(Kind::Result, &settings));
+        assert!(!enabled(Kind::Attention, &settings));
+    }
+
+    #[test]
+    fn long_bodies_are_cut() {
+        assert_eq!(snippet("  done\n"), "done");
+        let long = "é".repeat(MAX_BODY_CHARS + 5);
+        assert_eq!(snippet(&long).chars().count(), MAX_BODY_CHARS + 1);
+    }
+}
@@ -0,0 +1,374 @@
+// Setup checks behind the onboarding checklist (run_diagnostics).
+//
+// Sessions are spawned through the user's login shell, so the CLIs are
+// looked up the same way: one `$SHELL -l -c` probe reports where claude,
+// aider and codex resolve and which API key variables are set. A CLI that's
+// found is asked for its version, and its usual credential files are
+// checked to tell whether it's logged in. Claude is required; aider and
+// codex only matter if they're used, so problems with them are warnings.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How long a CLI gets to print its version
+const VERSION_TIMEOUT: Duration = Duration::from_secs(10);
+
+const CLIS: &[&str] = &["claude", "aider", "codex"];
+/// Reported as set or not; their values are never read
+const KEY_VARS: &[&str] = &["ANTHROPIC_API_KEY", "OPENAI_API_KEY"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Ok,
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    /// Stable key, e.g. "claude_installed"
+    pub id: String,
+    pub label: String,
+    pub status: Status,
+    pub detail: String,
+    /// What to do about it, when not ok
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fix: Option<String>,
+}
+
+impl Check {
+    fn new(id: &str, label: &str, status: Status, detail: impl Into<String>, fix: Option<&str>) -> Check {
+        Check {
+            id: id.to_string(),
+            label: label.to_string(),
+            status,
+            detail: detail.into(),
+            fix: fix.map(str::to_string),
+        }
+    }
+}
+
+/// What the login shell knows: CLI paths, which API key variables it sets,
+/// and CODEX_HOME
+#[derive(Debug, Default, PartialEq)]
+struct Probe {
+    paths: HashMap<String, String>,
+    env: HashMap<String, String>,
+}
+
+fn probe_script() -> String {
+    let clis = CLIS.join(" ");
+    let vars = KEY_VARS.join(" ");
+    format!(
+        "for bin in {clis}; do p=$(command -v $bin 2>/dev/null) && echo \"path.$bin=$p\"; done; \
+         for var in {vars}; do [ -n \"$(printenv $var)\" ] && echo \"env.$var=set\"; done; \
+         [ -n \"$CODEX_HOME\" ] && echo \"env.CODEX_HOME=$CODEX_HOME\"; true"
+    )
+}
+
+/// Parse the probe's `path.<cli>=...` / `env.<VAR>=...` lines, ignoring
+/// anything else shell startup files print
+fn parse_probe(output: &str) -> Probe {
+    let mut probe = Probe::default();
+    for line in output.lines() {
+        let Some((key, value)) = line.trim().split_once('=') else { continue };
+        if value.is_empty() {
+            continue;
+        }
+        if let Some(cli) = key.strip_prefix("path.").filter(|c| CLIS.contains(c)) {
+            probe.paths.insert(cli.to_string(), value.to_string());
+        } else if let Some(var) = key.strip_prefix("env.").filter(|v| KEY_VARS.contains(v) || *v == "CODEX_HOME") {
+            probe.env.insert(var.to_string(), value.to_string());
+        }
+    }
+    probe
+}
+
+async fn run_probe() -> Result<Probe, String> {
+    let shell = crate::platform::user_shell();
+    let output = tokio::time::timeout(
+        VERSION_TIMEOUT,
+        tokio::process::Command::new(&shell)
+            .args(["-l", "-c", &probe_script()])
+            .env("PATH", crate::platform::agent_path())
+            .output(),
+    )
+    .await
+    .map_err(|_| format!("{} took too long to start", shell))?
+    .map_err(|e| format!("Couldn't run {}: {}", shell, e))?;
+    Ok(parse_probe(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// First line of `<cli> --version`
+async fn cli_version(path: &str) -> Result<String, String> {
+    let output = tokio::time::timeout(VERSION_TIMEOUT, tokio::process::Command::new(path).arg("--version").output())
+        .await
+        .map_err(|_| "didn't answer --version".to_string())?
+        .map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(stderr.lines().next().unwrap_or("--version failed").trim().to_string());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).lines().next().unwrap_or("").trim().to_string())
+}
+
+fn home() -> PathBuf {
+    dirs::home_dir().unwrap_or_default()
+}
+
+/// Claude's config directory: the CLAUDE_CONFIG_DIR setting, else ~/.claude
+fn claude_dir() -> PathBuf {
+    crate::load_app_settings()
+        .ok()
+        .and_then(|s| s.claude_config_dir)
+        .filter(|d| !d.trim().is_empty())
+        .map(|d| PathBuf::from(shellexpand::tilde(d.trim()).to_string()))
+        .unwrap_or_else(|| home().join(".claude"))
+}
+
+/// A ~/.claude.json-style file recording a logged-in account
+fn has_oauth_account(path: &Path) -> bool {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|json| serde_json::from_str::<serde_json::Value>(&json).ok())
+        .is_some_and(|v| v.get("oauthAccount").is_some_and(|a| !a.is_null()))
+}
+
+#[cfg(target_os = "macos")]
+fn claude_keychain_login() -> bool {
+    std::process::Command::new("security")
+        .args(["find-generic-password", "-s", "Claude Code-credentials"])
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success())
+}
+
+#[cfg(not(target_os = "macos"))]
+fn claude_keychain_login() -> bool {
+    false
+}
+
+/// How a CLI is authenticated, if it is
+fn login_method(cli: &str, env: &HashMap<String, String>) -> Option<&'static str> {
+    match cli {
+        "claude" => {
+            let dir = claude_dir();
+            let account = dir.join(".credentials.json").is_file()
+                || has_oauth_account(&home().join(".claude.json"))
+                || has_oauth_account(&dir.join(".claude.json"))
+                || claude_keychain_login();
+            if account {
+                Some("Claude account")
+            } else if env.contains_key("ANTHROPIC_API_KEY") {
+                Some("ANTHROPIC_API_KEY")
+            } else {
+                None
+            }
+        }
+        "codex" => {
+            let codex_home = env
+                .get("CODEX_HOME")
+                .map(|d| PathBuf::from(shellexpand::tilde(d).to_string()))
+                .unwrap_or_else(|| home().join(".codex"));
+            if codex_home.join("auth.json").is_file() {
+                Some("ChatGPT account")
+            } else if env.contains_key("OPENAI_API_KEY") {
+                Some("OPENAI_API_KEY")
+            } else {
+                None
+            }
+        }
+        "aider" => {
+            if env.contains_key("ANTHROPIC_API_KEY") {
+                Some("ANTHROPIC_API_KEY")
+            } else if env.contains_key("OPENAI_API_KEY") {
+                Some("OPENAI_API_KEY")
+            } else if home().join(".aider.conf.yml").is_file() || home().join(".env").is_file() {
+                Some("aider config")
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+struct Cli {
+    name: &'static str,
+    label: &'static str,
+    required: bool,
+    install: &'static str,
+    login: &'static str,
+}
+
+const CLI_INFO: &[Cli] = &[
+    Cli {
+        name: "claude",
+        label: "Claude Code",
+        required: true,
+        install: "npm install -g @anthropic-ai/claude-code",
+        login: "Run `claude` in a terminal and log in, or set ANTHROPIC_API_KEY in your shell profile",
+    },
+    Cli {
+        name: "aider",
+        label: "Aider",
+        required: false,
+        install: "python -m pip install aider-install && aider-install",
+        login: "Set ANTHROPIC_API_KEY or OPENAI_API_KEY in your shell profile",
+    },
+    Cli {
+        name: "codex",
+        label: "Codex",
+        required: false,
+        install: "npm install -g @openai/codex",
+        login: "Run `codex login`, or set OPENAI_API_KEY in your shell profile",
+    },
+];
+
+/// The installed and logged-in checks for one CLI, from what was found
+fn cli_checks(cli: &Cli, path: Option<&str>, version: Option<Result<String, String>>, login: Option<&str>) -> Vec<Check> {
+    let problem = if cli.required { Status::Error } else { Status::Warning };
+    let installed_id = format!("{}_installed", cli.name);
+    let login_id = format!("{}_logged_in", cli.name);
+    let login_label = format!("{} logged in", cli.label);
+    let Some(path) = path else {
+        return vec![Check::new(
+            &installed_id,
+            cli.label,
+            problem,
+            format!("`{}` isn't on your login shell's PATH", cli.name),
+            Some(cli.install),
+        )];
+    };
+    let installed = match version {
+        Some(Err(e)) => Check::new(&installed_id, cli.label, problem, format!("{} is installed but {}", path, e), Some(cli.install)),
+        Some(Ok(v)) if !v.is_empty() => Check::new(&installed_id, cli.label, Status::Ok, format!("{} ({})", v, path), None),
+        _ => Check::new(&installed_id, cli.label, Status::Ok, path.to_string(), None),
+    };
+    let logged_in = match login {
+        Some(method) => Check::new(&login_id, &login_label, Status::Ok, format!("Using {}", method), None),
+        None => Check::new(&login_id, &login_label, problem, "No credentials found", Some(cli.login)),
+    };
+    vec![installed, logged_in]
+}
+
+fn claude_dir_check() -> Check {
+    let dir = claude_dir();
+    if dir.is_dir() {
+        Check::new("claude_dir", "Claude config directory", Status::Ok, dir.display().to_string(), None)
+    } else {
+        Check::new(
+            "claude_dir",
+            "Claude config directory",
+            Status::Warning,
+            format!("{} doesn't exist yet, so there are no past conversations to resume or search", dir.display()),
+            Some("Run `claude` once in a terminal to create it"),
+        )
+    }
+}
+
+/// Whether the web server (remote access, MCP bridge) is listening, or why
+/// it couldn't
+async fn network_check() -> Check {
+    const ID: &str = "network";
+    const LABEL: &str = "Remote access server";
+    let settings = crate::load_app_settings().unwrap_or_default();
+    let bind_ip = crate::web_bind_ip(&settings);
+    let running_port = *crate::state::get().web_server_port.lock();
+    match running_port {
+        Some(port) => {
+            let target = if bind_ip.is_unspecified() { std::net::Ipv4Addr::LOCALHOST.into() } else { bind_ip };
+            let connect = tokio::net::TcpStream::connect(std::net::SocketAddr::new(target, port));
+            match tokio::time::timeout(Duration::from_secs(3), connect).await {
+                Ok(Ok(_)) => Check::new(ID, LABEL, Status::Ok, format!("Listening on {}:{}", bind_ip, port), None),
+                Ok(Err(e)) => Check::new(ID, LABEL, Status::Error, format!("Port {} doesn't accept connections: {}", port, e), None),
+                Err(_) => Check::new(ID, LABEL, Status::Error, format!("Port {} didn't answer", port), None),
+            }
+        }
+        None => {
+            let addr = std::net::SocketAddr::new(bind_ip, crate::WEB_PORT_BASE);
+            match tokio::net::TcpListener::bind(addr).await {
+                Ok(_) => Check::new(ID, LABEL, Status::Warning, "Not running; restart the app to start it", None),
+                Err(e) => Check::new(
+                    ID,
+                    LABEL,
+                    Status::Error,
+                    format!("Can't listen on {}: {}", addr, e),
+                    Some("Quit whatever is using the port, or change the bind address in Settings"),
+                ),
+            }
+        }
+    }
+}
+
+/// Every check, in checklist order
+pub async fn run() -> Vec<Check> {
+    let probe = match run_probe().await {
+        Ok(probe) => probe,
+        Err(e) => {
+            return vec![Check::new("shell", "Login shell", Status::Error, e, Some("Set SHELL to a working shell"))];
+        }
+    };
+    let mut checks = Vec::new();
+    for cli in CLI_INFO {
+        let path = probe.paths.get(cli.name).map(String::as_str);
+        let version = match path {
+            Some(path) => Some(cli_version(path).await),
+            None => None,
+        };
+        checks.extend(cli_checks(cli, path, version, login_method(cli.name, &probe.env)));
+        if cli.name == "claude" {
+            checks.push(claude_dir_check());
+        }
+    }
+    checks.push(network_check().await);
+    checks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn probe_output_is_read_past_shell_noise() {
+        let output = "Welcome to fish-like zsh!\n\
+                      path.claude=/opt/homebrew/bin/claude\n\
+                      path.codex=/usr/local/bin/codex\n\
+                      path.rm=/bin/rm\n\
+                      env.OPENAI_API_KEY=set\n\
+                      env.CODEX_HOME=/Users/me/.codex-work\n\
+                      env.HOME=/Users/me\n\
+                      path.aider=\n";
+        let probe = parse_probe(output);
+        assert_eq!(probe.paths.len(), 2);
+        assert_eq!(probe.paths["claude"], "/opt/homebrew/bin/claude");
+        assert_eq!(probe.env.len(), 2);
+        assert!(probe.env.contains_key("OPENAI_API_KEY"));
+        assert_eq!(probe.env["CODEX_HOME"], "/Users/me/.codex-work");
+    }
+
+    #[test]
+    fn missing_optional_clis_are_only_warnings() {
+        let claude = &CLI_INFO[0];
+        let codex = &CLI_INFO[2];
+
+        let missing = cli_checks(claude, None, None, None);
+        assert_eq!(missing.len(), 1);
+        assert_eq!(missing[0].status, Status::Error);
+        assert!(missing[0].fix.is_some());
+        assert_eq!(cli_checks(codex, None, None, None)[0].status, Status::Warning);
+
+        let ready = cli_checks(claude, Some("/bin/claude"), Some(Ok("2.0.1 (Claude Code)".into())), Some("Claude account"));
+        assert!(ready.iter().all(|c| c.status == Status::Ok));
+        assert_eq!(ready[0].detail, "2.0.1 (Claude Code) (/bin/claude)");
+
+        let broken = cli_checks(codex, Some("/bin/codex"), Some(Err("exited 1".into())), None);
+        assert_eq!(broken[0].status, Status::Warning);
+        assert_eq!(broken[1].id, "codex_logged_in");
+        assert_eq!(broken[1].status, Status::Warning);
+    }
+}
@@ -0,0 +1,241 @@
+// LAN discovery over mDNS / Bonjour.
+//
+// The web server is advertised as `_agenthub._tcp` with TXT records for the
+// app version, API protocol version and whether it speaks HTTPS. There is no
+// mDNS stack in-process: we drive the OS responder through its CLI
+// (`dns-sd` on macOS, avahi on Linux), which also means advertisements
+// disappear with the process if the app is killed.
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+
+pub const SERVICE_TYPE: &str = "_agenthub._tcp";
+const BROWSE_WINDOW: Duration = Duration::from_millis(2000);
+const RESOLVE_WINDOW: Duration = Duration::from_millis(1500);
+
+static ADVERTISER: Lazy<Mutex<Option<Child>>> = Lazy::new(|| Mutex::new(None));
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Instance {
+    pub name: String,
+    pub host: String,
+    pub port: u16,
+    pub version: Option<String>,
+    pub protocol_version: Option<u32>,
+    pub tls: bool,
+    pub url: String,
+}
+
+impl Instance {
+    fn new(name: &str, host: &str, port: u16, txt: &HashMap<String, String>) -> Self {
+        let host = host.trim_end_matches('.').to_string();
+        let tls = txt.get("tls").map(|v| v == "1").unwrap_or(false);
+        let url = format!("{}://{}:{}", if tls { "https" } else { "http" }, host, port);
+        Instance {
+            name: name.to_string(),
+            host,
+            port,
+            version: txt.get("version").cloned(),
+            protocol_version: txt.get("protocol").and_then(|v| v.parse().ok()),
+            tls,
+            url,
+        }
+    }
+}
+
+/// Name this hub advertises under.
+pub fn instance_name() -> String {
+    let host = Command::new("scutil")
+        .args(["--get", "ComputerName"])
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+        .filter(|h| !h.is_empty())
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .unwrap_or_else(|| "this computer".to_string());
+    format!("Agent Hub on {}", host)
+}
+
+// =====================================================================
+//  Advertising
+// =====================================================================
+
+/// Start advertising the web server. Replaces any previous advertisement.
+pub fn advertise(port: u16, tls: bool) {
+    stop_advertising();
+    let name = instance_name();
+    let txt = [
+        format!("version={}", env!("CARGO_PKG_VERSION")),
+        format!("protocol={}", crate::PROTOCOL_VERSION),
+        format!("tls={}", u8::from(tls)),
+    ];
+    let mut cmd = if cfg!(target_os = "macos") {
+        let mut cmd = Command::new("dns-sd");
+        cmd.args(["-R", &name, SERVICE_TYPE, "local", &port.to_string()]);
+        cmd
+    } else {
+        let mut cmd = Command::new("avahi-publish-service");
+        cmd.args([name.as_str(), SERVICE_TYPE, &port.to_string()]);
+        cmd
+    };
+    cmd.args(&txt).stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null());
+    match cmd.spawn() {
+        Ok(child) => *ADVERTISER.lock() = Some(child),
+        Err(e) => tracing::warn!("mDNS advertising unavailable: {}", e),
+    }
+}
+
+pub fn stop_advertising() {
+    if let Some(mut child) = ADVERTISER.lock().take() {
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+}
+
+// =====================================================================
+//  Discovery
+// =====================================================================
+
+/// Run a long-lived browse/resolve command for `window` and return its output.
+fn run_for(program: &str, args: &[&str], window: Duration) -> String {
+    let Ok(mut child) = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+    else {
+        return String::new();
+    };
+    std::thread::sleep(window);
+    let _ = child.kill();
+    child
+        .wait_with_output()
+        .map(|o| String::from_utf8_lossy(&o.stdout).into_owned())
+        .unwrap_or_default()
+}
+
+/// Instance names from `dns-sd -B` output (adds not later removed).
+fn parse_browse(output: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for line in output.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        // Timestamp A/R Flags if Domain ServiceType InstanceName...
+        if fields.len() < 7 || !fields[5].starts_with(SERVICE_TYPE) {
+            continue;
+        }
+        let name = fields[6..].join(" ");
+        match fields[1] {
+            "Add" if !names.contains(&name) => names.push(name),
+            "Rmv" => names.retain(|n| n != &name),
+            _ => {}
+        }
+    }
+    names
+}
+
+/// Host, port and TXT record from `dns-sd -L` output.
+fn parse_resolve(output: &str) -> Option<(String, u16, HashMap<String, String>)> {
+    let mut lines = output.lines();
+    let line = lines.by_ref().find(|l| l.contains(" can be reached at "))?;
+    let target = line.split(" can be reached at ").nth(1)?.split_whitespace().next()?;
+    let (host, port) = target.rsplit_once(':')?;
+    let txt = lines.next().map(parse_txt).unwrap_or_default();
+    Some((host.to_string(), port.parse().ok()?, txt))
+}
+
+fn parse_txt(line: &str) -> HashMap<String, String> {
+    line.split_whitespace()
+        .filter_map(|kv| kv.trim_matches('"').split_once('='))
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+/// Resolved services from `avahi-browse -rpt` output.
+fn parse_avahi(output: &str) -> Vec<Instance> {
+    let mut found: Vec<Instance> = Vec::new();
+    for line in output.lines() {
+        // =;iface;proto;name;type;domain;host;address;port;"txt" "txt"
+        let fields: Vec<&str> = line.splitn(10, ';').collect();
+        if fields.len() < 9 || fields[0] != "=" || fields[4] != SERVICE_TYPE {
+            continue;
+        }
+        let Ok(port) = fields[8].parse() else { continue };
+        let txt = fields.get(9).map(|t| parse_txt(t)).unwrap_or_default();
+        let instance = Instance::new(&fields[3].replace("\\032", " "), fields[6], port, &txt);
+        // One entry per interface/protocol; keep the first
+        if !found.iter().any(|i| i.name == instance.name) {
+            found.push(instance);
+        }
+    }
+    found
+}
+
+/// Find Agent Hub instances on the local network (takes a few seconds).
+pub fn discover() -> Vec<Instance> {
+    if !cfg!(target_os = "macos") {
+        let output = run_for("avahi-browse", &["-rpt", SERVICE_TYPE], BROWSE_WINDOW);
+        return parse_avahi(&output);
+    }
+
+    let names = parse_browse(&run_for("dns-sd", &["-B", SERVICE_TYPE, "local"], BROWSE_WINDOW));
+    let handles: Vec<_> = names
+        .into_iter()
+        .map(|name| {
+            std::thread::spawn(move || {
+                let output = run_for("dns-sd", &["-L", &name, SERVICE_TYPE, "local"], RESOLVE_WINDOW);
+                parse_resolve(&output).map(|(host, port, txt)| Instance::new(&name, &host, port, &txt))
+            })
+        })
+        .collect();
+    handles.into_iter().filter_map(|h| h.join().ok().flatten()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn browse_tracks_adds_and_removes() {
+        let output = "\
+Browsing for _agenthub._tcp.local
+Timestamp     A/R    Flags  if Domain               Service Type         Instance Name
+10:00:00.123  Add        3   4 local.               _agenthub._tcp.      Agent Hub on Mac mini
+10:00:00.124  Add        2   4 local.               _agenthub._tcp.      Agent Hub on Laptop
+10:00:01.500  Rmv        0   4 local.               _agenthub._tcp.      Agent Hub on Laptop
+";
+        assert_eq!(parse_browse(output), vec!["Agent Hub on Mac mini".to_string()]);
+    }
+
+    #[test]
+    fn resolve_reads_host_port_and_txt() {
+        let output = "\
+Lookup Agent Hub on Mac mini._agenthub._tcp.local
+10:00:00.200  Agent\\032Hub\\032on\\032Mac\\032mini._agenthub._tcp.local. can be reached at Mac-mini.local.:3030 (interface 4)
+ version=0.1.109 protocol=1 tls=1
+";
+        let (host, port, txt) = parse_resolve(output).unwrap();
+        let instance = Instance::new("Agent Hub on Mac mini", &host, port, &txt);
+        assert_eq!(instance.url, "https://Mac-mini.local:3030");
+        assert_eq!(instance.protocol_version, Some(1));
+    }
+
+    #[test]
+    fn avahi_resolved_lines_become_instances() {
+        let output = "\
++;eth0;IPv4;Agent\\032Hub\\032on\\032box;_agenthub._tcp;local
+=;eth0;IPv4;Agent\\032Hub\\032on\\032box;_agenthub._tcp;local;box.local;192.168.1.5;3030;\"tls=0\" \"protocol=1\" \"version=0.1.109\"
+=;eth0;IPv6;Agent\\032Hub\\032on\\032box;_agenthub._tcp;local;box.local;fe80::1;3030;\"tls=0\"
+";
+        let found = parse_avahi(output);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].name, "Agent Hub on box");
+        assert_eq!(found[0].url, "http://box.local:3030");
+        assert_eq!(found[0].version.as_deref(), Some("0.1.109"));
+    }
+}
@@ -0,0 +1,53 @@
+// Dock / taskbar state: the badge counts sessions needing attention
+// (attention.rs), and an indeterminate progress bar runs while any agent is
+// working on a turn (fed from broadcast_processing_status). A session newly
+// needing attention also bounces the dock icon when `bounce_dock_on_bell` is
+// on and the window isn't focused - the JSON-session counterpart of the
+// terminal bell handling in the frontend.
+//
+// Badges are macOS and some Linux docks; progress is the macOS dock and the
+// Windows taskbar. Where unsupported the calls are no-ops.
+
+use tauri::window::{ProgressBarState, ProgressBarStatus};
+use tauri::{Manager, UserAttentionType};
+
+/// Badge value for a number of sessions: none at zero
+fn badge(count: usize) -> Option<i64> {
+    (count > 0).then_some(count as i64)
+}
+
+/// Bring the badge and progress bar in line with current state
+pub fn refresh() {
+    let Some(app) = crate::state::get().app_handle.lock().clone() else { return };
+    let Some(window) = app.get_webview_window("main") else { return };
+    let _ = window.set_badge_count(badge(crate::attention::count()));
+    let working = !crate::state::get().processing_sessions.lock().is_empty();
+    let _ = window.set_progress_bar(ProgressBarState {
+        status: Some(if working { ProgressBarStatus::Indeterminate } else { ProgressBarStatus::None }),
+        progress: None,
+    });
+}
+
+/// A session started needing attention
+pub fn on_attention() {
+    refresh();
+    if !crate::load_app_settings().unwrap_or_default().bounce_dock_on_bell {
+        return;
+    }
+    let Some(app) = crate::state::get().app_handle.lock().clone() else { return };
+    let Some(window) = app.get_webview_window("main") else { return };
+    if !window.is_focused().unwrap_or(false) {
+        let _ = window.request_user_attention(Some(UserAttentionType::Informational));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_badge_without_sessions() {
+        assert_eq!(badge(0), None);
+        assert_eq!(badge(3), Some(3));
+    }
+}
@@ -0,0 +1,171 @@
+// Events on the status stream (/api/ws/status, and the status messages
+// interleaved on /api/ws/<session id>).
+//
+// Producers broadcast a typed SessionEvent; how it looks on the wire is
+// decided where it leaves, per client protocol. There's one protocol so far:
+// {"type": "<event name>", "data": {...}} with snake_case keys. A Broadcast
+// serializes itself the first time a client needs it and every other client
+// reuses that text.
+//
+// The mobile WebSocket has its own camelCase messages, still sent alongside
+// by the producers through broadcast_to_mobile_clients.
+
+use crate::fanout::FanoutRun;
+use crate::queue::QueuedPrompt;
+use crate::session_errors::SessionError;
+use crate::{FolderData, SessionData};
+use serde::Serialize;
+use std::sync::OnceLock;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum SessionEvent {
+    SessionStatus { session_id: String, running: bool },
+    ProcessingStatus { session_id: String, processing: bool, context_used_pct: Option<u32> },
+    SessionCreated(SessionData),
+    SessionUpdated(SessionData),
+    SessionDeleted { session_id: String },
+    FolderCreated(FolderData),
+    FolderUpdated(FolderData),
+    FolderDeleted { folder_id: String },
+    /// The remotely visible settings (see remote_settings)
+    SettingsUpdated(serde_json::Value),
+    ContextWarning { session_id: String, context_used_pct: u32, used_tokens: u64, threshold_pct: u32 },
+    QueueUpdated { session_id: String, queue: Vec<QueuedPrompt>, in_flight: bool },
+    PipelineStatus {
+        pipeline_id: String,
+        name: String,
+        source_session_id: String,
+        target_session_id: String,
+        status: String,
+        error: Option<String>,
+    },
+    FanoutUpdated(FanoutRun),
+    SessionRestarted { session_id: String, attempt: u32, max_attempts: u32, exit_code: Option<i32> },
+    SessionHung { session_id: String, silent_secs: u64, policy: String },
+    SessionRecovered { session_id: String },
+    SessionError { session_id: String, error: SessionError },
+    /// A JSON session's process is being started; SessionReady follows once
+    /// it's running, or SessionError if it couldn't be
+    SessionStarting { session_id: String },
+    SessionReady { session_id: String },
+}
+
+impl SessionEvent {
+    /// The event's "type" on the wire, which is also what `events=` filters on
+    pub fn name(&self) -> &'static str {
+        match self {
+            SessionEvent::SessionStatus { .. } => "session_status",
+            SessionEvent::ProcessingStatus { .. } => "processing_status",
+            SessionEvent::SessionCreated(_) => "session_created",
+            SessionEvent::SessionUpdated(_) => "session_updated",
+            SessionEvent::SessionDeleted { .. } => "session_deleted",
+            SessionEvent::FolderCreated(_) => "folder_created",
+            SessionEvent::FolderUpdated(_) => "folder_updated",
+            SessionEvent::FolderDeleted { .. } => "folder_deleted",
+            SessionEvent::SettingsUpdated(_) => "settings_updated",
+            SessionEvent::ContextWarning { .. } => "context_warning",
+            SessionEvent::QueueUpdated { .. } => "queue_updated",
+            SessionEvent::PipelineStatus { .. } => "pipeline_status",
+            SessionEvent::FanoutUpdated(_) => "fanout_updated",
+            SessionEvent::SessionRestarted { .. } => "session_restarted",
+            SessionEvent::SessionHung { .. } => "session_hung",
+            SessionEvent::SessionRecovered { .. } => "session_recovered",
+            SessionEvent::SessionError { .. } => "session_error",
+            SessionEvent::SessionStarting { .. } => "session_starting",
+            SessionEvent::SessionReady { .. } => "session_ready",
+        }
+    }
+
+    /// The session the event is about. Pipelines and fan-outs span several
+    /// and count as about none.
+    pub fn session_id(&self) -> Option<&str> {
+        match self {
+            SessionEvent::SessionStatus { session_id, .. }
+            | SessionEvent::ProcessingStatus { session_id, .. }
+            | SessionEvent::SessionDeleted { session_id }
+            | SessionEvent::ContextWarning { session_id, .. }
+            | SessionEvent::QueueUpdated { session_id, .. }
+            | SessionEvent::SessionRestarted { session_id, .. }
+            | SessionEvent::SessionHung { session_id, .. }
+            | SessionEvent::SessionRecovered { session_id }
+            | SessionEvent::SessionStarting { session_id }
+            | SessionEvent::SessionReady { session_id }
+            | SessionEvent::SessionError { session_id, .. } => Some(session_id),
+            SessionEvent::SessionCreated(session) | SessionEvent::SessionUpdated(session) => Some(&session.id),
+            _ => None,
+        }
+    }
+
+    /// The folder the event names, if it names one: Some(None) is a session
+    /// that isn't in a folder. None means look the session's folder up.
+    pub fn folder_id(&self) -> Option<Option<&str>> {
+        match self {
+            SessionEvent::FolderCreated(folder) | SessionEvent::FolderUpdated(folder) => Some(Some(&folder.id)),
+            SessionEvent::FolderDeleted { folder_id } => Some(Some(folder_id)),
+            SessionEvent::SessionCreated(session) | SessionEvent::SessionUpdated(session) => {
+                Some(session.folder_id.as_deref())
+            }
+            _ => None,
+        }
+    }
+
+    pub fn is_folder_event(&self) -> bool {
+        matches!(
+            self,
+            SessionEvent::FolderCreated(_) | SessionEvent::FolderUpdated(_) | SessionEvent::FolderDeleted { .. }
+        )
+    }
+
+    /// Just the payload, for the desktop's Tauri event of the same name
+    pub fn data(&self) -> serde_json::Value {
+        match serde_json::to_value(self) {
+            Ok(serde_json::Value::Object(mut map)) => map.remove("data").unwrap_or_default(),
+            _ => serde_json::Value::Null,
+        }
+    }
+}
+
+/// One event on its way to every status subscriber
+#[derive(Debug)]
+pub struct Broadcast {
+    pub event: SessionEvent,
+    v1: OnceLock<String>,
+}
+
+impl Broadcast {
+    pub fn new(event: SessionEvent) -> Self {
+        Broadcast { event, v1: OnceLock::new() }
+    }
+
+    /// The protocol 1 text, serialized once for all clients
+    pub fn json(&self) -> &str {
+        self.v1.get_or_init(|| serde_json::to_string(&self.event).unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wire_format_matches_the_hand_built_messages() {
+        let event = SessionEvent::SessionStatus { session_id: "s1".to_string(), running: true };
+        let sent: serde_json::Value = serde_json::from_str(Broadcast::new(event.clone()).json()).unwrap();
+        assert_eq!(sent, serde_json::json!({ "type": "session_status", "data": { "session_id": "s1", "running": true } }));
+        assert_eq!(sent["type"], event.name());
+        assert_eq!(event.data(), serde_json::json!({ "session_id": "s1", "running": true }));
+
+        let settings = SessionEvent::SettingsUpdated(serde_json::json!({ "theme": "dark" }));
+        assert_eq!(
+            serde_json::to_value(&settings).unwrap(),
+            serde_json::json!({ "type": "settings_updated", "data": { "theme": "dark" } })
+        );
+    }
+
+    #[test]
+    fn serialized_once() {
+        let broadcast = Broadcast::new(SessionEvent::SessionRecovered { session_id: "s1".to_string() });
+        assert!(std::ptr::eq(broadcast.json(), broadcast.json()));
+    }
+}
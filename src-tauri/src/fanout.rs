@@ -0,0 +1,419 @@
+// Fan-out runs: send one prompt to several claude-json sessions and collect
+// the responses side by side for comparison (A/B-ing models, prompts,
+// settings).
+//
+// A run is a `fanout_runs` row plus one `fanout_responses` row per target
+// session. Each response starts "pending" and is filled in from that
+// session's next `result` message; once every response has landed the run
+// is marked "completed".
+//
+// A session is only waited on for the turn its fan-out prompt started: it's
+// registered in AppState::fanout_pending once the prompt has been sent, and
+// sessions that are mid-turn or already answering another fan-out are
+// refused up front, so an earlier turn's result can't be taken for the
+// response. A session that exits or is deleted before answering fails its
+// response.
+
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use tauri::Emitter;
+
+// =====================================================================
+//  Schema
+// =====================================================================
+
+pub fn run_fanout_migrations(conn: &Connection) {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS fanout_runs (
+            id         TEXT PRIMARY KEY,
+            prompt     TEXT NOT NULL,
+            status     TEXT NOT NULL,
+            created_at TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS fanout_responses (
+            fanout_id      TEXT NOT NULL,
+            session_id     TEXT NOT NULL,
+            status         TEXT NOT NULL,
+            result         TEXT,
+            is_error       INTEGER NOT NULL DEFAULT 0,
+            duration_ms    REAL,
+            total_cost_usd REAL,
+            num_turns      INTEGER,
+            completed_at   TEXT,
+            PRIMARY KEY (fanout_id, session_id)
+        );",
+    )
+    .expect("Failed to create fanout tables");
+}
+
+// =====================================================================
+//  Types
+// =====================================================================
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FanoutResponse {
+    pub session_id: String,
+    pub session_name: Option<String>,
+    /// "pending", "completed" or "failed".
+    pub status: String,
+    pub result: Option<String>,
+    pub is_error: bool,
+    pub duration_ms: Option<f64>,
+    pub total_cost_usd: Option<f64>,
+    pub num_turns: Option<u32>,
+    pub completed_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FanoutRun {
+    pub id: String,
+    pub prompt: String,
+    /// "running" until every response is in, then "completed".
+    pub status: String,
+    pub created_at: String,
+    pub responses: Vec<FanoutResponse>,
+}
+
+// =====================================================================
+//  Runs
+// =====================================================================
+
+/// Send `prompt` to every session in `session_ids`, starting stopped ones.
+/// Returns immediately; responses fill in as each session finishes its turn.
+pub fn fanout_prompt(session_ids: Vec<String>, prompt: String) -> Result<FanoutRun, String> {
+    if prompt.trim().is_empty() {
+        return Err("Prompt is empty".to_string());
+    }
+    let mut targets: Vec<String> = Vec::new();
+    for id in session_ids {
+        if !targets.contains(&id) {
+            targets.push(id);
+        }
+    }
+    if targets.len() < 2 {
+        return Err("Fan-out needs at least two distinct sessions".to_string());
+    }
+
+    {
+        let conn = crate::state::get().db().lock();
+        for id in &targets {
+            let agent_type: String = conn
+                .query_row("SELECT agent_type FROM sessions WHERE id = ?1", params![id], |r| r.get(0))
+                .map_err(|_| format!("Session not found: {}", id))?;
+            if agent_type != "claude-json" {
+                return Err(format!("Session {} is not a claude-json session", id));
+            }
+        }
+    }
+
+    // Claim the targets, refusing any that are busy
+    {
+        let pending = crate::state::get().fanout_pending.lock();
+        let mut sending = crate::state::get().fanout_sending.lock();
+        if let Some(busy) = targets
+            .iter()
+            .find(|id| pending.contains_key(*id) || sending.contains(*id) || crate::is_session_processing(id))
+        {
+            return Err(format!("Session {} is busy; try again when its turn has finished", busy));
+        }
+        sending.extend(targets.iter().cloned());
+    }
+    let run_id = match create_run(&targets, &prompt) {
+        Ok(run_id) => run_id,
+        Err(e) => {
+            let mut sending = crate::state::get().fanout_sending.lock();
+            for id in &targets {
+                sending.remove(id);
+            }
+            return Err(e);
+        }
+    };
+
+    // Starting a stopped session can take a few seconds, so each target
+    // gets its own thread and the caller isn't held up.
+    for session_id in targets {
+        let run_id = run_id.clone();
+        let prompt = prompt.clone();
+        std::thread::spawn(move || {
+            let sent = crate::ensure_json_session_running(&session_id)
+                .and_then(|_| crate::send_user_prompt(&session_id, &prompt));
+            // This prompt's turn is the one to wait for, unless the session
+            // went away meanwhile (forget)
+            {
+                let mut pending = crate::state::get().fanout_pending.lock();
+                let claimed = crate::state::get().fanout_sending.lock().remove(&session_id);
+                if sent.is_ok() && claimed {
+                    pending.insert(session_id.clone(), run_id.clone());
+                    return;
+                }
+            }
+            let error = sent.err().unwrap_or_else(|| "Session was stopped".to_string());
+            tracing::error!("failed to send to {}: {}", session_id, error);
+            record_response(&run_id, &session_id, "failed", Some(error), true, None, None, None);
+        });
+    }
+
+    get_fanout(&run_id)
+}
+
+/// The run's rows, every response pending; returns its id
+fn create_run(targets: &[String], prompt: &str) -> Result<String, String> {
+    let run_id = uuid::Uuid::new_v4().to_string();
+    {
+        let mut conn = crate::state::get().db().lock();
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        tx.execute(
+            "INSERT INTO fanout_runs (id, prompt, status, created_at) VALUES (?1, ?2, 'running', ?3)",
+            params![run_id, prompt, chrono::Utc::now().to_rfc3339()],
+        )
+        .map_err(|e| e.to_string())?;
+        for id in &targets {
+            tx.execute(
+                "INSERT INTO fanout_responses (fanout_id, session_id, status) VALUES (?1, ?2, 'pending')",
+                params![run_id, id],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+        tx.commit().map_err(|e| e.to_string())?;
+    }
+    Ok(run_id)
+}
+
+pub fn get_fanout(id: &str) -> Result<FanoutRun, String> {
+    let conn = crate::state::get().db().lock();
+    let (prompt, status, created_at) = conn
+        .query_row(
+            "SELECT prompt, status, created_at FROM fanout_runs WHERE id = ?1",
+            params![id],
+            |r| Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?, r.get::<_, String>(2)?)),
+        )
+        .map_err(|_| "Fan-out run not found".to_string())?;
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT r.session_id, s.name, r.status, r.result, r.is_error, r.duration_ms,
+                    r.total_cost_usd, r.num_turns, r.completed_at
+             FROM fanout_responses r LEFT JOIN sessions s ON s.id = r.session_id
+             WHERE r.fanout_id = ?1 ORDER BY s.name ASC",
+        )
+        .map_err(|e| e.to_string())?;
+    let responses = stmt
+        .query_map(params![id], |r| {
+            Ok(FanoutResponse {
+                session_id: r.get(0)?,
+                session_name: r.get(1)?,
+                status: r.get(2)?,
+                result: r.get(3)?,
+                is_error: r.get::<_, i64>(4)? != 0,
+                duration_ms: r.get(5)?,
+                total_cost_usd: r.get(6)?,
+                num_turns: r.get(7)?,
+                completed_at: r.get(8)?,
+            })
+        })
+        .map_err(|e| e.to_string())?
+        .filter_map(|r| r.ok())
+        .collect();
+
+    Ok(FanoutRun {
+        id: id.to_string(),
+        prompt,
+        status,
+        created_at,
+        responses,
+    })
+}
+
+/// Most recent runs first (responses included).
+pub fn list_fanouts(limit: u32) -> Result<Vec<FanoutRun>, String> {
+    let ids: Vec<String> = {
+        let conn = crate::state::get().db().lock();
+        let mut stmt = conn
+            .prepare("SELECT id FROM fanout_runs ORDER BY created_at DESC LIMIT ?1")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(params![limit], |r| r.get(0))
+            .map_err(|e| e.to_string())?
+            .filter_map(|r| r.ok())
+            .collect();
+        rows
+    };
+    ids.iter().map(|id| get_fanout(id)).collect()
+}
+
+// =====================================================================
+//  Completion tracking
+// =====================================================================
+
+/// Called from the stdout reader when a `result` message arrives.
+pub fn on_turn_complete(session_id: &str, msg: &crate::ClaudeJsonMessage) {
+    let Some(run_id) = crate::state::get().fanout_pending.lock().remove(session_id) else {
+        return;
+    };
+    let is_error = msg.is_error.unwrap_or(false);
+    record_response(
+        &run_id,
+        session_id,
+        if is_error { "failed" } else { "completed" },
+        msg.result.clone(),
+        is_error,
+        msg.duration_ms,
+        msg.total_cost_usd,
+        msg.num_turns,
+    );
+}
+
+/// The session exited or was deleted: a response it owed won't come
+pub fn forget(session_id: &str) {
+    crate::state::get().fanout_sending.lock().remove(session_id);
+    if let Some(run_id) = crate::state::get().fanout_pending.lock().remove(session_id) {
+        record_response(
+            &run_id,
+            session_id,
+            "failed",
+            Some("Session stopped before answering".to_string()),
+            true,
+            None,
+            None,
+            None,
+        );
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn record_response(
+    run_id: &str,
+    session_id: &str,
+    status: &str,
+    result: Option<String>,
+    is_error: bool,
+    duration_ms: Option<f64>,
+    total_cost_usd: Option<f64>,
+    num_turns: Option<u32>,
+) {
+    {
+        let conn = crate::state::get().db().lock();
+        let _ = conn.execute(
+            "UPDATE fanout_responses SET status = ?1, result = ?2, is_error = ?3, duration_ms = ?4,
+                total_cost_usd = ?5, num_turns = ?6, completed_at = ?7
+             WHERE fanout_id = ?8 AND session_id = ?9",
+            params![
+                status,
+                result,
+                is_error as i64,
+                duration_ms,
+                total_cost_usd,
+                num_turns,
+                chrono::Utc::now().to_rfc3339(),
+                run_id,
+                session_id,
+            ],
+        );
+        let _ = conn.execute(
+            "UPDATE fanout_runs SET status = 'completed'
+             WHERE id = ?1 AND NOT EXISTS (
+                SELECT 1 FROM fanout_responses WHERE fanout_id = ?1 AND status = 'pending'
+             )",
+            params![run_id],
+        );
+    }
+
+    if let Ok(run) = get_fanout(run_id) {
+        if let Some(app) = crate::state::get().app_handle.lock().as_ref() {
+            let _ = app.emit("fanout-updated", &run);
+        }
+        crate::broadcast_session_event(crate::events::SessionEvent::FanoutUpdated(run));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{fake_json_session, session_row};
+    use std::time::{Duration, Instant};
+
+    fn result(text: &str) -> crate::ClaudeJsonMessage {
+        serde_json::from_value(serde_json::json!({
+            "type": "result",
+            "result": text,
+            "is_error": false,
+            "duration_ms": 1200.0,
+            "total_cost_usd": 0.02,
+            "num_turns": 1,
+        }))
+        .unwrap()
+    }
+
+    fn response<'a>(run: &'a FanoutRun, session_id: &str) -> &'a FanoutResponse {
+        run.responses.iter().find(|r| r.session_id == session_id).unwrap()
+    }
+
+    #[test]
+    fn responses_are_collected_into_the_run() {
+        let b = session_row("Fan-out B");
+        let a = session_row("Fan-out A");
+        let run_id = create_run(&[b.clone(), a.clone()], "Compare").unwrap();
+
+        let run = get_fanout(&run_id).unwrap();
+        assert_eq!((run.prompt.as_str(), run.status.as_str()), ("Compare", "running"));
+        // By session name
+        assert_eq!(run.responses.iter().map(|r| r.session_id.as_str()).collect::<Vec<_>>(), [a.as_str(), b.as_str()]);
+        assert!(run.responses.iter().all(|r| r.status == "pending"));
+
+        record_response(&run_id, &a, "completed", Some("Plan A".to_string()), false, Some(1200.0), Some(0.02), Some(3));
+        let run = get_fanout(&run_id).unwrap();
+        assert_eq!(run.status, "running");
+        let answered = response(&run, &a);
+        assert_eq!(answered.result.as_deref(), Some("Plan A"));
+        assert_eq!((answered.duration_ms, answered.total_cost_usd, answered.num_turns), (Some(1200.0), Some(0.02), Some(3)));
+        assert_eq!(answered.session_name.as_deref(), Some("Fan-out A"));
+        assert!(answered.completed_at.is_some());
+
+        // Failures count as in
+        record_response(&run_id, &b, "failed", Some("crashed".to_string()), true, None, None, None);
+        let run = get_fanout(&run_id).unwrap();
+        assert_eq!(run.status, "completed");
+        assert!(response(&run, &b).is_error);
+    }
+
+    #[test]
+    fn busy_sessions_are_refused_and_each_answers_its_own_turn() {
+        let a = session_row("Fan-out busy A");
+        let b = session_row("Fan-out busy B");
+        let mut written_a = fake_json_session(&a);
+        let mut written_b = fake_json_session(&b);
+
+        // Mid-turn: its result would be for something else
+        crate::state::get().processing_sessions.lock().insert(a.clone());
+        let refused = fanout_prompt(vec![a.clone(), b.clone()], "Which is faster?".to_string()).unwrap_err();
+        assert!(refused.contains("busy"), "{}", refused);
+        assert!(!crate::state::get().fanout_sending.lock().contains(&b));
+        crate::state::get().processing_sessions.lock().remove(&a);
+
+        let run = fanout_prompt(vec![a.clone(), b.clone()], "Which is faster?".to_string()).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while ![&a, &b].iter().all(|id| crate::state::get().fanout_pending.lock().contains_key(*id)) {
+            assert!(Instant::now() < deadline, "Prompts weren't sent");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(written_a.try_recv().unwrap().contains("Which is faster?"));
+        assert!(written_b.try_recv().unwrap().contains("Which is faster?"));
+
+        // Already answering this one
+        assert!(fanout_prompt(vec![a.clone(), b.clone()], "Again".to_string()).unwrap_err().contains("busy"));
+
+        on_turn_complete(&a, &result("A is"));
+        forget(&b);
+        let run = get_fanout(&run.id).unwrap();
+        assert_eq!(run.status, "completed");
+        assert_eq!(response(&run, &a).result.as_deref(), Some("A is"));
+        assert_eq!(response(&run, &b).status, "failed");
+        // Later turns aren't taken for responses
+        assert!(!crate::state::get().fanout_pending.lock().contains_key(&a));
+
+        for id in [&a, &b] {
+            crate::state::get().json_processes.lock().remove(id);
+            crate::state::get().json_broadcasters.lock().remove(id);
+        }
+    }
+}
@@ -0,0 +1,197 @@
+// System-wide shortcuts (tauri-plugin-global-shortcut), configured in
+// settings as `global_shortcuts`: action -> accelerator, e.g.
+// { "toggle_window": "CmdOrCtrl+Shift+Space" }. Actions:
+//
+//   new_session_here   new session in the folder of the frontmost Finder or
+//                      VS Code window (default directory otherwise)
+//   toggle_window      show / hide the main window
+//   interrupt_active   interrupt the session open in the window, or the only
+//                      one working when that isn't
+//
+// They work while the app is in the background, so everything is handled
+// here rather than in the webview. register() replaces the whole set; it's
+// called at startup and by the register_hotkeys command when settings change.
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use tauri::{AppHandle, Emitter, Manager};
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut, ShortcutState};
+
+pub const ACTIONS: &[&str] = &["new_session_here", "toggle_window", "interrupt_active"];
+
+/// Session open in the main window (set_active_session)
+static ACTIVE_SESSION: Lazy<Mutex<Option<String>>> = Lazy::new(|| Mutex::new(None));
+
+pub fn set_active_session(session_id: Option<String>) {
+    *ACTIVE_SESSION.lock() = session_id;
+}
+
+/// Register `shortcuts`, replacing any registered before. Every valid one is
+/// registered; the error lists the ones that weren't.
+pub fn register(app: &AppHandle, shortcuts: &HashMap<String, String>) -> Result<(), String> {
+    let manager = app.global_shortcut();
+    manager.unregister_all().map_err(|e| e.to_string())?;
+    let mut errors = Vec::new();
+    for (action, accelerator) in shortcuts {
+        let accelerator = accelerator.trim();
+        if accelerator.is_empty() {
+            continue;
+        }
+        let Some(&action) = ACTIONS.iter().find(|a| **a == action.as_str()) else {
+            errors.push(format!("unknown action '{}'", action));
+            continue;
+        };
+        let registered = accelerator
+            .parse::<Shortcut>()
+            .map_err(|e| e.to_string())
+            .and_then(|shortcut| {
+                manager
+                    .on_shortcut(shortcut, move |app, _, event| {
+                        if event.state() == ShortcutState::Pressed {
+                            run(app, action);
+                        }
+                    })
+                    .map_err(|e| e.to_string())
+            });
+        if let Err(e) = registered {
+            errors.push(format!("{} ({}): {}", action, accelerator, e));
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("Some shortcuts weren't registered: {}", errors.join("; ")))
+    }
+}
+
+fn run(app: &AppHandle, action: &str) {
+    let result = match action {
+        "toggle_window" => {
+            toggle_window(app);
+            Ok(())
+        }
+        "interrupt_active" => interrupt_active(),
+        "new_session_here" => new_session_here(app),
+        _ => Ok(()),
+    };
+    if let Err(e) = result {
+        tracing::warn!("{}: {}", action, e);
+    }
+}
+
+fn show_window(app: &AppHandle) {
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.unminimize();
+        let _ = window.show();
+        let _ = window.set_focus();
+    }
+}
+
+fn toggle_window(app: &AppHandle) {
+    let Some(window) = app.get_webview_window("main") else { return };
+    let visible = window.is_visible().unwrap_or(false) && !window.is_minimized().unwrap_or(false);
+    if visible && window.is_focused().unwrap_or(false) {
+        let _ = window.hide();
+    } else {
+        show_window(app);
+    }
+}
+
+fn interrupt_active() -> Result<(), String> {
+    let active = ACTIVE_SESSION.lock().clone().filter(|id| crate::is_session_running(id));
+    let target = match active {
+        Some(id) => id,
+        None => {
+            let working: Vec<String> = crate::state::get().processing_sessions.lock().iter().cloned().collect();
+            match working.as_slice() {
+                [only] => only.clone(),
+                [] => return Err("No session to interrupt".to_string()),
+                _ => return Err("Several sessions are working and none is open".to_string()),
+            }
+        }
+    };
+    crate::interrupt_session(&target)
+}
+
+fn new_session_here(app: &AppHandle) -> Result<(), String> {
+    let settings = crate::load_app_settings().unwrap_or_default();
+    let dir = frontmost_folder().unwrap_or(settings.default_working_dir);
+    let session = crate::create_session_from_request(&serde_json::json!({
+        "agent_type": settings.default_agent_type,
+        "working_dir": dir,
+    }))?;
+    crate::broadcast_session_created(&session);
+    let id = session.id.clone();
+    crate::start_session(session)?;
+    show_window(app);
+    let _ = app.emit("open-session", id);
+    Ok(())
+}
+
+// =====================================================================
+//  Frontmost folder
+// =====================================================================
+
+/// Folder of the frontmost Finder window, or of the frontmost VS Code
+/// window when it has one open
+#[cfg(target_os = "macos")]
+fn frontmost_folder() -> Option<String> {
+    let app = osascript(
+        "tell application \"System Events\" to get name of first application process whose frontmost is true",
+    )?;
+    match app.as_str() {
+        "Finder" => osascript("tell application \"Finder\" to get POSIX path of (target of front window as alias)")
+            .map(|p| p.trim_end_matches('/').to_string()),
+        "Code" | "Code - Insiders" | "Cursor" => {
+            let title = osascript(&format!(
+                "tell application \"System Events\" to get name of front window of process \"{}\"",
+                app
+            ))?;
+            let data_dir = dirs::data_dir()?.join(if app == "Code" { "Code" } else { app.as_str() });
+            let workspaces = std::fs::read_to_string(data_dir.join("Backups").join("workspaces.json")).ok()?;
+            vscode_folder(&title, &workspaces)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+fn frontmost_folder() -> Option<String> {
+    None
+}
+
+#[cfg(target_os = "macos")]
+fn osascript(script: &str) -> Option<String> {
+    let output = std::process::Command::new("osascript").args(["-e", script]).output().ok()?;
+    let text = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !text.is_empty()).then_some(text)
+}
+
+/// The open folder a VS Code window title ("file — folder", or just
+/// "folder") refers to. Open windows' folders are listed in the editor's
+/// Backups/workspaces.json.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn vscode_folder(title: &str, workspaces_json: &str) -> Option<String> {
+    let name = title.rsplit(" — ").next()?.trim();
+    let workspaces: serde_json::Value = serde_json::from_str(workspaces_json).ok()?;
+    workspaces["folders"]
+        .as_array()?
+        .iter()
+        .filter_map(|f| f["folderUri"].as_str()?.strip_prefix("file://"))
+        .map(|path| path.replace("%20", " "))
+        .find(|path| std::path::Path::new(path).file_name().is_some_and(|f| f == name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vscode_titles_map_to_open_folders() {
+        let workspaces = r#"{"folders":[{"folderUri":"file:///Users/me/dev/api"},{"folderUri":"file:///Users/me/dev/my%20app"}]}"#;
+        assert_eq!(vscode_folder("main.rs — api", workspaces).as_deref(), Some("/Users/me/dev/api"));
+        assert_eq!(vscode_folder("my app", workspaces).as_deref(), Some("/Users/me/dev/my app"));
+        assert_eq!(vscode_folder("notes.md — elsewhere", workspaces), None);
+    }
+}
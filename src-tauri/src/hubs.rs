@@ -0,0 +1,464 @@
+// Remote hubs: other Agent Hub instances whose sessions are listed here too.
+//
+// A hub is registered with its base URL and a token from a device paired on
+// it. Its /api/sessions are merged into this hub's list, each session tagged
+// with `hub_id` and `host`, and the web server relays /api/hubs/<id>/api/...
+// and the mobile WebSocket at /api/hubs/<id>/ws/mobile to it, so one phone
+// can follow agents on several machines. The iOS build, which can't run
+// sessions itself, mostly shows other hubs' sessions this way.
+//
+// Tokens live in the secret store (HUB_TOKEN_PREFIX + hub id). What a client
+// may do through a hub is limited both by that token's scope on the hub and
+// by the client's own scope here. HTTPS hubs need a certificate the OS
+// trusts; for a self-signed one that means trusting it (GET /api/cert) first.
+
+use axum::extract::ws::{Message, WebSocket};
+use futures::{SinkExt, StreamExt};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_rustls::rustls;
+
+/// How long a hub gets to answer before it counts as unreachable
+const TIMEOUT: Duration = Duration::from_secs(8);
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct RemoteHub {
+    pub id: String,
+    pub name: String,
+    /// Base URL, e.g. https://studio.local:3847
+    pub url: String,
+    pub created_at: i64,
+}
+
+// =====================================================================
+//  Schema
+// =====================================================================
+
+pub fn run_hubs_migrations(conn: &Connection) {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS remote_hubs (
+            id         TEXT PRIMARY KEY,
+            name       TEXT NOT NULL,
+            url        TEXT NOT NULL UNIQUE,
+            created_at INTEGER NOT NULL
+        )",
+        [],
+    )
+    .expect("Failed to create remote_hubs table");
+}
+
+// =====================================================================
+//  Registry
+// =====================================================================
+
+/// A hub's base URL without trailing slashes; only http(s) with a host.
+fn normalize_url(url: &str) -> Result<String, String> {
+    let url = url.trim().trim_end_matches('/');
+    let parsed = reqwest::Url::parse(url).map_err(|_| format!("'{}' is not a URL", url))?;
+    if !matches!(parsed.scheme(), "http" | "https") || parsed.host_str().is_none() {
+        return Err("A hub URL must start with http:// or https://".to_string());
+    }
+    if parsed.query().is_some() || parsed.fragment().is_some() {
+        return Err("A hub URL can't have a query or fragment".to_string());
+    }
+    Ok(url.to_string())
+}
+
+fn token_key(hub_id: &str) -> String {
+    format!("{}{}", crate::secrets::HUB_TOKEN_PREFIX, hub_id)
+}
+
+fn from_row(row: &rusqlite::Row) -> rusqlite::Result<RemoteHub> {
+    Ok(RemoteHub { id: row.get(0)?, name: row.get(1)?, url: row.get(2)?, created_at: row.get(3)? })
+}
+
+fn list_in(conn: &Connection) -> Result<Vec<RemoteHub>, String> {
+    let mut stmt = conn
+        .prepare("SELECT id, name, url, created_at FROM remote_hubs ORDER BY name")
+        .map_err(|e| e.to_string())?;
+    let rows = stmt.query_map([], from_row).map_err(|e| e.to_string())?;
+    rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+}
+
+fn get_in(conn: &Connection, id: &str) -> Result<Option<RemoteHub>, String> {
+    conn.query_row("SELECT id, name, url, created_at FROM remote_hubs WHERE id = ?1", params![id], from_row)
+        .optional()
+        .map_err(|e| e.to_string())
+}
+
+fn insert_in(conn: &Connection, hub: &RemoteHub) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO remote_hubs (id, name, url, created_at) VALUES (?1, ?2, ?3, ?4)",
+        params![hub.id, hub.name, hub.url, hub.created_at],
+    )
+    .map_err(|e| match e {
+        rusqlite::Error::SqliteFailure(f, _) if f.code == rusqlite::ErrorCode::ConstraintViolation => {
+            format!("{} is already registered", hub.url)
+        }
+        e => e.to_string(),
+    })?;
+    Ok(())
+}
+
+fn remove_in(conn: &Connection, id: &str) -> Result<bool, String> {
+    conn.execute("DELETE FROM remote_hubs WHERE id = ?1", params![id])
+        .map(|n| n > 0)
+        .map_err(|e| e.to_string())
+}
+
+pub fn list() -> Result<Vec<RemoteHub>, String> {
+    list_in(&crate::state::get().db().lock())
+}
+
+pub fn get(id: &str) -> Option<RemoteHub> {
+    get_in(&crate::state::get().db().lock(), id).ok().flatten()
+}
+
+/// Register a hub once its token has been checked against it.
+pub async fn add(name: &str, url: &str, token: &str) -> Result<RemoteHub, String> {
+    let name = name.trim();
+    let token = token.trim();
+    if name.is_empty() {
+        return Err("A hub needs a name".to_string());
+    }
+    if token.is_empty() {
+        return Err("A token from a device paired with the hub is required".to_string());
+    }
+    let url = normalize_url(url)?;
+    fetch_sessions(&url, token).await.map_err(|e| format!("Can't use {}: {}", url, e))?;
+
+    let hub = RemoteHub {
+        id: uuid::Uuid::new_v4().to_string(),
+        name: name.to_string(),
+        url,
+        created_at: chrono::Utc::now().timestamp(),
+    };
+    crate::secrets::set(&token_key(&hub.id), token)?;
+    if let Err(e) = insert_in(&crate::state::get().db().lock(), &hub) {
+        let _ = crate::secrets::delete(&token_key(&hub.id));
+        return Err(e);
+    }
+    Ok(hub)
+}
+
+pub fn remove(id: &str) -> Result<(), String> {
+    if !remove_in(&crate::state::get().db().lock(), id)? {
+        return Err("Hub not found".to_string());
+    }
+    crate::secrets::delete(&token_key(id))
+}
+
+fn token(hub: &RemoteHub) -> Result<String, String> {
+    crate::secrets::get(&token_key(&hub.id)).ok_or_else(|| format!("No token stored for {}", hub.name))
+}
+
+// =====================================================================
+//  Sessions
+// =====================================================================
+
+fn client() -> reqwest::Client {
+    reqwest::Client::builder().timeout(TIMEOUT).build().unwrap_or_default()
+}
+
+async fn fetch_sessions(url: &str, token: &str) -> Result<Vec<serde_json::Value>, String> {
+    let response = client()
+        .get(format!("{}/api/sessions", url))
+        .bearer_auth(token)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    match response.status().as_u16() {
+        200..=299 => response.json().await.map_err(|e| e.to_string()),
+        401 | 403 => Err("the hub rejected the token".to_string()),
+        status => Err(format!("the hub answered HTTP {}", status)),
+    }
+}
+
+/// Mark sessions as belonging to `hub` (None: this one).
+fn tag(sessions: Vec<serde_json::Value>, hub: Option<&RemoteHub>) -> Vec<serde_json::Value> {
+    sessions
+        .into_iter()
+        .map(|mut session| {
+            if let Some(fields) = session.as_object_mut() {
+                fields.insert("hub_id".to_string(), hub.map(|h| h.id.clone()).into());
+                fields.insert("host".to_string(), hub.map(|h| h.name.clone()).into());
+            }
+            session
+        })
+        .collect()
+}
+
+/// `local` followed by every hub's sessions, and how each hub answered. A
+/// hub that can't be reached is reported rather than failing the list.
+pub async fn merged_sessions(local: Vec<serde_json::Value>) -> serde_json::Value {
+    let hubs = list().unwrap_or_default();
+    let answers = futures::future::join_all(hubs.iter().map(|hub| async move {
+        fetch_sessions(&hub.url, &token(hub)?).await
+    }))
+    .await;
+
+    let mut sessions = tag(local, None);
+    let mut statuses = Vec::new();
+    for (hub, answer) in hubs.iter().zip(answers) {
+        let error = match answer {
+            Ok(remote) => {
+                sessions.extend(tag(remote, Some(hub)));
+                None
+            }
+            Err(e) => Some(e),
+        };
+        statuses.push(serde_json::json!({
+            "id": hub.id,
+            "name": hub.name,
+            "url": hub.url,
+            "reachable": error.is_none(),
+            "error": error,
+        }));
+    }
+    serde_json::json!({ "sessions": sessions, "hubs": statuses })
+}
+
+// =====================================================================
+//  Relaying
+// =====================================================================
+
+/// Pass an API request on to a hub. `path` is below /api and may carry a
+/// query string; returns the hub's status, content type and body.
+pub async fn forward(
+    hub: &RemoteHub,
+    method: reqwest::Method,
+    path: &str,
+    content_type: Option<&str>,
+    body: Vec<u8>,
+) -> Result<(u16, Option<String>, Vec<u8>), String> {
+    let mut request = client()
+        .request(method, format!("{}/api/{}", hub.url, path.trim_start_matches('/')))
+        .bearer_auth(token(hub)?)
+        .body(body);
+    if let Some(content_type) = content_type {
+        request = request.header(reqwest::header::CONTENT_TYPE, content_type);
+    }
+    let response = request.send().await.map_err(|e| format!("Can't reach {}: {}", hub.name, e))?;
+    let status = response.status().as_u16();
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    let body = response.bytes().await.map_err(|e| e.to_string())?;
+    Ok((status, content_type, body.to_vec()))
+}
+
+/// Mobile WebSocket messages that change something on the hub, and so need
+/// interact access here as well as there.
+fn needs_interact(msg_type: &str) -> bool {
+    matches!(
+        msg_type,
+        "send_message"
+            | "interrupt"
+            | "terminal_input"
+            | "terminal_resize"
+            | "create_session"
+            | "rename_session"
+            | "move_session_to_folder"
+            | "delete_session"
+            | "stop_session"
+    )
+}
+
+pub trait Io: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send {}
+impl<T: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send> Io for T {}
+
+pub type HubSocket = tokio_tungstenite::WebSocketStream<Box<dyn Io>>;
+
+async fn connect(hub: &RemoteHub) -> Result<HubSocket, String> {
+    let url = reqwest::Url::parse(&hub.url).map_err(|e| e.to_string())?;
+    let host = url.host_str().ok_or("no host")?.to_string();
+    let port = url.port_or_known_default().ok_or("no port")?;
+    let tcp = tokio::time::timeout(TIMEOUT, tokio::net::TcpStream::connect((host.as_str(), port)))
+        .await
+        .map_err(|_| "timed out".to_string())?
+        .map_err(|e| e.to_string())?;
+    let stream: Box<dyn Io> = if url.scheme() == "https" {
+        use rustls_platform_verifier::BuilderVerifierExt;
+        let config = rustls::ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(|e| e.to_string())?
+            .with_platform_verifier()
+            .map_err(|e| e.to_string())?
+            .with_no_client_auth();
+        let name = rustls::pki_types::ServerName::try_from(host).map_err(|e| e.to_string())?;
+        let tls = tokio_rustls::TlsConnector::from(Arc::new(config))
+            .connect(name, tcp)
+            .await
+            .map_err(|e| e.to_string())?;
+        Box::new(tls)
+    } else {
+        Box::new(tcp)
+    };
+    let ws_url = format!("{}/api/ws/mobile", hub.url.replacen("http", "ws", 1));
+    let (socket, _) = tokio_tungstenite::client_async(ws_url, stream).await.map_err(|e| e.to_string())?;
+    Ok(socket)
+}
+
+/// The hub's mobile WebSocket, signed in with its token as a client of this
+/// app's protocol version (see remote_sessions.rs).
+pub async fn connect_as_client(hub: &RemoteHub) -> Result<HubSocket, String> {
+    use tokio_tungstenite::tungstenite::Message as HubMessage;
+
+    let token = token(hub)?;
+    let mut socket = connect(hub).await.map_err(|e| format!("Can't reach {}: {}", hub.name, e))?;
+    let auth = serde_json::json!({ "type": "auth", "token": token, "protocolVersion": crate::PROTOCOL_VERSION });
+    socket.send(HubMessage::Text(auth.to_string())).await.map_err(|e| e.to_string())?;
+    loop {
+        let reply = tokio::time::timeout(TIMEOUT, socket.next())
+            .await
+            .map_err(|_| format!("{} didn't answer", hub.name))?;
+        let Some(Ok(HubMessage::Text(text))) = reply else {
+            return Err(format!("{} closed the connection", hub.name));
+        };
+        let reply: serde_json::Value = serde_json::from_str(&text).unwrap_or_default();
+        match reply["type"].as_str() {
+            Some("auth_success") => return Ok(socket),
+            Some("auth_error") => return Err(format!("{} rejected the token", hub.name)),
+            _ => {}
+        }
+    }
+}
+
+/// Relay a mobile WebSocket to the hub's. The client's `auth` message is
+/// checked here with `authorize` (its token to its scope) and sent on with
+/// the hub's token instead; after that frames pass through both ways, except
+/// changes the client's scope here doesn't allow.
+pub async fn relay_ws(
+    mut socket: WebSocket,
+    hub: RemoteHub,
+    authorize: impl FnOnce(&str) -> Option<crate::DeviceScope>,
+) {
+    use tokio_tungstenite::tungstenite::Message as HubMessage;
+
+    let error = |message: String| Message::Text(serde_json::json!({ "type": "error", "message": message }).to_string());
+
+    let Some(Ok(Message::Text(first))) = socket.recv().await else {
+        return;
+    };
+    let mut auth: serde_json::Value = serde_json::from_str(&first).unwrap_or_default();
+    if auth["type"] != "auth" {
+        let _ = socket.send(error("Not authenticated".to_string())).await;
+        return;
+    }
+    let Some(scope) = authorize(auth["token"].as_str().unwrap_or_default()) else {
+        let _ = socket
+            .send(Message::Text(serde_json::json!({ "type": "auth_error", "message": "Invalid token" }).to_string()))
+            .await;
+        return;
+    };
+    let upstream = match token(&hub) {
+        Ok(token) => connect(&hub).await.map(|socket| (socket, token)),
+        Err(e) => Err(e),
+    };
+    let (mut upstream, hub_token) = match upstream {
+        Ok(connected) => connected,
+        Err(e) => {
+            let _ = socket.send(error(format!("Can't reach {}: {}", hub.name, e))).await;
+            let _ = socket.send(Message::Close(None)).await;
+            return;
+        }
+    };
+    auth["token"] = hub_token.into();
+    if upstream.send(HubMessage::Text(auth.to_string())).await.is_err() {
+        return;
+    }
+
+    loop {
+        tokio::select! {
+            from_client = socket.recv() => {
+                let forward = match from_client {
+                    Some(Ok(Message::Text(text))) => {
+                        let msg_type = serde_json::from_str::<serde_json::Value>(&text)
+                            .ok()
+                            .and_then(|v| v["type"].as_str().map(str::to_string))
+                            .unwrap_or_default();
+                        if needs_interact(&msg_type) && scope < crate::DeviceScope::Interact {
+                            let _ = socket.send(error("This device needs interact access".to_string())).await;
+                            continue;
+                        }
+                        HubMessage::Text(text)
+                    }
+                    Some(Ok(Message::Binary(data))) => HubMessage::Binary(data),
+                    Some(Ok(Message::Ping(_) | Message::Pong(_))) => continue,
+                    _ => break,
+                };
+                if upstream.send(forward).await.is_err() {
+                    break;
+                }
+            }
+            from_hub = upstream.next() => {
+                let forward = match from_hub {
+                    Some(Ok(HubMessage::Text(text))) => Message::Text(text),
+                    // Gzipped or CBOR frames, as negotiated between the client and the hub
+                    Some(Ok(HubMessage::Binary(data))) => Message::Binary(data),
+                    Some(Ok(HubMessage::Ping(_) | HubMessage::Pong(_) | HubMessage::Frame(_))) => continue,
+                    _ => break,
+                };
+                if socket.send(forward).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+    let _ = socket.send(Message::Close(None)).await;
+    let _ = upstream.close(None).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        run_hubs_migrations(&conn);
+        conn
+    }
+
+    fn hub(id: &str, name: &str, url: &str) -> RemoteHub {
+        RemoteHub { id: id.to_string(), name: name.to_string(), url: url.to_string(), created_at: 0 }
+    }
+
+    #[test]
+    fn urls_are_normalized_and_checked() {
+        assert_eq!(normalize_url(" https://studio.local:3847/ ").unwrap(), "https://studio.local:3847");
+        assert_eq!(normalize_url("http://10.0.0.7:3847").unwrap(), "http://10.0.0.7:3847");
+        assert!(normalize_url("studio.local:3847").is_err());
+        assert!(normalize_url("ftp://studio.local").is_err());
+        assert!(normalize_url("https://studio.local/?token=x").is_err());
+    }
+
+    #[test]
+    fn hubs_are_stored_once_per_url() {
+        let conn = setup();
+        insert_in(&conn, &hub("a", "studio", "https://studio.local:3847")).unwrap();
+        insert_in(&conn, &hub("b", "laptop", "http://laptop.local:3847")).unwrap();
+        assert!(insert_in(&conn, &hub("c", "again", "https://studio.local:3847")).unwrap_err().contains("already"));
+
+        let names: Vec<_> = list_in(&conn).unwrap().into_iter().map(|h| h.name).collect();
+        assert_eq!(names, vec!["laptop", "studio"]);
+        assert!(remove_in(&conn, "a").unwrap());
+        assert!(!remove_in(&conn, "a").unwrap());
+        assert_eq!(get_in(&conn, "a").unwrap(), None);
+        assert_eq!(get_in(&conn, "b").unwrap().unwrap().url, "http://laptop.local:3847");
+    }
+
+    #[test]
+    fn sessions_are_tagged_with_their_hub() {
+        let studio = hub("a", "studio", "https://studio.local:3847");
+        let tagged = tag(vec![serde_json::json!({ "id": "s1" })], Some(&studio));
+        assert_eq!(tagged[0]["hub_id"], "a");
+        assert_eq!(tagged[0]["host"], "studio");
+        let local = tag(vec![serde_json::json!({ "id": "s2" })], None);
+        assert!(local[0]["hub_id"].is_null() && local[0]["host"].is_null());
+        assert!(needs_interact("send_message") && !needs_interact("subscribe"));
+    }
+}
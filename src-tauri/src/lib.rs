@@ -12,9 +12,11 @@ use axum::{
 const APP_NAME: &str = "Agent Hub (Dev)";
 #[cfg(not(debug_assertions))]
 const APP_NAME: &str = "Agent Hub";
+#[cfg(not(target_os = "ios"))]
 use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
-use flate2::read::GzDecoder;
+#[cfg(not(target_os = "ios"))]
 use flate2::write::GzEncoder;
+#[cfg(not(target_os = "ios"))]
 use flate2::Compression;
 #[cfg(not(target_os = "ios"))]
 use futures::{SinkExt, StreamExt};
@@ -22,9 +24,10 @@ use once_cell::sync::Lazy;
 use parking_lot::Mutex;
 #[cfg(not(target_os = "ios"))]
 use portable_pty::{native_pty_system, CommandBuilder, PtyPair, PtySize};
-use rusqlite::{Connection, params};
+use rusqlite::params;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+#[cfg(not(target_os = "ios"))]
 use std::io::{Read, Write};
 use std::net::SocketAddr;
 use std::path::PathBuf;
@@ -46,128 +49,333 @@ mod mcp;
 // Full-content search index (wraps Claude's on-disk JSONL files).
 mod search;
 
-// Flag to track if MCP mode is enabled
+// Per-session prompt queue for claude-json sessions
 #[cfg(not(target_os = "ios"))]
-static MCP_MODE: Lazy<std::sync::atomic::AtomicBool> =
-    Lazy::new(|| std::sync::atomic::AtomicBool::new(false));
+mod queue;
 
+// Pipelines: feed one session's result into another session's prompt
 #[cfg(not(target_os = "ios"))]
-struct PtySession {
-    pair: PtyPair,
-    writer: Box<dyn Write + Send>,
-}
+mod pipeline;
 
+// Fan-out: send one prompt to several sessions and compare the responses
 #[cfg(not(target_os = "ios"))]
-static PTY_SESSIONS: Lazy<Mutex<HashMap<String, Arc<Mutex<PtySession>>>>> =
-    Lazy::new(|| Mutex::new(HashMap::new()));
+mod fanout;
 
-// Broadcast channels for PTY output - used by both Tauri and WebSocket clients
+// Saved prompt templates, offered to MCP clients as prompts
 #[cfg(not(target_os = "ios"))]
-static PTY_BROADCASTERS: Lazy<Mutex<HashMap<String, broadcast::Sender<Vec<u8>>>>> =
-    Lazy::new(|| Mutex::new(HashMap::new()));
+mod prompts;
 
-// JSON process sessions (non-PTY, for streaming JSON communication)
+// Plain-text view of PTY sessions' terminals for the MCP terminal tools
 #[cfg(not(target_os = "ios"))]
-struct JsonProcess {
-    stdin: tokio::sync::mpsc::Sender<String>,
-    #[allow(dead_code)]
-    child_id: u32,
-}
+mod term_screen;
 
+// File changes extracted from Edit/Write/MultiEdit tool calls
 #[cfg(not(target_os = "ios"))]
-static JSON_PROCESSES: Lazy<Mutex<HashMap<String, JsonProcess>>> =
-    Lazy::new(|| Mutex::new(HashMap::new()));
+mod changes;
 
-// Broadcast channels for JSON process output - used by WebSocket clients
+// Git snapshots of a session's working dir before each turn (turn-level undo)
 #[cfg(not(target_os = "ios"))]
-static JSON_BROADCASTERS: Lazy<Mutex<HashMap<String, broadcast::Sender<String>>>> =
-    Lazy::new(|| Mutex::new(HashMap::new()));
+mod snapshots;
 
-// Broadcast channel for session status changes (start/stop events)
-// All connected WebSocket clients receive these notifications
+// Classified claude-json failures (CLI missing, logged out, rate limited...)
 #[cfg(not(target_os = "ios"))]
-static STATUS_BROADCASTER: Lazy<broadcast::Sender<String>> =
-    Lazy::new(|| broadcast::channel::<String>(64).0);
+mod session_errors;
 
-// Global AppHandle for web server to use
-static APP_HANDLE: Lazy<Mutex<Option<AppHandle>>> = Lazy::new(|| Mutex::new(None));
+// Context window usage per claude-json session
+#[cfg(not(target_os = "ios"))]
+mod context;
 
-// History menu submenu for dynamic updates
+// Short transcript summaries for the session list, generated when idle
 #[cfg(not(target_os = "ios"))]
-static HISTORY_MENU: Lazy<Mutex<Option<Submenu<tauri::Wry>>>> = Lazy::new(|| Mutex::new(None));
+mod summaries;
+
+// Text-to-speech through the OS speech engine
+#[cfg(not(target_os = "ios"))]
+mod tts;
+
+// Speech-to-text for voice prompts via a configurable command
+#[cfg(not(target_os = "ios"))]
+mod transcribe;
 
-// Web server port - determined at runtime with failover
-static WEB_SERVER_PORT: Lazy<Mutex<Option<u16>>> = Lazy::new(|| Mutex::new(None));
+// Image uploads from remote clients, sent to sessions as image blocks
+#[cfg(not(target_os = "ios"))]
+mod attachments;
 
-// Shared database connection - initialized once, used everywhere
-static DB_CONNECTION: Lazy<Mutex<Connection>> = Lazy::new(|| {
-    let conn = Connection::open(get_db_path()).expect("Failed to open database");
-    conn.busy_timeout(std::time::Duration::from_secs(5)).expect("Failed to set busy timeout");
-    // Enable WAL mode for better concurrent read/write performance
-    conn.execute_batch("PRAGMA journal_mode=WAL;").expect("Failed to enable WAL mode");
-    Mutex::new(conn)
-});
+// Questions and approval requests from agents, answered from any client
+#[cfg(not(target_os = "ios"))]
+mod asks;
 
-// Authentication: Active pairing requests (pairing_id -> code)
-static PAIRING_REQUESTS: Lazy<Mutex<HashMap<String, PairingRequest>>> =
-    Lazy::new(|| Mutex::new(HashMap::new()));
+// Native notifications for results, errors and attention while unfocused
+#[cfg(not(target_os = "ios"))]
+mod desktop_notify;
 
-// Authentication: Paired devices (token -> device info)
-static PAIRED_DEVICES: Lazy<Mutex<HashMap<String, PairedDevice>>> =
-    Lazy::new(|| Mutex::new(HashMap::new()));
+// Masks secrets in agent output before it is stored or sent to clients
+#[cfg(not(target_os = "ios"))]
+mod redaction;
 
-// MCP HTTP: Pending requests waiting for JS execution results
-static MCP_HTTP_RESULTS: Lazy<Mutex<HashMap<String, Option<String>>>> =
-    Lazy::new(|| Mutex::new(HashMap::new()));
+// Headless one-shot claude runs for scripts (POST /api/run)
+#[cfg(not(target_os = "ios"))]
+mod runs;
 
-// PIN authentication: Rate limiting (IP -> (attempts, last_attempt_time))
-static PIN_RATE_LIMIT: Lazy<Mutex<HashMap<String, (u32, std::time::Instant)>>> =
-    Lazy::new(|| Mutex::new(HashMap::new()));
+// Detects claude-json sessions stuck mid-turn with no output
+#[cfg(not(target_os = "ios"))]
+mod watchdog;
 
-// Mobile WebSocket: Channel for sending messages to mobile clients
-// Each mobile client gets a sender that the server can use to push messages
+// Backoff restarts for claude-json processes that crash
 #[cfg(not(target_os = "ios"))]
-type MobileSender = tokio::sync::mpsc::UnboundedSender<String>;
+mod restarts;
+
+// Login shell environment and claude's path, captured once for JSON sessions
+#[cfg(not(target_os = "ios"))]
+mod agent_env;
+
+// Per-agent-type launch commands (built-in defaults + user overrides)
+mod agent_commands;
+
+// Prometheus counters and gauges for GET /metrics
+#[cfg(not(target_os = "ios"))]
+mod metrics;
+
+// Hand-written OpenAPI document for the web API
+mod openapi;
+
+// HTTPS for the web server (self-signed or user-provided certificate)
+#[cfg(not(target_os = "ios"))]
+mod tls;
+
+// mDNS advertisement and LAN discovery of other hubs (_agenthub._tcp)
+#[cfg(not(target_os = "ios"))]
+mod discovery;
+
+// Tailscale Funnel / SSH reverse tunnel for access from outside the LAN
+#[cfg(not(target_os = "ios"))]
+mod tunnel;
+
+// Remote PIN hashing and strength rules
+mod pin;
+
+// QR encoder for pairing by scanning
+#[cfg(not(target_os = "ios"))]
+mod qr;
+
+// Audit log of remote actions
+#[cfg(not(target_os = "ios"))]
+mod audit;
+
+// Keychain-backed storage for the PIN hash, token hashes and API keys
+mod secrets;
+
+// DB-backed rate limits for pairing, login and message sending
+mod ratelimit;
+
+// CIDR allowlist for web server clients
+mod allowlist;
+
+// The web API on a unix socket for local scripts, without tokens
+#[cfg(all(unix, not(target_os = "ios")))]
+mod local_socket;
+
+// Signed single-use links letting a guest watch one session
+#[cfg(not(target_os = "ios"))]
+mod share;
+
+// Sequence-numbered chat messages so mobile clients can catch up after a drop
+#[cfg(not(target_os = "ios"))]
+mod message_log;
+
+// Web push / ntfy / webhook notifications for devices not watching a session
+#[cfg(not(target_os = "ios"))]
+mod push;
+
+// Per-device choice of which notifications to get, and quiet hours
+mod notify_prefs;
+
+// Other Agent Hub instances whose sessions are listed and relayed here
+mod hubs;
+
+// Sessions on remote hubs opened in this app like local ones
+mod remote_sessions;
+
+// Workspaces above folders: default directory, env vars, budget, remote visibility
+mod workspaces;
+
+// Onboarding checks: agent CLIs installed and logged in, ~/.claude, the web server
 #[cfg(not(target_os = "ios"))]
-static MOBILE_CLIENTS: Lazy<Mutex<HashMap<String, MobileClient>>> =
-    Lazy::new(|| Mutex::new(HashMap::new()));
+mod diagnostics;
 
-// Backend-side message tracking: keeps all messages per session in memory
-// so they can be persisted to DB independently of the desktop frontend.
-// This ensures messages sent from mobile are never lost even if the desktop
-// doesn't have the session tab open.
+// tracing subscriber writing rotating log files, and reading them back
+mod logging;
+
+// Shell, PATH and data directory differences between macOS and Linux
+mod platform;
+
+// AppState: the shared runtime, session and client registries, and database
+mod state;
+
+// Terminal buffer storage: versioned, zstd-compressed BLOBs
+mod buffer_store;
+
+// Ordered shutdown and the journal that recovers chat messages after a crash
+#[cfg(not(target_os = "ios"))]
+mod shutdown;
+
+// Snapshots for WebSocket clients that fell behind a broadcast channel
+#[cfg(not(target_os = "ios"))]
+mod resync;
+
+// Typed status-stream events, serialized once per protocol where they leave
+#[cfg(not(target_os = "ios"))]
+mod events;
+
+// Which devices have each session open, broadcast as viewers come and go
+#[cfg(not(target_os = "ios"))]
+mod presence;
+
+// CBOR frames for chat and terminal traffic on the mobile WebSocket
+#[cfg(not(target_os = "ios"))]
+mod ws_cbor;
+
+// Content mobile chat subscriptions ask to be left out (tool results, ...)
+#[cfg(not(target_os = "ios"))]
+mod chat_filter;
+
+// Event type / session / folder filters for /api/ws/status
+#[cfg(not(target_os = "ios"))]
+mod status_filter;
+
+// System-wide shortcuts (new session here, show/hide, interrupt)
+#[cfg(not(target_os = "ios"))]
+mod hotkeys;
+
+// agenthub:// links (open a session, create one, pair a device)
+#[cfg(not(target_os = "ios"))]
+mod deep_link;
+
+// Slack / Discord messages when selected sessions finish a turn
+#[cfg(not(target_os = "ios"))]
+mod chat_notify;
+
+// Signed HTTP callbacks on session events
+#[cfg(not(target_os = "ios"))]
+mod webhooks;
+
+// Sessions waiting on the user (asks, signal_attention, hangs)
+#[cfg(not(target_os = "ios"))]
+mod attention;
+
+// Menu bar / system tray status item
+#[cfg(not(target_os = "ios"))]
+mod tray;
+
+// Dock badge, taskbar progress and attention bounce
+#[cfg(not(target_os = "ios"))]
+mod dock;
+
+// Menu item keyboard shortcuts (defaults and user overrides)
 #[cfg(not(target_os = "ios"))]
-static SESSION_MESSAGES: Lazy<Mutex<HashMap<String, Vec<serde_json::Value>>>> =
-    Lazy::new(|| Mutex::new(HashMap::new()));
+mod shortcuts;
 
-// Track which sessions have been loaded from DB into SESSION_MESSAGES
+// Named settings profiles (work / home)
 #[cfg(not(target_os = "ios"))]
-static SESSION_MESSAGES_LOADED: Lazy<Mutex<std::collections::HashSet<String>>> =
-    Lazy::new(|| Mutex::new(std::collections::HashSet::new()));
+mod profiles;
+
+// The web server in-process, for tests of the API and WebSocket protocol
+#[cfg(all(test, not(target_os = "ios")))]
+mod test_server;
+
+// Flag to track if MCP mode is enabled
+#[cfg(not(target_os = "ios"))]
+static MCP_MODE: Lazy<std::sync::atomic::AtomicBool> =
+    Lazy::new(|| std::sync::atomic::AtomicBool::new(false));
+
+#[cfg(not(target_os = "ios"))]
+struct PtySession {
+    pair: PtyPair,
+    writer: Box<dyn Write + Send>,
+    /// Leader of the child's process group (the PTY child runs setsid)
+    child_pid: Option<u32>,
+}
+
+// JSON process sessions (non-PTY, for streaming JSON communication)
+#[cfg(not(target_os = "ios"))]
+struct JsonProcess {
+    stdin: tokio::sync::mpsc::Sender<String>,
+    child_id: u32,
+}
+
+// History menu submenu for dynamic updates
+#[cfg(not(target_os = "ios"))]
+static HISTORY_MENU: Lazy<Mutex<Option<Submenu<tauri::Wry>>>> = Lazy::new(|| Mutex::new(None));
+
+// Window menu submenu, whose session items are updated dynamically
+#[cfg(not(target_os = "ios"))]
+static WINDOW_MENU: Lazy<Mutex<Option<Submenu<tauri::Wry>>>> = Lazy::new(|| Mutex::new(None));
+
+// Sessions last listed in the Window menu, to restore when the menu is rebuilt
+#[cfg(not(target_os = "ios"))]
+static SESSIONS_MENU_ENTRIES: Lazy<Mutex<Vec<SessionMenuEntry>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+// When the web server started, for /api/health uptime
+static SERVER_STARTED_AT: Lazy<std::time::Instant> = Lazy::new(std::time::Instant::now);
+
+/// Version of the HTTP/WebSocket API. Bump on incompatible changes, and when
+/// adding WebSocket messages, so remote clients can tell an outdated desktop
+/// from a broken one (GET /api/version).
+///   2: session management messages on /api/ws/mobile
+///   3: terminal (PTY) streaming on /api/ws/mobile
+///   4: `seq` on chat messages and `subscribe {since_seq}` catch-up
+///   5: `ack` for chat messages; unacked ones are resent on reconnect
+///   6: large messages sent gzipped as binary frames to clients that ask
+///   7: `agent_typing` and `agent_delta` (partial reply text)
+///   8: `presence`, who has each session open
+///   9: chat and terminal messages as CBOR binary frames to clients that ask
+///  10: session list kept current by session_* / folder_* events alone
+///  11: `filters` on subscribe, e.g. leaving out tool results
+///  12: `get_notification_prefs` / `set_notification_prefs`
+///  13: `session_ask` / `session_ask_settled` and `answer_question`
+const PROTOCOL_VERSION: u32 = 13;
+
+// Mobile WebSocket: Channel for sending messages to mobile clients
+// Each mobile client gets a sender that the server can use to push messages
+#[cfg(not(target_os = "ios"))]
+type MobileSender = tokio::sync::mpsc::UnboundedSender<String>;
 
 #[cfg(not(target_os = "ios"))]
 struct MobileClient {
     sender: MobileSender,
-    subscribed_sessions: std::collections::HashSet<String>,
+    /// Chat subscriptions, with what each leaves out of the messages
+    subscribed_sessions: HashMap<String, chat_filter::ChatFilter>,
+    /// Paired device the connection authenticated as
+    device_id: Option<String>,
+    /// The client acks chat messages (protocol 5), so deliveries to it are
+    /// tracked and resent until acked (see message_log)
+    acks: bool,
+    /// The client keeps its session list current from session_* / folder_*
+    /// events alone (protocol 10), so it's only sent the full list at auth
+    list_deltas: bool,
+    /// Closes the connection with the given reason (see disconnect_device)
+    revoke: Option<tokio::sync::oneshot::Sender<&'static str>>,
 }
 
 /// Broadcast a session event to all connected WebSocket clients
 #[cfg(not(target_os = "ios"))]
-fn broadcast_session_event(event_type: &str, data: serde_json::Value) {
-    let msg = serde_json::json!({
-        "type": event_type,
-        "data": data
-    }).to_string();
-    let _ = STATUS_BROADCASTER.send(msg);
+fn broadcast_session_event(event: events::SessionEvent) {
+    if event.session_id().is_some_and(is_remote_hidden) {
+        return;
+    }
+    let _ = state::get().status_broadcaster.send(Arc::new(events::Broadcast::new(event)));
 }
 
 /// Broadcast a session status change (started/stopped)
 #[cfg(not(target_os = "ios"))]
 fn broadcast_session_status(session_id: &str, running: bool) {
-    broadcast_session_event("session_status", serde_json::json!({
-        "session_id": session_id,
-        "running": running
-    }));
+    broadcast_session_event(events::SessionEvent::SessionStatus {
+        session_id: session_id.to_string(),
+        running,
+    });
+    mcp::notify_session_event(session_id, if running { "started" } else { "stopped" }, serde_json::json!({}));
+    webhooks::dispatch(if running { "session_started" } else { "session_stopped" }, session_id, serde_json::json!({}));
+    tray::refresh();
 
     // Broadcast to ALL mobile clients so the session list status updates too
     let msg = serde_json::json!({
@@ -180,20 +388,57 @@ fn broadcast_session_status(session_id: &str, running: bool) {
     broadcast_to_mobile_clients(&msg);
 }
 
+/// Whether the session's agent is working on a turn
+#[cfg(not(target_os = "ios"))]
+fn is_session_processing(session_id: &str) -> bool {
+    state::get().processing_sessions.lock().contains(session_id)
+}
+
+/// Record a session's processing state; the DB is only written on a change
+#[cfg(not(target_os = "ios"))]
+fn set_session_processing(session_id: &str, processing: bool) {
+    let changed = {
+        let mut sessions = state::get().processing_sessions.lock();
+        if processing {
+            sessions.insert(session_id.to_string())
+        } else {
+            sessions.remove(session_id)
+        }
+    };
+    if changed {
+        let _ = state::get().db().lock().execute(
+            "UPDATE sessions SET processing = ?1 WHERE id = ?2",
+            params![processing, session_id],
+        );
+        // Lets an open chat show a typing indicator
+        broadcast_to_session_subscribers(session_id, &serde_json::json!({
+            "type": "agent_typing",
+            "sessionId": session_id,
+            "typing": processing,
+        }).to_string());
+        tray::refresh();
+        dock::refresh();
+    }
+}
+
 /// Broadcast processing state change (thinking started/stopped)
 #[cfg(not(target_os = "ios"))]
 fn broadcast_processing_status(session_id: &str, processing: bool) {
-    broadcast_session_event("processing_status", serde_json::json!({
-        "session_id": session_id,
-        "processing": processing
-    }));
+    set_session_processing(session_id, processing);
+    let context_used_pct = context::used_pct(session_id);
+    broadcast_session_event(events::SessionEvent::ProcessingStatus {
+        session_id: session_id.to_string(),
+        processing,
+        context_used_pct,
+    });
 
     // Broadcast to ALL mobile clients so the session list status updates too
     let msg = serde_json::json!({
         "type": "session_status",
         "sessionId": session_id,
         "status": {
-            "isProcessing": processing
+            "isProcessing": processing,
+            "contextUsedPct": context_used_pct
         }
     }).to_string();
     broadcast_to_mobile_clients(&msg);
@@ -202,7 +447,8 @@ fn broadcast_processing_status(session_id: &str, processing: bool) {
 /// Broadcast that a session was created
 #[cfg(not(target_os = "ios"))]
 fn broadcast_session_created(session: &SessionData) {
-    broadcast_session_event("session_created", serde_json::json!(session));
+    broadcast_session_event(events::SessionEvent::SessionCreated(session.clone()));
+    webhooks::dispatch("session_created", &session.id, serde_json::json!(session));
 
     // Also broadcast to all mobile clients
     let msg = serde_json::json!({
@@ -215,9 +461,9 @@ fn broadcast_session_created(session: &SessionData) {
 /// Broadcast that a session was deleted
 #[cfg(not(target_os = "ios"))]
 fn broadcast_session_deleted(session_id: &str) {
-    broadcast_session_event("session_deleted", serde_json::json!({
-        "session_id": session_id
-    }));
+    attention::clear(session_id);
+    tray::refresh();
+    broadcast_session_event(events::SessionEvent::SessionDeleted { session_id: session_id.to_string() });
 
     // Also broadcast to all mobile clients
     let msg = serde_json::json!({
@@ -230,7 +476,7 @@ fn broadcast_session_deleted(session_id: &str) {
 /// Broadcast that a session was updated
 #[cfg(not(target_os = "ios"))]
 fn broadcast_session_updated(session: &SessionData) {
-    broadcast_session_event("session_updated", serde_json::json!(session));
+    broadcast_session_event(events::SessionEvent::SessionUpdated(session.clone()));
 
     // Also broadcast to all mobile clients
     let msg = serde_json::json!({
@@ -240,40 +486,239 @@ fn broadcast_session_updated(session: &SessionData) {
     broadcast_to_mobile_clients(&msg);
 }
 
+/// Broadcast that a folder was created or updated
+#[cfg(not(target_os = "ios"))]
+fn broadcast_folder_changed(event: &str, folder: &FolderData) {
+    broadcast_session_event(if event == "folder_created" {
+        events::SessionEvent::FolderCreated(folder.clone())
+    } else {
+        events::SessionEvent::FolderUpdated(folder.clone())
+    });
+
+    // Also broadcast to all mobile clients
+    let msg = serde_json::json!({
+        "type": event,
+        "folder": folder
+    }).to_string();
+    broadcast_to_mobile_clients(&msg);
+}
+
+/// Broadcast that a folder was deleted (its sessions are now unfiled)
+#[cfg(not(target_os = "ios"))]
+fn broadcast_folder_deleted(folder_id: &str) {
+    broadcast_session_event(events::SessionEvent::FolderDeleted { folder_id: folder_id.to_string() });
+
+    // Also broadcast to all mobile clients
+    let msg = serde_json::json!({
+        "type": "folder_deleted",
+        "folderId": folder_id
+    }).to_string();
+    broadcast_to_mobile_clients(&msg);
+}
+
 /// Send a message to all mobile clients
 #[cfg(not(target_os = "ios"))]
 fn broadcast_to_mobile_clients(msg: &str) {
-    let clients = MOBILE_CLIENTS.lock();
+    if message_concerns_hidden_session(msg) {
+        return;
+    }
+    let clients = state::get().mobile_clients.lock();
     for client in clients.values() {
         let _ = client.sender.send(msg.to_string());
     }
 }
 
+fn is_remote_hidden(session_id: &str) -> bool {
+    state::get().remote_hidden_sessions.lock().contains(session_id)
+}
+
+/// Whether an event payload is about a session hidden from remote devices
+#[cfg(not(target_os = "ios"))]
+fn concerns_hidden_session(value: &serde_json::Value) -> bool {
+    let hidden = state::get().remote_hidden_sessions.lock();
+    if hidden.is_empty() {
+        return false;
+    }
+    ["session_id", "sessionId", "id"]
+        .iter()
+        .filter_map(|key| value.get(key))
+        .chain(value.get("session").and_then(|s| s.get("id")))
+        .filter_map(|v| v.as_str())
+        .any(|id| hidden.contains(id))
+}
+
+#[cfg(not(target_os = "ios"))]
+fn message_concerns_hidden_session(msg: &str) -> bool {
+    if state::get().remote_hidden_sessions.lock().is_empty() {
+        return false;
+    }
+    serde_json::from_str(msg).is_ok_and(|v| concerns_hidden_session(&v))
+}
+
+// 404 for sessions hidden from remote devices, so they look like they don't exist
+fn check_session_remote(session_id: &str) -> Option<axum::response::Response> {
+    is_remote_hidden(session_id).then(|| {
+        (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": "Session not found"}))).into_response()
+    })
+}
+
+/// Keep a session off (or put it back on) paired devices
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn set_session_remote_access(session_id: String, enabled: bool) -> Result<(), String> {
+    {
+        let conn = state::get().db().lock();
+        let updated = conn
+            .execute(
+                "UPDATE sessions SET remote_access = ?1 WHERE id = ?2",
+                params![enabled, session_id],
+            )
+            .map_err(|e| e.to_string())?;
+        if updated == 0 {
+            return Err("Session not found".to_string());
+        }
+    }
+    refresh_remote_hidden()
+}
+
+/// Re-read which sessions are kept off paired devices (by their own setting
+/// or their workspace's) after either changed, tell connected devices about
+/// sessions that went away or came back, and resend the list
+#[cfg(not(target_os = "ios"))]
+fn refresh_remote_hidden() -> Result<(), String> {
+    let hidden = workspaces::remote_hidden_sessions(&state::get().db().lock());
+    let (newly_hidden, shown): (Vec<String>, std::collections::HashSet<String>) = {
+        let mut current = state::get().remote_hidden_sessions.lock();
+        let newly_hidden = hidden.difference(&current).cloned().collect();
+        let shown = current.difference(&hidden).cloned().collect();
+        *current = hidden;
+        (newly_hidden, shown)
+    };
+    for session_id in newly_hidden {
+        // Sent directly: broadcasts about hidden sessions are dropped
+        let msg = serde_json::json!({
+            "type": "session_deleted",
+            "sessionId": session_id
+        }).to_string();
+        for client in state::get().mobile_clients.lock().values_mut() {
+            client.subscribed_sessions.remove(&session_id);
+            let _ = client.sender.send(msg.clone());
+        }
+        presence::clear(&session_id);
+    }
+    // Back on paired devices, as if just created
+    for session in load_sessions()?.into_iter().filter(|s| shown.contains(&s.id)) {
+        broadcast_session_created(&session);
+        if is_session_running(&session.id) {
+            broadcast_session_status(&session.id, true);
+        }
+    }
+    broadcast_session_list_to_mobile();
+    Ok(())
+}
+
+/// Whether any mobile client has the session open
+#[cfg(not(target_os = "ios"))]
+fn session_has_mobile_subscribers(session_id: &str) -> bool {
+    state::get().mobile_clients.lock().values().any(|c| c.subscribed_sessions.contains_key(session_id))
+}
+
 /// Send a message to mobile clients subscribed to a specific session
 #[cfg(not(target_os = "ios"))]
 fn broadcast_to_session_subscribers(session_id: &str, msg: &str) {
-    let clients = MOBILE_CLIENTS.lock();
+    let clients = state::get().mobile_clients.lock();
     for client in clients.values() {
-        if client.subscribed_sessions.contains(session_id) {
+        if client.subscribed_sessions.contains_key(session_id) {
             let _ = client.sender.send(msg.to_string());
         }
     }
 }
 
-/// Broadcast session list to all mobile clients
+/// Send a chat message to the session's mobile subscribers, numbered with the
+/// next seq for the session so clients can notice and fill gaps (see
+/// message_log).
 #[cfg(not(target_os = "ios"))]
-fn broadcast_session_list_to_mobile() {
+fn broadcast_chat_message(session_id: &str, message: &ClaudeJsonMessage) {
+    let Ok(message) = serde_json::to_value(message) else { return };
+    let _order = message_log::lock();
+    let seq = match message_log::append(session_id, &message) {
+        Ok(seq) => Some(seq),
+        Err(e) => {
+            tracing::warn!("[message_log] {}: {}", session_id, e);
+            None
+        }
+    };
+    let chat_message = |message: &serde_json::Value| {
+        serde_json::json!({
+            "type": "chat_message",
+            "sessionId": session_id,
+            "seq": seq,
+            "message": message
+        }).to_string()
+    };
+    let msg = chat_message(&message);
+    let mut acking = Vec::new();
+    for client in state::get().mobile_clients.lock().values() {
+        let Some(filter) = client.subscribed_sessions.get(session_id) else { continue };
+        let sent = if filter.is_empty() { msg.clone() } else { chat_message(&filter.apply(&message)) };
+        let _ = client.sender.send(sent);
+        if client.acks {
+            acking.extend(client.device_id.clone());
+        }
+    }
+    if let Some(seq) = seq {
+        for device_id in acking {
+            message_log::delivered(&device_id, session_id, seq);
+        }
+    }
+}
+
+/// Resend chat messages a reconnecting device was sent but didn't ack, marked
+/// `retry` so clients that have since dropped the session's history skip them.
+#[cfg(not(target_os = "ios"))]
+fn resend_unacked(device_id: &str, tx: &MobileSender) {
+    let owed = match message_log::unacked(device_id) {
+        Ok(owed) => owed,
+        Err(e) => {
+            tracing::warn!("[message_log] unacked for {}: {}", device_id, e);
+            return;
+        }
+    };
+    for (session_id, acked) in owed {
+        let missed = if is_remote_hidden(&session_id) { None } else { message_log::since(&session_id, acked).ok().flatten() };
+        let Some(missed) = missed else {
+            // No longer resendable; its history loads in full on subscribe
+            let _ = message_log::acked(device_id, &session_id, message_log::latest(&session_id));
+            continue;
+        };
+        for (seq, message) in missed {
+            let _ = tx.send(serde_json::json!({
+                "type": "chat_message",
+                "sessionId": session_id,
+                "seq": seq,
+                "retry": true,
+                "message": message
+            }).to_string());
+        }
+    }
+}
+
+/// The mobile WebSocket's session_list message: sessions with running
+/// status, folders and workspaces, leaving out what's hidden from remote
+/// devices
+#[cfg(not(target_os = "ios"))]
+fn mobile_session_list() -> serde_json::Value {
     let sessions = load_sessions().unwrap_or_default();
     let json_running: std::collections::HashSet<String> = {
-        let broadcasters = JSON_BROADCASTERS.lock();
+        let broadcasters = state::get().json_broadcasters.lock();
         broadcasters.keys().cloned().collect()
     };
     let pty_running: std::collections::HashSet<String> = {
-        let pty_sessions = PTY_SESSIONS.lock();
+        let pty_sessions = state::get().pty_sessions.lock();
         pty_sessions.keys().cloned().collect()
     };
 
-    let sessions_with_status: Vec<serde_json::Value> = sessions.iter().map(|s| {
+    let sessions_with_status: Vec<serde_json::Value> = sessions.iter().filter(|s| !is_remote_hidden(&s.id)).map(|s| {
         let running = json_running.contains(&s.id) || pty_running.contains(&s.id);
         serde_json::json!({
             "id": s.id,
@@ -282,37 +727,63 @@ fn broadcast_session_list_to_mobile() {
             "agent_type": s.agent_type,
             "working_dir": s.working_dir,
             "folder_id": s.folder_id,
+            "parent_id": s.parent_id,
+            "workspace_id": s.workspace_id,
+            "summary": s.summary,
             "running": running,
+            "processing": is_session_processing(&s.id),
         })
     }).collect();
 
-    let folders_data: Vec<serde_json::Value> = load_folders().unwrap_or_default().into_iter().map(|f| {
-        serde_json::json!({
-            "id": f.id,
-            "name": f.name,
-            "sort_order": f.sort_order,
-            "collapsed": f.collapsed,
-        })
-    }).collect();
+    let hidden_workspaces = workspaces::remote_hidden_workspaces();
+    let folders_data: Vec<serde_json::Value> = load_folders().unwrap_or_default().into_iter()
+        .filter(|f| !f.workspace_id.as_ref().is_some_and(|w| hidden_workspaces.contains(w)))
+        .map(|f| {
+            serde_json::json!({
+                "id": f.id,
+                "name": f.name,
+                "sort_order": f.sort_order,
+                "collapsed": f.collapsed,
+                "workspace_id": f.workspace_id,
+            })
+        }).collect();
 
     let settings = load_app_settings().unwrap_or_default();
-    let msg = serde_json::json!({
+    serde_json::json!({
         "type": "session_list",
         "sessions": sessions_with_status,
         "folders": folders_data,
+        "workspaces": remote_workspaces(),
         "settings": {
             "show_active_sessions_group": settings.show_active_sessions_group
         }
-    }).to_string();
+    })
+}
 
-    broadcast_to_mobile_clients(&msg);
+/// Workspaces as listed to remote clients: the ones they may see
+#[cfg(not(target_os = "ios"))]
+fn remote_workspaces() -> Vec<workspaces::Workspace> {
+    workspaces::list().unwrap_or_default().into_iter().filter(|w| w.remote_access).collect()
+}
+
+/// Send the whole session list to mobile clients that predate list deltas
+/// (protocol 10); newer ones get a session_* / folder_* event per change
+#[cfg(not(target_os = "ios"))]
+fn broadcast_session_list_to_mobile() {
+    if state::get().mobile_clients.lock().values().all(|c| c.list_deltas) {
+        return;
+    }
+    let msg = mobile_session_list().to_string();
+    for client in state::get().mobile_clients.lock().values().filter(|c| !c.list_deltas) {
+        let _ = client.sender.send(msg.clone());
+    }
 }
 
-/// Load session messages from DB into the in-memory SESSION_MESSAGES buffer.
-/// Only loads once per session (tracked by SESSION_MESSAGES_LOADED).
+/// Load session messages from DB into the in-memory session_messages buffer.
+/// Only loads once per session (tracked by session_messages_loaded).
 #[cfg(not(target_os = "ios"))]
 fn ensure_session_messages_loaded(session_id: &str) {
-    let mut loaded = SESSION_MESSAGES_LOADED.lock();
+    let mut loaded = state::get().session_messages_loaded.lock();
     if loaded.contains(session_id) {
         return;
     }
@@ -323,8 +794,9 @@ fn ensure_session_messages_loaded(session_id: &str) {
     if let Ok(Some(buffer)) = load_terminal_buffer(session_id.to_string()) {
         let db_messages = parse_buffer_to_messages(&buffer);
         if !db_messages.is_empty() {
-            let mut messages = SESSION_MESSAGES.lock();
-            messages.insert(session_id.to_string(), db_messages);
+            let count = db_messages.len();
+            state::get().session_messages.lock().insert(session_id.to_string(), db_messages);
+            state::get().session_messages_saved.lock().insert(session_id.to_string(), count);
         }
     }
 }
@@ -350,29 +822,66 @@ fn parse_buffer_to_messages(buffer: &str) -> Vec<serde_json::Value> {
     messages
 }
 
-/// Append a message to the in-memory SESSION_MESSAGES buffer for a session.
+/// Append a message to the in-memory session_messages buffer for a session.
 /// Ensures DB history is loaded first so we have the complete picture.
 #[cfg(not(target_os = "ios"))]
 fn append_session_message(session_id: &str, message: serde_json::Value) {
     ensure_session_messages_loaded(session_id);
-    let mut messages = SESSION_MESSAGES.lock();
+    shutdown::journal_append(session_id, &message);
+    let mut messages = state::get().session_messages.lock();
     messages.entry(session_id.to_string()).or_default().push(message);
 }
 
-/// Save the in-memory SESSION_MESSAGES buffer for a session to the database.
-/// Uses save_terminal_buffer_to_db directly to avoid re-updating SESSION_MESSAGES.
+/// Write a session's in-memory messages to the database and nothing more
+/// (no search ingest); used while shutting down.
+#[cfg(not(target_os = "ios"))]
+fn flush_session_messages(session_id: &str) {
+    if let Err(e) = save_all_session_messages(session_id) {
+        tracing::error!("Failed to save messages for {}: {}", session_id, e);
+    }
+}
+
+/// Write all of a session's in-memory messages as its buffer, a JSON array
+#[cfg(not(target_os = "ios"))]
+fn save_all_session_messages(session_id: &str) -> Result<(), String> {
+    let messages = state::get().session_messages.lock().get(session_id).filter(|m| !m.is_empty()).cloned();
+    let Some(messages) = messages else { return Ok(()) };
+    save_terminal_buffer_to_db(session_id, &serde_json::to_string(&messages).unwrap_or_default())?;
+    state::get().session_messages_saved.lock().insert(session_id.to_string(), messages.len());
+    Ok(())
+}
+
+/// Save the in-memory session_messages buffer for a session to the database.
+/// Messages since the last save are appended, a line each after the saved
+/// array (load_terminal_buffer folds them back in); the whole buffer is
+/// written the first time and once enough has been appended.
+/// Uses save_terminal_buffer_to_db directly to avoid re-updating session_messages.
 #[cfg(not(target_os = "ios"))]
 fn save_session_messages_to_db(session_id: &str) {
-    let messages = {
-        let msgs = SESSION_MESSAGES.lock();
-        match msgs.get(session_id) {
-            Some(m) if !m.is_empty() => m.clone(),
-            _ => return,
+    let (unsaved, total) = {
+        let msgs = state::get().session_messages.lock();
+        let Some(messages) = msgs.get(session_id).filter(|m| !m.is_empty()) else { return };
+        let saved = state::get().session_messages_saved.lock().get(session_id).copied();
+        (saved.filter(|&n| n <= messages.len()).map(|n| messages[n..].to_vec()), messages.len())
+    };
+    let result = match unsaved {
+        Some(unsaved) if unsaved.is_empty() => Ok(()),
+        Some(unsaved) => {
+            let lines: String = unsaved.iter().map(|message| format!("\n{}", message)).collect();
+            append_terminal_buffer_to_db(session_id, &lines).and_then(|appended| {
+                state::get().session_messages_saved.lock().insert(session_id.to_string(), total);
+                if appended > buffer_store::REWRITE_AFTER {
+                    save_all_session_messages(session_id)
+                } else {
+                    Ok(())
+                }
+            })
         }
+        None => save_all_session_messages(session_id),
     };
-
-    let buffer_content = serde_json::to_string(&messages).unwrap_or_default();
-    let _ = save_terminal_buffer_to_db(session_id, &buffer_content);
+    if let Err(e) = result {
+        tracing::error!("Failed to save messages for {}: {}", session_id, e);
+    }
 
     // End-of-turn: re-scan this session's JSONL file(s) and ingest any new
     // bytes into the search index. Idempotent (resumes from last_offset).
@@ -395,12 +904,12 @@ fn save_session_messages_to_db(session_id: &str) {
 /// Get session history for mobile clients (returns JSON messages parsed from buffer)
 /// Prefers the on-disk JSONL when we have a claude_session_id (each line
 /// carries `uuid` so search-hit jump-to-message works on mobile too); falls
-/// back to the in-memory SESSION_MESSAGES buffer for sessions without a
+/// back to the in-memory session_messages buffer for sessions without a
 /// JSONL on disk.
 #[cfg(not(target_os = "ios"))]
 fn get_session_history(session_id: &str) -> Option<Vec<serde_json::Value>> {
     let session_meta: Option<(Option<String>, String)> = {
-        let conn = DB_CONNECTION.lock();
+        let conn = state::get().db().lock();
         conn.query_row(
             "SELECT claude_session_id, working_dir FROM sessions WHERE id = ?1",
             [session_id],
@@ -417,7 +926,7 @@ fn get_session_history(session_id: &str) -> Option<Vec<serde_json::Value>> {
     }
 
     ensure_session_messages_loaded(session_id);
-    let messages = SESSION_MESSAGES.lock();
+    let messages = state::get().session_messages.lock();
     match messages.get(session_id) {
         Some(msgs) if !msgs.is_empty() => Some(msgs.clone()),
         _ => None,
@@ -429,6 +938,19 @@ struct PairingRequest {
     code: String,
     created_at: chrono::DateTime<chrono::Utc>,
     device_name: Option<String>,
+    /// The device has entered the right code and is waiting for the desktop
+    /// to approve (or deny) it
+    #[serde(default)]
+    code_verified: bool,
+    #[serde(default)]
+    approved: bool,
+    /// What the desktop allowed the device, once approved
+    #[serde(default)]
+    scope: DeviceScope,
+    #[serde(default)]
+    denied: bool,
+    #[serde(default)]
+    failed_attempts: u32,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -437,6 +959,47 @@ struct PairedDevice {
     name: String,
     paired_at: String,
     last_seen: String,
+    /// When this token stops working (RFC 3339); None never expires
+    #[serde(default)]
+    expires_at: Option<String>,
+    #[serde(default)]
+    scope: DeviceScope,
+    /// Which push notifications the device gets
+    #[serde(default)]
+    notifications: notify_prefs::NotifyPrefs,
+}
+
+/// What a paired device may do. Ordered: each scope includes the ones before it.
+/// The default is the least: new devices get more only when the desktop grants it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum DeviceScope {
+    /// Watch sessions and read history
+    #[default]
+    ReadOnly,
+    /// Also type into, start, stop and manage sessions
+    Interact,
+    /// Also change settings, schedules and other devices
+    Admin,
+}
+
+impl DeviceScope {
+    fn as_str(self) -> &'static str {
+        match self {
+            DeviceScope::ReadOnly => "read_only",
+            DeviceScope::Interact => "interact",
+            DeviceScope::Admin => "admin",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "read_only" => Some(DeviceScope::ReadOnly),
+            "interact" => Some(DeviceScope::Interact),
+            "admin" => Some(DeviceScope::Admin),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -470,6 +1033,9 @@ struct AppSettings {
     read_aloud_enabled: bool,
     #[serde(default = "default_renderer")]
     renderer: String,
+    /// Salted hash of the remote access PIN (see pin.rs). Kept in the
+    /// secret store and filled in on load; never written to config.json and
+    /// only changed through set_remote_pin.
     #[serde(default)]
     remote_pin: Option<String>,
     #[serde(default = "default_true")]
@@ -484,6 +1050,171 @@ struct AppSettings {
     /// accounts.
     #[serde(default = "default_claude_search_dirs")]
     claude_search_dirs: Vec<String>,
+    /// Context usage (% of the usable window) at which a context_warning
+    /// event fires for a claude-json session.
+    #[serde(default = "default_context_warning_pct")]
+    context_warning_pct: u32,
+    /// Summarize idle claude-json sessions for the session list (one small
+    /// CLI run per idle period).
+    #[serde(default = "default_true")]
+    auto_summaries_enabled: bool,
+    /// OS voice for spoken output (e.g. "Samantha"); None = system default.
+    #[serde(default)]
+    tts_voice: Option<String>,
+    /// Command that transcribes an audio file to stdout, with `{file}` for
+    /// its path (e.g. whisper.cpp's whisper-cli). Voice prompts are
+    /// rejected while unset.
+    #[serde(default)]
+    transcribe_command: Option<String>,
+    /// Mask credentials (built-in patterns plus `redaction_rules`) in agent
+    /// output before it is persisted or sent to remote clients.
+    #[serde(default = "default_true")]
+    redaction_enabled: bool,
+    /// Launch command overrides per agent type (e.g. "claude-json"), with
+    /// `{model}` for the default model flag. Unlisted types use the
+    /// built-in commands.
+    #[serde(default)]
+    agent_commands: HashMap<String, String>,
+    /// Minutes a claude-json turn may go without output before the session
+    /// is reported as hung (per-session override in `watchdog_sessions`).
+    #[serde(default = "default_hang_timeout_mins")]
+    hang_timeout_mins: u32,
+    /// Times a crashed claude-json process is restarted (with backoff)
+    /// before giving up; 0 disables auto-restart.
+    #[serde(default = "default_auto_restart_max_attempts")]
+    auto_restart_max_attempts: u32,
+    /// Days a paired device's token stays valid; clients refresh before
+    /// then via POST /api/auth/refresh. 0 means tokens never expire.
+    #[serde(default = "default_token_lifetime_days")]
+    token_lifetime_days: u32,
+    /// Serve the web API over HTTPS (takes effect on restart). Loopback
+    /// clients may still use plain HTTP.
+    #[serde(default)]
+    tls_enabled: bool,
+    /// PEM certificate chain and private key; a self-signed certificate is
+    /// generated when both are unset.
+    #[serde(default)]
+    tls_cert_path: Option<String>,
+    #[serde(default)]
+    tls_key_path: Option<String>,
+    /// Expose the web server outside the LAN: "tailscale" (Funnel) or "ssh"
+    /// (reverse tunnel). Takes effect on restart.
+    #[serde(default)]
+    tunnel_mode: Option<String>,
+    /// `user@host` for the SSH reverse tunnel
+    #[serde(default)]
+    tunnel_ssh_target: Option<String>,
+    /// Port opened on the SSH host (defaults to the local port)
+    #[serde(default)]
+    tunnel_ssh_remote_port: Option<u16>,
+    /// URL clients should use when it isn't http://<ssh host>:<remote port>,
+    /// e.g. behind a reverse proxy on the SSH host
+    #[serde(default)]
+    tunnel_public_url: Option<String>,
+    /// Per-bucket overrides of the built-in rate limits ("pairing", "auth",
+    /// "messages"); see ratelimit.rs.
+    #[serde(default)]
+    rate_limits: HashMap<String, ratelimit::Limit>,
+    /// Run the web server (remote access, mobile web UI, MCP bridge).
+    #[serde(default = "default_true")]
+    web_server_enabled: bool,
+    /// Address the web server listens on; None means all interfaces.
+    /// "127.0.0.1" keeps it to this computer (tunnels still work).
+    #[serde(default)]
+    web_bind_address: Option<String>,
+    /// Origins allowed to call the API from a browser; empty allows any.
+    #[serde(default)]
+    cors_allowed_origins: Vec<String>,
+    /// Client addresses / CIDR ranges allowed to reach the web server (REST
+    /// and WebSockets); empty allows any. This computer is always allowed,
+    /// and so is anything arriving through an SSH tunnel; Tailscale Funnel
+    /// clients are checked by their forwarded address.
+    #[serde(default)]
+    ip_allowlist: Vec<String>,
+    /// Slack / Discord incoming-webhook URLs posted to when a session with
+    /// chat notifications turned on finishes a turn.
+    #[serde(default)]
+    slack_webhook_url: Option<String>,
+    #[serde(default)]
+    discord_webhook_url: Option<String>,
+    /// System-wide shortcuts, action -> accelerator (see hotkeys.rs)
+    #[serde(default)]
+    global_shortcuts: HashMap<String, String>,
+    /// Menu item shortcuts overriding the defaults, action -> accelerator
+    /// (see shortcuts.rs)
+    #[serde(default)]
+    shortcuts: HashMap<String, String>,
+    /// Name of the active settings profile (see profiles.rs)
+    #[serde(default = "default_profile")]
+    profile: String,
+    /// Workspace the sidebar shows and new sessions go into (None = all;
+    /// see workspaces.rs)
+    #[serde(default)]
+    active_workspace_id: Option<String>,
+}
+
+/// Settings remote clients may read and change through /api/settings.
+/// Anything that launches commands, touches credentials or controls remote
+/// access (remote_pin, agent_commands, transcribe_command, ...) stays
+/// desktop-only.
+#[cfg(not(target_os = "ios"))]
+const REMOTE_SETTINGS_FIELDS: &[&str] = &[
+    "theme",
+    "default_working_dir",
+    "default_agent_type",
+    "default_model",
+    "notifications_enabled",
+    "bell_notifications_enabled",
+    "bounce_dock_on_bell",
+    "read_aloud_enabled",
+    "tts_voice",
+    "show_active_sessions_group",
+    "context_warning_pct",
+    "auto_summaries_enabled",
+    "hang_timeout_mins",
+    "auto_restart_max_attempts",
+];
+
+/// The remotely visible subset of `settings`.
+#[cfg(not(target_os = "ios"))]
+fn remote_settings(settings: &AppSettings) -> serde_json::Value {
+    let all = serde_json::to_value(settings).unwrap_or_default();
+    let fields = REMOTE_SETTINGS_FIELDS
+        .iter()
+        .map(|&k| (k.to_string(), all.get(k).cloned().unwrap_or(serde_json::Value::Null)))
+        .collect();
+    serde_json::Value::Object(fields)
+}
+
+/// Apply a partial update from a remote client. Rejects fields outside
+/// REMOTE_SETTINGS_FIELDS and values of the wrong type.
+#[cfg(not(target_os = "ios"))]
+fn apply_remote_settings(settings: &AppSettings, changes: &serde_json::Value) -> Result<AppSettings, String> {
+    let changes = changes.as_object().ok_or("Expected a JSON object")?;
+    let mut all = serde_json::to_value(settings).map_err(|e| e.to_string())?;
+    for (key, value) in changes {
+        if !REMOTE_SETTINGS_FIELDS.contains(&key.as_str()) {
+            return Err(format!("Setting '{}' cannot be changed remotely", key));
+        }
+        all[key] = value.clone();
+    }
+    serde_json::from_value(all).map_err(|e| format!("Invalid settings: {}", e))
+}
+
+fn default_auto_restart_max_attempts() -> u32 {
+    3
+}
+
+fn default_token_lifetime_days() -> u32 {
+    30
+}
+
+fn default_hang_timeout_mins() -> u32 {
+    10
+}
+
+fn default_context_warning_pct() -> u32 {
+    80
 }
 
 fn default_claude_search_dirs() -> Vec<String> {
@@ -498,13 +1229,17 @@ fn default_true() -> bool {
     true
 }
 
+fn default_profile() -> String {
+    "default".to_string()
+}
+
 impl Default for AppSettings {
     fn default() -> Self {
         Self {
             font_size: 13,
             font_family: "Menlo, Monaco, 'Courier New', monospace".to_string(),
             theme: "dark".to_string(),
-            default_working_dir: "~/dev/pplsi".to_string(),
+            default_working_dir: platform::DEFAULT_WORKING_DIR.to_string(),
             default_agent_type: "claude".to_string(),
             notifications_enabled: true,
             bell_notifications_enabled: true,
@@ -516,6 +1251,33 @@ impl Default for AppSettings {
             default_model: None,
             claude_config_dir: None,
             claude_search_dirs: default_claude_search_dirs(),
+            context_warning_pct: default_context_warning_pct(),
+            auto_summaries_enabled: true,
+            tts_voice: None,
+            transcribe_command: None,
+            redaction_enabled: true,
+            agent_commands: HashMap::new(),
+            hang_timeout_mins: default_hang_timeout_mins(),
+            auto_restart_max_attempts: default_auto_restart_max_attempts(),
+            token_lifetime_days: default_token_lifetime_days(),
+            tls_enabled: false,
+            tls_cert_path: None,
+            tls_key_path: None,
+            tunnel_mode: None,
+            tunnel_ssh_target: None,
+            tunnel_ssh_remote_port: None,
+            tunnel_public_url: None,
+            rate_limits: HashMap::new(),
+            web_server_enabled: true,
+            web_bind_address: None,
+            cors_allowed_origins: Vec::new(),
+            ip_allowlist: Vec::new(),
+            slack_webhook_url: None,
+            discord_webhook_url: None,
+            global_shortcuts: HashMap::new(),
+            shortcuts: HashMap::new(),
+            profile: default_profile(),
+            active_workspace_id: None,
         }
     }
 }
@@ -534,6 +1296,18 @@ struct SessionData {
     folder_id: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     env_vars: Option<String>,
+    /// Session that spawned this one (sub-sessions created by an agent)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parent_id: Option<String>,
+    /// Auto-generated summary of the conversation (read-only; set by the backend)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    summary: Option<String>,
+    /// Whether paired devices can see this session (read-only; see set_session_remote_access)
+    #[serde(default = "default_true")]
+    remote_access: bool,
+    /// Workspace the session belongs to; a save without one keeps the stored value
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    workspace_id: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -542,6 +1316,9 @@ struct FolderData {
     name: String,
     sort_order: i32,
     collapsed: bool,
+    /// Workspace the folder belongs to (None = listed in every workspace)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    workspace_id: Option<String>,
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -686,6 +1463,25 @@ fn parse_claude_json(line: &str) -> Option<ClaudeJsonMessage> {
     serde_json::from_str(json_str).ok()
 }
 
+/// For a partial-message line (`stream_event`, sent with
+/// --include-partial-messages), the reply text it adds, which is empty for
+/// events other than top-level text deltas. None for any other line.
+fn parse_stream_event(line: &str) -> Option<String> {
+    if !line.contains("\"stream_event\"") {
+        return None;
+    }
+    let value: serde_json::Value = serde_json::from_str(&line[line.find('{')?..]).ok()?;
+    if value["type"] != "stream_event" {
+        return None;
+    }
+    // Subagent output isn't part of the reply being typed
+    if !value["parent_tool_use_id"].is_null() {
+        return Some(String::new());
+    }
+    let delta = &value["event"]["delta"];
+    Some(if delta["type"] == "text_delta" { delta["text"].as_str().unwrap_or_default().to_string() } else { String::new() })
+}
+
 /// Get the app data directory name based on build type
 /// In debug builds, use "agent-hub-dev" to separate data from production
 fn get_app_data_dir_name() -> &'static str {
@@ -697,9 +1493,7 @@ fn get_app_data_dir_name() -> &'static str {
 }
 
 fn get_db_path() -> PathBuf {
-    let data_dir = dirs::data_dir()
-        .unwrap_or_else(|| PathBuf::from("."))
-        .join(get_app_data_dir_name());
+    let data_dir = platform::data_dir();
     std::fs::create_dir_all(&data_dir).ok();
     data_dir.join("sessions.db")
 }
@@ -707,7 +1501,7 @@ fn get_db_path() -> PathBuf {
 /// Run database migrations once at startup using the shared connection.
 /// Must be called before any other DB operations.
 fn run_db_migrations() {
-    let conn = DB_CONNECTION.lock();
+    let conn = state::get().db().lock();
     conn.execute(
         "CREATE TABLE IF NOT EXISTS sessions (
             id TEXT PRIMARY KEY,
@@ -734,6 +1528,15 @@ fn run_db_migrations() {
     // Migration: Add env_vars column for per-session environment variables (JSON string)
     let _ = conn.execute("ALTER TABLE sessions ADD COLUMN env_vars TEXT", []);
 
+    // Migration: Add parent_id column for sub-sessions spawned by an agent
+    let _ = conn.execute("ALTER TABLE sessions ADD COLUMN parent_id TEXT", []);
+
+    // Migration: sessions can be kept off remote devices
+    let _ = conn.execute("ALTER TABLE sessions ADD COLUMN remote_access INTEGER NOT NULL DEFAULT 1", []);
+
+    // Migration: whether the agent is mid-turn (see processing_sessions)
+    let _ = conn.execute("ALTER TABLE sessions ADD COLUMN processing INTEGER NOT NULL DEFAULT 0", []);
+
     // Create folders table for session organization
     conn.execute(
         "CREATE TABLE IF NOT EXISTS folders (
@@ -746,16 +1549,7 @@ fn run_db_migrations() {
     ).expect("Failed to create folders table");
 
     // Create terminal_buffers table for scrollback persistence
-    // Stores compressed (gzip + base64) terminal buffer content
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS terminal_buffers (
-            session_id TEXT PRIMARY KEY,
-            buffer_data TEXT NOT NULL,
-            updated_at TEXT NOT NULL,
-            FOREIGN KEY (session_id) REFERENCES sessions(id) ON DELETE CASCADE
-        )",
-        [],
-    ).expect("Failed to create terminal_buffers table");
+    buffer_store::run_buffer_migrations(&conn);
 
     // Create paired_devices table for remote access authentication
     conn.execute(
@@ -769,6 +1563,22 @@ fn run_db_migrations() {
         [],
     ).expect("Failed to create paired_devices table");
 
+    // Migration: tokens are stored as SHA-256 hashes; rows from before this
+    // hold the plain token and are hashed by load_paired_devices()
+    let _ = conn.execute("ALTER TABLE paired_devices ADD COLUMN token_hashed INTEGER NOT NULL DEFAULT 0", []);
+
+    // Migration: per-token expiry (NULL = never)
+    let _ = conn.execute("ALTER TABLE paired_devices ADD COLUMN expires_at TEXT", []);
+
+    // Migration: per-device permission scope; existing devices keep full access
+    let _ = conn.execute("ALTER TABLE paired_devices ADD COLUMN scope TEXT NOT NULL DEFAULT 'admin'", []);
+
+    // Migration: per-device notification preferences (JSON; NULL = all)
+    let _ = conn.execute("ALTER TABLE paired_devices ADD COLUMN notify_prefs TEXT", []);
+
+    // token_hashed = 2: `token` is only a row key and the hash is in the
+    // secret store; load_paired_devices() moves hashed rows there
+
     // Create recently_closed table for undo close functionality
     conn.execute(
         "CREATE TABLE IF NOT EXISTS recently_closed (
@@ -801,58 +1611,275 @@ fn run_db_migrations() {
     // Search: schema-versioned migrations for message_index + session_files +
     // FTS. Drops/recreates if SCHEMA_VERSION has changed.
     search::run_search_migrations(&conn);
+
+    // Prompt queue for batch runs against claude-json sessions
+    #[cfg(not(target_os = "ios"))]
+    queue::run_queue_migrations(&conn);
+
+    // Pipelines chaining one session's output into another
+    #[cfg(not(target_os = "ios"))]
+    pipeline::run_pipeline_migrations(&conn);
+
+    // Saved prompt templates
+    #[cfg(not(target_os = "ios"))]
+    prompts::run_prompt_migrations(&conn);
+
+    // Fan-out runs and their per-session responses
+    #[cfg(not(target_os = "ios"))]
+    fanout::run_fanout_migrations(&conn);
+
+    // File changes made by agents
+    #[cfg(not(target_os = "ios"))]
+    changes::run_changes_migrations(&conn);
+
+    // Per-turn git snapshots
+    #[cfg(not(target_os = "ios"))]
+    snapshots::run_snapshot_migrations(&conn);
+
+    // Session summary columns
+    #[cfg(not(target_os = "ios"))]
+    summaries::run_summary_migrations(&conn);
+
+    // Sessions whose results are spoken aloud
+    #[cfg(not(target_os = "ios"))]
+    tts::run_tts_migrations(&conn);
+
+    // Sessions posted to Slack / Discord when they finish
+    #[cfg(not(target_os = "ios"))]
+    chat_notify::run_chat_notify_migrations(&conn);
+
+    // Custom secret redaction patterns
+    #[cfg(not(target_os = "ios"))]
+    redaction::run_redaction_migrations(&conn);
+
+    // Headless run history and usage
+    #[cfg(not(target_os = "ios"))]
+    runs::run_runs_migrations(&conn);
+
+    // Per-session hang policies
+    #[cfg(not(target_os = "ios"))]
+    watchdog::run_watchdog_migrations(&conn);
+
+    // Audit log of remote actions
+    #[cfg(not(target_os = "ios"))]
+    audit::run_audit_migrations(&conn);
+
+    // Outgoing webhooks and their delivery history
+    #[cfg(not(target_os = "ios"))]
+    webhooks::run_webhook_migrations(&conn);
+
+    // Rate limit counters (kept across restarts)
+    ratelimit::run_ratelimit_migrations(&conn);
+
+    // Secret store for platforms without a keychain
+    secrets::run_secrets_migrations(&conn);
+
+    // Guest share links
+    #[cfg(not(target_os = "ios"))]
+    share::run_share_migrations(&conn);
+
+    // Chat messages mobile clients can resume from
+    #[cfg(not(target_os = "ios"))]
+    message_log::run_message_log_migrations(&conn);
+
+    // Per-device push subscriptions
+    #[cfg(not(target_os = "ios"))]
+    push::run_push_migrations(&conn);
+
+    // Remote hubs aggregated into the session list
+    hubs::run_hubs_migrations(&conn);
+
+    // Workspaces, their monthly spend, and sessions' / folders' workspace_id
+    workspaces::run_workspace_migrations(&conn);
+    *state::get().remote_hidden_sessions.lock() = workspaces::remote_hidden_sessions(&conn);
+}
+
+/// Row key -> token hash, as kept in the secret store. An error if it can't
+/// be read, which is not the same as there being none.
+fn stored_token_slots() -> Result<HashMap<String, String>, String> {
+    match secrets::try_get(secrets::DEVICE_TOKENS)? {
+        Some(json) => serde_json::from_str(&json).map_err(|e| format!("Stored device tokens are unreadable: {}", e)),
+        None => Ok(HashMap::new()),
+    }
+}
+
+/// Take in the stored slots if load_paired_devices couldn't; writes of the
+/// map wait for this, so they never replace the stored one with part of it
+fn ensure_token_slots_loaded(slots: &mut HashMap<String, String>) -> Result<(), String> {
+    if state::get().token_slots_loaded.load(std::sync::atomic::Ordering::SeqCst) {
+        return Ok(());
+    }
+    for (key, hash) in stored_token_slots()? {
+        slots.entry(hash).or_insert(key);
+    }
+    state::get().token_slots_loaded.store(true, std::sync::atomic::Ordering::SeqCst);
+    Ok(())
+}
+
+fn store_token_slots(slots: &HashMap<String, String>) -> Result<(), String> {
+    let by_key: HashMap<&String, &String> = slots.iter().map(|(hash, key)| (key, hash)).collect();
+    let json = serde_json::to_string(&by_key).map_err(|e| e.to_string())?;
+    secrets::set(secrets::DEVICE_TOKENS, &json)
 }
 
 // Load paired devices from database into memory
 fn load_paired_devices() {
+    let stored = stored_token_slots();
+    if let Err(e) = &stored {
+        tracing::warn!("[secrets] couldn't read device tokens; devices using them can't connect for now: {}", e);
+    }
+    let readable = stored.is_ok();
+    let mut slots: HashMap<String, String> =
+        stored.unwrap_or_default().into_iter().map(|(key, hash)| (hash, key)).collect();
+
+    // Move token hashes still in the database into the secret store: give
+    // each row a random key, store the key -> hash mapping, then rewrite the
+    // rows (in that order, so a crash in between loses nothing)
+    let unmigrated: Vec<String> = {
+        let conn = state::get().db().lock();
+        // Hash any tokens still stored in the clear
+        let plain: Vec<String> = conn
+            .prepare("SELECT token FROM paired_devices WHERE token_hashed = 0")
+            .and_then(|mut stmt| stmt.query_map([], |row| row.get(0))?.collect())
+            .unwrap_or_default();
+        for token in plain {
+            let _ = conn.execute(
+                "UPDATE paired_devices SET token = ?1, token_hashed = 1 WHERE token = ?2",
+                params![hash_token(&token), token],
+            );
+        }
+        conn.prepare("SELECT token FROM paired_devices WHERE token_hashed = 1")
+            .and_then(|mut stmt| stmt.query_map([], |row| row.get(0))?.collect())
+            .unwrap_or_default()
+    };
+    // Writing the map without the stored entries would drop them
+    if readable && !unmigrated.is_empty() {
+        for hash in &unmigrated {
+            slots.entry(hash.clone()).or_insert_with(generate_token);
+        }
+        match store_token_slots(&slots) {
+            Ok(()) => {
+                let conn = state::get().db().lock();
+                for hash in &unmigrated {
+                    let _ = conn.execute(
+                        "UPDATE paired_devices SET token = ?1, token_hashed = 2 WHERE token = ?2",
+                        params![slots[hash], hash],
+                    );
+                }
+            }
+            Err(e) => tracing::warn!("[secrets] couldn't move device tokens to the secret store: {}", e),
+        }
+    }
+    let hash_for_key: HashMap<String, String> = slots.iter().map(|(hash, key)| (key.clone(), hash.clone())).collect();
+
+    let expiry = token_expiry();
     let loaded: Vec<(String, PairedDevice)> = {
-        let conn = DB_CONNECTION.lock();
-        let mut stmt = match conn.prepare("SELECT token, id, name, paired_at, last_seen FROM paired_devices") {
+        let conn = state::get().db().lock();
+        // Tokens from before expiry existed get a full lifetime from now
+        if let Some(expires_at) = expiry {
+            let _ = conn.execute(
+                "UPDATE paired_devices SET expires_at = ?1 WHERE expires_at IS NULL",
+                params![expires_at],
+            );
+        }
+
+        let mut stmt = match conn.prepare("SELECT token, id, name, paired_at, last_seen, expires_at, scope, token_hashed, notify_prefs FROM paired_devices") {
             Ok(s) => s,
             Err(_) => return,
         };
         let result: Vec<(String, PairedDevice)> = match stmt.query_map([], |row| {
+            let token: String = row.get(0)?;
+            // Rows whose hash never made it to the secret store stay usable
+            let hash = match row.get::<_, i64>(7)? {
+                2 => hash_for_key.get(&token).cloned().unwrap_or_default(),
+                _ => token,
+            };
             Ok((
-                row.get::<_, String>(0)?,
+                hash,
                 PairedDevice {
                     id: row.get(1)?,
                     name: row.get(2)?,
                     paired_at: row.get(3)?,
                     last_seen: row.get(4)?,
+                    expires_at: row.get(5)?,
+                    // Unknown values fail closed
+                    scope: DeviceScope::parse(&row.get::<_, String>(6)?).unwrap_or(DeviceScope::ReadOnly),
+                    notifications: row
+                        .get::<_, Option<String>>(8)?
+                        .and_then(|json| serde_json::from_str(&json).ok())
+                        .unwrap_or_default(),
                 },
             ))
         }) {
-            Ok(rows) => rows.flatten().collect(),
+            Ok(rows) => rows.flatten().filter(|(hash, _)| !hash.is_empty()).collect(),
             Err(_) => return,
         };
         result
     };
-    let mut devices = PAIRED_DEVICES.lock();
+    *state::get().token_slots.lock() = slots;
+    state::get().token_slots_loaded.store(readable, std::sync::atomic::Ordering::SeqCst);
+    let mut devices = state::get().paired_devices.lock();
     for (token, device) in loaded {
         devices.insert(token, device);
     }
 }
 
-// Save a paired device to database
-fn save_paired_device(token: &str, device: &PairedDevice) -> Result<(), String> {
-    let conn = DB_CONNECTION.lock();
+// Save a paired device to database; its token hash goes to the secret store
+fn save_paired_device(token_hash: &str, device: &PairedDevice) -> Result<(), String> {
+    let key = {
+        let mut slots = state::get().token_slots.lock();
+        match slots.get(token_hash) {
+            Some(key) => key.clone(),
+            None => {
+                ensure_token_slots_loaded(&mut slots)?;
+                let key = generate_token();
+                slots.insert(token_hash.to_string(), key.clone());
+                if let Err(e) = store_token_slots(&slots) {
+                    slots.remove(token_hash);
+                    return Err(e);
+                }
+                key
+            }
+        }
+    };
+    let conn = state::get().db().lock();
+    // A row left keyed by the hash (migration failed) is superseded
+    let _ = conn.execute("DELETE FROM paired_devices WHERE token = ?1", params![token_hash]);
     conn.execute(
-        "INSERT OR REPLACE INTO paired_devices (token, id, name, paired_at, last_seen) VALUES (?1, ?2, ?3, ?4, ?5)",
-        params![token, device.id, device.name, device.paired_at, device.last_seen],
+        "INSERT OR REPLACE INTO paired_devices (token, id, name, paired_at, last_seen, token_hashed, expires_at, scope, notify_prefs) VALUES (?1, ?2, ?3, ?4, ?5, 2, ?6, ?7, ?8)",
+        params![
+            key,
+            device.id,
+            device.name,
+            device.paired_at,
+            device.last_seen,
+            device.expires_at,
+            device.scope.as_str(),
+            serde_json::to_string(&device.notifications).ok(),
+        ],
     ).map_err(|e| e.to_string())?;
     Ok(())
 }
 
-// Delete a paired device from database
-fn delete_paired_device_db(token: &str) -> Result<(), String> {
-    let conn = DB_CONNECTION.lock();
-    conn.execute("DELETE FROM paired_devices WHERE token = ?1", params![token])
+// Delete a paired device from database and the secret store
+fn delete_paired_device_db(token_hash: &str) -> Result<(), String> {
+    let key = {
+        let mut slots = state::get().token_slots.lock();
+        ensure_token_slots_loaded(&mut slots)?;
+        let key = slots.remove(token_hash);
+        if key.is_some() {
+            store_token_slots(&slots)?;
+        }
+        key
+    };
+    let conn = state::get().db().lock();
+    conn.execute("DELETE FROM paired_devices WHERE token = ?1", params![key.as_deref().unwrap_or(token_hash)])
         .map_err(|e| e.to_string())?;
     Ok(())
 }
 
 fn load_scheduled_jobs() -> Result<Vec<ScheduledJob>, String> {
-    let conn = DB_CONNECTION.lock();
+    let conn = state::get().db().lock();
     let mut stmt = conn.prepare(
         "SELECT id, name, cron_expr, prompt, enabled, last_run_at, next_run_at, created_at FROM scheduled_jobs ORDER BY created_at ASC"
     ).map_err(|e| e.to_string())?;
@@ -874,7 +1901,7 @@ fn load_scheduled_jobs() -> Result<Vec<ScheduledJob>, String> {
 }
 
 fn save_scheduled_job(job: &ScheduledJob) -> Result<(), String> {
-    let conn = DB_CONNECTION.lock();
+    let conn = state::get().db().lock();
     conn.execute(
         "INSERT OR REPLACE INTO scheduled_jobs (id, name, cron_expr, prompt, enabled, last_run_at, next_run_at, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
         rusqlite::params![job.id, job.name, job.cron_expr, job.prompt, job.enabled as i64, job.last_run_at, job.next_run_at, job.created_at],
@@ -885,7 +1912,7 @@ fn save_scheduled_job(job: &ScheduledJob) -> Result<(), String> {
 /// Save/clear the running PID for a session
 #[cfg(not(target_os = "ios"))]
 fn save_session_pid(session_id: &str, pid: Option<u32>) {
-    let conn = DB_CONNECTION.lock();
+    let conn = state::get().db().lock();
     let _ = conn.execute(
         "UPDATE sessions SET running_pid = ?1 WHERE id = ?2",
         params![pid.map(|p| p as i64), session_id],
@@ -905,7 +1932,7 @@ fn is_process_running(pid: u32) -> bool {
 fn cleanup_orphaned_processes() {
     // Collect orphaned session data while holding the lock briefly
     let orphans: Vec<(String, u32)> = {
-        let conn = DB_CONNECTION.lock();
+        let conn = state::get().db().lock();
         let mut stmt = match conn.prepare("SELECT id, running_pid FROM sessions WHERE running_pid IS NOT NULL") {
             Ok(s) => s,
             Err(_) => return,
@@ -921,7 +1948,7 @@ fn cleanup_orphaned_processes() {
     // Process orphans without holding the DB lock (kill/sleep can be slow)
     for (session_id, pid) in orphans {
         if is_process_running(pid) {
-            println!("Killing orphaned process for session {}: PID {}", session_id, pid);
+            tracing::info!("Killing orphaned process for session {}: PID {}", session_id, pid);
             unsafe {
                 libc::kill(pid as i32, libc::SIGTERM);
             }
@@ -930,31 +1957,98 @@ fn cleanup_orphaned_processes() {
                 libc::kill(pid as i32, libc::SIGKILL);
             }
         } else {
-            println!("Clearing stale PID {} for session {}", pid, session_id);
+            tracing::info!("Clearing stale PID {} for session {}", pid, session_id);
         }
-        // Clear the PID - this will briefly lock DB_CONNECTION
+        // Clear the PID - this will briefly lock the database
         save_session_pid(&session_id, None);
     }
+    // No agent survives a restart, so none is mid-turn
+    let _ = state::get().db().lock().execute("UPDATE sessions SET processing = 0 WHERE processing = 1", []);
+}
+
+const PAIRING_TTL_SECS: i64 = 300;
+const MAX_PENDING_PAIRINGS: usize = 10;
+const MAX_PAIRING_CODE_ATTEMPTS: u32 = 5;
+
+fn prune_pairing_requests(requests: &mut HashMap<String, PairingRequest>) {
+    let now = chrono::Utc::now();
+    requests.retain(|_, r| (now - r.created_at).num_seconds() < PAIRING_TTL_SECS);
+}
+
+/// Open a pairing request; returns its id and code.
+fn create_pairing_request(device_name: Option<String>) -> Result<(String, String), String> {
+    let mut requests = state::get().pairing_requests.lock();
+    prune_pairing_requests(&mut requests);
+    if requests.len() >= MAX_PENDING_PAIRINGS {
+        return Err("Too many pairing requests in progress. Try again in a few minutes.".to_string());
+    }
+    let pairing_id = generate_token();
+    let code = generate_pairing_code();
+    requests.insert(pairing_id.clone(), PairingRequest {
+        code: code.clone(),
+        created_at: chrono::Utc::now(),
+        device_name,
+        code_verified: false,
+        approved: false,
+        denied: false,
+        failed_attempts: 0,
+    });
+    Ok((pairing_id, code))
+}
+
+/// Approve (with `scope`) or deny a device that has entered its pairing code
+fn decide_pairing(pairing_id: &str, approve: bool, scope: DeviceScope) -> Result<(), String> {
+    let mut requests = state::get().pairing_requests.lock();
+    prune_pairing_requests(&mut requests);
+    let request = requests.get_mut(pairing_id).ok_or("Pairing request has expired")?;
+    if !request.code_verified {
+        return Err("The device hasn't entered the pairing code yet".to_string());
+    }
+    request.approved = approve;
+    request.denied = !approve;
+    request.scope = scope;
+    Ok(())
+}
+
+/// Let a device that entered the right pairing code finish pairing, with
+/// `scope` (read_only, interact or admin)
+#[tauri::command]
+fn approve_pairing(pairing_id: String, scope: String) -> Result<(), String> {
+    let scope = DeviceScope::parse(&scope).ok_or_else(|| format!("Unknown scope '{}'", scope))?;
+    decide_pairing(&pairing_id, true, scope)
+}
+
+/// Refuse a pending pairing request
+#[tauri::command]
+fn deny_pairing(pairing_id: String) -> Result<(), String> {
+    decide_pairing(&pairing_id, false, DeviceScope::ReadOnly)
 }
 
 // Generate a random 6-digit pairing code
 fn generate_pairing_code() -> String {
-    use std::time::{SystemTime, UNIX_EPOCH};
-    let seed = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_nanos();
-    format!("{:06}", (seed % 1_000_000) as u32)
+    // Rejection sampling keeps all codes equally likely
+    loop {
+        let mut buf = [0u8; 4];
+        getrandom::getrandom(&mut buf).expect("OS random number generator unavailable");
+        let n = u32::from_le_bytes(buf);
+        if n < u32::MAX - u32::MAX % 1_000_000 {
+            return format!("{:06}", n % 1_000_000);
+        }
+    }
 }
 
-// Generate a random token for device auth
+// Generate a random token for device auth (256 bits, hex)
 fn generate_token() -> String {
-    use std::time::{SystemTime, UNIX_EPOCH};
-    let seed = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_nanos();
-    format!("{:x}{:x}", seed, seed.wrapping_mul(0x5DEECE66D))
+    let mut buf = [0u8; 32];
+    getrandom::getrandom(&mut buf).expect("OS random number generator unavailable");
+    buf.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Tokens are only kept as SHA-256 hashes, so a leaked database can't be
+// replayed as credentials
+fn hash_token(token: &str) -> String {
+    use sha2::{Digest, Sha256};
+    Sha256::digest(token.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
 }
 
 fn next_run_for_expr(expr: &str) -> Result<String, String> {
@@ -972,17 +2066,208 @@ fn next_run_for_expr(expr: &str) -> Result<String, String> {
         .ok_or_else(|| "No upcoming run time".to_string())
 }
 
+// Expiry for a token issued now; None when tokens don't expire
+fn token_expiry() -> Option<String> {
+    let days = load_app_settings().unwrap_or_default().token_lifetime_days;
+    (days > 0).then(|| (chrono::Utc::now() + chrono::Duration::days(days as i64)).to_rfc3339())
+}
+
+fn is_expired(device: &PairedDevice) -> bool {
+    device
+        .expires_at
+        .as_deref()
+        .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
+        .is_some_and(|t| t < chrono::Utc::now())
+}
+
+// Look up the device a token belongs to; None if unknown or expired
+fn device_for_token(token: &str) -> Option<PairedDevice> {
+    use subtle::ConstantTimeEq;
+    let hash = hash_token(token);
+    let devices = state::get().paired_devices.lock();
+    // Compare against every entry so timing doesn't depend on which matched
+    let mut found = None;
+    for (key, device) in devices.iter() {
+        if bool::from(key.as_bytes().ct_eq(hash.as_bytes())) {
+            found = Some(device);
+        }
+    }
+    found.filter(|d| !is_expired(d)).cloned()
+}
+
 // Check if a token is valid
 fn is_valid_token(token: &str) -> bool {
-    let devices = PAIRED_DEVICES.lock();
-    devices.contains_key(token)
+    device_for_token(token).is_some()
+}
+
+// Issue a token for `device` with a fresh expiry and store its hash
+fn issue_token(mut device: PairedDevice) -> (String, PairedDevice) {
+    let token = generate_token();
+    let token_hash = hash_token(&token);
+    device.expires_at = token_expiry();
+    state::get().paired_devices.lock().insert(token_hash.clone(), device.clone());
+    let _ = save_paired_device(&token_hash, &device);
+    (token, device)
+}
+
+// Forget a token (memory and database)
+fn remove_token(token_hash: &str) {
+    state::get().paired_devices.lock().remove(token_hash);
+    let _ = delete_paired_device_db(token_hash);
+}
+
+/// Close mobile WebSocket connections authenticated as this device
+#[cfg(not(target_os = "ios"))]
+fn disconnect_device(device_id: &str, reason: &'static str) {
+    let mut clients = state::get().mobile_clients.lock();
+    for client in clients.values_mut().filter(|c| c.device_id.as_deref() == Some(device_id)) {
+        if let Some(revoke) = client.revoke.take() {
+            let _ = revoke.send(reason);
+        }
+    }
+}
+
+/// Record activity for a token's device; written to the DB at most once a minute
+fn touch_token(token: &str) {
+    let hash = hash_token(token);
+    let now = chrono::Utc::now();
+    let changed = {
+        let mut devices = state::get().paired_devices.lock();
+        let Some(device) = devices.get_mut(&hash) else { return };
+        let persist = chrono::DateTime::parse_from_rfc3339(&device.last_seen)
+            .map(|t| now.signed_duration_since(t).num_seconds() >= 60)
+            .unwrap_or(true);
+        device.last_seen = now.to_rfc3339();
+        persist.then(|| device.clone())
+    };
+    if let Some(device) = changed {
+        let _ = save_paired_device(&hash, &device);
+    }
+}
+
+/// Paired devices, one entry per device (a device briefly holds two tokens
+/// while refreshing), oldest first
+#[tauri::command]
+fn list_paired_devices() -> Vec<PairedDevice> {
+    let mut by_id: HashMap<String, PairedDevice> = HashMap::new();
+    for device in state::get().paired_devices.lock().values() {
+        let newer = by_id.get(&device.id).is_none_or(|d| device.last_seen > d.last_seen);
+        if newer {
+            by_id.insert(device.id.clone(), device.clone());
+        }
+    }
+    let mut devices: Vec<PairedDevice> = by_id.into_values().collect();
+    devices.sort_by(|a, b| a.paired_at.cmp(&b.paired_at));
+    devices
+}
+
+/// Revoke all of a device's tokens and close its open connections
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn revoke_device(device_id: String) -> Result<(), String> {
+    let hashes: Vec<String> = state::get().paired_devices
+        .lock()
+        .iter()
+        .filter(|(_, d)| d.id == device_id)
+        .map(|(hash, _)| hash.clone())
+        .collect();
+    if hashes.is_empty() {
+        return Err("Device not found".to_string());
+    }
+    for hash in &hashes {
+        remove_token(hash);
+    }
+    {
+        let conn = state::get().db().lock();
+        message_log::delete_acks_for_device(&conn, &device_id);
+        push::delete_for_device(&conn, &device_id);
+    }
+    disconnect_device(&device_id, "revoked");
+    if let Some(app) = state::get().app_handle.lock().as_ref() {
+        let _ = app.emit("device-revoked", &device_id);
+    }
+    Ok(())
+}
+
+/// Change what a device may do; applies to its open connections on reconnect
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn set_device_scope(device_id: String, scope: DeviceScope) -> Result<(), String> {
+    let updated: Vec<(String, PairedDevice)> = {
+        let mut devices = state::get().paired_devices.lock();
+        devices
+            .iter_mut()
+            .filter(|(_, d)| d.id == device_id)
+            .map(|(hash, d)| {
+                d.scope = scope;
+                (hash.clone(), d.clone())
+            })
+            .collect()
+    };
+    if updated.is_empty() {
+        return Err("Device not found".to_string());
+    }
+    for (hash, device) in &updated {
+        save_paired_device(hash, device)?;
+    }
+    // Connections keep the scope they authenticated with; make them reconnect
+    disconnect_device(&device_id, "scope_changed");
+    Ok(())
+}
+
+/// Choose which push notifications a device gets
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn set_device_notify_prefs(device_id: String, prefs: notify_prefs::NotifyPrefs) -> Result<(), String> {
+    prefs.validate()?;
+    let updated: Vec<(String, PairedDevice)> = {
+        let mut devices = state::get().paired_devices.lock();
+        devices
+            .iter_mut()
+            .filter(|(_, d)| d.id == device_id)
+            .map(|(hash, d)| {
+                d.notifications = prefs.clone();
+                (hash.clone(), d.clone())
+            })
+            .collect()
+    };
+    if updated.is_empty() {
+        return Err("Device not found".to_string());
+    }
+    for (hash, device) in &updated {
+        save_paired_device(hash, device)?;
+    }
+    Ok(())
+}
+
+/// A device's notification preferences, None if it isn't paired
+#[cfg(not(target_os = "ios"))]
+fn device_notify_prefs(device_id: &str) -> Option<notify_prefs::NotifyPrefs> {
+    state::get().paired_devices.lock().values().find(|d| d.id == device_id).map(|d| d.notifications.clone())
+}
+
+/// Drop expired tokens and disconnect devices left without a valid one
+#[cfg(not(target_os = "ios"))]
+fn purge_expired_tokens() {
+    let expired: Vec<(String, String)> = state::get().paired_devices
+        .lock()
+        .iter()
+        .filter(|(_, d)| is_expired(d))
+        .map(|(hash, d)| (hash.clone(), d.id.clone()))
+        .collect();
+    for (hash, device_id) in expired {
+        remove_token(&hash);
+        if !state::get().paired_devices.lock().values().any(|d| d.id == device_id) {
+            disconnect_device(&device_id, "token_expired");
+        }
+    }
 }
 
 #[tauri::command]
 fn load_sessions() -> Result<Vec<SessionData>, String> {
-    let conn = DB_CONNECTION.lock();
+    let conn = state::get().db().lock();
     let mut stmt = conn
-        .prepare("SELECT id, name, agent_type, command, working_dir, created_at, claude_session_id, sort_order, folder_id, env_vars FROM sessions ORDER BY sort_order ASC, created_at DESC")
+        .prepare("SELECT id, name, agent_type, command, working_dir, created_at, claude_session_id, sort_order, folder_id, env_vars, parent_id, summary, remote_access, workspace_id FROM sessions ORDER BY sort_order ASC, created_at DESC")
         .map_err(|e| e.to_string())?;
 
     let sessions = stmt
@@ -998,6 +2283,10 @@ fn load_sessions() -> Result<Vec<SessionData>, String> {
                 sort_order: row.get(7)?,
                 folder_id: row.get(8)?,
                 env_vars: row.get(9)?,
+                parent_id: row.get(10)?,
+                summary: row.get(11)?,
+                remote_access: row.get(12)?,
+                workspace_id: row.get(13)?,
             })
         })
         .map_err(|e| e.to_string())?
@@ -1011,7 +2300,7 @@ fn load_sessions() -> Result<Vec<SessionData>, String> {
 fn save_session(session: SessionData) -> Result<(), String> {
     let is_new: bool;
     {
-        let conn = DB_CONNECTION.lock();
+        let conn = state::get().db().lock();
 
         // Check if this is an update or create
         is_new = conn.query_row(
@@ -1020,9 +2309,17 @@ fn save_session(session: SessionData) -> Result<(), String> {
             |row| row.get::<_, i32>(0)
         ).unwrap_or(0) == 0;
 
+        // parent_id is set once at creation; a save that doesn't carry it
+        // (e.g. from an older frontend) keeps the stored value. The summary
+        // is owned by the backend and always carried over, as is remote_access.
+        // workspace_id is carried over the same way; moving a session
+        // between workspaces is set_session_workspace.
         conn.execute(
-            "INSERT OR REPLACE INTO sessions (id, name, agent_type, command, working_dir, created_at, claude_session_id, sort_order, folder_id, env_vars)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            "INSERT OR REPLACE INTO sessions (id, name, agent_type, command, working_dir, created_at, claude_session_id, sort_order, folder_id, env_vars, parent_id, summary, summary_updated_at, remote_access, workspace_id)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, COALESCE(?11, (SELECT parent_id FROM sessions WHERE id = ?1)),
+                     (SELECT summary FROM sessions WHERE id = ?1), (SELECT summary_updated_at FROM sessions WHERE id = ?1),
+                     COALESCE((SELECT remote_access FROM sessions WHERE id = ?1), 1),
+                     COALESCE(?12, (SELECT workspace_id FROM sessions WHERE id = ?1)))",
             params![
                 session.id,
                 session.name,
@@ -1034,9 +2331,16 @@ fn save_session(session: SessionData) -> Result<(), String> {
                 session.sort_order,
                 session.folder_id,
                 session.env_vars,
+                session.parent_id,
+                session.workspace_id,
             ],
         )
         .map_err(|e| e.to_string())?;
+        // Created in a workspace hidden from remote devices: hidden before
+        // it's announced
+        if is_new && workspaces::hides_session(&conn, &session.id) {
+            state::get().remote_hidden_sessions.lock().insert(session.id.clone());
+        }
     }
     // Broadcast after releasing the DB lock
     #[cfg(not(target_os = "ios"))]
@@ -1053,7 +2357,7 @@ fn save_session(session: SessionData) -> Result<(), String> {
 
 #[tauri::command]
 fn update_session_orders(session_orders: Vec<(String, i32)>) -> Result<(), String> {
-    let conn = DB_CONNECTION.lock();
+    let conn = state::get().db().lock();
     for (session_id, sort_order) in session_orders {
         conn.execute(
             "UPDATE sessions SET sort_order = ?1 WHERE id = ?2",
@@ -1067,11 +2371,30 @@ fn update_session_orders(session_orders: Vec<(String, i32)>) -> Result<(), Strin
 #[tauri::command]
 fn delete_session(session_id: String) -> Result<(), String> {
     {
-        let conn = DB_CONNECTION.lock();
+        let conn = state::get().db().lock();
         search::delete_search_data_for_session(&conn, &session_id);
+        #[cfg(not(target_os = "ios"))]
+        {
+            queue::delete_queue_for_session(&conn, &session_id);
+            pipeline::delete_pipelines_for_session(&conn, &session_id);
+            changes::delete_changes_for_session(&conn, &session_id);
+            snapshots::delete_snapshots_for_session(&conn, &session_id);
+            context::forget(&session_id);
+            summaries::forget(&session_id);
+            tts::delete_tts_for_session(&conn, &session_id);
+            chat_notify::delete_chat_notify_for_session(&conn, &session_id);
+            attachments::delete_for_session(&session_id);
+            watchdog::delete_watchdog_for_session(&conn, &session_id);
+            watchdog::forget(&session_id);
+            restarts::forget(&session_id);
+            message_log::delete_log_for_session(&conn, &session_id);
+        }
         conn.execute("DELETE FROM sessions WHERE id = ?1", params![session_id])
             .map_err(|e| e.to_string())?;
     }
+    // Fails a fan-out response the session owed, which takes the DB lock
+    #[cfg(not(target_os = "ios"))]
+    fanout::forget(&session_id);
     // Broadcast after releasing the DB lock
     #[cfg(not(target_os = "ios"))]
     broadcast_session_deleted(&session_id);
@@ -1079,38 +2402,445 @@ fn delete_session(session_id: String) -> Result<(), String> {
     Ok(())
 }
 
-// --- Search commands ---
+// --- Prompt queue commands ---
 
+#[cfg(not(target_os = "ios"))]
 #[tauri::command]
-fn rebuild_search_index() -> Result<search::BackfillStats, String> {
-    Ok(search::rebuild_index())
+fn enqueue_prompt(session_id: String, prompt: String) -> Result<queue::QueuedPrompt, String> {
+    queue::enqueue_prompt(&session_id, &prompt)
 }
 
+#[cfg(not(target_os = "ios"))]
 #[tauri::command]
-fn get_search_index_stats() -> Result<search::SearchStats, String> {
-    Ok(search::get_stats())
+fn list_queue(session_id: String) -> Result<Vec<queue::QueuedPrompt>, String> {
+    queue::list_queue(&session_id)
 }
 
+#[cfg(not(target_os = "ios"))]
 #[tauri::command]
-fn list_orphan_jsonls() -> Result<Vec<search::OrphanJsonl>, String> {
-    Ok(search::list_orphan_jsonls())
+fn reorder_queue(session_id: String, prompt_ids: Vec<String>) -> Result<Vec<queue::QueuedPrompt>, String> {
+    queue::reorder_queue(&session_id, &prompt_ids)
 }
 
+#[cfg(not(target_os = "ios"))]
 #[tauri::command]
-fn import_orphan_jsonls() -> Result<search::ImportStats, String> {
-    Ok(search::import_orphans())
+fn remove_queued_prompt(session_id: String, prompt_id: String) -> Result<(), String> {
+    queue::remove_queued_prompt(&session_id, &prompt_id)
 }
 
-/// Propose links between stranded sessions and unlinked JSONLs by scoring
-/// (cwd, time, name) similarity. Pure read; the UI / caller decides which
-/// proposals to accept.
+// --- Pipeline commands ---
+
+#[cfg(not(target_os = "ios"))]
 #[tauri::command]
-fn propose_session_jsonl_reconciliation() -> Result<search::ReconciliationProposal, String> {
-    Ok(search::propose_reconciliation())
+fn list_pipelines() -> Result<Vec<pipeline::Pipeline>, String> {
+    pipeline::list_pipelines()
 }
 
-/// Apply a previously-proposed reconciliation: re-link sessions, optionally
-/// delete unmatched stranded ones, optionally import unmatched JSONLs as new.
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn create_pipeline(
+    name: String,
+    source_session_id: String,
+    target_session_id: String,
+    prompt_template: String,
+) -> Result<pipeline::Pipeline, String> {
+    pipeline::create_pipeline(name, source_session_id, target_session_id, prompt_template)
+}
+
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn update_pipeline(
+    id: String,
+    name: Option<String>,
+    prompt_template: Option<String>,
+    enabled: Option<bool>,
+) -> Result<pipeline::Pipeline, String> {
+    pipeline::update_pipeline(&id, name, prompt_template, enabled)
+}
+
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn delete_pipeline(id: String) -> Result<(), String> {
+    pipeline::delete_pipeline(&id)
+}
+
+// --- Prompt template commands ---
+
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn list_prompt_templates() -> Result<Vec<prompts::PromptTemplate>, String> {
+    prompts::list_prompts()
+}
+
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn save_prompt_template(
+    id: Option<String>,
+    name: String,
+    description: Option<String>,
+    template: String,
+) -> Result<prompts::PromptTemplate, String> {
+    prompts::save_prompt(id, name, description, template)
+}
+
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn delete_prompt_template(id: String) -> Result<(), String> {
+    prompts::delete_prompt(&id)
+}
+
+// --- Webhook commands ---
+
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn list_webhooks() -> Result<Vec<webhooks::Webhook>, String> {
+    webhooks::list_webhooks()
+}
+
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn save_webhook(
+    id: Option<String>,
+    url: String,
+    secret: Option<String>,
+    events: Vec<String>,
+) -> Result<webhooks::Webhook, String> {
+    webhooks::save_webhook(id, url, secret, events)
+}
+
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn delete_webhook(id: String) -> Result<(), String> {
+    webhooks::delete_webhook(&id)
+}
+
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn list_webhook_deliveries(webhook_id: String) -> Result<Vec<webhooks::Delivery>, String> {
+    webhooks::list_deliveries(&webhook_id)
+}
+
+// --- Fan-out commands ---
+
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn fanout_prompt(session_ids: Vec<String>, prompt: String) -> Result<fanout::FanoutRun, String> {
+    fanout::fanout_prompt(session_ids, prompt)
+}
+
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn get_fanout(id: String) -> Result<fanout::FanoutRun, String> {
+    fanout::get_fanout(&id)
+}
+
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn list_fanouts(limit: Option<u32>) -> Result<Vec<fanout::FanoutRun>, String> {
+    fanout::list_fanouts(limit.unwrap_or(20))
+}
+
+// --- File change commands ---
+
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn get_session_changes(session_id: String) -> Result<Vec<changes::FileChange>, String> {
+    changes::get_session_changes(&session_id)
+}
+
+// --- Turn snapshot commands ---
+
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn set_turn_snapshots(session_id: String, enabled: bool) -> Result<(), String> {
+    snapshots::set_snapshots_enabled(&session_id, enabled)
+}
+
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn get_turn_snapshots_enabled(session_id: String) -> bool {
+    snapshots::snapshots_enabled(&session_id)
+}
+
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn list_turn_snapshots(session_id: String) -> Result<Vec<snapshots::TurnSnapshot>, String> {
+    snapshots::list_turn_snapshots(&session_id)
+}
+
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+async fn revert_to_turn(session_id: String, turn: i64) -> Result<snapshots::TurnSnapshot, String> {
+    tokio::task::spawn_blocking(move || snapshots::revert_to_turn(&session_id, turn))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+// --- Redaction commands ---
+
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn list_redaction_rules() -> Result<Vec<redaction::RedactionRule>, String> {
+    redaction::list_rules()
+}
+
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn add_redaction_rule(name: String, pattern: String) -> Result<redaction::RedactionRule, String> {
+    redaction::add_rule(&name, &pattern)
+}
+
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn delete_redaction_rule(id: i64) -> Result<(), String> {
+    redaction::delete_rule(id)
+}
+
+// --- Context usage commands ---
+
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn get_context_usage(session_id: String) -> Option<context::ContextUsage> {
+    context::get_context_usage(&session_id)
+}
+
+// --- Watchdog commands ---
+
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn set_session_watchdog(session_id: String, policy: String, timeout_mins: Option<u32>) -> Result<(), String> {
+    watchdog::set_config(&session_id, &policy, timeout_mins)
+}
+
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn get_session_watchdog(session_id: String) -> watchdog::WatchdogConfig {
+    watchdog::get_config(&session_id)
+}
+
+// --- Text-to-speech commands ---
+
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn speak_text(text: String, voice: Option<String>) -> Result<(), String> {
+    let voice = voice.or_else(|| load_app_settings().unwrap_or_default().tts_voice);
+    tts::speak_text(&text, voice.as_deref())
+}
+
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn stop_speaking() {
+    tts::stop_speaking();
+}
+
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn set_session_auto_speak(session_id: String, enabled: bool) -> Result<(), String> {
+    tts::set_auto_speak(&session_id, enabled)
+}
+
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn get_session_auto_speak(session_id: String) -> bool {
+    tts::auto_speak_enabled(&session_id)
+}
+
+// --- Global shortcut commands ---
+
+/// Register the saved global_shortcuts, replacing the current ones
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn register_hotkeys(app: tauri::AppHandle) -> Result<(), String> {
+    hotkeys::register(&app, &load_app_settings()?.global_shortcuts)
+}
+
+/// The frontend has saved its terminal buffers after `app-shutting-down`
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn shutdown_ready() {
+    shutdown::frontend_saved();
+}
+
+/// Setup checklist for onboarding: each agent CLI installed and logged in,
+/// the Claude config directory, and the web server listening
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+async fn run_diagnostics() -> Vec<diagnostics::Check> {
+    diagnostics::run().await
+}
+
+// --- Settings profile commands ---
+
+/// The active settings profile and all saved ones
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn list_profiles() -> Result<profiles::Profiles, String> {
+    profiles::list()
+}
+
+/// Make another profile's settings live (creating it from the current
+/// settings if it doesn't exist); applied without a restart
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn switch_profile(name: String) -> Result<(), String> {
+    profiles::switch(name.trim())?;
+    let visible = remote_settings(&load_app_settings()?);
+    broadcast_session_event(events::SessionEvent::SettingsUpdated(visible.clone()));
+    broadcast_to_mobile_clients(&serde_json::json!({
+        "type": "settings_updated",
+        "settings": visible
+    }).to_string());
+    Ok(())
+}
+
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn delete_profile(name: String) -> Result<(), String> {
+    profiles::delete(name.trim())
+}
+
+// --- Workspace commands ---
+
+/// Every workspace with this month's spend
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn list_workspaces() -> Result<Vec<workspaces::Workspace>, String> {
+    workspaces::list()
+}
+
+/// Create (no id) or update a workspace
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn save_workspace(workspace: workspaces::Workspace) -> Result<workspaces::Workspace, String> {
+    let saved = workspaces::save(workspace)?;
+    refresh_remote_hidden()?;
+    Ok(saved)
+}
+
+/// Delete a workspace; its sessions and folders stay, in no workspace
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn delete_workspace(workspace_id: String) -> Result<(), String> {
+    workspaces::delete(&workspace_id)?;
+    refresh_remote_hidden()
+}
+
+/// Make a workspace active (None = all workspaces)
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn switch_workspace(workspace_id: Option<String>) -> Result<(), String> {
+    workspaces::set_active(workspace_id)
+}
+
+/// Move a session to another workspace (None = none)
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn set_session_workspace(session_id: String, workspace_id: Option<String>) -> Result<(), String> {
+    workspaces::move_session(&session_id, workspace_id.as_deref())?;
+    if let Some(session) = load_sessions()?.into_iter().find(|s| s.id == session_id) {
+        broadcast_session_updated(&session);
+    }
+    refresh_remote_hidden()
+}
+
+/// Move a folder and its sessions to another workspace (None = none)
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn set_folder_workspace(folder_id: String, workspace_id: Option<String>) -> Result<(), String> {
+    workspaces::move_folder(&folder_id, workspace_id.as_deref())?;
+    // Into a hidden workspace, the folder is gone as far as remote devices know
+    if workspace_id.as_ref().is_some_and(|w| workspaces::remote_hidden_workspaces().contains(w)) {
+        broadcast_folder_deleted(&folder_id);
+    } else if let Some(folder) = load_folders()?.into_iter().find(|f| f.id == folder_id) {
+        broadcast_folder_changed("folder_updated", &folder);
+    }
+    refresh_remote_hidden()
+}
+
+// --- Menu shortcut commands ---
+
+/// Every menu action with its current and default accelerator
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn get_shortcuts() -> Result<Vec<shortcuts::Shortcut>, String> {
+    Ok(shortcuts::list(&load_app_settings()?.shortcuts))
+}
+
+/// Change one menu shortcut; the menu is rebuilt by watch_settings. An
+/// empty accelerator removes the shortcut; None restores the default.
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn set_shortcut(action: String, accelerator: Option<String>) -> Result<(), String> {
+    let mut settings = load_app_settings()?;
+    shortcuts::set(&mut settings.shortcuts, &action, accelerator.as_deref())?;
+    write_app_settings(&settings)
+}
+
+/// The session open in the main window, for interrupt_active
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn set_active_session(session_id: Option<String>) {
+    if let Some(id) = session_id.as_deref() {
+        attention::clear(id);
+    }
+    hotkeys::set_active_session(session_id);
+}
+
+// --- Deep link commands ---
+
+/// The frontend's deep link listeners are registered
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn deep_links_ready(app: tauri::AppHandle) {
+    deep_link::ready(&app);
+}
+
+// --- Slack / Discord notification commands ---
+
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn set_session_chat_notify(session_id: String, enabled: bool) -> Result<(), String> {
+    chat_notify::set_enabled(&session_id, enabled)
+}
+
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn get_session_chat_notify(session_id: String) -> bool {
+    chat_notify::enabled_for(&session_id)
+}
+
+// --- Search commands ---
+
+#[tauri::command]
+fn rebuild_search_index() -> Result<search::BackfillStats, String> {
+    Ok(search::rebuild_index())
+}
+
+#[tauri::command]
+fn get_search_index_stats() -> Result<search::SearchStats, String> {
+    Ok(search::get_stats())
+}
+
+#[tauri::command]
+fn list_orphan_jsonls() -> Result<Vec<search::OrphanJsonl>, String> {
+    Ok(search::list_orphan_jsonls())
+}
+
+#[tauri::command]
+fn import_orphan_jsonls() -> Result<search::ImportStats, String> {
+    Ok(search::import_orphans())
+}
+
+/// Propose links between stranded sessions and unlinked JSONLs by scoring
+/// (cwd, time, name) similarity. Pure read; the UI / caller decides which
+/// proposals to accept.
+#[tauri::command]
+fn propose_session_jsonl_reconciliation() -> Result<search::ReconciliationProposal, String> {
+    Ok(search::propose_reconciliation())
+}
+
+/// Apply a previously-proposed reconciliation: re-link sessions, optionally
+/// delete unmatched stranded ones, optionally import unmatched JSONLs as new.
 #[tauri::command]
 fn apply_session_jsonl_reconciliation(
     actions: search::ReconciliationActions,
@@ -1178,22 +2908,90 @@ async fn fetch_claude_usage_limits() -> Result<serde_json::Value, String> {
 }
 
 #[tauri::command]
-fn get_message_context(
-    message_id: i64,
-    before: Option<u32>,
-    after: Option<u32>,
-) -> Result<search::MessageContext, String> {
-    search::get_message_context(message_id, before.unwrap_or(3), after.unwrap_or(3))
+fn list_remote_hubs() -> Result<Vec<hubs::RemoteHub>, String> {
+    hubs::list()
 }
 
+/// Register another Agent Hub; `token` is from a device paired with it.
 #[tauri::command]
-fn search_messages(
-    query: String,
-    session_id: Option<String>,
-    role: Option<String>,
-    from_ts: Option<i64>,
-    to_ts: Option<i64>,
-    limit: Option<u32>,
+async fn add_remote_hub(name: String, url: String, token: String) -> Result<hubs::RemoteHub, String> {
+    hubs::add(&name, &url, &token).await
+}
+
+#[tauri::command]
+fn remove_remote_hub(id: String) -> Result<(), String> {
+    hubs::remove(&id)
+}
+
+/// Every session here and on the remote hubs, tagged by host (see hubs.rs)
+#[tauri::command]
+async fn load_hub_sessions() -> Result<serde_json::Value, String> {
+    Ok(hubs::merged_sessions(listed_sessions()?).await)
+}
+
+/// Start a remote hub's session (see start_session for local ones)
+#[tauri::command]
+async fn start_remote_session(hub_id: String, session_id: String) -> Result<(), String> {
+    let hub = hubs::get(&hub_id).ok_or("Hub not found")?;
+    let path = format!("sessions/{}/start", session_id);
+    let (status, _, body) = hubs::forward(&hub, reqwest::Method::POST, &path, None, Vec::new()).await?;
+    if !(200..300).contains(&status) {
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap_or_default();
+        return Err(body["error"].as_str().map(str::to_string).unwrap_or_else(|| format!("{} answered HTTP {}", hub.name, status)));
+    }
+    Ok(())
+}
+
+/// Stream a remote hub's session into this app (see remote_sessions.rs)
+#[tauri::command]
+async fn attach_remote_session(app: AppHandle, hub_id: String, session_id: String, terminal: Option<bool>) -> Result<(), String> {
+    remote_sessions::attach(app, &hub_id, &session_id, terminal.unwrap_or(false)).await
+}
+
+#[tauri::command]
+fn write_remote_session(session_id: String, data: String) -> Result<(), String> {
+    remote_sessions::write(&session_id, &data)
+}
+
+#[tauri::command]
+fn interrupt_remote_session(session_id: String) -> Result<(), String> {
+    remote_sessions::interrupt(&session_id)
+}
+
+#[tauri::command]
+fn resize_remote_session(session_id: String, cols: u16, rows: u16) -> Result<(), String> {
+    remote_sessions::resize(&session_id, cols, rows)
+}
+
+#[tauri::command]
+fn detach_remote_session(session_id: String) {
+    remote_sessions::detach(&session_id)
+}
+
+/// Devices that have the session open; changes arrive as session-presence
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn get_session_viewers(session_id: String) -> Vec<presence::Viewer> {
+    presence::viewers(&session_id)
+}
+
+#[tauri::command]
+fn get_message_context(
+    message_id: i64,
+    before: Option<u32>,
+    after: Option<u32>,
+) -> Result<search::MessageContext, String> {
+    search::get_message_context(message_id, before.unwrap_or(3), after.unwrap_or(3))
+}
+
+#[tauri::command]
+fn search_messages(
+    query: String,
+    session_id: Option<String>,
+    role: Option<String>,
+    from_ts: Option<i64>,
+    to_ts: Option<i64>,
+    limit: Option<u32>,
     offset: Option<u32>,
 ) -> Result<Vec<search::SearchHit>, String> {
     search::search_messages(
@@ -1252,7 +3050,7 @@ fn update_scheduled_job(id: String, name: Option<String>, cron_expr: Option<Stri
 
 #[tauri::command]
 fn delete_scheduled_job(id: String) -> Result<(), String> {
-    let conn = DB_CONNECTION.lock();
+    let conn = state::get().db().lock();
     conn.execute("DELETE FROM scheduled_jobs WHERE id = ?1", [&id])
         .map_err(|e| e.to_string())?;
     Ok(())
@@ -1262,9 +3060,9 @@ fn delete_scheduled_job(id: String) -> Result<(), String> {
 
 #[tauri::command]
 fn load_folders() -> Result<Vec<FolderData>, String> {
-    let conn = DB_CONNECTION.lock();
+    let conn = state::get().db().lock();
     let mut stmt = conn
-        .prepare("SELECT id, name, sort_order, collapsed FROM folders ORDER BY sort_order ASC")
+        .prepare("SELECT id, name, sort_order, collapsed, workspace_id FROM folders ORDER BY sort_order ASC")
         .map_err(|e| e.to_string())?;
 
     let folders = stmt
@@ -1274,6 +3072,7 @@ fn load_folders() -> Result<Vec<FolderData>, String> {
                 name: row.get(1)?,
                 sort_order: row.get(2)?,
                 collapsed: row.get::<_, i32>(3)? != 0,
+                workspace_id: row.get(4)?,
             })
         })
         .map_err(|e| e.to_string())?
@@ -1285,10 +3084,12 @@ fn load_folders() -> Result<Vec<FolderData>, String> {
 
 #[tauri::command]
 fn save_folder(folder: FolderData) -> Result<(), String> {
-    let conn = DB_CONNECTION.lock();
+    let conn = state::get().db().lock();
+    // Like sessions, a save without a workspace_id keeps the stored one
     conn.execute(
-        "INSERT OR REPLACE INTO folders (id, name, sort_order, collapsed) VALUES (?1, ?2, ?3, ?4)",
-        params![folder.id, folder.name, folder.sort_order, folder.collapsed as i32],
+        "INSERT OR REPLACE INTO folders (id, name, sort_order, collapsed, workspace_id)
+         VALUES (?1, ?2, ?3, ?4, COALESCE(?5, (SELECT workspace_id FROM folders WHERE id = ?1)))",
+        params![folder.id, folder.name, folder.sort_order, folder.collapsed as i32, folder.workspace_id],
     )
     .map_err(|e| e.to_string())?;
     Ok(())
@@ -1296,7 +3097,7 @@ fn save_folder(folder: FolderData) -> Result<(), String> {
 
 #[tauri::command]
 fn delete_folder(folder_id: String) -> Result<(), String> {
-    let conn = DB_CONNECTION.lock();
+    let conn = state::get().db().lock();
     // Move sessions in this folder to unfiled
     conn.execute(
         "UPDATE sessions SET folder_id = NULL WHERE folder_id = ?1",
@@ -1311,7 +3112,7 @@ fn delete_folder(folder_id: String) -> Result<(), String> {
 
 #[tauri::command]
 fn update_folder_orders(folder_orders: Vec<(String, i32)>) -> Result<(), String> {
-    let conn = DB_CONNECTION.lock();
+    let conn = state::get().db().lock();
     for (folder_id, sort_order) in folder_orders {
         conn.execute(
             "UPDATE folders SET sort_order = ?1 WHERE id = ?2",
@@ -1324,18 +3125,25 @@ fn update_folder_orders(folder_orders: Vec<(String, i32)>) -> Result<(), String>
 
 #[tauri::command]
 fn update_session_folder(session_id: String, folder_id: Option<String>) -> Result<(), String> {
-    let conn = DB_CONNECTION.lock();
-    conn.execute(
-        "UPDATE sessions SET folder_id = ?1 WHERE id = ?2",
-        params![folder_id, session_id],
-    )
-    .map_err(|e| e.to_string())?;
+    // A session dropped into a folder joins the folder's workspace
+    let workspace_id = folder_id.as_deref().and_then(workspaces::of_folder);
+    state::get().db()
+        .lock()
+        .execute(
+            "UPDATE sessions SET folder_id = ?1, workspace_id = COALESCE(?3, workspace_id) WHERE id = ?2",
+            params![folder_id, session_id, workspace_id],
+        )
+        .map_err(|e| e.to_string())?;
+    #[cfg(not(target_os = "ios"))]
+    if workspace_id.is_some() {
+        refresh_remote_hidden()?;
+    }
     Ok(())
 }
 
 #[tauri::command]
 fn toggle_folder_collapsed(folder_id: String, collapsed: bool) -> Result<(), String> {
-    let conn = DB_CONNECTION.lock();
+    let conn = state::get().db().lock();
     conn.execute(
         "UPDATE folders SET collapsed = ?1 WHERE id = ?2",
         params![collapsed as i32, folder_id],
@@ -1357,7 +3165,7 @@ struct RecentlyClosedData {
 
 #[tauri::command]
 fn save_recently_closed(session: RecentlyClosedData) -> Result<(), String> {
-    let conn = DB_CONNECTION.lock();
+    let conn = state::get().db().lock();
 
     // Insert the newly closed session
     conn.execute(
@@ -1387,7 +3195,7 @@ fn save_recently_closed(session: RecentlyClosedData) -> Result<(), String> {
 
 #[tauri::command]
 fn get_recently_closed() -> Result<Vec<RecentlyClosedData>, String> {
-    let conn = DB_CONNECTION.lock();
+    let conn = state::get().db().lock();
     let mut stmt = conn
         .prepare("SELECT id, name, agent_type, command, working_dir, claude_session_id, closed_at FROM recently_closed ORDER BY closed_at DESC")
         .map_err(|e| e.to_string())?;
@@ -1413,7 +3221,7 @@ fn get_recently_closed() -> Result<Vec<RecentlyClosedData>, String> {
 
 #[tauri::command]
 fn delete_recently_closed(session_id: String) -> Result<(), String> {
-    let conn = DB_CONNECTION.lock();
+    let conn = state::get().db().lock();
     conn.execute("DELETE FROM recently_closed WHERE id = ?1", params![session_id])
         .map_err(|e| e.to_string())?;
     Ok(())
@@ -1423,11 +3231,12 @@ fn delete_recently_closed(session_id: String) -> Result<(), String> {
 #[cfg(not(target_os = "ios"))]
 #[tauri::command]
 fn update_history_menu(sessions: Vec<RecentlyClosedData>) -> Result<(), String> {
-    let app_handle = APP_HANDLE.lock();
+    let app_handle = state::get().app_handle.lock();
     let app = app_handle.as_ref().ok_or("App handle not available")?;
 
     let history_menu_guard = HISTORY_MENU.lock();
     let history_menu = history_menu_guard.as_ref().ok_or("History menu not available")?;
+    let reopen_accel = shortcuts::accelerator(&load_app_settings().unwrap_or_default().shortcuts, "reopen_session");
 
     // Remove all existing items
     if let Ok(items) = history_menu.items() {
@@ -1446,8 +3255,8 @@ fn update_history_menu(sessions: Vec<RecentlyClosedData>) -> Result<(), String>
         for (index, session) in sessions.iter().enumerate() {
             let id = format!("recent_{}", index);
             let label = &session.name;
-            // Add keyboard shortcut for first item (Cmd+Shift+T)
-            let accel = if index == 0 { Some("CmdOrCtrl+Shift+T") } else { None };
+            // The first item takes the reopen shortcut (Cmd+Shift+T by default)
+            let accel = if index == 0 { reopen_accel.clone() } else { None };
             let item = MenuItem::with_id(app, &id, label, true, accel)
                 .map_err(|e| e.to_string())?;
             history_menu.append(&item).map_err(|e| e.to_string())?;
@@ -1457,6 +3266,59 @@ fn update_history_menu(sessions: Vec<RecentlyClosedData>) -> Result<(), String>
     Ok(())
 }
 
+/// A session listed in the Window menu
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SessionMenuEntry {
+    id: String,
+    name: String,
+}
+
+/// Number of fixed items (Minimize, Zoom, separator, Close Window) at the
+/// top of the Window menu, before the sessions
+#[cfg(not(target_os = "ios"))]
+const WINDOW_MENU_FIXED_ITEMS: usize = 4;
+
+/// List sessions at the bottom of the Window menu, in sidebar order; the
+/// first nine get Cmd+1..9
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn update_sessions_menu(sessions: Vec<SessionMenuEntry>) -> Result<(), String> {
+    let app_handle = state::get().app_handle.lock();
+    let app = app_handle.as_ref().ok_or("App handle not available")?;
+
+    let window_menu_guard = WINDOW_MENU.lock();
+    let window_menu = window_menu_guard.as_ref().ok_or("Window menu not available")?;
+
+    // Remove the previous session items, keeping the fixed ones
+    if let Ok(items) = window_menu.items() {
+        for item in items.iter().skip(WINDOW_MENU_FIXED_ITEMS) {
+            let _ = window_menu.remove(item);
+        }
+    }
+
+    if !sessions.is_empty() {
+        let separator = PredefinedMenuItem::separator(app).map_err(|e| e.to_string())?;
+        window_menu.append(&separator).map_err(|e| e.to_string())?;
+        for (index, session) in sessions.iter().enumerate() {
+            let id = format!("go_session:{}", session.id);
+            let accel = (index < 9).then(|| format!("CmdOrCtrl+{}", index + 1));
+            let item = MenuItem::with_id(app, &id, &session.name, true, accel)
+                .map_err(|e| e.to_string())?;
+            window_menu.append(&item).map_err(|e| e.to_string())?;
+        }
+    }
+
+    *SESSIONS_MENU_ENTRIES.lock() = sessions;
+    Ok(())
+}
+
+// Stub for iOS - no menu support
+#[cfg(target_os = "ios")]
+#[tauri::command]
+fn update_sessions_menu(_sessions: Vec<SessionMenuEntry>) -> Result<(), String> {
+    Ok(())
+}
+
 // Stub for iOS - no menu support
 #[cfg(target_os = "ios")]
 #[tauri::command]
@@ -1466,7 +3328,7 @@ fn update_history_menu(_sessions: Vec<RecentlyClosedData>) -> Result<(), String>
 
 #[tauri::command]
 fn update_session_claude_id(session_id: String, claude_session_id: String) -> Result<(), String> {
-    let conn = DB_CONNECTION.lock();
+    let conn = state::get().db().lock();
     conn.execute(
         "UPDATE sessions SET claude_session_id = ?1 WHERE id = ?2",
         params![claude_session_id, session_id],
@@ -1771,6 +3633,11 @@ fn spawn_pty(
     resume_session: Option<bool>,
     env_vars: Option<String>,
 ) -> Result<(), String> {
+    if shutdown::in_progress() {
+        return Err("Agent Hub is shutting down".to_string());
+    }
+    // The session's workspace env vars, under its own
+    let env_vars = workspaces::env_vars_for(&session_id, env_vars);
     let pty_system = native_pty_system();
 
     let pair = pty_system
@@ -1782,9 +3649,10 @@ fn spawn_pty(
         })
         .map_err(|e| e.to_string())?;
 
-    let mut cmd_str = command.unwrap_or_else(|| {
-        std::env::var("SHELL").unwrap_or_else(|_| "/bin/zsh".to_string())
-    });
+    // "$SHELL" (the shell agent) isn't run through a shell to be expanded
+    let mut cmd_str = command
+        .map(|c| c.replace("$SHELL", &platform::user_shell()))
+        .unwrap_or_else(platform::user_shell);
 
     // Handle Claude session resume
     // Only use --resume flag when explicitly resuming an existing session
@@ -1822,17 +3690,11 @@ fn spawn_pty(
         .or_else(|| dirs::home_dir());
 
     // Get user's home directory and shell
-    let home_dir = dirs::home_dir()
-        .map(|p| p.to_string_lossy().to_string())
-        .unwrap_or_else(|| "/Users".to_string());
-    let user_shell = std::env::var("SHELL").unwrap_or_else(|_| "/bin/zsh".to_string());
+    let home_dir = platform::home_dir();
+    let user_shell = platform::user_shell();
 
     // Build PATH with common tool locations (GUI apps have minimal PATH)
-    let existing_path = std::env::var("PATH").unwrap_or_default();
-    let enhanced_path = format!(
-        "{}/.local/bin:{}/.nvm/versions/node/v24.10.0/bin:{}/.cargo/bin:/opt/homebrew/bin:/opt/homebrew/sbin:/usr/local/bin:{}",
-        home_dir, home_dir, home_dir, existing_path
-    );
+    let enhanced_path = platform::agent_path();
 
     // Always use login shell for agent commands to get proper environment
     // This ensures nvm, pyenv, rbenv, etc. are properly initialized
@@ -1852,6 +3714,8 @@ fn spawn_pty(
     cmd.env("HOME", &home_dir);
     cmd.env("PATH", &enhanced_path);
     cmd.env("SHELL", &user_shell);
+    cmd.env("AGENT_HUB_SESSION_ID", &session_id);
+    cmd.env("AGENT_HUB_LOCAL_TOKEN", LOCAL_TOKEN.as_str());
 
     // Apply global CLAUDE_CONFIG_DIR from app settings (if not overridden per-session)
     let custom_envs: std::collections::HashMap<String, String> = env_vars
@@ -1887,25 +3751,33 @@ fn spawn_pty(
     // Capture current time before spawning (for session ID detection)
     let spawn_time = std::time::SystemTime::now();
 
-    let _child = pair
+    let child = pair
         .slave
         .spawn_command(cmd)
-        .map_err(|e| e.to_string())?;
+        .map_err(|e| {
+            tracing::error!("Failed to spawn PTY for session {}: {}", session_id, e);
+            mcp::log(mcp::LogLevel::Error, "session", serde_json::json!({
+                "session_id": session_id,
+                "error": format!("Failed to spawn process: {}", e),
+            }));
+            e.to_string()
+        })?;
 
     let writer = pair.master.take_writer().map_err(|e| e.to_string())?;
     let mut reader = pair.master.try_clone_reader().map_err(|e| e.to_string())?;
 
-    let session = Arc::new(Mutex::new(PtySession { pair, writer }));
+    let child_pid = child.process_id();
+    let session = Arc::new(Mutex::new(PtySession { pair, writer, child_pid }));
 
     // Create broadcast channel for this session (for WebSocket clients)
     let (tx, _rx) = broadcast::channel::<Vec<u8>>(256);
 
     {
-        let mut sessions = PTY_SESSIONS.lock();
+        let mut sessions = state::get().pty_sessions.lock();
         sessions.insert(session_id.clone(), session);
     }
     {
-        let mut broadcasters = PTY_BROADCASTERS.lock();
+        let mut broadcasters = state::get().pty_broadcasters.lock();
         broadcasters.insert(session_id.clone(), tx.clone());
     }
 
@@ -1937,7 +3809,7 @@ fn spawn_pty(
                         session_id_for_detection.clone(),
                         detected_id.clone(),
                     ) {
-                        eprintln!("Failed to update session claude_id in DB: {}", e);
+                        tracing::error!("Failed to update session claude_id in DB: {}", e);
                     }
 
                     // Emit event to frontend
@@ -1977,6 +3849,12 @@ fn spawn_pty(
                 Ok(n) => {
                     let data_bytes = buf[..n].to_vec();
                     let data = String::from_utf8_lossy(&data_bytes).to_string();
+                    term_screen::feed(&session_id_clone, &data);
+                    // Remote viewers get secrets masked; the local terminal doesn't
+                    let ws_bytes = match redaction::redact(&data) {
+                        std::borrow::Cow::Owned(redacted) => redacted.into_bytes(),
+                        std::borrow::Cow::Borrowed(_) => data_bytes,
+                    };
 
                     // Emit to Tauri app
                     let _ = app_clone.emit(
@@ -1988,18 +3866,19 @@ fn spawn_pty(
                     );
 
                     // Broadcast to WebSocket clients
-                    let _ = tx.send(data_bytes);
+                    let _ = tx.send(ws_bytes);
                 }
                 Err(_) => break,
             }
         }
         // Clean up session
         {
-            let mut sessions = PTY_SESSIONS.lock();
+            let mut sessions = state::get().pty_sessions.lock();
             sessions.remove(&session_id_clone);
         }
+        term_screen::forget(&session_id_clone);
         {
-            let mut broadcasters = PTY_BROADCASTERS.lock();
+            let mut broadcasters = state::get().pty_broadcasters.lock();
             broadcasters.remove(&session_id_clone);
         }
         // Notify WebSocket clients that session stopped
@@ -2012,7 +3891,7 @@ fn spawn_pty(
 #[cfg(not(target_os = "ios"))]
 #[tauri::command]
 fn write_pty(session_id: String, data: String) -> Result<(), String> {
-    let sessions = PTY_SESSIONS.lock();
+    let sessions = state::get().pty_sessions.lock();
     if let Some(session) = sessions.get(&session_id) {
         let mut session = session.lock();
         session
@@ -2029,7 +3908,7 @@ fn write_pty(session_id: String, data: String) -> Result<(), String> {
 #[cfg(not(target_os = "ios"))]
 #[tauri::command]
 fn resize_pty(session_id: String, cols: u16, rows: u16) -> Result<(), String> {
-    let sessions = PTY_SESSIONS.lock();
+    let sessions = state::get().pty_sessions.lock();
     if let Some(session) = sessions.get(&session_id) {
         let session = session.lock();
         session
@@ -2051,7 +3930,7 @@ fn resize_pty(session_id: String, cols: u16, rows: u16) -> Result<(), String> {
 #[cfg(not(target_os = "ios"))]
 #[tauri::command]
 fn kill_pty(session_id: String) -> Result<(), String> {
-    let mut sessions = PTY_SESSIONS.lock();
+    let mut sessions = state::get().pty_sessions.lock();
     sessions.remove(&session_id);
     Ok(())
 }
@@ -2073,10 +3952,18 @@ fn spawn_json_process(
     env_vars: Option<String>,
 ) -> Result<(), String> {
     use std::process::Stdio;
-    use std::sync::mpsc;
     use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
     use tokio::process::Command;
 
+    if shutdown::in_progress() {
+        return Err("Agent Hub is shutting down".to_string());
+    }
+    if command.trim().is_empty() {
+        return Err("Empty command".to_string());
+    }
+    // The session's workspace env vars, under its own
+    let env_vars = workspaces::env_vars_for(&session_id, env_vars);
+
     // Build the command with resume flag if needed
     let mut cmd_str = command;
     if let Some(ref claude_id) = claude_session_id {
@@ -2088,6 +3975,18 @@ fn spawn_json_process(
         }
     }
 
+    // Give the agent Agent Hub's own tools (ask the user, request approval...)
+    if !cmd_str.contains("--mcp-config") {
+        match mcp::session_config(&session_id) {
+            Ok(path) => {
+                let quoted = path.to_string_lossy().replace('\'', "'\\''");
+                let flag = format!("claude --mcp-config '{}' ", quoted);
+                cmd_str = cmd_str.replacen("claude ", &flag, 1);
+            }
+            Err(e) => tracing::warn!("[mcp] No MCP config for {}: {}", session_id, e),
+        }
+    }
+
     let work_dir = working_dir
         .map(|d| shellexpand::tilde(&d).to_string())
         .unwrap_or_else(|| std::env::var("HOME").unwrap_or_else(|_| "/".to_string()));
@@ -2095,290 +3994,388 @@ fn spawn_json_process(
     // Create channel for stdin
     let (stdin_tx, mut stdin_rx) = tokio::sync::mpsc::channel::<String>(100);
 
-    // Create channel to signal when process is ready (registered in JSON_PROCESSES)
-    let (ready_tx, ready_rx) = mpsc::channel::<Result<(), String>>();
+    // Create broadcast channel for WebSocket clients
+    let (broadcast_tx, _rx) = broadcast::channel::<String>(256);
+
+    // Registered before the process exists, so WebSocket connections find the
+    // session and prompts queue in stdin_tx while it starts. child_id stays 0
+    // until then (kill_json_process leaves it alone).
+    state::get().json_processes.lock().insert(session_id.clone(), JsonProcess {
+        stdin: stdin_tx.clone(),
+        child_id: 0,
+    });
+    state::get().json_broadcasters.lock().insert(session_id.clone(), broadcast_tx.clone());
+    broadcast_session_startup(events::SessionEvent::SessionStarting { session_id: session_id.clone() });
 
     let session_id_clone = session_id.clone();
     let app_clone = app.clone();
 
-    // Spawn the process in a tokio task
-    std::thread::spawn(move || {
-        let rt = tokio::runtime::Runtime::new().expect("Failed to create runtime");
-        rt.block_on(async move {
-            // Check for empty command
-            if cmd_str.trim().is_empty() {
-                let _ = app_clone.emit("json-process-error", serde_json::json!({
-                    "session_id": session_id_clone,
-                    "error": "Empty command"
-                }));
-                let _ = ready_tx.send(Err("Empty command".to_string()));
-                return;
+    // Spawn the process in a task on the shared runtime
+    state::spawn(async move {
+        session_errors::reset(&session_id_clone);
+
+        // Parse custom environment variables (JSON string of key-value pairs)
+        let custom_envs: std::collections::HashMap<String, String> = env_vars
+            .as_deref()
+            .and_then(|s| serde_json::from_str(s).ok())
+            .unwrap_or_default();
+
+        // claude is exec'd directly in the environment captured from the
+        // user's login shell (see agent_env.rs). Anything else, or if that
+        // isn't available, goes through an interactive login shell as GUI
+        // apps don't inherit the user's shell PATH (see platform.rs):
+        // -i sources ~/.zshrc / ~/.bashrc (where nvm is typically configured)
+        // -l sources ~/.zprofile / ~/.profile (login files)
+        let agent_env = state::blocking(agent_env::get);
+        let direct = agent_env.and_then(|env| {
+            let claude = env.claude.as_deref().filter(|path| path.exists())?;
+            agent_env::direct_command(&cmd_str, claude).map(|direct| (env, direct))
+        });
+        let mut cmd = match direct {
+            Some((env, direct)) => {
+                let mut cmd = Command::new("/bin/sh");
+                cmd.args(["-c", &direct]).envs(env.vars.iter().map(|(k, v)| (k, v)));
+                cmd
             }
-
-            // Use an interactive shell to ensure PATH includes user-installed tools like nvm
-            // GUI apps on macOS don't inherit the user's shell PATH
-            // -i sources ~/.zshrc (where nvm is typically configured)
-            // -l sources ~/.zprofile (login files)
-            let shell = std::env::var("SHELL").unwrap_or_else(|_| "/bin/zsh".to_string());
-
-            // Parse custom environment variables (JSON string of key-value pairs)
-            let custom_envs: std::collections::HashMap<String, String> = env_vars
-                .as_deref()
-                .and_then(|s| serde_json::from_str(s).ok())
-                .unwrap_or_default();
-
-            let mut cmd = Command::new(&shell);
-            cmd.args(&["-i", "-l", "-c", &cmd_str])
-                .current_dir(&work_dir)
-                .stdin(Stdio::piped())
-                .stdout(Stdio::piped())
-                .stderr(Stdio::piped());
-
-            // Apply global CLAUDE_CONFIG_DIR from app settings (if not overridden per-session)
-            if !custom_envs.contains_key("CLAUDE_CONFIG_DIR") {
-                if let Some(ref config_dir) = load_app_settings().unwrap_or_default().claude_config_dir {
-                    if !config_dir.is_empty() {
-                        let expanded = shellexpand::tilde(config_dir).to_string();
-                        cmd.env("CLAUDE_CONFIG_DIR", &expanded);
-                    }
-                }
+            None => {
+                let mut cmd = Command::new(platform::user_shell());
+                cmd.args(["-i", "-l", "-c", &cmd_str]).env("PATH", platform::agent_path());
+                cmd
             }
-
-            // Apply custom environment variables (per-session, can override global)
-            // A value of "default" means "don't set this env var" (use the tool's built-in default)
-            for (key, value) in &custom_envs {
-                if value.eq_ignore_ascii_case("default") || value == "~" {
-                    cmd.env_remove(key);
-                } else {
-                    let expanded = shellexpand::tilde(&value).to_string();
-                    cmd.env(key, &expanded);
+        };
+        // Own process group, so shutdown can stop the CLI and anything it started
+        cmd.process_group(0)
+            .current_dir(&work_dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .env("AGENT_HUB_SESSION_ID", &session_id_clone)
+            .env("AGENT_HUB_LOCAL_TOKEN", LOCAL_TOKEN.as_str());
+
+        // Apply global CLAUDE_CONFIG_DIR from app settings (if not overridden per-session)
+        if !custom_envs.contains_key("CLAUDE_CONFIG_DIR") {
+            if let Some(ref config_dir) = load_app_settings().unwrap_or_default().claude_config_dir {
+                if !config_dir.is_empty() {
+                    let expanded = shellexpand::tilde(config_dir).to_string();
+                    cmd.env("CLAUDE_CONFIG_DIR", &expanded);
                 }
             }
+        }
 
-            let mut child = match cmd.spawn()
-            {
-                Ok(c) => c,
-                Err(e) => {
-                    let err_msg = format!("Failed to spawn process: {}", e);
-                    let _ = app_clone.emit("json-process-error", serde_json::json!({
-                        "session_id": session_id_clone,
-                        "error": &err_msg
-                    }));
-                    let _ = ready_tx.send(Err(err_msg));
-                    return;
-                }
-            };
+        // Apply custom environment variables (per-session, can override global)
+        // A value of "default" means "don't set this env var" (use the tool's built-in default)
+        for (key, value) in &custom_envs {
+            if value.eq_ignore_ascii_case("default") || value == "~" {
+                cmd.env_remove(key);
+            } else {
+                let expanded = shellexpand::tilde(&value).to_string();
+                cmd.env(key, &expanded);
+            }
+        }
 
-            let child_id = child.id().unwrap_or(0);
+        let mut child = match cmd.spawn()
+        {
+            Ok(c) => c,
+            Err(e) => {
+                let err_msg = format!("Failed to spawn process: {}", e);
+                tracing::error!("Failed to spawn JSON process for session {}: {}", session_id_clone, e);
+                unregister_json_process(&session_id_clone, &stdin_tx, &broadcast_tx);
+                let _ = app_clone.emit("json-process-error", serde_json::json!({
+                    "session_id": session_id_clone,
+                    "error": &err_msg
+                }));
+                session_errors::report_spawn_failure(&session_id_clone, &err_msg);
+                return;
+            }
+        };
 
-            // Take ownership of stdin/stdout/stderr
-            let mut stdin = child.stdin.take().expect("Failed to get stdin");
-            let stdout = child.stdout.take().expect("Failed to get stdout");
-            let stderr = child.stderr.take().expect("Failed to get stderr");
+        let child_id = child.id().unwrap_or(0);
+        tracing::info!("Started JSON process for session {} (PID {})", session_id_clone, child_id);
 
-            // Create broadcast channel for WebSocket clients
-            let (broadcast_tx, _rx) = broadcast::channel::<String>(256);
+        // Take ownership of stdin/stdout/stderr
+        let mut stdin = child.stdin.take().expect("Failed to get stdin");
+        let stdout = child.stdout.take().expect("Failed to get stdout");
+        let stderr = child.stderr.take().expect("Failed to get stderr");
 
-            // Store the process handle and broadcast channel
-            {
-                let mut processes = JSON_PROCESSES.lock();
-                processes.insert(session_id_clone.clone(), JsonProcess {
-                    stdin: stdin_tx.clone(),
-                    child_id,
-                });
-            }
-            {
-                let mut broadcasters = JSON_BROADCASTERS.lock();
-                broadcasters.insert(session_id_clone.clone(), broadcast_tx.clone());
+        // Stopped (or started again) while it was starting
+        let ours = match state::get().json_processes.lock().get_mut(&session_id_clone) {
+            Some(process) if process.stdin.same_channel(&stdin_tx) => {
+                process.child_id = child_id;
+                true
             }
+            _ => false,
+        };
+        if !ours {
+            tracing::info!("JSON session {} was stopped while starting", session_id_clone);
+            unregister_json_process(&session_id_clone, &stdin_tx, &broadcast_tx);
+            let _ = child.kill().await;
+            let _ = app_clone.emit("json-process-exit", serde_json::json!({
+                "session_id": session_id_clone,
+                "exit_code": null
+            }));
+            broadcast_session_status(&session_id_clone, false);
+            return;
+        }
 
-            // Signal that process is ready - WebSocket connections can now find it
-            let _ = ready_tx.send(Ok(()));
+        // Save the PID to database for crash recovery
+        save_session_pid(&session_id_clone, Some(child_id));
 
-            // Save the PID to database for crash recovery
-            save_session_pid(&session_id_clone, Some(child_id));
+        // Notify that process started
+        let _ = app_clone.emit("json-process-started", serde_json::json!({
+            "session_id": session_id_clone
+        }));
+        broadcast_session_startup(events::SessionEvent::SessionReady { session_id: session_id_clone.clone() });
+
+        // Notify WebSocket clients that session started
+        broadcast_session_status(&session_id_clone, true);
+
+        // Kick off any prompts queued while the session was stopped
+        queue::on_process_started(&session_id_clone);
+        restarts::on_started(&session_id_clone);
+
+        // Spawn task to handle stdin
+        let session_id_stdin = session_id_clone.clone();
+        tokio::spawn(async move {
+            while let Some(data) = stdin_rx.recv().await {
+                // Snapshot the workspace before the agent sees a new prompt
+                if snapshots::is_user_prompt(&data) {
+                    watchdog::on_prompt(&session_id_stdin);
+                    broadcast_processing_status(&session_id_stdin, true);
+                    let sid = session_id_stdin.clone();
+                    let _ = tokio::task::spawn_blocking(move || snapshots::before_user_turn(&sid)).await;
+                }
+                if let Err(e) = stdin.write_all(data.as_bytes()).await {
+                    tracing::error!("Error writing to stdin for {}: {}", session_id_stdin, e);
+                    break;
+                }
+                if let Err(e) = stdin.flush().await {
+                    tracing::error!("Error flushing stdin for {}: {}", session_id_stdin, e);
+                    break;
+                }
+            }
+        });
 
-            // Notify that process started
-            let _ = app_clone.emit("json-process-started", serde_json::json!({
-                "session_id": session_id_clone
-            }));
+        // Spawn task to handle stdout
+        let app_stdout = app_clone.clone();
+        let session_id_stdout = session_id_clone.clone();
+        let broadcast_stdout = broadcast_tx.clone();
+        tokio::spawn(async move {
+            let mut reader = BufReader::new(stdout).lines();
+            while let Ok(Some(line)) = reader.next_line().await {
+                // Mask secrets before the line is buffered, persisted or broadcast
+                let line = redaction::redact_output(&line).into_owned();
+                // Partial output only previews the reply on mobile; the
+                // complete assistant message follows and is what's kept
+                if let Some(text) = parse_stream_event(&line) {
+                    watchdog::on_output(&session_id_stdout, false);
+                    if !text.is_empty() {
+                        broadcast_to_session_subscribers(&session_id_stdout, &serde_json::json!({
+                            "type": "agent_delta",
+                            "sessionId": session_id_stdout,
+                            "text": text,
+                        }).to_string());
+                    }
+                    let _ = broadcast_stdout.send(line + "\n");
+                    continue;
+                }
+                // Parse JSON and emit structured message (new event)
+                // This offloads JSON parsing from the frontend
+                if let Some(parsed) = parse_claude_json(&line) {
+                    // Update context usage before the status broadcasts below
+                    if context::on_message(&session_id_stdout, &parsed).is_some() {
+                        let threshold = load_app_settings().unwrap_or_default().context_warning_pct;
+                        context::check_threshold(&session_id_stdout, threshold);
+                    }
 
-            // Notify WebSocket clients that session started
-            broadcast_session_status(&session_id_clone, true);
+                    // Detect processing state changes
+                    let is_result = parsed.msg_type == "result";
+                    watchdog::on_output(&session_id_stdout, is_result);
+                    summaries::on_message(&session_id_stdout, is_result);
+                    match parsed.msg_type.as_str() {
+                        "assistant" => {
+                            broadcast_processing_status(&session_id_stdout, true);
+                            changes::record_file_changes(&session_id_stdout, &parsed);
+                        }
+                        "result" => {
+                            broadcast_processing_status(&session_id_stdout, false);
+                        }
+                        _ => {}
+                    }
 
-            // Spawn task to handle stdin
-            let session_id_stdin = session_id_clone.clone();
-            tokio::spawn(async move {
-                while let Some(data) = stdin_rx.recv().await {
-                    if let Err(e) = stdin.write_all(data.as_bytes()).await {
-                        eprintln!("Error writing to stdin for {}: {}", session_id_stdin, e);
-                        break;
+                    // Track message in backend-side buffer
+                    if let Ok(msg_value) = serde_json::to_value(&parsed) {
+                        append_session_message(&session_id_stdout, msg_value);
                     }
-                    if let Err(e) = stdin.flush().await {
-                        eprintln!("Error flushing stdin for {}: {}", session_id_stdin, e);
-                        break;
+                    // Save to DB on result messages (conversation turn complete)
+                    if is_result {
+                        save_session_messages_to_db(&session_id_stdout);
                     }
-                }
-            });
 
-            // Spawn task to handle stdout
-            let app_stdout = app_clone.clone();
-            let session_id_stdout = session_id_clone.clone();
-            let broadcast_stdout = broadcast_tx.clone();
-            tokio::spawn(async move {
-                let mut reader = BufReader::new(stdout).lines();
-                while let Ok(Some(line)) = reader.next_line().await {
-                    // Parse JSON and emit structured message (new event)
-                    // This offloads JSON parsing from the frontend
-                    if let Some(parsed) = parse_claude_json(&line) {
-                        // Detect processing state changes
-                        let is_result = parsed.msg_type == "result";
-                        match parsed.msg_type.as_str() {
-                            "assistant" => {
-                                broadcast_processing_status(&session_id_stdout, true);
-                            }
-                            "result" => {
-                                broadcast_processing_status(&session_id_stdout, false);
-                            }
-                            _ => {}
-                        }
+                    // Emit pre-parsed message to Tauri frontend
+                    let _ = app_stdout.emit("json-process-message", serde_json::json!({
+                        "session_id": session_id_stdout,
+                        "message": parsed
+                    }));
 
-                        // Track message in backend-side buffer
-                        if let Ok(msg_value) = serde_json::to_value(&parsed) {
-                            append_session_message(&session_id_stdout, msg_value);
-                        }
-                        // Save to DB on result messages (conversation turn complete)
-                        if is_result {
-                            save_session_messages_to_db(&session_id_stdout);
-                        }
+                    // Broadcast to mobile WebSocket subscribers (pre-parsed)
+                    broadcast_chat_message(&session_id_stdout, &parsed);
 
-                        // Emit pre-parsed message to Tauri frontend
-                        let _ = app_stdout.emit("json-process-message", serde_json::json!({
-                            "session_id": session_id_stdout,
-                            "message": parsed
-                        }));
+                    // Broadcast to legacy WebSocket clients (raw string for backward compat)
+                    let data = line.clone() + "\n";
+                    let _ = broadcast_stdout.send(data);
 
-                        // Broadcast to mobile WebSocket subscribers (pre-parsed)
-                        let msg = serde_json::json!({
-                            "type": "chat_message",
-                            "sessionId": session_id_stdout,
-                            "message": parsed
-                        }).to_string();
-                        broadcast_to_session_subscribers(&session_id_stdout, &msg);
-
-                        // Broadcast to legacy WebSocket clients (raw string for backward compat)
-                        let data = line.clone() + "\n";
-                        let _ = broadcast_stdout.send(data);
-                    } else {
-                        // Failed to parse - emit raw line for debugging
-                        eprintln!("Failed to parse Claude JSON: {}", &line);
-                        let data = line + "\n";
-                        let _ = app_stdout.emit("json-process-output", serde_json::json!({
-                            "session_id": session_id_stdout,
-                            "data": &data
-                        }));
-                        let _ = broadcast_stdout.send(data);
+                    if is_result {
+                        on_turn_complete(&session_id_stdout, &parsed);
                     }
+                } else {
+                    // Failed to parse - emit raw line for debugging
+                    watchdog::on_output(&session_id_stdout, false);
+                    tracing::error!("Failed to parse Claude JSON: {}", &line);
+                    let data = line + "\n";
+                    let _ = app_stdout.emit("json-process-output", serde_json::json!({
+                        "session_id": session_id_stdout,
+                        "data": &data
+                    }));
+                    let _ = broadcast_stdout.send(data);
                 }
-            });
-
-            // Spawn task to handle stderr (usually non-JSON debug output)
-            let app_stderr = app_clone.clone();
-            let session_id_stderr = session_id_clone.clone();
-            let broadcast_stderr = broadcast_tx.clone();
-            tokio::spawn(async move {
-                let mut reader = BufReader::new(stderr).lines();
-                while let Ok(Some(line)) = reader.next_line().await {
-                    // Try to parse as JSON first (some errors come as JSON)
-                    if let Some(parsed) = parse_claude_json(&line) {
-                        // Track in backend-side buffer
-                        if let Ok(msg_value) = serde_json::to_value(&parsed) {
-                            append_session_message(&session_id_stderr, msg_value);
-                        }
+            }
+        });
 
-                        let _ = app_stderr.emit("json-process-message", serde_json::json!({
-                            "session_id": session_id_stderr,
-                            "message": parsed
-                        }));
-                        let msg = serde_json::json!({
-                            "type": "chat_message",
-                            "sessionId": session_id_stderr,
-                            "message": parsed
-                        }).to_string();
-                        broadcast_to_session_subscribers(&session_id_stderr, &msg);
-                        let data = line.clone() + "\n";
-                        let _ = broadcast_stderr.send(data);
-                    } else {
-                        // Non-JSON stderr - emit as raw output
-                        let data = line + "\n";
-                        let _ = app_stderr.emit("json-process-output", serde_json::json!({
-                            "session_id": session_id_stderr,
-                            "data": &data
-                        }));
-                        let _ = broadcast_stderr.send(data);
+        // Spawn task to handle stderr (usually non-JSON debug output)
+        let app_stderr = app_clone.clone();
+        let session_id_stderr = session_id_clone.clone();
+        let broadcast_stderr = broadcast_tx.clone();
+        tokio::spawn(async move {
+            let mut reader = BufReader::new(stderr).lines();
+            while let Ok(Some(line)) = reader.next_line().await {
+                let line = redaction::redact_output(&line).into_owned();
+                session_errors::report_if_known(&session_id_stderr, &line);
+
+                // Try to parse as JSON first (some errors come as JSON)
+                if let Some(parsed) = parse_claude_json(&line) {
+                    // Track in backend-side buffer
+                    if let Ok(msg_value) = serde_json::to_value(&parsed) {
+                        append_session_message(&session_id_stderr, msg_value);
                     }
-                }
-            });
 
-            // Wait for process to exit
-            match child.wait().await {
-                Ok(status) => {
-                    let _ = app_clone.emit("json-process-exit", serde_json::json!({
-                        "session_id": session_id_clone,
-                        "exit_code": status.code()
+                    let _ = app_stderr.emit("json-process-message", serde_json::json!({
+                        "session_id": session_id_stderr,
+                        "message": parsed
                     }));
-                }
-                Err(e) => {
-                    let _ = app_clone.emit("json-process-error", serde_json::json!({
-                        "session_id": session_id_clone,
-                        "error": format!("Process error: {}", e)
+                    broadcast_chat_message(&session_id_stderr, &parsed);
+                    let data = line.clone() + "\n";
+                    let _ = broadcast_stderr.send(data);
+                } else {
+                    // Non-JSON stderr - emit as raw output
+                    let data = line + "\n";
+                    let _ = app_stderr.emit("json-process-output", serde_json::json!({
+                        "session_id": session_id_stderr,
+                        "data": &data
                     }));
+                    let _ = broadcast_stderr.send(data);
                 }
             }
+        });
 
-            // Save any remaining messages to DB before cleanup
-            save_session_messages_to_db(&session_id_clone);
-
-            // Clean up
-            {
-                let mut processes = JSON_PROCESSES.lock();
-                processes.remove(&session_id_clone);
-            }
-            {
-                let mut broadcasters = JSON_BROADCASTERS.lock();
-                broadcasters.remove(&session_id_clone);
-            }
-            // Clear in-memory message buffer (already saved to DB)
-            {
-                let mut messages = SESSION_MESSAGES.lock();
-                messages.remove(&session_id_clone);
+        // Wait for process to exit
+        let mut exit_code = None;
+        match child.wait().await {
+            Ok(status) => {
+                exit_code = Some(status.code());
+                session_errors::report_exit_code(&session_id_clone, status.code());
+                let _ = app_clone.emit("json-process-exit", serde_json::json!({
+                    "session_id": session_id_clone,
+                    "exit_code": status.code()
+                }));
             }
-            {
-                let mut loaded = SESSION_MESSAGES_LOADED.lock();
-                loaded.remove(&session_id_clone);
+            Err(e) => {
+                let _ = app_clone.emit("json-process-error", serde_json::json!({
+                    "session_id": session_id_clone,
+                    "error": format!("Process error: {}", e)
+                }));
             }
-            // Clear the PID from database
-            save_session_pid(&session_id_clone, None);
-            // Notify WebSocket clients that session stopped
-            broadcast_session_status(&session_id_clone, false);
-        });
+        }
+
+        // Save any remaining messages to DB before cleanup
+        save_session_messages_to_db(&session_id_clone);
+
+        // Clean up
+        unregister_json_process(&session_id_clone, &stdin_tx, &broadcast_tx);
+        // Clear in-memory message buffer (already saved to DB)
+        {
+            let mut messages = state::get().session_messages.lock();
+            messages.remove(&session_id_clone);
+        }
+        {
+            let mut loaded = state::get().session_messages_loaded.lock();
+            loaded.remove(&session_id_clone);
+        }
+        state::get().session_messages_saved.lock().remove(&session_id_clone);
+        // Clear the PID from database
+        save_session_pid(&session_id_clone, None);
+        queue::on_process_exited(&session_id_clone);
+        fanout::forget(&session_id_clone);
+        watchdog::forget(&session_id_clone);
+        // A process that dies mid-turn never sends its result
+        if is_session_processing(&session_id_clone) {
+            broadcast_processing_status(&session_id_clone, false);
+        }
+        // Notify WebSocket clients that session stopped
+        broadcast_session_status(&session_id_clone, false);
+        if let Some(code) = exit_code {
+            restarts::on_exit(&session_id_clone, code);
+        }
     });
 
-    // Wait for the process to be ready (registered in JSON_PROCESSES)
-    // This ensures WebSocket connections can find the session immediately
-    // Timeout after 10 seconds to avoid blocking forever
-    match ready_rx.recv_timeout(std::time::Duration::from_secs(10)) {
-        Ok(Ok(())) => Ok(()),
-        Ok(Err(e)) => Err(e),
-        Err(_) => Err("Timeout waiting for process to start".to_string()),
+    Ok(())
+}
+
+/// Drop a JSON process and its output channel, unless the session has
+/// since been started again and they're the new process's
+#[cfg(not(target_os = "ios"))]
+fn unregister_json_process(
+    session_id: &str,
+    stdin: &tokio::sync::mpsc::Sender<String>,
+    output: &broadcast::Sender<String>,
+) {
+    let mut processes = state::get().json_processes.lock();
+    if processes.get(session_id).is_some_and(|process| process.stdin.same_channel(stdin)) {
+        processes.remove(session_id);
+    }
+    drop(processes);
+    let mut broadcasters = state::get().json_broadcasters.lock();
+    if broadcasters.get(session_id).is_some_and(|sender| sender.same_channel(output)) {
+        broadcasters.remove(session_id);
+    }
+}
+
+/// A JSON session starting (SessionStarting) or running (SessionReady), to
+/// the desktop, status subscribers and mobile clients
+#[cfg(not(target_os = "ios"))]
+fn broadcast_session_startup(event: events::SessionEvent) {
+    let msg = serde_json::json!({
+        "type": event.name(),
+        "sessionId": event.session_id(),
+    })
+    .to_string();
+    if let Some(app) = state::get().app_handle.lock().as_ref() {
+        let _ = app.emit(&event.name().replace('_', "-"), event.data());
     }
+    broadcast_session_event(event);
+    broadcast_to_mobile_clients(&msg);
 }
 
 /// Write data to a JSON process stdin
 #[cfg(not(target_os = "ios"))]
 #[tauri::command]
 fn write_to_process(session_id: String, data: String) -> Result<(), String> {
-    let processes = JSON_PROCESSES.lock();
+    // New prompts stop once the session's workspace has spent its budget
+    let is_prompt = serde_json::from_str::<serde_json::Value>(data.trim())
+        .is_ok_and(|v| v.get("type").and_then(|t| t.as_str()) == Some("user"));
+    if is_prompt {
+        workspaces::check_budget(&session_id)?;
+    }
+    let processes = state::get().json_processes.lock();
     if let Some(process) = processes.get(&session_id) {
         process.stdin.try_send(data.clone())
             .map_err(|e| format!("Failed to send to stdin: {}", e))?;
@@ -2387,7 +4384,7 @@ fn write_to_process(session_id: String, data: String) -> Result<(), String> {
         // so they can see messages typed on desktop
         drop(processes); // Release lock before acquiring another
         if let Some(tx) = {
-            let broadcasters = JSON_BROADCASTERS.lock();
+            let broadcasters = state::get().json_broadcasters.lock();
             broadcasters.get(&session_id).cloned()
         } {
             let _ = tx.send(data);
@@ -2399,127 +4396,308 @@ fn write_to_process(session_id: String, data: String) -> Result<(), String> {
     }
 }
 
-/// Interrupt a JSON process by sending SIGINT
+/// Send a plain-text prompt to a running claude-json session on the user's
+/// behalf (queued prompts, automation). Records it in the backend message
+/// buffer and mirrors it to the desktop and subscribed mobile clients the
+/// same way a typed message shows up.
 #[cfg(not(target_os = "ios"))]
-#[tauri::command]
-fn interrupt_json_process(session_id: String) -> Result<(), String> {
-    let processes = JSON_PROCESSES.lock();
-    if let Some(process) = processes.get(&session_id) {
-        if process.child_id > 0 {
-            // Send SIGINT to the process
-            unsafe {
-                libc::kill(process.child_id as i32, libc::SIGINT);
-            }
+fn send_user_prompt(session_id: &str, text: &str) -> Result<(), String> {
+    send_user_content(session_id, serde_json::json!([{ "type": "text", "text": text }]))
+}
+
+/// Like `send_user_prompt`, with arbitrary message content blocks (images).
+#[cfg(not(target_os = "ios"))]
+fn send_user_content(session_id: &str, content: serde_json::Value) -> Result<(), String> {
+    let envelope = serde_json::json!({
+        "type": "user",
+        "message": {
+            "role": "user",
+            "content": content
+        }
+    });
+    write_to_process(session_id.to_string(), envelope.to_string() + "\n")?;
+    append_session_message(session_id, envelope.clone());
+
+    if let Ok(parsed) = serde_json::from_value::<ClaudeJsonMessage>(envelope) {
+        if let Some(app) = state::get().app_handle.lock().as_ref() {
+            let _ = app.emit("json-process-message", serde_json::json!({
+                "session_id": session_id,
+                "message": parsed
+            }));
         }
+        broadcast_chat_message(session_id, &parsed);
     }
     Ok(())
 }
 
-/// Kill a JSON process
+/// A claude-json turn finished (its `result` message arrived). Feeds the
+/// automation that waits on turn boundaries.
 #[cfg(not(target_os = "ios"))]
-#[tauri::command]
-fn kill_json_process(session_id: String) -> Result<(), String> {
-    let mut processes = JSON_PROCESSES.lock();
-    if let Some(process) = processes.remove(&session_id) {
-        // Kill the process using its PID
-        unsafe {
-            libc::kill(process.child_id as i32, libc::SIGTERM);
+fn on_turn_complete(session_id: &str, result: &ClaudeJsonMessage) {
+    let is_error = result.is_error.unwrap_or(false);
+    if is_error {
+        if let Some(text) = &result.result {
+            session_errors::report_if_known(session_id, text);
         }
-        // Give it a moment to terminate gracefully, then force kill
-        std::thread::spawn(move || {
-            std::thread::sleep(std::time::Duration::from_millis(500));
-            unsafe {
-                libc::kill(process.child_id as i32, libc::SIGKILL);
-            }
-        });
     }
-    Ok(())
+    if let Some(cost) = result.total_cost_usd {
+        workspaces::record_cost(session_id, cost);
+    }
+    // Fan-out first so a queued follow-up can't be mistaken for its answer
+    fanout::on_turn_complete(session_id, result);
+    queue::on_turn_complete(session_id, is_error);
+    pipeline::on_turn_complete(session_id, result.result.clone(), is_error);
+    tts::on_turn_complete(session_id, result.result.as_deref(), is_error);
+    chat_notify::on_turn_complete(
+        session_id,
+        is_error,
+        result.duration_ms,
+        result.total_cost_usd,
+        result.result.as_deref(),
+    );
+    push::on_turn_complete(session_id, result.result.as_deref(), is_error);
+    desktop_notify::on_turn_complete(session_id, result.result.as_deref(), is_error);
+    mcp::notify_session_event(session_id, "result", serde_json::json!({
+        "is_error": is_error,
+        "result": result.result
+    }));
+    webhooks::dispatch("result", session_id, serde_json::json!({
+        "is_error": is_error,
+        "result": result.result
+    }));
 }
 
-/// Internal: Compress and save buffer content to the database only (no SESSION_MESSAGES update)
-fn save_terminal_buffer_to_db(session_id: &str, buffer_content: &str) -> Result<(), String> {
-    // Compress the buffer content using gzip
-    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
-    encoder
-        .write_all(buffer_content.as_bytes())
-        .map_err(|e| format!("Failed to compress buffer: {}", e))?;
-    let compressed = encoder
-        .finish()
-        .map_err(|e| format!("Failed to finish compression: {}", e))?;
-
-    // Encode to base64 for safe text storage
-    let encoded = BASE64.encode(&compressed);
-
-    let conn = DB_CONNECTION.lock();
-    let now = chrono::Utc::now().to_rfc3339();
-
-    conn.execute(
-        "INSERT OR REPLACE INTO terminal_buffers (session_id, buffer_data, updated_at)
-         VALUES (?1, ?2, ?3)",
-        params![session_id, encoded, now],
-    )
-    .map_err(|e| e.to_string())?;
-
+/// Start a stopped claude-json session, resuming its Claude conversation when
+/// one is recorded. No-op if the session is already running.
+#[cfg(not(target_os = "ios"))]
+fn ensure_json_session_running(session_id: &str) -> Result<(), String> {
+    if state::get().json_processes.lock().contains_key(session_id) {
+        return Ok(());
+    }
+    let session = load_sessions()?
+        .into_iter()
+        .find(|s| s.id == session_id)
+        .ok_or_else(|| "Session not found".to_string())?;
+    if session.agent_type != "claude-json" {
+        return Err(format!("Session {} is not a claude-json session", session_id));
+    }
+    let app = state::get().app_handle.lock().clone().ok_or_else(|| "App not initialized".to_string())?;
+    let should_resume = session.claude_session_id.is_some();
+    spawn_json_process(
+        app.clone(),
+        session.id.clone(),
+        session.command,
+        Some(session.working_dir),
+        session.claude_session_id,
+        Some(should_resume),
+        session.env_vars,
+    )?;
+    let _ = app.emit("remote-session-started", session.id);
     Ok(())
 }
 
-/// Compress and save terminal buffer content to the database
-/// Called from the desktop frontend. Also updates in-memory SESSION_MESSAGES
+/// Create a claude-json session as a child of `parent_id`, so a coordinating
+/// agent can hand work to a sibling agent in another directory. The child
+/// joins the parent's folder and, if `prompt` is given, is started with it.
+#[cfg(not(target_os = "ios"))]
+fn create_child_session(
+    parent_id: &str,
+    working_dir: &str,
+    name: Option<String>,
+    prompt: Option<String>,
+) -> Result<SessionData, String> {
+    let parent = load_sessions()?
+        .into_iter()
+        .find(|s| s.id == parent_id)
+        .ok_or_else(|| "Parent session not found".to_string())?;
+    let expanded = shellexpand::tilde(working_dir).to_string();
+    if !std::path::Path::new(&expanded).is_dir() {
+        return Err(format!("Working directory does not exist: {}", working_dir));
+    }
+
+    let session_name = name.filter(|n| !n.trim().is_empty()).unwrap_or_else(|| {
+        let dir_name = std::path::Path::new(&expanded)
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| working_dir.to_string());
+        format!("{} → {}", parent.name, dir_name)
+    });
+
+    let session = SessionData {
+        id: generate_token(),
+        name: session_name,
+        agent_type: "claude-json".to_string(),
+        command: agent_commands::command_for("claude-json")
+            .unwrap_or_else(|| agent_commands::CLAUDE_JSON_COMMAND.to_string()),
+        working_dir: working_dir.to_string(),
+        created_at: chrono::Utc::now().to_rfc3339(),
+        claude_session_id: None,
+        // Same sort slot as the parent keeps the child listed next to it
+        sort_order: parent.sort_order,
+        folder_id: parent.folder_id.clone(),
+        env_vars: parent.env_vars.clone(),
+        parent_id: Some(parent.id.clone()),
+        summary: None,
+        remote_access: true,
+        workspace_id: parent.workspace_id.clone(),
+    };
+    save_session(session.clone())?;
+
+    if let Some(app) = state::get().app_handle.lock().as_ref() {
+        let _ = app.emit("remote-session-created", serde_json::json!({
+            "session": {
+                "id": session.id,
+                "name": session.name,
+                "agent_type": session.agent_type,
+                "working_dir": session.working_dir,
+                "parent_id": session.parent_id,
+            }
+        }));
+    }
+
+    if let Some(prompt) = prompt.filter(|p| !p.trim().is_empty()) {
+        ensure_json_session_running(&session.id)?;
+        send_user_prompt(&session.id, &prompt)?;
+    }
+    Ok(session)
+}
+
+/// Interrupt a JSON process by sending SIGINT
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn interrupt_json_process(session_id: String) -> Result<(), String> {
+    restarts::mark_stopped(&session_id);
+    let processes = state::get().json_processes.lock();
+    if let Some(process) = processes.get(&session_id) {
+        if process.child_id > 0 {
+            // Send SIGINT to the process
+            unsafe {
+                libc::kill(process.child_id as i32, libc::SIGINT);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Kill a JSON process
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn kill_json_process(session_id: String) -> Result<(), String> {
+    restarts::mark_stopped(&session_id);
+    let mut processes = state::get().json_processes.lock();
+    // No PID (0) would signal our own process group
+    if let Some(process) = processes.remove(&session_id).filter(|p| p.child_id > 0) {
+        // Kill the process using its PID
+        unsafe {
+            libc::kill(process.child_id as i32, libc::SIGTERM);
+        }
+        // Give it a moment to terminate gracefully, then force kill
+        std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(500));
+            unsafe {
+                libc::kill(process.child_id as i32, libc::SIGKILL);
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Internal: Compress and save buffer content to the database only (no session_messages update)
+fn save_terminal_buffer_to_db(session_id: &str, buffer_content: &str) -> Result<(), String> {
+    #[cfg(not(target_os = "ios"))]
+    let redacted = redaction::redact_output(buffer_content);
+    #[cfg(not(target_os = "ios"))]
+    let buffer_content: &str = &redacted;
+
+    // Compressed before taking the database lock
+    let encoded = buffer_store::encode(buffer_content)?;
+
+    #[cfg(not(target_os = "ios"))]
+    let started = std::time::Instant::now();
+    buffer_store::write(&state::get().db().lock(), session_id, &encoded)?;
+    #[cfg(not(target_os = "ios"))]
+    metrics::observe_db_write(started.elapsed());
+    #[cfg(not(target_os = "ios"))]
+    shutdown::journal_clear(session_id);
+
+    Ok(())
+}
+
+/// Compress and save terminal buffer content to the database
+/// Called from the desktop frontend. Also updates in-memory session_messages
 /// to stay in sync (desktop has the most complete view of messages).
 #[tauri::command]
 fn save_terminal_buffer(session_id: String, buffer_content: String) -> Result<(), String> {
-    // Update in-memory SESSION_MESSAGES when desktop saves (desktop has the most complete view)
+    // Update in-memory session_messages when desktop saves (desktop has the most complete view)
     #[cfg(not(target_os = "ios"))]
     {
         let db_messages = parse_buffer_to_messages(&buffer_content);
         if !db_messages.is_empty() {
-            let mut messages = SESSION_MESSAGES.lock();
+            let count = db_messages.len();
+            let mut messages = state::get().session_messages.lock();
             messages.insert(session_id.clone(), db_messages);
-            let mut loaded = SESSION_MESSAGES_LOADED.lock();
+            let mut loaded = state::get().session_messages_loaded.lock();
             loaded.insert(session_id.clone());
+            state::get().session_messages_saved.lock().insert(session_id.clone(), count);
         }
     }
 
     save_terminal_buffer_to_db(&session_id, &buffer_content)
 }
 
+/// Internal: Redact and append output to the end of the saved buffer.
+/// Returns the bytes appended since it was last saved whole.
+fn append_terminal_buffer_to_db(session_id: &str, chunk: &str) -> Result<u64, String> {
+    // Line by line, so appended messages are each redacted as JSON
+    #[cfg(not(target_os = "ios"))]
+    let redacted: String = chunk.split_inclusive('\n').map(redaction::redact_output).collect();
+    #[cfg(not(target_os = "ios"))]
+    let chunk: &str = &redacted;
+
+    #[cfg(not(target_os = "ios"))]
+    let started = std::time::Instant::now();
+    let appended = buffer_store::append(state::get().db(), session_id, chunk)?;
+    #[cfg(not(target_os = "ios"))]
+    metrics::observe_db_write(started.elapsed());
+    #[cfg(not(target_os = "ios"))]
+    shutdown::journal_clear(session_id);
+
+    Ok(appended)
+}
+
+/// Append output to the saved terminal buffer, rather than saving the whole
+/// scrollback again. Returns true once enough has been appended that the
+/// buffer should be saved whole (save_terminal_buffer), which trims it.
+#[tauri::command]
+fn append_terminal_buffer(session_id: String, chunk: String) -> Result<bool, String> {
+    append_terminal_buffer_to_db(&session_id, &chunk).map(|appended| appended > buffer_store::REWRITE_AFTER)
+}
+
 /// Load and decompress terminal buffer content from the database
 /// Returns the raw terminal content to be written to xterm.js
 #[tauri::command]
 fn load_terminal_buffer(session_id: String) -> Result<Option<String>, String> {
-    let conn = DB_CONNECTION.lock();
-
-    let result: Result<String, _> = conn.query_row(
-        "SELECT buffer_data FROM terminal_buffers WHERE session_id = ?1",
-        params![session_id],
-        |row| row.get(0),
-    );
-
-    match result {
-        Ok(encoded) => {
-            // Decode from base64
-            let compressed = BASE64
-                .decode(&encoded)
-                .map_err(|e| format!("Failed to decode buffer: {}", e))?;
-
-            // Decompress using gzip
-            let mut decoder = GzDecoder::new(&compressed[..]);
-            let mut decompressed = String::new();
-            decoder
-                .read_to_string(&mut decompressed)
-                .map_err(|e| format!("Failed to decompress buffer: {}", e))?;
+    Ok(buffer_store::load(state::get().db(), &session_id)?.map(fold_appended_messages))
+}
 
-            Ok(Some(decompressed))
+/// A chat buffer that has had messages appended (save_session_messages_to_db)
+/// is a JSON array then a message per line; readers get the one array back.
+/// Anything else is returned as it is.
+fn fold_appended_messages(buffer: String) -> String {
+    let folded = buffer.split_once('\n').filter(|(first, _)| first.starts_with('[')).and_then(|(first, rest)| {
+        let mut messages: Vec<serde_json::Value> = serde_json::from_str(first).ok()?;
+        for line in rest.lines().filter(|line| !line.trim().is_empty()) {
+            messages.push(serde_json::from_str(line).ok()?);
         }
-        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
-        Err(e) => Err(e.to_string()),
-    }
+        serde_json::to_string(&messages).ok()
+    });
+    folded.unwrap_or(buffer)
 }
 
 /// Delete terminal buffer when session is deleted
 #[tauri::command]
 fn delete_terminal_buffer(session_id: String) -> Result<(), String> {
-    let conn = DB_CONNECTION.lock();
+    let conn = state::get().db().lock();
     conn.execute(
         "DELETE FROM terminal_buffers WHERE session_id = ?1",
         params![session_id],
@@ -2530,11 +4708,137 @@ fn delete_terminal_buffer(session_id: String) -> Result<(), String> {
 
 /// Get the web server port (for frontend to know which port to use for remote access)
 #[tauri::command]
-fn get_web_server_port() -> Result<Option<u16>, String> {
-    let port = WEB_SERVER_PORT.lock();
+fn get_web_server_port(state: tauri::State<'_, Arc<state::AppState>>) -> Result<Option<u16>, String> {
+    let port = state.web_server_port.lock();
     Ok(*port)
 }
 
+/// Certificate the web server is using; None when it serves plain HTTP
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn get_web_server_cert() -> Option<tls::CertInfo> {
+    tls::active_cert()
+}
+
+#[cfg(not(target_os = "ios"))]
+#[derive(Debug, Clone, Serialize)]
+struct PairingQr {
+    /// Web UI link with the pairing id and code; opening it pairs the device
+    url: String,
+    pairing_id: String,
+    code: String,
+    svg: String,
+    expires_in: u64,
+}
+
+/// Start a one-time pairing request and encode a link to it as a QR code
+#[cfg(not(target_os = "ios"))]
+fn create_pairing_qr() -> Result<PairingQr, String> {
+    let base = lan_base_url()?;
+    let (pairing_id, code) = create_pairing_request(None)?;
+
+    let url = format!("{}/?pair={}&code={}", base, pairing_id, code);
+    let svg = qr::QrCode::encode(url.as_bytes())
+        .ok_or("Pairing link is too long for a QR code")?
+        .to_svg();
+    Ok(PairingQr { url, pairing_id, code, svg, expires_in: PAIRING_TTL_SECS as u64 })
+}
+
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn generate_pairing_qr() -> Result<PairingQr, String> {
+    create_pairing_qr()
+}
+
+/// The web server's address on this machine's primary network interface
+#[cfg(not(target_os = "ios"))]
+fn lan_base_url() -> Result<String, String> {
+    let port = (*state::get().web_server_port.lock()).ok_or("Web server is not running")?;
+    let ip = match local_ip_address::local_ip().map_err(|e| format!("No network address: {}", e))? {
+        std::net::IpAddr::V6(v6) => format!("[{}]", v6),
+        ip => ip.to_string(),
+    };
+    let scheme = if tls::active_cert().is_some() { "https" } else { "http" };
+    Ok(format!("{}://{}:{}", scheme, ip, port))
+}
+
+#[cfg(not(target_os = "ios"))]
+#[derive(Serialize)]
+struct ShareLinkInfo {
+    #[serde(flatten)]
+    link: share::ShareLink,
+    url: String,
+}
+
+/// URL for a share link, through the tunnel when one is up so guests off
+/// the LAN can open it
+#[cfg(not(target_os = "ios"))]
+fn share_link_info(link: share::ShareLink, token: &str) -> Result<ShareLinkInfo, String> {
+    let base = match tunnel::status().public_url {
+        Some(url) => url,
+        None => lan_base_url()?,
+    };
+    let url = format!("{}/share/{}", base, token);
+    Ok(ShareLinkInfo { link, url })
+}
+
+/// Create a single-use link letting someone watch (or, unless `read_only`,
+/// also type into) a session until it expires
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn create_share_link(session_id: String, ttl_secs: Option<u64>, read_only: Option<bool>) -> Result<ShareLinkInfo, String> {
+    let exists: bool = state::get().db()
+        .lock()
+        .query_row("SELECT COUNT(*) > 0 FROM sessions WHERE id = ?1", params![session_id], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    if !exists {
+        return Err("Session not found".to_string());
+    }
+    if is_remote_hidden(&session_id) {
+        return Err("Session is hidden from remote devices".to_string());
+    }
+    let (link, token) = share::create(
+        &session_id,
+        ttl_secs.unwrap_or(share::DEFAULT_TTL_SECS),
+        read_only.unwrap_or(true),
+    )?;
+    share_link_info(link, &token)
+}
+
+/// Share links that are still usable or in use, optionally for one session
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn list_share_links(session_id: Option<String>) -> Result<Vec<ShareLinkInfo>, String> {
+    share::list(session_id.as_deref())?
+        .into_iter()
+        .map(|link| {
+            let token = share::token(&link)?;
+            share_link_info(link, &token)
+        })
+        .collect()
+}
+
+/// Revoke a share link, disconnecting the guest if they're watching
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn revoke_share_link(id: String) -> Result<(), String> {
+    share::revoke(&id)
+}
+
+/// Public URL of the remote-access tunnel, if one is configured
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn get_tunnel_status() -> tunnel::TunnelStatus {
+    tunnel::status()
+}
+
+/// Browse the LAN for other Agent Hub instances (blocks for a few seconds)
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+async fn discover_instances() -> Result<Vec<discovery::Instance>, String> {
+    tokio::task::spawn_blocking(discovery::discover).await.map_err(|e| e.to_string())
+}
+
 /// Get local IP addresses for remote access URL display
 #[tauri::command]
 fn get_local_ips() -> Vec<String> {
@@ -2576,17 +4880,13 @@ fn get_local_ips() -> Vec<String> {
 }
 
 fn get_config_path() -> PathBuf {
-    let data_dir = dirs::data_dir()
-        .unwrap_or_else(|| PathBuf::from("."))
-        .join(get_app_data_dir_name());
+    let data_dir = platform::data_dir();
     std::fs::create_dir_all(&data_dir).ok();
     data_dir.join("config.json")
 }
 
 fn get_window_state_path() -> PathBuf {
-    let data_dir = dirs::data_dir()
-        .unwrap_or_else(|| PathBuf::from("."))
-        .join(get_app_data_dir_name());
+    let data_dir = platform::data_dir();
     std::fs::create_dir_all(&data_dir).ok();
     data_dir.join("window_state.json")
 }
@@ -2619,25 +4919,127 @@ fn load_window_state() -> Result<WindowState, String> {
 /// Save app settings to config file
 #[tauri::command]
 fn save_app_settings(settings: AppSettings) -> Result<(), String> {
+    validate_app_settings(&settings)?;
+    write_app_settings(&settings)
+}
+
+/// Refuse settings that can't be applied (see watch_settings)
+fn validate_app_settings(settings: &AppSettings) -> Result<(), String> {
+    if let Some(addr) = settings.web_bind_address.as_deref().map(str::trim).filter(|a| !a.is_empty() && *a != "localhost") {
+        addr.parse::<std::net::IpAddr>().map_err(|_| format!("'{}' is not an IP address", addr))?;
+    }
+    if let Some(origin) = settings.cors_allowed_origins.iter().find(|o| !o.starts_with("http://") && !o.starts_with("https://")) {
+        return Err(format!("Allowed origin '{}' must start with http:// or https://", origin));
+    }
+    allowlist::Allowlist::parse(&settings.ip_allowlist)?;
+    #[cfg(not(target_os = "ios"))]
+    shortcuts::validate(&settings.shortcuts)?;
+    Ok(())
+}
+
+/// Apply saved settings to the running app - whichever way they were saved
+/// (settings modal, PATCH /api/settings, set_shortcut, switch_profile) -
+/// without a restart
+#[cfg(not(target_os = "ios"))]
+fn watch_settings() {
+    let mut rx = state::get().settings.subscribe();
+    tauri::async_runtime::spawn(async move {
+        let mut previous = rx.borrow_and_update().clone();
+        while rx.changed().await.is_ok() {
+            let current = rx.borrow_and_update().clone();
+            if let (Some(previous), Some(current)) = (&previous, &current) {
+                apply_settings_change(previous, current);
+            }
+            previous = current;
+        }
+    });
+}
+
+#[cfg(not(target_os = "ios"))]
+fn apply_settings_change(previous: &AppSettings, current: &AppSettings) {
+    // Network settings: rebind the web server
+    if current.web_server_enabled != previous.web_server_enabled
+        || current.web_bind_address != previous.web_bind_address
+        || current.cors_allowed_origins != previous.cors_allowed_origins
+        || current.ip_allowlist != previous.ip_allowlist
+    {
+        restart_web_server();
+    }
+    if current.shortcuts != previous.shortcuts {
+        if let Err(e) = rebuild_menu(&current.shortcuts) {
+            tracing::warn!("[settings] rebuilding menu: {}", e);
+        }
+    }
+    if current.global_shortcuts != previous.global_shortcuts {
+        if let Some(app) = state::get().app_handle.lock().clone() {
+            if let Err(e) = hotkeys::register(&app, &current.global_shortcuts) {
+                tracing::warn!("[hotkeys] {}", e);
+            }
+        }
+    }
+}
+
+/// Set or clear (None / empty) the remote access PIN
+#[tauri::command]
+fn set_remote_pin(pin: Option<String>) -> Result<(), String> {
+    match pin.as_deref().map(str::trim).filter(|p| !p.is_empty()) {
+        Some(p) => {
+            pin::validate(p)?;
+            secrets::set(secrets::REMOTE_PIN, &pin::hash(p)?)?;
+        }
+        None => secrets::delete(secrets::REMOTE_PIN)?,
+    }
+    state::get().settings.send_modify(|settings| {
+        if let Some(settings) = settings {
+            settings.remote_pin = secrets::get(secrets::REMOTE_PIN);
+        }
+    });
+    Ok(())
+}
+
+fn write_app_settings(settings: &AppSettings) -> Result<(), String> {
     let path = get_config_path();
-    let json = serde_json::to_string_pretty(&settings)
+    // The PIN hash lives in the secret store, whatever the caller passes
+    let on_disk = AppSettings { remote_pin: None, ..settings.clone() };
+    let json = serde_json::to_string_pretty(&on_disk)
         .map_err(|e| format!("Failed to serialize settings: {}", e))?;
     std::fs::write(&path, json)
         .map_err(|e| format!("Failed to write settings: {}", e))?;
+    state::get().settings.send_replace(Some(AppSettings { remote_pin: secrets::get(secrets::REMOTE_PIN), ..settings.clone() }));
+    #[cfg(not(target_os = "ios"))]
+    redaction::reload();
     Ok(())
 }
 
-/// Load app settings from config file
+/// Current app settings; config.json is only read the first time
 #[tauri::command]
 fn load_app_settings() -> Result<AppSettings, String> {
+    if let Some(settings) = state::get().settings.borrow().as_ref() {
+        return Ok(settings.clone());
+    }
+    let settings = read_app_settings()?;
+    state::get().settings.send_replace(Some(settings.clone()));
+    Ok(settings)
+}
+
+fn read_app_settings() -> Result<AppSettings, String> {
     let path = get_config_path();
-    if !path.exists() {
-        return Ok(AppSettings::default());
+    let mut settings = if path.exists() {
+        let json = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read settings: {}", e))?;
+        serde_json::from_str::<AppSettings>(&json)
+            .map_err(|e| format!("Failed to parse settings: {}", e))?
+    } else {
+        AppSettings::default()
+    };
+    // Older versions kept the PIN in the clear in config.json; hash it and
+    // move it to the secret store
+    if let Some(stored) = settings.remote_pin.take() {
+        let hash = if pin::is_hashed(&stored) { stored } else { pin::hash(&stored)? };
+        secrets::set(secrets::REMOTE_PIN, &hash)?;
+        write_app_settings(&settings)?;
     }
-    let json = std::fs::read_to_string(&path)
-        .map_err(|e| format!("Failed to read settings: {}", e))?;
-    let settings: AppSettings = serde_json::from_str(&json)
-        .map_err(|e| format!("Failed to parse settings: {}", e))?;
+    settings.remote_pin = secrets::get(secrets::REMOTE_PIN);
     Ok(settings)
 }
 
@@ -2719,6 +5121,13 @@ fn find_latest_plan_file() -> Result<String, String> {
         .ok_or("No plan files found".to_string())
 }
 
+/// Answer an agent's question or approval request from the desktop
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn answer_question(ask_id: String, text: Option<String>, approved: Option<bool>) -> Result<(), String> {
+    asks::answer(&ask_id, asks::Answer { text, approved })
+}
+
 /// MCP callback - receives results from JS execution
 #[cfg(not(target_os = "ios"))]
 #[tauri::command]
@@ -2726,69 +5135,91 @@ fn mcp_callback(request_id: String, result: String) {
     mcp::resolve_mcp_request(request_id, result);
 }
 
+/// Replace the menu with one built from `overrides`, restoring the session
+/// and History items the new one starts without
 #[cfg(not(target_os = "ios"))]
-fn create_menu(app: &AppHandle) -> tauri::Result<Menu<tauri::Wry>> {
-    // App menu (macOS standard - has Quit)
+fn rebuild_menu(overrides: &HashMap<String, String>) -> Result<(), String> {
+    let app = state::get().app_handle.lock().clone().ok_or("App handle not available")?;
+    let menu = create_menu(&app, overrides).map_err(|e| e.to_string())?;
+    app.set_menu(menu).map_err(|e| e.to_string())?;
+    let listed = SESSIONS_MENU_ENTRIES.lock().clone();
+    update_sessions_menu(listed)?;
+    update_history_menu(get_recently_closed()?)
+}
+
+#[cfg(not(target_os = "ios"))]
+fn create_menu(app: &AppHandle, overrides: &HashMap<String, String>) -> tauri::Result<Menu<tauri::Wry>> {
+    let accel = |action: &str| shortcuts::accelerator(overrides, action);
+    // GTK has no app menu and doesn't implement the predefined Quit, Undo /
+    // Redo, Full Screen or window items, so elsewhere Settings and Quit go in
+    // File and the rest are plain items handled in on_menu_event
+    let mac = cfg!(target_os = "macos");
+
     let about_text = format!("About {}", APP_NAME);
     let hide_text = format!("Hide {}", APP_NAME);
     let quit_text = format!("Quit {}", APP_NAME);
 
-    let settings = MenuItem::with_id(app, "settings", "Settings...", true, Some("CmdOrCtrl+,"))?;
+    let settings = MenuItem::with_id(app, "settings", "Settings...", true, accel("settings"))?;
 
-    let app_menu = Submenu::with_items(
-        app,
-        APP_NAME,
-        true,
-        &[
-            &PredefinedMenuItem::about(app, Some(&about_text), None)?,
-            &PredefinedMenuItem::separator(app)?,
-            &settings,
-            &PredefinedMenuItem::separator(app)?,
-            &PredefinedMenuItem::services(app, Some("Services"))?,
-            &PredefinedMenuItem::separator(app)?,
-            &PredefinedMenuItem::hide(app, Some(&hide_text))?,
-            &PredefinedMenuItem::hide_others(app, Some("Hide Others"))?,
-            &PredefinedMenuItem::show_all(app, Some("Show All"))?,
-            &PredefinedMenuItem::separator(app)?,
-            &PredefinedMenuItem::quit(app, Some(&quit_text))?,
-        ],
-    )?;
+    // App menu (macOS standard - has Quit)
+    let app_menu = if mac {
+        Some(Submenu::with_items(
+            app,
+            APP_NAME,
+            true,
+            &[
+                &PredefinedMenuItem::about(app, Some(&about_text), None)?,
+                &PredefinedMenuItem::separator(app)?,
+                &settings,
+                &PredefinedMenuItem::separator(app)?,
+                &PredefinedMenuItem::services(app, Some("Services"))?,
+                &PredefinedMenuItem::separator(app)?,
+                &PredefinedMenuItem::hide(app, Some(&hide_text))?,
+                &PredefinedMenuItem::hide_others(app, Some("Hide Others"))?,
+                &PredefinedMenuItem::show_all(app, Some("Show All"))?,
+                &PredefinedMenuItem::separator(app)?,
+                &PredefinedMenuItem::quit(app, Some(&quit_text))?,
+            ],
+        )?)
+    } else {
+        None
+    };
 
     // File menu
-    let new_session = MenuItem::with_id(app, "new_session", "New Session", true, Some("CmdOrCtrl+T"))?;
-    let close_session = MenuItem::with_id(app, "close_session", "Close Session", true, Some("CmdOrCtrl+W"))?;
+    let new_session = MenuItem::with_id(app, "new_session", "New Session", true, accel("new_session"))?;
+    let close_session = MenuItem::with_id(app, "close_session", "Close Session", true, accel("close_session"))?;
 
-    let file_menu = Submenu::with_items(
-        app,
-        "File",
-        true,
-        &[
-            &new_session,
-            &close_session,
-        ],
-    )?;
+    let file_menu = Submenu::with_items(app, "File", true, &[&new_session, &close_session])?;
+    if !mac {
+        file_menu.append_items(&[
+            &PredefinedMenuItem::separator(app)?,
+            &settings,
+            &PredefinedMenuItem::separator(app)?,
+            &MenuItem::with_id(app, "quit", "Quit", true, Some("CmdOrCtrl+Q"))?,
+        ])?;
+    }
 
     // Edit menu
-    let edit_menu = Submenu::with_items(
-        app,
-        "Edit",
-        true,
-        &[
+    let edit_menu = Submenu::new(app, "Edit", true)?;
+    if mac {
+        edit_menu.append_items(&[
             &PredefinedMenuItem::undo(app, Some("Undo"))?,
             &PredefinedMenuItem::redo(app, Some("Redo"))?,
             &PredefinedMenuItem::separator(app)?,
-            &PredefinedMenuItem::cut(app, Some("Cut"))?,
-            &PredefinedMenuItem::copy(app, Some("Copy"))?,
-            &PredefinedMenuItem::paste(app, Some("Paste"))?,
-            &PredefinedMenuItem::select_all(app, Some("Select All"))?,
-        ],
-    )?;
+        ])?;
+    }
+    edit_menu.append_items(&[
+        &PredefinedMenuItem::cut(app, Some("Cut"))?,
+        &PredefinedMenuItem::copy(app, Some("Copy"))?,
+        &PredefinedMenuItem::paste(app, Some("Paste"))?,
+        &PredefinedMenuItem::select_all(app, Some("Select All"))?,
+    ])?;
 
     // View menu
-    let toggle_sidebar = MenuItem::with_id(app, "toggle_sidebar", "Toggle Sidebar", true, Some("CmdOrCtrl+B"))?;
-    let zoom_in = MenuItem::with_id(app, "zoom_in", "Zoom In", true, Some("CmdOrCtrl+Plus"))?;
-    let zoom_out = MenuItem::with_id(app, "zoom_out", "Zoom Out", true, Some("CmdOrCtrl+-"))?;
-    let reset_zoom = MenuItem::with_id(app, "reset_zoom", "Reset Zoom", true, Some("CmdOrCtrl+0"))?;
+    let toggle_sidebar = MenuItem::with_id(app, "toggle_sidebar", "Toggle Sidebar", true, accel("toggle_sidebar"))?;
+    let zoom_in = MenuItem::with_id(app, "zoom_in", "Zoom In", true, accel("zoom_in"))?;
+    let zoom_out = MenuItem::with_id(app, "zoom_out", "Zoom Out", true, accel("zoom_out"))?;
+    let reset_zoom = MenuItem::with_id(app, "reset_zoom", "Reset Zoom", true, accel("reset_zoom"))?;
 
     let view_menu = Submenu::with_items(
         app,
@@ -2801,17 +5232,21 @@ fn create_menu(app: &AppHandle) -> tauri::Result<Menu<tauri::Wry>> {
             &zoom_out,
             &reset_zoom,
             &PredefinedMenuItem::separator(app)?,
-            &PredefinedMenuItem::fullscreen(app, Some("Toggle Full Screen"))?,
         ],
     )?;
+    if mac {
+        view_menu.append(&PredefinedMenuItem::fullscreen(app, Some("Toggle Full Screen"))?)?;
+    } else {
+        view_menu.append(&MenuItem::with_id(app, "fullscreen", "Toggle Full Screen", true, Some("F11"))?)?;
+    }
 
     // Session menu
-    let rename_session = MenuItem::with_id(app, "rename_session", "Rename Session", true, Some("CmdOrCtrl+I"))?;
-    let duplicate_session = MenuItem::with_id(app, "duplicate_session", "Duplicate Session", true, Some("CmdOrCtrl+Shift+D"))?;
-    let reset_session_id = MenuItem::with_id(app, "reset_session_id", "Reset Session ID", true, None::<&str>)?;
-    let browse_claude_sessions = MenuItem::with_id(app, "browse_claude_sessions", "Browse Claude Sessions...", true, Some("CmdOrCtrl+Shift+R"))?;
-    let next_session = MenuItem::with_id(app, "next_session", "Next Session", true, Some("Ctrl+Tab"))?;
-    let prev_session = MenuItem::with_id(app, "prev_session", "Previous Session", true, Some("Ctrl+Shift+Tab"))?;
+    let rename_session = MenuItem::with_id(app, "rename_session", "Rename Session", true, accel("rename_session"))?;
+    let duplicate_session = MenuItem::with_id(app, "duplicate_session", "Duplicate Session", true, accel("duplicate_session"))?;
+    let reset_session_id = MenuItem::with_id(app, "reset_session_id", "Reset Session ID", true, accel("reset_session_id"))?;
+    let browse_claude_sessions = MenuItem::with_id(app, "browse_claude_sessions", "Browse Claude Sessions...", true, accel("browse_claude_sessions"))?;
+    let next_session = MenuItem::with_id(app, "next_session", "Next Session", true, accel("next_session"))?;
+    let prev_session = MenuItem::with_id(app, "prev_session", "Previous Session", true, accel("prev_session"))?;
 
     let session_menu = Submenu::with_items(
         app,
@@ -2845,17 +5280,34 @@ fn create_menu(app: &AppHandle) -> tauri::Result<Menu<tauri::Wry>> {
     *HISTORY_MENU.lock() = Some(history_menu.clone());
 
     // Window menu
-    let window_menu = Submenu::with_items(
-        app,
-        "Window",
-        true,
-        &[
-            &PredefinedMenuItem::minimize(app, Some("Minimize"))?,
-            &PredefinedMenuItem::maximize(app, Some("Zoom"))?,
-            &PredefinedMenuItem::separator(app)?,
-            &PredefinedMenuItem::close_window(app, Some("Close Window"))?,
-        ],
-    )?;
+    let window_menu = if mac {
+        Submenu::with_items(
+            app,
+            "Window",
+            true,
+            &[
+                &PredefinedMenuItem::minimize(app, Some("Minimize"))?,
+                &PredefinedMenuItem::maximize(app, Some("Zoom"))?,
+                &PredefinedMenuItem::separator(app)?,
+                &PredefinedMenuItem::close_window(app, Some("Close Window"))?,
+            ],
+        )?
+    } else {
+        Submenu::with_items(
+            app,
+            "Window",
+            true,
+            &[
+                &MenuItem::with_id(app, "minimize", "Minimize", true, None::<&str>)?,
+                &MenuItem::with_id(app, "maximize", "Maximize", true, None::<&str>)?,
+                &PredefinedMenuItem::separator(app)?,
+                &MenuItem::with_id(app, "close_window", "Close Window", true, None::<&str>)?,
+            ],
+        )?
+    };
+
+    // Store window menu for the session list (update_sessions_menu)
+    *WINDOW_MENU.lock() = Some(window_menu.clone());
 
     // Help menu
     let help_about_text = format!("About {}", APP_NAME);
@@ -2871,10 +5323,9 @@ fn create_menu(app: &AppHandle) -> tauri::Result<Menu<tauri::Wry>> {
     )?;
 
     // Build the menu (app_menu first for macOS standard layout)
-    Menu::with_items(
+    let menu = Menu::with_items(
         app,
         &[
-            &app_menu,
             &file_menu,
             &edit_menu,
             &view_menu,
@@ -2883,7 +5334,11 @@ fn create_menu(app: &AppHandle) -> tauri::Result<Menu<tauri::Wry>> {
             &window_menu,
             &help_menu,
         ],
-    )
+    )?;
+    if let Some(app_menu) = app_menu {
+        menu.prepend(&app_menu)?;
+    }
+    Ok(menu)
 }
 
 // ============== Web API ==============
@@ -2948,46 +5403,118 @@ fn extract_token(headers: &axum::http::HeaderMap) -> Option<String> {
 
 // Check auth and return error response if not authorized
 fn check_auth(headers: &axum::http::HeaderMap) -> Option<impl IntoResponse> {
-    let devices = PAIRED_DEVICES.lock();
-    if devices.is_empty() {
-        // No devices paired yet - allow access (first-time setup)
+    if is_socket_request(headers) {
         return None;
     }
-    drop(devices);
+    check_token(extract_token(headers))
+}
 
-    match extract_token(headers) {
-        Some(token) if is_valid_token(&token) => None,
-        _ => Some((StatusCode::UNAUTHORIZED, Json(serde_json::json!({
-            "error": "unauthorized",
-            "message": "Device not paired. Request pairing first."
-        })))),
-    }
+// The local socket is protected by file permissions instead of tokens
+#[cfg(all(unix, not(target_os = "ios")))]
+fn is_socket_request(headers: &axum::http::HeaderMap) -> bool {
+    local_socket::is_socket_request(headers)
 }
 
-// POST /api/auth/request-pairing - Request a new pairing code
-async fn api_request_pairing(
-    _headers: axum::http::HeaderMap,
-    body: Option<Json<serde_json::Value>>,
-) -> impl IntoResponse {
-    let device_name = body
-        .and_then(|b| b.get("device_name").and_then(|v| v.as_str()).map(|s| s.to_string()));
+#[cfg(not(all(unix, not(target_os = "ios"))))]
+fn is_socket_request(_headers: &axum::http::HeaderMap) -> bool {
+    false
+}
 
-    let pairing_id = generate_token();
-    let code = generate_pairing_code();
+// Browsers can't set headers on a WebSocket upgrade, so these also take ?token=
+#[cfg(not(target_os = "ios"))]
+fn check_ws_auth(
+    headers: &axum::http::HeaderMap,
+    query: &HashMap<String, String>,
+) -> Option<impl IntoResponse> {
+    if is_socket_request(headers) {
+        return None;
+    }
+    check_token(extract_token(headers).or_else(|| query.get("token").cloned()))
+}
 
-    // Store pairing request
-    {
-        let mut requests = PAIRING_REQUESTS.lock();
-        requests.insert(pairing_id.clone(), PairingRequest {
-            code: code.clone(),
-            created_at: chrono::Utc::now(),
-            device_name: device_name.clone(),
-        });
+/// Handed to agents we spawn (AGENT_HUB_LOCAL_TOKEN) so the MCP bridge can
+/// prove it runs on this machine without pairing
+static LOCAL_TOKEN: Lazy<String> = Lazy::new(generate_token);
+
+/// Whether a request comes from this machine: it carries the local token or
+/// came in over the local socket. The peer address doesn't count; tunnelled
+/// and proxied traffic arrives over loopback as well.
+fn is_local_request(headers: &axum::http::HeaderMap) -> bool {
+    use subtle::ConstantTimeEq;
+    let local_token = headers.get("x-agent-hub-local").map(|v| v.as_bytes()).unwrap_or_default();
+    bool::from(local_token.ct_eq(LOCAL_TOKEN.as_bytes())) || is_socket_request(headers)
+}
+
+// Local tools (the MCP bridge) don't hold a token; everyone else authenticates
+fn check_local_or_auth(headers: &axum::http::HeaderMap, required: DeviceScope) -> Option<axum::response::Response> {
+    if is_local_request(headers) {
+        return None;
+    }
+    check_scope(headers, required)
+}
+
+// Scope of the device behind a token; before any device is paired (and over
+// the local socket) everyone is admin. Callers have checked auth already.
+fn token_scope(token: Option<&str>) -> DeviceScope {
+    token.and_then(device_for_token).map(|d| d.scope).unwrap_or(DeviceScope::Admin)
+}
+
+// Check auth, then that the device's scope allows the request
+fn check_scope(headers: &axum::http::HeaderMap, required: DeviceScope) -> Option<axum::response::Response> {
+    if let Some(err) = check_auth(headers) {
+        return Some(err.into_response());
+    }
+    if token_scope(extract_token(headers).as_deref()) >= required {
+        return None;
+    }
+    Some((StatusCode::FORBIDDEN, Json(serde_json::json!({
+        "error": "forbidden",
+        "message": format!("This device needs {} access", required.as_str())
+    }))).into_response())
+}
+
+fn check_token(token: Option<String>) -> Option<impl IntoResponse> {
+    let devices = state::get().paired_devices.lock();
+    if devices.is_empty() {
+        // No devices paired yet - allow access (first-time setup)
+        return None;
+    }
+    drop(devices);
+
+    match token {
+        Some(token) if is_valid_token(&token) => {
+            touch_token(&token);
+            None
+        }
+        _ => {
+            #[cfg(not(target_os = "ios"))]
+            metrics::on_auth_failure();
+            Some((StatusCode::UNAUTHORIZED, Json(serde_json::json!({
+                "error": "unauthorized",
+                "message": "Device not paired. Request pairing first."
+            }))))
+        }
     }
+}
+
+// POST /api/auth/request-pairing - Request a new pairing code
+async fn api_request_pairing(
+    _headers: axum::http::HeaderMap,
+    body: Option<Json<serde_json::Value>>,
+) -> impl IntoResponse {
+    let device_name = body
+        .and_then(|b| b.get("device_name").and_then(|v| v.as_str()).map(|s| s.to_string()));
 
+    let (pairing_id, code) = match create_pairing_request(device_name.clone()) {
+        Ok(created) => created,
+        Err(e) => return (StatusCode::SERVICE_UNAVAILABLE, Json(serde_json::json!({
+            "error": "too_many_pairing_requests",
+            "message": e
+        }))).into_response(),
+    };
 
     // Notify desktop app to show the code
-    if let Some(app) = APP_HANDLE.lock().as_ref() {
+    if let Some(app) = state::get().app_handle.lock().as_ref() {
         let _ = app.emit("pairing-requested", serde_json::json!({
             "pairing_id": pairing_id,
             "code": code,
@@ -2997,12 +5524,14 @@ async fn api_request_pairing(
 
     Json(serde_json::json!({
         "pairing_id": pairing_id,
-        "expires_in": 300  // 5 minutes
-    }))
+        "expires_in": PAIRING_TTL_SECS
+    })).into_response()
 }
 
-// POST /api/auth/pair - Complete pairing with code
+// POST /api/auth/pair - Complete pairing with code. Answers 202 until the
+// desktop approves the device; clients repeat the request to poll.
 async fn api_pair(
+    axum::extract::State(state): axum::extract::State<Arc<state::AppState>>,
     Json(body): Json<serde_json::Value>,
 ) -> impl IntoResponse {
     let pairing_id = body.get("pairing_id").and_then(|v| v.as_str());
@@ -3017,52 +5546,74 @@ async fn api_pair(
         }))).into_response(),
     };
 
-    // Verify the code
-    let valid = {
-        let requests = PAIRING_REQUESTS.lock();
-        if let Some(request) = requests.get(pairing_id) {
-            // Check code matches and hasn't expired (5 min)
-            let age = chrono::Utc::now() - request.created_at;
-            request.code == code && age.num_seconds() < 300
-        } else {
-            false
+    let invalid = || (StatusCode::UNAUTHORIZED, Json(serde_json::json!({
+        "error": "invalid_code",
+        "message": "Invalid or expired pairing code"
+    }))).into_response();
+
+    // Verify the code, then wait for the desktop to approve
+    let (approved_name, approved_scope) = {
+        use subtle::ConstantTimeEq;
+        let mut requests = state.pairing_requests.lock();
+        prune_pairing_requests(&mut requests);
+        let Some(request) = requests.get_mut(pairing_id) else {
+            return invalid();
+        };
+        if !bool::from(request.code.as_bytes().ct_eq(code.as_bytes())) {
+            // Too many wrong guesses burn the code
+            request.failed_attempts += 1;
+            if request.failed_attempts >= MAX_PAIRING_CODE_ATTEMPTS {
+                requests.remove(pairing_id);
+            }
+            return invalid();
+        }
+        if request.denied {
+            requests.remove(pairing_id);
+            return (StatusCode::FORBIDDEN, Json(serde_json::json!({
+                "error": "pairing_denied",
+                "message": "Pairing was declined on the desktop"
+            }))).into_response();
         }
+        if !request.approved {
+            let first_attempt = !request.code_verified;
+            if first_attempt {
+                request.code_verified = true;
+                request.device_name = Some(device_name.to_string());
+            }
+            let expires_in = PAIRING_TTL_SECS - (chrono::Utc::now() - request.created_at).num_seconds();
+            drop(requests);
+            if first_attempt {
+                if let Some(app) = state.app_handle.lock().as_ref() {
+                    let _ = app.emit("pairing-approval-requested", serde_json::json!({
+                        "pairing_id": pairing_id,
+                        "device_name": device_name,
+                    }));
+                }
+            }
+            return (StatusCode::ACCEPTED, Json(serde_json::json!({
+                "status": "pending_approval",
+                "expires_in": expires_in
+            }))).into_response();
+        }
+        let request = requests.remove(pairing_id);
+        let scope = request.as_ref().map(|r| r.scope).unwrap_or_default();
+        (request.and_then(|r| r.device_name).unwrap_or_else(|| device_name.to_string()), scope)
     };
 
-    if !valid {
-        return (StatusCode::UNAUTHORIZED, Json(serde_json::json!({
-            "error": "invalid_code",
-            "message": "Invalid or expired pairing code"
-        }))).into_response();
-    }
-
-    // Remove the pairing request
-    {
-        let mut requests = PAIRING_REQUESTS.lock();
-        requests.remove(pairing_id);
-    }
-
     // Generate token and store device
-    let token = generate_token();
-    let device_id = generate_token();
     let now = chrono::Utc::now().to_rfc3339();
-
-    let device = PairedDevice {
-        id: device_id,
-        name: device_name.to_string(),
+    let (token, device) = issue_token(PairedDevice {
+        id: generate_token(),
+        name: approved_name,
         paired_at: now.clone(),
         last_seen: now,
-    };
-
-    // Store in memory and database
-    {
-        let mut devices = PAIRED_DEVICES.lock();
-        devices.insert(token.clone(), device.clone());
-    }
-    let _ = save_paired_device(&token, &device);
+        expires_at: None,
+        scope: approved_scope,
+        notifications: Default::default(),
+    });
 
     // Notify desktop
-    if let Some(app) = APP_HANDLE.lock().as_ref() {
+    if let Some(app) = state.app_handle.lock().as_ref() {
         let _ = app.emit("device-paired", serde_json::json!({
             "device": device,
         }));
@@ -3070,7 +5621,52 @@ async fn api_pair(
 
     Json(serde_json::json!({
         "token": token,
-        "device_id": device.id
+        "device_id": device.id,
+        "expires_at": device.expires_at,
+    })).into_response()
+}
+
+// GET /api/auth/qr - Pairing QR code as SVG (only from this machine)
+#[cfg(not(target_os = "ios"))]
+async fn api_pairing_qr(headers: axum::http::HeaderMap) -> impl IntoResponse {
+    // Anyone who can fetch the QR can pair, so it never leaves the desktop
+    if !is_local_request(&headers) {
+        return (StatusCode::FORBIDDEN, Json(serde_json::json!({
+            "error": "forbidden",
+            "message": "The pairing QR code is only available on the desktop"
+        }))).into_response();
+    }
+    match create_pairing_qr() {
+        Ok(qr) => (
+            [
+                (axum::http::header::CONTENT_TYPE, "image/svg+xml".to_string()),
+                (axum::http::header::CACHE_CONTROL, "no-store".to_string()),
+            ],
+            qr.svg,
+        )
+            .into_response(),
+        Err(e) => (StatusCode::SERVICE_UNAVAILABLE, Json(serde_json::json!({"error": e}))).into_response(),
+    }
+}
+
+// POST /api/auth/refresh - Exchange a valid token for a new one with a fresh expiry
+async fn api_auth_refresh(headers: axum::http::HeaderMap) -> impl IntoResponse {
+    let Some((token, mut device)) = extract_token(&headers).and_then(|t| device_for_token(&t).map(|d| (t, d))) else {
+        return (StatusCode::UNAUTHORIZED, Json(serde_json::json!({
+            "error": "invalid_token",
+            "message": "Token is invalid or expired. Pair again."
+        }))).into_response();
+    };
+
+    // Rotate: the old token stops working as soon as the new one exists
+    device.last_seen = chrono::Utc::now().to_rfc3339();
+    let (new_token, device) = issue_token(device);
+    remove_token(&hash_token(&token));
+
+    Json(serde_json::json!({
+        "token": new_token,
+        "device_id": device.id,
+        "expires_at": device.expires_at,
     })).into_response()
 }
 
@@ -3086,30 +5682,8 @@ async fn api_pin_status() -> impl IntoResponse {
 
 // POST /api/auth/pin-login - Authenticate with PIN
 async fn api_pin_login(
-    axum::extract::ConnectInfo(addr): axum::extract::ConnectInfo<SocketAddr>,
     Json(body): Json<serde_json::Value>,
 ) -> impl IntoResponse {
-    let client_ip = addr.ip().to_string();
-
-    // Rate limiting: 5 attempts per 15 minutes
-    {
-        let mut rate_limits = PIN_RATE_LIMIT.lock();
-        if let Some((attempts, last_time)) = rate_limits.get(&client_ip) {
-            let elapsed = last_time.elapsed();
-            if elapsed < std::time::Duration::from_secs(900) && *attempts >= 5 {
-                let remaining = 900 - elapsed.as_secs();
-                return (StatusCode::TOO_MANY_REQUESTS, Json(serde_json::json!({
-                    "error": "rate_limited",
-                    "message": format!("Too many attempts. Try again in {} minutes.", remaining / 60 + 1)
-                }))).into_response();
-            }
-            // Reset if 15 minutes have passed
-            if elapsed >= std::time::Duration::from_secs(900) {
-                rate_limits.remove(&client_ip);
-            }
-        }
-    }
-
     let pin = match body.get("pin").and_then(|v| v.as_str()) {
         Some(p) => p,
         None => return (StatusCode::BAD_REQUEST, Json(serde_json::json!({
@@ -3123,7 +5697,13 @@ async fn api_pin_login(
     // Load settings and check PIN
     let settings = load_app_settings().unwrap_or_default();
     let valid = match &settings.remote_pin {
-        Some(configured_pin) => configured_pin == pin,
+        Some(configured_pin) => {
+            // Argon2 is deliberately slow; keep it off the async workers
+            let (pin, stored) = (pin.to_string(), configured_pin.clone());
+            tokio::task::spawn_blocking(move || pin::verify(&pin, &stored))
+            .await
+            .unwrap_or(false)
+        }
         None => return (StatusCode::BAD_REQUEST, Json(serde_json::json!({
             "error": "pin_not_configured",
             "message": "PIN authentication is not configured"
@@ -3131,47 +5711,28 @@ async fn api_pin_login(
     };
 
     if !valid {
-        // Record failed attempt
-        {
-            let mut rate_limits = PIN_RATE_LIMIT.lock();
-            let entry = rate_limits.entry(client_ip).or_insert((0, std::time::Instant::now()));
-            entry.0 += 1;
-            entry.1 = std::time::Instant::now();
-        }
-
         return (StatusCode::UNAUTHORIZED, Json(serde_json::json!({
             "error": "invalid_pin",
             "message": "Invalid PIN"
         }))).into_response();
     }
 
-    // Clear rate limit on success
-    {
-        let mut rate_limits = PIN_RATE_LIMIT.lock();
-        rate_limits.remove(&addr.ip().to_string());
-    }
-
     // Generate token and store device
-    let token = generate_token();
-    let device_id = generate_token();
     let now = chrono::Utc::now().to_rfc3339();
-
-    let device = PairedDevice {
-        id: device_id,
+    let (token, device) = issue_token(PairedDevice {
+        id: generate_token(),
         name: device_name.to_string(),
         paired_at: now.clone(),
         last_seen: now,
-    };
-
-    // Store in memory and database
-    {
-        let mut devices = PAIRED_DEVICES.lock();
-        devices.insert(token.clone(), device.clone());
-    }
-    let _ = save_paired_device(&token, &device);
+        expires_at: None,
+        // Anyone with the PIN gets in, so only to watch until the desktop
+        // raises it in the device list
+        scope: DeviceScope::ReadOnly,
+        notifications: Default::default(),
+    });
 
     // Notify desktop
-    if let Some(app) = APP_HANDLE.lock().as_ref() {
+    if let Some(app) = state::get().app_handle.lock().as_ref() {
         let _ = app.emit("device-paired", serde_json::json!({
             "device": device,
             "method": "pin"
@@ -3180,15 +5741,108 @@ async fn api_pin_login(
 
     Json(serde_json::json!({
         "token": token,
-        "device_id": device.id
+        "device_id": device.id,
+        "expires_at": device.expires_at,
     })).into_response()
 }
 
+// GET /api/health - Liveness for monitoring (no auth)
+async fn api_health() -> impl IntoResponse {
+    // Don't hang the probe behind a long-running query
+    let db_ok = state::get().db()
+        .try_lock_for(std::time::Duration::from_secs(2))
+        .map(|conn| conn.query_row("SELECT 1", [], |r| r.get::<_, i64>(0)).is_ok())
+        .unwrap_or(false);
+
+    #[cfg(not(target_os = "ios"))]
+    let sessions = serde_json::json!({
+        "pty": state::get().pty_sessions.lock().len(),
+        "json": state::get().json_processes.lock().len(),
+        "mobile_clients": state::get().mobile_clients.lock().len(),
+    });
+    #[cfg(target_os = "ios")]
+    let sessions = serde_json::json!({ "pty": 0, "json": 0, "mobile_clients": 0 });
+
+    #[cfg(not(target_os = "ios"))]
+    let tunnel = serde_json::to_value(tunnel::status()).unwrap_or_default();
+    #[cfg(target_os = "ios")]
+    let tunnel = serde_json::Value::Null;
+
+    let status = if db_ok { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (status, Json(serde_json::json!({
+        "status": if db_ok { "ok" } else { "degraded" },
+        "uptime_secs": SERVER_STARTED_AT.elapsed().as_secs(),
+        "db": db_ok,
+        "sessions": sessions,
+        "tunnel": tunnel,
+    }))).into_response()
+}
+
+// GET /api/version - App and API protocol version (no auth)
+async fn api_version() -> impl IntoResponse {
+    Json(serde_json::json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "protocol_version": PROTOCOL_VERSION,
+        "platform": std::env::consts::OS,
+    }))
+}
+
+// GET /metrics - Prometheus metrics (scrape with a paired device's bearer token)
+#[cfg(not(target_os = "ios"))]
+async fn api_metrics(headers: axum::http::HeaderMap) -> impl IntoResponse {
+    if let Some(err) = check_auth(&headers) {
+        return err.into_response();
+    }
+    let db_ping = state::get().db().try_lock_for(std::time::Duration::from_secs(2)).and_then(|conn| {
+        let start = std::time::Instant::now();
+        conn.query_row("SELECT 1", [], |r| r.get::<_, i64>(0)).ok().map(|_| start.elapsed())
+    });
+    let snapshot = metrics::Snapshot {
+        pty_sessions: state::get().pty_sessions.lock().len(),
+        json_sessions: state::get().json_processes.lock().len(),
+        uptime_secs: SERVER_STARTED_AT.elapsed().as_secs(),
+        db_ping,
+    };
+    (
+        [(axum::http::header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics::render(&snapshot),
+    )
+        .into_response()
+}
+
+// GET /api/cert - Fingerprint of the server certificate, for pinning (no auth)
+#[cfg(not(target_os = "ios"))]
+async fn api_cert() -> impl IntoResponse {
+    match tls::active_cert() {
+        Some(cert) => Json(serde_json::json!({
+            "tls": true,
+            "fingerprint_sha256": cert.fingerprint_sha256,
+            "self_signed": cert.self_signed,
+        })),
+        None => Json(serde_json::json!({ "tls": false })),
+    }
+}
+
+// GET /api/instances - Agent Hub instances found on the LAN via mDNS
+#[cfg(not(target_os = "ios"))]
+async fn api_discover_instances(headers: axum::http::HeaderMap) -> impl IntoResponse {
+    if let Some(err) = check_auth(&headers) {
+        return err.into_response();
+    }
+    match tokio::task::spawn_blocking(discovery::discover).await {
+        Ok(instances) => Json(serde_json::json!({ "instances": instances })).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": e.to_string()}))).into_response(),
+    }
+}
+
+// GET /api/openapi.json - OpenAPI description of this API (no auth)
+async fn api_openapi() -> impl IntoResponse {
+    Json(openapi::spec())
+}
+
 // GET /api/auth/check - Check if current token is valid
-async fn api_auth_check(
-    headers: axum::http::HeaderMap,
-) -> impl IntoResponse {
-    let devices = PAIRED_DEVICES.lock();
+async fn api_auth_check(headers: axum::http::HeaderMap) -> impl IntoResponse {
+    let devices = state::get().paired_devices.lock();
     if devices.is_empty() {
         // No devices paired - no auth required
         return Json(serde_json::json!({
@@ -3224,13 +5878,13 @@ struct SearchQueryParams {
 }
 
 async fn api_search_messages(
-    _headers: axum::http::HeaderMap,
+    headers: axum::http::HeaderMap,
     axum::extract::Query(params): axum::extract::Query<SearchQueryParams>,
 ) -> impl IntoResponse {
-    // Auth intentionally not enforced here — matches /api/mcp/execute's
-    // model so the local MCP bridge (and other local tools) can call this
-    // without managing tokens. Server binds 0.0.0.0; if you don't trust
-    // your LAN, restrict the listening address to 127.0.0.1.
+    // The local MCP bridge searches without a token
+    if let Some(err) = check_local_or_auth(&headers, DeviceScope::ReadOnly) {
+        return err;
+    }
     match search::search_messages(
         &params.q,
         search::SearchFilters {
@@ -3242,7 +5896,12 @@ async fn api_search_messages(
             offset: params.offset,
         },
     ) {
-        Ok(hits) => Json(serde_json::json!({ "hits": hits, "count": hits.len() })).into_response(),
+        Ok(mut hits) => {
+            if !is_local_request(&headers) {
+                hits.retain(|h| !is_remote_hidden(&h.session_id));
+            }
+            Json(serde_json::json!({ "hits": hits, "count": hits.len() })).into_response()
+        }
         Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({ "error": e })))
             .into_response(),
     }
@@ -3256,197 +5915,1110 @@ struct ContextQueryParams {
 }
 
 async fn api_search_context(
-    _headers: axum::http::HeaderMap,
+    headers: axum::http::HeaderMap,
     axum::extract::Query(params): axum::extract::Query<ContextQueryParams>,
 ) -> impl IntoResponse {
+    if let Some(err) = check_local_or_auth(&headers, DeviceScope::ReadOnly) {
+        return err;
+    }
     match search::get_message_context(
         params.message_id,
         params.before.unwrap_or(3),
         params.after.unwrap_or(3),
     ) {
+        Ok(ctx) if is_remote_hidden(&ctx.session_id) && !is_local_request(&headers) => {
+            (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": "hit not found"}))).into_response()
+        }
         Ok(ctx) => Json(ctx).into_response(),
         Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": e})))
             .into_response(),
     }
 }
 
-async fn api_search_stats(_headers: axum::http::HeaderMap) -> impl IntoResponse {
+async fn api_search_stats(headers: axum::http::HeaderMap) -> impl IntoResponse {
+    if let Some(err) = check_local_or_auth(&headers, DeviceScope::ReadOnly) {
+        return err;
+    }
     Json(search::get_stats()).into_response()
 }
 
-async fn api_search_rebuild(_headers: axum::http::HeaderMap) -> impl IntoResponse {
+async fn api_search_rebuild(headers: axum::http::HeaderMap) -> impl IntoResponse {
+    if let Some(err) = check_local_or_auth(&headers, DeviceScope::Interact) {
+        return err;
+    }
     let stats = search::rebuild_index();
     Json(stats).into_response()
 }
 
-// GET /api/sessions - List all sessions with running status
+/// Sessions as listed to remote clients (GET /api/sessions), with running status
 #[cfg(not(target_os = "ios"))]
-async fn api_list_sessions(headers: axum::http::HeaderMap) -> impl IntoResponse {
-    if let Some(err) = check_auth(&headers) {
-        return err.into_response();
-    }
-    match load_sessions() {
-        Ok(sessions) => {
-            // Check both PTY (shell) and JSON (chat) broadcasters for running status
-            let pty_running_ids: std::collections::HashSet<String> = {
-                let broadcasters = PTY_BROADCASTERS.lock();
-                broadcasters.keys().cloned().collect()
-            };
-            let json_running_ids: std::collections::HashSet<String> = {
-                let broadcasters = JSON_BROADCASTERS.lock();
-                broadcasters.keys().cloned().collect()
-            };
+fn listed_sessions() -> Result<Vec<serde_json::Value>, String> {
+    // Check both PTY (shell) and JSON (chat) broadcasters for running status
+    let pty_running_ids: std::collections::HashSet<String> = {
+        let broadcasters = state::get().pty_broadcasters.lock();
+        broadcasters.keys().cloned().collect()
+    };
+    let json_running_ids: std::collections::HashSet<String> = {
+        let broadcasters = state::get().json_broadcasters.lock();
+        broadcasters.keys().cloned().collect()
+    };
 
-            // Add running status to each session
-            let sessions_with_status: Vec<serde_json::Value> = sessions.into_iter().map(|s| {
-                let is_running = pty_running_ids.contains(&s.id) || json_running_ids.contains(&s.id);
-                serde_json::json!({
-                    "id": s.id,
-                    "name": s.name,
-                    "agent_type": s.agent_type,
-                    "command": s.command,
-                    "working_dir": s.working_dir,
-                    "created_at": s.created_at,
-                    "claude_session_id": s.claude_session_id,
-                    "sort_order": s.sort_order,
-                    "folder_id": s.folder_id,
-                    "running": is_running
-                })
-            }).collect();
-
-            Json(sessions_with_status).into_response()
-        }
-        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
-    }
+    // Add running status to each session
+    Ok(load_sessions()?.into_iter().filter(|s| !is_remote_hidden(&s.id)).map(|s| {
+        let is_running = pty_running_ids.contains(&s.id) || json_running_ids.contains(&s.id);
+        serde_json::json!({
+            "id": s.id,
+            "name": s.name,
+            "agent_type": s.agent_type,
+            "command": s.command,
+            "working_dir": s.working_dir,
+            "created_at": s.created_at,
+            "claude_session_id": s.claude_session_id,
+            "sort_order": s.sort_order,
+            "folder_id": s.folder_id,
+            "parent_id": s.parent_id,
+            "workspace_id": s.workspace_id,
+            "summary": s.summary,
+            "running": is_running,
+            "processing": is_session_processing(&s.id)
+        })
+    }).collect())
 }
 
 // iOS version - no PTY running status
 #[cfg(target_os = "ios")]
+fn listed_sessions() -> Result<Vec<serde_json::Value>, String> {
+    // On iOS, sessions are never running locally
+    Ok(load_sessions()?.into_iter().filter(|s| !is_remote_hidden(&s.id)).map(|s| {
+        serde_json::json!({
+            "id": s.id,
+            "name": s.name,
+            "agent_type": s.agent_type,
+            "command": s.command,
+            "working_dir": s.working_dir,
+            "created_at": s.created_at,
+            "claude_session_id": s.claude_session_id,
+            "sort_order": s.sort_order,
+            "folder_id": s.folder_id,
+            "parent_id": s.parent_id,
+            "workspace_id": s.workspace_id,
+            "summary": s.summary,
+            "running": false,
+            "processing": false
+        })
+    }).collect())
+}
+
+// GET /api/sessions - List all sessions with running status
 async fn api_list_sessions(headers: axum::http::HeaderMap) -> impl IntoResponse {
     if let Some(err) = check_auth(&headers) {
         return err.into_response();
     }
-    match load_sessions() {
-        Ok(sessions) => {
-            // On iOS, sessions are never running locally
-            let sessions_with_status: Vec<serde_json::Value> = sessions.into_iter().map(|s| {
-                serde_json::json!({
-                    "id": s.id,
-                    "name": s.name,
-                    "agent_type": s.agent_type,
-                    "command": s.command,
-                    "working_dir": s.working_dir,
-                    "created_at": s.created_at,
-                    "claude_session_id": s.claude_session_id,
-                    "sort_order": s.sort_order,
-                    "folder_id": s.folder_id,
-                    "running": false
-                })
-            }).collect();
-
-            Json(sessions_with_status).into_response()
+    match listed_sessions() {
+        Ok(sessions) => Json(sessions).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+    }
+}
+
+/// Build and save a new session from a POST /api/sessions body (also used by
+/// the mobile WebSocket's create_session)
+fn create_session_from_request(body: &serde_json::Value) -> Result<SessionData, String> {
+    let name = body.get("name").and_then(|v| v.as_str()).map(|s| s.to_string());
+    let agent_type = body.get("agent_type").and_then(|v| v.as_str()).unwrap_or("claude");
+    let custom_command = body.get("custom_command").and_then(|v| v.as_str()).map(|s| s.to_string());
+    // Into the given workspace, else the active one, starting in its
+    // directory. Remote clients can't see hidden workspaces, so they don't
+    // create sessions in them either.
+    let workspace = match body.get("workspace_id").and_then(|v| v.as_str()) {
+        Some(id) => Some(workspaces::get(id).filter(|w| w.remote_access).ok_or("Workspace not found")?),
+        None => workspaces::active_id().and_then(|id| workspaces::get(&id)).filter(|w| w.remote_access),
+    };
+    let workspace_id = workspace.as_ref().map(|w| w.id.clone());
+    let workspace_dir = workspace.and_then(|w| w.default_working_dir);
+    let working_dir = body
+        .get("working_dir")
+        .and_then(|v| v.as_str())
+        .or(workspace_dir.as_deref())
+        .unwrap_or(platform::DEFAULT_WORKING_DIR);
+    let env_vars = body.get("env_vars").and_then(|v| {
+        if v.is_object() { Some(v.to_string()) } else { v.as_str().map(|s| s.to_string()) }
+    });
+
+    // Generate session ID
+    let session_id = generate_token();
+
+    // Determine command based on agent type
+    let command = match agent_type {
+        "custom" => custom_command.clone().unwrap_or_else(platform::user_shell),
+        _ => agent_commands::command_for(agent_type)
+            .or_else(|| agent_commands::command_for("claude"))
+            .unwrap_or_default(),
+    };
+
+    // Generate auto-name if not provided
+    let session_name = name.unwrap_or_else(|| {
+        // Get count of existing sessions for auto-numbering
+        let count = load_sessions().map(|s| s.len()).unwrap_or(0);
+        let agent_label = match agent_type {
+            "claude" => "Claude",
+            "claude-json" => "Claude Chat",
+            "aider" => "Aider",
+            "shell" => "Shell",
+            "custom" => "Custom",
+            _ => "Session",
+        };
+        format!("{} {}", agent_label, count + 1)
+    });
+
+    // Don't pre-generate claude_session_id - it will be captured from Claude's output
+    // when the session is first started. Only existing sessions with saved IDs should resume.
+    let claude_session_id: Option<String> = None;
+
+    // Calculate sort order (put new sessions at top)
+    let min_sort_order = load_sessions()
+        .map(|sessions| sessions.iter().map(|s| s.sort_order).min().unwrap_or(0))
+        .unwrap_or(0);
+
+    let folder_id = body.get("folder_id").and_then(|v| v.as_str()).map(|s| s.to_string());
+
+    let session = SessionData {
+        id: session_id.clone(),
+        name: session_name,
+        agent_type: agent_type.to_string(),
+        command,
+        working_dir: working_dir.to_string(),
+        created_at: chrono::Utc::now().to_rfc3339(),
+        claude_session_id,
+        sort_order: min_sort_order - 1,
+        folder_id,
+        env_vars,
+        parent_id: None,
+        summary: None,
+        remote_access: true,
+        workspace_id,
+    };
+
+    // Save to database
+    save_session(session.clone())?;
+
+    // Notify desktop app about new session
+    if let Some(app) = state::get().app_handle.lock().as_ref() {
+        let _ = app.emit("remote-session-created", serde_json::json!({
+            "session": {
+                "id": session.id,
+                "name": session.name,
+                "agent_type": session.agent_type,
+                "working_dir": session.working_dir,
+            }
+        }));
+    }
+
+    Ok(session)
+}
+
+// POST /api/sessions - Create a new session
+async fn api_create_session(
+    headers: axum::http::HeaderMap,
+    Json(body): Json<serde_json::Value>,
+) -> impl IntoResponse {
+    if let Some(err) = check_scope(&headers, DeviceScope::Interact) {
+        return err;
+    }
+
+    let session = match create_session_from_request(&body) {
+        Ok(session) => session,
+        Err(e) => {
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({
+                "error": e
+            }))).into_response()
+        }
+    };
+
+    Json(serde_json::json!({
+        "id": session.id,
+        "name": session.name,
+        "agent_type": session.agent_type,
+        "command": session.command,
+        "working_dir": session.working_dir,
+        "folder_id": session.folder_id,
+        "parent_id": session.parent_id,
+        "running": false
+    })).into_response()
+}
+
+// PATCH /api/sessions/{id} - Rename, move to a folder, or change working dir / sort order
+//
+// Body: any of { "name", "folder_id" (null = no folder), "working_dir", "sort_order" }
+#[cfg(not(target_os = "ios"))]
+async fn api_update_session(
+    headers: axum::http::HeaderMap,
+    Path(session_id): Path<String>,
+    Json(body): Json<serde_json::Value>,
+) -> impl IntoResponse {
+    if let Some(err) = check_scope(&headers, DeviceScope::Interact) {
+        return err;
+    }
+    if let Some(err) = check_session_remote(&session_id) {
+        return err;
+    }
+    match update_session_from_request(&session_id, &body) {
+        Ok(session) => Json(serde_json::json!(session)).into_response(),
+        Err((status, e)) => (status, Json(serde_json::json!({"error": e}))).into_response(),
+    }
+}
+
+/// Apply a PATCH /api/sessions/{id} body (also used by the mobile
+/// WebSocket's rename_session and move_session_to_folder)
+#[cfg(not(target_os = "ios"))]
+fn update_session_from_request(session_id: &str, body: &serde_json::Value) -> Result<SessionData, (StatusCode, String)> {
+    let bad_request = |e: &str| Err((StatusCode::BAD_REQUEST, e.to_string()));
+
+    let mut session = match load_sessions().map(|all| all.into_iter().find(|s| s.id == session_id)) {
+        Ok(Some(s)) => s,
+        Ok(None) => return Err((StatusCode::NOT_FOUND, "Session not found".to_string())),
+        Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, e)),
+    };
+
+    if let Some(name) = body.get("name") {
+        match name.as_str().map(str::trim) {
+            Some(n) if !n.is_empty() => session.name = n.to_string(),
+            _ => return bad_request("name must be a non-empty string"),
+        }
+    }
+    if let Some(folder) = body.get("folder_id") {
+        session.folder_id = match folder {
+            serde_json::Value::Null => None,
+            serde_json::Value::String(id) => {
+                let hidden_workspaces = workspaces::remote_hidden_workspaces();
+                let folder = load_folders().unwrap_or_default().into_iter().find(|f| {
+                    &f.id == id && !f.workspace_id.as_ref().is_some_and(|w| hidden_workspaces.contains(w))
+                });
+                let Some(folder) = folder else {
+                    return bad_request("Folder not found");
+                };
+                // Joining a folder means joining its workspace
+                if folder.workspace_id.is_some() {
+                    session.workspace_id = folder.workspace_id;
+                }
+                Some(folder.id)
+            }
+            _ => return bad_request("folder_id must be a string or null"),
+        };
+    }
+    if let Some(dir) = body.get("working_dir") {
+        match dir.as_str().map(str::trim) {
+            Some(d) if std::path::Path::new(&shellexpand::tilde(d).to_string()).is_dir() => {
+                session.working_dir = d.to_string()
+            }
+            _ => return bad_request("working_dir must be an existing directory"),
+        }
+    }
+    if let Some(order) = body.get("sort_order") {
+        match order.as_i64() {
+            Some(o) => session.sort_order = o as i32,
+            None => return bad_request("sort_order must be an integer"),
+        }
+    }
+
+    save_session(session.clone()).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    broadcast_session_updated(&session);
+    if let Some(app) = state::get().app_handle.lock().as_ref() {
+        let _ = app.emit("remote-session-updated", serde_json::json!({ "session": session }));
+    }
+    Ok(session)
+}
+
+// DELETE /api/sessions/{id} - Stop and delete a session
+#[cfg(not(target_os = "ios"))]
+async fn api_delete_session(
+    headers: axum::http::HeaderMap,
+    Path(session_id): Path<String>,
+) -> impl IntoResponse {
+    if let Some(err) = check_scope(&headers, DeviceScope::Interact) {
+        return err;
+    }
+    if let Some(err) = check_session_remote(&session_id) {
+        return err;
+    }
+    match delete_session_remotely(&session_id) {
+        Ok(()) => Json(serde_json::json!({ "status": "deleted" })).into_response(),
+        Err((status, e)) => (status, Json(serde_json::json!({"error": e}))).into_response(),
+    }
+}
+
+/// Stop and delete a session for a remote client
+#[cfg(not(target_os = "ios"))]
+fn delete_session_remotely(session_id: &str) -> Result<(), (StatusCode, String)> {
+    let exists = load_sessions().map(|all| all.iter().any(|s| s.id == session_id)).unwrap_or(false);
+    if !exists {
+        return Err((StatusCode::NOT_FOUND, "Session not found".to_string()));
+    }
+
+    let _ = kill_json_process(session_id.to_string());
+    let _ = kill_pty(session_id.to_string());
+    let _ = delete_terminal_buffer(session_id.to_string());
+    delete_session(session_id.to_string()).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    // The desktop tears down the session's view (and offers it under recently closed)
+    if let Some(app) = state::get().app_handle.lock().as_ref() {
+        let _ = app.emit("remote-session-deleted", session_id);
+    }
+    Ok(())
+}
+
+// GET /api/settings - Remotely editable settings
+#[cfg(not(target_os = "ios"))]
+async fn api_get_settings(headers: axum::http::HeaderMap) -> impl IntoResponse {
+    if let Some(err) = check_auth(&headers) {
+        return err.into_response();
+    }
+    match load_app_settings() {
+        Ok(settings) => Json(remote_settings(&settings)).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": e}))).into_response(),
+    }
+}
+
+// PATCH /api/settings - Change remotely editable settings
+// Body: any subset of REMOTE_SETTINGS_FIELDS, e.g. { "show_active_sessions_group": false }
+#[cfg(not(target_os = "ios"))]
+async fn api_update_settings(
+    headers: axum::http::HeaderMap,
+    Json(body): Json<serde_json::Value>,
+) -> impl IntoResponse {
+    if let Some(err) = check_scope(&headers, DeviceScope::Admin) {
+        return err;
+    }
+    let current = match load_app_settings() {
+        Ok(s) => s,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": e}))).into_response(),
+    };
+    let updated = match apply_remote_settings(&current, &body) {
+        Ok(s) => s,
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(serde_json::json!({"error": e}))).into_response(),
+    };
+    if let Err(e) = save_app_settings(updated.clone()) {
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": e}))).into_response();
+    }
+
+    let visible = remote_settings(&updated);
+    broadcast_session_event(events::SessionEvent::SettingsUpdated(visible.clone()));
+    let msg = serde_json::json!({
+        "type": "settings_updated",
+        "settings": visible
+    }).to_string();
+    broadcast_to_mobile_clients(&msg);
+    // The desktop reloads its copy so the change shows up (and isn't
+    // overwritten by the next save from the settings modal)
+    if let Some(app) = state::get().app_handle.lock().as_ref() {
+        let _ = app.emit("remote-settings-updated", ());
+    }
+    Json(visible).into_response()
+}
+
+// GET /api/folders - List folders in sidebar order
+#[cfg(not(target_os = "ios"))]
+async fn api_list_folders(headers: axum::http::HeaderMap) -> impl IntoResponse {
+    if let Some(err) = check_auth(&headers) {
+        return err.into_response();
+    }
+    let hidden_workspaces = workspaces::remote_hidden_workspaces();
+    match load_folders() {
+        Ok(folders) => {
+            let visible: Vec<FolderData> = folders
+                .into_iter()
+                .filter(|f| !f.workspace_id.as_ref().is_some_and(|w| hidden_workspaces.contains(w)))
+                .collect();
+            Json(serde_json::json!(visible)).into_response()
+        }
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": e}))).into_response(),
+    }
+}
+
+// GET /api/workspaces - Workspaces remote devices can see, with this month's spend
+#[cfg(not(target_os = "ios"))]
+async fn api_list_workspaces(headers: axum::http::HeaderMap) -> impl IntoResponse {
+    if let Some(err) = check_auth(&headers) {
+        return err.into_response();
+    }
+    Json(remote_workspaces()).into_response()
+}
+
+// POST /api/folders - Create a folder at the end of the list
+// Body: { "name": "..." }
+#[cfg(not(target_os = "ios"))]
+async fn api_create_folder(
+    headers: axum::http::HeaderMap,
+    Json(body): Json<serde_json::Value>,
+) -> impl IntoResponse {
+    if let Some(err) = check_scope(&headers, DeviceScope::Interact) {
+        return err;
+    }
+    let Some(name) = body.get("name").and_then(|v| v.as_str()).map(str::trim).filter(|n| !n.is_empty()) else {
+        return (StatusCode::BAD_REQUEST, Json(serde_json::json!({"error": "name is required"}))).into_response();
+    };
+    let existing = load_folders().unwrap_or_default();
+    let folder = FolderData {
+        id: uuid::Uuid::new_v4().to_string(),
+        name: name.to_string(),
+        sort_order: existing.iter().map(|f| f.sort_order + 1).max().unwrap_or(0),
+        collapsed: false,
+        // The active workspace, unless it's one remote clients can't see
+        workspace_id: workspaces::active_id().and_then(|id| workspaces::get(&id)).filter(|w| w.remote_access).map(|w| w.id),
+    };
+    if let Err(e) = save_folder(folder.clone()) {
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": e}))).into_response();
+    }
+    broadcast_folder_changed("folder_created", &folder);
+    if let Some(app) = state::get().app_handle.lock().as_ref() {
+        let _ = app.emit("remote-folder-updated", serde_json::json!({ "folder": folder }));
+    }
+    (StatusCode::CREATED, Json(serde_json::json!(folder))).into_response()
+}
+
+// PATCH /api/folders - Reorder folders
+// Body: { "orders": [["<folder id>", <sort_order>], ...] }
+#[cfg(not(target_os = "ios"))]
+async fn api_reorder_folders(
+    headers: axum::http::HeaderMap,
+    Json(body): Json<serde_json::Value>,
+) -> impl IntoResponse {
+    if let Some(err) = check_scope(&headers, DeviceScope::Interact) {
+        return err;
+    }
+    let orders: Vec<(String, i32)> = match body.get("orders").map(|o| serde_json::from_value(o.clone())) {
+        Some(Ok(orders)) => orders,
+        _ => {
+            return (StatusCode::BAD_REQUEST, Json(serde_json::json!({"error": "orders must be a list of [id, sort_order] pairs"}))).into_response()
+        }
+    };
+    let changed: std::collections::HashSet<String> = orders.iter().map(|(id, _)| id.clone()).collect();
+    if let Err(e) = update_folder_orders(orders) {
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": e}))).into_response();
+    }
+    let folders = load_folders().unwrap_or_default();
+    for folder in folders.iter().filter(|f| changed.contains(&f.id)) {
+        broadcast_folder_changed("folder_updated", folder);
+        if let Some(app) = state::get().app_handle.lock().as_ref() {
+            let _ = app.emit("remote-folder-updated", serde_json::json!({ "folder": folder }));
+        }
+    }
+    Json(serde_json::json!(folders)).into_response()
+}
+
+// PATCH /api/folders/{id} - Rename, collapse/expand, or move a folder
+// Body: any of { "name", "collapsed", "sort_order" }
+#[cfg(not(target_os = "ios"))]
+async fn api_update_folder(
+    headers: axum::http::HeaderMap,
+    Path(folder_id): Path<String>,
+    Json(body): Json<serde_json::Value>,
+) -> impl IntoResponse {
+    if let Some(err) = check_scope(&headers, DeviceScope::Interact) {
+        return err;
+    }
+    let bad_request = |e: &str| (StatusCode::BAD_REQUEST, Json(serde_json::json!({"error": e}))).into_response();
+
+    let mut folder = match load_folders().map(|all| all.into_iter().find(|f| f.id == folder_id)) {
+        Ok(Some(f)) => f,
+        Ok(None) => return (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": "Folder not found"}))).into_response(),
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": e}))).into_response(),
+    };
+
+    if let Some(name) = body.get("name") {
+        match name.as_str().map(str::trim) {
+            Some(n) if !n.is_empty() => folder.name = n.to_string(),
+            _ => return bad_request("name must be a non-empty string"),
+        }
+    }
+    if let Some(collapsed) = body.get("collapsed") {
+        match collapsed.as_bool() {
+            Some(c) => folder.collapsed = c,
+            None => return bad_request("collapsed must be a boolean"),
+        }
+    }
+    if let Some(order) = body.get("sort_order") {
+        match order.as_i64() {
+            Some(o) => folder.sort_order = o as i32,
+            None => return bad_request("sort_order must be an integer"),
+        }
+    }
+
+    if let Err(e) = save_folder(folder.clone()) {
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": e}))).into_response();
+    }
+    broadcast_folder_changed("folder_updated", &folder);
+    if let Some(app) = state::get().app_handle.lock().as_ref() {
+        let _ = app.emit("remote-folder-updated", serde_json::json!({ "folder": folder }));
+    }
+    Json(serde_json::json!(folder)).into_response()
+}
+
+// DELETE /api/folders/{id} - Delete a folder; its sessions become unfiled
+#[cfg(not(target_os = "ios"))]
+async fn api_delete_folder(
+    headers: axum::http::HeaderMap,
+    Path(folder_id): Path<String>,
+) -> impl IntoResponse {
+    if let Some(err) = check_scope(&headers, DeviceScope::Interact) {
+        return err;
+    }
+    let exists = load_folders().map(|all| all.iter().any(|f| f.id == folder_id)).unwrap_or(false);
+    if !exists {
+        return (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": "Folder not found"}))).into_response();
+    }
+    if let Err(e) = delete_folder(folder_id.clone()) {
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": e}))).into_response();
+    }
+    broadcast_folder_deleted(&folder_id);
+    if let Some(app) = state::get().app_handle.lock().as_ref() {
+        let _ = app.emit("remote-folder-deleted", &folder_id);
+    }
+    Json(serde_json::json!({ "status": "deleted" })).into_response()
+}
+
+fn rate_limited_response(retry_after: u64) -> axum::response::Response {
+    let wait = if retry_after >= 60 {
+        format!("{} minutes", retry_after.div_ceil(60))
+    } else {
+        format!("{} seconds", retry_after)
+    };
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(axum::http::header::RETRY_AFTER, retry_after.to_string())],
+        Json(serde_json::json!({
+            "error": "rate_limited",
+            "message": format!("Too many attempts. Try again in {}.", wait),
+            "retry_after": retry_after,
+        })),
+    )
+        .into_response()
+}
+
+// Apply the pairing / auth / message-sending rate limits (see ratelimit.rs).
+// Messages are counted per device when the token is valid, everything else
+// per client IP. Requests from this machine aren't limited.
+async fn rate_limit(
+    axum::extract::ConnectInfo(addr): axum::extract::ConnectInfo<SocketAddr>,
+    matched: axum::extract::MatchedPath,
+    req: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    let Some(bucket) = ratelimit::Bucket::for_route(req.method(), matched.as_str()) else {
+        return next.run(req).await;
+    };
+    let settings = load_app_settings().unwrap_or_default();
+    let Some(limit) = bucket.limit(&settings.rate_limits) else {
+        return next.run(req).await;
+    };
+    if is_local_request(req.headers()) {
+        return next.run(req).await;
+    }
+    let device = match bucket {
+        ratelimit::Bucket::Messages => extract_token(req.headers()).and_then(|t| device_for_token(&t)),
+        _ => None,
+    };
+    let key = match device {
+        Some(d) => format!("device:{}", d.id),
+        None => format!("ip:{}", addr.ip()),
+    };
+    if let Some(retry_after) = ratelimit::retry_after(bucket, &key, limit) {
+        return rate_limited_response(retry_after);
+    }
+
+    let response = next.run(req).await;
+    if bucket.counts(response.status()) {
+        ratelimit::hit(bucket, &key, limit);
+    } else if bucket == ratelimit::Bucket::Auth && response.status().is_success() {
+        ratelimit::clear(bucket, &key);
+    }
+    response
+}
+
+// Audit-log state-changing requests and session/status WebSocket connections
+// that got past auth. The mobile WebSocket authenticates in its first message
+// and records its own actions.
+#[cfg(not(target_os = "ios"))]
+async fn audit_requests(
+    axum::extract::ConnectInfo(addr): axum::extract::ConnectInfo<SocketAddr>,
+    matched: axum::extract::MatchedPath,
+    params: axum::extract::RawPathParams,
+    req: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    let path = matched.as_str();
+    let is_ws = path.contains("/ws/");
+    if (req.method() == axum::http::Method::GET && !is_ws) || path.ends_with("/ws/mobile") {
+        return next.run(req).await;
+    }
+    let action = format!("{} {}", if is_ws { "ws" } else { req.method().as_str() }, path);
+    let query_token = axum::extract::Query::<HashMap<String, String>>::try_from_uri(req.uri())
+        .ok()
+        .and_then(|q| q.0.get("token").cloned());
+    let device = extract_token(req.headers()).or(query_token).and_then(|t| device_for_token(&t));
+    let session_id = params
+        .iter()
+        .find(|(key, _)| *key == "session_id" || (*key == "id" && path.contains("/sessions/")))
+        .map(|(_, value)| value.to_string());
+
+    let response = next.run(req).await;
+    if !matches!(response.status(), StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) {
+        audit::record(device.as_ref(), &action, session_id.as_deref(), Some(addr.ip()));
+    }
+    response
+}
+
+/// Audit log entries, newest first
+#[cfg(not(target_os = "ios"))]
+#[tauri::command]
+fn get_audit_log(
+    device_id: Option<String>,
+    session_id: Option<String>,
+    before: Option<i64>,
+    limit: Option<u32>,
+) -> Result<Vec<audit::AuditEntry>, String> {
+    audit::list(&audit::AuditQuery { device_id, session_id, before, limit })
+}
+
+// GET /api/audit - Audit log, newest first (?device_id=&session_id=&before=&limit=)
+#[cfg(not(target_os = "ios"))]
+async fn api_audit_log(
+    headers: axum::http::HeaderMap,
+    axum::extract::Query(query): axum::extract::Query<audit::AuditQuery>,
+) -> impl IntoResponse {
+    if let Some(err) = check_scope(&headers, DeviceScope::Admin) {
+        return err;
+    }
+    match audit::list(&query) {
+        Ok(entries) => Json(entries).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": e}))).into_response(),
+    }
+}
+
+/// Recent log lines, oldest first; `level` keeps that level and more severe
+#[tauri::command]
+fn get_recent_logs(level: Option<String>, limit: Option<usize>) -> Result<Vec<logging::LogLine>, String> {
+    logging::recent(level.as_deref(), limit)
+}
+
+#[cfg(not(target_os = "ios"))]
+#[derive(Deserialize)]
+struct LogsQuery {
+    level: Option<String>,
+    limit: Option<usize>,
+}
+
+// GET /api/logs - Recent app log lines, oldest first (?level=warn&limit=200)
+#[cfg(not(target_os = "ios"))]
+async fn api_logs(
+    headers: axum::http::HeaderMap,
+    axum::extract::Query(query): axum::extract::Query<LogsQuery>,
+) -> impl IntoResponse {
+    if let Some(err) = check_scope(&headers, DeviceScope::Admin) {
+        return err;
+    }
+    match logging::recent(query.level.as_deref(), query.limit) {
+        Ok(lines) => Json(lines).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, Json(serde_json::json!({"error": e}))).into_response(),
+    }
+}
+
+// GET /api/webhooks - Configured webhooks, without their secrets
+#[cfg(not(target_os = "ios"))]
+async fn api_list_webhooks(headers: axum::http::HeaderMap) -> impl IntoResponse {
+    if let Some(err) = check_scope(&headers, DeviceScope::Admin) {
+        return err;
+    }
+    match webhooks::list_webhooks() {
+        Ok(all) => Json(all.iter().map(webhooks::Webhook::public_json).collect::<Vec<_>>()).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": e}))).into_response(),
+    }
+}
+
+// GET /api/devices - Paired devices; `current` marks the caller
+#[cfg(not(target_os = "ios"))]
+async fn api_list_devices(headers: axum::http::HeaderMap) -> impl IntoResponse {
+    if let Some(err) = check_auth(&headers) {
+        return err.into_response();
+    }
+    let current = extract_token(&headers).and_then(|t| device_for_token(&t)).map(|d| d.id);
+    let devices: Vec<serde_json::Value> = list_paired_devices()
+        .into_iter()
+        .map(|d| {
+            let is_current = current.as_deref() == Some(d.id.as_str());
+            let undelivered = message_log::undelivered_count(&d.id);
+            let mut value = serde_json::to_value(d).unwrap_or_default();
+            value["current"] = serde_json::json!(is_current);
+            value["undelivered_count"] = serde_json::json!(undelivered);
+            value
+        })
+        .collect();
+    Json(devices).into_response()
+}
+
+// DELETE /api/devices/{id} - Revoke a device; its connections are closed
+#[cfg(not(target_os = "ios"))]
+async fn api_revoke_device(
+    headers: axum::http::HeaderMap,
+    Path(device_id): Path<String>,
+) -> impl IntoResponse {
+    if let Some(err) = check_scope(&headers, DeviceScope::Admin) {
+        return err;
+    }
+    match revoke_device(device_id) {
+        Ok(()) => Json(serde_json::json!({ "status": "revoked" })).into_response(),
+        Err(e) => (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": e}))).into_response(),
+    }
+}
+
+// PATCH /api/devices/{id} - Change a device's scope. Body: { "scope": "read_only" | "interact" | "admin" }
+#[cfg(not(target_os = "ios"))]
+async fn api_update_device(
+    headers: axum::http::HeaderMap,
+    Path(device_id): Path<String>,
+    Json(body): Json<serde_json::Value>,
+) -> impl IntoResponse {
+    if let Some(err) = check_scope(&headers, DeviceScope::Admin) {
+        return err;
+    }
+    let Some(scope) = body.get("scope").and_then(|v| v.as_str()).and_then(DeviceScope::parse) else {
+        return (StatusCode::BAD_REQUEST, Json(serde_json::json!({
+            "error": "scope must be read_only, interact or admin"
+        }))).into_response();
+    };
+    match set_device_scope(device_id, scope) {
+        Ok(()) => Json(serde_json::json!({ "scope": scope })).into_response(),
+        Err(e) => (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": e}))).into_response(),
+    }
+}
+
+// GET /api/hubs - Remote hubs whose sessions are listed here
+async fn api_list_hubs(headers: axum::http::HeaderMap) -> impl IntoResponse {
+    if let Some(err) = check_auth(&headers) {
+        return err.into_response();
+    }
+    match hubs::list() {
+        Ok(list) => Json(list).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": e}))).into_response(),
+    }
+}
+
+// POST /api/hubs - Register a remote hub. Only from this computer, since it
+// hands over a credential and points this hub at another machine.
+async fn api_add_hub(
+    headers: axum::http::HeaderMap,
+    Json(body): Json<serde_json::Value>,
+) -> impl IntoResponse {
+    if !is_local_request(&headers) {
+        return (StatusCode::FORBIDDEN, Json(serde_json::json!({
+            "error": "forbidden",
+            "message": "Remote hubs can only be added on this computer"
+        }))).into_response();
+    }
+    let field = |name: &str| body.get(name).and_then(|v| v.as_str()).unwrap_or_default().to_string();
+    match hubs::add(&field("name"), &field("url"), &field("token")).await {
+        Ok(hub) => (StatusCode::CREATED, Json(hub)).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, Json(serde_json::json!({"error": e}))).into_response(),
+    }
+}
+
+// DELETE /api/hubs/:id - Forget a remote hub and its token (this computer only)
+async fn api_remove_hub(
+    headers: axum::http::HeaderMap,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    if !is_local_request(&headers) {
+        return (StatusCode::FORBIDDEN, Json(serde_json::json!({
+            "error": "forbidden",
+            "message": "Remote hubs can only be removed on this computer"
+        }))).into_response();
+    }
+    match hubs::remove(&id) {
+        Ok(()) => Json(serde_json::json!({"success": true})).into_response(),
+        Err(e) => (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": e}))).into_response(),
+    }
+}
+
+// GET /api/hubs/sessions - Sessions here and on every remote hub, tagged by host
+async fn api_hub_sessions(headers: axum::http::HeaderMap) -> impl IntoResponse {
+    if let Some(err) = check_auth(&headers) {
+        return err.into_response();
+    }
+    match listed_sessions() {
+        Ok(local) => Json(hubs::merged_sessions(local).await).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": e}))).into_response(),
+    }
+}
+
+// ANY /api/hubs/:id/api/*path - A remote hub's API, called with its token.
+// Reads need read-only access here, anything else interact.
+async fn api_hub_proxy(
+    headers: axum::http::HeaderMap,
+    method: axum::http::Method,
+    Path((id, path)): Path<(String, String)>,
+    axum::extract::RawQuery(query): axum::extract::RawQuery,
+    body: axum::body::Bytes,
+) -> axum::response::Response {
+    let required = if method == axum::http::Method::GET { DeviceScope::ReadOnly } else { DeviceScope::Interact };
+    if let Some(err) = check_scope(&headers, required) {
+        return err;
+    }
+    let Some(hub) = hubs::get(&id) else {
+        return (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": "hub not found"}))).into_response();
+    };
+    let path = match query {
+        Some(query) => format!("{}?{}", path, query),
+        None => path,
+    };
+    let content_type = headers.get(axum::http::header::CONTENT_TYPE).and_then(|v| v.to_str().ok());
+    match hubs::forward(&hub, method, &path, content_type, body.to_vec()).await {
+        Ok((status, content_type, body)) => {
+            let mut response = (StatusCode::from_u16(status).unwrap_or(StatusCode::BAD_GATEWAY), body).into_response();
+            if let Some(value) = content_type.and_then(|v| axum::http::HeaderValue::from_str(&v).ok()) {
+                response.headers_mut().insert(axum::http::header::CONTENT_TYPE, value);
+            }
+            response
+        }
+        Err(e) => (StatusCode::BAD_GATEWAY, Json(serde_json::json!({"error": e}))).into_response(),
+    }
+}
+
+// GET /api/hubs/:id/ws/mobile - A remote hub's mobile WebSocket, relayed.
+// Authenticates with an auth message like /api/ws/mobile.
+async fn ws_hub_mobile_handler(Path(id): Path<String>, ws: WebSocketUpgrade) -> impl IntoResponse {
+    let Some(hub) = hubs::get(&id) else {
+        return (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": "hub not found"}))).into_response();
+    };
+    ws.on_upgrade(move |socket| {
+        hubs::relay_ws(socket, hub, |token| {
+            let no_devices = state::get().paired_devices.lock().is_empty();
+            let device = device_for_token(token);
+            (no_devices || device.is_some()).then(|| {
+                touch_token(token);
+                device.map(|d| d.scope).unwrap_or(DeviceScope::Admin)
+            })
+        })
+    })
+    .into_response()
+}
+
+// GET /api/push/vapid-key - Public key for browser push subscriptions (applicationServerKey)
+#[cfg(not(target_os = "ios"))]
+async fn api_push_vapid_key(headers: axum::http::HeaderMap) -> impl IntoResponse {
+    if let Some(err) = check_auth(&headers) {
+        return err.into_response();
+    }
+    match tokio::task::spawn_blocking(push::vapid_public_key).await.unwrap_or_else(|e| Err(e.to_string())) {
+        Ok(key) => Json(serde_json::json!({ "public_key": key })).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": e}))).into_response(),
+    }
+}
+
+// GET/PUT/DELETE /api/push/subscription - The calling device's push subscription
+// PUT body: a browser PushSubscription ({ endpoint, keys: { p256dh, auth } }),
+// or { "kind": "ntfy" | "webhook", "endpoint": "..." }
+#[cfg(not(target_os = "ios"))]
+async fn api_push_subscription(
+    method: axum::http::Method,
+    headers: axum::http::HeaderMap,
+    body: Option<Json<serde_json::Value>>,
+) -> impl IntoResponse {
+    if let Some(err) = check_auth(&headers) {
+        return err.into_response();
+    }
+    let Some(device) = extract_token(&headers).and_then(|t| device_for_token(&t)) else {
+        return (StatusCode::BAD_REQUEST, Json(serde_json::json!({
+            "error": "Push subscriptions belong to a paired device"
+        }))).into_response();
+    };
+    let result = match method {
+        axum::http::Method::PUT => {
+            let body = body.map(|Json(b)| b).unwrap_or_default();
+            match push::subscribe(&device.id, &body) {
+                Ok(sub) => Ok(serde_json::json!(sub)),
+                Err(e) => return (StatusCode::BAD_REQUEST, Json(serde_json::json!({"error": e}))).into_response(),
+            }
+        }
+        axum::http::Method::DELETE => {
+            push::unsubscribe(&device.id);
+            Ok(serde_json::json!({ "status": "unsubscribed" }))
+        }
+        _ => push::get(&device.id).map(|sub| serde_json::json!(sub)),
+    };
+    match result {
+        Ok(value) => Json(value).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": e}))).into_response(),
+    }
+}
+
+// POST /api/sessions/{id}/children - Spawn a child session of {id} in another working dir
+// Body: { "working_dir": "...", "name"?: "...", "prompt"?: "..." }
+#[cfg(not(target_os = "ios"))]
+async fn api_create_child_session(
+    headers: axum::http::HeaderMap,
+    Path(parent_id): Path<String>,
+    Json(body): Json<serde_json::Value>,
+) -> impl IntoResponse {
+    // Agents call this through the local MCP bridge, which has no token;
+    // anything not coming from this machine still has to authenticate.
+    if let Some(err) = check_local_or_auth(&headers, DeviceScope::Interact) {
+        return err;
+    }
+    let Some(working_dir) = body.get("working_dir").and_then(|v| v.as_str()) else {
+        return (StatusCode::BAD_REQUEST, Json(serde_json::json!({"error": "working_dir is required"}))).into_response();
+    };
+    let name = body.get("name").and_then(|v| v.as_str()).map(|s| s.to_string());
+    let prompt = body.get("prompt").and_then(|v| v.as_str()).map(|s| s.to_string());
+
+    let working_dir = working_dir.to_string();
+    let result = tokio::task::spawn_blocking(move || {
+        create_child_session(&parent_id, &working_dir, name, prompt)
+    }).await.unwrap_or_else(|e| Err(e.to_string()));
+
+    match result {
+        Ok(session) => Json(serde_json::json!({
+            "id": session.id,
+            "name": session.name,
+            "agent_type": session.agent_type,
+            "working_dir": session.working_dir,
+            "folder_id": session.folder_id,
+            "parent_id": session.parent_id,
+            "running": state::get().json_processes.lock().contains_key(&session.id),
+        })).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, Json(serde_json::json!({"error": e}))).into_response(),
+    }
+}
+
+// POST /api/sessions/{id}/audio - Transcribe a voice recording and send it as a prompt
+//
+// Body: the raw audio (Content-Type e.g. audio/webm). Responds with { "text" }.
+#[cfg(not(target_os = "ios"))]
+async fn api_session_audio(
+    headers: axum::http::HeaderMap,
+    Path(session_id): Path<String>,
+    body: axum::body::Bytes,
+) -> impl IntoResponse {
+    if let Some(err) = check_scope(&headers, DeviceScope::Interact) {
+        return err;
+    }
+    if let Some(err) = check_session_remote(&session_id) {
+        return err;
+    }
+    if body.is_empty() {
+        return (StatusCode::BAD_REQUEST, Json(serde_json::json!({"error": "empty audio"}))).into_response();
+    }
+    let content_type = headers
+        .get(axum::http::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.to_string());
+
+    let result = tokio::task::spawn_blocking(move || {
+        let text = transcribe::transcribe(&body, content_type.as_deref())?;
+        if text.is_empty() {
+            return Err("No speech recognized".to_string());
         }
-        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+        ensure_json_session_running(&session_id)?;
+        send_user_prompt(&session_id, &text)?;
+        Ok(text)
+    }).await.unwrap_or_else(|e| Err(e.to_string()));
+
+    match result {
+        Ok(text) => Json(serde_json::json!({ "text": text })).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, Json(serde_json::json!({"error": e}))).into_response(),
     }
 }
 
-// POST /api/sessions - Create a new session
-async fn api_create_session(
+// POST /api/sessions/{id}/attachments?text=... - Upload an image and send it to the session
+//
+// Body: the raw image bytes. `text` is an optional caption sent with it.
+#[cfg(not(target_os = "ios"))]
+async fn api_upload_attachment(
     headers: axum::http::HeaderMap,
-    Json(body): Json<serde_json::Value>,
+    Path(session_id): Path<String>,
+    axum::extract::Query(params): axum::extract::Query<HashMap<String, String>>,
+    body: axum::body::Bytes,
 ) -> impl IntoResponse {
-    if let Some(err) = check_auth(&headers) {
-        return err.into_response();
+    if let Some(err) = check_scope(&headers, DeviceScope::Interact) {
+        return err;
     }
+    if let Some(err) = check_session_remote(&session_id) {
+        return err;
+    }
+    let result = tokio::task::spawn_blocking(move || {
+        let attachment = attachments::save_attachment(&session_id, &body)?;
+        ensure_json_session_running(&session_id)?;
+        let content = attachments::content_blocks(&body, &attachment.media_type, params.get("text").map(|s| s.as_str()));
+        send_user_content(&session_id, content)?;
+        Ok::<_, String>(attachment)
+    }).await.unwrap_or_else(|e| Err(e.to_string()));
 
-    let name = body.get("name").and_then(|v| v.as_str()).map(|s| s.to_string());
-    let agent_type = body.get("agent_type").and_then(|v| v.as_str()).unwrap_or("claude");
-    let custom_command = body.get("custom_command").and_then(|v| v.as_str()).map(|s| s.to_string());
-    let working_dir = body.get("working_dir").and_then(|v| v.as_str()).unwrap_or("~/dev/pplsi");
-    let env_vars = body.get("env_vars").and_then(|v| {
-        if v.is_object() { Some(v.to_string()) } else { v.as_str().map(|s| s.to_string()) }
-    });
+    match result {
+        Ok(attachment) => Json(serde_json::json!(attachment)).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, Json(serde_json::json!({"error": e}))).into_response(),
+    }
+}
 
-    // Generate session ID
-    let session_id = generate_token();
+// POST /api/run - Run a prompt through a transient claude process
+//
+// Body: { "prompt", "working_dir"?, "model"?, "persist"?, "name"?, "timeout_secs"?, "stream"? }
+// With stream (default) the CLI's messages are sent as SSE `message` events
+// followed by a `done` event carrying the run record; otherwise the request
+// blocks and returns the record.
+#[cfg(not(target_os = "ios"))]
+async fn api_run(
+    headers: axum::http::HeaderMap,
+    Json(body): Json<serde_json::Value>,
+) -> axum::response::Response {
+    use axum::response::sse::{Event, KeepAlive, Sse};
 
-    // Determine command based on agent type
-    let command = match agent_type {
-        "claude" => "claude --dangerously-skip-permissions".to_string(),
-        "claude-json" => "claude --print --verbose --input-format stream-json --output-format stream-json --dangerously-skip-permissions".to_string(),
-        "aider" => "aider".to_string(),
-        "shell" => std::env::var("SHELL").unwrap_or_else(|_| "/bin/zsh".to_string()),
-        "custom" => custom_command.clone().unwrap_or_else(|| "/bin/zsh".to_string()),
-        _ => "claude --dangerously-skip-permissions".to_string(),
+    if let Some(err) = check_scope(&headers, DeviceScope::Interact) {
+        return err;
+    }
+    let stream = body.get("stream").and_then(|v| v.as_bool()).unwrap_or(true);
+    let req: runs::RunRequest = match serde_json::from_value(body) {
+        Ok(r) => r,
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(serde_json::json!({"error": e.to_string()}))).into_response(),
     };
-
-    // Generate auto-name if not provided
-    let session_name = name.unwrap_or_else(|| {
-        // Get count of existing sessions for auto-numbering
-        let count = load_sessions().map(|s| s.len()).unwrap_or(0);
-        let agent_label = match agent_type {
-            "claude" => "Claude",
-            "claude-json" => "Claude Chat",
-            "aider" => "Aider",
-            "shell" => "Shell",
-            "custom" => "Custom",
-            _ => "Session",
-        };
-        format!("{} {}", agent_label, count + 1)
-    });
-
-    // Don't pre-generate claude_session_id - it will be captured from Claude's output
-    // when the session is first started. Only existing sessions with saved IDs should resume.
-    let claude_session_id: Option<String> = None;
-
-    // Calculate sort order (put new sessions at top)
-    let min_sort_order = load_sessions()
-        .map(|sessions| sessions.iter().map(|s| s.sort_order).min().unwrap_or(0))
-        .unwrap_or(0);
-
-    let folder_id = body.get("folder_id").and_then(|v| v.as_str()).map(|s| s.to_string());
-
-    let session = SessionData {
-        id: session_id.clone(),
-        name: session_name,
-        agent_type: agent_type.to_string(),
-        command,
-        working_dir: working_dir.to_string(),
-        created_at: chrono::Utc::now().to_rfc3339(),
-        claude_session_id,
-        sort_order: min_sort_order - 1,
-        folder_id,
-        env_vars,
+    let (run_id, mut rx) = match runs::start(req) {
+        Ok(started) => started,
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(serde_json::json!({"error": e}))).into_response(),
     };
 
-    // Save to database
-    if let Err(e) = save_session(session.clone()) {
-        return (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({
-            "error": e
-        }))).into_response();
+    if !stream {
+        while let Some(event) = rx.recv().await {
+            if let runs::RunEvent::Done(record) = event {
+                return Json(serde_json::json!(record)).into_response();
+            }
+        }
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": "run ended unexpectedly", "id": run_id}))).into_response();
     }
 
-    // Notify desktop app about new session
-    if let Some(app) = APP_HANDLE.lock().as_ref() {
-        let _ = app.emit("remote-session-created", serde_json::json!({
-            "session": {
-                "id": session.id,
-                "name": session.name,
-                "agent_type": session.agent_type,
-                "working_dir": session.working_dir,
-            }
-        }));
+    let events = futures::stream::unfold(rx, |mut rx| async move {
+        let event = match rx.recv().await? {
+            runs::RunEvent::Message(msg) => Event::default().event("message").data(msg.to_string()),
+            runs::RunEvent::Done(record) => Event::default().event("done").data(serde_json::json!(record).to_string()),
+        };
+        Some((Ok::<_, std::convert::Infallible>(event), rx))
+    });
+    let mut response = Sse::new(events).keep_alive(KeepAlive::default()).into_response();
+    if let Ok(value) = axum::http::HeaderValue::from_str(&run_id) {
+        response.headers_mut().insert("x-run-id", value);
     }
+    response
+}
 
-    Json(serde_json::json!({
-        "id": session.id,
-        "name": session.name,
-        "agent_type": session.agent_type,
-        "command": session.command,
-        "working_dir": session.working_dir,
-        "folder_id": session.folder_id,
-        "running": false
-    })).into_response()
+// GET /api/run/{id} - Status, result and usage of a headless run
+#[cfg(not(target_os = "ios"))]
+async fn api_get_run(
+    headers: axum::http::HeaderMap,
+    Path(run_id): Path<String>,
+) -> impl IntoResponse {
+    if let Some(err) = check_auth(&headers) {
+        return err.into_response();
+    }
+    match runs::get_run(&run_id) {
+        Ok(Some(record)) => Json(serde_json::json!(record)).into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": "run not found"}))).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": e}))).into_response(),
+    }
 }
 
 // GET /api/sessions/{id}/buffer - Get saved terminal buffer for a session
@@ -3457,6 +7029,9 @@ async fn api_get_buffer(
     if let Some(err) = check_auth(&headers) {
         return err.into_response();
     }
+    if let Some(err) = check_session_remote(&session_id) {
+        return err;
+    }
     match load_terminal_buffer(session_id) {
         Ok(Some(buffer)) => Json(serde_json::json!({ "buffer": buffer })).into_response(),
         Ok(None) => Json(serde_json::json!({ "buffer": null })).into_response(),
@@ -3464,27 +7039,62 @@ async fn api_get_buffer(
     }
 }
 
+/// Whether the session has a process, PTY or claude-json
+#[cfg(not(target_os = "ios"))]
+fn is_session_running(session_id: &str) -> bool {
+    state::get().pty_broadcasters.lock().contains_key(session_id) || state::get().json_broadcasters.lock().contains_key(session_id)
+}
+
+/// Start a stopped session as the desktop would: a claude-json session
+/// resumes its Claude conversation when one is recorded, a terminal gets a
+/// PTY at a default size that the first viewer resizes. Used by the REST API
+/// and the MCP tools.
+#[cfg(not(target_os = "ios"))]
+fn start_session(session: SessionData) -> Result<(), String> {
+    let app = state::get().app_handle.lock().clone().ok_or_else(|| "App not initialized".to_string())?;
+    let should_resume = session.claude_session_id.is_some();
+    if session.agent_type == "claude-json" {
+        spawn_json_process(
+            app.clone(),
+            session.id.clone(),
+            session.command,
+            Some(session.working_dir),
+            session.claude_session_id,
+            Some(should_resume),
+            session.env_vars.clone(),
+        )?;
+    } else {
+        spawn_pty(
+            app.clone(),
+            session.id.clone(),
+            Some(session.command),
+            Some(session.working_dir),
+            120,  // default cols
+            30,   // default rows
+            session.claude_session_id,
+            Some(should_resume),
+            session.env_vars,
+        )?;
+    }
+    // Notify desktop app that session was started remotely
+    let _ = app.emit("remote-session-started", session.id);
+    Ok(())
+}
+
 // POST /api/sessions/{id}/start - Start a session remotely
 #[cfg(not(target_os = "ios"))]
 async fn api_start_session(
     headers: axum::http::HeaderMap,
     Path(session_id): Path<String>,
 ) -> impl IntoResponse {
-    if let Some(err) = check_auth(&headers) {
-        return err.into_response();
+    if let Some(err) = check_scope(&headers, DeviceScope::Interact) {
+        return err;
     }
-    // Check if already running (PTY or JSON)
-    {
-        let pty_broadcasters = PTY_BROADCASTERS.lock();
-        if pty_broadcasters.contains_key(&session_id) {
-            return Json(serde_json::json!({ "status": "already_running" })).into_response();
-        }
+    if let Some(err) = check_session_remote(&session_id) {
+        return err;
     }
-    {
-        let json_broadcasters = JSON_BROADCASTERS.lock();
-        if json_broadcasters.contains_key(&session_id) {
-            return Json(serde_json::json!({ "status": "already_running" })).into_response();
-        }
+    if is_session_running(&session_id) {
+        return Json(serde_json::json!({ "status": "already_running" })).into_response();
     }
 
     // Get session from database
@@ -3497,66 +7107,9 @@ async fn api_start_session(
         return (StatusCode::NOT_FOUND, "Session not found").into_response();
     };
 
-    // Get AppHandle
-    let app = {
-        let handle = APP_HANDLE.lock();
-        handle.clone()
-    };
-
-    let Some(app) = app else {
-        return (StatusCode::INTERNAL_SERVER_ERROR, "App not initialized").into_response();
-    };
-
-    // Check if this is a JSON session (claude-json)
-    let is_json_session = session.agent_type == "claude-json";
-
-    if is_json_session {
-        // Check if JSON process already running
-        {
-            let broadcasters = JSON_BROADCASTERS.lock();
-            if broadcasters.contains_key(&session_id) {
-                return Json(serde_json::json!({ "status": "already_running" })).into_response();
-            }
-        }
-
-        // Spawn JSON process for chat sessions
-        let should_resume = session.claude_session_id.is_some();
-        match spawn_json_process(
-            app.clone(),
-            session.id.clone(),
-            session.command,
-            Some(session.working_dir),
-            session.claude_session_id,
-            Some(should_resume),
-            session.env_vars.clone(),
-        ) {
-            Ok(()) => {
-                let _ = app.emit("remote-session-started", session.id.clone());
-                Json(serde_json::json!({ "status": "started", "session_id": session.id })).into_response()
-            }
-            Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
-        }
-    } else {
-        // Spawn PTY for terminal sessions (use default terminal size, will be resized on connect)
-        let should_resume = session.claude_session_id.is_some();
-        match spawn_pty(
-            app.clone(),
-            session.id.clone(),
-            Some(session.command),
-            Some(session.working_dir),
-            120,  // default cols
-            30,   // default rows
-            session.claude_session_id,
-            Some(should_resume),
-            session.env_vars,
-        ) {
-            Ok(()) => {
-                // Notify desktop app that session was started remotely
-                let _ = app.emit("remote-session-started", session.id.clone());
-                Json(serde_json::json!({ "status": "started", "session_id": session.id })).into_response()
-            }
-            Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
-        }
+    match start_session(session) {
+        Ok(()) => Json(serde_json::json!({ "status": "started", "session_id": session_id })).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
     }
 }
 
@@ -3566,8 +7119,8 @@ async fn api_start_session(
     headers: axum::http::HeaderMap,
     Path(_session_id): Path<String>,
 ) -> impl IntoResponse {
-    if let Some(err) = check_auth(&headers) {
-        return err.into_response();
+    if let Some(err) = check_scope(&headers, DeviceScope::Interact) {
+        return err;
     }
     (StatusCode::NOT_IMPLEMENTED, Json(serde_json::json!({
         "error": "not_supported",
@@ -3578,19 +7131,25 @@ async fn api_start_session(
 // POST /api/webhook/teams - Receive a Teams issue and create a claude-json session
 //
 // Payload: { "from": "...", "message": "...", "link": "..." }
-// Auth:    X-Webhook-Secret header checked against AGENT_HUB_WEBHOOK_SECRET env var (if set)
+// Auth:    X-Webhook-Secret header checked against AGENT_HUB_WEBHOOK_SECRET env var,
+//          or the "webhook-secret" item in the secret store (if either is set)
 #[cfg(not(target_os = "ios"))]
 async fn api_webhook_teams(
     headers: axum::http::HeaderMap,
     body: axum::body::Bytes,
 ) -> impl IntoResponse {
-    let secret = std::env::var("AGENT_HUB_WEBHOOK_SECRET").unwrap_or_default();
+    let secret = std::env::var("AGENT_HUB_WEBHOOK_SECRET")
+        .ok()
+        .filter(|s| !s.is_empty())
+        .or_else(|| secrets::get(secrets::WEBHOOK_SECRET))
+        .unwrap_or_default();
     if !secret.is_empty() {
+        use subtle::ConstantTimeEq;
         let provided = headers
             .get("x-webhook-secret")
             .and_then(|v| v.to_str().ok())
             .unwrap_or("");
-        if provided != secret {
+        if !bool::from(provided.as_bytes().ct_eq(secret.as_bytes())) {
             return (StatusCode::UNAUTHORIZED, Json(serde_json::json!({"error": "invalid secret"}))).into_response();
         }
     }
@@ -3605,10 +7164,11 @@ async fn api_webhook_teams(
     }
 
     const TEAMS_SESSION_NAME: &str = "Teams Issues";
-    let command = "claude --print --verbose --input-format stream-json --output-format stream-json --dangerously-skip-permissions".to_string();
-    let working_dir = std::env::var("AGENT_HUB_WEBHOOK_WORKDIR").unwrap_or_else(|_| "~/dev/pplsi".to_string());
+    let command = agent_commands::command_for("claude-json")
+        .unwrap_or_else(|| agent_commands::CLAUDE_JSON_COMMAND.to_string());
+    let working_dir = std::env::var("AGENT_HUB_WEBHOOK_WORKDIR").unwrap_or_else(|_| platform::DEFAULT_WORKING_DIR.to_string());
 
-    let app = { APP_HANDLE.lock().clone() };
+    let app = { state::get().app_handle.lock().clone() };
     let Some(app) = app else {
         return (StatusCode::INTERNAL_SERVER_ERROR, "App not initialized").into_response();
     };
@@ -3637,6 +7197,10 @@ async fn api_webhook_teams(
                 sort_order: min_sort_order - 1,
                 folder_id: None,
                 env_vars: None,
+                parent_id: None,
+                summary: None,
+                remote_access: true,
+                workspace_id: None,
             };
             if let Err(e) = save_session(session.clone()) {
                 return (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": e}))).into_response();
@@ -3653,83 +7217,246 @@ async fn api_webhook_teams(
         }
     };
 
-    // Start the process if not already running
-    let is_running = { JSON_BROADCASTERS.lock().contains_key(&session_id) };
-    if !is_running {
-        if let Err(e) = spawn_json_process(app.clone(), session_id.clone(), command, Some(working_dir), None, Some(false), None) {
-            return (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": e}))).into_response();
-        }
-        let _ = app.emit("remote-session-started", session_id.clone());
-        tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+    // Start the process if not already running
+    let is_running = { state::get().json_broadcasters.lock().contains_key(&session_id) };
+    if !is_running {
+        if let Err(e) = spawn_json_process(app.clone(), session_id.clone(), command, Some(working_dir), None, Some(false), None) {
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": e}))).into_response();
+        }
+        let _ = app.emit("remote-session-started", session_id.clone());
+        tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+    }
+
+    let link_line = if link.is_empty() { String::new() } else { format!("\nLink: {}", link) };
+    let text = format!(
+        "/process-teams-issue\n\nFrom: {}{}\n---\n{}",
+        from_name, link_line, message
+    );
+    let prompt = serde_json::json!({
+        "type": "user",
+        "message": { "role": "user", "content": text }
+    }).to_string() + "\n";
+
+    if let Err(e) = write_to_process(session_id.clone(), prompt) {
+        tracing::error!("[webhook] write_to_process failed for {}: {}", session_id, e);
+    }
+
+    Json(serde_json::json!({"status": "ok", "session_id": session_id})).into_response()
+}
+
+/// Stop the current turn: SIGINT to a claude-json process, Ctrl+C to a
+/// terminal. Used by the REST API and the MCP tools.
+#[cfg(not(target_os = "ios"))]
+fn interrupt_session(session_id: &str) -> Result<(), String> {
+    if state::get().json_broadcasters.lock().contains_key(session_id) {
+        restarts::mark_stopped(session_id);
+        let processes = state::get().json_processes.lock();
+        let process = processes.get(session_id).filter(|p| p.child_id > 0).ok_or("Process not found")?;
+        unsafe {
+            libc::kill(process.child_id as i32, libc::SIGINT);
+        }
+        return Ok(());
+    }
+    if state::get().pty_broadcasters.lock().contains_key(session_id) {
+        let sessions = state::get().pty_sessions.lock();
+        let session = sessions.get(session_id).ok_or("PTY session not found")?;
+        let mut session = session.lock();
+        // Send ETX (Ctrl+C)
+        let _ = session.writer.write_all(&[0x03]);
+        let _ = session.writer.flush();
+        return Ok(());
+    }
+    Err("Session not running".to_string())
+}
+
+// POST /api/sessions/{id}/interrupt - Interrupt a running session
+#[cfg(not(target_os = "ios"))]
+async fn api_interrupt_session(
+    headers: axum::http::HeaderMap,
+    Path(session_id): Path<String>,
+) -> impl IntoResponse {
+    if let Some(err) = check_scope(&headers, DeviceScope::Interact) {
+        return err;
+    }
+    if let Some(err) = check_session_remote(&session_id) {
+        return err;
+    }
+    match interrupt_session(&session_id) {
+        Ok(()) => Json(serde_json::json!({ "status": "interrupted" })).into_response(),
+        Err(e) => (StatusCode::NOT_FOUND, e).into_response(),
+    }
+}
+
+// GET /api/asks - Questions and approval requests agents are waiting on
+// (?session_id= for one session's)
+#[cfg(not(target_os = "ios"))]
+async fn api_list_asks(
+    headers: axum::http::HeaderMap,
+    axum::extract::Query(query): axum::extract::Query<HashMap<String, String>>,
+) -> impl IntoResponse {
+    if let Some(err) = check_scope(&headers, DeviceScope::ReadOnly) {
+        return err;
+    }
+    let asks: Vec<asks::Ask> = asks::pending(query.get("session_id").map(String::as_str))
+        .into_iter()
+        .filter(|ask| !is_remote_hidden(&ask.session_id))
+        .collect();
+    Json(asks).into_response()
+}
+
+// POST /api/asks/{id}/answer - Answer one: { "text": "..." } for a question,
+// { "approved": true|false, "text"?: "..." } for an approval
+#[cfg(not(target_os = "ios"))]
+async fn api_answer_ask(
+    headers: axum::http::HeaderMap,
+    Path(ask_id): Path<String>,
+    Json(answer): Json<asks::Answer>,
+) -> impl IntoResponse {
+    if let Some(err) = check_scope(&headers, DeviceScope::Interact) {
+        return err;
+    }
+    let Some(session_id) = asks::session_of(&ask_id) else {
+        return (StatusCode::NOT_FOUND, Json(serde_json::json!({ "error": "Ask not found" }))).into_response();
+    };
+    if let Some(err) = check_session_remote(&session_id) {
+        return err;
+    }
+    match asks::answer(&ask_id, answer) {
+        Ok(()) => Json(serde_json::json!({ "status": "answered" })).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, Json(serde_json::json!({ "error": e }))).into_response(),
+    }
+}
+
+// POST /api/sessions/{id}/input - Write to a PTY session's terminal
+//
+// Body: { "text": "ls\r" } writes the text as-is; { "text": "c", "is_control": true }
+// sends the control character for that key (here Ctrl+C).
+#[cfg(not(target_os = "ios"))]
+async fn api_session_input(
+    headers: axum::http::HeaderMap,
+    Path(session_id): Path<String>,
+    Json(body): Json<serde_json::Value>,
+) -> impl IntoResponse {
+    if let Some(err) = check_scope(&headers, DeviceScope::Interact) {
+        return err;
+    }
+    if let Some(err) = check_session_remote(&session_id) {
+        return err;
+    }
+    let Some(text) = body.get("text").and_then(|v| v.as_str()) else {
+        return (StatusCode::BAD_REQUEST, Json(serde_json::json!({"error": "text is required"}))).into_response();
+    };
+    let data = if body.get("is_control").and_then(|v| v.as_bool()).unwrap_or(false) {
+        // Ctrl+<key> clears the upper bits: Ctrl+C = 0x03, Ctrl+[ = ESC
+        let mut chars = text.chars();
+        match (chars.next().map(|c| c.to_ascii_uppercase()), chars.next()) {
+            (Some(c @ '@'..='_'), None) => char::from((c as u8) & 0x1f).to_string(),
+            _ => {
+                return (StatusCode::BAD_REQUEST, Json(serde_json::json!({"error": "is_control needs a single key, e.g. \"c\""}))).into_response()
+            }
+        }
+    } else {
+        text.to_string()
+    };
+
+    if !state::get().pty_sessions.lock().contains_key(&session_id) {
+        return (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": "PTY session not running"}))).into_response();
     }
-
-    let link_line = if link.is_empty() { String::new() } else { format!("\nLink: {}", link) };
-    let text = format!(
-        "/process-teams-issue\n\nFrom: {}{}\n---\n{}",
-        from_name, link_line, message
-    );
-    let prompt = serde_json::json!({
-        "type": "user",
-        "message": { "role": "user", "content": text }
-    }).to_string() + "\n";
-
-    if let Err(e) = write_to_process(session_id.clone(), prompt) {
-        eprintln!("[webhook] write_to_process failed for {}: {}", session_id, e);
+    match write_pty(session_id, data) {
+        Ok(()) => Json(serde_json::json!({ "status": "sent" })).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": e}))).into_response(),
     }
-
-    Json(serde_json::json!({"status": "ok", "session_id": session_id})).into_response()
 }
 
-// POST /api/sessions/{id}/interrupt - Interrupt a running session
+// POST /api/sessions/{id}/prompt - Send a prompt: { "text": "..." }
+//
+// A chat session is started first if it isn't running; a terminal session
+// must be running and gets the text followed by Enter.
 #[cfg(not(target_os = "ios"))]
-async fn api_interrupt_session(
+async fn api_session_prompt(
     headers: axum::http::HeaderMap,
     Path(session_id): Path<String>,
+    Json(body): Json<serde_json::Value>,
 ) -> impl IntoResponse {
-    if let Some(err) = check_auth(&headers) {
-        return err.into_response();
+    if let Some(err) = check_scope(&headers, DeviceScope::Interact) {
+        return err;
     }
-
-    // Check if it's a JSON session
-    let is_json = {
-        let json_broadcasters = JSON_BROADCASTERS.lock();
-        json_broadcasters.contains_key(&session_id)
+    if let Some(err) = check_session_remote(&session_id) {
+        return err;
+    }
+    let Some(text) = body.get("text").and_then(|v| v.as_str()).filter(|t| !t.trim().is_empty()).map(str::to_string) else {
+        return (StatusCode::BAD_REQUEST, Json(serde_json::json!({"error": "text is required"}))).into_response();
+    };
+    let Some(session) = load_sessions().ok().and_then(|all| all.into_iter().find(|s| s.id == session_id)) else {
+        return (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": "Session not found"}))).into_response();
+    };
+    let result = if session.agent_type == "claude-json" {
+        tokio::task::spawn_blocking(move || {
+            ensure_json_session_running(&session_id)?;
+            send_user_prompt(&session_id, &text)
+        }).await.unwrap_or_else(|e| Err(e.to_string()))
+    } else if state::get().pty_sessions.lock().contains_key(&session_id) {
+        write_pty(session_id, format!("{}\r", text))
+    } else {
+        return (StatusCode::CONFLICT, Json(serde_json::json!({"error": "Terminal session not running"}))).into_response();
     };
+    match result {
+        Ok(()) => Json(serde_json::json!({ "status": "sent" })).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, Json(serde_json::json!({"error": e}))).into_response(),
+    }
+}
 
-    if is_json {
-        // Send SIGINT to JSON process
-        let processes = JSON_PROCESSES.lock();
-        if let Some(process) = processes.get(&session_id) {
-            if process.child_id > 0 {
-                unsafe {
-                    libc::kill(process.child_id as i32, libc::SIGINT);
-                }
-                return Json(serde_json::json!({ "status": "interrupted" })).into_response();
-            }
-        }
-        return (StatusCode::NOT_FOUND, "Process not found").into_response();
+// POST /api/sessions/{id}/stop - Stop a session's process (the session itself is kept)
+#[cfg(not(target_os = "ios"))]
+async fn api_stop_session(
+    headers: axum::http::HeaderMap,
+    Path(session_id): Path<String>,
+) -> impl IntoResponse {
+    if let Some(err) = check_scope(&headers, DeviceScope::Interact) {
+        return err;
+    }
+    if let Some(err) = check_session_remote(&session_id) {
+        return err;
     }
+    match stop_session_remotely(&session_id) {
+        Ok(()) => Json(serde_json::json!({ "status": "stopped" })).into_response(),
+        Err((status, e)) => (status, Json(serde_json::json!({"error": e}))).into_response(),
+    }
+}
 
-    // Check if it's a PTY session
-    let is_pty = {
-        let pty_broadcasters = PTY_BROADCASTERS.lock();
-        pty_broadcasters.contains_key(&session_id)
-    };
+/// Stop a session's process for a remote client (the session itself is kept)
+#[cfg(not(target_os = "ios"))]
+fn stop_session_remotely(session_id: &str) -> Result<(), (StatusCode, String)> {
+    let is_json = state::get().json_processes.lock().contains_key(session_id);
+    let is_pty = state::get().pty_sessions.lock().contains_key(session_id);
+    if !is_json && !is_pty {
+        return Err((StatusCode::NOT_FOUND, "Session not running".to_string()));
+    }
 
+    if is_json {
+        let _ = kill_json_process(session_id.to_string());
+    }
     if is_pty {
-        // Send Ctrl+C to PTY
-        let sessions = PTY_SESSIONS.lock();
-        if let Some(session) = sessions.get(&session_id) {
-            let mut session = session.lock();
-            // Send ETX (Ctrl+C)
-            let _ = session.writer.write_all(&[0x03]);
-            let _ = session.writer.flush();
-            return Json(serde_json::json!({ "status": "interrupted" })).into_response();
-        }
-        return (StatusCode::NOT_FOUND, "PTY session not found").into_response();
+        let _ = kill_pty(session_id.to_string());
     }
+    save_session_pid(session_id, None);
+    broadcast_session_status(session_id, false);
+    Ok(())
+}
 
-    (StatusCode::NOT_FOUND, "Session not running").into_response()
+// iOS version
+#[cfg(target_os = "ios")]
+async fn api_stop_session(
+    headers: axum::http::HeaderMap,
+    Path(_session_id): Path<String>,
+) -> impl IntoResponse {
+    if let Some(err) = check_scope(&headers, DeviceScope::Interact) {
+        return err;
+    }
+    (StatusCode::NOT_IMPLEMENTED, Json(serde_json::json!({
+        "error": "not_supported",
+        "message": "Cannot stop sessions on iOS."
+    }))).into_response()
 }
 
 // iOS version
@@ -3738,8 +7465,8 @@ async fn api_interrupt_session(
     headers: axum::http::HeaderMap,
     Path(_session_id): Path<String>,
 ) -> impl IntoResponse {
-    if let Some(err) = check_auth(&headers) {
-        return err.into_response();
+    if let Some(err) = check_scope(&headers, DeviceScope::Interact) {
+        return err;
     }
     (StatusCode::NOT_IMPLEMENTED, Json(serde_json::json!({
         "error": "not_supported",
@@ -3751,11 +7478,12 @@ async fn api_interrupt_session(
 
 #[cfg(not(target_os = "ios"))]
 fn fire_job(job: &ScheduledJob) {
-    let app = { APP_HANDLE.lock().clone() };
+    let app = { state::get().app_handle.lock().clone() };
     let Some(app) = app else { return; };
 
-    let command = "claude --print --verbose --input-format stream-json --output-format stream-json --dangerously-skip-permissions".to_string();
-    let working_dir = std::env::var("AGENT_HUB_WEBHOOK_WORKDIR").unwrap_or_else(|_| "~/dev/pplsi".to_string());
+    let command = agent_commands::command_for("claude-json")
+        .unwrap_or_else(|| agent_commands::CLAUDE_JSON_COMMAND.to_string());
+    let working_dir = std::env::var("AGENT_HUB_WEBHOOK_WORKDIR").unwrap_or_else(|_| platform::DEFAULT_WORKING_DIR.to_string());
     let session_name = format!("[Scheduled] {}", job.name);
 
     // Find or create dedicated session
@@ -3782,6 +7510,10 @@ fn fire_job(job: &ScheduledJob) {
                 sort_order: min_sort_order - 1,
                 folder_id: None,
                 env_vars: None,
+                parent_id: None,
+                summary: None,
+                remote_access: true,
+                workspace_id: None,
             };
             if save_session(session.clone()).is_err() { return; }
             let _ = app.emit("remote-session-created", serde_json::json!({
@@ -3792,7 +7524,7 @@ fn fire_job(job: &ScheduledJob) {
     };
 
     // Start if not running (will resume previous Claude session if claude_session_id is set)
-    let is_running = { JSON_BROADCASTERS.lock().contains_key(&session_id) };
+    let is_running = { state::get().json_broadcasters.lock().contains_key(&session_id) };
     if !is_running {
         // Load session to get claude_session_id for resuming
         let session = load_sessions().ok()
@@ -3813,7 +7545,7 @@ fn fire_job(job: &ScheduledJob) {
     }).to_string() + "\n";
 
     if let Err(e) = write_to_process(session_id.clone(), prompt_json) {
-        eprintln!("[scheduler] write_to_process failed for job '{}': {}", job.name, e);
+        tracing::error!("[scheduler] write_to_process failed for job '{}': {}", job.name, e);
     }
 
     // Update last_run_at and next_run_at
@@ -3863,7 +7595,7 @@ async fn api_list_schedules(headers: axum::http::HeaderMap) -> impl IntoResponse
 }
 
 async fn api_create_schedule(headers: axum::http::HeaderMap, Json(body): Json<serde_json::Value>) -> impl IntoResponse {
-    if let Some(err) = check_auth(&headers) { return err.into_response(); }
+    if let Some(err) = check_scope(&headers, DeviceScope::Admin) { return err; }
     let name = match body.get("name").and_then(|v| v.as_str()) {
         Some(n) => n.to_string(),
         None => return (StatusCode::BAD_REQUEST, Json(serde_json::json!({"error": "name required"}))).into_response(),
@@ -3888,7 +7620,7 @@ async fn api_create_schedule(headers: axum::http::HeaderMap, Json(body): Json<se
 }
 
 async fn api_update_schedule(headers: axum::http::HeaderMap, Path(id): Path<String>, Json(body): Json<serde_json::Value>) -> impl IntoResponse {
-    if let Some(err) = check_auth(&headers) { return err.into_response(); }
+    if let Some(err) = check_scope(&headers, DeviceScope::Admin) { return err; }
     let name = body.get("name").and_then(|v| v.as_str()).map(|s| s.to_string());
     let cron_expr = body.get("cron_expr").and_then(|v| v.as_str()).map(|s| s.to_string());
     let prompt = body.get("prompt").and_then(|v| v.as_str()).map(|s| s.to_string());
@@ -3900,19 +7632,39 @@ async fn api_update_schedule(headers: axum::http::HeaderMap, Path(id): Path<Stri
 }
 
 async fn api_delete_schedule(headers: axum::http::HeaderMap, Path(id): Path<String>) -> impl IntoResponse {
-    if let Some(err) = check_auth(&headers) { return err.into_response(); }
+    if let Some(err) = check_scope(&headers, DeviceScope::Admin) { return err; }
     match delete_scheduled_job(id) {
         Ok(()) => Json(serde_json::json!({"status": "ok"})).into_response(),
         Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": e}))).into_response(),
     }
 }
 
+// GET /api/fanout/{id} - A fan-out run with every session's response
+#[cfg(not(target_os = "ios"))]
+async fn api_get_fanout(
+    headers: axum::http::HeaderMap,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    if let Some(err) = check_auth(&headers) {
+        return err.into_response();
+    }
+    match fanout::get_fanout(&id) {
+        Ok(run) => Json(serde_json::json!(run)).into_response(),
+        Err(e) => (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": e}))).into_response(),
+    }
+}
+
 // POST /api/mcp/execute - Execute JS in the webview and return result
-// This allows external MCP bridges to control the UI via HTTP
+// This allows external MCP bridges to control the UI via HTTP. The caller may
+// name the request (`request_id`) so it can cancel it from another request.
 #[cfg(not(target_os = "ios"))]
 async fn api_mcp_execute(
+    headers: axum::http::HeaderMap,
     Json(body): Json<serde_json::Value>,
 ) -> impl IntoResponse {
+    if let Some(err) = check_local_or_auth(&headers, DeviceScope::Admin) {
+        return err;
+    }
     let code = match body.get("code").and_then(|v| v.as_str()) {
         Some(c) => c.to_string(),
         None => return (StatusCode::BAD_REQUEST, Json(serde_json::json!({
@@ -3925,17 +7677,23 @@ async fn api_mcp_execute(
         .and_then(|v| v.as_u64())
         .unwrap_or(5000);
 
-    // Generate unique request ID
-    let request_id = uuid::Uuid::new_v4().to_string();
-
-    // Register the pending request
-    {
-        let mut results = MCP_HTTP_RESULTS.lock();
-        results.insert(request_id.clone(), None);
-    }
+    let request_id = body.get("request_id")
+        .and_then(|v| v.as_str())
+        .filter(|id| !id.is_empty())
+        .map(|id| id.to_string())
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+    // Register the pending request; dropped (and forgotten) however this ends,
+    // including the caller going away
+    let Some((result, _pending)) = mcp::pending_request(&request_id) else {
+        return (StatusCode::CONFLICT, Json(serde_json::json!({
+            "error": "duplicate_request_id",
+            "message": format!("Request {} is already running", request_id)
+        }))).into_response();
+    };
 
     // Emit event to frontend to execute the JS
-    let app_opt = APP_HANDLE.lock().clone();
+    let app_opt = state::get().app_handle.lock().clone();
     if let Some(app) = app_opt {
         let _ = app.emit("mcp-execute", serde_json::json!({
             "request_id": request_id,
@@ -3948,41 +7706,56 @@ async fn api_mcp_execute(
         }))).into_response();
     }
 
-    // Poll for result with timeout
-    let start = std::time::Instant::now();
-    let timeout = std::time::Duration::from_millis(timeout_ms);
-
-    loop {
-        {
-            let mut results = MCP_HTTP_RESULTS.lock();
-            if let Some(result_opt) = results.get(&request_id) {
-                if let Some(result) = result_opt {
-                    let result = result.clone();
-                    results.remove(&request_id);
-                    return Json(serde_json::json!({
-                        "success": true,
-                        "result": result
-                    })).into_response();
-                }
-            }
-        }
+    match tokio::time::timeout(std::time::Duration::from_millis(timeout_ms), result).await {
+        Ok(Ok(result)) => Json(serde_json::json!({
+            "success": true,
+            "request_id": request_id,
+            "result": result
+        })).into_response(),
+        Ok(Err(_)) => (StatusCode::CONFLICT, Json(serde_json::json!({
+            "error": "cancelled",
+            "message": format!("Request {} was cancelled", request_id)
+        }))).into_response(),
+        Err(_) => (StatusCode::GATEWAY_TIMEOUT, Json(serde_json::json!({
+            "error": "timeout",
+            "message": format!("JS execution timed out after {}ms", timeout_ms)
+        }))).into_response(),
+    }
+}
 
-        if start.elapsed() > timeout {
-            // Clean up and return timeout
-            let mut results = MCP_HTTP_RESULTS.lock();
-            results.remove(&request_id);
-            return (StatusCode::GATEWAY_TIMEOUT, Json(serde_json::json!({
-                "error": "timeout",
-                "message": format!("JS execution timed out after {}ms", timeout_ms)
-            }))).into_response();
-        }
+// POST /api/mcp/cancel - Stop waiting on a running execute (by its request_id)
+#[cfg(not(target_os = "ios"))]
+async fn api_mcp_cancel(
+    headers: axum::http::HeaderMap,
+    Json(body): Json<serde_json::Value>,
+) -> impl IntoResponse {
+    if let Some(err) = check_local_or_auth(&headers, DeviceScope::Admin) {
+        return err;
+    }
+    let request_id = body.get("request_id").and_then(|v| v.as_str()).unwrap_or("");
+    if mcp::cancel_request(request_id) {
+        Json(serde_json::json!({ "success": true })).into_response()
+    } else {
+        (StatusCode::NOT_FOUND, Json(serde_json::json!({
+            "error": "not_found",
+            "message": "No running request with that id"
+        }))).into_response()
+    }
+}
 
-        // Sleep briefly before polling again
-        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+// GET /api/mcp/tools - The MCP tools, with their input schemas
+#[cfg(not(target_os = "ios"))]
+async fn api_mcp_tools(headers: axum::http::HeaderMap) -> impl IntoResponse {
+    if let Some(err) = check_local_or_auth(&headers, DeviceScope::Admin) {
+        return err;
     }
+    Json(serde_json::json!({ "tools": mcp::tools_list() })).into_response()
 }
 
 // POST /api/mcp/result - Frontend calls this to return JS execution result
+// No auth: the request id is an unguessable UUID only the webview has seen.
+// The webview now answers through the mcp_callback command; this stays for
+// older frontends.
 #[cfg(not(target_os = "ios"))]
 async fn api_mcp_result(
     Json(body): Json<serde_json::Value>,
@@ -3996,41 +7769,173 @@ async fn api_mcp_result(
         .map(|v| if v.is_string() { v.as_str().unwrap().to_string() } else { v.to_string() })
         .unwrap_or_else(|| "null".to_string());
 
-    let mut results = MCP_HTTP_RESULTS.lock();
-    if results.contains_key(&request_id) {
-        results.insert(request_id, Some(result));
+    if mcp::resolve_mcp_request(request_id, result) {
         Json(serde_json::json!({ "success": true })).into_response()
     } else {
         (StatusCode::NOT_FOUND, "request not found").into_response()
     }
 }
 
+// POST /api/mcp/screenshot - PNG of the desktop window, base64, for the MCP bridge
+#[cfg(not(target_os = "ios"))]
+async fn api_mcp_screenshot(headers: axum::http::HeaderMap) -> impl IntoResponse {
+    if let Some(err) = check_local_or_auth(&headers, DeviceScope::Admin) {
+        return err;
+    }
+    let window = state::get().app_handle.lock().as_ref().and_then(|app| app.get_webview_window("main"));
+    let Some(window) = window else {
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(serde_json::json!({
+            "success": false,
+            "error": "Main window not found"
+        }))).into_response();
+    };
+    match mcp::capture_window(&window).await {
+        Ok(png) => Json(serde_json::json!({
+            "success": true,
+            "mime_type": "image/png",
+            "data": BASE64.encode(png)
+        })).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({
+            "success": false,
+            "error": e
+        }))).into_response(),
+    }
+}
+
+/// Header carrying the MCP session an HTTP request belongs to
+#[cfg(not(target_os = "ios"))]
+const MCP_SESSION_HEADER: &str = "mcp-session-id";
+
+/// The MCP client an HTTP request belongs to, or the response refusing it
+#[cfg(not(target_os = "ios"))]
+fn mcp_client(headers: &axum::http::HeaderMap) -> Result<String, (StatusCode, &'static str)> {
+    let client_id = headers
+        .get(MCP_SESSION_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .ok_or((StatusCode::BAD_REQUEST, "Missing Mcp-Session-Id header"))?;
+    if !mcp::is_connected(client_id) {
+        return Err((StatusCode::NOT_FOUND, "Unknown MCP session"));
+    }
+    Ok(client_id.to_string())
+}
+
+// POST /api/mcp - MCP over HTTP: one JSON-RPC message per request
+// An initialize without Mcp-Session-Id starts a session; its id comes back in
+// that header and goes on every later request. Notifications get 202.
+#[cfg(not(target_os = "ios"))]
+async fn api_mcp_post(
+    headers: axum::http::HeaderMap,
+    body: String,
+) -> impl IntoResponse {
+    if let Some(err) = check_local_or_auth(&headers, DeviceScope::Admin) {
+        return err;
+    }
+    let Some(app) = state::get().app_handle.lock().clone() else {
+        return (StatusCode::SERVICE_UNAVAILABLE, "App handle not available").into_response();
+    };
+    let is_initialize = serde_json::from_str::<serde_json::Value>(&body)
+        .is_ok_and(|msg| msg["method"] == "initialize");
+    let client_id = if is_initialize && !headers.contains_key(MCP_SESSION_HEADER) {
+        let client_id = mcp::connect();
+        // A session's own agent (see mcp::session_config)
+        let agent_of = headers.get(mcp::SESSION_HEADER).and_then(|v| v.to_str().ok());
+        if let Some(session_id) = agent_of.filter(|_| is_local_request(&headers)) {
+            mcp::bind_session(&client_id, session_id);
+        }
+        client_id
+    } else {
+        match mcp_client(&headers) {
+            Ok(id) => id,
+            Err(err) => return err.into_response(),
+        }
+    };
+
+    let reply = mcp::McpServer::for_app(app).handle_line(&client_id, &body).await;
+    let session = [(MCP_SESSION_HEADER, client_id)];
+    match reply {
+        Some(reply) => (session, [(axum::http::header::CONTENT_TYPE, "application/json")], reply).into_response(),
+        None => (StatusCode::ACCEPTED, session).into_response(),
+    }
+}
+
+// GET /api/mcp - Server-sent events stream of an MCP session's notifications
+#[cfg(not(target_os = "ios"))]
+async fn api_mcp_events(headers: axum::http::HeaderMap) -> impl IntoResponse {
+    use axum::response::sse::{Event, KeepAlive, Sse};
+
+    if let Some(err) = check_local_or_auth(&headers, DeviceScope::Admin) {
+        return err;
+    }
+    let client_id = match mcp_client(&headers) {
+        Ok(id) => id,
+        Err(err) => return err.into_response(),
+    };
+    let Some(outgoing) = mcp::attach(&client_id) else {
+        return (StatusCode::NOT_FOUND, "Unknown MCP session").into_response();
+    };
+    let events = futures::stream::unfold(outgoing, |mut outgoing| async move {
+        let message = outgoing.recv().await?;
+        Some((Ok::<_, std::convert::Infallible>(Event::default().event("message").data(message)), outgoing))
+    });
+    Sse::new(events).keep_alive(KeepAlive::default()).into_response()
+}
+
+// DELETE /api/mcp - End an MCP session, cancelling its running requests
+#[cfg(not(target_os = "ios"))]
+async fn api_mcp_close(headers: axum::http::HeaderMap) -> impl IntoResponse {
+    if let Some(err) = check_local_or_auth(&headers, DeviceScope::Admin) {
+        return err;
+    }
+    match mcp_client(&headers) {
+        Ok(client_id) => {
+            mcp::disconnect(&client_id);
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(err) => err.into_response(),
+    }
+}
+
 // WebSocket handler for PTY and JSON streaming
 #[cfg(not(target_os = "ios"))]
 async fn ws_handler(
+    headers: axum::http::HeaderMap,
     Path(session_id): Path<String>,
+    axum::extract::Query(query): axum::extract::Query<HashMap<String, String>>,
     ws: WebSocketUpgrade,
 ) -> impl IntoResponse {
-    ws.on_upgrade(move |socket| handle_ws(socket, session_id))
+    if let Some(err) = check_ws_auth(&headers, &query) {
+        return err.into_response();
+    }
+    if let Some(err) = check_session_remote(&session_id) {
+        return err;
+    }
+    // Read-only devices can watch but anything they send is dropped
+    let token = extract_token(&headers).or_else(|| query.get("token").cloned());
+    let device = token.as_deref().and_then(device_for_token);
+    // No device here means auth let it through: the local socket, or nothing paired yet
+    let can_write = device.as_ref().map(|d| d.scope).unwrap_or(DeviceScope::Admin) >= DeviceScope::Interact;
+    ws.on_upgrade(move |socket| handle_ws(socket, session_id, can_write, device))
 }
 
 #[cfg(not(target_os = "ios"))]
-async fn handle_ws(socket: WebSocket, session_id: String) {
+async fn handle_ws(socket: WebSocket, session_id: String, can_write: bool, device: Option<PairedDevice>) {
     use tokio::time::{interval, Duration};
 
     let (mut sender, mut receiver) = socket.split();
     let session_id_clone = session_id.clone();
+    // Counted as a viewer until the connection ends, however it ends
+    let _watching = presence::watch(&session_id, presence::View::Stream, device.as_ref());
 
     // Check if this is a JSON session or PTY session
     let is_json_session = {
-        let processes = JSON_PROCESSES.lock();
+        let processes = state::get().json_processes.lock();
         processes.contains_key(&session_id)
     };
 
     if is_json_session {
         // Handle JSON session
         let rx = {
-            let broadcasters = JSON_BROADCASTERS.lock();
+            let broadcasters = state::get().json_broadcasters.lock();
             broadcasters.get(&session_id).map(|tx| tx.subscribe())
         };
 
@@ -4038,35 +7943,61 @@ async fn handle_ws(socket: WebSocket, session_id: String) {
             let _ = sender.send(Message::Text("JSON session not found or not running".into())).await;
             return;
         };
+        let _connected = metrics::ws_connected(metrics::WsKind::Session);
 
         // Subscribe to status updates for all sessions
-        let mut status_rx = STATUS_BROADCASTER.subscribe();
+        let mut status_rx = state::get().status_broadcaster.subscribe();
 
         // Spawn task to forward JSON output and status updates to WebSocket with keepalive pings
+        let watched_id = session_id.clone();
         let send_task = tokio::spawn(async move {
             let mut ping_interval = interval(Duration::from_secs(30));
+            let mut restart = state::get().web_server_restart.subscribe();
             loop {
                 tokio::select! {
                     result = rx.recv() => {
                         match result {
+                            // Hidden from remote devices while connected
+                            Ok(_) if is_remote_hidden(&watched_id) => break,
                             Ok(data) => {
+                                metrics::add_bytes_streamed(data.len());
                                 if sender.send(Message::Text(data)).await.is_err() {
                                     break;
                                 }
                             }
-                            Err(_) => {
-                                break;
+                            Err(broadcast::error::RecvError::Lagged(missed)) => {
+                                rx = resync::resubscribe(metrics::Stream::Json, &rx, missed, Some(&watched_id));
+                                if sender.send(Message::Text(resync::json(&watched_id))).await.is_err() {
+                                    break;
+                                }
                             }
+                            Err(broadcast::error::RecvError::Closed) => break,
                         }
                     }
                     // Forward session status changes to client
                     result = status_rx.recv() => {
-                        if let Ok(status_msg) = result {
-                            if sender.send(Message::Text(status_msg)).await.is_err() {
-                                break;
+                        match result {
+                            Ok(event) => {
+                                let text = event.json();
+                                metrics::add_bytes_streamed(text.len());
+                                if sender.send(Message::Text(text.to_string())).await.is_err() {
+                                    break;
+                                }
+                            }
+                            Err(broadcast::error::RecvError::Lagged(missed)) => {
+                                status_rx = resync::resubscribe(metrics::Stream::Status, &status_rx, missed, None);
+                                if sender.send(Message::Text(resync::status())).await.is_err() {
+                                    break;
+                                }
                             }
+                            Err(broadcast::error::RecvError::Closed) => break,
                         }
                     }
+                    // Web server restarting with new network settings
+                    _ = restart.changed() => {
+                        let _ = sender.send(Message::Close(None)).await;
+                        break;
+                    }
                     _ = ping_interval.tick() => {
                         // Send WebSocket ping to keep connection alive
                         if sender.send(Message::Ping(vec![])).await.is_err() {
@@ -4081,27 +8012,7 @@ async fn handle_ws(socket: WebSocket, session_id: String) {
         let recv_task = tokio::spawn(async move {
             while let Some(Ok(msg)) = receiver.next().await {
                 match msg {
-                    Message::Text(text) => {
-                        // For JSON sessions, forward text directly to stdin
-                        let _ = write_to_process(session_id_clone.clone(), text.clone());
-
-                        // Also broadcast the user message so other clients (mobile web) can see it
-                        if let Some(tx) = {
-                            let broadcasters = JSON_BROADCASTERS.lock();
-                            broadcasters.get(&session_id_clone).cloned()
-                        } {
-                            let _ = tx.send(text.clone());
-                        }
-
-                        // Emit Tauri event so desktop frontend can see user messages from mobile
-                        // Use same event name as process output so frontend handles it consistently
-                        if let Some(app) = APP_HANDLE.lock().as_ref() {
-                            let _ = app.emit("json-process-output", serde_json::json!({
-                                "session_id": session_id_clone.clone(),
-                                "data": text,
-                            }));
-                        }
-                    }
+                    Message::Text(text) if can_write => json_ws_input(&session_id_clone, text),
                     Message::Pong(_) => {
                         // Pong received, connection is alive
                     }
@@ -4118,7 +8029,7 @@ async fn handle_ws(socket: WebSocket, session_id: String) {
     } else {
         // Handle PTY session (existing logic)
         let rx = {
-            let broadcasters = PTY_BROADCASTERS.lock();
+            let broadcasters = state::get().pty_broadcasters.lock();
             broadcasters.get(&session_id).map(|tx| tx.subscribe())
         };
 
@@ -4126,33 +8037,60 @@ async fn handle_ws(socket: WebSocket, session_id: String) {
             let _ = sender.send(Message::Text("Session not found or not running".into())).await;
             return;
         };
+        let _connected = metrics::ws_connected(metrics::WsKind::Session);
 
         // Subscribe to status updates for all sessions
-        let mut status_rx = STATUS_BROADCASTER.subscribe();
+        let mut status_rx = state::get().status_broadcaster.subscribe();
 
         // Spawn task to forward PTY output and status updates to WebSocket with keepalive pings
+        let watched_id = session_id.clone();
         let send_task = tokio::spawn(async move {
             let mut ping_interval = interval(Duration::from_secs(30));
+            let mut restart = state::get().web_server_restart.subscribe();
             loop {
                 tokio::select! {
                     result = rx.recv() => {
                         match result {
+                            Ok(_) if is_remote_hidden(&watched_id) => break,
                             Ok(data) => {
+                                metrics::add_bytes_streamed(data.len());
                                 if sender.send(Message::Binary(data)).await.is_err() {
                                     break;
                                 }
                             }
-                            Err(_) => break,
+                            Err(broadcast::error::RecvError::Lagged(missed)) => {
+                                rx = resync::resubscribe(metrics::Stream::Pty, &rx, missed, Some(&watched_id));
+                                if sender.send(Message::Binary(resync::pty(&watched_id))).await.is_err() {
+                                    break;
+                                }
+                            }
+                            Err(broadcast::error::RecvError::Closed) => break,
                         }
                     }
                     // Forward session status/events to client
                     result = status_rx.recv() => {
-                        if let Ok(status_msg) = result {
-                            if sender.send(Message::Text(status_msg)).await.is_err() {
-                                break;
+                        match result {
+                            Ok(event) => {
+                                let text = event.json();
+                                metrics::add_bytes_streamed(text.len());
+                                if sender.send(Message::Text(text.to_string())).await.is_err() {
+                                    break;
+                                }
+                            }
+                            Err(broadcast::error::RecvError::Lagged(missed)) => {
+                                status_rx = resync::resubscribe(metrics::Stream::Status, &status_rx, missed, None);
+                                if sender.send(Message::Text(resync::status())).await.is_err() {
+                                    break;
+                                }
                             }
+                            Err(broadcast::error::RecvError::Closed) => break,
                         }
                     }
+                    // Web server restarting with new network settings
+                    _ = restart.changed() => {
+                        let _ = sender.send(Message::Close(None)).await;
+                        break;
+                    }
                     _ = ping_interval.tick() => {
                         // Send WebSocket ping to keep connection alive
                         if sender.send(Message::Ping(vec![])).await.is_err() {
@@ -4167,23 +8105,8 @@ async fn handle_ws(socket: WebSocket, session_id: String) {
         let recv_task = tokio::spawn(async move {
             while let Some(Ok(msg)) = receiver.next().await {
                 match msg {
-                    Message::Text(text) => {
-                        // Check if it's a control message (JSON)
-                        if let Ok(json) = serde_json::from_str::<serde_json::Value>(&text) {
-                            if json.get("type").and_then(|v| v.as_str()) == Some("resize") {
-                                if let (Some(cols), Some(rows)) = (
-                                    json.get("cols").and_then(|v| v.as_u64()),
-                                    json.get("rows").and_then(|v| v.as_u64()),
-                                ) {
-                                    let _ = resize_pty(session_id_clone.clone(), cols as u16, rows as u16);
-                                }
-                            }
-                        } else {
-                            // Regular text input
-                            let _ = write_pty(session_id_clone.clone(), text);
-                        }
-                    }
-                    Message::Binary(data) => {
+                    Message::Text(text) if can_write => pty_ws_input(&session_id_clone, text),
+                    Message::Binary(data) if can_write => {
                         if let Ok(text) = String::from_utf8(data) {
                             let _ = write_pty(session_id_clone.clone(), text);
                         }
@@ -4202,49 +8125,273 @@ async fn handle_ws(socket: WebSocket, session_id: String) {
             _ = send_task => {},
             _ = recv_task => {},
         }
-    }
-
-    // Mobile client disconnected - notify desktop to restore its size
-    if let Some(app) = APP_HANDLE.lock().as_ref() {
-        let _ = app.emit("remote-client-disconnected", session_id);
-    }
+    }
+}
+
+/// Text from a session WebSocket for a claude-json session: a stream-json
+/// line for the process's stdin.
+#[cfg(not(target_os = "ios"))]
+fn json_ws_input(session_id: &str, text: String) {
+    // For JSON sessions, forward text directly to stdin
+    let _ = write_to_process(session_id.to_string(), text.clone());
+
+    // Also broadcast the user message so other clients (mobile web) can see it
+    if let Some(tx) = {
+        let broadcasters = state::get().json_broadcasters.lock();
+        broadcasters.get(session_id).cloned()
+    } {
+        let _ = tx.send(text.clone());
+    }
+
+    // Emit Tauri event so desktop frontend can see user messages from mobile
+    // Use same event name as process output so frontend handles it consistently
+    if let Some(app) = state::get().app_handle.lock().as_ref() {
+        let _ = app.emit("json-process-output", serde_json::json!({
+            "session_id": session_id,
+            "data": text,
+        }));
+    }
+}
+
+/// Text from a session WebSocket for a PTY session: a resize request or
+/// keyboard input.
+#[cfg(not(target_os = "ios"))]
+fn pty_ws_input(session_id: &str, text: String) {
+    // Check if it's a control message (JSON)
+    if let Ok(json) = serde_json::from_str::<serde_json::Value>(&text) {
+        if json.get("type").and_then(|v| v.as_str()) == Some("resize") {
+            if let (Some(cols), Some(rows)) = (
+                json.get("cols").and_then(|v| v.as_u64()),
+                json.get("rows").and_then(|v| v.as_u64()),
+            ) {
+                let _ = resize_pty(session_id.to_string(), cols as u16, rows as u16);
+            }
+        }
+    } else {
+        // Regular text input
+        let _ = write_pty(session_id.to_string(), text);
+    }
+}
+
+// iOS stub - WebSocket not supported without PTY
+#[cfg(target_os = "ios")]
+async fn ws_handler(
+    Path(_session_id): Path<String>,
+    _ws: WebSocketUpgrade,
+) -> impl IntoResponse {
+    (StatusCode::NOT_IMPLEMENTED, "WebSocket PTY streaming not supported on iOS")
+}
+
+// GET /share/{token} - Guest view of one session. A browser gets the viewer
+// page; the page's WebSocket upgrade is what uses up the link.
+#[cfg(not(target_os = "ios"))]
+async fn share_handler(
+    Path(token): Path<String>,
+    axum::extract::ConnectInfo(addr): axum::extract::ConnectInfo<SocketAddr>,
+    ws: Option<WebSocketUpgrade>,
+) -> axum::response::Response {
+    let Some(ws) = ws else {
+        return axum::response::Html(include_str!("share_viewer.html")).into_response();
+    };
+    let link = match share::verify(&token) {
+        Ok(link) => link,
+        Err(e) => return (StatusCode::FORBIDDEN, e).into_response(),
+    };
+    if is_remote_hidden(&link.session_id) {
+        return (StatusCode::FORBIDDEN, "This session is no longer shared").into_response();
+    }
+    // Don't use up the link while there is nothing to watch
+    let running = state::get().json_broadcasters.lock().contains_key(&link.session_id)
+        || state::get().pty_broadcasters.lock().contains_key(&link.session_id);
+    if !running {
+        return (StatusCode::CONFLICT, "The session isn't running; try again once it has started").into_response();
+    }
+    let link = match share::claim(link) {
+        Ok(link) => link,
+        Err(e) => return (StatusCode::FORBIDDEN, e).into_response(),
+    };
+    audit::record(None, "ws /share/:token", Some(&link.session_id), Some(addr.ip()));
+    ws.on_upgrade(move |socket| handle_share_ws(socket, link))
+}
+
+#[cfg(not(target_os = "ios"))]
+async fn handle_share_ws(mut socket: WebSocket, link: share::ShareLink) {
+    let is_json = state::get().json_processes.lock().contains_key(&link.session_id);
+    let info = serde_json::json!({
+        "type": "share",
+        "kind": if is_json { "json" } else { "pty" },
+        "read_only": link.read_only,
+        "expires_at": link.expires_at,
+    });
+    if socket.send(Message::Text(info.to_string())).await.is_err() {
+        return;
+    }
+    if is_json {
+        let rx = state::get().json_broadcasters.lock().get(&link.session_id).map(|tx| tx.subscribe());
+        if let Some(rx) = rx {
+            stream_share(socket, rx, Message::Text, |id| Message::Text(resync::json(id)), link, true).await;
+        }
+    } else {
+        let rx = state::get().pty_broadcasters.lock().get(&link.session_id).map(|tx| tx.subscribe());
+        if let Some(rx) = rx {
+            stream_share(socket, rx, Message::Binary, |id| Message::Binary(resync::pty(id)), link, false).await;
+        }
+    }
+}
+
+/// Forward one session's output to a guest (no status events: those cover
+/// every session) until the link expires or is revoked, passing their input
+/// on unless the link is read-only.
+#[cfg(not(target_os = "ios"))]
+async fn stream_share<T: Clone + Send + 'static>(
+    socket: WebSocket,
+    mut rx: tokio::sync::broadcast::Receiver<T>,
+    into_message: fn(T) -> Message,
+    snapshot: fn(&str) -> Message,
+    link: share::ShareLink,
+    is_json: bool,
+) {
+    use axum::extract::ws::{close_code, CloseFrame};
+    use tokio::time::{interval, sleep, Duration};
+
+    let (mut sender, mut receiver) = socket.split();
+    let _connected = metrics::ws_connected(metrics::WsKind::Session);
+
+    let session_id = link.session_id.clone();
+    let can_write = !link.read_only;
+    let mut recv_task = tokio::spawn(async move {
+        while let Some(Ok(msg)) = receiver.next().await {
+            match msg {
+                Message::Text(text) if can_write && is_json => json_ws_input(&session_id, text),
+                Message::Text(text) if can_write => pty_ws_input(&session_id, text),
+                Message::Binary(data) if can_write && !is_json => {
+                    if let Ok(text) = String::from_utf8(data) {
+                        let _ = write_pty(session_id.clone(), text);
+                    }
+                }
+                Message::Close(_) => break,
+                _ => {}
+            }
+        }
+    });
+
+    let mut revocations = share::subscribe_revocations();
+    let mut restart = state::get().web_server_restart.subscribe();
+    let mut ping_interval = interval(Duration::from_secs(30));
+    let remaining = (link.expires_at - chrono::Utc::now().timestamp()).max(0) as u64;
+    let expiry = sleep(Duration::from_secs(remaining));
+    tokio::pin!(expiry);
+
+    // Close reason, read by the viewer page
+    let reason = loop {
+        tokio::select! {
+            result = rx.recv() => {
+                match result {
+                    Ok(_) if is_remote_hidden(&link.session_id) => break "hidden",
+                    Ok(data) => {
+                        if sender.send(into_message(data)).await.is_err() {
+                            break "";
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        let stream = if is_json { metrics::Stream::Json } else { metrics::Stream::Pty };
+                        rx = resync::resubscribe(stream, &rx, missed, Some(&link.session_id));
+                        if sender.send(snapshot(&link.session_id)).await.is_err() {
+                            break "";
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break "ended",
+                }
+            }
+            Ok(id) = revocations.recv() => {
+                if id == link.id {
+                    break "revoked";
+                }
+            }
+            _ = &mut expiry => break "expired",
+            _ = restart.changed() => break "",
+            _ = &mut recv_task => break "",
+            _ = ping_interval.tick() => {
+                if sender.send(Message::Ping(vec![])).await.is_err() {
+                    break "";
+                }
+            }
+        }
+    };
+    recv_task.abort();
+    let _ = sender
+        .send(Message::Close(Some(CloseFrame { code: close_code::NORMAL, reason: reason.into() })))
+        .await;
 }
 
-// iOS stub - WebSocket not supported without PTY
-#[cfg(target_os = "ios")]
-async fn ws_handler(
-    Path(_session_id): Path<String>,
-    _ws: WebSocketUpgrade,
+// Status-only WebSocket for receiving session events (start/stop, create/update/delete)
+// This allows mobile clients to receive updates without being connected to a specific session.
+// ?events=, ?sessions= and ?folder= narrow it down (see status_filter)
+#[cfg(not(target_os = "ios"))]
+async fn ws_status_handler(
+    axum::extract::State(state): axum::extract::State<Arc<state::AppState>>,
+    headers: axum::http::HeaderMap,
+    axum::extract::Query(query): axum::extract::Query<HashMap<String, String>>,
+    ws: WebSocketUpgrade,
 ) -> impl IntoResponse {
-    (StatusCode::NOT_IMPLEMENTED, "WebSocket PTY streaming not supported on iOS")
+    if let Some(err) = check_ws_auth(&headers, &query) {
+        return err.into_response();
+    }
+    let filter = status_filter::StatusFilter::from_query(&query);
+    ws.on_upgrade(move |socket| handle_ws_status(state, socket, filter))
 }
 
-// Status-only WebSocket for receiving session events (start/stop, create/update/delete)
-// This allows mobile clients to receive updates without being connected to a specific session
+/// The folder a session is in, for status_filter
 #[cfg(not(target_os = "ios"))]
-async fn ws_status_handler(ws: WebSocketUpgrade) -> impl IntoResponse {
-    ws.on_upgrade(handle_ws_status)
+fn session_folder_id(session_id: &str) -> Option<String> {
+    state::get().db()
+        .lock()
+        .query_row("SELECT folder_id FROM sessions WHERE id = ?1", params![session_id], |row| row.get(0))
+        .ok()
+        .flatten()
 }
 
 #[cfg(not(target_os = "ios"))]
-async fn handle_ws_status(socket: WebSocket) {
+async fn handle_ws_status(state: Arc<state::AppState>, socket: WebSocket, filter: status_filter::StatusFilter) {
     use tokio::time::{interval, Duration};
 
     let (mut sender, mut receiver) = socket.split();
-    let mut status_rx = STATUS_BROADCASTER.subscribe();
+    let mut status_rx = state.status_broadcaster.subscribe();
+    let _connected = metrics::ws_connected(metrics::WsKind::Status);
 
     // Spawn task to forward status updates to WebSocket with keepalive pings
     let send_task = tokio::spawn(async move {
         let mut ping_interval = interval(Duration::from_secs(30));
+        let mut restart = state.web_server_restart.subscribe();
         loop {
             tokio::select! {
                 result = status_rx.recv() => {
-                    if let Ok(status_msg) = result {
-                        if sender.send(Message::Text(status_msg)).await.is_err() {
-                            break;
+                    match result {
+                        Ok(event) => {
+                            if !filter.is_empty() && !filter.matches(&event.event, session_folder_id) {
+                                continue;
+                            }
+                            let text = event.json();
+                            metrics::add_bytes_streamed(text.len());
+                            if sender.send(Message::Text(text.to_string())).await.is_err() {
+                                break;
+                            }
+                        }
+                        // Sent whatever the filter: the client can't tell what it missed
+                        Err(broadcast::error::RecvError::Lagged(missed)) => {
+                            status_rx = resync::resubscribe(metrics::Stream::Status, &status_rx, missed, None);
+                            if sender.send(Message::Text(resync::status())).await.is_err() {
+                                break;
+                            }
                         }
+                        Err(broadcast::error::RecvError::Closed) => break,
                     }
                 }
+                // Web server restarting with new network settings
+                _ = restart.changed() => {
+                    let _ = sender.send(Message::Close(None)).await;
+                    break;
+                }
                 _ = ping_interval.tick() => {
                     if sender.send(Message::Ping(vec![])).await.is_err() {
                         break;
@@ -4274,47 +8421,120 @@ async fn ws_status_handler(_ws: WebSocketUpgrade) -> impl IntoResponse {
 
 // Mobile WebSocket handler - multiplexed connection with auth and subscriptions
 #[cfg(not(target_os = "ios"))]
-async fn ws_mobile_handler(ws: WebSocketUpgrade) -> impl IntoResponse {
-    ws.on_upgrade(handle_ws_mobile)
+async fn ws_mobile_handler(
+    axum::extract::State(state): axum::extract::State<Arc<state::AppState>>,
+    axum::extract::ConnectInfo(addr): axum::extract::ConnectInfo<SocketAddr>,
+    ws: WebSocketUpgrade,
+) -> impl IntoResponse {
+    ws.on_upgrade(move |socket| handle_ws_mobile(state, socket, addr))
 }
 
+/// Mobile WebSocket messages at least this long are gzipped for clients that
+/// asked for compression (history loads, terminal scrollback)
 #[cfg(not(target_os = "ios"))]
-async fn handle_ws_mobile(socket: WebSocket) {
-    use tokio::time::{interval, Duration};
+const WS_COMPRESS_MIN_BYTES: usize = 4096;
+
+/// Mobile clients are pinged this often, and dropped once they've missed
+/// MOBILE_MISSED_PINGS pongs in a row: a phone that lost its network leaves
+/// a half-open socket that would otherwise count as connected for hours.
+#[cfg(not(target_os = "ios"))]
+const MOBILE_PING_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+#[cfg(not(target_os = "ios"))]
+const MOBILE_MISSED_PINGS: u32 = 3;
+
+/// A message as a gzipped binary frame, if that's smaller
+#[cfg(not(target_os = "ios"))]
+fn gzip_ws_message(body: &[u8]) -> Option<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+    encoder.write_all(body).ok()?;
+    encoder.finish().ok().filter(|gz| gz.len() < body.len())
+}
+
+#[cfg(not(target_os = "ios"))]
+async fn handle_ws_mobile(state: Arc<state::AppState>, socket: WebSocket, addr: SocketAddr) {
+    use tokio::time::interval;
 
     let (mut sender, mut receiver) = socket.split();
     let client_id = generate_token();
     let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<String>();
+    let (revoke_tx, mut revoke_rx) = tokio::sync::oneshot::channel::<&'static str>();
+    let _connected = metrics::ws_connected(metrics::WsKind::Mobile);
 
     // We'll authenticate on first message, so track auth state
     let mut authenticated = false;
+    let mut scope = DeviceScope::ReadOnly;
+    let mut auth_device: Option<PairedDevice> = None;
+    let mut acks = false;
+    // Set once the client asks for compressed frames (protocol 6)
+    let compress = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+    // Set once the client asks for CBOR frames (protocol 9)
+    let cbor = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+    // PTY sessions this client is attached to, and the tasks forwarding their output
+    let mut terminals: HashMap<String, tokio::task::JoinHandle<()>> = HashMap::new();
 
     // Register client (not yet authenticated)
     {
-        let mut clients = MOBILE_CLIENTS.lock();
+        let mut clients = state.mobile_clients.lock();
         clients.insert(client_id.clone(), MobileClient {
             sender: tx.clone(),
-            subscribed_sessions: std::collections::HashSet::new(),
+            subscribed_sessions: HashMap::new(),
+            device_id: None,
+            acks: false,
+            list_deltas: false,
+            revoke: Some(revoke_tx),
         });
     }
 
     let client_id_for_cleanup = client_id.clone();
 
     // Spawn task to forward messages from channel to WebSocket
+    let send_compressed = compress.clone();
+    let send_cbor = cbor.clone();
     let send_task = tokio::spawn(async move {
-        let mut ping_interval = interval(Duration::from_secs(30));
+        let mut ping_interval = interval(MOBILE_PING_INTERVAL);
         loop {
             tokio::select! {
                 msg = rx.recv() => {
                     match msg {
                         Some(text) => {
-                            if sender.send(Message::Text(text)).await.is_err() {
+                            let encoded = send_cbor
+                                .load(std::sync::atomic::Ordering::Relaxed)
+                                .then(|| ws_cbor::encode_message(&text))
+                                .flatten();
+                            let body = encoded.as_deref().unwrap_or(text.as_bytes());
+                            let gzipped = (body.len() >= WS_COMPRESS_MIN_BYTES
+                                && send_compressed.load(std::sync::atomic::Ordering::Relaxed))
+                                .then(|| gzip_ws_message(body))
+                                .flatten();
+                            let frame = match (gzipped, encoded) {
+                                (Some(gz), _) => {
+                                    metrics::add_bytes_streamed(gz.len());
+                                    Message::Binary(gz)
+                                }
+                                (None, Some(encoded)) => {
+                                    metrics::add_bytes_streamed(encoded.len());
+                                    Message::Binary(encoded)
+                                }
+                                (None, None) => {
+                                    metrics::add_bytes_streamed(text.len());
+                                    Message::Text(text)
+                                }
+                            };
+                            if sender.send(frame).await.is_err() {
                                 break;
                             }
                         }
                         None => break,
                     }
                 }
+                Ok(reason) = &mut revoke_rx => {
+                    let _ = sender.send(Message::Text(serde_json::json!({
+                        "type": "auth_revoked",
+                        "reason": reason,
+                    }).to_string())).await;
+                    let _ = sender.send(Message::Close(None)).await;
+                    break;
+                }
                 _ = ping_interval.tick() => {
                     if sender.send(Message::Ping(vec![])).await.is_err() {
                         break;
@@ -4324,8 +8544,23 @@ async fn handle_ws_mobile(socket: WebSocket) {
         }
     });
 
-    // Handle incoming messages
-    while let Some(msg) = receiver.next().await {
+    // Handle incoming messages. A live client answers every ping, so a
+    // silence longer than the allowed misses means it's gone.
+    let mut auth_token: Option<String> = None;
+    let mut stale = false;
+    loop {
+        let msg = match tokio::time::timeout(MOBILE_PING_INTERVAL * MOBILE_MISSED_PINGS, receiver.next()).await {
+            Ok(Some(msg)) => msg,
+            Ok(None) => break,
+            Err(_) => {
+                stale = true;
+                break;
+            }
+        };
+        // Revoked: stop acting on anything else the client sends
+        if send_task.is_finished() {
+            break;
+        }
         match msg {
             Ok(Message::Text(text)) => {
                 // Parse JSON message
@@ -4346,58 +8581,53 @@ async fn handle_ws_mobile(socket: WebSocket) {
 
                         // Check if no devices paired (allow access for setup)
                         let no_devices = {
-                            let devices = PAIRED_DEVICES.lock();
+                            let devices = state.paired_devices.lock();
                             devices.is_empty()
                         };
 
-                        if no_devices || is_valid_token(token) {
+                        let device = device_for_token(token);
+                        if no_devices || device.is_some() {
                             authenticated = true;
+                            touch_token(token);
+                            auth_token = Some(token.to_string());
+                            scope = device.as_ref().map(|d| d.scope).unwrap_or(DeviceScope::Admin);
+                            auth_device = device.clone();
+                            audit::record(auth_device.as_ref(), "ws:auth", None, Some(addr.ip()));
+                            // Speak the newest protocol both sides know; clients that
+                            // predate negotiation don't send one and get v1.
+                            let client_protocol = json.get("protocolVersion").and_then(|v| v.as_u64()).unwrap_or(1);
+                            acks = device.is_some() && client_protocol >= 5;
+                            let wants_gzip = json.get("compression").and_then(|v| v.as_str()) == Some("gzip");
+                            compress.store(wants_gzip && client_protocol >= 6, std::sync::atomic::Ordering::Relaxed);
+                            let wants_cbor = json.get("encoding").and_then(|v| v.as_str()) == Some("cbor");
+                            cbor.store(wants_cbor && client_protocol >= 9, std::sync::atomic::Ordering::Relaxed);
+                            if let Some(client) = state.mobile_clients.lock().get_mut(&client_id) {
+                                client.device_id = device.map(|d| d.id);
+                                client.acks = acks;
+                                client.list_deltas = client_protocol >= 10;
+                            }
                             let _ = tx.send(serde_json::json!({
-                                "type": "auth_success"
+                                "type": "auth_success",
+                                "protocolVersion": client_protocol.min(PROTOCOL_VERSION as u64),
+                                "serverProtocolVersion": PROTOCOL_VERSION,
+                                "serverVersion": env!("CARGO_PKG_VERSION"),
+                                "scope": scope,
                             }).to_string());
 
                             // Send initial session list
-                            let sessions = load_sessions().unwrap_or_default();
-                            let json_running: std::collections::HashSet<String> = {
-                                let broadcasters = JSON_BROADCASTERS.lock();
-                                broadcasters.keys().cloned().collect()
-                            };
-                            let pty_running: std::collections::HashSet<String> = {
-                                let pty_sessions = PTY_SESSIONS.lock();
-                                pty_sessions.keys().cloned().collect()
-                            };
+                            let _ = tx.send(mobile_session_list().to_string());
 
-                            let sessions_with_status: Vec<serde_json::Value> = sessions.iter().map(|s| {
-                                let running = json_running.contains(&s.id) || pty_running.contains(&s.id);
-                                serde_json::json!({
-                                    "id": s.id,
-                                    "name": s.name,
-                                    "created_at": s.created_at,
-                                    "agent_type": s.agent_type,
-                                    "working_dir": s.working_dir,
-                                    "folder_id": s.folder_id,
-                                    "running": running,
-                                })
-                            }).collect();
-
-                            let folders_data: Vec<serde_json::Value> = load_folders().unwrap_or_default().into_iter().map(|f| {
-                                serde_json::json!({
-                                    "id": f.id,
-                                    "name": f.name,
-                                    "sort_order": f.sort_order,
-                                    "collapsed": f.collapsed,
-                                })
-                            }).collect();
-
-                            let settings = load_app_settings().unwrap_or_default();
-                            let _ = tx.send(serde_json::json!({
-                                "type": "session_list",
-                                "sessions": sessions_with_status,
-                                "folders": folders_data,
-                                "settings": {
-                                    "show_active_sessions_group": settings.show_active_sessions_group
+                            // Who's watching which session
+                            for message in presence::snapshot() {
+                                if !message["sessionId"].as_str().is_some_and(is_remote_hidden) {
+                                    let _ = tx.send(message.to_string());
                                 }
-                            }).to_string());
+                            }
+
+                            // Resend what the device was sent but never acked
+                            if let Some(device) = auth_device.as_ref().filter(|_| acks) {
+                                resend_unacked(&device.id, &tx);
+                            }
                         } else {
                             let _ = tx.send(serde_json::json!({
                                 "type": "auth_error",
@@ -4408,65 +8638,354 @@ async fn handle_ws_mobile(socket: WebSocket) {
 
                     "subscribe" => {
                         if !authenticated {
-                            let _ = tx.send(serde_json::json!({
-                                "type": "error",
-                                "message": "Not authenticated"
-                            }).to_string());
+                            let _ = tx.send(serde_json::json!({
+                                "type": "error",
+                                "message": "Not authenticated"
+                            }).to_string());
+                            continue;
+                        }
+
+                        let session_id = json.get("sessionId").and_then(|v| v.as_str()).unwrap_or("");
+                        if session_id.is_empty() {
+                            let _ = tx.send(serde_json::json!({
+                                "type": "error",
+                                "message": "sessionId required"
+                            }).to_string());
+                            continue;
+                        }
+                        if is_remote_hidden(session_id) {
+                            let _ = tx.send(serde_json::json!({
+                                "type": "error",
+                                "message": "Session not found"
+                            }).to_string());
+                            continue;
+                        }
+
+                        // A client that was subscribed before gets just what it missed;
+                        // the log lock keeps new messages queued behind the replay
+                        let since_seq = json.get("since_seq").and_then(|v| v.as_u64());
+                        let order = message_log::lock();
+                        let missed = since_seq.and_then(|seq| message_log::since(session_id, seq).ok().flatten());
+                        let filter = chat_filter::ChatFilter::from_subscribe(&json);
+
+                        // Add subscription
+                        {
+                            let mut clients = state.mobile_clients.lock();
+                            if let Some(client) = clients.get_mut(&client_id) {
+                                client.subscribed_sessions.insert(session_id.to_string(), filter);
+                            }
+                        }
+                        presence::join(session_id, &client_id, presence::View::Chat, auth_device.as_ref());
+
+                        if let Some(missed) = missed {
+                            for (seq, message) in missed {
+                                let _ = tx.send(serde_json::json!({
+                                    "type": "chat_message",
+                                    "sessionId": session_id,
+                                    "seq": seq,
+                                    "message": filter.apply(&message)
+                                }).to_string());
+                                if let Some(device) = auth_device.as_ref().filter(|_| acks) {
+                                    message_log::delivered(&device.id, session_id, seq);
+                                }
+                            }
+                        } else if let Some(history) = get_session_history(session_id) {
+                            // Send chat history for this session
+                            let history: Vec<serde_json::Value> = history.iter().map(|m| filter.apply(m)).collect();
+                            let _ = tx.send(serde_json::json!({
+                                "type": "chat_history",
+                                "sessionId": session_id,
+                                "seq": message_log::latest(session_id),
+                                "messages": history
+                            }).to_string());
+                        }
+                        drop(order);
+
+                        // Send current session status
+                        let is_running = {
+                            let json_broadcasters = state.json_broadcasters.lock();
+                            json_broadcasters.contains_key(session_id)
+                        };
+                        let _ = tx.send(serde_json::json!({
+                            "type": "session_status",
+                            "sessionId": session_id,
+                            "status": {
+                                "running": is_running,
+                                "isProcessing": is_session_processing(session_id)
+                            }
+                        }).to_string());
+                    }
+
+                    "unsubscribe" => {
+                        let session_id = json.get("sessionId").and_then(|v| v.as_str()).unwrap_or("");
+                        {
+                            let mut clients = state.mobile_clients.lock();
+                            if let Some(client) = clients.get_mut(&client_id) {
+                                client.subscribed_sessions.remove(session_id);
+                            }
+                        }
+                        presence::leave(session_id, &client_id, presence::View::Chat);
+                    }
+
+                    "send_message" => {
+                        if !authenticated {
+                            let _ = tx.send(serde_json::json!({
+                                "type": "error",
+                                "message": "Not authenticated"
+                            }).to_string());
+                            continue;
+                        }
+                        if scope < DeviceScope::Interact {
+                            let _ = tx.send(serde_json::json!({
+                                "type": "error",
+                                "message": "This device is read-only"
+                            }).to_string());
+                            continue;
+                        }
+
+                        let session_id = json.get("sessionId").and_then(|v| v.as_str()).unwrap_or("");
+                        let content = json.get("content");
+
+                        if session_id.is_empty() || content.is_none() {
+                            let _ = tx.send(serde_json::json!({
+                                "type": "error",
+                                "message": "sessionId and content required"
+                            }).to_string());
+                            continue;
+                        }
+                        if is_remote_hidden(session_id) {
+                            let _ = tx.send(serde_json::json!({
+                                "type": "error",
+                                "message": "Session not found"
+                            }).to_string());
+                            continue;
+                        }
+                        let settings = load_app_settings().unwrap_or_default();
+                        if let (Some(device), Some(limit)) =
+                            (auth_device.as_ref(), ratelimit::Bucket::Messages.limit(&settings.rate_limits))
+                        {
+                            let key = format!("device:{}", device.id);
+                            if let Some(retry_after) = ratelimit::retry_after(ratelimit::Bucket::Messages, &key, limit) {
+                                let _ = tx.send(serde_json::json!({
+                                    "type": "error",
+                                    "error": "rate_limited",
+                                    "message": format!("Sending too fast. Try again in {} seconds.", retry_after),
+                                    "retryAfter": retry_after
+                                }).to_string());
+                                continue;
+                            }
+                            ratelimit::hit(ratelimit::Bucket::Messages, &key, limit);
+                        }
+
+                        audit::record(auth_device.as_ref(), "ws:send_message", Some(session_id), Some(addr.ip()));
+
+                        // Convert content to string for the process
+                        // If content is already a string (pre-formatted JSON from mobile), use it directly
+                        // Otherwise serialize it as JSON
+                        let content_str = match content.unwrap() {
+                            serde_json::Value::String(s) => s.clone(),
+                            other => other.to_string(),
+                        };
+
+                        // Track user message in backend-side buffer and persist to DB
+                        // This ensures mobile messages survive even if desktop doesn't have the session open
+                        if let Ok(msg_value) = serde_json::from_str::<serde_json::Value>(&content_str) {
+                            append_session_message(session_id, msg_value);
+                            save_session_messages_to_db(session_id);
+                        }
+
+                        // Write to the session's process (ensure trailing newline)
+                        let write_str = if content_str.ends_with('\n') {
+                            content_str.clone()
+                        } else {
+                            content_str.clone() + "\n"
+                        };
+                        let _ = write_to_process(session_id.to_string(), write_str);
+
+                        // Broadcast to other clients watching this session
+                        if let Some(broadcaster) = {
+                            let broadcasters = state.json_broadcasters.lock();
+                            broadcasters.get(session_id).cloned()
+                        } {
+                            let _ = broadcaster.send(content_str.clone());
+                        }
+
+                        // Emit pre-parsed Tauri event so desktop sees mobile messages
+                        // Use json-process-message (not json-process-output) so the desktop
+                        // handles it correctly even without a trailing newline
+                        if let Some(app) = state.app_handle.lock().as_ref() {
+                            if let Ok(parsed) = serde_json::from_str::<ClaudeJsonMessage>(&content_str) {
+                                let _ = app.emit("json-process-message", serde_json::json!({
+                                    "session_id": session_id,
+                                    "message": parsed,
+                                }));
+                            } else {
+                                // Fallback: emit as raw output with trailing newline
+                                let _ = app.emit("json-process-output", serde_json::json!({
+                                    "session_id": session_id,
+                                    "data": content_str.clone() + "\n",
+                                }));
+                            }
+                        }
+                    }
+
+                    "get_changes" => {
+                        if !authenticated {
+                            let _ = tx.send(serde_json::json!({
+                                "type": "error",
+                                "message": "Not authenticated"
+                            }).to_string());
+                            continue;
+                        }
+
+                        let session_id = json.get("sessionId").and_then(|v| v.as_str()).unwrap_or("");
+                        if is_remote_hidden(session_id) {
+                            continue;
+                        }
+                        match changes::get_session_changes(session_id) {
+                            Ok(list) => {
+                                let _ = tx.send(serde_json::json!({
+                                    "type": "session_changes",
+                                    "sessionId": session_id,
+                                    "changes": list
+                                }).to_string());
+                            }
+                            Err(e) => {
+                                let _ = tx.send(serde_json::json!({
+                                    "type": "error",
+                                    "message": e
+                                }).to_string());
+                            }
+                        }
+                    }
+
+                    "subscribe_terminal" => {
+                        if !authenticated {
+                            let _ = tx.send(serde_json::json!({
+                                "type": "error",
+                                "message": "Not authenticated"
+                            }).to_string());
+                            continue;
+                        }
+
+                        let session_id = json.get("sessionId").and_then(|v| v.as_str()).unwrap_or("");
+                        if session_id.is_empty() || is_remote_hidden(session_id) {
+                            let _ = tx.send(serde_json::json!({
+                                "type": "error",
+                                "message": "Session not found"
+                            }).to_string());
+                            continue;
+                        }
+                        let rx = state.pty_broadcasters.lock().get(session_id).map(|b| b.subscribe());
+                        let Some(mut rx) = rx else {
+                            let _ = tx.send(serde_json::json!({
+                                "type": "error",
+                                "message": "Terminal session not running"
+                            }).to_string());
+                            continue;
+                        };
+                        audit::record(auth_device.as_ref(), "ws:subscribe_terminal", Some(session_id), Some(addr.ip()));
+
+                        // Last saved scrollback, so the view isn't blank until the next output
+                        let _ = tx.send(serde_json::json!({
+                            "type": "terminal_subscribed",
+                            "sessionId": session_id,
+                            "buffer": load_terminal_buffer(session_id.to_string()).ok().flatten(),
+                        }).to_string());
+
+                        let out = tx.clone();
+                        let watched_id = session_id.to_string();
+                        let forward = tokio::spawn(async move {
+                            use base64::Engine;
+                            loop {
+                                match rx.recv().await {
+                                    Ok(_) if is_remote_hidden(&watched_id) => break,
+                                    Ok(data) => {
+                                        let frame = serde_json::json!({
+                                            "type": "terminal_output",
+                                            "sessionId": watched_id,
+                                            "data": base64::engine::general_purpose::STANDARD.encode(&data),
+                                        });
+                                        if out.send(frame.to_string()).is_err() {
+                                            return;
+                                        }
+                                    }
+                                    // Dropped output would garble the screen; redraw it instead
+                                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                                        rx = resync::resubscribe(metrics::Stream::Pty, &rx, missed, Some(&watched_id));
+                                        let frame = serde_json::json!({
+                                            "type": "terminal_output",
+                                            "sessionId": watched_id,
+                                            "data": base64::engine::general_purpose::STANDARD.encode(resync::pty(&watched_id)),
+                                        });
+                                        if out.send(frame.to_string()).is_err() {
+                                            return;
+                                        }
+                                    }
+                                    Err(broadcast::error::RecvError::Closed) => break,
+                                }
+                            }
+                            let _ = out.send(serde_json::json!({
+                                "type": "terminal_closed",
+                                "sessionId": watched_id,
+                            }).to_string());
+                        });
+                        if let Some(previous) = terminals.insert(session_id.to_string(), forward) {
+                            previous.abort();
+                        }
+                        presence::join(session_id, &client_id, presence::View::Terminal, auth_device.as_ref());
+                    }
+
+                    "unsubscribe_terminal" => {
+                        let session_id = json.get("sessionId").and_then(|v| v.as_str()).unwrap_or("");
+                        if let Some(forward) = terminals.remove(session_id) {
+                            forward.abort();
+                        }
+                        presence::leave(session_id, &client_id, presence::View::Terminal);
+                    }
+
+                    // Keystrokes and window size for an attached terminal
+                    "terminal_input" | "terminal_resize" => {
+                        if !authenticated {
                             continue;
                         }
-
                         let session_id = json.get("sessionId").and_then(|v| v.as_str()).unwrap_or("");
-                        if session_id.is_empty() {
+                        if !terminals.contains_key(session_id) || is_remote_hidden(session_id) {
                             let _ = tx.send(serde_json::json!({
                                 "type": "error",
-                                "message": "sessionId required"
+                                "message": "Not subscribed to this terminal"
                             }).to_string());
                             continue;
                         }
-
-                        // Add subscription
-                        {
-                            let mut clients = MOBILE_CLIENTS.lock();
-                            if let Some(client) = clients.get_mut(&client_id) {
-                                client.subscribed_sessions.insert(session_id.to_string());
-                            }
-                        }
-
-                        // Send chat history for this session
-                        if let Some(history) = get_session_history(session_id) {
+                        if scope < DeviceScope::Interact {
                             let _ = tx.send(serde_json::json!({
-                                "type": "chat_history",
-                                "sessionId": session_id,
-                                "messages": history
+                                "type": "error",
+                                "message": "This device is read-only"
                             }).to_string());
+                            continue;
                         }
-
-                        // Send current session status
-                        let is_running = {
-                            let json_broadcasters = JSON_BROADCASTERS.lock();
-                            json_broadcasters.contains_key(session_id)
-                        };
-                        let _ = tx.send(serde_json::json!({
-                            "type": "session_status",
-                            "sessionId": session_id,
-                            "status": {
-                                "running": is_running,
-                                "isProcessing": false  // We'd need to track this properly
+                        let result = if msg_type == "terminal_input" {
+                            match json.get("data").and_then(|v| v.as_str()) {
+                                Some(data) => write_pty(session_id.to_string(), data.to_string()),
+                                None => Err("data required".to_string()),
                             }
-                        }).to_string());
-                    }
-
-                    "unsubscribe" => {
-                        let session_id = json.get("sessionId").and_then(|v| v.as_str()).unwrap_or("");
-                        {
-                            let mut clients = MOBILE_CLIENTS.lock();
-                            if let Some(client) = clients.get_mut(&client_id) {
-                                client.subscribed_sessions.remove(session_id);
+                        } else {
+                            match (json.get("cols").and_then(|v| v.as_u64()), json.get("rows").and_then(|v| v.as_u64())) {
+                                (Some(cols), Some(rows)) if cols > 0 && rows > 0 => {
+                                    resize_pty(session_id.to_string(), cols.min(u16::MAX as u64) as u16, rows.min(u16::MAX as u64) as u16)
+                                }
+                                _ => Err("cols and rows required".to_string()),
                             }
+                        };
+                        if let Err(e) = result {
+                            let _ = tx.send(serde_json::json!({
+                                "type": "error",
+                                "message": e
+                            }).to_string());
                         }
                     }
 
-                    "send_message" => {
+                    "create_session" | "rename_session" | "move_session_to_folder" | "delete_session" | "stop_session" => {
                         if !authenticated {
                             let _ = tx.send(serde_json::json!({
                                 "type": "error",
@@ -4474,75 +8993,112 @@ async fn handle_ws_mobile(socket: WebSocket) {
                             }).to_string());
                             continue;
                         }
+                        let result = if scope < DeviceScope::Interact {
+                            Err("This device is read-only".to_string())
+                        } else {
+                            mobile_session_action(msg_type, &json)
+                        };
+                        if let Ok(session) = &result {
+                            let session_id = session.as_ref().map(|s| s.id.as_str())
+                                .or_else(|| json.get("sessionId").and_then(|v| v.as_str()));
+                            audit::record(auth_device.as_ref(), &format!("ws:{}", msg_type), session_id, Some(addr.ip()));
+                        }
+                        // Everyone (this client included) also gets the usual
+                        // session_created / _updated / _deleted / _status broadcast
+                        let reply = match result {
+                            Ok(session) => serde_json::json!({
+                                "type": "session_action_result",
+                                "action": msg_type,
+                                "requestId": json.get("requestId"),
+                                "ok": true,
+                                "session": session,
+                            }),
+                            Err(e) => serde_json::json!({
+                                "type": "session_action_result",
+                                "action": msg_type,
+                                "requestId": json.get("requestId"),
+                                "ok": false,
+                                "error": e,
+                            }),
+                        };
+                        let _ = tx.send(reply.to_string());
+                    }
 
+                    // The client has shown chat messages up to seq
+                    "ack" => {
+                        let Some(device) = auth_device.as_ref().filter(|_| authenticated) else {
+                            continue;
+                        };
                         let session_id = json.get("sessionId").and_then(|v| v.as_str()).unwrap_or("");
-                        let content = json.get("content");
+                        if let Some(seq) = json.get("seq").and_then(|v| v.as_u64()) {
+                            if let Err(e) = message_log::acked(&device.id, session_id, seq) {
+                                tracing::warn!("[message_log] ack from {}: {}", device.id, e);
+                            }
+                        }
+                    }
 
-                        if session_id.is_empty() || content.is_none() {
+                    // This device's notification preferences (see notify_prefs)
+                    "get_notification_prefs" | "set_notification_prefs" => {
+                        let Some(device) = auth_device.as_ref().filter(|_| authenticated) else {
                             let _ = tx.send(serde_json::json!({
                                 "type": "error",
-                                "message": "sessionId and content required"
+                                "message": "Notification preferences need a paired device"
                             }).to_string());
                             continue;
-                        }
-
-                        // Convert content to string for the process
-                        // If content is already a string (pre-formatted JSON from mobile), use it directly
-                        // Otherwise serialize it as JSON
-                        let content_str = match content.unwrap() {
-                            serde_json::Value::String(s) => s.clone(),
-                            other => other.to_string(),
                         };
-
-                        // Track user message in backend-side buffer and persist to DB
-                        // This ensures mobile messages survive even if desktop doesn't have the session open
-                        if let Ok(msg_value) = serde_json::from_str::<serde_json::Value>(&content_str) {
-                            append_session_message(session_id, msg_value);
-                            save_session_messages_to_db(session_id);
+                        if msg_type == "set_notification_prefs" {
+                            let saved = serde_json::from_value::<notify_prefs::NotifyPrefs>(json["prefs"].clone())
+                                .map_err(|e| format!("Invalid preferences: {}", e))
+                                .and_then(|prefs| set_device_notify_prefs(device.id.clone(), prefs));
+                            if let Err(e) = saved {
+                                let _ = tx.send(serde_json::json!({
+                                    "type": "error",
+                                    "message": e
+                                }).to_string());
+                                continue;
+                            }
+                            audit::record(auth_device.as_ref(), "ws:set_notification_prefs", None, Some(addr.ip()));
                         }
+                        let _ = tx.send(serde_json::json!({
+                            "type": "notification_prefs",
+                            "prefs": device_notify_prefs(&device.id).unwrap_or_default(),
+                        }).to_string());
+                    }
 
-                        // Write to the session's process (ensure trailing newline)
-                        let write_str = if content_str.ends_with('\n') {
-                            content_str.clone()
-                        } else {
-                            content_str.clone() + "\n"
-                        };
-                        let _ = write_to_process(session_id.to_string(), write_str);
-
-                        // Broadcast to other clients watching this session
-                        if let Some(broadcaster) = {
-                            let broadcasters = JSON_BROADCASTERS.lock();
-                            broadcasters.get(session_id).cloned()
-                        } {
-                            let _ = broadcaster.send(content_str.clone());
+                    // Reply to an agent's ask_user / request_approval (see asks)
+                    "answer_question" => {
+                        if !authenticated {
+                            continue;
                         }
-
-                        // Emit pre-parsed Tauri event so desktop sees mobile messages
-                        // Use json-process-message (not json-process-output) so the desktop
-                        // handles it correctly even without a trailing newline
-                        if let Some(app) = APP_HANDLE.lock().as_ref() {
-                            if let Ok(parsed) = serde_json::from_str::<ClaudeJsonMessage>(&content_str) {
-                                let _ = app.emit("json-process-message", serde_json::json!({
-                                    "session_id": session_id,
-                                    "message": parsed,
-                                }));
-                            } else {
-                                // Fallback: emit as raw output with trailing newline
-                                let _ = app.emit("json-process-output", serde_json::json!({
-                                    "session_id": session_id,
-                                    "data": content_str.clone() + "\n",
-                                }));
+                        let ask_id = json.get("askId").and_then(|v| v.as_str()).unwrap_or("");
+                        let session_id = asks::session_of(ask_id).filter(|id| !is_remote_hidden(id));
+                        let result = match &session_id {
+                            None => Err("Question not found".to_string()),
+                            Some(_) if scope < DeviceScope::Interact => Err("This device is read-only".to_string()),
+                            Some(_) => asks::answer(ask_id, asks::Answer {
+                                text: json.get("text").and_then(|v| v.as_str()).map(String::from),
+                                approved: json.get("approved").and_then(|v| v.as_bool()),
+                            }),
+                        };
+                        match result {
+                            Ok(()) => audit::record(auth_device.as_ref(), "ws:answer_question", session_id.as_deref(), Some(addr.ip())),
+                            Err(e) => {
+                                let _ = tx.send(serde_json::json!({
+                                    "type": "error",
+                                    "message": e
+                                }).to_string());
                             }
                         }
                     }
 
                     "interrupt" => {
-                        if !authenticated {
+                        if !authenticated || scope < DeviceScope::Interact {
                             continue;
                         }
 
                         let session_id = json.get("sessionId").and_then(|v| v.as_str()).unwrap_or("");
-                        if !session_id.is_empty() {
+                        if !session_id.is_empty() && !is_remote_hidden(session_id) {
+                            audit::record(auth_device.as_ref(), "ws:interrupt", Some(session_id), Some(addr.ip()));
                             let _ = interrupt_json_process(session_id.to_string());
                         }
                     }
@@ -4556,7 +9112,10 @@ async fn handle_ws_mobile(socket: WebSocket) {
                 }
             }
             Ok(Message::Pong(_)) => {
-                // Connection alive
+                // Connection alive; the device was seen just now
+                if let Some(token) = auth_token.as_deref() {
+                    touch_token(token);
+                }
             }
             Ok(Message::Close(_)) => break,
             Err(_) => break,
@@ -4566,10 +9125,64 @@ async fn handle_ws_mobile(socket: WebSocket) {
 
     // Cleanup
     send_task.abort();
-    {
-        let mut clients = MOBILE_CLIENTS.lock();
+    for forward in terminals.into_values() {
+        forward.abort();
+    }
+    let device_still_connected = {
+        let mut clients = state.mobile_clients.lock();
         clients.remove(&client_id_for_cleanup);
+        auth_device.as_ref().is_some_and(|d| clients.values().any(|c| c.device_id.as_deref() == Some(d.id.as_str())))
+    };
+    presence::leave_all(&client_id_for_cleanup);
+    if stale {
+        tracing::warn!("[mobile-ws] dropped {} after {} missed pings", addr, MOBILE_MISSED_PINGS);
     }
+    // The device's last connection is gone
+    if let Some(device) = auth_device.filter(|_| !device_still_connected) {
+        if let Some(app) = state.app_handle.lock().as_ref() {
+            let _ = app.emit("device-offline", serde_json::json!({
+                "device_id": device.id,
+                "name": device.name,
+                "reason": if stale { "missed_pings" } else { "closed" },
+            }));
+        }
+    }
+}
+
+/// Session management from the mobile WebSocket, through the same code as the
+/// REST endpoints. Returns the created or changed session, if any.
+#[cfg(not(target_os = "ios"))]
+fn mobile_session_action(action: &str, msg: &serde_json::Value) -> Result<Option<SessionData>, String> {
+    let field = |name: &str| msg.get(name).cloned().unwrap_or(serde_json::Value::Null);
+    if action == "create_session" {
+        let body = serde_json::json!({
+            "name": field("name"),
+            "agent_type": field("agentType"),
+            "working_dir": field("workingDir"),
+            "folder_id": field("folderId"),
+            "custom_command": field("customCommand"),
+        });
+        return create_session_from_request(&body).map(Some);
+    }
+
+    let session_id = msg.get("sessionId").and_then(|v| v.as_str()).unwrap_or("");
+    if session_id.is_empty() {
+        return Err("sessionId required".to_string());
+    }
+    if is_remote_hidden(session_id) {
+        return Err("Session not found".to_string());
+    }
+    let result = match action {
+        "rename_session" => update_session_from_request(session_id, &serde_json::json!({ "name": field("name") })).map(Some),
+        // folderId null (or missing) takes the session out of its folder
+        "move_session_to_folder" => {
+            update_session_from_request(session_id, &serde_json::json!({ "folder_id": field("folderId") })).map(Some)
+        }
+        "delete_session" => delete_session_remotely(session_id).map(|()| None),
+        "stop_session" => stop_session_remotely(session_id).map(|()| None),
+        _ => return Err(format!("Unknown message type: {}", action)),
+    };
+    result.map_err(|(_, e)| e)
 }
 
 // iOS stub for mobile WebSocket
@@ -4580,103 +9193,356 @@ async fn ws_mobile_handler(_ws: WebSocketUpgrade) -> impl IntoResponse {
 
 #[cfg(not(target_os = "ios"))]
 fn start_web_server() {
+    Lazy::force(&SERVER_STARTED_AT);
     // Load paired devices from database
     load_paired_devices();
 
-    // Spawn web server in a dedicated thread with its own tokio runtime
-    // This avoids issues with Tauri's runtime not being ready during setup
-    thread::spawn(|| {
-        let rt = tokio::runtime::Runtime::new().expect("Failed to create tokio runtime for web server");
-        rt.block_on(async {
-            // Find mobile-web-dist directory for serving static assets
-            let exe_dir = std::env::current_exe()
-                .ok()
-                .and_then(|p| p.parent().map(|p| p.to_path_buf()));
-
-            let mobile_web_dir = [
-                exe_dir.as_ref().map(|d| d.join("mobile-web-dist")),
-                exe_dir.as_ref().map(|d| d.join("../Resources/mobile-web-dist")),
-                Some(std::path::PathBuf::from("mobile-web-dist")),
-            ]
-            .into_iter()
-            .flatten()
-            .find(|p| p.exists())
-            .unwrap_or_else(|| std::path::PathBuf::from("mobile-web-dist"));
-
-            let app = Router::new()
-                .route("/", get(web_index))
-                // Serve static assets from mobile-web-dist
-                .nest_service("/assets", ServeDir::new(mobile_web_dir.join("assets")))
-                // Auth endpoints (no auth required)
-                .route("/api/auth/check", get(api_auth_check))
-                .route("/api/auth/request-pairing", axum::routing::post(api_request_pairing))
-                .route("/api/auth/pair", axum::routing::post(api_pair))
-                .route("/api/auth/pin-status", get(api_pin_status))
-                .route("/api/auth/pin-login", axum::routing::post(api_pin_login))
-                // Protected endpoints
-                .route("/api/sessions", get(api_list_sessions).post(api_create_session))
-                .route("/api/sessions/:session_id/buffer", get(api_get_buffer))
-                .route("/api/sessions/:session_id/start", axum::routing::post(api_start_session))
-                .route("/api/sessions/:session_id/interrupt", axum::routing::post(api_interrupt_session))
-                .route("/api/webhook/teams", axum::routing::post(api_webhook_teams))
-                .route("/api/ws/:session_id", get(ws_handler))
-                .route("/api/ws/status", get(ws_status_handler))
-                .route("/api/ws/mobile", get(ws_mobile_handler))
-                // MCP HTTP endpoints for external control
-                .route("/api/mcp/execute", axum::routing::post(api_mcp_execute))
-                .route("/api/mcp/result", axum::routing::post(api_mcp_result))
-                // Search
-                .route("/api/search/messages", get(api_search_messages))
-                .route("/api/search/context", get(api_search_context))
-                .route("/api/search/stats", get(api_search_stats))
-                .route("/api/search/rebuild", axum::routing::post(api_search_rebuild))
-                .route("/api/schedules", get(api_list_schedules).post(api_create_schedule))
-                .route("/api/schedules/:id", axum::routing::patch(api_update_schedule).delete(api_delete_schedule))
-                .layer(CorsLayer::permissive());
-
-            // Try ports starting from WEB_PORT_BASE until we find one available
-            let mut listener = None;
-            let mut bound_port = WEB_PORT_BASE;
-
-            for port_offset in 0..WEB_PORT_MAX_ATTEMPTS {
-                let port = WEB_PORT_BASE + port_offset;
-                let addr = SocketAddr::from(([0, 0, 0, 0], port));
-
-                match tokio::net::TcpListener::bind(addr).await {
-                    Ok(l) => {
-                        bound_port = port;
-                        listener = Some(l);
-                        break;
-                    }
-                    Err(e) => {
-                        println!("Port {} unavailable ({}), trying next...", port, e);
-                    }
-                }
+    // Spawn web server on the shared runtime
+    state::spawn(async {
+        // Expire tokens in the background so idle connections get dropped too
+        tokio::spawn(async {
+            let mut sweep = tokio::time::interval(std::time::Duration::from_secs(300));
+            loop {
+                sweep.tick().await;
+                purge_expired_tokens();
+                prune_pairing_requests(&mut state::get().pairing_requests.lock());
             }
+        });
 
-            let listener = listener.expect(&format!(
-                "Failed to bind to any port in range {}-{}",
-                WEB_PORT_BASE,
-                WEB_PORT_BASE + WEB_PORT_MAX_ATTEMPTS - 1
-            ));
-
-            // Store the bound port for other parts of the app to access
-            {
-                let mut port_guard = WEB_SERVER_PORT.lock();
-                *port_guard = Some(bound_port);
+        // Find mobile-web-dist directory for serving static assets
+        let exe_dir = std::env::current_exe()
+            .ok()
+            .and_then(|p| p.parent().map(|p| p.to_path_buf()));
+
+        let mobile_web_dir = [
+            exe_dir.as_ref().map(|d| d.join("mobile-web-dist")),
+            exe_dir.as_ref().map(|d| d.join("../Resources/mobile-web-dist")),
+            Some(std::path::PathBuf::from("mobile-web-dist")),
+        ]
+        .into_iter()
+        .flatten()
+        .find(|p| p.exists())
+        .unwrap_or_else(|| std::path::PathBuf::from("mobile-web-dist"));
+
+        // Serve until restart_web_server() asks for the settings to be re-read
+        loop {
+            let mut restart = state::get().web_server_restart.subscribe();
+            let settings = load_app_settings().unwrap_or_default();
+            if shutdown::in_progress() {
+                break;
+            }
+            if settings.web_server_enabled {
+                let restarted = tokio::select! {
+                    _ = serve_web(&mobile_web_dir, settings) => false,
+                    _ = restart.changed() => true,
+                };
+                on_web_server_stopped();
+                if restarted {
+                    continue;
+                }
+            } else {
+                tracing::info!("Web server disabled");
+                let _ = tokio::task::spawn_blocking(tunnel::stop).await;
             }
+            let _ = restart.changed().await;
+        }
+    });
+}
 
-            // Notify the app about the bound port
-            if let Some(app) = APP_HANDLE.lock().as_ref() {
-                let _ = app.emit("web-server-started", serde_json::json!({
-                    "port": bound_port
-                }));
+/// The web app and API, handling requests against `state`
+#[cfg(not(target_os = "ios"))]
+fn web_router(state: Arc<state::AppState>, mobile_web_dir: &std::path::Path, settings: &AppSettings) -> Router {
+    // Versioned API. /api/* stays an alias for the current version so
+    // existing clients keep working; a breaking change gets /api/v2.
+    let api = Router::new()
+        // Auth endpoints (no auth required)
+        .route("/health", get(api_health))
+        .route("/version", get(api_version))
+        .route("/openapi.json", get(api_openapi))
+        .route("/cert", get(api_cert))
+        .route("/instances", get(api_discover_instances))
+        .route("/auth/qr", get(api_pairing_qr))
+        .route("/auth/check", get(api_auth_check))
+        .route("/auth/request-pairing", axum::routing::post(api_request_pairing))
+        .route("/auth/pair", axum::routing::post(api_pair))
+        .route("/auth/refresh", axum::routing::post(api_auth_refresh))
+        .route("/auth/pin-status", get(api_pin_status))
+        .route("/auth/pin-login", axum::routing::post(api_pin_login))
+        // Protected endpoints
+        .route("/sessions", get(api_list_sessions).post(api_create_session))
+        .route("/sessions/:session_id/buffer", get(api_get_buffer))
+        .route("/sessions/:session_id/start", axum::routing::post(api_start_session))
+        .route("/sessions/:session_id/interrupt", axum::routing::post(api_interrupt_session))
+        .route("/sessions/:session_id/stop", axum::routing::post(api_stop_session))
+        .route("/sessions/:session_id/input", axum::routing::post(api_session_input))
+        .route("/sessions/:session_id/prompt", axum::routing::post(api_session_prompt))
+        .route("/webhook/teams", axum::routing::post(api_webhook_teams))
+        .route("/ws/:session_id", get(ws_handler))
+        .route("/ws/status", get(ws_status_handler))
+        .route("/ws/mobile", get(ws_mobile_handler))
+        // MCP HTTP endpoints for external control
+        .route("/mcp/execute", axum::routing::post(api_mcp_execute))
+        .route("/mcp/result", axum::routing::post(api_mcp_result))
+        .route("/mcp/screenshot", axum::routing::post(api_mcp_screenshot))
+        .route("/mcp/cancel", axum::routing::post(api_mcp_cancel))
+        .route("/mcp/tools", get(api_mcp_tools))
+        .route("/asks", get(api_list_asks))
+        .route("/asks/:ask_id/answer", axum::routing::post(api_answer_ask))
+        .route("/mcp", axum::routing::post(api_mcp_post).get(api_mcp_events).delete(api_mcp_close))
+        // Search
+        .route("/search/messages", get(api_search_messages))
+        .route("/search/context", get(api_search_context))
+        .route("/search/stats", get(api_search_stats))
+        .route("/search/rebuild", axum::routing::post(api_search_rebuild))
+        .route("/schedules", get(api_list_schedules).post(api_create_schedule))
+        .route("/schedules/:id", axum::routing::patch(api_update_schedule).delete(api_delete_schedule))
+        .route("/fanout/:id", get(api_get_fanout))
+        .route("/sessions/:id/children", axum::routing::post(api_create_child_session))
+        .route(
+            "/sessions/:session_id",
+            axum::routing::patch(api_update_session).delete(api_delete_session),
+        )
+        .route("/settings", get(api_get_settings).patch(api_update_settings))
+        .route(
+            "/folders",
+            get(api_list_folders).post(api_create_folder).patch(api_reorder_folders),
+        )
+        .route("/folders/:id", axum::routing::patch(api_update_folder).delete(api_delete_folder))
+        .route("/workspaces", get(api_list_workspaces))
+        .route("/devices", get(api_list_devices))
+        .route("/devices/:id", axum::routing::patch(api_update_device).delete(api_revoke_device))
+        .route("/audit", get(api_audit_log))
+        .route("/logs", get(api_logs))
+        .route("/webhooks", get(api_list_webhooks))
+        .route("/push/vapid-key", get(api_push_vapid_key))
+        .route("/hubs", get(api_list_hubs).post(api_add_hub))
+        .route("/hubs/sessions", get(api_hub_sessions))
+        .route("/hubs/:id", axum::routing::delete(api_remove_hub))
+        .route("/hubs/:id/api/*path", axum::routing::any(api_hub_proxy))
+        .route("/hubs/:id/ws/mobile", get(ws_hub_mobile_handler))
+        .route(
+            "/push/subscription",
+            get(api_push_subscription).put(api_push_subscription).delete(api_push_subscription),
+        )
+        .route("/run", axum::routing::post(api_run))
+        .route("/run/:id", get(api_get_run))
+        .route(
+            "/sessions/:id/audio",
+            axum::routing::post(api_session_audio)
+                .layer(axum::extract::DefaultBodyLimit::max(25 * 1024 * 1024)),
+        )
+        .route(
+            "/sessions/:id/attachments",
+            axum::routing::post(api_upload_attachment)
+                .layer(axum::extract::DefaultBodyLimit::max(20 * 1024 * 1024)),
+        )
+        .route_layer(axum::middleware::from_fn(audit_requests))
+        .route_layer(axum::middleware::from_fn(rate_limit));
+
+    Router::new()
+        .route("/", get(web_index))
+        // Serve static assets from mobile-web-dist
+        .nest_service("/assets", ServeDir::new(mobile_web_dir.join("assets")))
+        // Service worker for push notifications; must be at the root to cover the app
+        .route_service("/sw.js", tower_http::services::ServeFile::new(mobile_web_dir.join("sw.js")))
+        // Prometheus scrape endpoint (protected)
+        .route("/metrics", get(api_metrics))
+        // Guest share links (the token is the credential)
+        .route("/share/:token", get(share_handler))
+        .nest("/api/v1", api.clone())
+        .nest("/api", api)
+        .layer(cors_layer(&settings.cors_allowed_origins))
+        .layer(axum::middleware::from_fn_with_state(
+            std::sync::Arc::new(allowlist::Allowlist::parse(&settings.ip_allowlist).unwrap_or_else(|e| {
+                // Fail closed: only this computer until the list is fixed
+                tracing::error!("[web] invalid IP allowlist ({}); accepting local connections only", e);
+                allowlist::Allowlist::local_only()
+            })),
+            enforce_ip_allowlist,
+        ))
+        .with_state(state)
+}
+
+/// Bind, advertise and serve the web API with the given settings. Runs until
+/// the listener fails or the future is dropped.
+#[cfg(not(target_os = "ios"))]
+async fn serve_web(mobile_web_dir: &std::path::Path, settings: AppSettings) {
+    let app = web_router(state::get().clone(), mobile_web_dir, &settings);
+
+    // Try ports starting from WEB_PORT_BASE until we find one available
+    let bind_ip = web_bind_ip(&settings);
+    let mut listener = None;
+    let mut bound_port = WEB_PORT_BASE;
+
+    for port_offset in 0..WEB_PORT_MAX_ATTEMPTS {
+        let port = WEB_PORT_BASE + port_offset;
+        let addr = SocketAddr::new(bind_ip, port);
+
+        match tokio::net::TcpListener::bind(addr).await {
+            Ok(l) => {
+                bound_port = port;
+                listener = Some(l);
+                break;
             }
+            Err(e) => {
+                tracing::info!("Port {} unavailable ({}), trying next...", port, e);
+            }
+        }
+    }
+
+    let Some(listener) = listener else {
+        tracing::error!(
+            "Failed to bind to any port in range {}-{}",
+            WEB_PORT_BASE,
+            WEB_PORT_BASE + WEB_PORT_MAX_ATTEMPTS - 1
+        );
+        return;
+    };
 
-            println!("Web server listening on http://0.0.0.0:{}", bound_port);
-            axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await.unwrap();
+    // Store the bound port for other parts of the app to access
+    {
+        let mut port_guard = state::get().web_server_port.lock();
+        *port_guard = Some(bound_port);
+    }
+
+    // HTTPS if enabled; a certificate problem falls back to plain HTTP
+    // rather than leaving remote access down
+    let acceptor = if settings.tls_enabled {
+        match tls::acceptor(settings.tls_cert_path.as_deref(), settings.tls_key_path.as_deref()) {
+            Ok(acceptor) => Some(acceptor),
+            Err(e) => {
+                tracing::warn!("[tls] {}; serving plain HTTP", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    // Nothing on the LAN can reach a loopback-only server
+    if !bind_ip.is_loopback() {
+        discovery::advertise(bound_port, acceptor.is_some());
+    }
+    std::thread::spawn(move || {
+        tunnel::start(bound_port, &tunnel::TunnelConfig {
+            mode: settings.tunnel_mode.as_deref(),
+            ssh_target: settings.tunnel_ssh_target.as_deref(),
+            ssh_remote_port: settings.tunnel_ssh_remote_port,
+            public_url: settings.tunnel_public_url.as_deref(),
         });
     });
+
+    // Notify the app about the bound port
+    if let Some(app) = state::get().app_handle.lock().as_ref() {
+        let _ = app.emit("web-server-started", serde_json::json!({
+            "port": bound_port,
+            "tls": acceptor.is_some()
+        }));
+    }
+
+    // Local automation socket, alongside the TCP listener
+    #[cfg(unix)]
+    let local_socket = local_socket::serve(app.clone());
+    #[cfg(not(unix))]
+    let local_socket = std::future::pending::<()>();
+
+    let tcp = async move {
+        match acceptor {
+            Some(acceptor) => {
+                tracing::info!("Web server listening on https://{}", SocketAddr::new(bind_ip, bound_port));
+                tls::serve(listener, app, acceptor).await;
+            }
+            None => {
+                tracing::info!("Web server listening on http://{}", SocketAddr::new(bind_ip, bound_port));
+                if let Err(e) = axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await {
+                    tracing::error!("Web server stopped: {}", e);
+                }
+            }
+        }
+    };
+    tokio::select! {
+        _ = tcp => {}
+        _ = local_socket => {}
+    }
+}
+
+#[cfg(not(target_os = "ios"))]
+fn on_web_server_stopped() {
+    *state::get().web_server_port.lock() = None;
+    discovery::stop_advertising();
+    #[cfg(unix)]
+    local_socket::remove();
+    if let Some(app) = state::get().app_handle.lock().as_ref() {
+        let _ = app.emit("web-server-stopped", serde_json::json!({}));
+    }
+}
+
+/// Drop every connection and start the web server again from the current
+/// settings (or leave it stopped if it has been disabled).
+#[cfg(not(target_os = "ios"))]
+fn restart_web_server() {
+    close_web_server("server_restarting");
+}
+
+/// Drop every connection and stop serving for good (shutdown)
+#[cfg(not(target_os = "ios"))]
+fn stop_web_server() {
+    close_web_server("server_shutting_down");
+}
+
+#[cfg(not(target_os = "ios"))]
+fn close_web_server(reason: &'static str) {
+    for client in state::get().mobile_clients.lock().values_mut() {
+        if let Some(revoke) = client.revoke.take() {
+            let _ = revoke.send(reason);
+        }
+    }
+    state::get().web_server_restart.send_modify(|generation| *generation += 1);
+}
+
+/// Parsed `web_bind_address`; an unparseable address falls back to loopback
+/// rather than exposing the server more widely than intended.
+#[cfg(not(target_os = "ios"))]
+fn web_bind_ip(settings: &AppSettings) -> std::net::IpAddr {
+    match settings.web_bind_address.as_deref().map(str::trim).filter(|a| !a.is_empty()) {
+        None => std::net::Ipv4Addr::UNSPECIFIED.into(),
+        Some("localhost") => std::net::Ipv4Addr::LOCALHOST.into(),
+        Some(addr) => addr.parse().unwrap_or_else(|e| {
+            tracing::warn!("[web] invalid bind address '{}' ({}); listening on loopback only", addr, e);
+            std::net::Ipv4Addr::LOCALHOST.into()
+        }),
+    }
+}
+
+/// Refuse clients outside `ip_allowlist` before any route (including
+/// WebSocket upgrades and static files) sees the request.
+#[cfg(not(target_os = "ios"))]
+async fn enforce_ip_allowlist(
+    axum::extract::State(allowlist): axum::extract::State<std::sync::Arc<allowlist::Allowlist>>,
+    axum::extract::ConnectInfo(addr): axum::extract::ConnectInfo<SocketAddr>,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    let forwarded_for = request.headers().get("x-forwarded-for").and_then(|v| v.to_str().ok());
+    if !allowlist.allows_forwarded(addr.ip(), forwarded_for) {
+        return (StatusCode::FORBIDDEN, "This address is not allowed to connect").into_response();
+    }
+    next.run(request).await
+}
+
+#[cfg(not(target_os = "ios"))]
+fn cors_layer(allowed_origins: &[String]) -> CorsLayer {
+    if allowed_origins.is_empty() {
+        return CorsLayer::permissive();
+    }
+    let origins: Vec<axum::http::HeaderValue> = allowed_origins
+        .iter()
+        .filter_map(|o| axum::http::HeaderValue::from_str(o.trim().trim_end_matches('/')).ok())
+        .collect();
+    CorsLayer::new()
+        .allow_origin(tower_http::cors::AllowOrigin::list(origins))
+        .allow_methods(tower_http::cors::Any)
+        .allow_headers(tower_http::cors::Any)
+        .expose_headers(tower_http::cors::Any)
 }
 
 // iOS version of web server - same functionality but no PTY routes will work
@@ -4684,97 +9550,114 @@ fn start_web_server() {
 // but we still run the server for potential local testing/development
 #[cfg(target_os = "ios")]
 fn start_web_server() {
+    Lazy::force(&SERVER_STARTED_AT);
     // Load paired devices from database
     load_paired_devices();
 
-    // Spawn web server in a dedicated thread with its own tokio runtime
-    thread::spawn(|| {
-        let rt = tokio::runtime::Runtime::new().expect("Failed to create tokio runtime for web server");
-        rt.block_on(async {
-            // Find mobile-web-dist directory for serving static assets
-            let exe_dir = std::env::current_exe()
-                .ok()
-                .and_then(|p| p.parent().map(|p| p.to_path_buf()));
-
-            let mobile_web_dir = [
-                exe_dir.as_ref().map(|d| d.join("mobile-web-dist")),
-                exe_dir.as_ref().map(|d| d.join("../Resources/mobile-web-dist")),
-                Some(std::path::PathBuf::from("mobile-web-dist")),
-            ]
-            .into_iter()
-            .flatten()
-            .find(|p| p.exists())
-            .unwrap_or_else(|| std::path::PathBuf::from("mobile-web-dist"));
-
-            let app = Router::new()
-                .route("/", get(web_index))
-                // Serve static assets from mobile-web-dist
-                .nest_service("/assets", tower_http::services::ServeDir::new(mobile_web_dir.join("assets")))
-                // Auth endpoints (no auth required)
-                .route("/api/auth/check", get(api_auth_check))
-                .route("/api/auth/request-pairing", axum::routing::post(api_request_pairing))
-                .route("/api/auth/pair", axum::routing::post(api_pair))
-                .route("/api/auth/pin-status", get(api_pin_status))
-                .route("/api/auth/pin-login", axum::routing::post(api_pin_login))
-                // Protected endpoints - PTY start and WebSocket will return errors on iOS
-                .route("/api/sessions", get(api_list_sessions).post(api_create_session))
-                .route("/api/sessions/:session_id/buffer", get(api_get_buffer))
-                .route("/api/sessions/:session_id/start", axum::routing::post(api_start_session))
-                .route("/api/sessions/:session_id/interrupt", axum::routing::post(api_interrupt_session))
-                .route("/api/ws/:session_id", get(ws_handler))
-                .route("/api/ws/status", get(ws_status_handler))
-                .route("/api/ws/mobile", get(ws_mobile_handler))
-                // Search
-                .route("/api/search/messages", get(api_search_messages))
-                .route("/api/search/context", get(api_search_context))
-                .route("/api/search/stats", get(api_search_stats))
-                .route("/api/search/rebuild", axum::routing::post(api_search_rebuild))
-                .route("/api/schedules", get(api_list_schedules).post(api_create_schedule))
-                .route("/api/schedules/:id", axum::routing::patch(api_update_schedule).delete(api_delete_schedule))
-                .layer(CorsLayer::permissive());
-
-            // Try ports starting from WEB_PORT_BASE until we find one available
-            let mut listener = None;
-            let mut bound_port = WEB_PORT_BASE;
-
-            for port_offset in 0..WEB_PORT_MAX_ATTEMPTS {
-                let port = WEB_PORT_BASE + port_offset;
-                let addr = SocketAddr::from(([0, 0, 0, 0], port));
-
-                match tokio::net::TcpListener::bind(addr).await {
-                    Ok(l) => {
-                        bound_port = port;
-                        listener = Some(l);
-                        break;
-                    }
-                    Err(e) => {
-                        println!("Port {} unavailable ({}), trying next...", port, e);
-                    }
+    // Spawn web server on the shared runtime
+    state::spawn(async {
+        // Find mobile-web-dist directory for serving static assets
+        let exe_dir = std::env::current_exe()
+            .ok()
+            .and_then(|p| p.parent().map(|p| p.to_path_buf()));
+
+        let mobile_web_dir = [
+            exe_dir.as_ref().map(|d| d.join("mobile-web-dist")),
+            exe_dir.as_ref().map(|d| d.join("../Resources/mobile-web-dist")),
+            Some(std::path::PathBuf::from("mobile-web-dist")),
+        ]
+        .into_iter()
+        .flatten()
+        .find(|p| p.exists())
+        .unwrap_or_else(|| std::path::PathBuf::from("mobile-web-dist"));
+
+        // Versioned API. /api/* stays an alias for the current version so
+        // existing clients keep working; a breaking change gets /api/v2.
+        let api = Router::new()
+            // Auth endpoints (no auth required)
+            .route("/health", get(api_health))
+            .route("/version", get(api_version))
+            .route("/openapi.json", get(api_openapi))
+            .route("/auth/check", get(api_auth_check))
+            .route("/auth/request-pairing", axum::routing::post(api_request_pairing))
+            .route("/auth/pair", axum::routing::post(api_pair))
+            .route("/auth/refresh", axum::routing::post(api_auth_refresh))
+            .route("/auth/pin-status", get(api_pin_status))
+            .route("/auth/pin-login", axum::routing::post(api_pin_login))
+            // Protected endpoints - PTY start and WebSocket will return errors on iOS
+            .route("/sessions", get(api_list_sessions).post(api_create_session))
+            .route("/sessions/:session_id/buffer", get(api_get_buffer))
+            .route("/sessions/:session_id/start", axum::routing::post(api_start_session))
+            .route("/sessions/:session_id/interrupt", axum::routing::post(api_interrupt_session))
+            .route("/sessions/:session_id/stop", axum::routing::post(api_stop_session))
+            .route("/ws/:session_id", get(ws_handler))
+            .route("/ws/status", get(ws_status_handler))
+            .route("/ws/mobile", get(ws_mobile_handler))
+            // Search
+            .route("/search/messages", get(api_search_messages))
+            .route("/search/context", get(api_search_context))
+            .route("/search/stats", get(api_search_stats))
+            .route("/search/rebuild", axum::routing::post(api_search_rebuild))
+            .route("/schedules", get(api_list_schedules).post(api_create_schedule))
+            .route("/schedules/:id", axum::routing::patch(api_update_schedule).delete(api_delete_schedule))
+            // Remote hubs; an iOS hub mostly shows other machines' sessions
+            .route("/hubs", get(api_list_hubs).post(api_add_hub))
+            .route("/hubs/sessions", get(api_hub_sessions))
+            .route("/hubs/:id", axum::routing::delete(api_remove_hub))
+            .route("/hubs/:id/api/*path", axum::routing::any(api_hub_proxy))
+            .route("/hubs/:id/ws/mobile", get(ws_hub_mobile_handler))
+            .route_layer(axum::middleware::from_fn(rate_limit));
+
+        let app = Router::new()
+            .route("/", get(web_index))
+            // Serve static assets from mobile-web-dist
+            .nest_service("/assets", tower_http::services::ServeDir::new(mobile_web_dir.join("assets")))
+            .nest("/api/v1", api.clone())
+            .nest("/api", api)
+            .layer(CorsLayer::permissive())
+            .with_state(state::get().clone());
+
+        // Try ports starting from WEB_PORT_BASE until we find one available
+        let mut listener = None;
+        let mut bound_port = WEB_PORT_BASE;
+
+        for port_offset in 0..WEB_PORT_MAX_ATTEMPTS {
+            let port = WEB_PORT_BASE + port_offset;
+            let addr = SocketAddr::from(([0, 0, 0, 0], port));
+
+            match tokio::net::TcpListener::bind(addr).await {
+                Ok(l) => {
+                    bound_port = port;
+                    listener = Some(l);
+                    break;
+                }
+                Err(e) => {
+                    tracing::info!("Port {} unavailable ({}), trying next...", port, e);
                 }
             }
+        }
 
-            let listener = listener.expect(&format!(
-                "Failed to bind to any port in range {}-{}",
-                WEB_PORT_BASE,
-                WEB_PORT_BASE + WEB_PORT_MAX_ATTEMPTS - 1
-            ));
+        let listener = listener.expect(&format!(
+            "Failed to bind to any port in range {}-{}",
+            WEB_PORT_BASE,
+            WEB_PORT_BASE + WEB_PORT_MAX_ATTEMPTS - 1
+        ));
 
-            // Store the bound port for other parts of the app to access
-            {
-                let mut port_guard = WEB_SERVER_PORT.lock();
-                *port_guard = Some(bound_port);
-            }
+        // Store the bound port for other parts of the app to access
+        {
+            let mut port_guard = state::get().web_server_port.lock();
+            *port_guard = Some(bound_port);
+        }
 
-            // Notify the app about the bound port
-            if let Some(app) = APP_HANDLE.lock().as_ref() {
-                let _ = app.emit("web-server-started", serde_json::json!({
-                    "port": bound_port
-                }));
-            }
+        // Notify the app about the bound port
+        if let Some(app) = state::get().app_handle.lock().as_ref() {
+            let _ = app.emit("web-server-started", serde_json::json!({
+                "port": bound_port
+            }));
+        }
 
-            println!("Web server listening on http://0.0.0.0:{}", bound_port);
-            axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await.unwrap();
-        });
+        tracing::info!("Web server listening on http://0.0.0.0:{}", bound_port);
+        axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await.unwrap();
     });
 }
 
@@ -4789,7 +9672,7 @@ fn setup_app(app: &tauri::App) -> Result<(), Box<dyn std::error::Error>> {
     }
 
     // Create and set the menu
-    let menu = create_menu(app.handle())?;
+    let menu = create_menu(app.handle(), &load_app_settings().unwrap_or_default().shortcuts)?;
     app.set_menu(menu)?;
 
     // Handle menu events
@@ -4838,11 +9721,28 @@ fn setup_app(app: &tauri::App) -> Result<(), Box<dyn std::error::Error>> {
             "about" => {
                 let _ = app.emit("menu-event", "about");
             }
+            // Plain stand-ins for the predefined items GTK lacks (create_menu)
+            "quit" => app.exit(0),
+            "minimize" | "maximize" | "close_window" | "fullscreen" => {
+                if let Some(window) = app.get_webview_window("main") {
+                    let _ = match id {
+                        "minimize" => window.minimize(),
+                        "maximize" if window.is_maximized().unwrap_or(false) => window.unmaximize(),
+                        "maximize" => window.maximize(),
+                        "close_window" => window.close(),
+                        _ => window.set_fullscreen(!window.is_fullscreen().unwrap_or(false)),
+                    };
+                }
+            }
             _ => {
                 // Handle recently closed items (recent_0, recent_1, etc.)
                 if id.starts_with("recent_") {
                     let _ = app.emit("menu-event", id);
                 }
+                // Sessions in the Window menu (go_session:<id>)
+                if let Some(session_id) = id.strip_prefix("go_session:") {
+                    let _ = app.emit("open-session", session_id);
+                }
             }
         }
     });
@@ -4850,18 +9750,65 @@ fn setup_app(app: &tauri::App) -> Result<(), Box<dyn std::error::Error>> {
     // Initialize shared database connection and run migrations
     run_db_migrations();
 
-    // Store AppHandle for web server to use
+    // Chat messages an unclean exit didn't get to save
+    shutdown::recover();
+
+    // The login shell's environment, ready before the first JSON session
+    agent_env::prewarm();
+
+    // Store AppHandle for web server to use, and make the state available to
+    // commands through tauri::State
     {
-        let mut handle = APP_HANDLE.lock();
+        let mut handle = state::get().app_handle.lock();
         *handle = Some(app.handle().clone());
     }
+    app.manage(state::get().clone());
 
     // Start web server for remote access
     start_web_server();
 
+    // Quit through the shutdown sequence on SIGTERM / SIGINT
+    shutdown::handle_signals(app.handle().clone());
+
     // Start cron scheduler for scheduled jobs
     start_scheduler();
 
+    // Start the pipeline coordinator (chains session results into other sessions)
+    pipeline::start_coordinator();
+
+    // Watch for claude-json sessions that stop producing output mid-turn
+    watchdog::start();
+
+    // agenthub:// links: the one the app was launched with, then any opened
+    // while it runs
+    {
+        use tauri_plugin_deep_link::DeepLinkExt;
+        #[cfg(any(target_os = "linux", target_os = "windows"))]
+        if let Err(e) = app.deep_link().register_all() {
+            tracing::error!("[deep_link] failed to register scheme: {}", e);
+        }
+        if let Ok(Some(urls)) = app.deep_link().get_current() {
+            deep_link::handle_urls(app.handle(), urls.iter().map(|u| u.to_string()).collect());
+        }
+        let handle = app.handle().clone();
+        app.deep_link().on_open_url(move |event| {
+            deep_link::handle_urls(&handle, event.urls().iter().map(|u| u.to_string()).collect());
+        });
+    }
+
+    // Apply settings changes as they're saved
+    watch_settings();
+
+    // Menu bar / tray status item
+    if let Err(e) = tray::create(app) {
+        tracing::warn!("[tray] {}", e);
+    }
+
+    // System-wide shortcuts from settings
+    if let Err(e) = hotkeys::register(app.handle(), &load_app_settings().unwrap_or_default().global_shortcuts) {
+        tracing::warn!("[hotkeys] {}", e);
+    }
+
     // Clean up orphaned processes from previous app instance
     // We can't reattach to them (no stdin/stdout handles), so kill them
     std::thread::spawn(|| {
@@ -4880,13 +9827,10 @@ fn setup_app(app: &tauri::App) -> Result<(), Box<dyn std::error::Error>> {
     // Start MCP server if --mcp flag was passed
     if MCP_MODE.load(std::sync::atomic::Ordering::Relaxed) {
         let app_handle = app.handle().clone();
-        std::thread::spawn(move || {
-            let rt = tokio::runtime::Runtime::new().expect("Failed to create tokio runtime for MCP");
-            rt.block_on(async {
-                if let Err(e) = mcp::start_mcp_server(app_handle).await {
-                    eprintln!("MCP server error: {}", e);
-                }
-            });
+        state::spawn(async move {
+            if let Err(e) = mcp::start_mcp_server(app_handle).await {
+                tracing::error!("MCP server error: {}", e);
+            }
         });
     }
 
@@ -4899,11 +9843,13 @@ fn setup_app(app: &tauri::App) -> Result<(), Box<dyn std::error::Error>> {
     // Initialize shared database connection and run migrations
     run_db_migrations();
 
-    // Store AppHandle for web server to use
+    // Store AppHandle for web server to use, and make the state available to
+    // commands through tauri::State
     {
-        let mut handle = APP_HANDLE.lock();
+        let mut handle = state::get().app_handle.lock();
         *handle = Some(app.handle().clone());
     }
+    app.manage(state::get().clone());
 
     // Start web server for remote access
     start_web_server();
@@ -4915,21 +9861,46 @@ fn setup_app(app: &tauri::App) -> Result<(), Box<dyn std::error::Error>> {
 #[cfg(not(target_os = "ios"))]
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    // One runtime for Tauri and everything else, before anything spawns
+    state::init();
+
     // Check for --mcp flag to enable MCP server mode
     let args: Vec<String> = std::env::args().collect();
     if args.iter().any(|arg| arg == "--mcp") {
         MCP_MODE.store(true, std::sync::atomic::Ordering::Relaxed);
     }
-
-    tauri::Builder::default()
+    logging::init(MCP_MODE.load(std::sync::atomic::Ordering::Relaxed));
+
+    let mut builder = tauri::Builder::default();
+    // Must come first. An --mcp instance is a separate process on purpose,
+    // so it isn't folded into the running one.
+    if !MCP_MODE.load(std::sync::atomic::Ordering::Relaxed) {
+        builder = builder.plugin(tauri_plugin_single_instance::init(|app, _argv, _cwd| {
+            if let Some(window) = app.get_webview_window("main") {
+                let _ = window.unminimize();
+                let _ = window.show();
+                let _ = window.set_focus();
+            }
+        }));
+    }
+    builder
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_notification::init())
         .plugin(tauri_plugin_process::init())
         .plugin(tauri_plugin_updater::Builder::new().build())
+        .plugin(tauri_plugin_deep_link::init())
+        .plugin(tauri_plugin_global_shortcut::Builder::new().build())
         .setup(|app| {
             setup_app(app)
         })
+        .on_window_event(|window, event| {
+            if let tauri::WindowEvent::Focused(true) = event {
+                if window.label() == "main" {
+                    desktop_notify::on_focus(window.app_handle());
+                }
+            }
+        })
         .invoke_handler(tauri::generate_handler![
             spawn_pty,
             write_pty,
@@ -4955,19 +9926,39 @@ pub fn run() {
             get_recently_closed,
             delete_recently_closed,
             update_history_menu,
+            update_sessions_menu,
             save_terminal_buffer,
+            append_terminal_buffer,
             load_terminal_buffer,
             delete_terminal_buffer,
             save_window_state,
             load_window_state,
             save_app_settings,
+            set_remote_pin,
             load_app_settings,
             read_image_file,
             read_text_file,
             find_latest_plan_file,
             get_web_server_port,
+            get_web_server_cert,
+            get_tunnel_status,
+            list_paired_devices,
+            revoke_device,
+            set_session_remote_access,
+            get_audit_log,
+            get_recent_logs,
+            set_device_scope,
+            set_device_notify_prefs,
+            generate_pairing_qr,
+            approve_pairing,
+            deny_pairing,
+            create_share_link,
+            list_share_links,
+            revoke_share_link,
+            discover_instances,
             get_local_ips,
             mcp_callback,
+            answer_question,
             load_folders,
             save_folder,
             delete_folder,
@@ -4982,28 +9973,82 @@ pub fn run() {
             import_orphan_jsonls,
             propose_session_jsonl_reconciliation,
             apply_session_jsonl_reconciliation,
-            fetch_claude_usage_limits
+            fetch_claude_usage_limits,
+            list_remote_hubs,
+            add_remote_hub,
+            remove_remote_hub,
+            load_hub_sessions,
+            start_remote_session,
+            attach_remote_session,
+            write_remote_session,
+            interrupt_remote_session,
+            resize_remote_session,
+            detach_remote_session,
+            get_session_viewers,
+            enqueue_prompt,
+            list_queue,
+            reorder_queue,
+            remove_queued_prompt,
+            list_pipelines,
+            create_pipeline,
+            update_pipeline,
+            delete_pipeline,
+            list_prompt_templates,
+            save_prompt_template,
+            delete_prompt_template,
+            list_webhooks,
+            save_webhook,
+            delete_webhook,
+            list_webhook_deliveries,
+            fanout_prompt,
+            get_fanout,
+            list_fanouts,
+            get_session_changes,
+            set_turn_snapshots,
+            get_turn_snapshots_enabled,
+            list_turn_snapshots,
+            revert_to_turn,
+            get_context_usage,
+            speak_text,
+            stop_speaking,
+            set_session_auto_speak,
+            get_session_auto_speak,
+            set_session_chat_notify,
+            deep_links_ready,
+            register_hotkeys,
+            set_active_session,
+            get_shortcuts,
+            set_shortcut,
+            list_profiles,
+            switch_profile,
+            delete_profile,
+            run_diagnostics,
+            shutdown_ready,
+            list_workspaces,
+            save_workspace,
+            delete_workspace,
+            switch_workspace,
+            set_session_workspace,
+            set_folder_workspace,
+            get_session_chat_notify,
+            list_redaction_rules,
+            add_redaction_rule,
+            delete_redaction_rule,
+            set_session_watchdog,
+            get_session_watchdog
         ])
         .build(tauri::generate_context!())
         .expect("error while building tauri application")
-        .run(|_app, event| {
-            if let tauri::RunEvent::Exit = event {
-                // Kill all JSON processes on app exit
-                let processes = JSON_PROCESSES.lock();
-                for (session_id, process) in processes.iter() {
-                    println!("Cleaning up process for session {}", session_id);
-                    unsafe {
-                        libc::kill(process.child_id as i32, libc::SIGTERM);
-                    }
-                }
-                // Give processes a moment to terminate, then force kill
-                std::thread::sleep(std::time::Duration::from_millis(200));
-                for (_session_id, process) in processes.iter() {
-                    unsafe {
-                        libc::kill(process.child_id as i32, libc::SIGKILL);
-                    }
+        .run(|app, event| match event {
+            // Held back until the frontend has saved and shutdown::run is done
+            tauri::RunEvent::ExitRequested { api, .. } => {
+                if shutdown::on_exit_requested(app) {
+                    api.prevent_exit();
                 }
             }
+            // A no-op after ExitRequested; covers exits that skipped it
+            tauri::RunEvent::Exit => shutdown::run(),
+            _ => {}
         });
 }
 
@@ -5011,6 +10056,8 @@ pub fn run() {
 #[cfg(target_os = "ios")]
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    state::init();
+    logging::init(false);
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_shell::init())
@@ -5022,6 +10069,9 @@ pub fn run() {
             // PTY commands not available on iOS:
             // spawn_pty, write_pty, resize_pty, kill_pty
             load_sessions,
+            get_recent_logs,
+            approve_pairing,
+            deny_pairing,
             save_session,
             list_scheduled_jobs,
             create_scheduled_job,
@@ -5036,12 +10086,15 @@ pub fn run() {
             get_recently_closed,
             delete_recently_closed,
             update_history_menu,
+            update_sessions_menu,
             save_terminal_buffer,
+            append_terminal_buffer,
             load_terminal_buffer,
             delete_terminal_buffer,
             save_window_state,
             load_window_state,
             save_app_settings,
+            set_remote_pin,
             load_app_settings,
             read_image_file,
             read_text_file,
@@ -5062,7 +10115,17 @@ pub fn run() {
             import_orphan_jsonls,
             propose_session_jsonl_reconciliation,
             apply_session_jsonl_reconciliation,
-            fetch_claude_usage_limits
+            fetch_claude_usage_limits,
+            list_remote_hubs,
+            add_remote_hub,
+            remove_remote_hub,
+            load_hub_sessions,
+            start_remote_session,
+            attach_remote_session,
+            write_remote_session,
+            interrupt_remote_session,
+            resize_remote_session,
+            detach_remote_session
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
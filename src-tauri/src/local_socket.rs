@@ -0,0 +1,98 @@
+// The web API on a unix domain socket, for local scripts and the CLI.
+//
+// Requests on the socket skip authentication: only processes running as this
+// user can open it (the socket is created mode 0600 in the app data
+// directory). The router stamps each request with SOCKET_HEADER carrying a
+// per-process secret, which is how the auth checks recognise them; clients
+// never see or send it.
+//
+//   curl --unix-socket "<data dir>/agent-hub.sock" http://localhost/api/sessions
+
+use once_cell::sync::Lazy;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
+
+pub const SOCKET_HEADER: &str = "x-agent-hub-socket";
+
+static SOCKET_SECRET: Lazy<String> = Lazy::new(crate::generate_token);
+
+pub fn path() -> PathBuf {
+    crate::get_db_path().with_file_name("agent-hub.sock")
+}
+
+/// Whether a request arrived over the socket.
+pub fn is_socket_request(headers: &axum::http::HeaderMap) -> bool {
+    use subtle::ConstantTimeEq;
+    headers
+        .get(SOCKET_HEADER)
+        .is_some_and(|v| bool::from(v.as_bytes().ct_eq(SOCKET_SECRET.as_bytes())))
+}
+
+/// Serve `app` on the socket until the future is dropped. Never returns if
+/// the socket can't be created, so it can sit in a select! next to the TCP
+/// server.
+pub async fn serve(app: axum::Router) {
+    use tower::ServiceExt;
+
+    let path = path();
+    // Left over from a previous run
+    let _ = std::fs::remove_file(&path);
+    // Created 0600 rather than chmod'ed after bind, which would leave a
+    // window where other users could connect. The umask is process-wide, but
+    // only for the moment bind takes, and it can only make files stricter.
+    let previous = unsafe { libc::umask(0o177) };
+    let bound = tokio::net::UnixListener::bind(&path);
+    unsafe { libc::umask(previous) };
+    let listener = match bound {
+        Ok(listener) => listener,
+        Err(e) => {
+            tracing::error!("can't listen on {}: {}", path.display(), e);
+            return std::future::pending().await;
+        }
+    };
+    tracing::info!("Local API listening on {}", path.display());
+
+    let secret = axum::http::HeaderValue::from_str(&SOCKET_SECRET).expect("token is a valid header value");
+    // Socket clients count as local for the handlers that look at the peer
+    let local = SocketAddr::from((Ipv4Addr::LOCALHOST, 0));
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                tracing::error!("accept failed: {}", e);
+                continue;
+            }
+        };
+        let app = app.clone();
+        let secret = secret.clone();
+        tokio::spawn(async move {
+            let service = hyper::service::service_fn(move |mut req: hyper::Request<hyper::body::Incoming>| {
+                req.extensions_mut().insert(axum::extract::ConnectInfo::<SocketAddr>(local));
+                req.headers_mut().insert(SOCKET_HEADER, secret.clone());
+                app.clone().oneshot(req)
+            });
+            let io = hyper_util::rt::TokioIo::new(stream);
+            let _ = hyper::server::conn::http1::Builder::new().serve_connection(io, service).with_upgrades().await;
+        });
+    }
+}
+
+/// Remove the socket file once the server has stopped.
+pub fn remove() {
+    let _ = std::fs::remove_file(path());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_process_secret_marks_a_socket_request() {
+        let mut headers = axum::http::HeaderMap::new();
+        assert!(!is_socket_request(&headers));
+        headers.insert(SOCKET_HEADER, axum::http::HeaderValue::from_static("guess"));
+        assert!(!is_socket_request(&headers));
+        headers.insert(SOCKET_HEADER, axum::http::HeaderValue::from_str(&SOCKET_SECRET).unwrap());
+        assert!(is_socket_request(&headers));
+    }
+}
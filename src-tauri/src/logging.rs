@@ -0,0 +1,176 @@
+// Structured logging with `tracing`.
+//
+// Everything goes to stderr as before and to daily files under
+// <app data>/logs (agent-hub.YYYY-MM-DD.log, the last MAX_FILES kept), so
+// "why didn't my session start" can be answered after the fact: the
+// get_recent_logs command and GET /api/logs read the files back, newest
+// last. The --mcp instance is a separate process and writes its own files
+// (agent-hub-mcp.*) so the two don't interleave.
+//
+// Our own crate logs at DEBUG and above, dependencies at WARN and above.
+
+use serde::Serialize;
+use std::path::PathBuf;
+use tracing::Level;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+const MAX_FILES: usize = 7;
+const DEFAULT_LIMIT: usize = 200;
+const MAX_LIMIT: usize = 2000;
+
+pub fn log_dir() -> PathBuf {
+    crate::get_config_path().with_file_name("logs")
+}
+
+/// Install the subscriber; called once, first thing in run()
+pub fn init(mcp: bool) {
+    let filter = Targets::new()
+        .with_target(env!("CARGO_CRATE_NAME"), Level::DEBUG)
+        .with_default(Level::WARN);
+    let file = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix(if mcp { "agent-hub-mcp" } else { "agent-hub" })
+        .filename_suffix("log")
+        .max_log_files(MAX_FILES)
+        .build(log_dir());
+    let file_layer = file.ok().map(|file| tracing_subscriber::fmt::layer().with_ansi(false).with_writer(file));
+    let registry = tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
+        .with(file_layer);
+    if registry.try_init().is_err() {
+        return;
+    }
+
+    // Panics end up in the file too, not only on a stderr nobody is watching
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        tracing::error!("panic: {}", info);
+        default_hook(info);
+    }));
+}
+
+// =====================================================================
+//  Reading back
+// =====================================================================
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct LogLine {
+    pub timestamp: String,
+    pub level: String,
+    pub target: String,
+    pub message: String,
+}
+
+fn parse_level(level: &str) -> Option<Level> {
+    level.trim().parse().ok()
+}
+
+/// One line as the fmt layer writes it without ANSI:
+/// `2026-10-15T09:12:03.123456Z  WARN agent_hub_lib::queue: message`.
+/// Anything else is the continuation of a multi-line message.
+fn parse_line(line: &str) -> Option<LogLine> {
+    let (timestamp, rest) = line.split_once(' ')?;
+    if !timestamp.starts_with(|c: char| c.is_ascii_digit()) || !timestamp.ends_with('Z') {
+        return None;
+    }
+    let (level, rest) = rest.trim_start().split_once(' ')?;
+    let level = parse_level(level)?;
+    let (target, message) = rest.split_once(": ").unwrap_or((rest, ""));
+    Some(LogLine {
+        timestamp: timestamp.to_string(),
+        level: level.as_str().to_string(),
+        target: target.to_string(),
+        message: message.to_string(),
+    })
+}
+
+/// Parse a whole file, folding continuation lines into the entry above
+fn parse_file(contents: &str) -> Vec<LogLine> {
+    let mut lines: Vec<LogLine> = Vec::new();
+    for line in contents.lines() {
+        match parse_line(line) {
+            Some(parsed) => lines.push(parsed),
+            None => {
+                if let Some(last) = lines.last_mut() {
+                    last.message.push('\n');
+                    last.message.push_str(line);
+                }
+            }
+        }
+    }
+    lines
+}
+
+/// The most recent `limit` lines at `level` or more severe (default: all
+/// levels, 200 lines), oldest first
+pub fn recent(level: Option<&str>, limit: Option<usize>) -> Result<Vec<LogLine>, String> {
+    let min_level = match level {
+        Some(level) => parse_level(level).ok_or_else(|| format!("'{}' isn't a log level", level))?,
+        None => Level::TRACE,
+    };
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    // Dated names sort chronologically; the app's own files only
+    let mut files: Vec<PathBuf> = std::fs::read_dir(log_dir())
+        .map_err(|e| format!("Failed to read log directory: {}", e))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+            name.starts_with("agent-hub.") && name.ends_with(".log")
+        })
+        .collect();
+    files.sort();
+
+    let mut collected: Vec<LogLine> = Vec::new();
+    for path in files.iter().rev() {
+        let contents = std::fs::read_to_string(path).unwrap_or_default();
+        let mut matching: Vec<LogLine> = parse_file(&contents)
+            .into_iter()
+            .filter(|line| parse_level(&line.level).is_some_and(|l| l <= min_level))
+            .collect();
+        let skip = matching.len().saturating_sub(limit - collected.len());
+        matching.drain(..skip);
+        matching.append(&mut collected);
+        collected = matching;
+        if collected.len() >= limit {
+            break;
+        }
+    }
+    Ok(collected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_are_parsed_and_continuations_folded() {
+        let contents = "\
+2026-10-15T09:12:03.123456Z  INFO agent_hub_lib: Web server listening on http://127.0.0.1:3847
+2026-10-15T09:12:04.000001Z ERROR agent_hub_lib::queue: failed to send queued prompt for abc: boom
+second line of the error
+2026-10-15T09:12:05.000001Z  WARN hyper::proto: connection reset";
+        let lines = parse_file(contents);
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0].level, "INFO");
+        assert_eq!(lines[0].target, "agent_hub_lib");
+        assert_eq!(lines[0].message, "Web server listening on http://127.0.0.1:3847");
+        assert_eq!(lines[1].level, "ERROR");
+        assert_eq!(lines[1].target, "agent_hub_lib::queue");
+        assert_eq!(lines[1].message, "failed to send queued prompt for abc: boom\nsecond line of the error");
+        assert_eq!(lines[2].target, "hyper::proto");
+    }
+
+    #[test]
+    fn level_filter_keeps_more_severe_lines() {
+        let warn = parse_level("warn").unwrap();
+        assert!(Level::ERROR <= warn);
+        assert!(Level::WARN <= warn);
+        assert!(Level::INFO > warn);
+        assert!(parse_level("loud").is_none());
+    }
+}
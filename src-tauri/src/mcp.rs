@@ -1,12 +1,12 @@
 // Simple MCP server implementation for Agent Hub
-// Implements the Model Context Protocol over stdio
+// Implements the Model Context Protocol over stdio and over HTTP
 
 use once_cell::sync::Lazy;
 use parking_lot::Mutex as ParkingMutex;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use std::collections::HashMap;
-use std::io::{self, BufRead, Write};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Duration;
 use tauri::{AppHandle, Manager, WebviewWindow};
@@ -15,16 +15,375 @@ use uuid::Uuid;
 
 const PROTOCOL_VERSION: &str = "2024-11-05";
 
-// Global map for pending MCP requests that await results from JS
-static PENDING_REQUESTS: Lazy<ParkingMutex<HashMap<String, oneshot::Sender<String>>>> =
+// Global map for pending MCP requests that await results from JS: the
+// in-app server's evals and POST /api/mcp/execute alike. Each entry carries
+// the number of its PendingGuard, so a guard only ever removes its own.
+static PENDING_REQUESTS: Lazy<ParkingMutex<HashMap<String, (u64, oneshot::Sender<String>)>>> =
     Lazy::new(|| ParkingMutex::new(HashMap::new()));
 
-/// Called by the IPC command when JS sends back a result
-pub fn resolve_mcp_request(request_id: String, result: String) {
+static NEXT_PENDING: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+// =====================================================================
+//  Clients
+// =====================================================================
+//
+// The protocol core (`McpServer::handle_line`) doesn't know how messages
+// travel; a transport registers each client it serves and hands it lines.
+// The stdio transport is the one client of an `--mcp` launch; the web server
+// adds one per HTTP session (see api_mcp_post). Requests run as their own
+// tasks, so a slow tool call doesn't hold up the client's other requests and
+// `notifications/cancelled` can abort it.
+
+struct Client {
+    /// Messages the server sends unprompted; None until the transport has
+    /// somewhere to put them
+    outgoing: Option<tokio::sync::mpsc::UnboundedSender<String>>,
+    /// Sent notifications/initialized; session events wait for it
+    initialized: bool,
+    /// Resource URIs it asked to hear about (resources/subscribe)
+    subscriptions: HashSet<String>,
+    /// Requests being handled, by JSON-RPC id, for cancellation
+    in_flight: HashMap<String, tokio::task::AbortHandle>,
+    /// Declared the roots capability at initialize
+    supports_roots: bool,
+    /// Id of our roots/list request awaiting its answer
+    roots_request: Option<String>,
+    /// The client's workspace roots, once it has told us
+    roots: Option<Vec<PathBuf>>,
+    /// Least severe log message it wants (logging/setLevel); none until set
+    log_level: Option<LogLevel>,
+    /// The Agent Hub session whose agent this is (see session_config)
+    session_id: Option<String>,
+}
+
+static CLIENTS: Lazy<ParkingMutex<HashMap<String, Client>>> = Lazy::new(|| ParkingMutex::new(HashMap::new()));
+
+/// Register a client; returns its id
+pub fn connect() -> String {
+    let id = Uuid::new_v4().to_string();
+    CLIENTS.lock().insert(id.clone(), Client {
+        outgoing: None,
+        initialized: false,
+        subscriptions: HashSet::new(),
+        in_flight: HashMap::new(),
+        supports_roots: false,
+        roots_request: None,
+        roots: None,
+        log_level: None,
+        session_id: None,
+    });
+    id
+}
+
+/// Mark a client as the agent of `session_id`, giving it the hub tools
+pub fn bind_session(client_id: &str, session_id: &str) {
+    if let Some(client) = CLIENTS.lock().get_mut(client_id) {
+        client.session_id = Some(session_id.to_string());
+    }
+}
+
+// =====================================================================
+//  Session-scoped server
+// =====================================================================
+//
+// claude-json sessions are spawned with `--mcp-config` naming this server
+// over the local HTTP transport, with the local token and the session's id
+// in headers. A client that connects that way is bound to its session and
+// gets the hub tools besides the rest: signal_attention, ask_user and
+// request_approval, which reach the user on whichever device they're using.
+
+/// Header naming the session an agent's MCP client belongs to
+pub const SESSION_HEADER: &str = "x-agent-hub-session";
+
+/// Longest ask_user / request_approval wait
+const ASK_LIMIT_SECS: u64 = 3600;
+
+fn session_config_path(session_id: &str) -> PathBuf {
+    crate::platform::data_dir()
+        .join("mcp")
+        .join(format!("{}.json", session_id))
+}
+
+/// The `--mcp-config` contents for a session's agent
+fn session_config_json(session_id: &str, port: u16, local_token: &str) -> Value {
+    json!({
+        "mcpServers": {
+            "agent-hub": {
+                "type": "http",
+                "url": format!("http://127.0.0.1:{}/api/mcp", port),
+                "headers": {
+                    "x-agent-hub-local": local_token,
+                    SESSION_HEADER: session_id
+                }
+            }
+        }
+    })
+}
+
+/// Write the session's MCP config, readable only by this user since it holds
+/// the local token, and return its path. Fails while the web server is down.
+pub fn session_config(session_id: &str) -> Result<PathBuf, String> {
+    let port = (*crate::state::get().web_server_port.lock()).ok_or("Web server is not running")?;
+    let path = session_config_path(session_id);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    let config = session_config_json(session_id, port, crate::LOCAL_TOKEN.as_str());
+    std::fs::write(&path, config.to_string()).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let _ = std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600));
+    }
+    Ok(path)
+}
+
+// =====================================================================
+//  Logging
+// =====================================================================
+//
+// A client that sends logging/setLevel gets `notifications/message` for
+// what happens on our side at that level and above: sessions failing to
+// spawn or erroring (error), JS evals failing (error), sessions starting,
+// stopping and finishing turns (info). Until it does, it gets none.
+
+/// Syslog severities, least severe first, as the protocol names them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Notice,
+    Warning,
+    Error,
+    Critical,
+    Alert,
+    Emergency,
+}
+
+/// Send a log message to every client that asked for `level` or below
+pub fn log(level: LogLevel, logger: &str, data: Value) {
+    let message = json!({
+        "jsonrpc": "2.0",
+        "method": "notifications/message",
+        "params": { "level": level, "logger": logger, "data": data }
+    }).to_string();
+    for client in CLIENTS.lock().values() {
+        if client.log_level.is_none_or(|wanted| level < wanted) {
+            continue;
+        }
+        if let Some(outgoing) = &client.outgoing {
+            let _ = outgoing.send(message.clone());
+        }
+    }
+}
+
+// =====================================================================
+//  Roots
+// =====================================================================
+//
+// A client that declares `roots` at initialize is asked for them with
+// roots/list once it's initialized, and again whenever it sends
+// notifications/roots/list_changed. The answer arrives later as a response
+// on the same transport. Tools default working directories to the first
+// root and refuse ones outside all of them; a client without roots isn't
+// restricted.
+
+/// Ask a client for its roots, if it has them and we can reach it
+fn request_roots(client_id: &str) {
+    let mut clients = CLIENTS.lock();
+    let Some(client) = clients.get_mut(client_id) else { return };
+    let Some(outgoing) = client.outgoing.as_ref().filter(|_| client.supports_roots) else { return };
+    let id = format!("roots-{}", Uuid::new_v4());
+    let _ = outgoing.send(json!({ "jsonrpc": "2.0", "id": id, "method": "roots/list" }).to_string());
+    client.roots_request = Some(id);
+}
+
+/// A client's response to a request we sent it
+fn on_client_response(client_id: &str, response: &Value) {
+    let mut clients = CLIENTS.lock();
+    let Some(client) = clients.get_mut(client_id) else { return };
+    let id = response["id"].as_str();
+    if id.is_none() || id != client.roots_request.as_deref() {
+        return;
+    }
+    client.roots_request = None;
+    if let Some(roots) = response.pointer("/result/roots").and_then(|r| r.as_array()) {
+        client.roots = Some(roots.iter().filter_map(|root| root_path(root["uri"].as_str()?)).collect());
+    }
+}
+
+/// The directory a root's file:// URI names
+fn root_path(uri: &str) -> Option<PathBuf> {
+    tauri::Url::parse(uri).ok()?.to_file_path().ok()
+}
+
+/// `~` expanded and, where it exists, symlinks resolved
+fn resolve_path(path: &str) -> PathBuf {
+    let expanded = match path.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => dirs::home_dir()
+            .map(|home| home.join(rest.trim_start_matches('/')))
+            .unwrap_or_else(|| PathBuf::from(path)),
+        _ => PathBuf::from(path),
+    };
+    expanded.canonicalize().unwrap_or(expanded)
+}
+
+/// Whether `path` is one of `roots` or inside one; true when there are none
+fn within_roots(path: &Path, roots: &[PathBuf]) -> bool {
+    roots.is_empty() || roots.iter().any(|root| path.starts_with(resolve_path(&root.to_string_lossy())))
+}
+
+/// The client's notification stream, replacing any earlier one
+pub fn attach(client_id: &str) -> Option<tokio::sync::mpsc::UnboundedReceiver<String>> {
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    CLIENTS.lock().get_mut(client_id)?.outgoing = Some(tx);
+    Some(rx)
+}
+
+pub fn is_connected(client_id: &str) -> bool {
+    CLIENTS.lock().contains_key(client_id)
+}
+
+/// Forget a client, aborting whatever it still has running
+pub fn disconnect(client_id: &str) -> bool {
+    let Some(client) = CLIENTS.lock().remove(client_id) else { return false };
+    for task in client.in_flight.values() {
+        task.abort();
+    }
+    true
+}
+
+/// What a tool call knows of its caller: the client, for its roots, and where
+/// `notifications/progress` go if the request carried `_meta.progressToken`.
+/// Tools that run for a while report through it.
+pub struct CallContext {
+    client_id: String,
+    token: Option<Value>,
+}
+
+impl CallContext {
+    fn for_request(client_id: &str, params: &Value) -> Self {
+        CallContext {
+            client_id: client_id.to_string(),
+            token: params.pointer("/_meta/progressToken").filter(|t| t.is_string() || t.is_number()).cloned(),
+        }
+    }
+
+    /// The session this client is the agent of, for the hub tools
+    fn session_id(&self) -> Result<String, String> {
+        CLIENTS.lock()
+            .get(&self.client_id)
+            .and_then(|c| c.session_id.clone())
+            .ok_or_else(|| "Only available to agents running in Agent Hub sessions".to_string())
+    }
+
+    /// The client's workspace roots; empty if it hasn't given any
+    pub fn roots(&self) -> Vec<PathBuf> {
+        CLIENTS.lock().get(&self.client_id).and_then(|c| c.roots.clone()).unwrap_or_default()
+    }
+
+    /// A working directory for a tool: `requested`, or the first root when
+    /// none is given. Refused if it lies outside the client's roots.
+    pub fn working_dir(&self, requested: Option<&str>) -> Result<Option<String>, String> {
+        let roots = self.roots();
+        let Some(requested) = requested else {
+            return Ok(roots.first().map(|root| root.to_string_lossy().to_string()));
+        };
+        if !within_roots(&resolve_path(requested), &roots) {
+            return Err(format!("{} is outside the client's roots", requested));
+        }
+        Ok(Some(requested.to_string()))
+    }
+
+    /// `progress` of `total` done so far, in whatever unit the tool counts
+    pub fn report(&self, progress: f64, total: Option<f64>, message: &str) {
+        let Some(token) = &self.token else { return };
+        let mut params = json!({ "progressToken": token, "progress": progress, "message": message });
+        if let Some(total) = total {
+            params["total"] = json!(total);
+        }
+        let notification = json!({ "jsonrpc": "2.0", "method": "notifications/progress", "params": params });
+        if let Some(outgoing) = CLIENTS.lock().get(&self.client_id).and_then(|c| c.outgoing.as_ref()) {
+            let _ = outgoing.send(notification.to_string());
+        }
+    }
+}
+
+/// Drops a JS result nobody is waiting for any more: on timeout, or when
+/// the request was cancelled and its task aborted mid-eval
+pub struct PendingGuard(String, u64);
+
+impl Drop for PendingGuard {
+    fn drop(&mut self) {
+        let mut pending = PENDING_REQUESTS.lock();
+        if pending.get(&self.0).is_some_and(|(number, _)| *number == self.1) {
+            pending.remove(&self.0);
+        }
+    }
+}
+
+/// Wait for the webview's result for `request_id`, until the guard drops.
+/// None if a request with that id is already waiting.
+pub fn pending_request(request_id: &str) -> Option<(oneshot::Receiver<String>, PendingGuard)> {
     let mut pending = PENDING_REQUESTS.lock();
-    if let Some(sender) = pending.remove(&request_id) {
-        let _ = sender.send(result);
+    if pending.contains_key(request_id) {
+        return None;
     }
+    let (tx, rx) = oneshot::channel();
+    let number = NEXT_PENDING.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    pending.insert(request_id.to_string(), (number, tx));
+    Some((rx, PendingGuard(request_id.to_string(), number)))
+}
+
+/// Stop waiting for a result; its waiter sees the channel close. The JS
+/// itself can't be stopped once the webview is running it.
+pub fn cancel_request(request_id: &str) -> bool {
+    PENDING_REQUESTS.lock().remove(request_id).is_some()
+}
+
+/// Tell MCP clients about a session starting, stopping or finishing a turn,
+/// so an orchestrator needn't poll list_sessions. Sends the custom
+/// `agent-hub/session-event` notification with `data` merged into its params,
+/// plus `notifications/resources/updated` to clients subscribed to the
+/// session's transcript when the event changed it.
+pub fn notify_session_event(session_id: &str, event: &str, data: Value) {
+    let mut params = json!({ "session_id": session_id, "event": event });
+    if let (Some(params), Some(data)) = (params.as_object_mut(), data.as_object()) {
+        params.extend(data.clone());
+    }
+    let message = json!({ "jsonrpc": "2.0", "method": "agent-hub/session-event", "params": &params }).to_string();
+    let uri = transcript_uri(session_id);
+    let updated = json!({
+        "jsonrpc": "2.0",
+        "method": "notifications/resources/updated",
+        "params": { "uri": uri }
+    }).to_string();
+
+    for client in CLIENTS.lock().values().filter(|c| c.initialized) {
+        let Some(outgoing) = &client.outgoing else { continue };
+        let _ = outgoing.send(message.clone());
+        if event != "started" && client.subscriptions.contains(&uri) {
+            let _ = outgoing.send(updated.clone());
+        }
+    }
+    log(LogLevel::Info, "session", params);
+}
+
+/// Called by the IPC command when JS sends back a result; false if nothing
+/// was waiting for it
+pub fn resolve_mcp_request(request_id: String, result: String) -> bool {
+    let mut pending = PENDING_REQUESTS.lock();
+    match pending.remove(&request_id) {
+        Some((_, sender)) => sender.send(result).is_ok(),
+        None => false,
+    }
+}
+
+/// The tools this server offers, with their input schemas (also served at
+/// GET /api/mcp/tools)
+pub fn tools_list() -> Value {
+    McpServer::new().get_tools_list()
 }
 
 #[derive(Debug, Deserialize)]
@@ -79,6 +438,7 @@ impl JsonRpcResponse {
 }
 
 /// MCP Server for controlling the Agent Hub app
+#[derive(Clone)]
 pub struct McpServer {
     app_handle: Arc<Mutex<Option<AppHandle>>>,
 }
@@ -90,6 +450,13 @@ impl McpServer {
         }
     }
 
+    /// A server for the running app, as the HTTP transport uses
+    pub fn for_app(handle: AppHandle) -> Self {
+        Self {
+            app_handle: Arc::new(Mutex::new(Some(handle))),
+        }
+    }
+
     pub async fn set_app_handle(&self, handle: AppHandle) {
         let mut app_handle = self.app_handle.lock().await;
         *app_handle = Some(handle);
@@ -103,31 +470,48 @@ impl McpServer {
 
     /// Execute JS and get the result back via callback
     async fn eval_with_result(&self, js_code: &str, timeout_ms: u64) -> Result<String, String> {
+        self.eval_with_args(js_code, json!({}), timeout_ms).await
+    }
+
+    /// Like `eval_with_result`, with `args` available to the code as a
+    /// variable of that name. Values go in as JSON, never spliced into the
+    /// source, so selectors and text need no escaping. Failures, including
+    /// the code throwing, are logged to clients.
+    async fn eval_with_args(&self, js_code: &str, args: Value, timeout_ms: u64) -> Result<String, String> {
+        let result = self.eval_in_webview(js_code, args, timeout_ms).await;
+        let error = match &result {
+            Err(e) => Some(e.clone()),
+            Ok(text) => thrown_error(text),
+        };
+        if let Some(error) = error {
+            log(LogLevel::Error, "webview", json!({ "error": error, "code": js_code }));
+        }
+        result
+    }
+
+    async fn eval_in_webview(&self, js_code: &str, args: Value, timeout_ms: u64) -> Result<String, String> {
         let window = self.get_window().await?;
         let request_id = Uuid::new_v4().to_string();
 
-        // Create oneshot channel for the result
-        let (tx, rx) = oneshot::channel();
+        // Register for the result
+        let (rx, _pending) = pending_request(&request_id).ok_or("Duplicate request id")?;
 
-        // Store the sender
-        {
-            let mut pending = PENDING_REQUESTS.lock();
-            pending.insert(request_id.clone(), tx);
-        }
-
-        // Encode js_code as base64 to avoid any escaping issues
+        // Encode js_code and args as base64 to avoid any escaping issues
         let encoded = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, js_code);
+        let encoded_args = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, args.to_string());
 
         // Build the JS: decode base64, wrap in async function, execute, send result via callback
         // The code is wrapped in an async IIFE so return statements work properly
         let wrapped_js = [
             "(async () => {",
             "try {",
-            &format!("const __mcpEncoded = '{}';", encoded),
-            "const __mcpCode = atob(__mcpEncoded);",
-            // Create async function from decoded code, then call it
-            "const __mcpAsyncFn = new Function('return (async function() {' + __mcpCode + '})');",
-            "const __mcpResult = await __mcpAsyncFn()();",
+            // atob gives bytes; decode them as the UTF-8 they are
+            "const __mcpDecode = (b64) => new TextDecoder().decode(Uint8Array.from(atob(b64), c => c.charCodeAt(0)));",
+            &format!("const __mcpCode = __mcpDecode('{}');", encoded),
+            &format!("const __mcpArgs = JSON.parse(__mcpDecode('{}'));", encoded_args),
+            // Create async function from decoded code, then call it with args in scope
+            "const __mcpAsyncFn = new Function('args', 'return (async function() {' + __mcpCode + '})');",
+            "const __mcpResult = await __mcpAsyncFn(__mcpArgs)();",
             "const __mcpStr = __mcpResult === undefined ? 'null' : (typeof __mcpResult === 'string' ? __mcpResult : JSON.stringify(__mcpResult));",
             &format!("window.__TAURI__.core.invoke('mcp_callback', {{ requestId: '{}', result: __mcpStr }});", request_id),
             "} catch (__mcpErr) {",
@@ -143,12 +527,7 @@ impl McpServer {
         match tokio::time::timeout(Duration::from_millis(timeout_ms), rx).await {
             Ok(Ok(result)) => Ok(result),
             Ok(Err(_)) => Err("Channel closed".to_string()),
-            Err(_) => {
-                // Clean up pending request on timeout
-                let mut pending = PENDING_REQUESTS.lock();
-                pending.remove(&request_id);
-                Err("Timeout waiting for result".to_string())
-            }
+            Err(_) => Err("Timeout waiting for result".to_string()),
         }
     }
 
@@ -156,7 +535,7 @@ impl McpServer {
         json!([
             {
                 "name": "take_screenshot",
-                "description": "Get information about the current window state including page content",
+                "description": "Capture the Agent Hub window as a PNG image, with the page's size, title and text",
                 "inputSchema": {
                     "type": "object",
                     "properties": {},
@@ -258,11 +637,210 @@ impl McpServer {
                     "properties": {},
                     "required": []
                 }
+            },
+            {
+                "name": "list_sessions",
+                "description": "List Agent Hub sessions with their agent type, working directory and whether they are running",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {},
+                    "required": []
+                }
+            },
+            {
+                "name": "create_session",
+                "description": "Create a new session (not started)",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "name": {
+                            "type": "string",
+                            "description": "Session name (default: numbered by agent type)"
+                        },
+                        "agent_type": {
+                            "type": "string",
+                            "description": "claude, claude-json, aider, shell or custom (default: claude)"
+                        },
+                        "working_dir": {
+                            "type": "string",
+                            "description": "Directory the session runs in (default: the client's first root); must be inside the client's roots if it has any"
+                        },
+                        "folder_id": {
+                            "type": "string",
+                            "description": "Folder to put the session in"
+                        },
+                        "custom_command": {
+                            "type": "string",
+                            "description": "Command for a custom session"
+                        }
+                    },
+                    "required": []
+                }
+            },
+            {
+                "name": "start_session",
+                "description": "Start a stopped session, resuming its Claude conversation if it has one",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "session_id": {
+                            "type": "string",
+                            "description": "ID of the session"
+                        }
+                    },
+                    "required": ["session_id"]
+                }
+            },
+            {
+                "name": "send_prompt",
+                "description": "Send a prompt to a claude-json session, starting it if needed",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "session_id": {
+                            "type": "string",
+                            "description": "ID of the session"
+                        },
+                        "text": {
+                            "type": "string",
+                            "description": "The prompt"
+                        }
+                    },
+                    "required": ["session_id", "text"]
+                }
+            },
+            {
+                "name": "get_transcript",
+                "description": "Get the latest messages of a session's conversation as text",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "session_id": {
+                            "type": "string",
+                            "description": "ID of the session"
+                        },
+                        "limit": {
+                            "type": "number",
+                            "description": "Number of messages to return, newest last (default: 50)"
+                        }
+                    },
+                    "required": ["session_id"]
+                }
+            },
+            {
+                "name": "terminal_exec",
+                "description": "Type into a terminal session (pressing Enter) and return what it prints until it goes quiet",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "session_id": {
+                            "type": "string",
+                            "description": "ID of a running terminal session"
+                        },
+                        "input": {
+                            "type": "string",
+                            "description": "Text to type; Enter is added unless it ends with a newline"
+                        },
+                        "wait_for_idle_ms": {
+                            "type": "number",
+                            "description": "Return once there has been no output for this long (default: 500, output is collected for at most 60s)"
+                        }
+                    },
+                    "required": ["session_id", "input"]
+                }
+            },
+            {
+                "name": "terminal_read",
+                "description": "Read the last lines a terminal session shows, as plain text",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "session_id": {
+                            "type": "string",
+                            "description": "ID of a running terminal session"
+                        },
+                        "lines": {
+                            "type": "number",
+                            "description": "Number of lines (default: 50)"
+                        }
+                    },
+                    "required": ["session_id"]
+                }
+            },
+            {
+                "name": "interrupt_session",
+                "description": "Interrupt a running session's current turn (Ctrl+C)",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "session_id": {
+                            "type": "string",
+                            "description": "ID of the session"
+                        }
+                    },
+                    "required": ["session_id"]
+                }
             }
         ])
     }
 
-    async fn handle_request(&self, request: JsonRpcRequest) -> Option<JsonRpcResponse> {
+    /// Handle one JSON-RPC message from a client; returns the reply, if the
+    /// message gets one. A request cancelled while running gets none.
+    pub async fn handle_line(&self, client_id: &str, line: &str) -> Option<String> {
+        let message: Value = serde_json::from_str(line).unwrap_or(Value::Null);
+        if message.get("method").is_none() && message.get("id").is_some() {
+            // Answer to a request we sent (roots/list)
+            on_client_response(client_id, &message);
+            return None;
+        }
+        let request: JsonRpcRequest = match serde_json::from_value(message) {
+            Ok(r) => r,
+            Err(e) => {
+                let error_response = JsonRpcResponse::error(Value::Null, -32700, format!("Parse error: {}", e));
+                return Some(serde_json::to_string(&error_response).unwrap());
+            }
+        };
+
+        match request.method.as_str() {
+            "notifications/initialized" => {
+                if let Some(client) = CLIENTS.lock().get_mut(client_id) {
+                    client.initialized = true;
+                }
+                request_roots(client_id);
+                return None;
+            }
+            "notifications/roots/list_changed" => {
+                request_roots(client_id);
+                return None;
+            }
+            "notifications/cancelled" => {
+                let key = request.params.get("requestId").map(|id| id.to_string()).unwrap_or_default();
+                if let Some(task) = CLIENTS.lock().get_mut(client_id).and_then(|c| c.in_flight.remove(&key)) {
+                    task.abort();
+                }
+                return None;
+            }
+            _ => {}
+        }
+        let key = request.id.as_ref()?.to_string();
+
+        let server = self.clone();
+        let client = client_id.to_string();
+        let task = tokio::spawn(async move { server.handle_request(&client, request).await });
+        match CLIENTS.lock().get_mut(client_id) {
+            Some(client) => {
+                client.in_flight.insert(key.clone(), task.abort_handle());
+            }
+            None => task.abort(),
+        }
+        let response = task.await;
+        if let Some(client) = CLIENTS.lock().get_mut(client_id) {
+            client.in_flight.remove(&key);
+        }
+        response.ok().flatten().map(|r| serde_json::to_string(&r).unwrap())
+    }
+
+    async fn handle_request(&self, client_id: &str, request: JsonRpcRequest) -> Option<JsonRpcResponse> {
         let id = match &request.id {
             Some(id) => id.clone(),
             None => return None, // Notification, no response needed
@@ -270,10 +848,16 @@ impl McpServer {
 
         match request.method.as_str() {
             "initialize" => {
+                if let Some(client) = CLIENTS.lock().get_mut(client_id) {
+                    client.supports_roots = request.params.pointer("/capabilities/roots").is_some();
+                }
                 Some(JsonRpcResponse::success(id, json!({
                     "protocolVersion": PROTOCOL_VERSION,
                     "capabilities": {
-                        "tools": {}
+                        "tools": {},
+                        "resources": { "subscribe": true },
+                        "prompts": {},
+                        "logging": {}
                     },
                     "serverInfo": {
                         "name": "agent-hub",
@@ -282,9 +866,12 @@ impl McpServer {
                 })))
             }
             "tools/list" => {
-                Some(JsonRpcResponse::success(id, json!({
-                    "tools": self.get_tools_list()
-                })))
+                let mut tools = self.get_tools_list();
+                let bound = CLIENTS.lock().get(client_id).is_some_and(|c| c.session_id.is_some());
+                if let (true, Some(tools), Some(hub)) = (bound, tools.as_array_mut(), session_tools_list().as_array()) {
+                    tools.extend(hub.iter().cloned());
+                }
+                Some(JsonRpcResponse::success(id, json!({ "tools": tools })))
             }
             "tools/call" => {
                 let tool_name = request.params.get("name")
@@ -294,17 +881,77 @@ impl McpServer {
                     .cloned()
                     .unwrap_or(json!({}));
 
-                match self.call_tool(tool_name, arguments).await {
-                    Ok(result) => Some(JsonRpcResponse::success(id, json!({
-                        "content": [{"type": "text", "text": result}],
-                        "isError": false
-                    }))),
+                let call = CallContext::for_request(client_id, &request.params);
+                match self.call_tool(tool_name, arguments, &call).await {
+                    Ok(content) => Some(JsonRpcResponse::success(id, tool_result(content))),
                     Err(e) => Some(JsonRpcResponse::success(id, json!({
                         "content": [{"type": "text", "text": e}],
                         "isError": true
                     }))),
                 }
             }
+            "resources/list" => {
+                match list_resources() {
+                    Ok(resources) => Some(JsonRpcResponse::success(id, json!({ "resources": resources }))),
+                    Err(e) => Some(JsonRpcResponse::error(id, -32603, e)),
+                }
+            }
+            "resources/read" => {
+                let uri = request.params.get("uri")
+                    .and_then(|u| u.as_str())
+                    .unwrap_or("");
+                match read_resource(uri) {
+                    Ok(text) => Some(JsonRpcResponse::success(id, json!({
+                        "contents": [{"uri": uri, "mimeType": "text/plain", "text": text}]
+                    }))),
+                    // -32002: resource not found
+                    Err(e) => Some(JsonRpcResponse::error(id, -32002, e)),
+                }
+            }
+            "resources/subscribe" | "resources/unsubscribe" => {
+                let uri = request.params.get("uri")
+                    .and_then(|u| u.as_str())
+                    .unwrap_or("");
+                if session_id_from_uri(uri).is_none() {
+                    return Some(JsonRpcResponse::error(id, -32002, format!("Unknown resource: {}", uri)));
+                }
+                if let Some(client) = CLIENTS.lock().get_mut(client_id) {
+                    if request.method == "resources/subscribe" {
+                        client.subscriptions.insert(uri.to_string());
+                    } else {
+                        client.subscriptions.remove(uri);
+                    }
+                }
+                Some(JsonRpcResponse::success(id, json!({})))
+            }
+            "prompts/list" => {
+                match list_prompts() {
+                    Ok(prompts) => Some(JsonRpcResponse::success(id, json!({ "prompts": prompts }))),
+                    Err(e) => Some(JsonRpcResponse::error(id, -32603, e)),
+                }
+            }
+            "prompts/get" => {
+                let name = request.params.get("name")
+                    .and_then(|n| n.as_str())
+                    .unwrap_or("");
+                let arguments = request.params.get("arguments")
+                    .cloned()
+                    .unwrap_or(json!({}));
+                match get_prompt(name, &arguments) {
+                    Ok(result) => Some(JsonRpcResponse::success(id, result)),
+                    // -32602: invalid params (unknown prompt, missing argument)
+                    Err(e) => Some(JsonRpcResponse::error(id, -32602, e)),
+                }
+            }
+            "logging/setLevel" => {
+                let Ok(level) = LogLevel::deserialize(&request.params["level"]) else {
+                    return Some(JsonRpcResponse::error(id, -32602, "Unknown log level".to_string()));
+                };
+                if let Some(client) = CLIENTS.lock().get_mut(client_id) {
+                    client.log_level = Some(level);
+                }
+                Some(JsonRpcResponse::success(id, json!({})))
+            }
             "ping" => {
                 Some(JsonRpcResponse::success(id, json!({})))
             }
@@ -315,9 +962,25 @@ impl McpServer {
         }
     }
 
-    async fn call_tool(&self, name: &str, args: Value) -> Result<String, String> {
+    /// The content blocks of a tool's result: an image and text for
+    /// take_screenshot, text for the rest
+    async fn call_tool(&self, name: &str, args: Value, call: &CallContext) -> Result<Vec<Value>, String> {
+        let (tools, hub_tools) = (self.get_tools_list(), session_tools_list());
+        let tool = tools.as_array().into_iter()
+            .chain(hub_tools.as_array())
+            .flatten()
+            .find(|t| t["name"] == name)
+            .ok_or_else(|| format!("Unknown tool: {}", name))?;
+        validate_arguments(&tool["inputSchema"], &args)?;
+        if name == "take_screenshot" {
+            return self.tool_take_screenshot().await;
+        }
+        let text = self.call_text_tool(name, args, call).await?;
+        Ok(vec![json!({"type": "text", "text": text})])
+    }
+
+    async fn call_text_tool(&self, name: &str, args: Value, call: &CallContext) -> Result<String, String> {
         match name {
-            "take_screenshot" => self.tool_take_screenshot().await,
             "execute_js" => {
                 let code = args.get("code")
                     .and_then(|c| c.as_str())
@@ -348,7 +1011,7 @@ impl McpServer {
                     .and_then(|t| t.as_i64())
                     .map(|t| t as u64)
                     .unwrap_or(5000);
-                self.tool_wait_for_element(selector, timeout).await
+                self.tool_wait_for_element(selector, timeout, call).await
             }
             "get_text" => {
                 let selector = args.get("selector")
@@ -357,11 +1020,112 @@ impl McpServer {
                 self.tool_get_text(selector).await
             }
             "list_elements" => self.tool_list_elements().await,
+            "list_sessions" => tool_list_sessions(),
+            "create_session" => tool_create_session(&args, call),
+            "start_session" => {
+                let session_id = args.get("session_id")
+                    .and_then(|s| s.as_str())
+                    .ok_or("Missing 'session_id' parameter")?;
+                tool_start_session(session_id)
+            }
+            "send_prompt" => {
+                let session_id = args.get("session_id")
+                    .and_then(|s| s.as_str())
+                    .ok_or("Missing 'session_id' parameter")?;
+                let text = args.get("text")
+                    .and_then(|t| t.as_str())
+                    .ok_or("Missing 'text' parameter")?;
+                tool_send_prompt(session_id, text)
+            }
+            "get_transcript" => {
+                let session_id = args.get("session_id")
+                    .and_then(|s| s.as_str())
+                    .ok_or("Missing 'session_id' parameter")?;
+                let limit = args.get("limit")
+                    .and_then(|l| l.as_u64())
+                    .map(|l| l as usize)
+                    .unwrap_or(50);
+                tool_get_transcript(session_id, limit)
+            }
+            "terminal_exec" => {
+                let session_id = args.get("session_id")
+                    .and_then(|s| s.as_str())
+                    .ok_or("Missing 'session_id' parameter")?;
+                let input = args.get("input")
+                    .and_then(|t| t.as_str())
+                    .ok_or("Missing 'input' parameter")?;
+                let idle = args.get("wait_for_idle_ms")
+                    .and_then(|t| t.as_u64())
+                    .unwrap_or(500);
+                tool_terminal_exec(session_id, input, idle, call).await
+            }
+            "terminal_read" => {
+                let session_id = args.get("session_id")
+                    .and_then(|s| s.as_str())
+                    .ok_or("Missing 'session_id' parameter")?;
+                let lines = args.get("lines")
+                    .and_then(|l| l.as_u64())
+                    .map(|l| l as usize)
+                    .unwrap_or(50);
+                tool_terminal_read(session_id, lines)
+            }
+            "interrupt_session" => {
+                let session_id = args.get("session_id")
+                    .and_then(|s| s.as_str())
+                    .ok_or("Missing 'session_id' parameter")?;
+                crate::interrupt_session(session_id)?;
+                Ok(json!({ "status": "interrupted", "session_id": session_id }).to_string())
+            }
+            "signal_attention" => {
+                let message = args.get("message")
+                    .and_then(|m| m.as_str())
+                    .ok_or("Missing 'message' parameter")?;
+                crate::asks::signal_attention(&call.session_id()?, message);
+                Ok(json!({ "status": "signalled" }).to_string())
+            }
+            "ask_user" => {
+                let question = args.get("question")
+                    .and_then(|q| q.as_str())
+                    .ok_or("Missing 'question' parameter")?;
+                let options = args.get("options")
+                    .and_then(|o| o.as_array())
+                    .map(|o| o.iter().filter_map(|v| v.as_str().map(String::from)).collect())
+                    .unwrap_or_default();
+                let answer = tool_ask(call, crate::asks::AskKind::Question, question, options, &args).await?;
+                Ok(json!({ "answer": answer.text }).to_string())
+            }
+            "request_approval" => {
+                let action = args.get("action")
+                    .and_then(|a| a.as_str())
+                    .ok_or("Missing 'action' parameter")?;
+                let prompt = match args.get("details").and_then(|d| d.as_str()) {
+                    Some(details) => format!("{}\n\n{}", action, details),
+                    None => action.to_string(),
+                };
+                let answer = tool_ask(call, crate::asks::AskKind::Approval, &prompt, Vec::new(), &args).await?;
+                Ok(json!({ "approved": answer.approved.unwrap_or(false), "note": answer.text }).to_string())
+            }
             _ => Err(format!("Unknown tool: {}", name)),
         }
     }
 
-    async fn tool_take_screenshot(&self) -> Result<String, String> {
+    async fn tool_take_screenshot(&self) -> Result<Vec<Value>, String> {
+        use base64::Engine;
+
+        let png = capture_window(&self.get_window().await?).await?;
+        let mut content = vec![json!({
+            "type": "image",
+            "data": base64::engine::general_purpose::STANDARD.encode(png),
+            "mimeType": "image/png"
+        })];
+        // Page details alongside, when the webview answers
+        if let Ok(details) = self.page_details().await {
+            content.push(json!({"type": "text", "text": details}));
+        }
+        Ok(content)
+    }
+
+    async fn page_details(&self) -> Result<String, String> {
         let js = r#"
             return {
                 width: window.innerWidth,
@@ -425,63 +1189,70 @@ impl McpServer {
     }
 
     async fn tool_click_element(&self, selector: &str) -> Result<String, String> {
-        let escaped = selector.replace('\\', "\\\\").replace('\'', "\\'").replace('"', "\\\"");
-        let js = format!(r#"
-            const el = document.querySelector("{}");
-            if (!el) {{
-                return {{ success: false, error: 'Element not found: {}' }};
-            }}
+        let js = r#"
+            const el = document.querySelector(args.selector);
+            if (!el) {
+                return { success: false, error: 'Element not found: ' + args.selector };
+            }
             el.click();
-            return {{ success: true, clicked: '{}', tagName: el.tagName, text: (el.textContent || '').trim().substring(0, 50) }};
-        "#, escaped, escaped, escaped);
-        self.eval_with_result(&js, 5000).await
+            return { success: true, clicked: args.selector, tagName: el.tagName, text: (el.textContent || '').trim().substring(0, 50) };
+        "#;
+        self.eval_with_args(js, json!({ "selector": selector }), 5000).await
     }
 
     async fn tool_type_text(&self, selector: &str, text: &str) -> Result<String, String> {
-        let escaped_sel = selector.replace('\\', "\\\\").replace('\'', "\\'").replace('"', "\\\"");
-        let escaped_text = text.replace('\\', "\\\\").replace('\'', "\\'").replace('"', "\\\"").replace('\n', "\\n");
-        let js = format!(r#"
-            const el = document.querySelector("{}");
-            if (!el) {{
-                return {{ success: false, error: 'Element not found: {}' }};
-            }}
+        let js = r#"
+            const el = document.querySelector(args.selector);
+            if (!el) {
+                return { success: false, error: 'Element not found: ' + args.selector };
+            }
             el.focus();
-            el.value = "{}";
-            el.dispatchEvent(new Event('input', {{ bubbles: true }}));
-            el.dispatchEvent(new Event('change', {{ bubbles: true }}));
-            return {{ success: true, selector: '{}', typedLength: {} }};
-        "#, escaped_sel, escaped_sel, escaped_text, escaped_sel, text.len());
-        self.eval_with_result(&js, 5000).await
-    }
-
-    async fn tool_wait_for_element(&self, selector: &str, timeout_ms: u64) -> Result<String, String> {
-        let escaped = selector.replace('\\', "\\\\").replace('\'', "\\'").replace('"', "\\\"");
-        let js = format!(r#"
-            const start = Date.now();
-            while (Date.now() - start < {}) {{
-                const el = document.querySelector("{}");
-                if (el) {{
-                    return {{ success: true, found: true, selector: '{}', waitedMs: Date.now() - start }};
-                }}
-                await new Promise(r => setTimeout(r, 100));
-            }}
-            return {{ success: false, found: false, selector: '{}', error: 'Timeout after {}ms' }};
-        "#, timeout_ms, escaped, escaped, escaped, timeout_ms);
-        // Add extra time for the JS timeout plus overhead
-        self.eval_with_result(&js, timeout_ms + 1000).await
+            el.value = args.text;
+            el.dispatchEvent(new Event('input', { bubbles: true }));
+            el.dispatchEvent(new Event('change', { bubbles: true }));
+            return { success: true, selector: args.selector, typedLength: args.text.length };
+        "#;
+        self.eval_with_args(js, json!({ "selector": selector, "text": text }), 5000).await
+    }
+
+    /// Polls from here rather than looping in the webview, so a cancelled
+    /// request stops at once and progress can be reported between polls
+    async fn tool_wait_for_element(&self, selector: &str, timeout_ms: u64, call: &CallContext) -> Result<String, String> {
+        let js = "return document.querySelector(args.selector) !== null;";
+        let args = json!({ "selector": selector });
+        let start = tokio::time::Instant::now();
+        let mut reported = 0;
+        loop {
+            let waited = start.elapsed().as_millis() as u64;
+            if self.eval_with_args(js, args.clone(), 5000).await? == "true" {
+                return Ok(json!({ "success": true, "found": true, "selector": selector, "waitedMs": waited }).to_string());
+            }
+            if waited >= timeout_ms {
+                return Ok(json!({
+                    "success": false,
+                    "found": false,
+                    "selector": selector,
+                    "error": format!("Timeout after {}ms", timeout_ms)
+                }).to_string());
+            }
+            if waited / 1000 > reported {
+                reported = waited / 1000;
+                call.report(waited as f64, Some(timeout_ms as f64), &format!("Waiting for {}", selector));
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
     }
 
     async fn tool_get_text(&self, selector: &str) -> Result<String, String> {
-        let escaped = selector.replace('\\', "\\\\").replace('\'', "\\'").replace('"', "\\\"");
-        let js = format!(r#"
-            const el = document.querySelector("{}");
-            if (!el) {{
-                return {{ success: false, error: 'Element not found: {}' }};
-            }}
+        let js = r#"
+            const el = document.querySelector(args.selector);
+            if (!el) {
+                return { success: false, error: 'Element not found: ' + args.selector };
+            }
             const text = (el.textContent || el.innerText || '').trim();
-            return {{ success: true, selector: '{}', text: text.substring(0, 5000), length: text.length }};
-        "#, escaped, escaped, escaped);
-        self.eval_with_result(&js, 5000).await
+            return { success: true, selector: args.selector, text: text.substring(0, 5000), length: text.length };
+        "#;
+        self.eval_with_args(js, json!({ "selector": selector }), 5000).await
     }
 
     async fn tool_list_elements(&self) -> Result<String, String> {
@@ -539,41 +1310,523 @@ impl McpServer {
         self.eval_with_result(js, 5000).await
     }
 
+    /// Serve the process's stdin/stdout as one client until stdin closes.
+    /// Requests are handled concurrently; replies and notifications share
+    /// one writer so lines never interleave.
     pub async fn run(&self) {
-        let stdin = io::stdin();
-        let mut stdout = io::stdout();
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
 
-        for line in stdin.lock().lines() {
-            let line = match line {
-                Ok(l) => l,
-                Err(_) => break,
-            };
+        let client_id = connect();
+        let mut outgoing = attach(&client_id).expect("client was just connected");
+        let replies = CLIENTS.lock().get(&client_id).and_then(|c| c.outgoing.clone()).expect("client was just attached");
+        let writer = tokio::spawn(async move {
+            let mut stdout = tokio::io::stdout();
+            while let Some(message) = outgoing.recv().await {
+                let _ = stdout.write_all(format!("{}\n", message).as_bytes()).await;
+                let _ = stdout.flush().await;
+            }
+        });
 
+        let mut lines = tokio::io::BufReader::new(tokio::io::stdin()).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
             if line.trim().is_empty() {
                 continue;
             }
+            let server = self.clone();
+            let client_id = client_id.clone();
+            let replies = replies.clone();
+            tokio::spawn(async move {
+                if let Some(reply) = server.handle_line(&client_id, &line).await {
+                    let _ = replies.send(reply);
+                }
+            });
+        }
+
+        // Let requests still running finish and be written before going
+        if let Some(client) = CLIENTS.lock().get_mut(&client_id) {
+            client.outgoing = None;
+        }
+        drop(replies);
+        let _ = writer.await;
+        disconnect(&client_id);
+    }
+}
+
+// =====================================================================
+//  Screen capture
+// =====================================================================
+//
+// Webviews can't render themselves to an image through Tauri, so the
+// screenshot tool captures the window's area of the screen with the
+// platform's own tool. Coordinates are physical pixels; `scale` converts to
+// the points macOS's screencapture expects. The window must be on screen and
+// uncovered to be captured as it looks.
+
+#[cfg(target_os = "macos")]
+fn capture_command(x: i32, y: i32, width: u32, height: u32, scale: f64, path: &str) -> Result<tokio::process::Command, String> {
+    let points = |v: f64| (v / scale).round() as i64;
+    let mut cmd = tokio::process::Command::new("screencapture");
+    let region = format!("{},{},{},{}", points(x as f64), points(y as f64), points(width as f64), points(height as f64));
+    cmd.args(["-x", "-t", "png", "-R", &region, path]);
+    Ok(cmd)
+}
+
+#[cfg(target_os = "windows")]
+fn capture_command(x: i32, y: i32, width: u32, height: u32, _scale: f64, path: &str) -> Result<tokio::process::Command, String> {
+    let mut cmd = tokio::process::Command::new("powershell");
+    cmd.args([
+        "-NoProfile",
+        "-Command",
+        &format!(
+            "Add-Type -AssemblyName System.Drawing; $b = New-Object System.Drawing.Bitmap {w}, {h}; \
+             $g = [System.Drawing.Graphics]::FromImage($b); $g.CopyFromScreen({x}, {y}, 0, 0, $b.Size); \
+             $b.Save('{path}', [System.Drawing.Imaging.ImageFormat]::Png)",
+            w = width, h = height, x = x, y = y, path = path.replace('\'', "''")
+        ),
+    ]);
+    Ok(cmd)
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+fn capture_command(x: i32, y: i32, width: u32, height: u32, _scale: f64, path: &str) -> Result<tokio::process::Command, String> {
+    let available = |bin: &str| {
+        std::process::Command::new("sh")
+            .args(["-c", &format!("command -v {} >/dev/null 2>&1", bin)])
+            .status()
+            .is_ok_and(|s| s.success())
+    };
+    // grim on Wayland, ImageMagick's import on X11
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() && available("grim") {
+        let mut cmd = tokio::process::Command::new("grim");
+        cmd.args(["-g", &format!("{},{} {}x{}", x, y, width, height), path]);
+        return Ok(cmd);
+    }
+    if available("import") {
+        let mut cmd = tokio::process::Command::new("import");
+        cmd.args(["-window", "root", "-crop", &format!("{}x{}+{}+{}", width, height, x, y), &format!("png:{}", path)]);
+        return Ok(cmd);
+    }
+    Err("No screen capture tool found; install grim (Wayland) or ImageMagick (X11)".to_string())
+}
+
+/// PNG of the window as it appears on screen (the screenshot tool, and
+/// POST /api/mcp/screenshot for the stdio bridge)
+pub async fn capture_window(window: &WebviewWindow) -> Result<Vec<u8>, String> {
+    if window.is_minimized().unwrap_or(false) || !window.is_visible().unwrap_or(true) {
+        return Err("The Agent Hub window is hidden; show it to take a screenshot".to_string());
+    }
+    let position = window.outer_position().map_err(|e| e.to_string())?;
+    let size = window.outer_size().map_err(|e| e.to_string())?;
+    let scale = window.scale_factor().unwrap_or(1.0);
+    capture_region(position.x, position.y, size.width, size.height, scale).await
+}
+
+/// PNG bytes of a region of the screen
+async fn capture_region(x: i32, y: i32, width: u32, height: u32, scale: f64) -> Result<Vec<u8>, String> {
+    let path = std::env::temp_dir().join(format!("agent-hub-screenshot-{}.png", Uuid::new_v4()));
+    let path_str = path.to_string_lossy().to_string();
+    let output = capture_command(x, y, width, height, scale, &path_str)?
+        .output()
+        .await
+        .map_err(|e| format!("Screen capture failed: {}", e))?;
+    let png = tokio::fs::read(&path).await;
+    let _ = tokio::fs::remove_file(&path).await;
+    if !output.status.success() {
+        return Err(format!("Screen capture failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    let png = png.map_err(|e| format!("Screen capture produced no image: {}", e))?;
+    if !png.starts_with(b"\x89PNG") {
+        return Err("Screen capture produced no image".to_string());
+    }
+    Ok(png)
+}
+
+/// Check tool arguments against the tool's inputSchema: an object with the
+/// required properties, each declared property of its declared type. Other
+/// properties pass through.
+fn validate_arguments(schema: &Value, args: &Value) -> Result<(), String> {
+    let args = args.as_object().ok_or("Arguments must be an object")?;
+    for name in schema["required"].as_array().into_iter().flatten().filter_map(|n| n.as_str()) {
+        if args.get(name).is_none_or(|v| v.is_null()) {
+            return Err(format!("Missing '{}' parameter", name));
+        }
+    }
+    let Some(properties) = schema["properties"].as_object() else { return Ok(()) };
+    for (name, value) in args {
+        let Some(expected) = properties.get(name).and_then(|p| p["type"].as_str()) else { continue };
+        let ok = match expected {
+            "string" => value.is_string(),
+            "number" => value.is_number(),
+            "integer" => value.is_i64() || value.is_u64(),
+            "boolean" => value.is_boolean(),
+            "object" => value.is_object(),
+            "array" => value.is_array(),
+            _ => true,
+        } || value.is_null();
+        if !ok {
+            return Err(format!("Parameter '{}' must be a {}", name, expected));
+        }
+    }
+    Ok(())
+}
+
+/// A successful tools/call result. A lone text block that holds a JSON
+/// object is also given as `structuredContent`, so clients needn't parse it.
+fn tool_result(content: Vec<Value>) -> Value {
+    let structured = match content.as_slice() {
+        [block] if block["type"] == "text" => block["text"]
+            .as_str()
+            .and_then(|text| serde_json::from_str::<Value>(text).ok())
+            .filter(|value| value.is_object()),
+        _ => None,
+    };
+    let mut result = json!({ "content": content, "isError": false });
+    if let Some(structured) = structured {
+        result["structuredContent"] = structured;
+    }
+    result
+}
+
+/// The message of an error the eval'd code threw: the wrapper reports it
+/// as an object holding nothing but `error`
+fn thrown_error(result: &str) -> Option<String> {
+    let value: Value = serde_json::from_str(result).ok()?;
+    let object = value.as_object().filter(|o| o.len() == 1)?;
+    Some(object.get("error")?.as_str().unwrap_or("unknown error").to_string())
+}
+
+// Session tools call the command layer directly rather than going through
+// the webview, so they work whatever the UI is showing.
+
+fn find_session(session_id: &str) -> Result<crate::SessionData, String> {
+    crate::load_sessions()?
+        .into_iter()
+        .find(|s| s.id == session_id)
+        .ok_or_else(|| format!("Session not found: {}", session_id))
+}
+
+fn tool_list_sessions() -> Result<String, String> {
+    let sessions: Vec<Value> = crate::load_sessions()?
+        .into_iter()
+        .map(|s| json!({
+            "id": s.id,
+            "name": s.name,
+            "agent_type": s.agent_type,
+            "working_dir": s.working_dir,
+            "folder_id": s.folder_id,
+            "running": crate::is_session_running(&s.id),
+            "processing": crate::is_session_processing(&s.id)
+        }))
+        .collect();
+    Ok(json!({ "sessions": sessions, "count": sessions.len() }).to_string())
+}
+
+fn tool_create_session(args: &Value, call: &CallContext) -> Result<String, String> {
+    let mut body = args.clone();
+    if let Some(working_dir) = call.working_dir(args["working_dir"].as_str())? {
+        body["working_dir"] = json!(working_dir);
+    }
+    let session = crate::create_session_from_request(&body)?;
+    Ok(json!({
+        "id": session.id,
+        "name": session.name,
+        "agent_type": session.agent_type,
+        "working_dir": session.working_dir,
+        "folder_id": session.folder_id,
+        "running": false
+    }).to_string())
+}
+
+fn tool_start_session(session_id: &str) -> Result<String, String> {
+    if crate::is_session_running(session_id) {
+        return Ok(json!({ "status": "already_running", "session_id": session_id }).to_string());
+    }
+    crate::start_session(find_session(session_id)?)?;
+    Ok(json!({ "status": "started", "session_id": session_id }).to_string())
+}
+
+/// Put an ask to the user and wait for the answer, for at most the call's
+/// `timeout_secs`
+async fn tool_ask(
+    call: &CallContext,
+    kind: crate::asks::AskKind,
+    prompt: &str,
+    options: Vec<String>,
+    args: &Value,
+) -> Result<crate::asks::Answer, String> {
+    let session_id = call.session_id()?;
+    let timeout = args.get("timeout_secs")
+        .and_then(|t| t.as_u64())
+        .unwrap_or(600)
+        .min(ASK_LIMIT_SECS);
+    // Withdrawn when the guard drops, however the wait ends
+    let (_ask, _guard, answer) = crate::asks::ask(&session_id, kind, prompt, options);
+    call.report(0.0, None, "Waiting for the user to answer");
+    match tokio::time::timeout(Duration::from_secs(timeout), answer).await {
+        Ok(Ok(answer)) => Ok(answer),
+        Ok(Err(_)) => Err("The question was withdrawn".to_string()),
+        Err(_) => Err(format!("No answer from the user within {}s", timeout)),
+    }
+}
+
+/// Tools only agents running in Agent Hub sessions get
+fn session_tools_list() -> Value {
+    json!([
+        {
+            "name": "signal_attention",
+            "description": "Tell the user this session needs them (notifies their phone), without waiting for a reply",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "message": { "type": "string", "description": "What needs their attention" }
+                },
+                "required": ["message"]
+            }
+        },
+        {
+            "name": "ask_user",
+            "description": "Ask the user a question on whichever device they're using and wait for the answer",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "question": { "type": "string" },
+                    "options": { "type": "array", "items": { "type": "string" }, "description": "Suggested answers; the user may still write their own" },
+                    "timeout_secs": { "type": "number", "description": "Give up after this long (default 600, at most 3600)" }
+                },
+                "required": ["question"]
+            }
+        },
+        {
+            "name": "request_approval",
+            "description": "Ask the user to approve or deny an action before taking it, and wait for their decision",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "action": { "type": "string", "description": "What you want to do, in one line" },
+                    "details": { "type": "string" },
+                    "timeout_secs": { "type": "number", "description": "Give up after this long (default 600, at most 3600)" }
+                },
+                "required": ["action"]
+            }
+        }
+    ])
+}
+
+fn tool_send_prompt(session_id: &str, text: &str) -> Result<String, String> {
+    find_session(session_id)?;
+    crate::ensure_json_session_running(session_id)?;
+    crate::send_user_prompt(session_id, text)?;
+    Ok(json!({ "status": "sent", "session_id": session_id }).to_string())
+}
 
-            let request: JsonRpcRequest = match serde_json::from_str(&line) {
-                Ok(r) => r,
-                Err(e) => {
-                    let error_response = JsonRpcResponse::error(
-                        Value::Null,
-                        -32700,
-                        format!("Parse error: {}", e),
-                    );
-                    let _ = writeln!(stdout, "{}", serde_json::to_string(&error_response).unwrap());
-                    let _ = stdout.flush();
-                    continue;
+/// Longest terminal_exec waits for output to stop
+const TERMINAL_EXEC_LIMIT: Duration = Duration::from_secs(60);
+
+fn require_terminal(session_id: &str) -> Result<(), String> {
+    if crate::state::get().pty_sessions.lock().contains_key(session_id) {
+        return Ok(());
+    }
+    let session = find_session(session_id)?;
+    if session.agent_type == "claude-json" {
+        return Err(format!("Session {} is a chat session; use send_prompt", session_id));
+    }
+    Err(format!("Session {} is not running; start it with start_session", session_id))
+}
+
+async fn tool_terminal_exec(session_id: &str, input: &str, idle_ms: u64, call: &CallContext) -> Result<String, String> {
+    require_terminal(session_id)?;
+    let mut output = crate::state::get().pty_broadcasters
+        .lock()
+        .get(session_id)
+        .map(|tx| tx.subscribe())
+        .ok_or("Session not running")?;
+    let keys = if input.ends_with('\n') || input.ends_with('\r') { input.to_string() } else { format!("{}\r", input) };
+    crate::write_pty(session_id.to_string(), keys)?;
+
+    let idle = Duration::from_millis(idle_ms.max(50));
+    let deadline = tokio::time::Instant::now() + TERMINAL_EXEC_LIMIT;
+    let mut bytes = Vec::new();
+    let (mut running, mut quiet) = (true, false);
+    let mut reported = 0;
+    while tokio::time::Instant::now() < deadline {
+        match tokio::time::timeout(idle, output.recv()).await {
+            Ok(Ok(chunk)) => {
+                bytes.extend(chunk);
+                // Still printing; say so every 64KB
+                if bytes.len() / 65536 > reported {
+                    reported = bytes.len() / 65536;
+                    call.report(bytes.len() as f64, None, "bytes of output");
                 }
+            }
+            Ok(Err(tokio::sync::broadcast::error::RecvError::Lagged(_))) => {}
+            Ok(Err(tokio::sync::broadcast::error::RecvError::Closed)) => {
+                running = false;
+                break;
+            }
+            Err(_) => {
+                quiet = true;
+                break;
+            }
+        }
+    }
+    Ok(json!({
+        "output": crate::term_screen::render(&String::from_utf8_lossy(&bytes)),
+        // false when output was still coming at the time limit
+        "idle": quiet,
+        "running": running
+    }).to_string())
+}
+
+fn tool_terminal_read(session_id: &str, lines: usize) -> Result<String, String> {
+    require_terminal(session_id)?;
+    Ok(crate::term_screen::tail(session_id, lines).unwrap_or_default())
+}
+
+fn tool_get_transcript(session_id: &str, limit: usize) -> Result<String, String> {
+    find_session(session_id)?;
+    let history = crate::get_session_history(session_id).unwrap_or_default();
+    Ok(format_transcript(&history, limit))
+}
+
+/// One line per user or assistant message: text as is, tool calls by name
+fn format_transcript(history: &[Value], limit: usize) -> String {
+    let lines: Vec<String> = history
+        .iter()
+        .filter_map(|message| {
+            let role = message["type"].as_str().filter(|t| *t == "user" || *t == "assistant")?;
+            let content = &message["message"]["content"];
+            let parts: Vec<String> = match content.as_str() {
+                Some(text) => vec![text.to_string()],
+                None => content
+                    .as_array()?
+                    .iter()
+                    .filter_map(|block| match block["type"].as_str()? {
+                        "text" => block["text"].as_str().map(String::from),
+                        "tool_use" => Some(format!("[tool: {}]", block["name"].as_str().unwrap_or("?"))),
+                        _ => None,
+                    })
+                    .collect(),
             };
+            let text = parts.join("\n").trim().to_string();
+            (!text.is_empty()).then(|| format!("{}: {}", role, text))
+        })
+        .collect();
+    if lines.is_empty() {
+        return "(no messages)".to_string();
+    }
+    lines[lines.len().saturating_sub(limit)..].join("\n\n")
+}
 
-            if let Some(response) = self.handle_request(request).await {
-                let response_str = serde_json::to_string(&response).unwrap();
-                let _ = writeln!(stdout, "{}", response_str);
-                let _ = stdout.flush();
-            }
+// Resources: each session's transcript as agent-hub://sessions/{id}/transcript.
+// For a claude-json session that's its conversation; for a terminal session
+// it's the last saved terminal buffer, as plain text.
+
+fn transcript_uri(session_id: &str) -> String {
+    format!("agent-hub://sessions/{}/transcript", session_id)
+}
+
+fn session_id_from_uri(uri: &str) -> Option<&str> {
+    uri.strip_prefix("agent-hub://sessions/")?
+        .strip_suffix("/transcript")
+        .filter(|id| !id.is_empty() && !id.contains('/'))
+}
+
+fn list_resources() -> Result<Vec<Value>, String> {
+    Ok(crate::load_sessions()?
+        .into_iter()
+        .map(|s| {
+            let kind = if s.agent_type == "claude-json" { "Conversation" } else { "Terminal output" };
+            json!({
+                "uri": transcript_uri(&s.id),
+                "name": format!("{} transcript", s.name),
+                "description": format!("{} of the {} session in {}", kind, s.agent_type, s.working_dir),
+                "mimeType": "text/plain"
+            })
+        })
+        .collect())
+}
+
+fn read_resource(uri: &str) -> Result<String, String> {
+    let session_id = session_id_from_uri(uri).ok_or_else(|| format!("Unknown resource: {}", uri))?;
+    let session = find_session(session_id)?;
+    if session.agent_type == "claude-json" {
+        let history = crate::get_session_history(session_id).unwrap_or_default();
+        return Ok(format_transcript(&history, usize::MAX));
+    }
+    Ok(match crate::load_terminal_buffer(session_id.to_string())? {
+        Some(buffer) => strip_ansi(&buffer),
+        None => "(no terminal output saved)".to_string(),
+    })
+}
+
+/// Terminal output without escape sequences and carriage returns
+fn strip_ansi(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => match chars.next() {
+                // CSI: parameters up to a final byte in @..~
+                Some('[') => {
+                    for c in chars.by_ref() {
+                        if ('@'..='~').contains(&c) {
+                            break;
+                        }
+                    }
+                }
+                // OSC: up to BEL or ST (ESC \)
+                Some(']') => {
+                    while let Some(c) = chars.next() {
+                        if c == '\x07' || (c == '\x1b' && chars.next_if_eq(&'\\').is_some()) {
+                            break;
+                        }
+                    }
+                }
+                _ => {}
+            },
+            '\r' => {}
+            _ => out.push(c),
         }
     }
+    out
+}
+
+// Prompts: the saved prompt templates (prompts.rs), with their placeholders
+// as required arguments.
+
+fn list_prompts() -> Result<Vec<Value>, String> {
+    Ok(crate::prompts::list_prompts()?
+        .into_iter()
+        .map(|p| {
+            let arguments: Vec<Value> = crate::prompts::arguments(&p.template)
+                .into_iter()
+                .map(|name| json!({ "name": name, "required": true }))
+                .collect();
+            json!({
+                "name": p.name,
+                "description": p.description,
+                "arguments": arguments
+            })
+        })
+        .collect())
+}
+
+fn get_prompt(name: &str, arguments: &Value) -> Result<Value, String> {
+    let prompt = crate::prompts::get_prompt_by_name(name)?;
+    // Argument values are strings per the spec; take numbers and bools as written
+    let values: HashMap<String, String> = arguments
+        .as_object()
+        .map(|args| {
+            args.iter()
+                .map(|(k, v)| (k.clone(), v.as_str().map(String::from).unwrap_or_else(|| v.to_string())))
+                .collect()
+        })
+        .unwrap_or_default();
+    let text = crate::prompts::render(&prompt.template, &values)?;
+    Ok(json!({
+        "description": prompt.description,
+        "messages": [{ "role": "user", "content": { "type": "text", "text": text } }]
+    }))
 }
 
 /// Start the MCP server on stdio
@@ -583,3 +1836,181 @@ pub async fn start_mcp_server(app_handle: AppHandle) -> Result<(), Box<dyn std::
     server.run().await;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transcript_keeps_text_and_tool_names() {
+        let history = vec![
+            json!({ "type": "system", "subtype": "init" }),
+            json!({ "type": "user", "message": { "content": "fix the build" } }),
+            json!({ "type": "assistant", "message": { "content": [
+                { "type": "thinking", "thinking": "hmm" },
+                { "type": "text", "text": "Looking." },
+                { "type": "tool_use", "name": "Bash", "input": {} },
+            ]}}),
+            json!({ "type": "user", "message": { "content": [{ "type": "tool_result", "content": "ok" }] } }),
+            json!({ "type": "assistant", "message": { "content": [{ "type": "text", "text": "Done." }] } }),
+        ];
+        assert_eq!(
+            format_transcript(&history, 50),
+            "user: fix the build\n\nassistant: Looking.\n[tool: Bash]\n\nassistant: Done."
+        );
+        assert_eq!(format_transcript(&history, 1), "assistant: Done.");
+        assert_eq!(format_transcript(&[], 50), "(no messages)");
+    }
+
+    #[test]
+    fn arguments_are_checked_against_the_schema() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "session_id": { "type": "string" },
+                "limit": { "type": "number" }
+            },
+            "required": ["session_id"]
+        });
+        assert!(validate_arguments(&schema, &json!({ "session_id": "s1", "limit": 5, "extra": true })).is_ok());
+        assert_eq!(validate_arguments(&schema, &json!({ "limit": 5 })).unwrap_err(), "Missing 'session_id' parameter");
+        assert_eq!(validate_arguments(&schema, &json!({ "session_id": 1 })).unwrap_err(), "Parameter 'session_id' must be a string");
+        assert!(validate_arguments(&schema, &json!("s1")).is_err());
+        assert!(validate_arguments(&json!({ "type": "object", "properties": {} }), &json!({})).is_ok());
+    }
+
+    #[test]
+    fn json_text_results_are_also_structured() {
+        let result = tool_result(vec![json!({ "type": "text", "text": r#"{"status":"started"}"# })]);
+        assert_eq!(result["structuredContent"], json!({ "status": "started" }));
+        assert_eq!(result["isError"], false);
+
+        assert!(tool_result(vec![json!({ "type": "text", "text": "user: hi" })]).get("structuredContent").is_none());
+        assert!(tool_result(vec![json!({ "type": "text", "text": "[1, 2]" })]).get("structuredContent").is_none());
+    }
+
+    #[test]
+    fn session_events_reach_initialized_clients() {
+        let (a, b) = (connect(), connect());
+        let mut a_events = attach(&a).unwrap();
+        let mut b_events = attach(&b).unwrap();
+        CLIENTS.lock().get_mut(&a).unwrap().initialized = true;
+        CLIENTS.lock().get_mut(&a).unwrap().subscriptions.insert(transcript_uri("s1"));
+
+        notify_session_event("s1", "started", json!({}));
+        let event: Value = serde_json::from_str(&a_events.try_recv().unwrap()).unwrap();
+        assert_eq!(event["method"], "agent-hub/session-event");
+        assert_eq!(event["params"], json!({ "session_id": "s1", "event": "started" }));
+        // Starting doesn't change the transcript
+        assert!(a_events.try_recv().is_err());
+        assert!(b_events.try_recv().is_err());
+
+        notify_session_event("s1", "result", json!({ "is_error": false, "result": "done" }));
+        let event: Value = serde_json::from_str(&a_events.try_recv().unwrap()).unwrap();
+        assert_eq!(event["params"]["result"], "done");
+        let updated: Value = serde_json::from_str(&a_events.try_recv().unwrap()).unwrap();
+        assert_eq!(updated["params"]["uri"], transcript_uri("s1"));
+
+        assert!(disconnect(&a) && disconnect(&b));
+        assert!(!is_connected(&a) && !disconnect(&a));
+    }
+
+    #[test]
+    fn progress_goes_to_the_requesting_client_when_asked_for() {
+        let client = connect();
+        let mut events = attach(&client).unwrap();
+
+        let silent = CallContext::for_request(&client, &json!({ "name": "wait_for_element" }));
+        silent.report(1000.0, Some(5000.0), "Waiting");
+        assert!(events.try_recv().is_err());
+
+        let tracked = CallContext::for_request(&client, &json!({ "_meta": { "progressToken": "p1" } }));
+        tracked.report(1000.0, Some(5000.0), "Waiting");
+        let event: Value = serde_json::from_str(&events.try_recv().unwrap()).unwrap();
+        assert_eq!(event["method"], "notifications/progress");
+        assert_eq!(event["params"], json!({ "progressToken": "p1", "progress": 1000.0, "total": 5000.0, "message": "Waiting" }));
+
+        disconnect(&client);
+    }
+
+    #[test]
+    fn roots_default_and_bound_working_dirs() {
+        let client = connect();
+        let _events = attach(&client).unwrap();
+        let call = CallContext::for_request(&client, &json!({}));
+        // No roots: anything goes, nothing is defaulted
+        assert_eq!(call.working_dir(None).unwrap(), None);
+        assert_eq!(call.working_dir(Some("/etc")).unwrap(), Some("/etc".to_string()));
+
+        {
+            let mut clients = CLIENTS.lock();
+            let state = clients.get_mut(&client).unwrap();
+            state.supports_roots = true;
+            state.roots_request = Some("roots-1".to_string());
+        }
+        // An answer to some other request is ignored
+        on_client_response(&client, &json!({ "id": "x", "result": { "roots": [{ "uri": "file:///nowhere" }] } }));
+        assert!(call.roots().is_empty());
+        on_client_response(&client, &json!({ "id": "roots-1", "result": { "roots": [
+            { "uri": "file:///work/agent%20hub", "name": "hub" },
+            { "uri": "https://example.com" },
+        ]}}));
+        assert_eq!(call.roots(), [PathBuf::from("/work/agent hub")]);
+
+        assert_eq!(call.working_dir(None).unwrap(), Some("/work/agent hub".to_string()));
+        assert!(call.working_dir(Some("/work/agent hub/src-tauri")).is_ok());
+        assert!(call.working_dir(Some("/work/agent hubcap")).is_err());
+        assert!(call.working_dir(Some("/etc")).is_err());
+
+        disconnect(&client);
+    }
+
+    #[test]
+    fn log_messages_respect_the_clients_level() {
+        let client = connect();
+        let mut events = attach(&client).unwrap();
+        log(LogLevel::Emergency, "test", json!("unasked"));
+        assert!(events.try_recv().is_err());
+
+        CLIENTS.lock().get_mut(&client).unwrap().log_level = Some(LogLevel::Error);
+        log(LogLevel::Warning, "test", json!("too quiet"));
+        assert!(events.try_recv().is_err());
+        log(LogLevel::Error, "webview", json!({ "error": "boom" }));
+        let event: Value = serde_json::from_str(&events.try_recv().unwrap()).unwrap();
+        assert_eq!(event["method"], "notifications/message");
+        assert_eq!(event["params"], json!({ "level": "error", "logger": "webview", "data": { "error": "boom" } }));
+
+        assert_eq!(thrown_error(r#"{"error":"x is not defined"}"#).as_deref(), Some("x is not defined"));
+        assert_eq!(thrown_error(r#"{"error":"x","count":2}"#), None);
+        assert_eq!(thrown_error("null"), None);
+
+        disconnect(&client);
+    }
+
+    #[test]
+    fn session_configs_point_agents_at_the_local_server() {
+        let config = session_config_json("s1", 3857, "tok");
+        let server = &config["mcpServers"]["agent-hub"];
+        assert_eq!(server["url"], "http://127.0.0.1:3857/api/mcp");
+        assert_eq!(server["headers"]["x-agent-hub-local"], "tok");
+        assert_eq!(server["headers"][SESSION_HEADER], "s1");
+
+        let client = connect();
+        let call = CallContext::for_request(&client, &json!({}));
+        assert!(call.session_id().is_err());
+        bind_session(&client, "s1");
+        assert_eq!(call.session_id().unwrap(), "s1");
+        disconnect(&client);
+    }
+
+    #[test]
+    fn resource_uris_and_terminal_text() {
+        assert_eq!(session_id_from_uri(&transcript_uri("abc")), Some("abc"));
+        assert_eq!(session_id_from_uri("agent-hub://sessions//transcript"), None);
+        assert_eq!(session_id_from_uri("agent-hub://sessions/a/b/transcript"), None);
+        assert_eq!(session_id_from_uri("file:///etc/passwd"), None);
+
+        let output = "\x1b]0;title\x07\x1b[1;32mok\x1b[0m done\r\n$ \x1b[K";
+        assert_eq!(strip_ansi(output), "ok done\n$ ");
+    }
+}
@@ -0,0 +1,275 @@
+// Sequence-numbered log of the chat messages broadcast to mobile clients.
+//
+// Every chat_message a session emits gets the next `seq` for that session and
+// is kept here, so a phone that drops off the network (walking from Wi-Fi to
+// cellular) can resubscribe with the last seq it saw and get exactly the
+// messages it missed before the live stream resumes. Only the most recent
+// KEEP messages per session are retained; a client further behind than that
+// reloads the whole history instead.
+//
+// Clients speaking protocol 5 also acknowledge the messages they've shown.
+// message_acks records, per device and session, the newest seq sent to the
+// device and the newest it acked; whatever lies between is sent again when
+// the device reconnects, so an answer that finished while the phone was
+// locked still arrives.
+
+use once_cell::sync::Lazy;
+use parking_lot::{Mutex, MutexGuard};
+use rusqlite::{params, Connection};
+
+/// Messages kept per session for catching up
+const KEEP: u64 = 2000;
+
+/// Held from logging a message until it has been broadcast, and while a
+/// subscriber is caught up, so replayed and live messages can't interleave.
+static ORDER: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+// =====================================================================
+//  Schema
+// =====================================================================
+
+pub fn run_message_log_migrations(conn: &Connection) {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS message_log (
+            session_id TEXT NOT NULL,
+            seq        INTEGER NOT NULL,
+            message    TEXT NOT NULL,
+            PRIMARY KEY (session_id, seq)
+        )",
+        [],
+    )
+    .expect("Failed to create message_log table");
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS message_acks (
+            device_id     TEXT NOT NULL,
+            session_id    TEXT NOT NULL,
+            delivered_seq INTEGER NOT NULL,
+            acked_seq     INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY (device_id, session_id)
+        )",
+        [],
+    )
+    .expect("Failed to create message_acks table");
+}
+
+// =====================================================================
+//  Log
+// =====================================================================
+
+fn latest_in(conn: &Connection, session_id: &str) -> u64 {
+    conn.query_row(
+        "SELECT COALESCE(MAX(seq), 0) FROM message_log WHERE session_id = ?1",
+        params![session_id],
+        |row| row.get::<_, i64>(0),
+    )
+    .map(|seq| seq as u64)
+    .unwrap_or(0)
+}
+
+fn append_in(conn: &Connection, session_id: &str, message: &serde_json::Value) -> Result<u64, String> {
+    let seq = latest_in(conn, session_id) + 1;
+    conn.execute(
+        "INSERT INTO message_log (session_id, seq, message) VALUES (?1, ?2, ?3)",
+        params![session_id, seq as i64, message.to_string()],
+    )
+    .map_err(|e| e.to_string())?;
+    // Trim in batches rather than on every insert
+    if seq.is_multiple_of(100) && seq > KEEP {
+        let _ = conn.execute(
+            "DELETE FROM message_log WHERE session_id = ?1 AND seq <= ?2",
+            params![session_id, (seq - KEEP) as i64],
+        );
+    }
+    Ok(seq)
+}
+
+/// Messages after `seq`, oldest first, or None if some of them are no longer
+/// kept (or `seq` is from a log that has since been cleared).
+fn since_in(conn: &Connection, session_id: &str, seq: u64) -> Result<Option<Vec<(u64, serde_json::Value)>>, String> {
+    let latest = latest_in(conn, session_id);
+    if seq > latest {
+        return Ok(None);
+    }
+    let mut stmt = conn
+        .prepare("SELECT seq, message FROM message_log WHERE session_id = ?1 AND seq > ?2 ORDER BY seq")
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![session_id, seq as i64], |row| {
+            Ok((row.get::<_, i64>(0)? as u64, row.get::<_, String>(1)?))
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    if rows.first().is_some_and(|(first, _)| *first != seq + 1) {
+        return Ok(None);
+    }
+    Ok(Some(
+        rows.into_iter()
+            .filter_map(|(seq, text)| serde_json::from_str(&text).ok().map(|message| (seq, message)))
+            .collect(),
+    ))
+}
+
+pub fn delete_log_for_session(conn: &Connection, session_id: &str) {
+    let _ = conn.execute("DELETE FROM message_log WHERE session_id = ?1", params![session_id]);
+    let _ = conn.execute("DELETE FROM message_acks WHERE session_id = ?1", params![session_id]);
+}
+
+// =====================================================================
+//  Acknowledgements
+// =====================================================================
+
+fn delivered_in(conn: &Connection, device_id: &str, session_id: &str, seq: u64) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO message_acks (device_id, session_id, delivered_seq) VALUES (?1, ?2, ?3)
+         ON CONFLICT (device_id, session_id) DO UPDATE SET delivered_seq = MAX(delivered_seq, excluded.delivered_seq)",
+        params![device_id, session_id, seq as i64],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn acked_in(conn: &Connection, device_id: &str, session_id: &str, seq: u64) -> Result<(), String> {
+    // A client acking past what it was sent (history it loaded) owes nothing
+    conn.execute(
+        "INSERT INTO message_acks (device_id, session_id, delivered_seq, acked_seq) VALUES (?1, ?2, ?3, ?3)
+         ON CONFLICT (device_id, session_id) DO UPDATE SET
+             acked_seq = MAX(acked_seq, excluded.acked_seq),
+             delivered_seq = MAX(delivered_seq, excluded.acked_seq)",
+        params![device_id, session_id, seq as i64],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Sessions with messages sent to the device but not acked, with the newest
+/// seq it did ack in each.
+fn unacked_in(conn: &Connection, device_id: &str) -> Result<Vec<(String, u64)>, String> {
+    let mut stmt = conn
+        .prepare("SELECT session_id, acked_seq FROM message_acks WHERE device_id = ?1 AND delivered_seq > acked_seq")
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![device_id], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as u64)))
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+}
+
+fn undelivered_count_in(conn: &Connection, device_id: &str) -> u64 {
+    conn.query_row(
+        "SELECT COALESCE(SUM(delivered_seq - acked_seq), 0) FROM message_acks WHERE device_id = ?1",
+        params![device_id],
+        |row| row.get::<_, i64>(0),
+    )
+    .map(|n| n as u64)
+    .unwrap_or(0)
+}
+
+pub fn delete_acks_for_device(conn: &Connection, device_id: &str) {
+    let _ = conn.execute("DELETE FROM message_acks WHERE device_id = ?1", params![device_id]);
+}
+
+/// Take before `append` and keep until the message is broadcast; take before
+/// `since` and keep until the replay is sent and the client subscribed.
+pub fn lock() -> MutexGuard<'static, ()> {
+    ORDER.lock()
+}
+
+/// Log a message, returning its seq.
+pub fn append(session_id: &str, message: &serde_json::Value) -> Result<u64, String> {
+    append_in(&crate::state::get().db().lock(), session_id, message)
+}
+
+/// The seq of the session's newest message (0 if none).
+pub fn latest(session_id: &str) -> u64 {
+    latest_in(&crate::state::get().db().lock(), session_id)
+}
+
+pub fn since(session_id: &str, seq: u64) -> Result<Option<Vec<(u64, serde_json::Value)>>, String> {
+    since_in(&crate::state::get().db().lock(), session_id, seq)
+}
+
+/// Record that `seq` was sent to a device that acks.
+pub fn delivered(device_id: &str, session_id: &str, seq: u64) {
+    if let Err(e) = delivered_in(&crate::state::get().db().lock(), device_id, session_id, seq) {
+        tracing::warn!("delivery to {}: {}", device_id, e);
+    }
+}
+
+pub fn acked(device_id: &str, session_id: &str, seq: u64) -> Result<(), String> {
+    acked_in(&crate::state::get().db().lock(), device_id, session_id, seq)
+}
+
+pub fn unacked(device_id: &str) -> Result<Vec<(String, u64)>, String> {
+    unacked_in(&crate::state::get().db().lock(), device_id)
+}
+
+/// Messages sent to the device that it hasn't acknowledged.
+pub fn undelivered_count(device_id: &str) -> u64 {
+    undelivered_count_in(&crate::state::get().db().lock(), device_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        run_message_log_migrations(&conn);
+        conn
+    }
+
+    #[test]
+    fn replays_only_what_was_missed() {
+        let conn = setup();
+        for i in 1..=3 {
+            assert_eq!(append_in(&conn, "s1", &serde_json::json!({ "n": i })).unwrap(), i);
+        }
+        // Sessions are numbered independently
+        assert_eq!(append_in(&conn, "s2", &serde_json::json!({ "n": 1 })).unwrap(), 1);
+
+        let missed = since_in(&conn, "s1", 1).unwrap().unwrap();
+        assert_eq!(missed.iter().map(|(seq, _)| *seq).collect::<Vec<_>>(), vec![2, 3]);
+        assert_eq!(missed[1].1["n"], 3);
+        assert!(since_in(&conn, "s1", 3).unwrap().unwrap().is_empty());
+        // A seq the log never reached means the client's view is stale
+        assert_eq!(since_in(&conn, "s1", 7).unwrap(), None);
+
+        delete_log_for_session(&conn, "s1");
+        assert_eq!(latest_in(&conn, "s1"), 0);
+        assert_eq!(latest_in(&conn, "s2"), 1);
+    }
+
+    #[test]
+    fn trimmed_gaps_need_a_full_reload() {
+        let conn = setup();
+        for _ in 0..KEEP + 100 {
+            append_in(&conn, "s1", &serde_json::json!({})).unwrap();
+        }
+        assert_eq!(since_in(&conn, "s1", 50).unwrap(), None);
+        assert_eq!(since_in(&conn, "s1", 100).unwrap().unwrap().len(), KEEP as usize);
+    }
+
+    #[test]
+    fn unacked_messages_are_owed_until_acked() {
+        let conn = setup();
+        for seq in 1..=5 {
+            delivered_in(&conn, "phone", "s1", seq).unwrap();
+        }
+        delivered_in(&conn, "phone", "s2", 2).unwrap();
+        acked_in(&conn, "phone", "s1", 3).unwrap();
+        // Late or repeated acks never move backwards
+        acked_in(&conn, "phone", "s1", 1).unwrap();
+
+        let mut owed = unacked_in(&conn, "phone").unwrap();
+        owed.sort();
+        assert_eq!(owed, vec![("s1".to_string(), 3), ("s2".to_string(), 0)]);
+        assert_eq!(undelivered_count_in(&conn, "phone"), 4);
+
+        // Acking beyond what was sent settles the session
+        acked_in(&conn, "phone", "s1", 9).unwrap();
+        acked_in(&conn, "phone", "s2", 2).unwrap();
+        assert!(unacked_in(&conn, "phone").unwrap().is_empty());
+        assert_eq!(undelivered_count_in(&conn, "phone"), 0);
+        assert_eq!(undelivered_count_in(&conn, "tablet"), 0);
+    }
+}
@@ -0,0 +1,182 @@
+// Prometheus metrics for the web server (GET /metrics).
+//
+// Counters and WebSocket gauges are updated in place by the code they
+// describe; session counts and the DB probe are sampled at scrape time and
+// passed in as a `Snapshot`.
+
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::time::Duration;
+
+#[derive(Clone, Copy)]
+pub enum WsKind {
+    Session,
+    Status,
+    Mobile,
+}
+
+impl WsKind {
+    const ALL: [WsKind; 3] = [WsKind::Session, WsKind::Status, WsKind::Mobile];
+
+    fn labels(self) -> &'static str {
+        match self {
+            WsKind::Session => "{kind=\"session\"}",
+            WsKind::Status => "{kind=\"status\"}",
+            WsKind::Mobile => "{kind=\"mobile\"}",
+        }
+    }
+}
+
+/// Broadcast channels WebSocket clients can fall behind on
+#[derive(Clone, Copy, Debug)]
+pub enum Stream {
+    Pty,
+    Json,
+    Status,
+}
+
+impl Stream {
+    const ALL: [Stream; 3] = [Stream::Pty, Stream::Json, Stream::Status];
+
+    fn labels(self) -> &'static str {
+        match self {
+            Stream::Pty => "{stream=\"pty\"}",
+            Stream::Json => "{stream=\"json\"}",
+            Stream::Status => "{stream=\"status\"}",
+        }
+    }
+}
+
+static WS_CLIENTS: [AtomicI64; 3] = [AtomicI64::new(0), AtomicI64::new(0), AtomicI64::new(0)];
+static BYTES_STREAMED: AtomicU64 = AtomicU64::new(0);
+static BROADCAST_LAGGED: [AtomicU64; 3] = [AtomicU64::new(0), AtomicU64::new(0), AtomicU64::new(0)];
+static BROADCAST_RESYNCS: [AtomicU64; 3] = [AtomicU64::new(0), AtomicU64::new(0), AtomicU64::new(0)];
+static AUTH_FAILURES: AtomicU64 = AtomicU64::new(0);
+static DB_WRITE_COUNT: AtomicU64 = AtomicU64::new(0);
+static DB_WRITE_MICROS: AtomicU64 = AtomicU64::new(0);
+
+/// Counts a WebSocket connection for as long as it is held.
+pub struct WsGuard(WsKind);
+
+impl Drop for WsGuard {
+    fn drop(&mut self) {
+        WS_CLIENTS[self.0 as usize].fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+pub fn ws_connected(kind: WsKind) -> WsGuard {
+    WS_CLIENTS[kind as usize].fetch_add(1, Ordering::Relaxed);
+    WsGuard(kind)
+}
+
+/// Bytes of session output / events sent to WebSocket clients.
+pub fn add_bytes_streamed(n: usize) {
+    BYTES_STREAMED.fetch_add(n as u64, Ordering::Relaxed);
+}
+
+/// Record messages a slow WebSocket subscriber missed, and the resync that
+/// replaces them.
+pub fn on_lagged(stream: Stream, missed: u64) {
+    BROADCAST_LAGGED[stream as usize].fetch_add(missed, Ordering::Relaxed);
+    BROADCAST_RESYNCS[stream as usize].fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn on_auth_failure() {
+    AUTH_FAILURES.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Time spent writing a terminal buffer to the database.
+pub fn observe_db_write(elapsed: Duration) {
+    DB_WRITE_COUNT.fetch_add(1, Ordering::Relaxed);
+    DB_WRITE_MICROS.fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+}
+
+/// Values sampled by the /metrics handler.
+pub struct Snapshot {
+    pub pty_sessions: usize,
+    pub json_sessions: usize,
+    pub uptime_secs: u64,
+    /// Latency of a trivial query; None when the DB couldn't be reached.
+    pub db_ping: Option<Duration>,
+}
+
+fn metric(out: &mut String, name: &str, kind: &str, help: &str, samples: &[(&str, String)]) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    for (labels, value) in samples {
+        let _ = writeln!(out, "{}{} {}", name, labels, value);
+    }
+}
+
+/// Render all metrics in the Prometheus text exposition format.
+pub fn render(snapshot: &Snapshot) -> String {
+    let mut out = String::new();
+    metric(&mut out, "agent_hub_uptime_seconds", "gauge", "Seconds since the web server started.", &[
+        ("", snapshot.uptime_secs.to_string()),
+    ]);
+    metric(&mut out, "agent_hub_active_sessions", "gauge", "Sessions with a running process.", &[
+        ("{kind=\"pty\"}", snapshot.pty_sessions.to_string()),
+        ("{kind=\"json\"}", snapshot.json_sessions.to_string()),
+    ]);
+    let ws: Vec<(&str, String)> = WsKind::ALL
+        .iter()
+        .map(|k| (k.labels(), WS_CLIENTS[*k as usize].load(Ordering::Relaxed).max(0).to_string()))
+        .collect();
+    metric(&mut out, "agent_hub_websocket_clients", "gauge", "Connected WebSocket clients.", &ws);
+    metric(&mut out, "agent_hub_streamed_bytes_total", "counter", "Bytes sent to WebSocket clients.", &[
+        ("", BYTES_STREAMED.load(Ordering::Relaxed).to_string()),
+    ]);
+    let per_stream = |counters: &[AtomicU64; 3]| -> Vec<(&str, String)> {
+        Stream::ALL
+            .iter()
+            .map(|s| (s.labels(), counters[*s as usize].load(Ordering::Relaxed).to_string()))
+            .collect()
+    };
+    metric(
+        &mut out,
+        "agent_hub_broadcast_lagged_total",
+        "counter",
+        "Broadcast messages dropped for WebSocket clients that fell behind.",
+        &per_stream(&BROADCAST_LAGGED),
+    );
+    metric(
+        &mut out,
+        "agent_hub_broadcast_resyncs_total",
+        "counter",
+        "Snapshots sent to WebSocket clients in place of dropped messages.",
+        &per_stream(&BROADCAST_RESYNCS),
+    );
+    metric(&mut out, "agent_hub_auth_failures_total", "counter", "Rejected API requests.", &[
+        ("", AUTH_FAILURES.load(Ordering::Relaxed).to_string()),
+    ]);
+    metric(&mut out, "agent_hub_db_write_seconds", "summary", "Terminal buffer writes to the database.", &[
+        ("_sum", format!("{:.6}", DB_WRITE_MICROS.load(Ordering::Relaxed) as f64 / 1e6)),
+        ("_count", DB_WRITE_COUNT.load(Ordering::Relaxed).to_string()),
+    ]);
+    metric(&mut out, "agent_hub_db_up", "gauge", "Whether the database answered the scrape probe.", &[
+        ("", u8::from(snapshot.db_ping.is_some()).to_string()),
+    ]);
+    if let Some(ping) = snapshot.db_ping {
+        metric(&mut out, "agent_hub_db_ping_seconds", "gauge", "Latency of the scrape probe query.", &[
+            ("", format!("{:.6}", ping.as_secs_f64())),
+        ]);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_exposition_format() {
+        let _guard = ws_connected(WsKind::Mobile);
+        let out = render(&Snapshot { pty_sessions: 2, json_sessions: 1, uptime_secs: 9, db_ping: None });
+        assert!(out.contains("# TYPE agent_hub_active_sessions gauge\n"));
+        assert!(out.contains("agent_hub_active_sessions{kind=\"pty\"} 2\n"));
+        assert!(out.contains("agent_hub_websocket_clients{kind=\"mobile\"} 1\n"));
+        assert!(out.contains("agent_hub_broadcast_resyncs_total{stream=\"status\"} "));
+        assert!(out.contains("agent_hub_db_up 0\n"));
+        assert!(!out.contains("agent_hub_db_ping_seconds"));
+    }
+}
@@ -0,0 +1,105 @@
+// Which notifications a paired device wants (see push.rs): the events, the
+// sessions it has muted, and quiet hours. Kept on the PairedDevice, so each
+// device's token carries them, and edited by the device itself over the
+// mobile WebSocket or from the desktop's device list.
+
+use serde::{Deserialize, Serialize};
+
+/// Events push.rs sends
+pub const EVENTS: &[&str] = &["result", "error", "attention"];
+
+/// Local times, "HH:MM"; a range that ends before it starts runs overnight
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct QuietHours {
+    pub start: String,
+    pub end: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct NotifyPrefs {
+    /// Events that notify; None for all of them
+    #[serde(default)]
+    pub events: Option<Vec<String>>,
+    /// Sessions that never notify
+    #[serde(default)]
+    pub muted_sessions: Vec<String>,
+    /// No notifications during these hours
+    #[serde(default)]
+    pub quiet_hours: Option<QuietHours>,
+}
+
+/// Minutes since midnight for "HH:MM"
+fn minutes(time: &str) -> Option<u32> {
+    let (h, m) = time.split_once(':')?;
+    let (h, m): (u32, u32) = (h.parse().ok()?, m.parse().ok()?);
+    (h < 24 && m < 60).then_some(h * 60 + m)
+}
+
+impl NotifyPrefs {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(event) = self.events.iter().flatten().find(|e| !EVENTS.contains(&e.as_str())) {
+            return Err(format!("Unknown event '{}'; expected one of {}", event, EVENTS.join(", ")));
+        }
+        if let Some(quiet) = &self.quiet_hours {
+            if minutes(&quiet.start).is_none() || minutes(&quiet.end).is_none() {
+                return Err("Quiet hours must be HH:MM".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    fn is_quiet(&self, now: u32) -> bool {
+        let Some(quiet) = &self.quiet_hours else { return false };
+        let (Some(start), Some(end)) = (minutes(&quiet.start), minutes(&quiet.end)) else { return false };
+        if start <= end {
+            (start..end).contains(&now)
+        } else {
+            now >= start || now < end
+        }
+    }
+
+    /// Whether `event` about `session_id` notifies at `now`, in minutes since
+    /// local midnight
+    pub fn allows(&self, session_id: &str, event: &str, now: u32) -> bool {
+        self.events.as_ref().is_none_or(|events| events.iter().any(|e| e == event))
+            && !self.muted_sessions.iter().any(|s| s == session_id)
+            && !self.is_quiet(now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_mutes_and_overnight_quiet_hours() {
+        let prefs: NotifyPrefs = serde_json::from_value(serde_json::json!({
+            "events": ["error", "attention"],
+            "muted_sessions": ["batch"],
+            "quiet_hours": { "start": "22:30", "end": "07:00" },
+        }))
+        .unwrap();
+        prefs.validate().unwrap();
+        let noon = 12 * 60;
+        assert!(prefs.allows("s1", "error", noon));
+        assert!(!prefs.allows("s1", "result", noon));
+        assert!(!prefs.allows("batch", "error", noon));
+        assert!(!prefs.allows("s1", "error", 23 * 60));
+        assert!(!prefs.allows("s1", "error", 3 * 60));
+        assert!(prefs.allows("s1", "error", 7 * 60));
+
+        // Nothing set allows everything
+        assert!(NotifyPrefs::default().allows("batch", "result", 3 * 60));
+    }
+
+    #[test]
+    fn invalid_prefs_are_rejected() {
+        let bad_event = NotifyPrefs { events: Some(vec!["everything".to_string()]), ..Default::default() };
+        assert!(bad_event.validate().is_err());
+        let bad_time = NotifyPrefs {
+            quiet_hours: Some(QuietHours { start: "25:00".to_string(), end: "07:00".to_string() }),
+            ..Default::default()
+        };
+        assert!(bad_time.validate().is_err());
+    }
+}
@@ -0,0 +1,570 @@
+// OpenAPI 3 description of the web API, served at GET /api/openapi.json.
+//
+// Paths are documented under the /api alias; every /api/* path is also
+// served under /api/v1. Written by hand (the handlers take loosely-typed JSON bodies, so there is
+// nothing to derive from); keep it in step with the routes registered in
+// `start_web_server`. A test fails when a route is missing here.
+
+use serde_json::{json, Map, Value};
+
+struct Endpoint {
+    method: &'static str,
+    path: &'static str,
+    tag: &'static str,
+    summary: &'static str,
+    public: bool,
+    query: Vec<(&'static str, &'static str, bool)>,
+    body: Option<Value>,
+    response: Value,
+}
+
+fn ep(method: &'static str, path: &'static str, tag: &'static str, summary: &'static str) -> Endpoint {
+    Endpoint {
+        method,
+        path,
+        tag,
+        summary,
+        public: false,
+        query: Vec::new(),
+        body: None,
+        response: schema_ref("Status"),
+    }
+}
+
+impl Endpoint {
+    /// No bearer token required.
+    fn public(mut self) -> Self {
+        self.public = true;
+        self
+    }
+
+    fn query(mut self, name: &'static str, ty: &'static str, required: bool) -> Self {
+        self.query.push((name, ty, required));
+        self
+    }
+
+    fn body(mut self, schema: Value) -> Self {
+        self.body = Some(schema);
+        self
+    }
+
+    fn returns(mut self, schema: Value) -> Self {
+        self.response = schema;
+        self
+    }
+}
+
+fn ty(t: &str) -> Value {
+    json!({ "type": t })
+}
+
+fn nullable(t: &str) -> Value {
+    json!({ "type": t, "nullable": true })
+}
+
+fn array_of(items: Value) -> Value {
+    json!({ "type": "array", "items": items })
+}
+
+fn schema_ref(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{}", name) })
+}
+
+fn object(props: &[(&str, Value)], required: &[&str]) -> Value {
+    let properties: Map<String, Value> = props.iter().map(|(k, v)| (k.to_string(), v.clone())).collect();
+    let mut schema = json!({ "type": "object", "properties": properties });
+    if !required.is_empty() {
+        schema["required"] = json!(required);
+    }
+    schema
+}
+
+fn endpoints() -> Vec<Endpoint> {
+    let id_list = array_of(json!({ "type": "array", "items": {}, "minItems": 2, "maxItems": 2 }));
+    vec![
+        // Server
+        ep("get", "/api/health", "server", "Liveness: uptime, database, running sessions").public().returns(object(
+            &[
+                ("status", ty("string")),
+                ("uptime_secs", ty("integer")),
+                ("db", ty("boolean")),
+                ("sessions", ty("object")),
+                ("tunnel", object(&[("mode", ty("string")), ("public_url", ty("string")), ("error", ty("string"))], &[])),
+            ],
+            &[],
+        )),
+        ep("get", "/api/version", "server", "App and API protocol version").public().returns(object(
+            &[("version", ty("string")), ("protocol_version", ty("integer")), ("platform", ty("string"))],
+            &[],
+        )),
+        ep("get", "/metrics", "server", "Prometheus metrics (text exposition format)").returns(ty("string")),
+        ep("get", "/api/logs", "server", "Recent app log lines, oldest first; `level` keeps that level and more severe (admin only)")
+            .query("level", "string", false)
+            .query("limit", "integer", false)
+            .returns(array_of(object(
+                &[("timestamp", ty("string")), ("level", ty("string")), ("target", ty("string")), ("message", ty("string"))],
+                &["timestamp", "level", "target", "message"],
+            ))),
+        ep("get", "/api/openapi.json", "server", "This document").public().returns(ty("object")),
+        ep("get", "/api/cert", "server", "Server certificate fingerprint for pinning").public().returns(object(
+            &[("tls", ty("boolean")), ("fingerprint_sha256", ty("string")), ("self_signed", ty("boolean"))],
+            &["tls"],
+        )),
+        ep("get", "/api/instances", "server", "Other Agent Hub instances on the LAN (mDNS, takes a few seconds)").returns(
+            object(&[("instances", array_of(object(
+                &[
+                    ("name", ty("string")),
+                    ("host", ty("string")),
+                    ("port", ty("integer")),
+                    ("version", ty("string")),
+                    ("protocol_version", ty("integer")),
+                    ("tls", ty("boolean")),
+                    ("url", ty("string")),
+                ],
+                &["name", "host", "port", "tls", "url"],
+            )))], &["instances"]),
+        ),
+        // Auth
+        ep("get", "/api/auth/check", "auth", "Whether the bearer token is valid").public()
+            .returns(object(&[("authenticated", ty("boolean")), ("reason", ty("string"))], &[])),
+        ep("post", "/api/auth/request-pairing", "auth", "Start pairing; the desktop shows a code (rate limited)").public()
+            .body(object(&[("device_name", ty("string"))], &[]))
+            .returns(object(&[("pairing_id", ty("string")), ("expires_in", ty("integer"))], &[])),
+        ep("post", "/api/auth/pair", "auth", "Complete pairing with the displayed code; answers 202 until the desktop approves (rate limited)").public()
+            .body(object(&[("pairing_id", ty("string")), ("code", ty("string")), ("device_name", ty("string"))], &["pairing_id", "code"]))
+            .returns(object(&[("token", ty("string")), ("device_id", ty("string")), ("expires_at", ty("string"))], &[])),
+        ep("post", "/api/auth/refresh", "auth", "Exchange the bearer token for a new one; the old one stops working (rate limited)").public()
+            .returns(object(&[("token", ty("string")), ("device_id", ty("string")), ("expires_at", ty("string"))], &[])),
+        ep("get", "/api/auth/qr", "auth", "Pairing QR code (SVG); loopback clients only").public().returns(ty("string")),
+        ep("get", "/api/auth/pin-status", "auth", "Whether PIN login is configured").public()
+            .returns(object(&[("pin_configured", ty("boolean"))], &[])),
+        ep("post", "/api/auth/pin-login", "auth", "Log in with the remote PIN (rate limited)").public()
+            .body(object(&[("pin", ty("string")), ("device_name", ty("string"))], &["pin"]))
+            .returns(object(&[("token", ty("string")), ("device_id", ty("string")), ("expires_at", ty("string"))], &[])),
+        // Sessions
+        ep("get", "/api/sessions", "sessions", "List sessions with running status").returns(array_of(schema_ref("Session"))),
+        ep("post", "/api/sessions", "sessions", "Create a session")
+            .body(object(
+                &[
+                    ("name", ty("string")),
+                    ("agent_type", ty("string")),
+                    ("custom_command", ty("string")),
+                    ("working_dir", ty("string")),
+                    ("env_vars", ty("object")),
+                    ("folder_id", ty("string")),
+                    ("workspace_id", ty("string")),
+                ],
+                &[],
+            ))
+            .returns(schema_ref("Session")),
+        ep("patch", "/api/sessions/{session_id}", "sessions", "Rename, move, or change working dir / sort order")
+            .body(object(
+                &[("name", ty("string")), ("folder_id", nullable("string")), ("working_dir", ty("string")), ("sort_order", ty("integer"))],
+                &[],
+            ))
+            .returns(schema_ref("Session")),
+        ep("delete", "/api/sessions/{session_id}", "sessions", "Stop and delete a session"),
+        ep("get", "/api/sessions/{session_id}/buffer", "sessions", "Saved terminal / message buffer")
+            .returns(object(&[("buffer", nullable("string"))], &[])),
+        ep("post", "/api/sessions/{session_id}/start", "sessions", "Start the session's process"),
+        ep("post", "/api/sessions/{session_id}/interrupt", "sessions", "Interrupt the current turn (SIGINT / Ctrl+C)"),
+        ep("post", "/api/sessions/{session_id}/stop", "sessions", "Stop the session's process"),
+        ep("get", "/api/asks", "sessions", "Questions and approval requests agents are waiting on")
+            .query("session_id", "string", false)
+            .returns(array_of(object(
+                &[("id", ty("string")), ("session_id", ty("string")), ("kind", ty("string")), ("prompt", ty("string")),
+                  ("options", array_of(ty("string"))), ("created_at", ty("integer"))],
+                &[],
+            ))),
+        ep("post", "/api/asks/{ask_id}/answer", "sessions", "Answer a question (text) or approval request (approved)")
+            .body(object(&[("text", ty("string")), ("approved", ty("boolean"))], &[])),
+        ep("post", "/api/sessions/{session_id}/input", "sessions", "Write to a terminal session (rate limited)")
+            .body(object(&[("text", ty("string")), ("is_control", ty("boolean"))], &["text"])),
+        ep("post", "/api/sessions/{session_id}/prompt", "sessions", "Send a prompt; chat sessions are started if needed (rate limited)")
+            .body(object(&[("text", ty("string"))], &["text"])),
+        ep("post", "/api/sessions/{id}/children", "sessions", "Spawn a child session")
+            .body(object(&[("working_dir", ty("string")), ("name", ty("string")), ("prompt", ty("string"))], &["working_dir"]))
+            .returns(schema_ref("Session")),
+        ep("post", "/api/sessions/{id}/audio", "sessions", "Send a voice prompt (raw audio body, transcribed on the desktop; rate limited)")
+            .body(json!({ "type": "string", "format": "binary" }))
+            .returns(object(&[("text", ty("string"))], &[])),
+        ep("post", "/api/sessions/{id}/attachments", "sessions", "Send an image (raw body) with optional caption (rate limited)")
+            .query("text", "string", false)
+            .body(json!({ "type": "string", "format": "binary" }))
+            .returns(object(&[("id", ty("string")), ("media_type", ty("string"))], &[])),
+        // Folders
+        ep("get", "/api/folders", "folders", "List folders").returns(array_of(schema_ref("Folder"))),
+        ep("post", "/api/folders", "folders", "Create a folder")
+            .body(object(&[("name", ty("string"))], &["name"]))
+            .returns(schema_ref("Folder")),
+        ep("patch", "/api/folders", "folders", "Reorder folders")
+            .body(object(&[("orders", id_list)], &["orders"]))
+            .returns(array_of(schema_ref("Folder"))),
+        ep("patch", "/api/folders/{id}", "folders", "Rename, collapse or move a folder")
+            .body(object(&[("name", ty("string")), ("collapsed", ty("boolean")), ("sort_order", ty("integer"))], &[]))
+            .returns(schema_ref("Folder")),
+        ep("delete", "/api/folders/{id}", "folders", "Delete a folder (its sessions become unfiled)"),
+        // Workspaces
+        ep("get", "/api/workspaces", "workspaces", "Workspaces visible to remote devices, with this month's spend")
+            .returns(array_of(schema_ref("Workspace"))),
+        // Devices
+        ep("get", "/api/devices", "devices", "Paired devices; `current` is the caller, `undelivered_count` the chat messages it hasn't acked").returns(array_of(object(
+            &[
+                ("id", ty("string")),
+                ("name", ty("string")),
+                ("paired_at", ty("string")),
+                ("last_seen", ty("string")),
+                ("expires_at", ty("string")),
+                ("scope", ty("string")),
+                (
+                    "notifications",
+                    object(
+                        &[
+                            ("events", array_of(ty("string"))),
+                            ("muted_sessions", array_of(ty("string"))),
+                            ("quiet_hours", object(&[("start", ty("string")), ("end", ty("string"))], &["start", "end"])),
+                        ],
+                        &[],
+                    ),
+                ),
+                ("current", ty("boolean")),
+                ("undelivered_count", ty("integer")),
+            ],
+            &["id", "name", "paired_at", "last_seen"],
+        ))),
+        ep("patch", "/api/devices/{id}", "devices", "Set a device's scope: read_only, interact or admin (admin only)")
+            .body(object(&[("scope", ty("string"))], &["scope"])),
+        ep("delete", "/api/devices/{id}", "devices", "Revoke a device and close its connections (admin only)"),
+        ep("get", "/api/audit", "devices", "Audit log of remote actions, newest first (admin only)")
+            .query("device_id", "string", false)
+            .query("session_id", "string", false)
+            .query("before", "integer", false)
+            .query("limit", "integer", false)
+            .returns(array_of(object(
+                &[
+                    ("id", ty("integer")),
+                    ("ts", ty("string")),
+                    ("device_id", ty("string")),
+                    ("device_name", ty("string")),
+                    ("action", ty("string")),
+                    ("session_id", ty("string")),
+                    ("source_ip", ty("string")),
+                ],
+                &["id", "ts", "action"],
+            ))),
+        ep("get", "/api/webhooks", "devices", "Configured webhooks, without their secrets (admin only)")
+            .returns(array_of(object(
+                &[("id", ty("string")), ("url", ty("string")), ("events", array_of(ty("string"))), ("created_at", ty("string"))],
+                &["id", "url", "events", "created_at"],
+            ))),
+        // Push notifications
+        ep("get", "/api/push/vapid-key", "devices", "VAPID public key for browser push subscriptions")
+            .returns(object(&[("public_key", ty("string"))], &["public_key"])),
+        ep("get", "/api/push/subscription", "devices", "The calling device's push subscription, or null")
+            .returns(object(&[("kind", ty("string")), ("endpoint", ty("string")), ("created_at", ty("integer"))], &[])),
+        ep("put", "/api/push/subscription", "devices", "Set the calling device's push subscription (webpush, ntfy or webhook)")
+            .body(object(
+                &[
+                    ("kind", ty("string")),
+                    ("endpoint", ty("string")),
+                    ("keys", object(&[("p256dh", ty("string")), ("auth", ty("string"))], &[])),
+                ],
+                &["endpoint"],
+            ))
+            .returns(object(&[("kind", ty("string")), ("endpoint", ty("string")), ("created_at", ty("integer"))], &[])),
+        ep("delete", "/api/push/subscription", "devices", "Stop push notifications to the calling device"),
+        // Remote hubs
+        ep("get", "/api/hubs", "hubs", "Other Agent Hub instances whose sessions are listed here")
+            .returns(array_of(object(&[("id", ty("string")), ("name", ty("string")), ("url", ty("string")), ("created_at", ty("integer"))], &["id", "name", "url"]))),
+        ep("post", "/api/hubs", "hubs", "Register a remote hub with a token from a device paired on it (this computer only)")
+            .body(object(&[("name", ty("string")), ("url", ty("string")), ("token", ty("string"))], &["name", "url", "token"]))
+            .returns(object(&[("id", ty("string")), ("name", ty("string")), ("url", ty("string")), ("created_at", ty("integer"))], &["id", "name", "url"])),
+        ep("delete", "/api/hubs/{id}", "hubs", "Forget a remote hub and its token (this computer only)"),
+        ep("get", "/api/hubs/sessions", "hubs", "Sessions here and on every remote hub, tagged with `hub_id` and `host` (null here)")
+            .returns(object(
+                &[
+                    ("sessions", array_of(schema_ref("Session"))),
+                    (
+                        "hubs",
+                        array_of(object(
+                            &[
+                                ("id", ty("string")),
+                                ("name", ty("string")),
+                                ("url", ty("string")),
+                                ("reachable", ty("boolean")),
+                                ("error", nullable("string")),
+                            ],
+                            &["id", "reachable"],
+                        )),
+                    ),
+                ],
+                &["sessions", "hubs"],
+            )),
+        ep("get", "/api/hubs/{id}/api/{path}", "hubs", "A remote hub's API at /api/<path>, called with its token (any method; writes need interact)")
+            .returns(ty("object")),
+        ep("get", "/api/hubs/{id}/ws/mobile", "websocket", "WebSocket: a remote hub's mobile protocol, relayed (auth in first message)").public(),
+        // Settings
+        ep("get", "/api/settings", "settings", "Remotely editable settings").returns(ty("object")),
+        ep("patch", "/api/settings", "settings", "Change remotely editable settings").body(ty("object")).returns(ty("object")),
+        // Headless runs
+        ep("post", "/api/run", "runs", "One-shot run; streams SSE unless stream is false")
+            .body(object(
+                &[
+                    ("prompt", ty("string")),
+                    ("working_dir", ty("string")),
+                    ("model", ty("string")),
+                    ("persist", ty("boolean")),
+                    ("name", ty("string")),
+                    ("timeout_secs", ty("integer")),
+                    ("stream", ty("boolean")),
+                ],
+                &["prompt"],
+            ))
+            .returns(schema_ref("Run")),
+        ep("get", "/api/run/{id}", "runs", "A headless run").returns(schema_ref("Run")),
+        // Search
+        ep("get", "/api/search/messages", "search", "Full-text search over message history")
+            .query("q", "string", true)
+            .query("session_id", "string", false)
+            .query("role", "string", false)
+            .query("from_ts", "integer", false)
+            .query("to_ts", "integer", false)
+            .query("limit", "integer", false)
+            .query("offset", "integer", false)
+            .returns(ty("object")),
+        ep("get", "/api/search/context", "search", "Messages around a search hit")
+            .query("message_id", "integer", true)
+            .query("before", "integer", false)
+            .query("after", "integer", false)
+            .returns(ty("object")),
+        ep("get", "/api/search/stats", "search", "Search index statistics").returns(ty("object")),
+        ep("post", "/api/search/rebuild", "search", "Rebuild the search index"),
+        // Schedules
+        ep("get", "/api/schedules", "schedules", "List scheduled jobs").returns(array_of(schema_ref("Schedule"))),
+        ep("post", "/api/schedules", "schedules", "Create a scheduled job")
+            .body(object(&[("name", ty("string")), ("cron_expr", ty("string")), ("prompt", ty("string"))], &["name", "cron_expr", "prompt"]))
+            .returns(schema_ref("Schedule")),
+        ep("patch", "/api/schedules/{id}", "schedules", "Update a scheduled job")
+            .body(object(&[("name", ty("string")), ("cron_expr", ty("string")), ("prompt", ty("string")), ("enabled", ty("boolean"))], &[]))
+            .returns(schema_ref("Schedule")),
+        ep("delete", "/api/schedules/{id}", "schedules", "Delete a scheduled job"),
+        ep("get", "/api/fanout/{id}", "schedules", "A fan-out run with each session's response").returns(ty("object")),
+        // Integrations
+        ep("post", "/api/webhook/teams", "integrations", "Incoming Teams message (X-Webhook-Secret header)").public()
+            .body(object(&[("from", ty("string")), ("message", ty("string")), ("link", ty("string"))], &["message"])),
+        ep("post", "/api/mcp/execute", "integrations", "Run JavaScript in the desktop webview (token or local MCP bridge)")
+            .body(object(&[("code", ty("string")), ("timeout_ms", ty("integer")), ("request_id", ty("string"))], &["code"]))
+            .returns(object(&[("success", ty("boolean")), ("request_id", ty("string")), ("result", ty("string"))], &[])),
+        ep("post", "/api/mcp/cancel", "integrations", "Cancel a running execute by its request_id")
+            .body(object(&[("request_id", ty("string"))], &["request_id"])),
+        ep("get", "/api/mcp/tools", "integrations", "The MCP tools with their input schemas").returns(ty("object")),
+        ep("post", "/api/mcp/result", "integrations", "Webview callback with an execute result (keyed by request id)").public()
+            .body(object(&[("request_id", ty("string")), ("result", ty("string"))], &["request_id"])),
+        ep("post", "/api/mcp/screenshot", "integrations", "PNG of the desktop window, base64 (token or local MCP bridge)")
+            .returns(object(&[("mime_type", ty("string")), ("data", ty("string"))], &["data"])),
+        ep("post", "/api/mcp", "integrations", "MCP over HTTP: a JSON-RPC message (Mcp-Session-Id header after initialize)")
+            .returns(ty("object")),
+        ep("get", "/api/mcp", "integrations", "Server-sent events: an MCP session's notifications"),
+        ep("delete", "/api/mcp", "integrations", "End an MCP session"),
+        // WebSockets
+        ep("get", "/api/ws/{session_id}", "websocket", "WebSocket: one session's output and input")
+            .query("token", "string", false),
+        ep("get", "/api/ws/status", "websocket", "WebSocket: session status events")
+            .query("token", "string", false)
+            .query("events", "string", false)
+            .query("sessions", "string", false)
+            .query("folder", "string", false),
+        ep("get", "/api/ws/mobile", "websocket", "WebSocket: multiplexed mobile protocol (auth in first message)").public(),
+        ep("get", "/share/{token}", "websocket", "Guest share link: viewer page, or WebSocket for one session (single use)")
+            .public(),
+    ]
+}
+
+fn components() -> Value {
+    json!({
+        "securitySchemes": {
+            "bearer": { "type": "http", "scheme": "bearer", "description": "Token from pairing or PIN login" }
+        },
+        "schemas": {
+            "Error": object(&[("error", ty("string")), ("message", ty("string"))], &["error"]),
+            "Status": object(&[("status", ty("string"))], &[]),
+            "Session": object(
+                &[
+                    ("id", ty("string")),
+                    ("name", ty("string")),
+                    ("agent_type", ty("string")),
+                    ("command", ty("string")),
+                    ("working_dir", ty("string")),
+                    ("created_at", ty("string")),
+                    ("sort_order", ty("integer")),
+                    ("folder_id", nullable("string")),
+                    ("parent_id", nullable("string")),
+                    ("workspace_id", nullable("string")),
+                    ("summary", nullable("string")),
+                    ("running", ty("boolean")),
+                    ("processing", ty("boolean")),
+                    ("hub_id", nullable("string")),
+                    ("host", nullable("string")),
+                ],
+                &["id", "name", "agent_type"],
+            ),
+            "Folder": object(
+                &[
+                    ("id", ty("string")),
+                    ("name", ty("string")),
+                    ("sort_order", ty("integer")),
+                    ("collapsed", ty("boolean")),
+                    ("workspace_id", nullable("string")),
+                ],
+                &["id", "name"],
+            ),
+            "Workspace": object(
+                &[
+                    ("id", ty("string")),
+                    ("name", ty("string")),
+                    ("default_working_dir", nullable("string")),
+                    ("env_vars", nullable("string")),
+                    ("budget_usd", nullable("number")),
+                    ("remote_access", ty("boolean")),
+                    ("sort_order", ty("integer")),
+                    ("spent_usd", ty("number")),
+                ],
+                &["id", "name"],
+            ),
+            "Schedule": object(
+                &[
+                    ("id", ty("string")),
+                    ("name", ty("string")),
+                    ("cron_expr", ty("string")),
+                    ("prompt", ty("string")),
+                    ("enabled", ty("boolean")),
+                    ("last_run_at", nullable("string")),
+                    ("next_run_at", ty("string")),
+                    ("created_at", ty("string")),
+                ],
+                &["id", "name", "cron_expr", "prompt"],
+            ),
+            "Run": object(
+                &[
+                    ("id", ty("string")),
+                    ("prompt", ty("string")),
+                    ("working_dir", ty("string")),
+                    ("status", json!({ "type": "string", "enum": ["running", "completed", "error", "timeout"] })),
+                    ("result", nullable("string")),
+                    ("cost_usd", nullable("number")),
+                    ("input_tokens", nullable("integer")),
+                    ("output_tokens", nullable("integer")),
+                    ("duration_ms", nullable("number")),
+                    ("claude_session_id", nullable("string")),
+                    ("session_id", nullable("string")),
+                    ("created_at", ty("string")),
+                    ("finished_at", nullable("string")),
+                ],
+                &["id", "status"],
+            ),
+        }
+    })
+}
+
+fn operation(endpoint: &Endpoint) -> Value {
+    let mut parameters: Vec<Value> = endpoint
+        .path
+        .split('/')
+        .filter_map(|seg| seg.strip_prefix('{')?.strip_suffix('}'))
+        .map(|name| json!({ "name": name, "in": "path", "required": true, "schema": ty("string") }))
+        .collect();
+    parameters.extend(
+        endpoint
+            .query
+            .iter()
+            .map(|(name, t, required)| json!({ "name": name, "in": "query", "required": required, "schema": ty(t) })),
+    );
+
+    let mut op = json!({
+        "tags": [endpoint.tag],
+        "summary": endpoint.summary,
+        "responses": {
+            "200": { "description": "OK", "content": { "application/json": { "schema": endpoint.response } } },
+            "400": { "description": "Invalid request", "content": { "application/json": { "schema": schema_ref("Error") } } },
+            "401": { "description": "Missing or invalid token", "content": { "application/json": { "schema": schema_ref("Error") } } },
+            "404": { "description": "Not found", "content": { "application/json": { "schema": schema_ref("Error") } } },
+        },
+    });
+    if !parameters.is_empty() {
+        op["parameters"] = json!(parameters);
+    }
+    if let Some(body) = &endpoint.body {
+        let content_type = if body.get("format").and_then(|f| f.as_str()) == Some("binary") {
+            "application/octet-stream"
+        } else {
+            "application/json"
+        };
+        op["requestBody"] = json!({ "required": true, "content": { content_type: { "schema": body } } });
+    }
+    if endpoint.public {
+        op["security"] = json!([]);
+    }
+    op
+}
+
+/// The complete OpenAPI document.
+pub fn spec() -> Value {
+    let mut paths = Map::new();
+    for endpoint in endpoints() {
+        let item = paths.entry(endpoint.path.to_string()).or_insert_with(|| json!({}));
+        item[endpoint.method] = operation(&endpoint);
+    }
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "Agent Hub API",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "Control Agent Hub sessions remotely. Pair a device (or log in with the remote PIN) and send the token as `Authorization: Bearer <token>`. Every `/api/...` path is also available under `/api/v1/...`.",
+        },
+        "security": [{ "bearer": [] }],
+        "paths": paths,
+        "components": components(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_route_is_documented() {
+        let spec = spec();
+        let source = include_str!("lib.rs");
+        let route = regex::Regex::new(r#"\.route\(\s*"([^"]+)""#).unwrap();
+        for cap in route.captures_iter(source) {
+            let path = &cap[1];
+            if path == "/" {
+                continue;
+            }
+            // Routes of the versioned API router are relative to /api
+            let path = if path == "/metrics" || path.starts_with("/share/") {
+                path.to_string()
+            } else {
+                format!("/api{}", path)
+            };
+            let openapi_path: Vec<String> = path
+                .split('/')
+                .map(|seg| match seg.strip_prefix(':').or_else(|| seg.strip_prefix('*')) {
+                    Some(name) => format!("{{{}}}", name),
+                    None => seg.to_string(),
+                })
+                .collect();
+            let openapi_path = openapi_path.join("/");
+            assert!(spec["paths"].get(&openapi_path).is_some(), "{} is not in the OpenAPI spec", path);
+        }
+    }
+
+    #[test]
+    fn path_parameters_are_declared() {
+        let spec = spec();
+        let op = &spec["paths"]["/api/sessions/{session_id}/input"]["post"];
+        assert_eq!(op["parameters"][0]["name"], "session_id");
+        assert_eq!(op["parameters"][0]["in"], "path");
+        assert!(spec["paths"]["/api/health"]["get"]["security"].as_array().unwrap().is_empty());
+    }
+}
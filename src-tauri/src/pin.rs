@@ -0,0 +1,91 @@
+// Remote access PIN: strength rules and salted hashing.
+//
+// The secret store (secrets::REMOTE_PIN) holds an Argon2id PHC string
+// (`$argon2id$v=19$m=...,t=...,p=...$<salt>$<hash>`). Earlier versions kept
+// the PIN in the clear in config.json; it is hashed and moved the first time
+// settings are loaded.
+
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+
+const PREFIX: &str = "$argon2id$";
+const SALT_LEN: usize = 16;
+const MIN_LEN: usize = 6;
+
+/// Whether `stored` is a hash rather than a plain PIN.
+pub fn is_hashed(stored: &str) -> bool {
+    stored.starts_with(PREFIX)
+}
+
+pub fn hash(pin: &str) -> Result<String, String> {
+    let mut salt = [0u8; SALT_LEN];
+    getrandom::getrandom(&mut salt).map_err(|e| format!("Random number generator unavailable: {}", e))?;
+    let salt = SaltString::encode_b64(&salt).map_err(|e| format!("Failed to encode salt: {}", e))?;
+    // Argon2id with the crate's (OWASP-recommended) default cost
+    Argon2::default()
+        .hash_password(pin.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| format!("Failed to hash PIN: {}", e))
+}
+
+/// Check `pin` against a stored hash. Anything else never matches.
+pub fn verify(pin: &str, stored: &str) -> bool {
+    // Constant-time comparison happens inside argon2
+    is_hashed(stored)
+        && PasswordHash::new(stored)
+            .is_ok_and(|hash| Argon2::default().verify_password(pin.as_bytes(), &hash).is_ok())
+}
+
+/// Reject PINs that are short or trivially guessable.
+pub fn validate(pin: &str) -> Result<(), String> {
+    let chars: Vec<char> = pin.chars().collect();
+    if chars.len() < MIN_LEN {
+        return Err(format!("PIN must be at least {} characters", MIN_LEN));
+    }
+    let mut distinct = chars.clone();
+    distinct.sort_unstable();
+    distinct.dedup();
+    if distinct.len() < 3 {
+        return Err("PIN must use at least 3 different characters".to_string());
+    }
+    // 123456, 987654, abcdef, ...
+    let steps: Vec<i64> = chars.windows(2).map(|w| w[1] as i64 - w[0] as i64).collect();
+    if steps.iter().all(|&s| s == 1) || steps.iter().all(|&s| s == -1) {
+        return Err("PIN can't be a simple sequence".to_string());
+    }
+    // 121212, 123123, ...
+    let repeats_short_pattern = (1..=chars.len() / 2)
+        .any(|p| chars.len().is_multiple_of(p) && chars.chunks(p).all(|c| c == &chars[..p]));
+    if repeats_short_pattern {
+        return Err("PIN can't be a repeated pattern".to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash_round_trips_and_is_salted() {
+        let a = hash("480913").unwrap();
+        let b = hash("480913").unwrap();
+        assert!(a.starts_with("$argon2id$") && is_hashed(&a));
+        assert_ne!(a, b);
+        assert!(verify("480913", &a));
+        assert!(!verify("480914", &a));
+        // A plain PIN is never accepted as its own hash
+        assert!(!is_hashed("480913"));
+        assert!(!verify("480913", "480913"));
+    }
+
+    #[test]
+    fn rejects_weak_pins() {
+        for weak in ["1234", "111111", "112112", "123456", "987654", "123123", "abcdef"] {
+            assert!(validate(weak).is_err(), "{} should be rejected", weak);
+        }
+        for ok in ["480913", "7391-25", "correct horse"] {
+            assert!(validate(ok).is_ok(), "{} should be accepted", ok);
+        }
+    }
+}
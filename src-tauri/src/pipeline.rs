@@ -0,0 +1,367 @@
+// Agent pipelines: chain the output of one claude-json session into another.
+//
+// A pipeline row says "when session A finishes a turn, render
+// `prompt_template` with A's result text and send it to session B". Rows
+// live in `pipelines`; execution happens on a single coordinator thread fed
+// by the stdout reader in spawn_json_process, so slow work (starting the
+// target session) never blocks output streaming.
+//
+// Chains (A → B → C) fall out naturally because B's own result re-enters the
+// coordinator. A hop counter carried across the chain stops cycles
+// (A → B → A ...) after MAX_CHAIN_DEPTH hops.
+
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::sync::mpsc;
+use tauri::Emitter;
+
+const MAX_CHAIN_DEPTH: u32 = 8;
+
+/// Placeholders understood by `render_template`.
+const RESULT_PLACEHOLDER: &str = "{{result}}";
+const SOURCE_NAME_PLACEHOLDER: &str = "{{source_name}}";
+
+pub(crate) struct TurnComplete {
+    session_id: String,
+    result: Option<String>,
+    is_error: bool,
+}
+
+// =====================================================================
+//  Schema
+// =====================================================================
+
+pub fn run_pipeline_migrations(conn: &Connection) {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS pipelines (
+            id                TEXT PRIMARY KEY,
+            name              TEXT NOT NULL,
+            source_session_id TEXT NOT NULL,
+            target_session_id TEXT NOT NULL,
+            prompt_template   TEXT NOT NULL,
+            enabled           INTEGER NOT NULL DEFAULT 1,
+            created_at        TEXT NOT NULL,
+            last_run_at       TEXT,
+            last_status       TEXT,
+            last_error        TEXT
+        )",
+        [],
+    )
+    .expect("Failed to create pipelines table");
+}
+
+/// Remove pipelines that read from or write to a deleted session.
+pub fn delete_pipelines_for_session(conn: &Connection, session_id: &str) {
+    let _ = conn.execute(
+        "DELETE FROM pipelines WHERE source_session_id = ?1 OR target_session_id = ?1",
+        params![session_id],
+    );
+    crate::state::get().pipeline_runs.lock().remove(session_id);
+}
+
+// =====================================================================
+//  CRUD
+// =====================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Pipeline {
+    pub id: String,
+    pub name: String,
+    pub source_session_id: String,
+    pub target_session_id: String,
+    /// Prompt sent to the target. `{{result}}` is replaced with the source
+    /// turn's result text, `{{source_name}}` with the source session name.
+    pub prompt_template: String,
+    pub enabled: bool,
+    pub created_at: String,
+    pub last_run_at: Option<String>,
+    /// "running", "completed" or "failed" once the pipeline has fired.
+    pub last_status: Option<String>,
+    pub last_error: Option<String>,
+}
+
+const PIPELINE_COLUMNS: &str = "id, name, source_session_id, target_session_id, prompt_template, enabled, created_at, last_run_at, last_status, last_error";
+
+fn row_to_pipeline(row: &rusqlite::Row) -> rusqlite::Result<Pipeline> {
+    Ok(Pipeline {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        source_session_id: row.get(2)?,
+        target_session_id: row.get(3)?,
+        prompt_template: row.get(4)?,
+        enabled: row.get::<_, i64>(5)? != 0,
+        created_at: row.get(6)?,
+        last_run_at: row.get(7)?,
+        last_status: row.get(8)?,
+        last_error: row.get(9)?,
+    })
+}
+
+pub fn list_pipelines() -> Result<Vec<Pipeline>, String> {
+    let conn = crate::state::get().db().lock();
+    let mut stmt = conn
+        .prepare(&format!("SELECT {} FROM pipelines ORDER BY created_at ASC", PIPELINE_COLUMNS))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], row_to_pipeline)
+        .map_err(|e| e.to_string())?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(rows)
+}
+
+fn get_pipeline(id: &str) -> Result<Pipeline, String> {
+    let conn = crate::state::get().db().lock();
+    conn.query_row(
+        &format!("SELECT {} FROM pipelines WHERE id = ?1", PIPELINE_COLUMNS),
+        params![id],
+        row_to_pipeline,
+    )
+    .map_err(|_| "Pipeline not found".to_string())
+}
+
+fn save_pipeline(p: &Pipeline) -> Result<(), String> {
+    let conn = crate::state::get().db().lock();
+    conn.execute(
+        &format!(
+            "INSERT OR REPLACE INTO pipelines ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            PIPELINE_COLUMNS
+        ),
+        params![
+            p.id,
+            p.name,
+            p.source_session_id,
+            p.target_session_id,
+            p.prompt_template,
+            p.enabled as i64,
+            p.created_at,
+            p.last_run_at,
+            p.last_status,
+            p.last_error,
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn require_json_session(session_id: &str) -> Result<(), String> {
+    let conn = crate::state::get().db().lock();
+    let agent_type: String = conn
+        .query_row("SELECT agent_type FROM sessions WHERE id = ?1", params![session_id], |r| r.get(0))
+        .map_err(|_| format!("Session not found: {}", session_id))?;
+    if agent_type != "claude-json" {
+        return Err(format!("Session {} is not a claude-json session", session_id));
+    }
+    Ok(())
+}
+
+pub fn create_pipeline(
+    name: String,
+    source_session_id: String,
+    target_session_id: String,
+    prompt_template: String,
+) -> Result<Pipeline, String> {
+    if source_session_id == target_session_id {
+        return Err("Source and target must be different sessions".to_string());
+    }
+    require_json_session(&source_session_id)?;
+    require_json_session(&target_session_id)?;
+    let pipeline = Pipeline {
+        id: uuid::Uuid::new_v4().to_string(),
+        name,
+        source_session_id,
+        target_session_id,
+        prompt_template,
+        enabled: true,
+        created_at: chrono::Utc::now().to_rfc3339(),
+        last_run_at: None,
+        last_status: None,
+        last_error: None,
+    };
+    save_pipeline(&pipeline)?;
+    Ok(pipeline)
+}
+
+pub fn update_pipeline(
+    id: &str,
+    name: Option<String>,
+    prompt_template: Option<String>,
+    enabled: Option<bool>,
+) -> Result<Pipeline, String> {
+    let mut pipeline = get_pipeline(id)?;
+    if let Some(n) = name { pipeline.name = n; }
+    if let Some(t) = prompt_template { pipeline.prompt_template = t; }
+    if let Some(e) = enabled { pipeline.enabled = e; }
+    save_pipeline(&pipeline)?;
+    Ok(pipeline)
+}
+
+pub fn delete_pipeline(id: &str) -> Result<(), String> {
+    let conn = crate::state::get().db().lock();
+    conn.execute("DELETE FROM pipelines WHERE id = ?1", params![id])
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// =====================================================================
+//  Coordinator
+// =====================================================================
+
+/// Substitute the source turn into a pipeline's prompt template. A template
+/// without `{{result}}` gets the result appended so it is never dropped.
+pub fn render_template(template: &str, result: &str, source_name: &str) -> String {
+    let rendered = template.replace(SOURCE_NAME_PLACEHOLDER, source_name);
+    if rendered.contains(RESULT_PLACEHOLDER) {
+        rendered.replace(RESULT_PLACEHOLDER, result)
+    } else if rendered.trim().is_empty() {
+        result.to_string()
+    } else {
+        format!("{}\n\n{}", rendered, result)
+    }
+}
+
+/// Start the coordinator thread. Call once at startup.
+pub fn start_coordinator() {
+    let (tx, rx) = mpsc::channel::<TurnComplete>();
+    *crate::state::get().pipeline_coordinator.lock() = Some(tx);
+    std::thread::spawn(move || {
+        while let Ok(event) = rx.recv() {
+            handle_turn_complete(event);
+        }
+    });
+}
+
+/// Called from the stdout reader when a `result` message arrives.
+pub fn on_turn_complete(session_id: &str, result: Option<String>, is_error: bool) {
+    if let Some(tx) = crate::state::get().pipeline_coordinator.lock().as_ref() {
+        let _ = tx.send(TurnComplete {
+            session_id: session_id.to_string(),
+            result,
+            is_error,
+        });
+    }
+}
+
+fn handle_turn_complete(event: TurnComplete) {
+    // Finish the run that targeted this session, if any
+    let depth = match crate::state::get().pipeline_runs.lock().remove(&event.session_id) {
+        Some((pipeline_id, depth)) => {
+            if event.is_error {
+                record_status(&pipeline_id, "failed", Some("Target turn ended with an error"));
+            } else {
+                record_status(&pipeline_id, "completed", None);
+            }
+            depth
+        }
+        None => 0,
+    };
+
+    if event.is_error {
+        return;
+    }
+    let Some(result) = event.result.filter(|r| !r.trim().is_empty()) else {
+        return;
+    };
+
+    let pipelines: Vec<Pipeline> = list_pipelines()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|p| p.enabled && p.source_session_id == event.session_id)
+        .collect();
+    if pipelines.is_empty() {
+        return;
+    }
+    if depth >= MAX_CHAIN_DEPTH {
+        tracing::warn!(
+            "chain from {} reached {} hops, not continuing",
+            event.session_id, MAX_CHAIN_DEPTH
+        );
+        for p in &pipelines {
+            record_status(&p.id, "failed", Some("Maximum chain depth reached"));
+        }
+        return;
+    }
+
+    let source_name = session_name(&event.session_id).unwrap_or_default();
+    for pipeline in pipelines {
+        let prompt = render_template(&pipeline.prompt_template, &result, &source_name);
+        match run_pipeline(&pipeline, &prompt, depth + 1) {
+            Ok(()) => record_status(&pipeline.id, "running", None),
+            Err(e) => {
+                tracing::error!("'{}' failed: {}", pipeline.name, e);
+                record_status(&pipeline.id, "failed", Some(&e));
+            }
+        }
+    }
+}
+
+fn run_pipeline(pipeline: &Pipeline, prompt: &str, depth: u32) -> Result<(), String> {
+    crate::ensure_json_session_running(&pipeline.target_session_id)?;
+    crate::state::get().pipeline_runs
+        .lock()
+        .insert(pipeline.target_session_id.clone(), (pipeline.id.clone(), depth));
+    if let Err(e) = crate::send_user_prompt(&pipeline.target_session_id, prompt) {
+        crate::state::get().pipeline_runs.lock().remove(&pipeline.target_session_id);
+        return Err(e);
+    }
+    Ok(())
+}
+
+fn session_name(session_id: &str) -> Option<String> {
+    let conn = crate::state::get().db().lock();
+    conn.query_row("SELECT name FROM sessions WHERE id = ?1", params![session_id], |r| r.get(0))
+        .ok()
+}
+
+/// Persist the latest status and notify the desktop UI and WebSocket listeners.
+fn record_status(pipeline_id: &str, status: &str, error: Option<&str>) {
+    {
+        let conn = crate::state::get().db().lock();
+        let _ = conn.execute(
+            "UPDATE pipelines SET last_status = ?1, last_error = ?2,
+                last_run_at = CASE WHEN ?1 = 'running' THEN ?3 ELSE last_run_at END
+             WHERE id = ?4",
+            params![status, error, chrono::Utc::now().to_rfc3339(), pipeline_id],
+        );
+    }
+    let Ok(pipeline) = get_pipeline(pipeline_id) else { return; };
+
+    let event = crate::events::SessionEvent::PipelineStatus {
+        pipeline_id: pipeline.id,
+        name: pipeline.name,
+        source_session_id: pipeline.source_session_id,
+        target_session_id: pipeline.target_session_id,
+        status: status.to_string(),
+        error: error.map(String::from),
+    };
+    if let Some(app) = crate::state::get().app_handle.lock().as_ref() {
+        let _ = app.emit("pipeline-status", event.data());
+    }
+    crate::broadcast_session_event(event);
+}
+
+// =====================================================================
+//  Tests
+// =====================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn substitutes_result_and_source_name() {
+        let out = render_template("From {{source_name}}:\n{{result}}\nImplement it.", "the plan", "Planner");
+        assert_eq!(out, "From Planner:\nthe plan\nImplement it.");
+    }
+
+    #[test]
+    fn appends_result_when_placeholder_missing() {
+        assert_eq!(render_template("Review this", "diff", "A"), "Review this\n\ndiff");
+        assert_eq!(render_template("  ", "diff", "A"), "diff");
+    }
+
+    #[test]
+    fn repeated_placeholders_all_replaced() {
+        assert_eq!(render_template("{{result}}/{{result}}", "x", "A"), "x/x");
+    }
+}
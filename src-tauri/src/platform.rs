@@ -0,0 +1,183 @@
+// What differs between macOS and Linux when spawning agents and laying out
+// files.
+//
+// An app started from Finder, the Dock, or a Linux desktop launcher gets a
+// minimal PATH rather than the user's shell PATH. Agents are spawned through
+// the user's login shell with agent_path(), which puts the usual tool install
+// locations first: Homebrew on macOS, and Linuxbrew, snap and ~/.local/bin on
+// Linux. Both platforms also get nvm, cargo, bun and npm's global prefix.
+// Ubuntu's ~/.bashrc (where nvm goes) does nothing in a
+// non-interactive shell, so the one-shot `-l -c` runs depend on this PATH
+// as well.
+//
+// App data goes in the platform's data directory: ~/Library/Application
+// Support/agent-hub on macOS, and $XDG_DATA_HOME/agent-hub (by default
+// ~/.local/share/agent-hub) on Linux.
+//
+// The pure functions take an Os, so the tests check both platforms wherever
+// they run.
+
+use std::path::{Path, PathBuf};
+
+/// Where new sessions start when settings and the workspace don't say
+pub const DEFAULT_WORKING_DIR: &str = "~";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Os {
+    MacOs,
+    /// Linux and the other Unixes
+    Linux,
+}
+
+impl Os {
+    pub fn current() -> Os {
+        if cfg!(target_os = "macos") {
+            Os::MacOs
+        } else {
+            Os::Linux
+        }
+    }
+}
+
+/// Each platform's default login shell
+fn default_shell(os: Os) -> &'static str {
+    match os {
+        Os::MacOs => "/bin/zsh",
+        Os::Linux => "/bin/bash",
+    }
+}
+
+/// The user's login shell: $SHELL, then the password database (desktop
+/// launchers don't always set SHELL), then the platform default
+pub fn user_shell() -> String {
+    std::env::var("SHELL")
+        .ok()
+        .filter(|shell| !shell.is_empty())
+        .or_else(passwd_shell)
+        .unwrap_or_else(|| default_shell(Os::current()).to_string())
+}
+
+#[cfg(not(target_os = "ios"))]
+fn passwd_shell() -> Option<String> {
+    // SAFETY: getpwuid returns null or a pointer to a static entry, which is
+    // copied out before anything else can call it again on this thread
+    unsafe {
+        let entry = libc::getpwuid(libc::getuid());
+        if entry.is_null() || (*entry).pw_shell.is_null() {
+            return None;
+        }
+        let shell = std::ffi::CStr::from_ptr((*entry).pw_shell).to_str().ok()?;
+        (!shell.is_empty()).then(|| shell.to_string())
+    }
+}
+
+#[cfg(target_os = "ios")]
+fn passwd_shell() -> Option<String> {
+    None
+}
+
+pub fn home_dir() -> String {
+    dirs::home_dir()
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_else(|| "/".to_string())
+}
+
+/// The app's data directory (not created here)
+#[cfg(not(test))]
+pub fn data_dir() -> PathBuf {
+    dirs::data_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join(crate::get_app_data_dir_name())
+}
+
+/// Tests get a scratch directory per run, so the database, config.json and
+/// secrets they touch are never the user's
+#[cfg(test)]
+pub fn data_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("agent-hub-test-{}", std::process::id()))
+}
+
+/// The newest installed nvm node, by version number rather than name
+fn newest_node(versions: &[String]) -> Option<&String> {
+    let number = |v: &String| -> Vec<u64> {
+        v.trim_start_matches('v').split('.').map(|part| part.parse().unwrap_or(0)).collect()
+    };
+    versions.iter().max_by_key(|v| number(v))
+}
+
+/// Directories CLIs are commonly installed into, most specific first
+fn tool_dirs(os: Os, home: &str, node_versions: &[String]) -> Vec<String> {
+    let mut dirs = vec![format!("{}/.local/bin", home)];
+    if let Some(node) = newest_node(node_versions) {
+        dirs.push(format!("{}/.nvm/versions/node/{}/bin", home, node));
+    }
+    dirs.extend([
+        format!("{}/.cargo/bin", home),
+        format!("{}/.bun/bin", home),
+        format!("{}/.npm-global/bin", home),
+    ]);
+    match os {
+        Os::MacOs => dirs.extend(["/opt/homebrew/bin", "/opt/homebrew/sbin", "/usr/local/bin"].map(String::from)),
+        Os::Linux => dirs.extend([
+            "/home/linuxbrew/.linuxbrew/bin".to_string(),
+            format!("{}/.linuxbrew/bin", home),
+            "/usr/local/bin".to_string(),
+            "/snap/bin".to_string(),
+        ]),
+    }
+    dirs
+}
+
+/// `dirs` ahead of `existing`, without repeating what's already in it
+fn prepend_path(dirs: Vec<String>, existing: &str) -> String {
+    let present: Vec<&str> = existing.split(':').filter(|d| !d.is_empty()).collect();
+    let mut parts: Vec<String> = Vec::new();
+    for dir in dirs {
+        if !present.contains(&dir.as_str()) && !parts.contains(&dir) {
+            parts.push(dir);
+        }
+    }
+    parts.extend(present.into_iter().map(String::from));
+    parts.join(":")
+}
+
+/// PATH for spawned agents and one-shot CLI runs
+pub fn agent_path() -> String {
+    let home = home_dir();
+    let node_versions: Vec<String> = std::fs::read_dir(Path::new(&home).join(".nvm/versions/node"))
+        .map(|entries| entries.filter_map(|e| e.ok()?.file_name().into_string().ok()).collect())
+        .unwrap_or_default();
+    prepend_path(tool_dirs(Os::current(), &home, &node_versions), &std::env::var("PATH").unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tool_dirs_follow_the_platform() {
+        let mac = tool_dirs(Os::MacOs, "/Users/me", &[]);
+        assert!(mac.contains(&"/opt/homebrew/bin".to_string()));
+        assert!(!mac.iter().any(|d| d.contains("linuxbrew") || d == "/snap/bin"));
+
+        let linux = tool_dirs(Os::Linux, "/home/me", &["v18.20.1".to_string(), "v24.10.0".to_string(), "v24.9.0".to_string()]);
+        assert!(linux.contains(&"/home/linuxbrew/.linuxbrew/bin".to_string()));
+        assert!(linux.contains(&"/snap/bin".to_string()));
+        assert!(!linux.iter().any(|d| d.contains("homebrew")));
+        assert_eq!(linux[0], "/home/me/.local/bin");
+        assert_eq!(linux[1], "/home/me/.nvm/versions/node/v24.10.0/bin");
+
+        assert_eq!(default_shell(Os::MacOs), "/bin/zsh");
+        assert_eq!(default_shell(Os::Linux), "/bin/bash");
+    }
+
+    #[test]
+    fn path_is_prepended_once() {
+        let dirs = vec!["/home/me/.local/bin".to_string(), "/usr/local/bin".to_string(), "/snap/bin".to_string()];
+        assert_eq!(
+            prepend_path(dirs.clone(), "/usr/local/bin:/usr/bin:/bin"),
+            "/home/me/.local/bin:/snap/bin:/usr/local/bin:/usr/bin:/bin"
+        );
+        assert_eq!(prepend_path(dirs, ""), "/home/me/.local/bin:/usr/local/bin:/snap/bin");
+    }
+}
@@ -0,0 +1,227 @@
+// Who has each session open: mobile clients subscribed to its chat or
+// attached to its terminal, and session WebSockets (/api/ws/:session_id).
+//
+// Every change goes to all mobile clients as a `presence` message and to the
+// desktop as a session-presence event, both with the viewer count and who
+// the viewers are. remote-client-disconnected, which tells the desktop it can
+// size a terminal to its own window again, is only emitted once the last
+// viewer that could resize the session has left it.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use tauri::Emitter;
+
+/// How a viewer has the session open
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum View {
+    /// Mobile chat subscription
+    Chat,
+    /// Mobile terminal attachment
+    Terminal,
+    /// Session WebSocket
+    Stream,
+}
+
+impl View {
+    /// Terminal and stream viewers send their window size to the session
+    fn resizes(self) -> bool {
+        self != View::Chat
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Viewer {
+    pub device_id: Option<String>,
+    pub device_name: Option<String>,
+    pub view: View,
+    /// The WebSocket connection; one connection can hold several views
+    #[serde(skip)]
+    connection: String,
+}
+
+#[derive(Default)]
+pub(crate) struct Presence {
+    sessions: HashMap<String, Vec<Viewer>>,
+}
+
+impl Presence {
+    /// False if the connection already had the session open this way
+    fn join(&mut self, session_id: &str, viewer: Viewer) -> bool {
+        let viewers = self.sessions.entry(session_id.to_string()).or_default();
+        if viewers.iter().any(|v| v.connection == viewer.connection && v.view == viewer.view) {
+            return false;
+        }
+        viewers.push(viewer);
+        true
+    }
+
+    fn leave(&mut self, session_id: &str, connection: &str, view: View) -> Option<Viewer> {
+        let viewers = self.sessions.get_mut(session_id)?;
+        let index = viewers.iter().position(|v| v.connection == connection && v.view == view)?;
+        let viewer = viewers.remove(index);
+        if viewers.is_empty() {
+            self.sessions.remove(session_id);
+        }
+        Some(viewer)
+    }
+
+    /// Everything the connection had open, as (session id, view)
+    fn opened_by(&self, connection: &str) -> Vec<(String, View)> {
+        self.sessions
+            .iter()
+            .flat_map(|(session_id, viewers)| {
+                viewers.iter().filter(|v| v.connection == connection).map(move |v| (session_id.clone(), v.view))
+            })
+            .collect()
+    }
+
+    fn viewers(&self, session_id: &str) -> &[Viewer] {
+        self.sessions.get(session_id).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Distinct connections with the session open
+    fn count(&self, session_id: &str) -> usize {
+        let mut connections: Vec<&str> = self.viewers(session_id).iter().map(|v| v.connection.as_str()).collect();
+        connections.sort_unstable();
+        connections.dedup();
+        connections.len()
+    }
+
+    fn message(&self, session_id: &str) -> serde_json::Value {
+        serde_json::json!({
+            "type": "presence",
+            "sessionId": session_id,
+            "viewerCount": self.count(session_id),
+            "viewers": self.viewers(session_id),
+        })
+    }
+}
+
+/// Tell mobile clients and the desktop who's watching the session now
+fn announce(session_id: &str, presence: &Presence, resize_released: bool) {
+    crate::broadcast_to_mobile_clients(&presence.message(session_id).to_string());
+    if let Some(app) = crate::state::get().app_handle.lock().as_ref() {
+        let _ = app.emit(
+            "session-presence",
+            serde_json::json!({
+                "session_id": session_id,
+                "viewer_count": presence.count(session_id),
+                "viewers": presence.viewers(session_id),
+            }),
+        );
+        if resize_released {
+            let _ = app.emit("remote-client-disconnected", session_id);
+        }
+    }
+}
+
+pub fn join(session_id: &str, connection: &str, view: View, device: Option<&crate::PairedDevice>) {
+    let mut presence = crate::state::get().presence.lock();
+    let viewer = Viewer {
+        device_id: device.map(|d| d.id.clone()),
+        device_name: device.map(|d| d.name.clone()),
+        view,
+        connection: connection.to_string(),
+    };
+    if presence.join(session_id, viewer) {
+        announce(session_id, &presence, false);
+    }
+}
+
+pub fn leave(session_id: &str, connection: &str, view: View) {
+    let mut presence = crate::state::get().presence.lock();
+    if let Some(viewer) = presence.leave(session_id, connection, view) {
+        let released = viewer.view.resizes() && !presence.viewers(session_id).iter().any(|v| v.view.resizes());
+        announce(session_id, &presence, released);
+    }
+}
+
+/// Joins for as long as it's held, for connections that only ever have
+/// the one session open
+pub struct Watching {
+    session_id: String,
+    connection: String,
+    view: View,
+}
+
+pub fn watch(session_id: &str, view: View, device: Option<&crate::PairedDevice>) -> Watching {
+    let connection = crate::generate_token();
+    join(session_id, &connection, view, device);
+    Watching { session_id: session_id.to_string(), connection, view }
+}
+
+impl Drop for Watching {
+    fn drop(&mut self) {
+        leave(&self.session_id, &self.connection, self.view);
+    }
+}
+
+/// A connection closed: it no longer has anything open
+pub fn leave_all(connection: &str) {
+    let opened = crate::state::get().presence.lock().opened_by(connection);
+    for (session_id, view) in opened {
+        leave(&session_id, connection, view);
+    }
+}
+
+/// Remote access to the session was turned off: nobody is watching it
+pub fn clear(session_id: &str) {
+    let mut presence = crate::state::get().presence.lock();
+    let Some(viewers) = presence.sessions.remove(session_id) else { return };
+    announce(session_id, &presence, viewers.iter().any(|v| v.view.resizes()));
+}
+
+pub fn viewers(session_id: &str) -> Vec<Viewer> {
+    crate::state::get().presence.lock().viewers(session_id).to_vec()
+}
+
+/// A presence message for every session that has viewers, for a client
+/// that has just connected
+pub fn snapshot() -> Vec<serde_json::Value> {
+    let presence = crate::state::get().presence.lock();
+    presence.sessions.keys().map(|session_id| presence.message(session_id)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn viewer(connection: &str, view: View) -> Viewer {
+        Viewer { device_id: None, device_name: Some("Phone".to_string()), view, connection: connection.to_string() }
+    }
+
+    #[test]
+    fn viewers_are_counted_per_connection() {
+        let mut presence = Presence::default();
+        assert!(presence.join("s1", viewer("c1", View::Chat)));
+        assert!(!presence.join("s1", viewer("c1", View::Chat)));
+        assert!(presence.join("s1", viewer("c1", View::Terminal)));
+        assert!(presence.join("s1", viewer("c2", View::Stream)));
+        assert!(presence.join("s2", viewer("c1", View::Chat)));
+        assert_eq!(presence.count("s1"), 2);
+        assert_eq!(presence.viewers("s1").len(), 3);
+
+        let message = presence.message("s1");
+        assert_eq!(message["viewerCount"], 2);
+        assert_eq!(message["viewers"][1]["view"], "terminal");
+        assert!(message["viewers"][0].get("connection").is_none());
+
+        let mut opened = presence.opened_by("c1");
+        opened.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(opened, vec![("s1".to_string(), View::Chat), ("s1".to_string(), View::Terminal), ("s2".to_string(), View::Chat)]);
+    }
+
+    #[test]
+    fn leaving_removes_only_that_view() {
+        let mut presence = Presence::default();
+        presence.join("s1", viewer("c1", View::Chat));
+        presence.join("s1", viewer("c1", View::Terminal));
+        assert!(presence.leave("s1", "c2", View::Chat).is_none());
+        assert_eq!(presence.leave("s1", "c1", View::Terminal).unwrap().view, View::Terminal);
+        assert_eq!(presence.count("s1"), 1);
+        presence.leave("s1", "c1", View::Chat);
+        assert_eq!(presence.count("s1"), 0);
+        assert!(presence.sessions.is_empty());
+    }
+}
@@ -0,0 +1,113 @@
+// Named settings profiles (e.g. "work", "home").
+//
+// config.json always holds the live settings, whose `profile` field names
+// the active profile. Each profile is a full copy of the settings in
+// profiles/<name>.json next to it. Switching saves the live settings under
+// the current profile's name, then makes the target's copy live; a profile
+// that doesn't exist yet starts as a copy of the current one. The change is
+// applied to the running app by watch_settings like any other save. The
+// remote PIN lives in the secret store and is shared by all profiles.
+
+use serde::Serialize;
+use std::path::PathBuf;
+
+#[derive(Debug, Serialize)]
+pub struct Profiles {
+    pub active: String,
+    pub profiles: Vec<String>,
+}
+
+fn profiles_dir() -> PathBuf {
+    let dir = crate::get_config_path().with_file_name("profiles");
+    std::fs::create_dir_all(&dir).ok();
+    dir
+}
+
+fn profile_path(name: &str) -> PathBuf {
+    profiles_dir().join(format!("{}.json", name))
+}
+
+pub fn validate_name(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty()
+        && name.len() <= 40
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("'{}' isn't a valid profile name (letters, digits, - and _)", name))
+    }
+}
+
+/// The active profile and every saved one, sorted
+pub fn list() -> Result<Profiles, String> {
+    let active = crate::load_app_settings()?.profile;
+    let mut profiles: Vec<String> = std::fs::read_dir(profiles_dir())
+        .map_err(|e| e.to_string())?
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let name = path.file_stem()?.to_str()?.to_string();
+            (path.extension()? == "json" && validate_name(&name).is_ok()).then_some(name)
+        })
+        .collect();
+    if !profiles.contains(&active) {
+        profiles.push(active.clone());
+    }
+    profiles.sort();
+    Ok(Profiles { active, profiles })
+}
+
+fn write_profile(settings: &crate::AppSettings) -> Result<(), String> {
+    let on_disk = crate::AppSettings { remote_pin: None, ..settings.clone() };
+    let json = serde_json::to_string_pretty(&on_disk).map_err(|e| format!("Failed to serialize settings: {}", e))?;
+    std::fs::write(profile_path(&settings.profile), json).map_err(|e| format!("Failed to write profile: {}", e))
+}
+
+/// Make `name` the live settings, keeping the current ones under their name
+pub fn switch(name: &str) -> Result<(), String> {
+    validate_name(name)?;
+    let current = crate::load_app_settings()?;
+    if current.profile == name {
+        return Ok(());
+    }
+    let path = profile_path(name);
+    let mut target = if path.exists() {
+        let json = std::fs::read_to_string(&path).map_err(|e| format!("Failed to read profile: {}", e))?;
+        serde_json::from_str::<crate::AppSettings>(&json).map_err(|e| format!("Failed to parse profile: {}", e))?
+    } else {
+        current.clone()
+    };
+    target.profile = name.to_string();
+    crate::validate_app_settings(&target)?;
+    write_profile(&current)?;
+    crate::write_app_settings(&target)?;
+    // Written now too, so a new profile is listed right away
+    write_profile(&target)
+}
+
+/// Remove a saved profile; the active one can't be deleted
+pub fn delete(name: &str) -> Result<(), String> {
+    validate_name(name)?;
+    if crate::load_app_settings()?.profile == name {
+        return Err("Switch to another profile before deleting this one".to_string());
+    }
+    let path = profile_path(name);
+    if !path.exists() {
+        return Err(format!("No profile named '{}'", name));
+    }
+    std::fs::remove_file(path).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profile_names_are_plain_file_stems() {
+        assert!(validate_name("work").is_ok());
+        assert!(validate_name("client_a-2").is_ok());
+        assert!(validate_name("").is_err());
+        assert!(validate_name("../config").is_err());
+        assert!(validate_name("home office").is_err());
+        assert!(validate_name(&"x".repeat(41)).is_err());
+    }
+}
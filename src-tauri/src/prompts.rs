@@ -0,0 +1,172 @@
+// Saved prompt templates. A template's text may contain `{{argument}}`
+// placeholders; its arguments are the placeholders it uses, in order of first
+// appearance. Templates are edited from the desktop and offered to MCP
+// clients as prompts (see mcp.rs), keyed by their name.
+
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// =====================================================================
+//  Schema
+// =====================================================================
+
+pub fn run_prompt_migrations(conn: &Connection) {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS prompt_templates (
+            id          TEXT PRIMARY KEY,
+            name        TEXT NOT NULL UNIQUE,
+            description TEXT,
+            template    TEXT NOT NULL,
+            created_at  TEXT NOT NULL
+        )",
+        [],
+    )
+    .expect("Failed to create prompt_templates table");
+}
+
+// =====================================================================
+//  CRUD
+// =====================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptTemplate {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub template: String,
+    pub created_at: String,
+}
+
+const PROMPT_COLUMNS: &str = "id, name, description, template, created_at";
+
+fn row_to_prompt(row: &rusqlite::Row) -> rusqlite::Result<PromptTemplate> {
+    Ok(PromptTemplate {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        description: row.get(2)?,
+        template: row.get(3)?,
+        created_at: row.get(4)?,
+    })
+}
+
+pub fn list_prompts() -> Result<Vec<PromptTemplate>, String> {
+    let conn = crate::state::get().db().lock();
+    let mut stmt = conn
+        .prepare(&format!("SELECT {} FROM prompt_templates ORDER BY name ASC", PROMPT_COLUMNS))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], row_to_prompt)
+        .map_err(|e| e.to_string())?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(rows)
+}
+
+pub fn get_prompt_by_name(name: &str) -> Result<PromptTemplate, String> {
+    let conn = crate::state::get().db().lock();
+    conn.query_row(
+        &format!("SELECT {} FROM prompt_templates WHERE name = ?1", PROMPT_COLUMNS),
+        params![name],
+        row_to_prompt,
+    )
+    .map_err(|_| format!("Prompt not found: {}", name))
+}
+
+/// Create a template, or replace the one with `id`
+pub fn save_prompt(
+    id: Option<String>,
+    name: String,
+    description: Option<String>,
+    template: String,
+) -> Result<PromptTemplate, String> {
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err("Prompt name is required".to_string());
+    }
+    if template.trim().is_empty() {
+        return Err("Prompt template is empty".to_string());
+    }
+    let conn = crate::state::get().db().lock();
+    let created_at = id
+        .as_ref()
+        .and_then(|id| {
+            conn.query_row("SELECT created_at FROM prompt_templates WHERE id = ?1", params![id], |r| r.get(0))
+                .ok()
+        })
+        .unwrap_or_else(|| chrono::Utc::now().to_rfc3339());
+    let prompt = PromptTemplate {
+        id: id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string()),
+        name,
+        description: description.filter(|d| !d.trim().is_empty()),
+        template,
+        created_at,
+    };
+    conn.execute(
+        &format!("INSERT OR REPLACE INTO prompt_templates ({}) VALUES (?1, ?2, ?3, ?4, ?5)", PROMPT_COLUMNS),
+        params![prompt.id, prompt.name, prompt.description, prompt.template, prompt.created_at],
+    )
+    .map_err(|e| match e {
+        rusqlite::Error::SqliteFailure(f, _) if f.code == rusqlite::ErrorCode::ConstraintViolation => {
+            format!("A prompt named '{}' already exists", prompt.name)
+        }
+        e => e.to_string(),
+    })?;
+    Ok(prompt)
+}
+
+pub fn delete_prompt(id: &str) -> Result<(), String> {
+    let conn = crate::state::get().db().lock();
+    conn.execute("DELETE FROM prompt_templates WHERE id = ?1", params![id])
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// =====================================================================
+//  Arguments
+// =====================================================================
+
+/// Placeholder names in `template`, each once, in order of first appearance
+pub fn arguments(template: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        rest = &rest[start + 2..];
+        let Some(end) = rest.find("}}") else { break };
+        let name = rest[..end].trim();
+        if !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_') && !names.iter().any(|n| n == name) {
+            names.push(name.to_string());
+        }
+        rest = &rest[end + 2..];
+    }
+    names
+}
+
+/// The template with every argument substituted; each must be given
+pub fn render(template: &str, values: &HashMap<String, String>) -> Result<String, String> {
+    let mut text = template.to_string();
+    for name in arguments(template) {
+        let value = values.get(&name).ok_or_else(|| format!("Missing argument '{}'", name))?;
+        text = text.replace(&format!("{{{{{}}}}}", name), value);
+    }
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placeholders_become_required_arguments() {
+        let template = "Review {{file}} for {{concern}}. Only {{file}}, {{ not an arg }}.";
+        assert_eq!(arguments(template), ["file", "concern"]);
+
+        let values: HashMap<String, String> =
+            [("file", "lib.rs"), ("concern", "panics")].iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        assert_eq!(render(template, &values).unwrap(), "Review lib.rs for panics. Only lib.rs, {{ not an arg }}.");
+
+        let err = render(template, &HashMap::new()).unwrap_err();
+        assert!(err.contains("file"));
+        assert_eq!(render("No arguments", &HashMap::new()).unwrap(), "No arguments");
+    }
+}
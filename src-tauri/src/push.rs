@@ -0,0 +1,492 @@
+// Push notifications for paired devices: a session finished its turn, failed,
+// or looks stuck, and nobody has it open on a phone.
+//
+// Each device can register one subscription:
+//   webpush  - a browser push subscription (the mobile web app's service
+//              worker); payloads are encrypted per RFC 8291 and the request
+//              signed with this desktop's VAPID key (RFC 8292)
+//   ntfy     - an ntfy topic URL; title and text go in a plain POST
+//   webhook  - any URL, which gets the notification as a JSON POST
+// Web push services answer 404/410 for subscriptions the browser dropped;
+// those are deleted.
+
+use base64::Engine;
+use ring::rand::SystemRandom;
+use ring::{aead, agreement, hkdf, signature};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+
+/// Identifies the sender to web push services (RFC 8292 `sub`)
+const VAPID_SUBJECT: &str = "https://github.com/jamesbillinger/agent-hub";
+/// Longest notification text; the rest is in the app
+const MAX_BODY_CHARS: usize = 200;
+/// A failed turn is also reported by session_errors; only the first of the
+/// two notifies
+const REPEAT_WINDOW: std::time::Duration = std::time::Duration::from_secs(10);
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct PushSubscription {
+    #[serde(skip)]
+    pub device_id: String,
+    /// "webpush", "ntfy" or "webhook"
+    pub kind: String,
+    pub endpoint: String,
+    /// Browser's ECDH public key (webpush only)
+    #[serde(skip)]
+    pub p256dh: Option<String>,
+    /// Browser's auth secret (webpush only)
+    #[serde(skip)]
+    pub auth: Option<String>,
+    pub created_at: i64,
+}
+
+// =====================================================================
+//  Schema
+// =====================================================================
+
+pub fn run_push_migrations(conn: &Connection) {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS push_subscriptions (
+            device_id  TEXT PRIMARY KEY,
+            kind       TEXT NOT NULL,
+            endpoint   TEXT NOT NULL,
+            p256dh     TEXT,
+            auth       TEXT,
+            created_at INTEGER NOT NULL
+        )",
+        [],
+    )
+    .expect("Failed to create push_subscriptions table");
+}
+
+// =====================================================================
+//  Subscriptions
+// =====================================================================
+
+const COLUMNS: &str = "device_id, kind, endpoint, p256dh, auth, created_at";
+
+fn from_row(row: &rusqlite::Row) -> rusqlite::Result<PushSubscription> {
+    Ok(PushSubscription {
+        device_id: row.get(0)?,
+        kind: row.get(1)?,
+        endpoint: row.get(2)?,
+        p256dh: row.get(3)?,
+        auth: row.get(4)?,
+        created_at: row.get(5)?,
+    })
+}
+
+/// Check a subscription request from a device. Body:
+/// { "kind": "webpush", "endpoint": ..., "keys": { "p256dh": ..., "auth": ... } }
+/// (the shape of a browser PushSubscription) or { "kind": "ntfy" | "webhook", "endpoint": ... }.
+fn parse_subscription(device_id: &str, body: &serde_json::Value, now: i64) -> Result<PushSubscription, String> {
+    let kind = body.get("kind").and_then(|v| v.as_str()).unwrap_or("webpush");
+    let endpoint = body.get("endpoint").and_then(|v| v.as_str()).unwrap_or("").trim();
+    let url = reqwest::Url::parse(endpoint).map_err(|_| "endpoint must be a URL".to_string())?;
+    let (p256dh, auth) = match kind {
+        "webpush" => {
+            if url.scheme() != "https" {
+                return Err("Web push endpoints must use https".to_string());
+            }
+            let key = |name: &str, len: usize| {
+                body.pointer(&format!("/keys/{}", name))
+                    .and_then(|v| v.as_str())
+                    .filter(|k| decode(k).is_ok_and(|bytes| bytes.len() == len))
+                    .map(str::to_string)
+                    .ok_or_else(|| format!("keys.{} is missing or invalid", name))
+            };
+            (Some(key("p256dh", 65)?), Some(key("auth", 16)?))
+        }
+        "ntfy" | "webhook" => {
+            if !matches!(url.scheme(), "http" | "https") {
+                return Err("endpoint must be an http(s) URL".to_string());
+            }
+            (None, None)
+        }
+        other => return Err(format!("Unknown subscription kind '{}'", other)),
+    };
+    Ok(PushSubscription {
+        device_id: device_id.to_string(),
+        kind: kind.to_string(),
+        endpoint: endpoint.to_string(),
+        p256dh,
+        auth,
+        created_at: now,
+    })
+}
+
+fn save_in(conn: &Connection, sub: &PushSubscription) -> Result<(), String> {
+    conn.execute(
+        &format!("INSERT OR REPLACE INTO push_subscriptions ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6)", COLUMNS),
+        params![sub.device_id, sub.kind, sub.endpoint, sub.p256dh, sub.auth, sub.created_at],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+pub fn delete_for_device(conn: &Connection, device_id: &str) {
+    let _ = conn.execute("DELETE FROM push_subscriptions WHERE device_id = ?1", params![device_id]);
+}
+
+/// Register (or replace) a device's subscription.
+pub fn subscribe(device_id: &str, body: &serde_json::Value) -> Result<PushSubscription, String> {
+    let sub = parse_subscription(device_id, body, chrono::Utc::now().timestamp())?;
+    save_in(&crate::state::get().db().lock(), &sub)?;
+    Ok(sub)
+}
+
+pub fn get(device_id: &str) -> Result<Option<PushSubscription>, String> {
+    crate::state::get().db()
+        .lock()
+        .query_row(
+            &format!("SELECT {} FROM push_subscriptions WHERE device_id = ?1", COLUMNS),
+            params![device_id],
+            from_row,
+        )
+        .optional()
+        .map_err(|e| e.to_string())
+}
+
+pub fn unsubscribe(device_id: &str) {
+    delete_for_device(&crate::state::get().db().lock(), device_id);
+}
+
+fn list() -> Vec<PushSubscription> {
+    let conn = crate::state::get().db().lock();
+    let Ok(mut stmt) = conn.prepare(&format!("SELECT {} FROM push_subscriptions", COLUMNS)) else {
+        return Vec::new();
+    };
+    stmt.query_map([], from_row)
+        .map(|rows| rows.filter_map(Result::ok).collect())
+        .unwrap_or_default()
+}
+
+// =====================================================================
+//  Web push
+// =====================================================================
+
+fn decode(b64url: &str) -> Result<Vec<u8>, base64::DecodeError> {
+    // Browsers send unpadded base64url, but be lenient about padding
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(b64url.trim_end_matches('='))
+}
+
+fn encode(bytes: &[u8]) -> String {
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// The VAPID signing key (PKCS#8), created on first use.
+fn vapid_key() -> Result<signature::EcdsaKeyPair, String> {
+    let rng = SystemRandom::new();
+    let pkcs8 = match crate::secrets::get(crate::secrets::PUSH_VAPID_KEY) {
+        Some(stored) => decode(&stored).map_err(|e| format!("VAPID key is corrupt: {}", e))?,
+        None => {
+            let pkcs8 = signature::EcdsaKeyPair::generate_pkcs8(&signature::ECDSA_P256_SHA256_FIXED_SIGNING, &rng)
+                .map_err(|_| "Failed to generate a VAPID key".to_string())?;
+            crate::secrets::set(crate::secrets::PUSH_VAPID_KEY, &encode(pkcs8.as_ref()))?;
+            pkcs8.as_ref().to_vec()
+        }
+    };
+    signature::EcdsaKeyPair::from_pkcs8(&signature::ECDSA_P256_SHA256_FIXED_SIGNING, &pkcs8, &rng)
+        .map_err(|_| "VAPID key is corrupt".to_string())
+}
+
+/// The public key browsers need as `applicationServerKey`.
+pub fn vapid_public_key() -> Result<String, String> {
+    use signature::KeyPair;
+    Ok(encode(vapid_key()?.public_key().as_ref()))
+}
+
+/// `Authorization` header value for a push to `endpoint`.
+fn vapid_authorization(key: &signature::EcdsaKeyPair, endpoint: &str, now: i64) -> Result<String, String> {
+    use signature::KeyPair;
+    let url = reqwest::Url::parse(endpoint).map_err(|e| e.to_string())?;
+    let audience = url.origin().ascii_serialization();
+    let header = encode(br#"{"typ":"JWT","alg":"ES256"}"#);
+    let claims = encode(
+        serde_json::json!({ "aud": audience, "exp": now + 12 * 3600, "sub": VAPID_SUBJECT })
+            .to_string()
+            .as_bytes(),
+    );
+    let signing_input = format!("{}.{}", header, claims);
+    let sig = key
+        .sign(&SystemRandom::new(), signing_input.as_bytes())
+        .map_err(|_| "Failed to sign VAPID token".to_string())?;
+    Ok(format!("vapid t={}.{}, k={}", signing_input, encode(sig.as_ref()), encode(key.public_key().as_ref())))
+}
+
+struct Len(usize);
+
+impl hkdf::KeyType for Len {
+    fn len(&self) -> usize {
+        self.0
+    }
+}
+
+fn hkdf_expand(prk: &hkdf::Prk, info: &[u8], len: usize) -> Result<Vec<u8>, String> {
+    let mut out = vec![0u8; len];
+    prk.expand(&[info], Len(len))
+        .and_then(|okm| okm.fill(&mut out))
+        .map_err(|_| "HKDF failed".to_string())?;
+    Ok(out)
+}
+
+/// Encrypt a payload for one browser subscription (RFC 8291, a single
+/// aes128gcm record).
+fn encrypt(payload: &[u8], ua_public: &[u8], auth_secret: &[u8]) -> Result<Vec<u8>, String> {
+    let rng = SystemRandom::new();
+    let as_private = agreement::EphemeralPrivateKey::generate(&agreement::ECDH_P256, &rng)
+        .map_err(|_| "Failed to generate a push key".to_string())?;
+    let as_public = as_private.compute_public_key().map_err(|_| "Failed to generate a push key".to_string())?;
+    let ecdh_secret = agreement::agree_ephemeral(
+        as_private,
+        &agreement::UnparsedPublicKey::new(&agreement::ECDH_P256, ua_public),
+        |secret| secret.to_vec(),
+    )
+    .map_err(|_| "Subscription key is not a valid P-256 point".to_string())?;
+
+    let mut key_info = b"WebPush: info\0".to_vec();
+    key_info.extend_from_slice(ua_public);
+    key_info.extend_from_slice(as_public.as_ref());
+    let prk_key = hkdf::Salt::new(hkdf::HKDF_SHA256, auth_secret).extract(&ecdh_secret);
+    let ikm = hkdf_expand(&prk_key, &key_info, 32)?;
+
+    let mut salt = [0u8; 16];
+    ring::rand::SecureRandom::fill(&rng, &mut salt).map_err(|_| "Random number generator unavailable".to_string())?;
+    let prk = hkdf::Salt::new(hkdf::HKDF_SHA256, &salt).extract(&ikm);
+    let cek = hkdf_expand(&prk, b"Content-Encoding: aes128gcm\0", 16)?;
+    let nonce = hkdf_expand(&prk, b"Content-Encoding: nonce\0", 12)?;
+
+    // Padding delimiter for the last (only) record
+    let mut record = payload.to_vec();
+    record.push(2);
+    let key = aead::LessSafeKey::new(aead::UnboundKey::new(&aead::AES_128_GCM, &cek).map_err(|_| "AES key error".to_string())?);
+    let nonce = aead::Nonce::try_assume_unique_for_key(&nonce).map_err(|_| "AES nonce error".to_string())?;
+    key.seal_in_place_append_tag(nonce, aead::Aad::empty(), &mut record)
+        .map_err(|_| "Encryption failed".to_string())?;
+
+    // Header: salt, record size, key id (our public key)
+    let mut body = salt.to_vec();
+    body.extend_from_slice(&4096u32.to_be_bytes());
+    body.push(as_public.as_ref().len() as u8);
+    body.extend_from_slice(as_public.as_ref());
+    body.extend_from_slice(&record);
+    Ok(body)
+}
+
+// =====================================================================
+//  Delivery
+// =====================================================================
+
+fn truncate(text: &str) -> String {
+    let text = text.trim();
+    match text.char_indices().nth(MAX_BODY_CHARS) {
+        Some((i, _)) => format!("{}…", &text[..i]),
+        None => text.to_string(),
+    }
+}
+
+/// A turn finished; the notification carries the start of its answer.
+pub fn on_turn_complete(session_id: &str, result: Option<&str>, is_error: bool) {
+    if is_error {
+        notify(session_id, "error", "failed", result.unwrap_or("The turn ended with an error"));
+    } else {
+        notify(session_id, "result", "finished", result.unwrap_or(""));
+    }
+}
+
+/// The session hit a classified error (see session_errors).
+pub fn on_error(session_id: &str, message: &str) {
+    notify(session_id, "error", "failed", message);
+}
+
+/// The watchdog found the session silent mid-turn.
+pub fn on_hung(session_id: &str, silent_secs: u64) {
+    let body = format!("No output for {} min; it may be stuck.", silent_secs.div_ceil(60));
+    notify(session_id, "attention", "needs attention", &body);
+}
+
+/// The agent asked the user something (see asks).
+pub fn on_ask(session_id: &str, prompt: &str) {
+    notify(session_id, "attention", "has a question", prompt);
+}
+
+/// The agent flagged that it needs the user (signal_attention).
+pub fn on_attention(session_id: &str, message: &str) {
+    notify(session_id, "attention", "needs attention", message);
+}
+
+/// Notify every subscribed device that wants to hear about `session_id`,
+/// unless a mobile client is watching it (they already see it) or it's
+/// hidden from remote devices.
+/// `event` is "result", "error" or "attention".
+fn notify(session_id: &str, event: &str, what: &str, body: &str) {
+    if crate::is_remote_hidden(session_id) || crate::session_has_mobile_subscribers(session_id) {
+        return;
+    }
+    // Minus devices that don't want this one (see notify_prefs)
+    let now = {
+        use chrono::Timelike;
+        let local = chrono::Local::now();
+        local.hour() * 60 + local.minute()
+    };
+    let subs: Vec<PushSubscription> = list()
+        .into_iter()
+        .filter(|sub| crate::device_notify_prefs(&sub.device_id).is_none_or(|prefs| prefs.allows(session_id, event, now)))
+        .collect();
+    if subs.is_empty() {
+        return;
+    }
+    {
+        let mut last_sent = crate::state::get().push_last_sent.lock();
+        let now = std::time::Instant::now();
+        last_sent.retain(|_, at| now.duration_since(*at) < REPEAT_WINDOW);
+        if last_sent.insert((session_id.to_string(), event.to_string()), now).is_some() {
+            return;
+        }
+    }
+    let name = crate::load_sessions()
+        .ok()
+        .and_then(|sessions| sessions.into_iter().find(|s| s.id == session_id))
+        .map(|s| s.name)
+        .unwrap_or_else(|| "Session".to_string());
+    let payload = serde_json::json!({
+        "event": event,
+        "sessionId": session_id,
+        "title": format!("{} {}", name, what),
+        "body": truncate(body),
+    });
+    tauri::async_runtime::spawn(async move {
+        let client = reqwest::Client::new();
+        for sub in subs {
+            if let Err(e) = send(&client, &sub, &payload).await {
+                tracing::warn!("{} ({}): {}", sub.device_id, sub.kind, e);
+            }
+        }
+    });
+}
+
+async fn send(client: &reqwest::Client, sub: &PushSubscription, payload: &serde_json::Value) -> Result<(), String> {
+    let request = match sub.kind.as_str() {
+        "webpush" => {
+            let ua_public = decode(sub.p256dh.as_deref().unwrap_or("")).map_err(|e| e.to_string())?;
+            let auth = decode(sub.auth.as_deref().unwrap_or("")).map_err(|e| e.to_string())?;
+            let body = encrypt(payload.to_string().as_bytes(), &ua_public, &auth)?;
+            let authorization = vapid_authorization(&vapid_key()?, &sub.endpoint, chrono::Utc::now().timestamp())?;
+            client
+                .post(&sub.endpoint)
+                .header("Authorization", authorization)
+                .header("Content-Encoding", "aes128gcm")
+                .header("Content-Type", "application/octet-stream")
+                .header("TTL", "86400")
+                .body(body)
+        }
+        "ntfy" => client
+            .post(&sub.endpoint)
+            .header("Title", payload["title"].as_str().unwrap_or(""))
+            .header("Tags", if payload["event"] == "result" { "white_check_mark" } else { "warning" })
+            .body(payload["body"].as_str().unwrap_or("").to_string()),
+        _ => client.post(&sub.endpoint).json(payload),
+    };
+    let resp = request
+        .timeout(std::time::Duration::from_secs(15))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    let status = resp.status();
+    if sub.kind == "webpush" && matches!(status.as_u16(), 404 | 410) {
+        // The browser dropped the subscription
+        unsubscribe(&sub.device_id);
+        return Err("subscription expired; removed".to_string());
+    }
+    if !status.is_success() {
+        return Err(format!("push service returned {}", status));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Decrypt as the browser would (RFC 8291 section 3.4)
+    fn decrypt(body: &[u8], ua_private: agreement::EphemeralPrivateKey, ua_public: &[u8], auth: &[u8]) -> Vec<u8> {
+        let (salt, rest) = body.split_at(16);
+        assert_eq!(u32::from_be_bytes(rest[..4].try_into().unwrap()), 4096);
+        let id_len = rest[4] as usize;
+        let (as_public, ciphertext) = rest[5..].split_at(id_len);
+        let ecdh_secret = agreement::agree_ephemeral(
+            ua_private,
+            &agreement::UnparsedPublicKey::new(&agreement::ECDH_P256, as_public),
+            |s| s.to_vec(),
+        )
+        .unwrap();
+        let mut key_info = b"WebPush: info\0".to_vec();
+        key_info.extend_from_slice(ua_public);
+        key_info.extend_from_slice(as_public);
+        let ikm = hkdf_expand(&hkdf::Salt::new(hkdf::HKDF_SHA256, auth).extract(&ecdh_secret), &key_info, 32).unwrap();
+        let prk = hkdf::Salt::new(hkdf::HKDF_SHA256, salt).extract(&ikm);
+        let cek = hkdf_expand(&prk, b"Content-Encoding: aes128gcm\0", 16).unwrap();
+        let nonce = hkdf_expand(&prk, b"Content-Encoding: nonce\0", 12).unwrap();
+        let key = aead::LessSafeKey::new(aead::UnboundKey::new(&aead::AES_128_GCM, &cek).unwrap());
+        let mut data = ciphertext.to_vec();
+        let plain = key
+            .open_in_place(aead::Nonce::try_assume_unique_for_key(&nonce).unwrap(), aead::Aad::empty(), &mut data)
+            .unwrap();
+        assert_eq!(plain.last(), Some(&2));
+        plain[..plain.len() - 1].to_vec()
+    }
+
+    #[test]
+    fn browser_can_decrypt_the_payload() {
+        let rng = SystemRandom::new();
+        let ua_private = agreement::EphemeralPrivateKey::generate(&agreement::ECDH_P256, &rng).unwrap();
+        let ua_public = ua_private.compute_public_key().unwrap().as_ref().to_vec();
+        let auth = [7u8; 16];
+
+        let body = encrypt(br#"{"title":"done"}"#, &ua_public, &auth).unwrap();
+        assert_eq!(decrypt(&body, ua_private, &ua_public, &auth), br#"{"title":"done"}"#);
+        assert!(encrypt(b"x", &[4u8; 65], &auth).is_err());
+    }
+
+    #[test]
+    fn vapid_token_is_signed_for_the_push_service_origin() {
+        use signature::KeyPair;
+        let rng = SystemRandom::new();
+        let pkcs8 = signature::EcdsaKeyPair::generate_pkcs8(&signature::ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let key = signature::EcdsaKeyPair::from_pkcs8(&signature::ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng).unwrap();
+
+        let header = vapid_authorization(&key, "https://fcm.googleapis.com/fcm/send/abc", 1_000).unwrap();
+        let (token, k) = header.strip_prefix("vapid t=").unwrap().split_once(", k=").unwrap();
+        assert_eq!(decode(k).unwrap(), key.public_key().as_ref());
+        let (signing_input, sig) = token.rsplit_once('.').unwrap();
+        signature::UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_FIXED, key.public_key().as_ref())
+            .verify(signing_input.as_bytes(), &decode(sig).unwrap())
+            .unwrap();
+        let claims: serde_json::Value =
+            serde_json::from_slice(&decode(signing_input.split('.').nth(1).unwrap()).unwrap()).unwrap();
+        assert_eq!(claims["aud"], "https://fcm.googleapis.com");
+        assert_eq!(claims["exp"], 1_000 + 12 * 3600);
+    }
+
+    #[test]
+    fn subscriptions_are_validated() {
+        let web = serde_json::json!({
+            "endpoint": "https://push.example/abc",
+            "keys": { "p256dh": encode(&[4u8; 65]), "auth": encode(&[1u8; 16]) }
+        });
+        let sub = parse_subscription("phone", &web, 5).unwrap();
+        assert_eq!(sub.kind, "webpush");
+        assert!(sub.p256dh.is_some());
+
+        let mut bad_key = web.clone();
+        bad_key["keys"]["auth"] = serde_json::json!("abc");
+        assert!(parse_subscription("phone", &bad_key, 5).is_err());
+        let mut plain_http = web.clone();
+        plain_http["endpoint"] = serde_json::json!("http://push.example/abc");
+        assert!(parse_subscription("phone", &plain_http, 5).is_err());
+
+        let ntfy = serde_json::json!({ "kind": "ntfy", "endpoint": "http://ntfy.lan/agents" });
+        assert_eq!(parse_subscription("phone", &ntfy, 5).unwrap().p256dh, None);
+        assert!(parse_subscription("phone", &serde_json::json!({ "kind": "sms", "endpoint": "https://x" }), 5).is_err());
+    }
+}
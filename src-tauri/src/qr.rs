@@ -0,0 +1,390 @@
+// Minimal QR code encoder for the pairing QR (GET /api/auth/qr).
+//
+// Byte mode, error correction level M, versions 1-10 (up to 213 bytes) --
+// plenty for a URL with a pairing id and code. Rendered as SVG so no image
+// dependencies are needed.
+
+/// (total codewords, EC codewords per block, blocks) for level M, versions 1-10.
+const VERSIONS_M: [(usize, usize, usize); 10] = [
+    (26, 10, 1),
+    (44, 16, 1),
+    (70, 26, 1),
+    (100, 18, 2),
+    (134, 24, 2),
+    (172, 16, 4),
+    (196, 18, 4),
+    (242, 22, 4),
+    (292, 22, 5),
+    (346, 26, 5),
+];
+
+/// Alignment pattern centres per version.
+const ALIGNMENT: [&[usize]; 10] = [
+    &[],
+    &[6, 18],
+    &[6, 22],
+    &[6, 26],
+    &[6, 30],
+    &[6, 34],
+    &[6, 22, 38],
+    &[6, 24, 42],
+    &[6, 26, 46],
+    &[6, 28, 50],
+];
+
+pub struct QrCode {
+    size: usize,
+    modules: Vec<bool>,
+    is_function: Vec<bool>,
+}
+
+impl QrCode {
+    /// Encode `data` in byte mode, or None if it doesn't fit in version 10.
+    pub fn encode(data: &[u8]) -> Option<QrCode> {
+        let (version, (total, ecc_len, blocks)) = VERSIONS_M.iter().enumerate().find_map(|(i, v)| {
+            let count_bits = if i < 9 { 8 } else { 16 };
+            let capacity_bits = (v.0 - v.1 * v.2) * 8;
+            (4 + count_bits + data.len() * 8 <= capacity_bits).then_some((i + 1, *v))
+        })?;
+        let data_len = total - ecc_len * blocks;
+
+        // Mode, length, payload, terminator, then pad bytes
+        let mut bits: Vec<bool> = Vec::with_capacity(data_len * 8);
+        push_bits(&mut bits, 0b0100, 4);
+        push_bits(&mut bits, data.len() as u32, if version < 10 { 8 } else { 16 });
+        for &b in data {
+            push_bits(&mut bits, b as u32, 8);
+        }
+        let terminator = (data_len * 8 - bits.len()).min(4);
+        push_bits(&mut bits, 0, terminator);
+        let to_byte = (8 - bits.len() % 8) % 8;
+        push_bits(&mut bits, 0, to_byte);
+        let mut codewords: Vec<u8> =
+            bits.chunks(8).map(|c| c.iter().fold(0u8, |acc, &b| (acc << 1) | u8::from(b))).collect();
+        for pad in [0xEC, 0x11].iter().cycle() {
+            if codewords.len() >= data_len {
+                break;
+            }
+            codewords.push(*pad);
+        }
+
+        let mut qr = QrCode {
+            size: version * 4 + 17,
+            modules: vec![false; (version * 4 + 17).pow(2)],
+            is_function: vec![false; (version * 4 + 17).pow(2)],
+        };
+        qr.draw_function_patterns(version);
+        qr.draw_codewords(&interleave(&codewords, total, ecc_len, blocks));
+
+        // Pick the mask with the lowest penalty
+        let mask = (0..8)
+            .min_by_key(|&mask| {
+                qr.apply_mask(mask);
+                qr.draw_format_bits(mask);
+                let penalty = qr.penalty();
+                qr.apply_mask(mask);
+                penalty
+            })
+            .unwrap_or(0);
+        qr.apply_mask(mask);
+        qr.draw_format_bits(mask);
+        Some(qr)
+    }
+
+    pub fn is_dark(&self, x: usize, y: usize) -> bool {
+        self.modules[y * self.size + x]
+    }
+
+    /// SVG with a 4-module quiet zone; one unit per module.
+    pub fn to_svg(&self) -> String {
+        let dim = self.size + 8;
+        let mut path = String::new();
+        for y in 0..self.size {
+            for x in 0..self.size {
+                if self.is_dark(x, y) {
+                    path.push_str(&format!("M{},{}h1v1h-1z", x + 4, y + 4));
+                }
+            }
+        }
+        format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 {dim} {dim}\" shape-rendering=\"crispEdges\">\
+             <rect width=\"100%\" height=\"100%\" fill=\"#fff\"/><path d=\"{path}\" fill=\"#000\"/></svg>"
+        )
+    }
+
+    fn set_function(&mut self, x: usize, y: usize, dark: bool) {
+        let i = y * self.size + x;
+        self.modules[i] = dark;
+        self.is_function[i] = true;
+    }
+
+    fn draw_function_patterns(&mut self, version: usize) {
+        let size = self.size;
+        for i in 0..size {
+            self.set_function(6, i, i % 2 == 0);
+            self.set_function(i, 6, i % 2 == 0);
+        }
+        for (cx, cy) in [(3, 3), (size - 4, 3), (3, size - 4)] {
+            for dy in -4i32..=4 {
+                for dx in -4i32..=4 {
+                    let (x, y) = (cx as i32 + dx, cy as i32 + dy);
+                    if (0..size as i32).contains(&x) && (0..size as i32).contains(&y) {
+                        let dist = dx.abs().max(dy.abs());
+                        self.set_function(x as usize, y as usize, dist != 2 && dist != 4);
+                    }
+                }
+            }
+        }
+        let centres = ALIGNMENT[version - 1];
+        let last = centres.len().saturating_sub(1);
+        for (i, &cx) in centres.iter().enumerate() {
+            for (j, &cy) in centres.iter().enumerate() {
+                // Skip the three that would overlap finder patterns
+                if (i == 0 || j == 0) && (i == j || i == last || j == last) {
+                    continue;
+                }
+                for dy in -2i32..=2 {
+                    for dx in -2i32..=2 {
+                        let dark = dx.abs().max(dy.abs()) != 1;
+                        self.set_function((cx as i32 + dx) as usize, (cy as i32 + dy) as usize, dark);
+                    }
+                }
+            }
+        }
+        // Reserve the format areas; real bits are drawn once the mask is known
+        self.draw_format_bits(0);
+        if version >= 7 {
+            let mut rem = version as u32;
+            for _ in 0..12 {
+                rem = (rem << 1) ^ ((rem >> 11) * 0x1F25);
+            }
+            let bits = (version as u32) << 12 | rem;
+            for i in 0..18 {
+                let dark = (bits >> i) & 1 == 1;
+                let (a, b) = (size - 11 + i % 3, i / 3);
+                self.set_function(a, b, dark);
+                self.set_function(b, a, dark);
+            }
+        }
+    }
+
+    fn draw_format_bits(&mut self, mask: u32) {
+        // Level M is 0b00, so the data is just the mask
+        let data = mask;
+        let mut rem = data;
+        for _ in 0..10 {
+            rem = (rem << 1) ^ ((rem >> 9) * 0x537);
+        }
+        let bits = (data << 10 | rem) ^ 0x5412;
+        let bit = |i: usize| (bits >> i) & 1 == 1;
+        let size = self.size;
+        for i in 0..6 {
+            self.set_function(8, i, bit(i));
+        }
+        self.set_function(8, 7, bit(6));
+        self.set_function(8, 8, bit(7));
+        self.set_function(7, 8, bit(8));
+        for i in 9..15 {
+            self.set_function(14 - i, 8, bit(i));
+        }
+        for i in 0..8 {
+            self.set_function(size - 1 - i, 8, bit(i));
+        }
+        for i in 8..15 {
+            self.set_function(8, size - 15 + i, bit(i));
+        }
+        self.set_function(8, size - 8, true);
+    }
+
+    /// Zig-zag the codewords into the non-function modules, two columns at a time.
+    fn draw_codewords(&mut self, codewords: &[u8]) {
+        let size = self.size;
+        let total_bits = codewords.len() * 8;
+        let mut i = 0;
+        let mut right = size - 1;
+        while right >= 1 {
+            if right == 6 {
+                right = 5;
+            }
+            for vert in 0..size {
+                for j in 0..2 {
+                    let x = right - j;
+                    let upward = (right + 1) & 2 == 0;
+                    let y = if upward { size - 1 - vert } else { vert };
+                    if !self.is_function[y * size + x] && i < total_bits {
+                        self.modules[y * size + x] = (codewords[i >> 3] >> (7 - (i & 7))) & 1 == 1;
+                        i += 1;
+                    }
+                }
+            }
+            if right < 2 {
+                break;
+            }
+            right -= 2;
+        }
+    }
+
+    /// XOR the data modules with mask pattern `mask` (applying twice undoes it).
+    fn apply_mask(&mut self, mask: u32) {
+        for y in 0..self.size {
+            for x in 0..self.size {
+                let invert = match mask {
+                    0 => (x + y) % 2 == 0,
+                    1 => y % 2 == 0,
+                    2 => x % 3 == 0,
+                    3 => (x + y) % 3 == 0,
+                    4 => (x / 3 + y / 2) % 2 == 0,
+                    5 => x * y % 2 + x * y % 3 == 0,
+                    6 => (x * y % 2 + x * y % 3) % 2 == 0,
+                    _ => ((x + y) % 2 + x * y % 3) % 2 == 0,
+                };
+                let i = y * self.size + x;
+                if invert && !self.is_function[i] {
+                    self.modules[i] = !self.modules[i];
+                }
+            }
+        }
+    }
+
+    /// Mask penalty: runs of 5+ same-colour modules, 2x2 blocks and
+    /// dark/light imbalance. (The finder-lookalike rule is left out; any
+    /// mask decodes, this only nudges towards an easier scan.)
+    fn penalty(&self) -> usize {
+        let size = self.size;
+        let mut penalty = 0;
+        for horizontal in [true, false] {
+            for a in 0..size {
+                let mut run = 0;
+                let mut colour = false;
+                for b in 0..size {
+                    let dark = if horizontal { self.is_dark(b, a) } else { self.is_dark(a, b) };
+                    if b > 0 && dark == colour {
+                        run += 1;
+                        if run == 5 {
+                            penalty += 3;
+                        } else if run > 5 {
+                            penalty += 1;
+                        }
+                    } else {
+                        colour = dark;
+                        run = 1;
+                    }
+                }
+            }
+        }
+        for y in 0..size - 1 {
+            for x in 0..size - 1 {
+                let c = self.is_dark(x, y);
+                if c == self.is_dark(x + 1, y) && c == self.is_dark(x, y + 1) && c == self.is_dark(x + 1, y + 1) {
+                    penalty += 3;
+                }
+            }
+        }
+        let dark = self.modules.iter().filter(|&&m| m).count();
+        let total = size * size;
+        let deviation = (dark * 20).abs_diff(total * 10);
+        penalty + deviation.div_ceil(total).saturating_sub(1) * 10
+    }
+}
+
+fn push_bits(bits: &mut Vec<bool>, value: u32, len: usize) {
+    for i in (0..len).rev() {
+        bits.push((value >> i) & 1 == 1);
+    }
+}
+
+/// Split data into blocks, append Reed-Solomon EC to each, and interleave.
+fn interleave(data: &[u8], total: usize, ecc_len: usize, blocks: usize) -> Vec<u8> {
+    let short_blocks = blocks - total % blocks;
+    let short_len = total / blocks;
+    let divisor = rs_divisor(ecc_len);
+
+    let mut split: Vec<Vec<u8>> = Vec::with_capacity(blocks);
+    let mut offset = 0;
+    for i in 0..blocks {
+        let len = short_len - ecc_len + usize::from(i >= short_blocks);
+        let mut block = data[offset..offset + len].to_vec();
+        offset += len;
+        let ecc = rs_remainder(&block, &divisor);
+        if i < short_blocks {
+            // Placeholder so all blocks line up; skipped when interleaving
+            block.push(0);
+        }
+        block.extend(ecc);
+        split.push(block);
+    }
+
+    let mut out = Vec::with_capacity(total);
+    for i in 0..split[0].len() {
+        for (j, block) in split.iter().enumerate() {
+            if i != short_len - ecc_len || j >= short_blocks {
+                out.push(block[i]);
+            }
+        }
+    }
+    out
+}
+
+/// Multiply in GF(2^8) modulo x^8 + x^4 + x^3 + x^2 + 1.
+fn gf_mul(x: u8, y: u8) -> u8 {
+    let mut z: u32 = 0;
+    for i in (0..8).rev() {
+        z = (z << 1) ^ ((z >> 7) * 0x11D);
+        z ^= ((y as u32 >> i) & 1) * x as u32;
+    }
+    z as u8
+}
+
+/// Generator polynomial of the given degree (leading 1 omitted).
+fn rs_divisor(degree: usize) -> Vec<u8> {
+    let mut result = vec![0u8; degree];
+    result[degree - 1] = 1;
+    let mut root: u8 = 1;
+    for _ in 0..degree {
+        for j in 0..degree {
+            result[j] = gf_mul(result[j], root);
+            if j + 1 < degree {
+                result[j] ^= result[j + 1];
+            }
+        }
+        root = gf_mul(root, 0x02);
+    }
+    result
+}
+
+fn rs_remainder(data: &[u8], divisor: &[u8]) -> Vec<u8> {
+    let mut result = vec![0u8; divisor.len()];
+    for &b in data {
+        let factor = b ^ result.remove(0);
+        result.push(0);
+        for (r, &d) in result.iter_mut().zip(divisor) {
+            *r ^= gf_mul(d, factor);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reed_solomon_matches_reference() {
+        // The widely used "HELLO WORLD" version 1-M worked example
+        let data = [32, 91, 11, 120, 209, 114, 220, 77, 67, 64, 236, 17, 236, 17, 236, 17];
+        let ecc = rs_remainder(&data, &rs_divisor(10));
+        assert_eq!(ecc, vec![196, 35, 39, 119, 235, 215, 231, 226, 93, 23]);
+    }
+
+    #[test]
+    fn picks_smallest_version_and_draws_finders() {
+        let qr = QrCode::encode(b"https://192.168.1.20:3030/?pair=18c2f0a9b7d61e4f2a&code=123456").unwrap();
+        // 62 bytes just fits version 4 at level M (64 data codewords)
+        assert_eq!(qr.size, 33);
+        for (x, y) in [(0, 0), (qr.size - 7, 0), (0, qr.size - 7)] {
+            assert!(qr.is_dark(x, y) && qr.is_dark(x + 6, y + 6) && !qr.is_dark(x + 1, y + 1));
+        }
+        assert!(QrCode::encode(&[b'a'; 214]).is_none());
+        assert!(qr.to_svg().starts_with("<svg"));
+    }
+}
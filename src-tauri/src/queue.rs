@@ -0,0 +1,358 @@
+// Per-session prompt queue for claude-json sessions.
+//
+// Prompts are persisted in `prompt_queue` and sent one at a time: the next
+// pending prompt goes out when the `result` message for the previous turn
+// arrives (see the stdout task in spawn_json_process). This lets a batch of
+// prompts ("run these 5 refactors one after another") run unattended.
+//
+// A turn that ends in an error result pauses the queue rather than plowing
+// ahead; the next enqueue, reorder, successful turn or session start
+// resumes it.
+
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use tauri::Emitter;
+
+// =====================================================================
+//  Schema
+// =====================================================================
+
+pub fn run_queue_migrations(conn: &Connection) {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS prompt_queue (
+            id         TEXT PRIMARY KEY,
+            session_id TEXT NOT NULL,
+            prompt     TEXT NOT NULL,
+            position   INTEGER NOT NULL,
+            status     TEXT NOT NULL DEFAULT 'pending',
+            created_at TEXT NOT NULL,
+            sent_at    TEXT
+        )",
+        [],
+    )
+    .expect("Failed to create prompt_queue table");
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_prompt_queue_session ON prompt_queue(session_id, status, position)",
+        [],
+    )
+    .expect("Failed to create prompt_queue index");
+}
+
+/// Remove every queued prompt for a session (called when the session is deleted).
+pub fn delete_queue_for_session(conn: &Connection, session_id: &str) {
+    let _ = conn.execute("DELETE FROM prompt_queue WHERE session_id = ?1", params![session_id]);
+    crate::state::get().queue_in_flight.lock().remove(session_id);
+}
+
+// =====================================================================
+//  Queue operations
+// =====================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueuedPrompt {
+    pub id: String,
+    pub session_id: String,
+    pub prompt: String,
+    pub position: i64,
+    /// "pending" until sent, then "sent".
+    pub status: String,
+    pub created_at: String,
+    pub sent_at: Option<String>,
+}
+
+fn row_to_prompt(row: &rusqlite::Row) -> rusqlite::Result<QueuedPrompt> {
+    Ok(QueuedPrompt {
+        id: row.get(0)?,
+        session_id: row.get(1)?,
+        prompt: row.get(2)?,
+        position: row.get(3)?,
+        status: row.get(4)?,
+        created_at: row.get(5)?,
+        sent_at: row.get(6)?,
+    })
+}
+
+/// Pending prompts for a session, in the order they will be sent.
+pub fn list_queue(session_id: &str) -> Result<Vec<QueuedPrompt>, String> {
+    let conn = crate::state::get().db().lock();
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, session_id, prompt, position, status, created_at, sent_at
+             FROM prompt_queue WHERE session_id = ?1 AND status = 'pending'
+             ORDER BY position ASC",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![session_id], row_to_prompt)
+        .map_err(|e| e.to_string())?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(rows)
+}
+
+/// Append a prompt to the end of a session's queue. If the session is running
+/// and the queue isn't waiting on a turn, it is sent right away.
+pub fn enqueue_prompt(session_id: &str, prompt: &str) -> Result<QueuedPrompt, String> {
+    if prompt.trim().is_empty() {
+        return Err("Prompt is empty".to_string());
+    }
+    let item = {
+        let conn = crate::state::get().db().lock();
+        let exists: bool = conn
+            .query_row("SELECT COUNT(*) FROM sessions WHERE id = ?1", params![session_id], |r| {
+                r.get::<_, i64>(0)
+            })
+            .map(|n| n > 0)
+            .unwrap_or(false);
+        if !exists {
+            return Err("Session not found".to_string());
+        }
+        let next_position: i64 = conn
+            .query_row(
+                "SELECT COALESCE(MAX(position), -1) + 1 FROM prompt_queue WHERE session_id = ?1",
+                params![session_id],
+                |r| r.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+        let item = QueuedPrompt {
+            id: uuid::Uuid::new_v4().to_string(),
+            session_id: session_id.to_string(),
+            prompt: prompt.to_string(),
+            position: next_position,
+            status: "pending".to_string(),
+            created_at: chrono::Utc::now().to_rfc3339(),
+            sent_at: None,
+        };
+        conn.execute(
+            "INSERT INTO prompt_queue (id, session_id, prompt, position, status, created_at, sent_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, NULL)",
+            params![item.id, item.session_id, item.prompt, item.position, item.status, item.created_at],
+        )
+        .map_err(|e| e.to_string())?;
+        item
+    };
+    notify_queue_changed(session_id);
+    dispatch_next(session_id);
+    Ok(item)
+}
+
+/// Rewrite the order of a session's pending prompts. `prompt_ids` must list
+/// every pending prompt exactly once.
+pub fn reorder_queue(session_id: &str, prompt_ids: &[String]) -> Result<Vec<QueuedPrompt>, String> {
+    let pending: HashSet<String> = list_queue(session_id)?.into_iter().map(|p| p.id).collect();
+    let requested: HashSet<String> = prompt_ids.iter().cloned().collect();
+    if requested.len() != prompt_ids.len() || requested != pending {
+        return Err("prompt_ids must list each pending prompt exactly once".to_string());
+    }
+    {
+        let mut conn = crate::state::get().db().lock();
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        for (position, id) in prompt_ids.iter().enumerate() {
+            tx.execute(
+                "UPDATE prompt_queue SET position = ?1 WHERE id = ?2 AND session_id = ?3",
+                params![position as i64, id, session_id],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+        tx.commit().map_err(|e| e.to_string())?;
+    }
+    notify_queue_changed(session_id);
+    dispatch_next(session_id);
+    list_queue(session_id)
+}
+
+/// Drop a single pending prompt from a session's queue.
+pub fn remove_queued_prompt(session_id: &str, prompt_id: &str) -> Result<(), String> {
+    {
+        let conn = crate::state::get().db().lock();
+        conn.execute(
+            "DELETE FROM prompt_queue WHERE id = ?1 AND session_id = ?2 AND status = 'pending'",
+            params![prompt_id, session_id],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    notify_queue_changed(session_id);
+    Ok(())
+}
+
+// =====================================================================
+//  Dispatch
+// =====================================================================
+
+/// Called from the stdout reader when a `result` message arrives. Sends the
+/// next pending prompt unless the turn ended in an error.
+pub fn on_turn_complete(session_id: &str, is_error: bool) {
+    let was_in_flight = crate::state::get().queue_in_flight.lock().remove(session_id);
+    if is_error {
+        if was_in_flight && !list_queue(session_id).unwrap_or_default().is_empty() {
+            tracing::error!("turn failed for {}, pausing queue", session_id);
+            notify_queue_changed(session_id);
+        }
+        return;
+    }
+    dispatch_next(session_id);
+}
+
+/// Called once a claude-json process is registered, so prompts queued while
+/// the session was stopped start flowing as soon as it comes up.
+pub fn on_process_started(session_id: &str) {
+    crate::state::get().queue_in_flight.lock().remove(session_id);
+    dispatch_next(session_id);
+}
+
+/// Called when a claude-json process exits; whatever was in flight is lost.
+pub fn on_process_exited(session_id: &str) {
+    crate::state::get().queue_in_flight.lock().remove(session_id);
+}
+
+/// Send the first pending prompt if the session is running and idle.
+/// Returns true if a prompt was sent.
+fn dispatch_next(session_id: &str) -> bool {
+    let is_running = crate::state::get().json_processes.lock().contains_key(session_id);
+    if !is_running {
+        return false;
+    }
+    {
+        let mut in_flight = crate::state::get().queue_in_flight.lock();
+        if in_flight.contains(session_id) {
+            return false;
+        }
+        // Claim the slot before touching the DB so two concurrent callers
+        // can't both send.
+        in_flight.insert(session_id.to_string());
+    }
+
+    let next = list_queue(session_id).ok().and_then(|q| q.into_iter().next());
+    let Some(next) = next else {
+        crate::state::get().queue_in_flight.lock().remove(session_id);
+        return false;
+    };
+
+    if let Err(e) = crate::send_user_prompt(session_id, &next.prompt) {
+        tracing::error!("failed to send queued prompt for {}: {}", session_id, e);
+        crate::state::get().queue_in_flight.lock().remove(session_id);
+        return false;
+    }
+
+    {
+        let conn = crate::state::get().db().lock();
+        let _ = conn.execute(
+            "UPDATE prompt_queue SET status = 'sent', sent_at = ?1 WHERE id = ?2",
+            params![chrono::Utc::now().to_rfc3339(), next.id],
+        );
+    }
+    notify_queue_changed(session_id);
+    true
+}
+
+/// Push the current queue to the desktop UI and WebSocket status listeners.
+fn notify_queue_changed(session_id: &str) {
+    let queue = list_queue(session_id).unwrap_or_default();
+    let in_flight = crate::state::get().queue_in_flight.lock().contains(session_id);
+    let event = crate::events::SessionEvent::QueueUpdated {
+        session_id: session_id.to_string(),
+        queue,
+        in_flight,
+    };
+    if let Some(app) = crate::state::get().app_handle.lock().as_ref() {
+        let _ = app.emit("prompt-queue-updated", event.data());
+    }
+    crate::broadcast_session_event(event);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{fake_json_session, session_row};
+    use tokio::sync::mpsc::Receiver;
+
+    /// The prompts written to a fake session's process so far
+    fn sent(written: &mut Receiver<String>) -> Vec<String> {
+        std::iter::from_fn(|| written.try_recv().ok())
+            .map(|line| {
+                let envelope: serde_json::Value = serde_json::from_str(&line).unwrap();
+                envelope["message"]["content"][0]["text"].as_str().unwrap().to_string()
+            })
+            .collect()
+    }
+
+    fn prompts(session_id: &str) -> Vec<String> {
+        list_queue(session_id).unwrap().into_iter().map(|p| p.prompt).collect()
+    }
+
+    #[test]
+    fn prompts_queue_in_order_and_can_be_reordered() {
+        let session_id = session_row("Queue order");
+        let ids: Vec<String> = ["one", "two", "three"]
+            .iter()
+            .map(|prompt| enqueue_prompt(&session_id, prompt).unwrap().id)
+            .collect();
+        assert_eq!(prompts(&session_id), ["one", "two", "three"]);
+
+        assert!(enqueue_prompt(&session_id, "  ").is_err());
+        assert!(enqueue_prompt("no-such-session", "hi").is_err());
+
+        let reversed: Vec<String> = ids.iter().rev().cloned().collect();
+        let reordered = reorder_queue(&session_id, &reversed).unwrap();
+        assert_eq!(reordered.iter().map(|p| p.prompt.as_str()).collect::<Vec<_>>(), ["three", "two", "one"]);
+        // Each pending prompt exactly once
+        assert!(reorder_queue(&session_id, &ids[..2]).is_err());
+        assert!(reorder_queue(&session_id, &[ids[0].clone(), ids[0].clone(), ids[1].clone()]).is_err());
+
+        remove_queued_prompt(&session_id, &ids[1]).unwrap();
+        assert_eq!(prompts(&session_id), ["three", "one"]);
+    }
+
+    #[test]
+    fn results_send_the_next_prompt_and_errors_pause() {
+        let session_id = session_row("Queue dispatch");
+        let mut written = fake_json_session(&session_id);
+
+        // Running and idle: the first goes out right away, the rest wait
+        for prompt in ["first", "second", "third"] {
+            enqueue_prompt(&session_id, prompt).unwrap();
+        }
+        assert_eq!(sent(&mut written), ["first"]);
+        assert!(crate::state::get().queue_in_flight.lock().contains(&session_id));
+        assert_eq!(prompts(&session_id), ["second", "third"]);
+
+        on_turn_complete(&session_id, false);
+        assert_eq!(sent(&mut written), ["second"]);
+
+        // A failed turn holds the rest back
+        on_turn_complete(&session_id, true);
+        assert!(sent(&mut written).is_empty());
+        assert!(!crate::state::get().queue_in_flight.lock().contains(&session_id));
+        assert_eq!(prompts(&session_id), ["third"]);
+
+        // Until something resumes it
+        enqueue_prompt(&session_id, "fourth").unwrap();
+        assert_eq!(sent(&mut written), ["third"]);
+        assert_eq!(prompts(&session_id), ["fourth"]);
+
+        crate::state::get().json_processes.lock().remove(&session_id);
+        crate::state::get().json_broadcasters.lock().remove(&session_id);
+    }
+
+    #[test]
+    fn the_queue_goes_with_its_session() {
+        let session_id = session_row("Queue delete");
+        let mut written = fake_json_session(&session_id);
+        enqueue_prompt(&session_id, "in flight").unwrap();
+        enqueue_prompt(&session_id, "waiting").unwrap();
+        assert_eq!(sent(&mut written), ["in flight"]);
+
+        crate::state::get().json_processes.lock().remove(&session_id);
+        crate::state::get().json_broadcasters.lock().remove(&session_id);
+        crate::delete_session(session_id.clone()).unwrap();
+        assert!(list_queue(&session_id).unwrap().is_empty());
+        assert!(!crate::state::get().queue_in_flight.lock().contains(&session_id));
+        let rows: i64 = crate::state::get()
+            .db()
+            .lock()
+            .query_row("SELECT COUNT(*) FROM prompt_queue WHERE session_id = ?1", params![session_id], |r| r.get(0))
+            .unwrap();
+        assert_eq!(rows, 0);
+    }
+}
@@ -0,0 +1,214 @@
+// Rate limits for pairing, login and message-sending endpoints.
+//
+// Each bucket is a fixed window per key (client IP, or the device for
+// authenticated message sends): the first counted request opens a window of
+// `window_secs`, and once `max` requests have been counted the key is
+// refused until the window ends. Counters live in SQLite so restarting or
+// crashing the app doesn't hand an attacker a fresh budget.
+//
+// Limits can be overridden per bucket with `rate_limits` in config.json,
+// e.g. {"auth": {"max": 10, "window_secs": 600}}; `max: 0` turns a bucket off.
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Windows still open after this long are dropped at startup.
+const STALE_SECS: i64 = 7 * 24 * 3600;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bucket {
+    /// Requesting and completing pairing (every request counts except polls
+    /// while waiting for desktop approval)
+    Pairing,
+    /// PIN login and token refresh (failures count; a success clears the key)
+    Auth,
+    /// Input, voice prompts and attachments sent to sessions
+    Messages,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Limit {
+    pub max: u32,
+    pub window_secs: u64,
+}
+
+impl Bucket {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Bucket::Pairing => "pairing",
+            Bucket::Auth => "auth",
+            Bucket::Messages => "messages",
+        }
+    }
+
+    fn default_limit(self) -> Limit {
+        match self {
+            Bucket::Pairing => Limit { max: 10, window_secs: 15 * 60 },
+            Bucket::Auth => Limit { max: 5, window_secs: 15 * 60 },
+            Bucket::Messages => Limit { max: 30, window_secs: 60 },
+        }
+    }
+
+    /// The configured limit, or None when the bucket is turned off.
+    pub fn limit(self, overrides: &HashMap<String, Limit>) -> Option<Limit> {
+        let limit = overrides.get(self.as_str()).copied().unwrap_or_else(|| self.default_limit());
+        (limit.max > 0 && limit.window_secs > 0).then_some(limit)
+    }
+
+    /// Bucket for an API route (`path` as matched, with or without the
+    /// /api or /api/v1 prefix).
+    pub fn for_route(method: &axum::http::Method, path: &str) -> Option<Bucket> {
+        if *method != axum::http::Method::POST {
+            return None;
+        }
+        const ROUTES: &[(&str, Bucket)] = &[
+            ("/auth/request-pairing", Bucket::Pairing),
+            ("/auth/pair", Bucket::Pairing),
+            ("/auth/pin-login", Bucket::Auth),
+            ("/auth/refresh", Bucket::Auth),
+            ("/sessions/:session_id/input", Bucket::Messages),
+            ("/sessions/:session_id/prompt", Bucket::Messages),
+            ("/sessions/:id/audio", Bucket::Messages),
+            ("/sessions/:id/attachments", Bucket::Messages),
+        ];
+        ROUTES.iter().find(|(route, _)| path.ends_with(route)).map(|&(_, bucket)| bucket)
+    }
+
+    /// Whether a response with `status` uses up part of the budget.
+    pub fn counts(self, status: axum::http::StatusCode) -> bool {
+        match self {
+            Bucket::Auth => status.is_client_error() && status != axum::http::StatusCode::TOO_MANY_REQUESTS,
+            Bucket::Pairing => !matches!(
+                status,
+                axum::http::StatusCode::TOO_MANY_REQUESTS | axum::http::StatusCode::ACCEPTED
+            ),
+            Bucket::Messages => status != axum::http::StatusCode::TOO_MANY_REQUESTS,
+        }
+    }
+}
+
+// =====================================================================
+//  Schema
+// =====================================================================
+
+pub fn run_ratelimit_migrations(conn: &Connection) {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS rate_limits (
+            bucket       TEXT NOT NULL,
+            key          TEXT NOT NULL,
+            window_start INTEGER NOT NULL,
+            count        INTEGER NOT NULL,
+            PRIMARY KEY (bucket, key)
+        )",
+        [],
+    )
+    .expect("Failed to create rate_limits table");
+    let _ = conn.execute("DELETE FROM rate_limits WHERE window_start < ?1", params![now() - STALE_SECS]);
+}
+
+// =====================================================================
+//  Counting
+// =====================================================================
+
+fn now() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+/// Count in the window open at `now`, if any.
+fn current_count(conn: &Connection, bucket: Bucket, key: &str, limit: Limit, now: i64) -> Option<(u32, i64)> {
+    conn.query_row(
+        "SELECT count, window_start FROM rate_limits WHERE bucket = ?1 AND key = ?2",
+        params![bucket.as_str(), key],
+        |row| Ok((row.get::<_, u32>(0)?, row.get::<_, i64>(1)?)),
+    )
+    .optional()
+    .ok()
+    .flatten()
+    .filter(|&(_, start)| now < start + limit.window_secs as i64)
+}
+
+fn retry_after_at(conn: &Connection, bucket: Bucket, key: &str, limit: Limit, now: i64) -> Option<u64> {
+    let (count, start) = current_count(conn, bucket, key, limit, now)?;
+    (count >= limit.max).then(|| (start + limit.window_secs as i64 - now).max(1) as u64)
+}
+
+fn hit_at(conn: &Connection, bucket: Bucket, key: &str, limit: Limit, now: i64) -> Result<(), String> {
+    let (count, start) = current_count(conn, bucket, key, limit, now).unwrap_or((0, now));
+    conn.execute(
+        "INSERT OR REPLACE INTO rate_limits (bucket, key, window_start, count) VALUES (?1, ?2, ?3, ?4)",
+        params![bucket.as_str(), key, start, count + 1],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn clear_in(conn: &Connection, bucket: Bucket, key: &str) -> Result<(), String> {
+    conn.execute("DELETE FROM rate_limits WHERE bucket = ?1 AND key = ?2", params![bucket.as_str(), key])
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Seconds until `key` may try again, or None while it is under the limit.
+pub fn retry_after(bucket: Bucket, key: &str, limit: Limit) -> Option<u64> {
+    retry_after_at(&crate::state::get().db().lock(), bucket, key, limit, now())
+}
+
+/// Count one request against `key`.
+pub fn hit(bucket: Bucket, key: &str, limit: Limit) {
+    if let Err(e) = hit_at(&crate::state::get().db().lock(), bucket, key, limit, now()) {
+        tracing::error!("failed to count {} for {}: {}", bucket.as_str(), key, e);
+    }
+}
+
+/// Forget `key`'s count (after a successful login).
+pub fn clear(bucket: Bucket, key: &str) {
+    if let Err(e) = clear_in(&crate::state::get().db().lock(), bucket, key) {
+        tracing::error!("failed to clear {} for {}: {}", bucket.as_str(), key, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refuses_once_full_until_window_ends() {
+        let conn = Connection::open_in_memory().unwrap();
+        run_ratelimit_migrations(&conn);
+        let limit = Limit { max: 3, window_secs: 60 };
+        let t = 1_000_000;
+        for i in 0..3 {
+            assert_eq!(retry_after_at(&conn, Bucket::Auth, "ip:10.0.0.9", limit, t + i), None);
+            hit_at(&conn, Bucket::Auth, "ip:10.0.0.9", limit, t + i).unwrap();
+        }
+        assert_eq!(retry_after_at(&conn, Bucket::Auth, "ip:10.0.0.9", limit, t + 10), Some(50));
+        // Other keys and buckets have their own budget
+        assert_eq!(retry_after_at(&conn, Bucket::Auth, "ip:10.0.0.10", limit, t + 10), None);
+        assert_eq!(retry_after_at(&conn, Bucket::Pairing, "ip:10.0.0.9", limit, t + 10), None);
+        // A new window starts from scratch
+        assert_eq!(retry_after_at(&conn, Bucket::Auth, "ip:10.0.0.9", limit, t + 60), None);
+        hit_at(&conn, Bucket::Auth, "ip:10.0.0.9", limit, t + 60).unwrap();
+        assert_eq!(current_count(&conn, Bucket::Auth, "ip:10.0.0.9", limit, t + 61), Some((1, t + 60)));
+
+        clear_in(&conn, Bucket::Auth, "ip:10.0.0.9").unwrap();
+        assert_eq!(current_count(&conn, Bucket::Auth, "ip:10.0.0.9", limit, t + 61), None);
+    }
+
+    #[test]
+    fn routes_map_to_buckets() {
+        use axum::http::Method;
+        assert_eq!(Bucket::for_route(&Method::POST, "/api/v1/auth/pin-login"), Some(Bucket::Auth));
+        assert_eq!(Bucket::for_route(&Method::POST, "/api/auth/pair"), Some(Bucket::Pairing));
+        assert_eq!(Bucket::for_route(&Method::POST, "/api/sessions/:session_id/input"), Some(Bucket::Messages));
+        assert_eq!(Bucket::for_route(&Method::GET, "/api/auth/pin-status"), None);
+        assert_eq!(Bucket::for_route(&Method::POST, "/api/sessions"), None);
+
+        let mut overrides = HashMap::new();
+        overrides.insert("messages".to_string(), Limit { max: 0, window_secs: 60 });
+        overrides.insert("auth".to_string(), Limit { max: 10, window_secs: 600 });
+        assert_eq!(Bucket::Messages.limit(&overrides), None);
+        assert_eq!(Bucket::Auth.limit(&overrides), Some(Limit { max: 10, window_secs: 600 }));
+        assert_eq!(Bucket::Pairing.limit(&overrides), Some(Bucket::Pairing.default_limit()));
+    }
+}
@@ -0,0 +1,216 @@
+// Sessions on a remote hub (see hubs.rs) opened in this app's own UI.
+//
+// The app attaches to the session as a client of the hub's mobile WebSocket,
+// signed in with the hub's token, and from then on the session looks like a
+// local one to the frontend: output arrives as the same events
+// (json-process-message for chat sessions, pty-output / pty-exit for
+// terminals, each with an extra `hub_id`), and write / interrupt / resize
+// mirror write_to_process, interrupt_json_process, write_pty and resize_pty.
+// A terminal's saved scrollback arrives once as remote-session-buffer, a
+// chat session's history as remote-session-history, and errors the hub
+// reports about the connection as remote-session-error.
+
+use futures::{SinkExt, StreamExt};
+use tauri::{AppHandle, Emitter};
+use tokio_tungstenite::tungstenite::Message as HubMessage;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Chat,
+    Terminal,
+}
+
+pub(crate) struct Attachment {
+    kind: Kind,
+    /// Messages for the hub, in the mobile protocol
+    outbox: tokio::sync::mpsc::UnboundedSender<String>,
+    task: tauri::async_runtime::JoinHandle<()>,
+}
+
+/// The frontend event for a message from the hub about `session_id`, if any.
+/// `ended` is set for messages after which the session won't send more.
+fn translate(kind: Kind, session_id: &str, hub_id: &str, message: &serde_json::Value) -> Option<(&'static str, serde_json::Value, bool)> {
+    use base64::Engine;
+
+    if message["sessionId"] != session_id {
+        // Errors aren't addressed to a session; on this connection they're ours
+        return (message["type"] == "error").then(|| {
+            (
+                "remote-session-error",
+                serde_json::json!({ "session_id": session_id, "hub_id": hub_id, "error": message["message"] }),
+                false,
+            )
+        });
+    }
+    let event = match (kind, message["type"].as_str()?) {
+        (Kind::Chat, "chat_message") => (
+            "json-process-message",
+            serde_json::json!({ "session_id": session_id, "hub_id": hub_id, "message": message["message"] }),
+            false,
+        ),
+        (Kind::Chat, "chat_history") => (
+            "remote-session-history",
+            serde_json::json!({ "session_id": session_id, "hub_id": hub_id, "messages": message["messages"] }),
+            false,
+        ),
+        (Kind::Chat, "session_error") => (
+            "json-process-error",
+            serde_json::json!({ "session_id": session_id, "hub_id": hub_id, "error": message["error"]["message"] }),
+            false,
+        ),
+        (Kind::Terminal, "terminal_subscribed") => (
+            "remote-session-buffer",
+            serde_json::json!({ "session_id": session_id, "hub_id": hub_id, "buffer": message["buffer"] }),
+            false,
+        ),
+        (Kind::Terminal, "terminal_output") => {
+            let data = base64::engine::general_purpose::STANDARD.decode(message["data"].as_str()?).ok()?;
+            (
+                "pty-output",
+                serde_json::json!({ "session_id": session_id, "hub_id": hub_id, "data": String::from_utf8_lossy(&data) }),
+                false,
+            )
+        }
+        (Kind::Terminal, "terminal_closed") | (Kind::Terminal, "session_deleted") => {
+            ("pty-exit", serde_json::json!({ "session_id": session_id, "hub_id": hub_id, "data": "" }), true)
+        }
+        (Kind::Chat, "session_deleted") => (
+            "json-process-exit",
+            serde_json::json!({ "session_id": session_id, "hub_id": hub_id, "exit_code": null }),
+            true,
+        ),
+        _ => return None,
+    };
+    Some(event)
+}
+
+fn send(session_id: &str, message: serde_json::Value) -> Result<(), String> {
+    let attached = crate::state::get().remote_attached.lock();
+    let attachment = attached.get(session_id).ok_or_else(|| format!("Remote session {} is not attached", session_id))?;
+    attachment.outbox.send(message.to_string()).map_err(|_| "The connection to the hub was closed".to_string())
+}
+
+/// Open a session on a hub. A session that's already attached is attached
+/// again, which reloads its history or scrollback.
+pub async fn attach(app: AppHandle, hub_id: &str, session_id: &str, terminal: bool) -> Result<(), String> {
+    let hub = crate::hubs::get(hub_id).ok_or("Hub not found")?;
+    let kind = if terminal { Kind::Terminal } else { Kind::Chat };
+    let mut socket = crate::hubs::connect_as_client(&hub).await?;
+    let subscribe = match kind {
+        Kind::Chat => serde_json::json!({ "type": "subscribe", "sessionId": session_id }),
+        Kind::Terminal => serde_json::json!({ "type": "subscribe_terminal", "sessionId": session_id }),
+    };
+    socket.send(HubMessage::Text(subscribe.to_string())).await.map_err(|e| e.to_string())?;
+
+    let (outbox, mut outgoing) = tokio::sync::mpsc::unbounded_channel::<String>();
+    let id = session_id.to_string();
+    let task = tauri::async_runtime::spawn(async move {
+        let mut ended = false;
+        loop {
+            tokio::select! {
+                message = outgoing.recv() => {
+                    let Some(text) = message else { break };
+                    if socket.send(HubMessage::Text(text)).await.is_err() {
+                        break;
+                    }
+                }
+                frame = socket.next() => {
+                    let Some(Ok(HubMessage::Text(text))) = frame else {
+                        match frame {
+                            Some(Ok(_)) => continue,
+                            _ => break,
+                        }
+                    };
+                    let Ok(message) = serde_json::from_str::<serde_json::Value>(&text) else { continue };
+                    if let Some((event, payload, last)) = translate(kind, &id, &hub.id, &message) {
+                        let _ = app.emit(event, payload);
+                        if last {
+                            ended = true;
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        let _ = socket.close(None).await;
+        // A dropped connection ends the session for the frontend as well
+        if !ended {
+            let (event, payload) = match kind {
+                Kind::Chat => ("json-process-exit", serde_json::json!({ "session_id": id, "hub_id": hub.id, "exit_code": null })),
+                Kind::Terminal => ("pty-exit", serde_json::json!({ "session_id": id, "hub_id": hub.id, "data": "" })),
+            };
+            let _ = app.emit(event, payload);
+        }
+        // Unless the session has been attached again since
+        drop(outgoing);
+        let mut attached = crate::state::get().remote_attached.lock();
+        if attached.get(&id).is_some_and(|a| a.outbox.is_closed()) {
+            attached.remove(&id);
+        }
+    });
+
+    let attachment = Attachment { kind, outbox, task };
+    if let Some(previous) = crate::state::get().remote_attached.lock().insert(session_id.to_string(), attachment) {
+        previous.task.abort();
+    }
+    Ok(())
+}
+
+/// For chat sessions a stream-json line, as written to a local process's
+/// stdin; for terminals keystrokes.
+pub fn write(session_id: &str, data: &str) -> Result<(), String> {
+    let kind = crate::state::get().remote_attached.lock().get(session_id).map(|a| a.kind);
+    let message = match kind {
+        Some(Kind::Terminal) => serde_json::json!({ "type": "terminal_input", "sessionId": session_id, "data": data }),
+        _ => serde_json::json!({ "type": "send_message", "sessionId": session_id, "content": data }),
+    };
+    send(session_id, message)
+}
+
+pub fn interrupt(session_id: &str) -> Result<(), String> {
+    let kind = crate::state::get().remote_attached.lock().get(session_id).map(|a| a.kind);
+    let message = match kind {
+        // Ctrl+C, as the terminal would send it
+        Some(Kind::Terminal) => serde_json::json!({ "type": "terminal_input", "sessionId": session_id, "data": "\u{3}" }),
+        _ => serde_json::json!({ "type": "interrupt", "sessionId": session_id }),
+    };
+    send(session_id, message)
+}
+
+pub fn resize(session_id: &str, cols: u16, rows: u16) -> Result<(), String> {
+    if crate::state::get().remote_attached.lock().get(session_id).map(|a| a.kind) != Some(Kind::Terminal) {
+        return Err("Only terminal sessions can be resized".to_string());
+    }
+    send(session_id, serde_json::json!({ "type": "terminal_resize", "sessionId": session_id, "cols": cols, "rows": rows }))
+}
+
+/// Close the connection; the session keeps running on its hub.
+pub fn detach(session_id: &str) {
+    if let Some(attachment) = crate::state::get().remote_attached.lock().remove(session_id) {
+        attachment.task.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hub_messages_become_local_session_events() {
+        let chat = |m: serde_json::Value| translate(Kind::Chat, "s1", "h1", &m);
+        let (event, payload, ended) = chat(serde_json::json!({ "type": "chat_message", "sessionId": "s1", "seq": 4, "message": { "type": "assistant" } })).unwrap();
+        assert_eq!((event, ended), ("json-process-message", false));
+        assert_eq!(payload["hub_id"], "h1");
+        assert_eq!(payload["message"]["type"], "assistant");
+        // Other sessions' traffic on the same connection is ignored
+        assert!(chat(serde_json::json!({ "type": "chat_message", "sessionId": "s2", "message": {} })).is_none());
+        assert!(chat(serde_json::json!({ "type": "session_deleted", "sessionId": "s1" })).unwrap().2);
+
+        let terminal = |m: serde_json::Value| translate(Kind::Terminal, "s1", "h1", &m);
+        let (event, payload, _) = terminal(serde_json::json!({ "type": "terminal_output", "sessionId": "s1", "data": "aGkNCg==" })).unwrap();
+        assert_eq!(event, "pty-output");
+        assert_eq!(payload["data"], "hi\r\n");
+        assert_eq!(terminal(serde_json::json!({ "type": "terminal_closed", "sessionId": "s1" })).unwrap().0, "pty-exit");
+        assert_eq!(terminal(serde_json::json!({ "type": "error", "message": "Terminal session not running" })).unwrap().0, "remote-session-error");
+    }
+}
@@ -0,0 +1,128 @@
+// Automatic restart of claude-json processes that die unexpectedly.
+//
+// When a process exits with a failure status (or is killed by a signal we
+// didn't send), it is restarted after an exponential backoff, resuming the
+// Claude conversation via its recorded claude_session_id. A run that stays
+// up for STABLE_AFTER resets the attempt count, so only crash loops exhaust
+// the `auto_restart_max_attempts` budget. Failures that a restart can't fix
+// (CLI missing, not logged in, context full) are never retried.
+
+use std::time::{Duration, Instant};
+use tauri::Emitter;
+
+const BASE_DELAY: Duration = Duration::from_secs(5);
+const MAX_DELAY: Duration = Duration::from_secs(300);
+const STABLE_AFTER: Duration = Duration::from_secs(300);
+/// An exit this soon after we stopped/interrupted a session is ours.
+const INTENTIONAL_WINDOW: Duration = Duration::from_secs(10);
+
+pub(crate) struct RestartState {
+    attempts: u32,
+    started_at: Instant,
+}
+
+/// A process for the session was started.
+pub fn on_started(session_id: &str) {
+    let mut state = crate::state::get().restarts.lock();
+    let entry = state
+        .entry(session_id.to_string())
+        .or_insert(RestartState { attempts: 0, started_at: Instant::now() });
+    entry.started_at = Instant::now();
+}
+
+/// The session is being stopped or interrupted on purpose; its exit must
+/// not trigger a restart.
+pub fn mark_stopped(session_id: &str) {
+    crate::state::get().restarts_stopped.lock().insert(session_id.to_string(), Instant::now());
+}
+
+/// Drop tracked state (session deleted).
+pub fn forget(session_id: &str) {
+    crate::state::get().restarts.lock().remove(session_id);
+    crate::state::get().restarts_stopped.lock().remove(session_id);
+}
+
+/// Delay before restart attempt `attempt` (1-based).
+fn backoff(attempt: u32) -> Duration {
+    let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+    BASE_DELAY.saturating_mul(factor).min(MAX_DELAY)
+}
+
+/// Called once a process has exited and been cleaned up. `exit_code` is None
+/// when the process was killed by a signal.
+pub fn on_exit(session_id: &str, exit_code: Option<i32>) {
+    let intentional = crate::state::get().restarts_stopped
+        .lock()
+        .remove(session_id)
+        .map(|at| at.elapsed() < INTENTIONAL_WINDOW)
+        .unwrap_or(false);
+    if intentional || exit_code == Some(0) || crate::session_errors::has_fatal(session_id) {
+        if let Some(state) = crate::state::get().restarts.lock().get_mut(session_id) {
+            state.attempts = 0;
+        }
+        return;
+    }
+
+    let max_attempts = crate::load_app_settings().unwrap_or_default().auto_restart_max_attempts;
+    let attempt = {
+        let mut state = crate::state::get().restarts.lock();
+        let Some(entry) = state.get_mut(session_id) else { return };
+        if entry.started_at.elapsed() >= STABLE_AFTER {
+            entry.attempts = 0;
+        }
+        if entry.attempts >= max_attempts {
+            tracing::error!("{} exited ({:?}); giving up after {} attempts", session_id, exit_code, entry.attempts);
+            return;
+        }
+        entry.attempts += 1;
+        entry.attempts
+    };
+
+    let delay = backoff(attempt);
+    tracing::warn!("{} exited ({:?}); restarting in {}s (attempt {}/{})", session_id, exit_code, delay.as_secs(), attempt, max_attempts);
+    let session_id = session_id.to_string();
+    std::thread::spawn(move || {
+        std::thread::sleep(delay);
+        if crate::state::get().json_processes.lock().contains_key(&session_id) {
+            return; // Started again by someone else meanwhile
+        }
+        if crate::state::get().restarts_stopped.lock().contains_key(&session_id) {
+            return;
+        }
+        if let Err(e) = crate::ensure_json_session_running(&session_id) {
+            tracing::error!("failed to restart {}: {}", session_id, e);
+            return;
+        }
+        let event = crate::events::SessionEvent::SessionRestarted {
+            session_id: session_id.clone(),
+            attempt,
+            max_attempts,
+            exit_code,
+        };
+        if let Some(app) = crate::state::get().app_handle.lock().as_ref() {
+            let _ = app.emit("session-restarted", event.data());
+        }
+        crate::broadcast_session_event(event);
+        let msg = serde_json::json!({
+            "type": "session_restarted",
+            "sessionId": session_id,
+            "attempt": attempt,
+            "maxAttempts": max_attempts,
+        })
+        .to_string();
+        crate::broadcast_to_mobile_clients(&msg);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_cap() {
+        assert_eq!(backoff(1), Duration::from_secs(5));
+        assert_eq!(backoff(2), Duration::from_secs(10));
+        assert_eq!(backoff(4), Duration::from_secs(40));
+        assert_eq!(backoff(40), MAX_DELAY);
+    }
+}
@@ -0,0 +1,83 @@
+// What a WebSocket client that fell behind a broadcast channel gets in place
+// of the messages it missed.
+//
+// A lagging tokio broadcast receiver silently loses the oldest messages.
+// Carrying on from that gap leaves a garbled terminal, missing chat lines or
+// stale running flags, so forwarders instead count the loss, jump to the
+// newest message and send a snapshot first:
+//   - PTY: clear the screen, then the last SCREEN_LINES of term_screen's
+//     plain-text copy (colors are lost until the program redraws)
+//   - JSON: {"type":"resync","session_id","messages":[...]}, the session's
+//     whole history, which replaces what the client has
+//   - status: {"type":"resync","sessions":[...]}, listed as GET /api/sessions
+
+use crate::metrics::{self, Stream};
+use tokio::sync::broadcast::Receiver;
+
+const SCREEN_LINES: usize = 200;
+
+/// Count the loss and return a receiver at the newest message
+pub fn resubscribe<T: Clone>(stream: Stream, rx: &Receiver<T>, missed: u64, session_id: Option<&str>) -> Receiver<T> {
+    metrics::on_lagged(stream, missed);
+    tracing::warn!("{:?} subscriber for {} missed {} messages; resyncing", stream, session_id.unwrap_or("all sessions"), missed);
+    rx.resubscribe()
+}
+
+/// Terminal bytes that redraw a PTY session from its server-side copy
+pub fn pty(session_id: &str) -> Vec<u8> {
+    let screen = crate::term_screen::tail(session_id, SCREEN_LINES).unwrap_or_default();
+    pty_redraw(&screen)
+}
+
+fn pty_redraw(screen: &str) -> Vec<u8> {
+    // Reset attributes, clear screen and scrollback, cursor home
+    let mut bytes = b"\x1b[0m\x1b[2J\x1b[3J\x1b[H".to_vec();
+    bytes.extend(screen.replace('\n', "\r\n").into_bytes());
+    bytes
+}
+
+/// A claude-json session's full message history
+pub fn json(session_id: &str) -> String {
+    crate::ensure_session_messages_loaded(session_id);
+    let messages = crate::state::get().session_messages.lock().get(session_id).cloned().unwrap_or_default();
+    serde_json::json!({
+        "type": "resync",
+        "session_id": session_id,
+        "messages": messages,
+    })
+    .to_string()
+}
+
+/// Every visible session with its running state
+pub fn status() -> String {
+    serde_json::json!({
+        "type": "resync",
+        "sessions": crate::listed_sessions().unwrap_or_default(),
+    })
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::broadcast;
+
+    #[test]
+    fn redraw_clears_then_writes_lines() {
+        let bytes = pty_redraw("$ ls\nfile");
+        assert_eq!(bytes, b"\x1b[0m\x1b[2J\x1b[3J\x1b[H$ ls\r\nfile".to_vec());
+    }
+
+    #[test]
+    fn resubscribed_receiver_skips_the_backlog() {
+        let (tx, mut rx) = broadcast::channel::<u32>(2);
+        for n in 0..5 {
+            tx.send(n).unwrap();
+        }
+        let Err(broadcast::error::TryRecvError::Lagged(missed)) = rx.try_recv() else { panic!("expected a lag") };
+        let mut rx = resubscribe(Stream::Pty, &rx, missed, Some("s1"));
+        assert!(rx.try_recv().is_err());
+        tx.send(5).unwrap();
+        assert_eq!(rx.try_recv().unwrap(), 5);
+    }
+}
@@ -0,0 +1,384 @@
+// Headless one-shot runs (`POST /api/run`): a transient claude process that
+// answers a single prompt and exits, so scripts and CI jobs can use Agent Hub
+// as a local agent gateway.
+//
+// Each run is recorded in `headless_runs` with its outcome and usage. Output
+// is streamed to the caller as it arrives; with `persist` the transcript is
+// also saved as a claude-json session that can be resumed from the UI.
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::process::Stdio;
+use std::time::Duration;
+use tauri::Emitter;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc;
+
+const DEFAULT_TIMEOUT_SECS: u64 = 600;
+/// Stderr kept for the error message of a failed run.
+const MAX_STDERR_CHARS: usize = 2_000;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RunRequest {
+    pub prompt: String,
+    #[serde(default)]
+    pub working_dir: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Save the transcript as a session.
+    #[serde(default)]
+    pub persist: bool,
+    /// Name for the persisted session.
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RunRecord {
+    pub id: String,
+    pub prompt: String,
+    pub working_dir: String,
+    /// running | completed | error | timeout
+    pub status: String,
+    pub result: Option<String>,
+    pub cost_usd: Option<f64>,
+    pub input_tokens: Option<u64>,
+    pub output_tokens: Option<u64>,
+    pub duration_ms: Option<f64>,
+    pub claude_session_id: Option<String>,
+    /// Agent Hub session holding the transcript (persisted runs only).
+    pub session_id: Option<String>,
+    pub created_at: String,
+    pub finished_at: Option<String>,
+}
+
+pub enum RunEvent {
+    /// A stream-json message from the CLI.
+    Message(serde_json::Value),
+    /// The run finished; always the last event.
+    Done(Box<RunRecord>),
+}
+
+pub fn run_runs_migrations(conn: &Connection) {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS headless_runs (
+            id TEXT PRIMARY KEY,
+            prompt TEXT NOT NULL,
+            working_dir TEXT NOT NULL,
+            status TEXT NOT NULL,
+            result TEXT,
+            cost_usd REAL,
+            input_tokens INTEGER,
+            output_tokens INTEGER,
+            duration_ms REAL,
+            claude_session_id TEXT,
+            session_id TEXT,
+            created_at TEXT NOT NULL,
+            finished_at TEXT
+        )",
+        [],
+    )
+    .expect("Failed to create headless_runs table");
+}
+
+fn save_record(record: &RunRecord) -> Result<(), String> {
+    let conn = crate::state::get().db().lock();
+    conn.execute(
+        "INSERT OR REPLACE INTO headless_runs
+            (id, prompt, working_dir, status, result, cost_usd, input_tokens, output_tokens,
+             duration_ms, claude_session_id, session_id, created_at, finished_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+        params![
+            record.id,
+            record.prompt,
+            record.working_dir,
+            record.status,
+            record.result,
+            record.cost_usd,
+            record.input_tokens.map(|n| n as i64),
+            record.output_tokens.map(|n| n as i64),
+            record.duration_ms,
+            record.claude_session_id,
+            record.session_id,
+            record.created_at,
+            record.finished_at,
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+pub fn get_run(id: &str) -> Result<Option<RunRecord>, String> {
+    let conn = crate::state::get().db().lock();
+    conn.query_row(
+        "SELECT id, prompt, working_dir, status, result, cost_usd, input_tokens, output_tokens,
+                duration_ms, claude_session_id, session_id, created_at, finished_at
+         FROM headless_runs WHERE id = ?1",
+        params![id],
+        |r| {
+            Ok(RunRecord {
+                id: r.get(0)?,
+                prompt: r.get(1)?,
+                working_dir: r.get(2)?,
+                status: r.get(3)?,
+                result: r.get(4)?,
+                cost_usd: r.get(5)?,
+                input_tokens: r.get::<_, Option<i64>>(6)?.map(|n| n as u64),
+                output_tokens: r.get::<_, Option<i64>>(7)?.map(|n| n as u64),
+                duration_ms: r.get(8)?,
+                claude_session_id: r.get(9)?,
+                session_id: r.get(10)?,
+                created_at: r.get(11)?,
+                finished_at: r.get(12)?,
+            })
+        },
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+/// The configured claude-json command, with `model` forced if given.
+fn build_command(base: &str, model: Option<&str>) -> String {
+    match model.filter(|m| !m.is_empty()) {
+        Some(m) if !base.contains("--model") => {
+            base.replacen("claude ", &format!("claude --model '{}' ", m.replace('\'', "'\\''")), 1)
+        }
+        _ => base.to_string(),
+    }
+}
+
+/// Fold a stream-json message into the run record.
+fn apply_message(record: &mut RunRecord, msg: &serde_json::Value) {
+    let field = |k: &str| msg.get(k);
+    match field("type").and_then(|t| t.as_str()) {
+        Some("system") if field("subtype").and_then(|s| s.as_str()) == Some("init") => {
+            record.claude_session_id = field("session_id").and_then(|s| s.as_str()).map(String::from);
+        }
+        Some("result") => {
+            let is_error = field("is_error").and_then(|e| e.as_bool()).unwrap_or(false);
+            record.status = if is_error { "error" } else { "completed" }.to_string();
+            record.result = field("result").and_then(|r| r.as_str()).map(String::from);
+            record.cost_usd = field("total_cost_usd").and_then(|c| c.as_f64());
+            record.duration_ms = field("duration_ms").and_then(|d| d.as_f64());
+            record.input_tokens = msg.pointer("/usage/input_tokens").and_then(|n| n.as_u64());
+            record.output_tokens = msg.pointer("/usage/output_tokens").and_then(|n| n.as_u64());
+        }
+        _ => {}
+    }
+}
+
+/// Start a run. Must be called from within the tokio runtime; events arrive
+/// on the returned channel until `RunEvent::Done`.
+pub fn start(req: RunRequest) -> Result<(String, mpsc::UnboundedReceiver<RunEvent>), String> {
+    if req.prompt.trim().is_empty() {
+        return Err("prompt is required".to_string());
+    }
+    let settings = crate::load_app_settings().unwrap_or_default();
+    let working_dir = req
+        .working_dir
+        .clone()
+        .filter(|d| !d.trim().is_empty())
+        .unwrap_or_else(|| settings.default_working_dir.clone());
+    let work_dir = shellexpand::tilde(&working_dir).to_string();
+    if !std::path::Path::new(&work_dir).is_dir() {
+        return Err(format!("Working directory not found: {}", working_dir));
+    }
+    let base = crate::agent_commands::command_for("claude-json")
+        .unwrap_or_else(|| crate::agent_commands::CLAUDE_JSON_COMMAND.to_string());
+    let command = build_command(&base, req.model.as_deref());
+
+    let mut record = RunRecord {
+        id: uuid::Uuid::new_v4().to_string(),
+        prompt: req.prompt.clone(),
+        working_dir,
+        status: "running".to_string(),
+        result: None,
+        cost_usd: None,
+        input_tokens: None,
+        output_tokens: None,
+        duration_ms: None,
+        claude_session_id: None,
+        session_id: None,
+        created_at: chrono::Utc::now().to_rfc3339(),
+        finished_at: None,
+    };
+    save_record(&record)?;
+
+    let shell = crate::platform::user_shell();
+    let mut cmd = tokio::process::Command::new(&shell);
+    cmd.args(["-l", "-c", &command])
+        .env("PATH", crate::platform::agent_path())
+        .current_dir(&work_dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    if let Some(dir) = settings.claude_config_dir.filter(|d| !d.is_empty()) {
+        cmd.env("CLAUDE_CONFIG_DIR", shellexpand::tilde(&dir).to_string());
+    }
+    let mut child = cmd.spawn().map_err(|e| format!("Failed to start claude: {}", e))?;
+
+    let prompt_envelope = serde_json::json!({
+        "type": "user",
+        "message": { "role": "user", "content": [{ "type": "text", "text": req.prompt }] }
+    });
+    let (tx, rx) = mpsc::unbounded_channel();
+    let run_id = record.id.clone();
+
+    tokio::spawn(async move {
+        let mut stdin = child.stdin.take();
+        let stdout = child.stdout.take();
+        let mut stderr = child.stderr.take();
+        if let Some(stdin) = stdin.as_mut() {
+            let _ = stdin.write_all(format!("{}\n", prompt_envelope).as_bytes()).await;
+        }
+        // Closing stdin ends the conversation after this turn
+        drop(stdin);
+
+        let stderr_task = tokio::spawn(async move {
+            let mut text = String::new();
+            if let Some(stderr) = stderr.as_mut() {
+                let _ = stderr.read_to_string(&mut text).await;
+            }
+            text
+        });
+
+        let mut transcript = vec![prompt_envelope];
+        let timeout = Duration::from_secs(req.timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS));
+        let read = tokio::time::timeout(timeout, async {
+            let Some(stdout) = stdout else { return };
+            let mut lines = BufReader::new(stdout).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                let line = crate::redaction::redact_output(&line).into_owned();
+                let Ok(msg) = serde_json::from_str::<serde_json::Value>(&line) else { continue };
+                apply_message(&mut record, &msg);
+                transcript.push(msg.clone());
+                let _ = tx.send(RunEvent::Message(msg));
+            }
+        })
+        .await;
+
+        if read.is_err() {
+            let _ = child.kill().await;
+            record.status = "timeout".to_string();
+        } else {
+            let _ = child.wait().await;
+        }
+        let stderr_text = stderr_task.await.unwrap_or_default();
+        if record.status == "running" {
+            record.status = "error".to_string();
+        }
+        if record.status != "completed" && record.result.is_none() {
+            let chars: Vec<char> = stderr_text.trim().chars().collect();
+            let tail: String = chars[chars.len().saturating_sub(MAX_STDERR_CHARS)..].iter().collect();
+            if !tail.is_empty() {
+                record.result = Some(tail);
+            }
+        }
+        if req.persist {
+            match persist_transcript(&record, req.name.as_deref(), &transcript) {
+                Ok(session_id) => record.session_id = Some(session_id),
+                Err(e) => tracing::error!("failed to persist run {}: {}", record.id, e),
+            }
+        }
+        record.finished_at = Some(chrono::Utc::now().to_rfc3339());
+        if let Err(e) = save_record(&record) {
+            tracing::error!("failed to record run {}: {}", record.id, e);
+        }
+        let _ = tx.send(RunEvent::Done(Box::new(record)));
+    });
+
+    Ok((run_id, rx))
+}
+
+/// Save a finished run as a stopped claude-json session; resuming it
+/// continues the CLI conversation.
+fn persist_transcript(record: &RunRecord, name: Option<&str>, transcript: &[serde_json::Value]) -> Result<String, String> {
+    let name = name.map(str::trim).filter(|n| !n.is_empty()).map(String::from).unwrap_or_else(|| {
+        let first_line = record.prompt.lines().next().unwrap_or("").trim();
+        let short: String = first_line.chars().take(40).collect();
+        format!("[Run] {}", short)
+    });
+    let session = crate::SessionData {
+        id: crate::generate_token(),
+        name,
+        agent_type: "claude-json".to_string(),
+        command: crate::agent_commands::command_for("claude-json")
+            .unwrap_or_else(|| crate::agent_commands::CLAUDE_JSON_COMMAND.to_string()),
+        working_dir: record.working_dir.clone(),
+        created_at: record.created_at.clone(),
+        claude_session_id: record.claude_session_id.clone(),
+        sort_order: 0,
+        folder_id: None,
+        env_vars: None,
+        parent_id: None,
+        summary: None,
+        remote_access: true,
+        workspace_id: None,
+    };
+    crate::save_session(session.clone())?;
+    let buffer = serde_json::to_string(transcript).map_err(|e| e.to_string())?;
+    crate::save_terminal_buffer_to_db(&session.id, &buffer)?;
+
+    if let Some(app) = crate::state::get().app_handle.lock().as_ref() {
+        let _ = app.emit("remote-session-created", serde_json::json!({
+            "session": {
+                "id": session.id,
+                "name": session.name,
+                "agent_type": session.agent_type,
+                "working_dir": session.working_dir,
+            }
+        }));
+    }
+    crate::broadcast_session_created(&session);
+    crate::broadcast_session_list_to_mobile();
+    Ok(session.id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record() -> RunRecord {
+        RunRecord {
+            id: "r".to_string(),
+            prompt: "p".to_string(),
+            working_dir: "/tmp".to_string(),
+            status: "running".to_string(),
+            result: None,
+            cost_usd: None,
+            input_tokens: None,
+            output_tokens: None,
+            duration_ms: None,
+            claude_session_id: None,
+            session_id: None,
+            created_at: String::new(),
+            finished_at: None,
+        }
+    }
+
+    #[test]
+    fn result_message_records_usage() {
+        let mut r = record();
+        apply_message(&mut r, &serde_json::json!({"type": "system", "subtype": "init", "session_id": "abc"}));
+        apply_message(&mut r, &serde_json::json!({
+            "type": "result", "subtype": "success", "is_error": false, "result": "done",
+            "total_cost_usd": 0.12, "duration_ms": 3400.0,
+            "usage": {"input_tokens": 10, "output_tokens": 200}
+        }));
+        assert_eq!(r.claude_session_id.as_deref(), Some("abc"));
+        assert_eq!(r.status, "completed");
+        assert_eq!(r.result.as_deref(), Some("done"));
+        assert_eq!(r.cost_usd, Some(0.12));
+        assert_eq!((r.input_tokens, r.output_tokens), (Some(10), Some(200)));
+    }
+
+    #[test]
+    fn model_is_added_unless_already_set() {
+        assert_eq!(build_command("claude --print", Some("claude-haiku-4-5")), "claude --model 'claude-haiku-4-5' --print");
+        assert_eq!(build_command("claude --model x --print", Some("y")), "claude --model x --print");
+        assert_eq!(build_command("claude --print", None), "claude --print");
+    }
+}
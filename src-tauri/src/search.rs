@@ -23,7 +23,7 @@ use tauri::Emitter;
 /// don't get these events directly — the mobile Settings page is rare
 /// enough that the simple "click Rebuild → wait" UX is acceptable for it.
 fn emit_progress(payload: serde_json::Value) {
-    if let Some(app) = crate::APP_HANDLE.lock().as_ref() {
+    if let Some(app) = crate::state::get().app_handle.lock().as_ref() {
         let _ = app.emit("search-progress", payload);
     }
 }
@@ -409,7 +409,7 @@ fn lookup_session_by_parent_uuids(conn: &Connection, parents: &[String]) -> Opti
 /// First-time backfill: skips immediately if `session_files` already has rows.
 pub fn backfill_if_needed() -> BackfillStats {
     let already = {
-        let conn = crate::DB_CONNECTION.lock();
+        let conn = crate::state::get().db().lock();
         conn.query_row("SELECT COUNT(*) FROM session_files", [], |r| {
             r.get::<_, i64>(0)
         })
@@ -425,7 +425,7 @@ pub fn backfill_if_needed() -> BackfillStats {
 /// Manual rebuild — wipes index + bookkeeping, then re-runs backfill.
 pub fn rebuild_index() -> BackfillStats {
     {
-        let conn = crate::DB_CONNECTION.lock();
+        let conn = crate::state::get().db().lock();
         let _ = conn.execute("DELETE FROM message_index", []);
         let _ = conn.execute("DELETE FROM session_files", []);
     }
@@ -451,7 +451,7 @@ fn run_backfill() -> BackfillStats {
             None => continue,
         };
         let session_id = {
-            let conn = crate::DB_CONNECTION.lock();
+            let conn = crate::state::get().db().lock();
             lookup_session_by_claude_id(&conn, &claude_session_id)
         };
         match session_id {
@@ -485,7 +485,7 @@ fn run_backfill() -> BackfillStats {
             };
             let parents = collect_candidate_parent_uuids(&path);
             let session_id = {
-                let conn = crate::DB_CONNECTION.lock();
+                let conn = crate::state::get().db().lock();
                 lookup_session_by_parent_uuids(&conn, &parents)
             };
             match session_id {
@@ -502,8 +502,8 @@ fn run_backfill() -> BackfillStats {
     }
 
     stats.files_skipped_unlinked = unlinked.len() as u32;
-    eprintln!(
-        "[search] backfill done in {:.1}s: scanned={} ingested={} rows={} unlinked={} uptodate={} errors={}",
+    tracing::info!(
+        "backfill done in {:.1}s: scanned={} ingested={} rows={} unlinked={} uptodate={} errors={}",
         start.elapsed().as_secs_f64(),
         stats.files_scanned,
         stats.files_ingested,
@@ -527,7 +527,7 @@ fn run_backfill() -> BackfillStats {
 /// mtime/size has changed. Cheap when nothing has changed.
 pub fn incremental_rescan() -> BackfillStats {
     let known: Vec<(String, String, String, i64, i64, Option<String>)> = {
-        let conn = crate::DB_CONNECTION.lock();
+        let conn = crate::state::get().db().lock();
         let mut stmt = match conn.prepare(
             "SELECT claude_session_id, session_id, file_path, last_mtime, last_size, claude_home FROM session_files",
         ) {
@@ -568,8 +568,8 @@ pub fn incremental_rescan() -> BackfillStats {
         ingest_one(&path, &session_id, &claude_session_id, &home, &mut stats);
     }
     if stats.files_ingested > 0 || stats.rows_inserted > 0 {
-        eprintln!(
-            "[search] rescan: ingested={} rows={} errors={}",
+        tracing::info!(
+            "rescan: ingested={} rows={} errors={}",
             stats.files_ingested, stats.rows_inserted, stats.errors
         );
     }
@@ -580,7 +580,7 @@ pub fn incremental_rescan() -> BackfillStats {
 /// and ingest any new bytes. Idempotent. Called from save_session_messages_to_db.
 pub fn ingest_session_files(session_id: &str) {
     let files: Vec<(String, String, Option<String>)> = {
-        let conn = crate::DB_CONNECTION.lock();
+        let conn = crate::state::get().db().lock();
         let mut stmt = match conn.prepare(
             "SELECT claude_session_id, file_path, claude_home FROM session_files WHERE session_id = ?1",
         ) {
@@ -620,7 +620,7 @@ pub fn ingest_session_files(session_id: &str) {
 /// Return cwd, claude_session_id, and CLAUDE_CONFIG_DIR override (parsed
 /// from sessions.env_vars JSON, if present and not the "default" sentinel).
 fn lookup_session_meta(session_id: &str) -> Option<(String, String, Option<String>)> {
-    let conn = crate::DB_CONNECTION.lock();
+    let conn = crate::state::get().db().lock();
     conn.query_row(
         "SELECT working_dir, claude_session_id, env_vars FROM sessions WHERE id = ?1",
         [session_id],
@@ -688,7 +688,7 @@ fn ingest_one(
             stats.rows_inserted += n;
         }
         Err(e) => {
-            eprintln!("[search] ingest error {:?}: {}", path, e);
+            tracing::warn!("ingest error {:?}: {}", path, e);
             stats.errors += 1;
         }
     }
@@ -717,7 +717,7 @@ fn ingest_file(
     let now_ms = chrono::Utc::now().timestamp_millis();
 
     let (start_offset, first_seen_at, prior_completed) = {
-        let conn = crate::DB_CONNECTION.lock();
+        let conn = crate::state::get().db().lock();
         let prev: Option<(i64, i64, i64, i64, Option<i64>)> = conn
             .query_row(
                 "SELECT last_offset, last_size, last_mtime, first_seen_at, completed_at
@@ -814,11 +814,11 @@ fn flush_batch(
     first_seen_at: i64,
     completed_at: Option<i64>,
 ) -> u64 {
-    let mut conn = crate::DB_CONNECTION.lock();
+    let mut conn = crate::state::get().db().lock();
     let tx = match conn.transaction() {
         Ok(t) => t,
         Err(e) => {
-            eprintln!("[search] tx: {}", e);
+            tracing::error!("tx: {}", e);
             return 0;
         }
     };
@@ -831,7 +831,7 @@ fn flush_batch(
         ) {
             Ok(s) => s,
             Err(e) => {
-                eprintln!("[search] prepare insert: {}", e);
+                tracing::error!("prepare insert: {}", e);
                 return 0;
             }
         };
@@ -847,7 +847,7 @@ fn flush_batch(
                 row.search_text,
             ]) {
                 Ok(changed) => n += changed as u64,
-                Err(e) => eprintln!("[search] insert: {}", e),
+                Err(e) => tracing::error!("insert: {}", e),
             }
         }
     }
@@ -868,7 +868,7 @@ fn flush_batch(
         ) {
             Ok(s) => s,
             Err(e) => {
-                eprintln!("[search] prepare upsert: {}", e);
+                tracing::error!("prepare upsert: {}", e);
                 return n;
             }
         };
@@ -886,7 +886,7 @@ fn flush_batch(
         ]);
     }
     if let Err(e) = tx.commit() {
-        eprintln!("[search] commit: {}", e);
+        tracing::error!("commit: {}", e);
     }
     n
 }
@@ -959,7 +959,7 @@ pub struct ReconciliationProposal {
 /// Only claude-json sessions are considered (other agent types don't
 /// have JSONL history).
 fn list_stranded_sessions(known_jsonl_ids: &std::collections::HashSet<String>) -> Vec<StrandedSession> {
-    let conn = crate::DB_CONNECTION.lock();
+    let conn = crate::state::get().db().lock();
     let mut stmt = match conn.prepare(
         "SELECT id, name, working_dir, created_at, claude_session_id, agent_type
          FROM sessions
@@ -1168,7 +1168,7 @@ pub fn apply_reconciliation(actions: ReconciliationActions) -> ReconciliationRes
 
     // 1) Matches: re-link stranded session rows.
     {
-        let conn = crate::DB_CONNECTION.lock();
+        let conn = crate::state::get().db().lock();
         for m in &actions.accept_matches {
             // Update sessions.claude_session_id; if the session has env_vars
             // missing CLAUDE_CONFIG_DIR but the JSONL came from a non-default
@@ -1180,7 +1180,7 @@ pub fn apply_reconciliation(actions: ReconciliationActions) -> ReconciliationRes
             match updated {
                 Ok(_) => result.matched += 1,
                 Err(e) => {
-                    eprintln!("[search] match update failed for {}: {}", m.session_id, e);
+                    tracing::error!("match update failed for {}: {}", m.session_id, e);
                     result.errors += 1;
                     continue;
                 }
@@ -1207,13 +1207,13 @@ pub fn apply_reconciliation(actions: ReconciliationActions) -> ReconciliationRes
 
     // 2) Deletes: stranded sessions the user agreed to drop.
     {
-        let conn = crate::DB_CONNECTION.lock();
+        let conn = crate::state::get().db().lock();
         for sid in &actions.delete_session_ids {
             delete_search_data_for_session(&conn, sid);
             match conn.execute("DELETE FROM sessions WHERE id = ?1", [sid]) {
                 Ok(_) => result.deleted += 1,
                 Err(e) => {
-                    eprintln!("[search] delete session failed for {}: {}", sid, e);
+                    tracing::error!("delete session failed for {}: {}", sid, e);
                     result.errors += 1;
                 }
             }
@@ -1227,7 +1227,7 @@ pub fn apply_reconciliation(actions: ReconciliationActions) -> ReconciliationRes
         let folder_id = ensure_imported_folder();
         let now_iso = chrono::Utc::now().to_rfc3339();
         let cmd = "claude --print --verbose --input-format stream-json --output-format stream-json --dangerously-skip-permissions";
-        let conn = crate::DB_CONNECTION.lock();
+        let conn = crate::state::get().db().lock();
         for orphan in orphans.into_iter().filter(|o| want.contains(&o.claude_session_id)) {
             let session_id = uuid::Uuid::new_v4().to_string();
             let name = orphan
@@ -1289,7 +1289,7 @@ pub fn apply_reconciliation(actions: ReconciliationActions) -> ReconciliationRes
 /// JSONL files we know about that aren't linked to a session yet.
 pub fn list_orphan_jsonls() -> Vec<OrphanJsonl> {
     let known: std::collections::HashSet<String> = {
-        let conn = crate::DB_CONNECTION.lock();
+        let conn = crate::state::get().db().lock();
         let mut stmt = match conn.prepare("SELECT file_path FROM session_files") {
             Ok(s) => s,
             Err(_) => return Vec::new(),
@@ -1414,7 +1414,7 @@ pub fn import_orphans() -> ImportStats {
     let cmd = "claude --print --verbose --input-format stream-json --output-format stream-json --dangerously-skip-permissions";
 
     {
-        let conn = crate::DB_CONNECTION.lock();
+        let conn = crate::state::get().db().lock();
         for orphan in &orphans {
             // Don't re-import if a session already exists with this claude_id.
             let exists: bool = conn
@@ -1494,7 +1494,7 @@ pub fn import_orphans() -> ImportStats {
 }
 
 fn ensure_imported_folder() -> String {
-    let conn = crate::DB_CONNECTION.lock();
+    let conn = crate::state::get().db().lock();
     if let Ok(id) = conn.query_row(
         "SELECT id FROM folders WHERE name = 'IMPORTED' LIMIT 1",
         [],
@@ -1520,13 +1520,13 @@ pub fn delete_search_data_for_session(conn: &Connection, session_id: &str) {
         "DELETE FROM message_index WHERE session_id = ?1",
         [session_id],
     ) {
-        eprintln!("[search] delete message_index: {}", e);
+        tracing::error!("delete message_index: {}", e);
     }
     if let Err(e) = conn.execute(
         "DELETE FROM session_files WHERE session_id = ?1",
         [session_id],
     ) {
-        eprintln!("[search] delete session_files: {}", e);
+        tracing::error!("delete session_files: {}", e);
     }
 }
 
@@ -1543,7 +1543,7 @@ pub struct SearchStats {
 
 pub fn get_stats() -> SearchStats {
     let (indexed_files, indexed_messages, last_completed_ms) = {
-        let conn = crate::DB_CONNECTION.lock();
+        let conn = crate::state::get().db().lock();
         let f: i64 = conn
             .query_row("SELECT COUNT(*) FROM session_files", [], |r| r.get(0))
             .unwrap_or(0);
@@ -1637,6 +1637,7 @@ pub struct ContextEntry {
 
 #[derive(Debug, Serialize)]
 pub struct MessageContext {
+    pub session_id: String,
     pub before: Vec<ContextEntry>,
     pub hit: Option<ContextEntry>,
     pub after: Vec<ContextEntry>,
@@ -1646,7 +1647,7 @@ pub struct MessageContext {
 /// session, ordered by ts. Each entry includes its full JSONL line so
 /// the renderer can show real content, not just the snippet.
 pub fn get_message_context(message_id: i64, before: u32, after: u32) -> Result<MessageContext, String> {
-    let conn = crate::DB_CONNECTION.lock();
+    let conn = crate::state::get().db().lock();
     // Anchor: the hit row.
     let (anchor_session, anchor_ts, anchor_uuid, anchor_path, anchor_offset, anchor_role): (
         String, i64, String, String, i64, String,
@@ -1751,6 +1752,7 @@ pub fn get_message_context(message_id: i64, before: u32, after: u32) -> Result<M
     };
 
     Ok(MessageContext {
+        session_id: anchor_session,
         before: before_rows,
         hit: Some(hit),
         after: after_rows,
@@ -1765,7 +1767,7 @@ pub fn search_messages(query: &str, filters: SearchFilters) -> Result<Vec<Search
     let limit = filters.limit.unwrap_or(50).min(500) as i64;
     let offset = filters.offset.unwrap_or(0) as i64;
 
-    let conn = crate::DB_CONNECTION.lock();
+    let conn = crate::state::get().db().lock();
     let mut stmt = conn
         .prepare(
             "SELECT mi.id, mi.session_id, s.name, mi.claude_session_id, mi.uuid,
@@ -0,0 +1,350 @@
+// Secret storage: the remote PIN hash, device token hashes and API keys.
+//
+// Secrets live in the platform's keychain, under the service named by
+// service() with the secret name as the account: the login Keychain on
+// macOS (inspect with Keychain Access or `security find-generic-password -s
+// <service>`), the Secret Service (GNOME Keyring, KWallet) on Linux and
+// Credential Manager on Windows, the latter two through the keyring crate.
+// The service is named after the data directory, so a dev build keeps its
+// own secrets; tests use a keychain in memory and never touch the user's.
+//
+// The `secrets` table in the app database is only a fallback: writes go
+// there, with a warning, when the keychain can't be used (no Secret Service
+// running, say), and versions without keychain support kept everything
+// there. A value found in the table wins, being newer than the keychain's,
+// and is moved into the keychain on the next read that can reach it.
+//
+// Reads are cached in memory; the keychain is only touched on first use and
+// on writes.
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::HashMap;
+
+/// `com.jamesbillinger.agent-hub` for the installed app,
+/// `com.jamesbillinger.agent-hub-dev` for dev builds
+#[cfg(all(any(target_os = "macos", target_os = "linux", target_os = "windows"), not(test)))]
+fn service() -> String {
+    format!("com.jamesbillinger.{}", crate::get_app_data_dir_name())
+}
+
+/// Argon2id hash of the remote access PIN (see pin.rs)
+pub const REMOTE_PIN: &str = "remote-pin";
+/// JSON object mapping paired_devices row keys to token hashes
+pub const DEVICE_TOKENS: &str = "device-tokens";
+/// Shared secret for POST /api/webhook/teams (AGENT_HUB_WEBHOOK_SECRET wins)
+pub const WEBHOOK_SECRET: &str = "webhook-secret";
+/// HMAC key signing guest share links (see share.rs)
+pub const SHARE_LINK_KEY: &str = "share-link-key";
+/// ECDSA P-256 key (PKCS#8) signing web push requests (see push.rs)
+pub const PUSH_VAPID_KEY: &str = "push-vapid-key";
+/// Followed by a remote hub's id: the device token used there (see hubs.rs)
+pub const HUB_TOKEN_PREFIX: &str = "hub-token:";
+
+static CACHE: Lazy<Mutex<HashMap<String, Option<String>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+// =====================================================================
+//  Schema
+// =====================================================================
+
+/// Table backing the store where the keychain can't be used.
+pub fn run_secrets_migrations(conn: &Connection) {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS secrets (
+            name  TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        )",
+        [],
+    )
+    .expect("Failed to create secrets table");
+}
+
+// =====================================================================
+//  Keychains
+// =====================================================================
+
+/// A platform secret store. Errors mean it can't be used right now.
+trait Keychain {
+    fn get(&self, name: &str) -> Result<Option<String>, String>;
+    fn set(&self, name: &str, value: &str) -> Result<(), String>;
+    fn delete(&self, name: &str) -> Result<(), String>;
+}
+
+struct Platform;
+
+#[cfg(all(target_os = "macos", not(test)))]
+impl Keychain for Platform {
+    fn get(&self, name: &str) -> Result<Option<String>, String> {
+        match security_framework::passwords::get_generic_password(&service(), name) {
+            Ok(bytes) => String::from_utf8(bytes).map(Some).map_err(|e| e.to_string()),
+            Err(e) if e.code() == NOT_FOUND => Ok(None),
+            Err(e) => Err(format!("Keychain read failed: {}", e)),
+        }
+    }
+
+    fn set(&self, name: &str, value: &str) -> Result<(), String> {
+        security_framework::passwords::set_generic_password(&service(), name, value.as_bytes())
+            .map_err(|e| format!("Keychain write failed: {}", e))
+    }
+
+    fn delete(&self, name: &str) -> Result<(), String> {
+        match security_framework::passwords::delete_generic_password(&service(), name) {
+            Err(e) if e.code() != NOT_FOUND => Err(format!("Keychain delete failed: {}", e)),
+            _ => Ok(()),
+        }
+    }
+}
+
+// errSecItemNotFound
+#[cfg(all(target_os = "macos", not(test)))]
+const NOT_FOUND: i32 = -25300;
+
+#[cfg(all(any(target_os = "linux", target_os = "windows"), not(test)))]
+impl Keychain for Platform {
+    fn get(&self, name: &str) -> Result<Option<String>, String> {
+        match entry(name)?.get_password() {
+            Ok(value) => Ok(Some(value)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(format!("Keychain read failed: {}", e)),
+        }
+    }
+
+    fn set(&self, name: &str, value: &str) -> Result<(), String> {
+        entry(name)?.set_password(value).map_err(|e| format!("Keychain write failed: {}", e))
+    }
+
+    fn delete(&self, name: &str) -> Result<(), String> {
+        match entry(name)?.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(format!("Keychain delete failed: {}", e)),
+        }
+    }
+}
+
+#[cfg(all(any(target_os = "linux", target_os = "windows"), not(test)))]
+fn entry(name: &str) -> Result<keyring::Entry, String> {
+    keyring::Entry::new(&service(), name).map_err(|e| format!("Keychain unavailable: {}", e))
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows", test)))]
+impl Keychain for Platform {
+    fn get(&self, _name: &str) -> Result<Option<String>, String> {
+        Err("No keychain on this platform".to_string())
+    }
+
+    fn set(&self, _name: &str, _value: &str) -> Result<(), String> {
+        Err("No keychain on this platform".to_string())
+    }
+
+    fn delete(&self, _name: &str) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Tests' keychain, in memory, so they never read or replace the user's
+#[cfg(test)]
+static TEST_KEYCHAIN: Lazy<Mutex<HashMap<String, String>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+#[cfg(test)]
+impl Keychain for Platform {
+    fn get(&self, name: &str) -> Result<Option<String>, String> {
+        Ok(TEST_KEYCHAIN.lock().get(name).cloned())
+    }
+
+    fn set(&self, name: &str, value: &str) -> Result<(), String> {
+        TEST_KEYCHAIN.lock().insert(name.to_string(), value.to_string());
+        Ok(())
+    }
+
+    fn delete(&self, name: &str) -> Result<(), String> {
+        TEST_KEYCHAIN.lock().remove(name);
+        Ok(())
+    }
+}
+
+// =====================================================================
+//  Keychain with the database as fallback
+// =====================================================================
+
+// A connection of its own, so callers may hold the shared one
+fn open() -> Result<Connection, String> {
+    Connection::open(crate::get_db_path()).map_err(|e| e.to_string())
+}
+
+fn get_from(keychain: &impl Keychain, conn: &Connection, name: &str) -> Result<Option<String>, String> {
+    if let Some(value) = get_in(conn, name)? {
+        // Written by an older version or while the keychain was unavailable
+        match keychain.set(name, &value) {
+            Ok(()) => delete_in(conn, name)?,
+            Err(e) => tracing::warn!("{}: kept in the database, the keychain can't be used: {}", name, e),
+        }
+        return Ok(Some(value));
+    }
+    keychain.get(name)
+}
+
+fn set_to(keychain: &impl Keychain, conn: &Connection, name: &str, value: &str) -> Result<(), String> {
+    match keychain.set(name, value) {
+        Ok(()) => delete_in(conn, name),
+        Err(e) => {
+            tracing::warn!("{}: storing in the database, the keychain can't be used: {}", name, e);
+            set_in(conn, name, value)
+        }
+    }
+}
+
+fn delete_from(keychain: &impl Keychain, conn: &Connection, name: &str) -> Result<(), String> {
+    delete_in(conn, name)?;
+    keychain.delete(name)
+}
+
+fn get_in(conn: &Connection, name: &str) -> Result<Option<String>, String> {
+    conn.query_row("SELECT value FROM secrets WHERE name = ?1", params![name], |row| row.get(0))
+        .optional()
+        .map_err(|e| e.to_string())
+}
+
+fn set_in(conn: &Connection, name: &str, value: &str) -> Result<(), String> {
+    conn.execute("INSERT OR REPLACE INTO secrets (name, value) VALUES (?1, ?2)", params![name, value])
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn delete_in(conn: &Connection, name: &str) -> Result<(), String> {
+    conn.execute("DELETE FROM secrets WHERE name = ?1", params![name]).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// =====================================================================
+//  API
+// =====================================================================
+
+pub fn get(name: &str) -> Option<String> {
+    try_get(name).unwrap_or_else(|e| {
+        tracing::warn!("{}: {}", name, e);
+        None
+    })
+}
+
+/// Like get(), but tells a secret that isn't set from one that couldn't be
+/// read, for callers that write back what they read
+pub fn try_get(name: &str) -> Result<Option<String>, String> {
+    if let Some(cached) = CACHE.lock().get(name) {
+        return Ok(cached.clone());
+    }
+    // Errors aren't cached, so the next read tries again
+    let value = open().and_then(|conn| get_from(&Platform, &conn, name))?;
+    CACHE.lock().insert(name.to_string(), value.clone());
+    Ok(value)
+}
+
+pub fn set(name: &str, value: &str) -> Result<(), String> {
+    set_to(&Platform, &open()?, name, value)?;
+    CACHE.lock().insert(name.to_string(), Some(value.to_string()));
+    Ok(())
+}
+
+pub fn delete(name: &str) -> Result<(), String> {
+    delete_from(&Platform, &open()?, name)?;
+    CACHE.lock().insert(name.to_string(), None);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    /// A keychain in memory, which can be made unavailable
+    #[derive(Default)]
+    struct FakeKeychain {
+        entries: RefCell<HashMap<String, String>>,
+        unavailable: bool,
+    }
+
+    impl Keychain for FakeKeychain {
+        fn get(&self, name: &str) -> Result<Option<String>, String> {
+            if self.unavailable {
+                return Err("locked".to_string());
+            }
+            Ok(self.entries.borrow().get(name).cloned())
+        }
+
+        fn set(&self, name: &str, value: &str) -> Result<(), String> {
+            if self.unavailable {
+                return Err("locked".to_string());
+            }
+            self.entries.borrow_mut().insert(name.to_string(), value.to_string());
+            Ok(())
+        }
+
+        fn delete(&self, name: &str) -> Result<(), String> {
+            if self.unavailable {
+                return Err("locked".to_string());
+            }
+            self.entries.borrow_mut().remove(name);
+            Ok(())
+        }
+    }
+
+    fn database() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        run_secrets_migrations(&conn);
+        conn
+    }
+
+    #[test]
+    fn database_store_round_trips() {
+        let conn = database();
+        assert_eq!(get_in(&conn, REMOTE_PIN).unwrap(), None);
+        set_in(&conn, REMOTE_PIN, "$argon2id$v=19$a$b").unwrap();
+        set_in(&conn, REMOTE_PIN, "$argon2id$v=19$c$d").unwrap();
+        assert_eq!(get_in(&conn, REMOTE_PIN).unwrap().as_deref(), Some("$argon2id$v=19$c$d"));
+        delete_in(&conn, REMOTE_PIN).unwrap();
+        delete_in(&conn, REMOTE_PIN).unwrap();
+        assert_eq!(get_in(&conn, REMOTE_PIN).unwrap(), None);
+    }
+
+    #[test]
+    fn values_from_the_database_move_to_the_keychain() {
+        // As versions without keychain support left them
+        let conn = database();
+        let slots = r#"{"device:1":"hash-one","device:2":"hash-two"}"#;
+        set_in(&conn, DEVICE_TOKENS, slots).unwrap();
+        set_in(&conn, REMOTE_PIN, "$argon2id$v=19$m=19456,t=2,p=1$c2FsdA$aGFzaA").unwrap();
+
+        let keychain = FakeKeychain::default();
+        assert_eq!(get_from(&keychain, &conn, DEVICE_TOKENS).unwrap().as_deref(), Some(slots));
+        assert_eq!(get_from(&keychain, &conn, REMOTE_PIN).unwrap().as_deref(), Some("$argon2id$v=19$m=19456,t=2,p=1$c2FsdA$aGFzaA"));
+        assert_eq!(keychain.entries.borrow().get(DEVICE_TOKENS).map(String::as_str), Some(slots));
+        assert_eq!(get_in(&conn, DEVICE_TOKENS).unwrap(), None);
+        assert_eq!(get_in(&conn, REMOTE_PIN).unwrap(), None);
+        // Read from the keychain from now on
+        assert_eq!(get_from(&keychain, &conn, DEVICE_TOKENS).unwrap().as_deref(), Some(slots));
+
+        set_to(&keychain, &conn, REMOTE_PIN, "$argon2id$new").unwrap();
+        assert_eq!(get_in(&conn, REMOTE_PIN).unwrap(), None);
+        assert_eq!(get_from(&keychain, &conn, REMOTE_PIN).unwrap().as_deref(), Some("$argon2id$new"));
+        delete_from(&keychain, &conn, REMOTE_PIN).unwrap();
+        assert_eq!(get_from(&keychain, &conn, REMOTE_PIN).unwrap(), None);
+    }
+
+    #[test]
+    fn the_database_stands_in_while_the_keychain_is_unavailable() {
+        let conn = database();
+        let mut keychain = FakeKeychain::default();
+        set_to(&keychain, &conn, WEBHOOK_SECRET, "old").unwrap();
+
+        keychain.unavailable = true;
+        set_to(&keychain, &conn, WEBHOOK_SECRET, "new").unwrap();
+        assert_eq!(get_from(&keychain, &conn, WEBHOOK_SECRET).unwrap().as_deref(), Some("new"));
+        assert!(get_from(&keychain, &conn, SHARE_LINK_KEY).is_err());
+
+        // The fallback copy is newer, and moves over once the keychain is back
+        keychain.unavailable = false;
+        assert_eq!(get_from(&keychain, &conn, WEBHOOK_SECRET).unwrap().as_deref(), Some("new"));
+        assert_eq!(get_in(&conn, WEBHOOK_SECRET).unwrap(), None);
+        assert_eq!(keychain.entries.borrow().get(WEBHOOK_SECRET).map(String::as_str), Some("new"));
+    }
+}
@@ -0,0 +1,170 @@
+// Classification of claude-json failure output into a small set of error
+// codes, so clients can show "Claude CLI isn't installed" instead of a wall
+// of raw stderr (or nothing at all when the process dies on spawn).
+//
+// Each code is reported at most once per process run; the raw output is
+// still forwarded as before.
+
+use serde::Serialize;
+use tauri::Emitter;
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct SessionError {
+    /// "cli_not_found", "not_logged_in", "rate_limited", "context_overflow"
+    /// or "spawn_failed".
+    pub code: &'static str,
+    /// Human-readable explanation suitable for showing as-is.
+    pub message: &'static str,
+    /// The output line that triggered the classification.
+    pub detail: String,
+}
+
+/// (code, message, lowercase patterns) — first match wins.
+const RULES: &[(&str, &str, &[&str])] = &[
+    (
+        "cli_not_found",
+        "The agent CLI isn't installed or isn't on PATH. Settings > Setup Check shows what's missing.",
+        &["command not found", "not recognized as an internal or external command"],
+    ),
+    (
+        "not_logged_in",
+        "The agent CLI isn't logged in. Run `claude` in a terminal and log in.",
+        &["invalid api key", "please run /login", "not logged in", "authentication_error", "oauth token has expired", "login required"],
+    ),
+    (
+        "rate_limited",
+        "Rate or usage limit reached. Try again later.",
+        &["rate limit", "rate_limit_error", "usage limit reached", "too many requests", "api error: 429"],
+    ),
+    (
+        "context_overflow",
+        "The conversation no longer fits in the model's context window. Run /compact or start a new session.",
+        &["prompt is too long", "context length", "context window", "exceed context limit", "maximum context"],
+    ),
+];
+
+/// Match a line of output against the known failure modes.
+pub fn classify(line: &str) -> Option<SessionError> {
+    let lower = line.to_lowercase();
+    RULES.iter().find_map(|(code, message, patterns)| {
+        patterns.iter().any(|p| lower.contains(p)).then(|| SessionError {
+            code,
+            message,
+            detail: line.trim().to_string(),
+        })
+    })
+}
+
+/// Classify `line` and, if it matches something not yet reported for this
+/// process run, emit a `session_error` event.
+pub fn report_if_known(session_id: &str, line: &str) {
+    if let Some(err) = classify(line) {
+        report(session_id, err);
+    }
+}
+
+/// Emit a `session_error` event for `session_id` unless this code was
+/// already reported for the current process run.
+pub fn report(session_id: &str, err: SessionError) {
+    {
+        let mut reported = crate::state::get().session_errors_reported.lock();
+        if !reported.entry(session_id.to_string()).or_default().insert(err.code) {
+            return;
+        }
+    }
+    tracing::warn!("{} for {}: {}", err.code, session_id, err.detail);
+
+    if let Some(app) = crate::state::get().app_handle.lock().as_ref() {
+        let _ = app.emit("session-error", serde_json::json!({
+            "session_id": session_id,
+            "error": err,
+        }));
+    }
+    crate::broadcast_session_event(crate::events::SessionEvent::SessionError {
+        session_id: session_id.to_string(),
+        error: err.clone(),
+    });
+    // Sent to every mobile client, not just subscribers: a session that
+    // failed to spawn usually has nobody subscribed yet.
+    let msg = serde_json::json!({
+        "type": "session_error",
+        "sessionId": session_id,
+        "error": err,
+    })
+    .to_string();
+    crate::broadcast_to_mobile_clients(&msg);
+    crate::push::on_error(session_id, err.message);
+    crate::desktop_notify::on_error(session_id, err.message);
+    crate::webhooks::dispatch("error", session_id, serde_json::json!({
+        "code": err.code,
+        "message": err.message,
+        "detail": err.detail,
+    }));
+    crate::mcp::log(crate::mcp::LogLevel::Error, "session", serde_json::json!({
+        "session_id": session_id,
+        "error": err,
+    }));
+}
+
+/// A process exited with shell status 127 ("command not found").
+pub fn report_exit_code(session_id: &str, code: Option<i32>) {
+    if code == Some(127) {
+        report(session_id, SessionError {
+            code: "cli_not_found",
+            message: RULES[0].1,
+            detail: "exit status 127".to_string(),
+        });
+    }
+}
+
+/// The process couldn't be started at all.
+pub fn report_spawn_failure(session_id: &str, detail: &str) {
+    report(session_id, SessionError {
+        code: "spawn_failed",
+        message: "The agent process could not be started.",
+        detail: detail.to_string(),
+    });
+}
+
+/// Whether the current run hit a failure that restarting won't fix.
+pub fn has_fatal(session_id: &str) -> bool {
+    const FATAL: &[&str] = &["cli_not_found", "not_logged_in", "context_overflow", "spawn_failed"];
+    crate::state::get().session_errors_reported
+        .lock()
+        .get(session_id)
+        .map(|codes| FATAL.iter().any(|c| codes.contains(c)))
+        .unwrap_or(false)
+}
+
+/// Start a fresh run: codes reported for a previous process are forgotten.
+pub fn reset(session_id: &str) {
+    crate::state::get().session_errors_reported.lock().remove(session_id);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_known_failures() {
+        let code = |line: &str| classify(line).map(|e| e.code);
+        assert_eq!(code("zsh:1: command not found: claude"), Some("cli_not_found"));
+        assert_eq!(code("Invalid API key · Please run /login"), Some("not_logged_in"));
+        assert_eq!(code("Claude AI usage limit reached|1760000000"), Some("rate_limited"));
+        assert_eq!(code("API Error: 429 {\"type\":\"rate_limit_error\"}"), Some("rate_limited"));
+        assert_eq!(code("Prompt is too long"), Some("context_overflow"));
+    }
+
+    #[test]
+    fn ignores_ordinary_output() {
+        assert_eq!(classify("Loaded 3 MCP servers"), None);
+        assert_eq!(classify(""), None);
+    }
+
+    #[test]
+    fn keeps_the_triggering_line() {
+        let err = classify("  bash: claude: command not found\n").unwrap();
+        assert_eq!(err.detail, "bash: claude: command not found");
+        assert!(!err.message.is_empty());
+    }
+}
@@ -0,0 +1,283 @@
+// Guest share links: a signed, single-use URL that lets someone who isn't
+// paired watch (and optionally type into) one session for a limited time.
+//
+// The token in /share/<token> is `<id>.<signature>`, the signature being an
+// HMAC-SHA256 over the link's id, session, expiry and access under a key kept
+// in the secret store, so a token can't be edited to reach another session or
+// outlive its expiry. The first WebSocket that connects with a link uses it
+// up; revoking the link or reaching expires_at closes that connection.
+
+use base64::Engine;
+use once_cell::sync::Lazy;
+use ring::hmac;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+
+pub const DEFAULT_TTL_SECS: u64 = 3600;
+pub const MAX_TTL_SECS: u64 = 24 * 3600;
+const MIN_TTL_SECS: u64 = 60;
+
+const USED: &str = "This share link has already been used";
+
+/// Ids of links revoked while a guest may be connected.
+static REVOKED: Lazy<tokio::sync::broadcast::Sender<String>> =
+    Lazy::new(|| tokio::sync::broadcast::channel(16).0);
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ShareLink {
+    pub id: String,
+    pub session_id: String,
+    /// Guests can watch but not send input
+    pub read_only: bool,
+    pub created_at: i64,
+    /// Unix seconds; the link and any connection made with it end here
+    pub expires_at: i64,
+    /// When a guest connected (the link can't be used again)
+    pub used_at: Option<i64>,
+    pub revoked: bool,
+}
+
+// =====================================================================
+//  Schema
+// =====================================================================
+
+pub fn run_share_migrations(conn: &Connection) {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS share_links (
+            id         TEXT PRIMARY KEY,
+            session_id TEXT NOT NULL,
+            read_only  INTEGER NOT NULL,
+            created_at INTEGER NOT NULL,
+            expires_at INTEGER NOT NULL,
+            used_at    INTEGER,
+            revoked    INTEGER NOT NULL DEFAULT 0
+        )",
+        [],
+    )
+    .expect("Failed to create share_links table");
+    let _ = conn.execute("DELETE FROM share_links WHERE expires_at < ?1", params![now()]);
+}
+
+// =====================================================================
+//  Signing
+// =====================================================================
+
+fn now() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+/// The signing key, created on first use.
+fn signing_key() -> Result<hmac::Key, String> {
+    let b64 = base64::engine::general_purpose::STANDARD;
+    let bytes = match crate::secrets::get(crate::secrets::SHARE_LINK_KEY) {
+        Some(stored) => b64.decode(stored).map_err(|e| format!("Share link key is corrupt: {}", e))?,
+        None => {
+            let mut bytes = vec![0u8; 32];
+            getrandom::getrandom(&mut bytes).map_err(|e| format!("Random number generator unavailable: {}", e))?;
+            crate::secrets::set(crate::secrets::SHARE_LINK_KEY, &b64.encode(&bytes))?;
+            bytes
+        }
+    };
+    Ok(hmac::Key::new(hmac::HMAC_SHA256, &bytes))
+}
+
+fn signed_fields(link: &ShareLink) -> String {
+    format!("{}\n{}\n{}\n{}", link.id, link.session_id, link.expires_at, link.read_only)
+}
+
+fn token_for(key: &hmac::Key, link: &ShareLink) -> String {
+    let tag = hmac::sign(key, signed_fields(link).as_bytes());
+    format!("{}.{}", link.id, base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(tag.as_ref()))
+}
+
+// =====================================================================
+//  Links
+// =====================================================================
+
+const COLUMNS: &str = "id, session_id, read_only, created_at, expires_at, used_at, revoked";
+
+fn from_row(row: &rusqlite::Row) -> rusqlite::Result<ShareLink> {
+    Ok(ShareLink {
+        id: row.get(0)?,
+        session_id: row.get(1)?,
+        read_only: row.get(2)?,
+        created_at: row.get(3)?,
+        expires_at: row.get(4)?,
+        used_at: row.get(5)?,
+        revoked: row.get(6)?,
+    })
+}
+
+fn get_in(conn: &Connection, id: &str) -> Result<Option<ShareLink>, String> {
+    conn.query_row(&format!("SELECT {} FROM share_links WHERE id = ?1", COLUMNS), params![id], from_row)
+        .optional()
+        .map_err(|e| e.to_string())
+}
+
+fn create_in(
+    conn: &Connection,
+    key: &hmac::Key,
+    session_id: &str,
+    ttl_secs: u64,
+    read_only: bool,
+    now: i64,
+) -> Result<(ShareLink, String), String> {
+    let link = ShareLink {
+        id: uuid::Uuid::new_v4().simple().to_string(),
+        session_id: session_id.to_string(),
+        read_only,
+        created_at: now,
+        expires_at: now + ttl_secs.clamp(MIN_TTL_SECS, MAX_TTL_SECS) as i64,
+        used_at: None,
+        revoked: false,
+    };
+    conn.execute(
+        "INSERT INTO share_links (id, session_id, read_only, created_at, expires_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![link.id, link.session_id, link.read_only, link.created_at, link.expires_at],
+    )
+    .map_err(|e| e.to_string())?;
+    let token = token_for(key, &link);
+    Ok((link, token))
+}
+
+/// The link `token` grants access through right now. The error is shown to
+/// the guest.
+fn verify_in(conn: &Connection, key: &hmac::Key, token: &str, now: i64) -> Result<ShareLink, String> {
+    const INVALID: &str = "This share link is not valid";
+    let (id, signature) = token.split_once('.').ok_or(INVALID)?;
+    let signature = base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(signature).map_err(|_| INVALID)?;
+    let link = get_in(conn, id)?.ok_or(INVALID)?;
+    // Constant-time comparison happens inside ring
+    hmac::verify(key, signed_fields(&link).as_bytes(), &signature).map_err(|_| INVALID)?;
+    if link.revoked {
+        return Err("This share link has been revoked".to_string());
+    }
+    if now >= link.expires_at {
+        return Err("This share link has expired".to_string());
+    }
+    if link.used_at.is_some() {
+        return Err(USED.to_string());
+    }
+    Ok(link)
+}
+
+/// Use up a verified link (only one caller wins a race).
+fn claim_in(conn: &Connection, link: ShareLink, now: i64) -> Result<ShareLink, String> {
+    let claimed = conn
+        .execute("UPDATE share_links SET used_at = ?2 WHERE id = ?1 AND used_at IS NULL", params![link.id, now])
+        .map_err(|e| e.to_string())?;
+    if claimed == 0 {
+        return Err(USED.to_string());
+    }
+    Ok(ShareLink { used_at: Some(now), ..link })
+}
+
+fn revoke_in(conn: &Connection, id: &str) -> Result<bool, String> {
+    let changed = conn
+        .execute("UPDATE share_links SET revoked = 1 WHERE id = ?1 AND revoked = 0", params![id])
+        .map_err(|e| e.to_string())?;
+    Ok(changed > 0)
+}
+
+/// Unexpired, unrevoked links, newest first.
+fn list_in(conn: &Connection, session_id: Option<&str>, now: i64) -> Result<Vec<ShareLink>, String> {
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM share_links WHERE revoked = 0 AND expires_at > ?1 AND (?2 IS NULL OR session_id = ?2)
+             ORDER BY created_at DESC",
+            COLUMNS
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt.query_map(params![now, session_id], from_row).map_err(|e| e.to_string())?;
+    rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+}
+
+/// Create a link to `session_id`, returning it and the token for its URL.
+pub fn create(session_id: &str, ttl_secs: u64, read_only: bool) -> Result<(ShareLink, String), String> {
+    let key = signing_key()?;
+    create_in(&crate::state::get().db().lock(), &key, session_id, ttl_secs, read_only, now())
+}
+
+pub fn verify(token: &str) -> Result<ShareLink, String> {
+    let key = signing_key()?;
+    verify_in(&crate::state::get().db().lock(), &key, token, now())
+}
+
+pub fn claim(link: ShareLink) -> Result<ShareLink, String> {
+    claim_in(&crate::state::get().db().lock(), link, now())
+}
+
+/// The URL token for an existing link.
+pub fn token(link: &ShareLink) -> Result<String, String> {
+    Ok(token_for(&signing_key()?, link))
+}
+
+/// Revoke a link, closing the guest's connection if one is open.
+pub fn revoke(id: &str) -> Result<(), String> {
+    if !revoke_in(&crate::state::get().db().lock(), id)? {
+        return Err("Share link not found or already revoked".to_string());
+    }
+    let _ = REVOKED.send(id.to_string());
+    Ok(())
+}
+
+pub fn list(session_id: Option<&str>) -> Result<Vec<ShareLink>, String> {
+    list_in(&crate::state::get().db().lock(), session_id, now())
+}
+
+pub fn subscribe_revocations() -> tokio::sync::broadcast::Receiver<String> {
+    REVOKED.subscribe()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> (Connection, hmac::Key) {
+        let conn = Connection::open_in_memory().unwrap();
+        run_share_migrations(&conn);
+        (conn, hmac::Key::new(hmac::HMAC_SHA256, b"test key"))
+    }
+
+    fn redeem_in(conn: &Connection, key: &hmac::Key, token: &str, now: i64) -> Result<ShareLink, String> {
+        claim_in(conn, verify_in(conn, key, token, now)?, now)
+    }
+
+    #[test]
+    fn link_works_once_and_only_as_signed() {
+        let (conn, key) = setup();
+        let t = 1_000_000;
+        let (link, token) = create_in(&conn, &key, "s1", 600, true, t).unwrap();
+        assert_eq!(link.expires_at, t + 600);
+
+        // Tampered or foreign-key signatures are refused without using the link
+        let other_key = hmac::Key::new(hmac::HMAC_SHA256, b"other key");
+        assert!(redeem_in(&conn, &other_key, &token, t).is_err());
+        assert!(redeem_in(&conn, &key, &format!("{}x", token), t).is_err());
+        assert!(redeem_in(&conn, &key, &link.id, t).is_err());
+
+        let redeemed = redeem_in(&conn, &key, &token, t + 5).unwrap();
+        assert_eq!(redeemed.session_id, "s1");
+        assert!(redeemed.read_only);
+        assert_eq!(redeemed.used_at, Some(t + 5));
+        assert_eq!(redeem_in(&conn, &key, &token, t + 6).unwrap_err(), USED);
+        // A second connection racing past verify still loses the claim
+        assert_eq!(claim_in(&conn, link, t + 6).unwrap_err(), USED);
+    }
+
+    #[test]
+    fn revoked_and_expired_links_are_refused() {
+        let (conn, key) = setup();
+        let t = 1_000_000;
+        let (revoked, revoked_token) = create_in(&conn, &key, "s1", 600, false, t).unwrap();
+        let (_, expired_token) = create_in(&conn, &key, "s2", 1, false, t).unwrap();
+        // TTLs are clamped to at least a minute
+        assert_eq!(list_in(&conn, None, t).unwrap().len(), 2);
+
+        assert!(revoke_in(&conn, &revoked.id).unwrap());
+        assert!(!revoke_in(&conn, &revoked.id).unwrap());
+        assert_eq!(redeem_in(&conn, &key, &revoked_token, t).unwrap_err(), "This share link has been revoked");
+        assert_eq!(redeem_in(&conn, &key, &expired_token, t + 60).unwrap_err(), "This share link has expired");
+        assert!(list_in(&conn, None, t + 60).unwrap().is_empty());
+    }
+}
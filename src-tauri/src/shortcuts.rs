@@ -0,0 +1,125 @@
+// Keyboard shortcuts for menu items.
+//
+// DEFAULTS are the built-in accelerators; `shortcuts` in settings overrides
+// them per action (an empty string removes one). create_menu reads the
+// effective set, and changing it through set_shortcut or the settings modal
+// rebuilds the menu. The frontend's own keydown handlers for the same
+// actions stand down when an action has been remapped.
+
+use muda::accelerator::Accelerator;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// (action / menu item id, label, default accelerator)
+pub const DEFAULTS: &[(&str, &str, &str)] = &[
+    ("settings", "Settings", "CmdOrCtrl+,"),
+    ("new_session", "New Session", "CmdOrCtrl+T"),
+    ("close_session", "Close Session", "CmdOrCtrl+W"),
+    ("toggle_sidebar", "Toggle Sidebar", "CmdOrCtrl+B"),
+    ("zoom_in", "Zoom In", "CmdOrCtrl+="),
+    ("zoom_out", "Zoom Out", "CmdOrCtrl+-"),
+    ("reset_zoom", "Reset Zoom", "CmdOrCtrl+0"),
+    ("rename_session", "Rename Session", "CmdOrCtrl+I"),
+    ("duplicate_session", "Duplicate Session", "CmdOrCtrl+Shift+D"),
+    ("reset_session_id", "Reset Session ID", ""),
+    ("browse_claude_sessions", "Browse Claude Sessions", "CmdOrCtrl+Shift+R"),
+    ("next_session", "Next Session", "Ctrl+Tab"),
+    ("prev_session", "Previous Session", "Ctrl+Shift+Tab"),
+    ("reopen_session", "Reopen Closed Session", "CmdOrCtrl+Shift+T"),
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Shortcut {
+    pub action: String,
+    pub label: String,
+    pub accelerator: String,
+    pub default: String,
+}
+
+/// The accelerator for `action`, if it has one
+pub fn accelerator(overrides: &HashMap<String, String>, action: &str) -> Option<String> {
+    let default = DEFAULTS.iter().find(|(a, _, _)| *a == action).map(|(_, _, d)| *d)?;
+    let accelerator = overrides.get(action).map(String::as_str).unwrap_or(default).trim();
+    (!accelerator.is_empty()).then(|| accelerator.to_string())
+}
+
+/// Every action with its effective and default accelerator, in menu order
+pub fn list(overrides: &HashMap<String, String>) -> Vec<Shortcut> {
+    DEFAULTS
+        .iter()
+        .map(|(action, label, default)| Shortcut {
+            action: action.to_string(),
+            label: label.to_string(),
+            accelerator: accelerator(overrides, action).unwrap_or_default(),
+            default: default.to_string(),
+        })
+        .collect()
+}
+
+/// Refuse unknown actions, accelerators that don't parse, and two actions
+/// sharing one accelerator
+pub fn validate(overrides: &HashMap<String, String>) -> Result<(), String> {
+    for (action, accelerator) in overrides {
+        if !DEFAULTS.iter().any(|(a, _, _)| a == action) {
+            return Err(format!("Unknown menu action '{}'", action));
+        }
+        let accelerator = accelerator.trim();
+        if !accelerator.is_empty() && accelerator.parse::<Accelerator>().is_err() {
+            return Err(format!("'{}' isn't a valid shortcut", accelerator));
+        }
+    }
+    // Compared parsed, so "Shift+CmdOrCtrl+d" clashes with "CmdOrCtrl+Shift+D"
+    let mut seen: HashMap<Accelerator, &str> = HashMap::new();
+    for (action, label, _) in DEFAULTS {
+        let Some(accelerator) = accelerator(overrides, action) else { continue };
+        let Ok(parsed) = accelerator.parse::<Accelerator>() else { continue };
+        if let Some(other) = seen.insert(parsed, label) {
+            return Err(format!("{} is used by both {} and {}", accelerator, other, label));
+        }
+    }
+    Ok(())
+}
+
+/// Apply one change: Some sets the action's accelerator ("" removes it),
+/// None goes back to the default
+pub fn set(overrides: &mut HashMap<String, String>, action: &str, accelerator: Option<&str>) -> Result<(), String> {
+    let mut updated = overrides.clone();
+    match accelerator {
+        Some(accelerator) => {
+            updated.insert(action.to_string(), accelerator.trim().to_string());
+        }
+        None => {
+            updated.remove(action);
+        }
+    }
+    validate(&updated)?;
+    *overrides = updated;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overrides_replace_defaults_and_clashes_are_refused() {
+        let mut overrides = HashMap::new();
+        assert_eq!(accelerator(&overrides, "rename_session").as_deref(), Some("CmdOrCtrl+I"));
+        assert_eq!(accelerator(&overrides, "reset_session_id"), None);
+
+        set(&mut overrides, "rename_session", Some("CmdOrCtrl+Shift+E")).unwrap();
+        assert_eq!(accelerator(&overrides, "rename_session").as_deref(), Some("CmdOrCtrl+Shift+E"));
+        set(&mut overrides, "zoom_in", Some("")).unwrap();
+        assert_eq!(accelerator(&overrides, "zoom_in"), None);
+
+        // Taken by duplicate_session, however it's spelled
+        assert!(set(&mut overrides, "reset_session_id", Some("shift+cmdorctrl+d")).is_err());
+        assert!(set(&mut overrides, "no_such_action", Some("CmdOrCtrl+J")).is_err());
+        assert!(set(&mut overrides, "settings", Some("CmdOrCtrl+Nope")).is_err());
+        assert_eq!(overrides.len(), 2);
+
+        set(&mut overrides, "rename_session", None).unwrap();
+        assert_eq!(accelerator(&overrides, "rename_session").as_deref(), Some("CmdOrCtrl+I"));
+        assert!(validate(&HashMap::new()).is_ok());
+    }
+}
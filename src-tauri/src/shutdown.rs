@@ -0,0 +1,275 @@
+// Ordered shutdown, and recovery of what an unclean one lost.
+//
+// Quitting (Cmd+Q, the tray, app.exit, SIGTERM / SIGINT) arrives as
+// ExitRequested. The first request is held back while the frontend saves
+// its terminal buffers (it calls shutdown_ready, or FRONTEND_TIMEOUT
+// passes), then run() stops things in order:
+//   1. the web server stops accepting and mobile WebSockets are closed
+//   2. unsaved chat messages are written to the DB
+//   3. each agent's process group gets SIGTERM, and SIGKILL after GRACE
+//   4. the WAL is checkpointed so the DB file is complete on its own
+// and the exit goes ahead.
+//
+// SIGKILL, crashes and relaunching after an update skip all of that, so chat
+// messages are also appended to a per-session journal
+// (recovery/<session id>.jsonl) as they arrive. Saving the session's buffer
+// removes it. recover() runs at startup and appends whatever journals are
+// left to their sessions' saved buffers.
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::Once;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter};
+
+const FRONTEND_TIMEOUT: Duration = Duration::from_secs(3);
+const GRACE: Duration = Duration::from_secs(2);
+
+const RUNNING: u8 = 0;
+const STOPPING: u8 = 1;
+const STOPPED: u8 = 2;
+
+static STATE: AtomicU8 = AtomicU8::new(RUNNING);
+static FRONTEND_SAVED: AtomicBool = AtomicBool::new(false);
+
+/// Once set, nothing new should start: no connections, no spawns
+pub fn in_progress() -> bool {
+    STATE.load(Ordering::SeqCst) != RUNNING
+}
+
+/// ExitRequested: true while the exit has to wait for the shutdown sequence
+pub fn on_exit_requested(app: &AppHandle) -> bool {
+    match STATE.compare_exchange(RUNNING, STOPPING, Ordering::SeqCst, Ordering::SeqCst) {
+        Ok(_) => {
+            let _ = app.emit("app-shutting-down", ());
+            let app = app.clone();
+            std::thread::spawn(move || {
+                let deadline = Instant::now() + FRONTEND_TIMEOUT;
+                while !FRONTEND_SAVED.load(Ordering::SeqCst) && Instant::now() < deadline {
+                    std::thread::sleep(Duration::from_millis(50));
+                }
+                run();
+                app.exit(0);
+            });
+            true
+        }
+        Err(state) => state == STOPPING,
+    }
+}
+
+/// The frontend has saved its terminal buffers
+pub fn frontend_saved() {
+    FRONTEND_SAVED.store(true, Ordering::SeqCst);
+}
+
+/// The shutdown sequence; later calls return straight away
+pub fn run() {
+    static ONCE: Once = Once::new();
+    ONCE.call_once(|| {
+        STATE.store(STOPPING, Ordering::SeqCst);
+        let started = Instant::now();
+
+        crate::stop_web_server();
+        crate::discovery::stop_advertising();
+        crate::tunnel::stop();
+
+        flush_journaled();
+        terminate_children();
+
+        if let Err(e) = crate::state::get().db().lock().execute_batch("PRAGMA wal_checkpoint(TRUNCATE);") {
+            tracing::warn!("checkpoint failed: {}", e);
+        }
+
+        STATE.store(STOPPED, Ordering::SeqCst);
+        tracing::info!("Shut down in {}ms", started.elapsed().as_millis());
+    });
+}
+
+/// Quit cleanly on SIGTERM (logout, `kill`) and SIGINT (Ctrl+C in dev)
+pub fn handle_signals(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        use tokio::signal::unix::{signal, SignalKind};
+        let (Ok(mut term), Ok(mut int)) = (signal(SignalKind::terminate()), signal(SignalKind::interrupt())) else {
+            return;
+        };
+        tokio::select! {
+            _ = term.recv() => {}
+            _ = int.recv() => {}
+        }
+        tracing::info!("Signal received, shutting down");
+        app.exit(0);
+    });
+}
+
+/// Every session with a journal has messages the DB doesn't
+fn flush_journaled() {
+    for session_id in journaled_sessions() {
+        crate::flush_session_messages(&session_id);
+    }
+}
+
+/// SIGTERM every agent's process group, then SIGKILL the ones still there
+/// after GRACE. Agents are group leaders (setsid for PTYs, process_group(0)
+/// for JSON processes), so this reaches the CLIs the shells started too.
+fn terminate_children() {
+    let mut children: Vec<(String, u32)> =
+        crate::state::get().json_processes.lock().iter().map(|(id, p)| (id.clone(), p.child_id)).collect();
+    children.extend(
+        crate::state::get().pty_sessions
+            .lock()
+            .iter()
+            .filter_map(|(id, session)| session.lock().child_pid.map(|pid| (id.clone(), pid))),
+    );
+    children.retain(|(_, pid)| *pid > 0);
+
+    for (session_id, pid) in &children {
+        tracing::info!("Stopping session {} (process group {})", session_id, pid);
+        crate::restarts::mark_stopped(session_id);
+        signal_group(*pid, libc::SIGTERM);
+    }
+    let deadline = Instant::now() + GRACE;
+    while children.iter().any(|(_, pid)| group_alive(*pid)) && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    for (session_id, pid) in &children {
+        if group_alive(*pid) {
+            tracing::warn!("Session {} didn't stop within {}s; killing it", session_id, GRACE.as_secs());
+            signal_group(*pid, libc::SIGKILL);
+        }
+        crate::save_session_pid(session_id, None);
+    }
+}
+
+fn signal_group(pid: u32, signal: i32) {
+    unsafe {
+        libc::killpg(pid as i32, signal);
+    }
+}
+
+fn group_alive(pid: u32) -> bool {
+    unsafe { libc::killpg(pid as i32, 0) == 0 }
+}
+
+// =====================================================================
+//  Recovery journal
+// =====================================================================
+
+fn journal_dir() -> PathBuf {
+    crate::get_config_path().with_file_name("recovery")
+}
+
+/// Session ids are uuids; anything else can't name a file safely
+fn journal_path(session_id: &str) -> Option<PathBuf> {
+    let safe = !session_id.is_empty() && session_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    safe.then(|| journal_dir().join(format!("{}.jsonl", session_id)))
+}
+
+/// Record a chat message that isn't in the DB yet
+pub fn journal_append(session_id: &str, message: &serde_json::Value) {
+    let Some(path) = journal_path(session_id) else { return };
+    let line = crate::redaction::redact_output(&message.to_string());
+    let written = std::fs::create_dir_all(journal_dir()).and_then(|_| {
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(&path)?;
+        writeln!(file, "{}", line)
+    });
+    if let Err(e) = written {
+        tracing::warn!("journal for {}: {}", session_id, e);
+    }
+}
+
+/// The session's buffer was saved; its journal is no longer needed
+pub fn journal_clear(session_id: &str) {
+    if let Some(path) = journal_path(session_id) {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+fn journaled_sessions() -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(journal_dir()) else { return Vec::new() };
+    entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension()? != "jsonl" {
+                return None;
+            }
+            path.file_stem()?.to_str().map(str::to_string)
+        })
+        .collect()
+}
+
+/// Append the journal to what was saved. A journal whose save went through
+/// but which wasn't removed yet overlaps the end of the saved messages, and
+/// that overlap isn't added twice.
+fn merge_journal(mut saved: Vec<serde_json::Value>, journal: Vec<serde_json::Value>) -> Vec<serde_json::Value> {
+    let overlap = (0..=journal.len().min(saved.len()))
+        .rev()
+        .find(|&n| saved[saved.len() - n..] == journal[..n])
+        .unwrap_or(0);
+    saved.extend(journal.into_iter().skip(overlap));
+    saved
+}
+
+/// Startup: fold journals left by an unclean exit into the saved buffers.
+/// Runs after migrations and before anything reads the buffers.
+pub fn recover() {
+    for session_id in journaled_sessions() {
+        let Some(path) = journal_path(&session_id) else { continue };
+        let journal: Vec<serde_json::Value> = std::fs::read_to_string(&path)
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect();
+        let exists = crate::state::get().db()
+            .lock()
+            .query_row("SELECT 1 FROM sessions WHERE id = ?1", [&session_id], |_| Ok(()))
+            .is_ok();
+        if !exists || journal.is_empty() {
+            journal_clear(&session_id);
+            continue;
+        }
+        let saved = crate::load_terminal_buffer(session_id.clone())
+            .ok()
+            .flatten()
+            .map(|buffer| crate::parse_buffer_to_messages(&buffer))
+            .unwrap_or_default();
+        let before = saved.len();
+        let merged = merge_journal(saved, journal);
+        let recovered = merged.len() - before;
+        match serde_json::to_string(&merged) {
+            Ok(buffer) => match crate::save_terminal_buffer_to_db(&session_id, &buffer) {
+                Ok(()) => tracing::info!("Recovered {} unsaved messages for {}", recovered, session_id),
+                Err(e) => tracing::error!("failed to recover messages for {}: {}", session_id, e),
+            },
+            Err(e) => tracing::error!("failed to recover messages for {}: {}", session_id, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn journal_is_appended_without_repeating_what_was_saved() {
+        let saved = vec![json!({"n": 1}), json!({"n": 2})];
+
+        // Crashed before the save: everything in the journal is new
+        let merged = merge_journal(saved.clone(), vec![json!({"n": 3}), json!({"n": 4})]);
+        assert_eq!(merged, vec![json!({"n": 1}), json!({"n": 2}), json!({"n": 3}), json!({"n": 4})]);
+
+        // Crashed after the save but before the journal was removed
+        let merged = merge_journal(saved.clone(), vec![json!({"n": 2})]);
+        assert_eq!(merged, saved);
+
+        assert_eq!(merge_journal(Vec::new(), vec![json!({"n": 1})]), vec![json!({"n": 1})]);
+    }
+
+    #[test]
+    fn only_plain_session_ids_name_journals() {
+        assert!(journal_path("3f2b9c1e-8a4d-4e0f-9b7a-1c2d3e4f5a6b").is_some());
+        assert!(journal_path("../config").is_none());
+        assert!(journal_path("").is_none());
+    }
+}
@@ -0,0 +1,405 @@
+// Turn-level undo via git snapshots.
+//
+// When enabled for a session, the working tree is snapshotted right before
+// each user prompt reaches the agent (see the stdin task in
+// spawn_json_process), so "turn N" is the state of the workspace before the
+// agent started on prompt N. `revert_to_turn` rolls the workspace back to
+// one of those snapshots.
+//
+// Snapshots are ordinary commits built from a throwaway index and kept alive
+// under `refs/agent-hub/<session_id>/`; the user's branch, HEAD and real
+// index are never touched. Untracked files are included (ignored ones are
+// not). Reverting first snapshots the current state as a "pre-revert" entry,
+// so a revert can itself be undone.
+
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tauri::Emitter;
+
+// =====================================================================
+//  Schema
+// =====================================================================
+
+pub fn run_snapshot_migrations(conn: &Connection) {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS snapshot_sessions (
+            session_id TEXT PRIMARY KEY
+        )",
+        [],
+    )
+    .expect("Failed to create snapshot_sessions table");
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS turn_snapshots (
+            session_id TEXT NOT NULL,
+            turn       INTEGER NOT NULL,
+            kind       TEXT NOT NULL,
+            repo_root  TEXT NOT NULL,
+            commit_sha TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            PRIMARY KEY (session_id, turn)
+        )",
+        [],
+    )
+    .expect("Failed to create turn_snapshots table");
+}
+
+/// Forget a session's snapshots (called when the session is deleted). The
+/// refs are removed in the background since that means running git.
+pub fn delete_snapshots_for_session(conn: &Connection, session_id: &str) {
+    let roots: Vec<String> = conn
+        .prepare("SELECT DISTINCT repo_root FROM turn_snapshots WHERE session_id = ?1")
+        .and_then(|mut stmt| {
+            stmt.query_map(params![session_id], |r| r.get(0))
+                .map(|rows| rows.filter_map(|r| r.ok()).collect())
+        })
+        .unwrap_or_default();
+    let _ = conn.execute("DELETE FROM turn_snapshots WHERE session_id = ?1", params![session_id]);
+    let _ = conn.execute("DELETE FROM snapshot_sessions WHERE session_id = ?1", params![session_id]);
+
+    let prefix = ref_prefix(session_id);
+    std::thread::spawn(move || {
+        for root in roots {
+            let Ok(refs) = git(Path::new(&root), &["for-each-ref", "--format=%(refname)", &prefix], &[]) else {
+                continue;
+            };
+            for name in refs.lines() {
+                let _ = git(Path::new(&root), &["update-ref", "-d", name], &[]);
+            }
+        }
+    });
+}
+
+// =====================================================================
+//  Settings
+// =====================================================================
+
+pub fn snapshots_enabled(session_id: &str) -> bool {
+    let conn = crate::state::get().db().lock();
+    conn.query_row(
+        "SELECT COUNT(*) FROM snapshot_sessions WHERE session_id = ?1",
+        params![session_id],
+        |r| r.get::<_, i64>(0),
+    )
+    .map(|n| n > 0)
+    .unwrap_or(false)
+}
+
+pub fn set_snapshots_enabled(session_id: &str, enabled: bool) -> Result<(), String> {
+    let conn = crate::state::get().db().lock();
+    if enabled {
+        conn.execute(
+            "INSERT OR IGNORE INTO snapshot_sessions (session_id) VALUES (?1)",
+            params![session_id],
+        )
+    } else {
+        conn.execute("DELETE FROM snapshot_sessions WHERE session_id = ?1", params![session_id])
+    }
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// =====================================================================
+//  Snapshots
+// =====================================================================
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TurnSnapshot {
+    pub turn: i64,
+    /// "turn" (taken before a prompt) or "pre-revert" (taken before a revert).
+    pub kind: String,
+    pub commit_sha: String,
+    pub created_at: String,
+}
+
+/// True for a stream-json line carrying a new user prompt (as opposed to a
+/// tool result or control message).
+pub fn is_user_prompt(line: &str) -> bool {
+    let Ok(value) = serde_json::from_str::<serde_json::Value>(line.trim()) else {
+        return false;
+    };
+    if value.get("type").and_then(|t| t.as_str()) != Some("user") {
+        return false;
+    }
+    match value.pointer("/message/content") {
+        Some(serde_json::Value::Array(items)) => !items
+            .iter()
+            .all(|i| i.get("type").and_then(|t| t.as_str()) == Some("tool_result")),
+        Some(serde_json::Value::String(_)) => true,
+        _ => false,
+    }
+}
+
+/// Called from the stdin writer just before a user prompt is handed to the
+/// agent. Blocks while git runs; does nothing unless snapshots are enabled.
+pub fn before_user_turn(session_id: &str) {
+    if !snapshots_enabled(session_id) {
+        return;
+    }
+    match take_snapshot(session_id, "turn") {
+        Ok(snapshot) => {
+            if let Some(app) = crate::state::get().app_handle.lock().as_ref() {
+                let _ = app.emit("turn-snapshot", serde_json::json!({
+                    "session_id": session_id,
+                    "snapshot": snapshot,
+                }));
+            }
+        }
+        Err(e) => tracing::error!("snapshot failed for {}: {}", session_id, e),
+    }
+}
+
+pub fn list_turn_snapshots(session_id: &str) -> Result<Vec<TurnSnapshot>, String> {
+    let conn = crate::state::get().db().lock();
+    let mut stmt = conn
+        .prepare(
+            "SELECT turn, kind, commit_sha, created_at FROM turn_snapshots
+             WHERE session_id = ?1 ORDER BY turn ASC",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![session_id], |r| {
+            Ok(TurnSnapshot {
+                turn: r.get(0)?,
+                kind: r.get(1)?,
+                commit_sha: r.get(2)?,
+                created_at: r.get(3)?,
+            })
+        })
+        .map_err(|e| e.to_string())?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(rows)
+}
+
+/// Roll the session's workspace back to the snapshot taken before `turn`.
+/// Returns the "pre-revert" snapshot of the state that was replaced.
+pub fn revert_to_turn(session_id: &str, turn: i64) -> Result<TurnSnapshot, String> {
+    let (repo_root, target) = {
+        let conn = crate::state::get().db().lock();
+        conn.query_row(
+            "SELECT repo_root, commit_sha FROM turn_snapshots WHERE session_id = ?1 AND turn = ?2",
+            params![session_id, turn],
+            |r| Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?)),
+        )
+        .map_err(|_| format!("No snapshot for turn {}", turn))?
+    };
+
+    let safety = take_snapshot(session_id, "pre-revert")?;
+    restore_worktree(Path::new(&repo_root), &target, &safety.commit_sha)?;
+
+    if let Some(app) = crate::state::get().app_handle.lock().as_ref() {
+        let _ = app.emit("turn-reverted", serde_json::json!({
+            "session_id": session_id,
+            "turn": turn,
+            "pre_revert": safety,
+        }));
+    }
+    Ok(safety)
+}
+
+fn take_snapshot(session_id: &str, kind: &str) -> Result<TurnSnapshot, String> {
+    let working_dir: String = {
+        let conn = crate::state::get().db().lock();
+        conn.query_row("SELECT working_dir FROM sessions WHERE id = ?1", params![session_id], |r| r.get(0))
+            .map_err(|_| "Session not found".to_string())?
+    };
+    let dir = PathBuf::from(shellexpand::tilde(&working_dir).to_string());
+    let repo_root = git(&dir, &["rev-parse", "--show-toplevel"], &[])
+        .map_err(|_| format!("{} is not inside a git repository", working_dir))?;
+    let repo_root = PathBuf::from(repo_root.trim());
+
+    let turn: i64 = {
+        let conn = crate::state::get().db().lock();
+        conn.query_row(
+            "SELECT COALESCE(MAX(turn), 0) + 1 FROM turn_snapshots WHERE session_id = ?1",
+            params![session_id],
+            |r| r.get(0),
+        )
+        .map_err(|e| e.to_string())?
+    };
+    let commit_sha = snapshot_worktree(&repo_root, &format!("agent-hub {} snapshot {} ({})", kind, turn, session_id))?;
+    git(&repo_root, &["update-ref", &format!("{}{}", ref_prefix(session_id), turn), &commit_sha], &[])?;
+
+    let snapshot = TurnSnapshot {
+        turn,
+        kind: kind.to_string(),
+        commit_sha,
+        created_at: chrono::Utc::now().to_rfc3339(),
+    };
+    {
+        let conn = crate::state::get().db().lock();
+        conn.execute(
+            "INSERT INTO turn_snapshots (session_id, turn, kind, repo_root, commit_sha, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                session_id,
+                snapshot.turn,
+                snapshot.kind,
+                repo_root.to_string_lossy(),
+                snapshot.commit_sha,
+                snapshot.created_at
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    Ok(snapshot)
+}
+
+fn ref_prefix(session_id: &str) -> String {
+    format!("refs/agent-hub/{}/", session_id)
+}
+
+// =====================================================================
+//  Git plumbing
+// =====================================================================
+
+/// Identity for snapshot commits, so they work without user.name/email set.
+const SNAPSHOT_IDENTITY: &[(&str, &str)] = &[
+    ("GIT_AUTHOR_NAME", "Agent Hub"),
+    ("GIT_AUTHOR_EMAIL", "agent-hub@localhost"),
+    ("GIT_COMMITTER_NAME", "Agent Hub"),
+    ("GIT_COMMITTER_EMAIL", "agent-hub@localhost"),
+];
+
+fn git(dir: &Path, args: &[&str], envs: &[(&str, &str)]) -> Result<String, String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .envs(envs.iter().copied())
+        .output()
+        .map_err(|e| format!("Failed to run git: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "git {} failed: {}",
+            args.first().unwrap_or(&""),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// A temporary index file, removed on drop.
+struct ScratchIndex(PathBuf);
+
+impl ScratchIndex {
+    /// Start from a copy of the repo's real index so `git add` can reuse its
+    /// stat cache instead of rehashing every file.
+    fn new(repo_root: &Path) -> Self {
+        let path = std::env::temp_dir().join(format!("agent-hub-index-{}", uuid::Uuid::new_v4()));
+        if let Ok(real) = git(repo_root, &["rev-parse", "--path-format=absolute", "--git-path", "index"], &[]) {
+            let _ = std::fs::copy(real.trim(), &path);
+        }
+        Self(path)
+    }
+
+    fn env(&self) -> (&'static str, &str) {
+        ("GIT_INDEX_FILE", self.0.to_str().unwrap_or_default())
+    }
+}
+
+impl Drop for ScratchIndex {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Commit the full working tree (tracked + untracked, minus ignored files)
+/// without touching HEAD or the real index. Returns the commit sha.
+fn snapshot_worktree(repo_root: &Path, message: &str) -> Result<String, String> {
+    let index = ScratchIndex::new(repo_root);
+    git(repo_root, &["add", "-A"], &[index.env()])?;
+    let tree = git(repo_root, &["write-tree"], &[index.env()])?;
+    let tree = tree.trim();
+
+    let head = git(repo_root, &["rev-parse", "--verify", "-q", "HEAD"], &[]).ok();
+    let mut args = vec!["commit-tree", tree, "-m", message];
+    if let Some(head) = head.as_deref().map(str::trim) {
+        args.push("-p");
+        args.push(head);
+    }
+    let commit = git(repo_root, &args, SNAPSHOT_IDENTITY)?;
+    Ok(commit.trim().to_string())
+}
+
+/// Make the working tree match snapshot `target`. `current` is a snapshot of
+/// the present state, used to find files created since `target` (which are
+/// removed). The real index is left alone.
+fn restore_worktree(repo_root: &Path, target: &str, current: &str) -> Result<(), String> {
+    let added = git(
+        repo_root,
+        &["diff", "--name-only", "-z", "--no-renames", "--diff-filter=A", target, current],
+        &[],
+    )?;
+    for path in added.split('\0').filter(|p| !p.is_empty()) {
+        let _ = std::fs::remove_file(repo_root.join(path));
+    }
+
+    let index = ScratchIndex::new(repo_root);
+    git(repo_root, &["read-tree", target], &[index.env()])?;
+    git(repo_root, &["checkout-index", "-a", "-f"], &[index.env()])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_repo() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("agent-hub-snap-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        git(&dir, &["init", "-q"], &[]).unwrap();
+        dir
+    }
+
+    #[test]
+    fn restore_undoes_edits_and_new_files() {
+        let repo = temp_repo();
+        std::fs::write(repo.join("a.txt"), "original").unwrap();
+        git(&repo, &["add", "a.txt"], &[]).unwrap();
+        git(&repo, &["commit", "-q", "-m", "init"], SNAPSHOT_IDENTITY).unwrap();
+        std::fs::write(repo.join("untracked.txt"), "keep me").unwrap();
+
+        let before = snapshot_worktree(&repo, "before").unwrap();
+
+        std::fs::write(repo.join("a.txt"), "wrecked").unwrap();
+        std::fs::remove_file(repo.join("untracked.txt")).unwrap();
+        std::fs::write(repo.join("new.txt"), "created by agent").unwrap();
+        let after = snapshot_worktree(&repo, "after").unwrap();
+
+        restore_worktree(&repo, &before, &after).unwrap();
+
+        assert_eq!(std::fs::read_to_string(repo.join("a.txt")).unwrap(), "original");
+        assert_eq!(std::fs::read_to_string(repo.join("untracked.txt")).unwrap(), "keep me");
+        assert!(!repo.join("new.txt").exists());
+        // HEAD and the real index are untouched
+        let status = git(&repo, &["status", "--porcelain"], &[]).unwrap();
+        assert_eq!(status.trim(), "?? untracked.txt");
+
+        let _ = std::fs::remove_dir_all(&repo);
+    }
+
+    #[test]
+    fn snapshot_works_before_first_commit() {
+        let repo = temp_repo();
+        std::fs::write(repo.join("a.txt"), "x").unwrap();
+        let sha = snapshot_worktree(&repo, "snap").unwrap();
+        assert_eq!(sha.len(), 40);
+        assert!(git(&repo, &["rev-parse", "--verify", "-q", "HEAD"], &[]).is_err());
+        let _ = std::fs::remove_dir_all(&repo);
+    }
+
+    #[test]
+    fn detects_user_prompts() {
+        assert!(is_user_prompt(
+            r#"{"type":"user","message":{"role":"user","content":[{"type":"text","text":"hi"}]}}"#
+        ));
+        assert!(is_user_prompt(r#"{"type":"user","message":{"role":"user","content":"hi"}}"#));
+        assert!(!is_user_prompt(
+            r#"{"type":"user","message":{"role":"user","content":[{"type":"tool_result","tool_use_id":"x"}]}}"#
+        ));
+        assert!(!is_user_prompt(r#"{"type":"control_request"}"#));
+        assert!(!is_user_prompt("not json"));
+    }
+}
@@ -0,0 +1,332 @@
+// State shared by the app's subsystems.
+//
+// One multi-thread tokio runtime runs everything async: Tauri's own tasks
+// (it's handed over with tauri::async_runtime::set), the web server, each
+// JSON session's stdin / stdout / stderr tasks and the --mcp server. Each of
+// those used to build a Runtime on a thread of its own, which cost a set of
+// threads per running session and meant a session's tasks couldn't await
+// work on another subsystem's runtime.
+//
+// AppState also owns the registries that were process-wide statics: running
+// PTY and JSON sessions and their output channels, the status stream, mobile
+// clients, pairing, settings and the database connection from lib.rs, and
+// the subsystems' per-session state (queue, fan-out, pipelines, context
+// usage, presence, asks, attention, restarts, the watchdog, push, errors,
+// summaries, terminal screens, remote attachments). What's still a static
+// belongs to the process or the desktop rather than to sessions: the tunnel,
+// mDNS advertiser and TTS child processes, the active TLS certificate, the
+// menus, the global shortcut's target and the last desktop notification,
+// caches (secrets, redaction rules), the message log's write lock and links
+// held until the frontend is ready. Tauri commands get it from `app.state()`
+// (it's managed in setup) and web handlers from axum's `State`; code with
+// neither reaches it through get(). A state built with new() against a
+// scratch database and installed before anything calls get() is how tests
+// run the server in-process.
+
+use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
+use rusqlite::Connection;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use tauri::AppHandle;
+use tokio::runtime::{Handle, Runtime, RuntimeFlavor};
+use tokio::sync::{broadcast, watch};
+use tokio::task::JoinHandle;
+
+pub struct AppState {
+    runtime: Runtime,
+    db_path: PathBuf,
+    /// Opened on first use, so a state can be built before the data
+    /// directory is settled
+    db: OnceCell<Mutex<Connection>>,
+    /// For the web server and background tasks to emit to the desktop
+    pub(crate) app_handle: Mutex<Option<AppHandle>>,
+    #[cfg(not(target_os = "ios"))]
+    pub(crate) pty_sessions: Mutex<HashMap<String, Arc<Mutex<crate::PtySession>>>>,
+    /// PTY output, for both Tauri and WebSocket clients
+    #[cfg(not(target_os = "ios"))]
+    pub(crate) pty_broadcasters: Mutex<HashMap<String, broadcast::Sender<Vec<u8>>>>,
+    /// JSON process sessions (non-PTY, streaming JSON over stdin / stdout)
+    #[cfg(not(target_os = "ios"))]
+    pub(crate) json_processes: Mutex<HashMap<String, crate::JsonProcess>>,
+    /// JSON process output, for WebSocket clients
+    #[cfg(not(target_os = "ios"))]
+    pub(crate) json_broadcasters: Mutex<HashMap<String, broadcast::Sender<String>>>,
+    /// Session status changes; every status WebSocket subscribes
+    #[cfg(not(target_os = "ios"))]
+    pub(crate) status_broadcaster: broadcast::Sender<Arc<crate::events::Broadcast>>,
+    /// claude-json sessions mid-turn, as last reported by
+    /// broadcast_processing_status; mirrored to sessions.processing
+    #[cfg(not(target_os = "ios"))]
+    pub(crate) processing_sessions: Mutex<HashSet<String>>,
+    /// Connected mobile WebSockets, by connection id
+    #[cfg(not(target_os = "ios"))]
+    pub(crate) mobile_clients: Mutex<HashMap<String, crate::MobileClient>>,
+    /// Every message of each session, kept here so they're persisted to the
+    /// DB whether or not the desktop has the session's tab open (messages sent
+    /// from mobile are never lost)
+    #[cfg(not(target_os = "ios"))]
+    pub(crate) session_messages: Mutex<HashMap<String, Vec<serde_json::Value>>>,
+    /// Sessions whose messages have been loaded from the DB into session_messages
+    #[cfg(not(target_os = "ios"))]
+    pub(crate) session_messages_loaded: Mutex<HashSet<String>>,
+    /// How many of each session's session_messages its saved buffer holds, so
+    /// end-of-turn saves append just the rest
+    #[cfg(not(target_os = "ios"))]
+    pub(crate) session_messages_saved: Mutex<HashMap<String, usize>>,
+    /// Sessions kept off remote devices (remote_access = 0), mirrored from the DB
+    pub(crate) remote_hidden_sessions: Mutex<HashSet<String>>,
+    /// Active pairing requests (pairing_id -> code)
+    pub(crate) pairing_requests: Mutex<HashMap<String, crate::PairingRequest>>,
+    /// Paired devices (SHA-256 of token -> device info)
+    pub(crate) paired_devices: Mutex<HashMap<String, crate::PairedDevice>>,
+    /// Token hash -> paired_devices row key. The hashes themselves live in the
+    /// secret store (secrets::DEVICE_TOKENS), not the database.
+    pub(crate) token_slots: Mutex<HashMap<String, String>>,
+    /// Whether token_slots holds everything in the secret store. Until it
+    /// does (the store couldn't be read at startup) the map isn't written
+    /// back, so a partial one can't replace it.
+    pub(crate) token_slots_loaded: AtomicBool,
+    /// Current settings: read from config.json on first use and replaced on
+    /// every write, so readers don't go to disk (or the secret store for the
+    /// PIN) and subscribers (watch_settings) see changes as they're saved
+    pub(crate) settings: watch::Sender<Option<crate::AppSettings>>,
+    /// Determined at startup, with failover
+    pub(crate) web_server_port: Mutex<Option<u16>>,
+    /// Bumped to make the web server re-read its network settings and rebind;
+    /// open WebSockets close when it changes
+    pub(crate) web_server_restart: watch::Sender<u64>,
+
+    // Per-session registries of the subsystems, each only touched by its
+    // own module
+    /// Sessions with a queued prompt currently being answered (queue.rs).
+    /// Only prompts the queue itself sent are tracked here — a prompt typed
+    /// by hand while the queue is idle doesn't block the queue (stream-json
+    /// input is processed in order anyway, so an extra message just waits
+    /// its turn).
+    #[cfg(not(target_os = "ios"))]
+    pub(crate) queue_in_flight: Mutex<HashSet<String>>,
+    /// Session -> fan-out run it is answering (fanout.rs)
+    #[cfg(not(target_os = "ios"))]
+    pub(crate) fanout_pending: Mutex<HashMap<String, String>>,
+    /// Sessions a fan-out has picked but not yet sent its prompt to (they may
+    /// be starting), so a second fan-out can't pick them too
+    #[cfg(not(target_os = "ios"))]
+    pub(crate) fanout_sending: Mutex<HashSet<String>>,
+    /// Finished turns to the pipeline coordinator, once it's started (pipeline.rs)
+    #[cfg(not(target_os = "ios"))]
+    pub(crate) pipeline_coordinator: Mutex<Option<std::sync::mpsc::Sender<crate::pipeline::TurnComplete>>>,
+    /// Target session -> (pipeline id, hop depth) for prompts the coordinator
+    /// has sent and is waiting on. The target's next result completes the run
+    /// and inherits the depth for any downstream pipelines.
+    #[cfg(not(target_os = "ios"))]
+    pub(crate) pipeline_runs: Mutex<HashMap<String, (String, u32)>>,
+    /// Context window usage per claude-json session (context.rs)
+    #[cfg(not(target_os = "ios"))]
+    pub(crate) context_usage: Mutex<HashMap<String, crate::context::ContextUsage>>,
+    /// Who has each session open (presence.rs)
+    #[cfg(not(target_os = "ios"))]
+    pub(crate) presence: Mutex<crate::presence::Presence>,
+    /// Open questions and approval requests from agents (asks.rs)
+    #[cfg(not(target_os = "ios"))]
+    pub(crate) asks: Mutex<crate::asks::Asks>,
+    /// Sessions waiting on the user (attention.rs)
+    #[cfg(not(target_os = "ios"))]
+    pub(crate) attention_flagged: Mutex<HashSet<String>>,
+    /// Restart attempts of crashing claude-json processes (restarts.rs)
+    #[cfg(not(target_os = "ios"))]
+    pub(crate) restarts: Mutex<HashMap<String, crate::restarts::RestartState>>,
+    /// Session -> when we last stopped or interrupted it ourselves
+    #[cfg(not(target_os = "ios"))]
+    pub(crate) restarts_stopped: Mutex<HashMap<String, std::time::Instant>>,
+    /// Output and turn state the hang watchdog goes by (watchdog.rs)
+    #[cfg(not(target_os = "ios"))]
+    pub(crate) watchdog_activity: Mutex<HashMap<String, crate::watchdog::Activity>>,
+    /// When each (session, event) was last notified (push.rs)
+    #[cfg(not(target_os = "ios"))]
+    pub(crate) push_last_sent: Mutex<HashMap<(String, String), std::time::Instant>>,
+    /// Session -> error codes already reported for the current process
+    /// (session_errors.rs)
+    #[cfg(not(target_os = "ios"))]
+    pub(crate) session_errors_reported: Mutex<HashMap<String, HashSet<&'static str>>>,
+    /// Session -> activity counter; a pending idle check only fires if the
+    /// counter hasn't moved since it was scheduled (summaries.rs)
+    #[cfg(not(target_os = "ios"))]
+    pub(crate) summary_activity: Mutex<HashMap<String, u64>>,
+    /// Session -> hash of the transcript last summarized
+    #[cfg(not(target_os = "ios"))]
+    pub(crate) summarized: Mutex<HashMap<String, u64>>,
+    /// Sessions with a summary being generated
+    #[cfg(not(target_os = "ios"))]
+    pub(crate) summaries_running: Mutex<HashSet<String>>,
+    /// Plain-text screens of PTY sessions (term_screen.rs)
+    #[cfg(not(target_os = "ios"))]
+    pub(crate) term_screens: Mutex<HashMap<String, crate::term_screen::Screen>>,
+    /// Attached remote sessions by session id (remote_sessions.rs)
+    pub(crate) remote_attached: Mutex<HashMap<String, crate::remote_sessions::Attachment>>,
+}
+
+static STATE: OnceCell<Arc<AppState>> = OnceCell::new();
+
+impl AppState {
+    /// A state whose database is `db_path` (not opened until first use)
+    pub fn new(db_path: PathBuf) -> Self {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .thread_name("agent-hub-rt")
+            .build()
+            .expect("Failed to create tokio runtime");
+        AppState {
+            runtime,
+            db_path,
+            db: OnceCell::new(),
+            app_handle: Mutex::new(None),
+            #[cfg(not(target_os = "ios"))]
+            pty_sessions: Mutex::new(HashMap::new()),
+            #[cfg(not(target_os = "ios"))]
+            pty_broadcasters: Mutex::new(HashMap::new()),
+            #[cfg(not(target_os = "ios"))]
+            json_processes: Mutex::new(HashMap::new()),
+            #[cfg(not(target_os = "ios"))]
+            json_broadcasters: Mutex::new(HashMap::new()),
+            #[cfg(not(target_os = "ios"))]
+            status_broadcaster: broadcast::channel(64).0,
+            #[cfg(not(target_os = "ios"))]
+            processing_sessions: Mutex::new(HashSet::new()),
+            #[cfg(not(target_os = "ios"))]
+            mobile_clients: Mutex::new(HashMap::new()),
+            #[cfg(not(target_os = "ios"))]
+            session_messages: Mutex::new(HashMap::new()),
+            #[cfg(not(target_os = "ios"))]
+            session_messages_loaded: Mutex::new(HashSet::new()),
+            #[cfg(not(target_os = "ios"))]
+            session_messages_saved: Mutex::new(HashMap::new()),
+            remote_hidden_sessions: Mutex::new(HashSet::new()),
+            pairing_requests: Mutex::new(HashMap::new()),
+            paired_devices: Mutex::new(HashMap::new()),
+            token_slots: Mutex::new(HashMap::new()),
+            token_slots_loaded: AtomicBool::new(false),
+            settings: watch::channel(None).0,
+            web_server_port: Mutex::new(None),
+            web_server_restart: watch::channel(0).0,
+            #[cfg(not(target_os = "ios"))]
+            queue_in_flight: Mutex::new(HashSet::new()),
+            #[cfg(not(target_os = "ios"))]
+            fanout_pending: Mutex::new(HashMap::new()),
+            #[cfg(not(target_os = "ios"))]
+            fanout_sending: Mutex::new(HashSet::new()),
+            #[cfg(not(target_os = "ios"))]
+            pipeline_coordinator: Mutex::new(None),
+            #[cfg(not(target_os = "ios"))]
+            pipeline_runs: Mutex::new(HashMap::new()),
+            #[cfg(not(target_os = "ios"))]
+            context_usage: Mutex::new(HashMap::new()),
+            #[cfg(not(target_os = "ios"))]
+            presence: Mutex::new(Default::default()),
+            #[cfg(not(target_os = "ios"))]
+            asks: Mutex::new(Default::default()),
+            #[cfg(not(target_os = "ios"))]
+            attention_flagged: Mutex::new(HashSet::new()),
+            #[cfg(not(target_os = "ios"))]
+            restarts: Mutex::new(HashMap::new()),
+            #[cfg(not(target_os = "ios"))]
+            restarts_stopped: Mutex::new(HashMap::new()),
+            #[cfg(not(target_os = "ios"))]
+            watchdog_activity: Mutex::new(HashMap::new()),
+            #[cfg(not(target_os = "ios"))]
+            push_last_sent: Mutex::new(HashMap::new()),
+            #[cfg(not(target_os = "ios"))]
+            session_errors_reported: Mutex::new(HashMap::new()),
+            #[cfg(not(target_os = "ios"))]
+            summary_activity: Mutex::new(HashMap::new()),
+            #[cfg(not(target_os = "ios"))]
+            summarized: Mutex::new(HashMap::new()),
+            #[cfg(not(target_os = "ios"))]
+            summaries_running: Mutex::new(HashSet::new()),
+            #[cfg(not(target_os = "ios"))]
+            term_screens: Mutex::new(HashMap::new()),
+            remote_attached: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn runtime(&self) -> &Handle {
+        self.runtime.handle()
+    }
+
+    /// The shared database connection
+    pub fn db(&self) -> &Mutex<Connection> {
+        self.db.get_or_init(|| {
+            let conn = Connection::open(&self.db_path).expect("Failed to open database");
+            conn.busy_timeout(std::time::Duration::from_secs(5)).expect("Failed to set busy timeout");
+            // Enable WAL mode for better concurrent read/write performance
+            conn.execute_batch("PRAGMA journal_mode=WAL;").expect("Failed to enable WAL mode");
+            Mutex::new(conn)
+        })
+    }
+}
+
+/// Build the state and give Tauri its runtime; first thing in run(), before
+/// anything uses tauri::async_runtime
+pub fn init() {
+    tauri::async_runtime::set(get().runtime().clone());
+}
+
+/// Use `state` rather than the app's own; fails if get() has already built one
+pub fn install(state: Arc<AppState>) -> Result<(), String> {
+    STATE.set(state).map_err(|_| "App state is already initialized".to_string())
+}
+
+pub fn get() -> &'static Arc<AppState> {
+    STATE.get_or_init(|| Arc::new(AppState::new(crate::get_db_path())))
+}
+
+/// Run a future on the shared runtime, from any thread
+pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    get().runtime().spawn(future)
+}
+
+/// Run blocking `f` (waiting on a std channel, say). On a runtime worker the
+/// worker's other tasks are moved off first so they aren't stalled behind it.
+pub fn blocking<T>(f: impl FnOnce() -> T) -> T {
+    match Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => tokio::task::block_in_place(f),
+        _ => f(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spawned_tasks_run_and_can_block() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        spawn(async move {
+            tx.send(blocking(|| 1 + 1)).unwrap();
+        });
+        assert_eq!(rx.recv_timeout(std::time::Duration::from_secs(5)), Ok(2));
+        // Off the runtime it's a plain call
+        assert_eq!(blocking(|| "ok"), "ok");
+    }
+
+    #[test]
+    fn database_opens_on_first_use() {
+        let dir = std::env::temp_dir().join(format!("agent-hub-state-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("sessions.db");
+        let state = AppState::new(path.clone());
+        assert!(!path.exists());
+        let one: i64 = state.db().lock().query_row("SELECT 1", [], |r| r.get(0)).unwrap();
+        assert_eq!(one, 1);
+        assert!(path.exists());
+        drop(state);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
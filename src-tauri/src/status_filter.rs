@@ -0,0 +1,123 @@
+// Narrowing /api/ws/status to what a client asked for in its query string:
+//   events=session_status,session_updated   only these event types
+//   sessions=<id>,<id>                      only events about these sessions
+//   folder=<id>                             only events about that folder and
+//                                           the sessions in it
+// Without any of them the client gets every event, as before. Events that
+// aren't about a session (settings_updated, ...) only pass the events filter.
+
+use crate::events::SessionEvent;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Default)]
+pub struct StatusFilter {
+    events: Option<HashSet<String>>,
+    sessions: Option<HashSet<String>>,
+    folder: Option<String>,
+}
+
+fn list(query: &HashMap<String, String>, key: &str) -> Option<HashSet<String>> {
+    let items: HashSet<String> = query
+        .get(key)?
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(String::from)
+        .collect();
+    (!items.is_empty()).then_some(items)
+}
+
+impl StatusFilter {
+    pub fn from_query(query: &HashMap<String, String>) -> Self {
+        StatusFilter {
+            events: list(query, "events"),
+            sessions: list(query, "sessions"),
+            folder: query.get("folder").filter(|f| !f.is_empty()).cloned(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_none() && self.sessions.is_none() && self.folder.is_none()
+    }
+
+    /// Whether a status event goes to the client. `folder_of` looks up a
+    /// session's folder when the event doesn't say.
+    pub fn matches(&self, event: &SessionEvent, folder_of: impl Fn(&str) -> Option<String>) -> bool {
+        if self.events.as_ref().is_some_and(|events| !events.contains(event.name())) {
+            return false;
+        }
+        if self.sessions.is_none() && self.folder.is_none() {
+            return true;
+        }
+        if event.is_folder_event() {
+            // About a folder, not a session
+            let folder_id = event.folder_id().flatten();
+            return self.sessions.is_none() && folder_id.is_some() && folder_id == self.folder.as_deref();
+        }
+        let Some(session_id) = event.session_id() else { return false };
+        if self.sessions.as_ref().is_some_and(|sessions| !sessions.contains(session_id)) {
+            return false;
+        }
+        match &self.folder {
+            None => true,
+            Some(folder) => {
+                let folder_id = match event.folder_id() {
+                    Some(folder_id) => folder_id.map(String::from),
+                    None => folder_of(session_id),
+                };
+                folder_id.as_ref() == Some(folder)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(query: &[(&str, &str)]) -> StatusFilter {
+        StatusFilter::from_query(&query.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect())
+    }
+
+    fn session(id: &str, folder_id: Option<&str>) -> crate::SessionData {
+        serde_json::from_value(serde_json::json!({
+            "id": id, "name": id, "agent_type": "claude", "command": "claude", "working_dir": "/tmp",
+            "created_at": "", "claude_session_id": null, "sort_order": 0, "folder_id": folder_id,
+        }))
+        .unwrap()
+    }
+
+    fn folder(id: &str) -> crate::FolderData {
+        serde_json::from_value(serde_json::json!({ "id": id, "name": "Work", "sort_order": 0, "collapsed": false })).unwrap()
+    }
+
+    #[test]
+    fn filters_by_event_session_and_folder() {
+        let no_folder = |_: &str| None;
+        let status = SessionEvent::SessionStatus { session_id: "s1".to_string(), running: true };
+        let settings = SessionEvent::SettingsUpdated(serde_json::json!({ "theme": "dark" }));
+
+        let everything = filter(&[]);
+        assert!(everything.matches(&status, no_folder));
+        assert!(everything.matches(&settings, no_folder));
+
+        let events = filter(&[("events", "session_status, session_updated")]);
+        assert!(events.matches(&status, no_folder));
+        assert!(!events.matches(&settings, no_folder));
+
+        let sessions = filter(&[("sessions", "s2,s1")]);
+        assert!(sessions.matches(&status, no_folder));
+        assert!(!sessions.matches(&SessionEvent::SessionDeleted { session_id: "s3".to_string() }, no_folder));
+        assert!(!sessions.matches(&settings, no_folder));
+
+        let in_f1 = filter(&[("folder", "f1")]);
+        assert!(in_f1.matches(&status, |id| (id == "s1").then(|| "f1".to_string())));
+        assert!(!in_f1.matches(&status, no_folder));
+        // Sessions carry their folder; an unfiled one isn't in it
+        assert!(in_f1.matches(&SessionEvent::SessionUpdated(session("s2", Some("f1"))), no_folder));
+        assert!(!in_f1.matches(&SessionEvent::SessionUpdated(session("s1", None)), |_| Some("f1".to_string())));
+        assert!(in_f1.matches(&SessionEvent::FolderUpdated(folder("f1")), no_folder));
+        assert!(in_f1.matches(&SessionEvent::FolderDeleted { folder_id: "f1".to_string() }, no_folder));
+        assert!(!in_f1.matches(&SessionEvent::FolderDeleted { folder_id: "f2".to_string() }, no_folder));
+    }
+}
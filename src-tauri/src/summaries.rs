@@ -0,0 +1,225 @@
+// One- or two-sentence summaries of claude-json sessions for the session
+// list, so a list of "Claude Chat 12"s is navigable.
+//
+// When a session has been idle for IDLE_DELAY after a turn, the tail of its
+// transcript is handed to a one-shot `claude -p` run (small model) and the
+// answer is stored in `sessions.summary`. Sessions whose transcript hasn't
+// changed since the last summary are skipped.
+
+use rusqlite::{params, Connection};
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+const IDLE_DELAY: Duration = Duration::from_secs(45);
+const CLI_TIMEOUT: Duration = Duration::from_secs(120);
+/// Only the end of long transcripts is summarized.
+const MAX_TRANSCRIPT_CHARS: usize = 12_000;
+const MAX_SUMMARY_CHARS: usize = 300;
+
+const INSTRUCTIONS: &str = "Below is the end of a conversation between a user and a coding agent. \
+Summarize what the session is working on in one or two short sentences, for display in a session list. \
+Reply with the summary only: no preamble, no quotes, no markdown.";
+
+pub fn run_summary_migrations(conn: &Connection) {
+    let _ = conn.execute("ALTER TABLE sessions ADD COLUMN summary TEXT", []);
+    let _ = conn.execute("ALTER TABLE sessions ADD COLUMN summary_updated_at TEXT", []);
+}
+
+/// Called for every parsed stdout message. A `result` (turn finished) starts
+/// the idle timer; anything else just counts as activity.
+pub fn on_message(session_id: &str, is_result: bool) {
+    let generation = {
+        let mut activity = crate::state::get().summary_activity.lock();
+        let counter = activity.entry(session_id.to_string()).or_insert(0);
+        *counter += 1;
+        *counter
+    };
+    if !is_result || !crate::load_app_settings().unwrap_or_default().auto_summaries_enabled {
+        return;
+    }
+
+    let session_id = session_id.to_string();
+    std::thread::spawn(move || {
+        std::thread::sleep(IDLE_DELAY);
+        if crate::state::get().summary_activity.lock().get(&session_id).copied() != Some(generation) {
+            return; // Another turn started meanwhile
+        }
+        if !crate::state::get().summaries_running.lock().insert(session_id.clone()) {
+            return;
+        }
+        if let Err(e) = summarize_session(&session_id) {
+            tracing::warn!("{}: {}", session_id, e);
+        }
+        crate::state::get().summaries_running.lock().remove(&session_id);
+    });
+}
+
+pub fn forget(session_id: &str) {
+    crate::state::get().summary_activity.lock().remove(session_id);
+    crate::state::get().summarized.lock().remove(session_id);
+}
+
+fn summarize_session(session_id: &str) -> Result<(), String> {
+    let history = crate::get_session_history(session_id).unwrap_or_default();
+    let transcript = build_transcript(&history);
+    if transcript.is_empty() {
+        return Ok(());
+    }
+    let hash = {
+        use std::hash::{Hash, Hasher};
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        transcript.hash(&mut hasher);
+        hasher.finish()
+    };
+    if crate::state::get().summarized.lock().get(session_id) == Some(&hash) {
+        return Ok(());
+    }
+
+    let summary = clean_summary(&run_cli(&format!("{}\n\n{}", INSTRUCTIONS, transcript))?);
+    if summary.is_empty() {
+        return Err("empty summary".to_string());
+    }
+    {
+        let conn = crate::state::get().db().lock();
+        conn.execute(
+            "UPDATE sessions SET summary = ?1, summary_updated_at = ?2 WHERE id = ?3",
+            params![summary, chrono::Utc::now().to_rfc3339(), session_id],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    crate::state::get().summarized.lock().insert(session_id.to_string(), hash);
+
+    if let Some(session) = crate::load_sessions()?.into_iter().find(|s| s.id == session_id) {
+        crate::broadcast_session_updated(&session);
+        crate::broadcast_session_list_to_mobile();
+    }
+    Ok(())
+}
+
+/// Plain "User: / Assistant:" text of a session's messages (tool calls and
+/// results left out), trimmed to the last MAX_TRANSCRIPT_CHARS.
+fn build_transcript(messages: &[serde_json::Value]) -> String {
+    let mut lines = Vec::new();
+    for msg in messages {
+        let role = match msg.get("type").and_then(|t| t.as_str()) {
+            Some("user") => "User",
+            Some("assistant") => "Assistant",
+            _ => continue,
+        };
+        let text = match msg.pointer("/message/content") {
+            Some(serde_json::Value::String(s)) => s.clone(),
+            Some(serde_json::Value::Array(items)) => items
+                .iter()
+                .filter(|i| i.get("type").and_then(|t| t.as_str()) == Some("text"))
+                .filter_map(|i| i.get("text").and_then(|t| t.as_str()))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            _ => continue,
+        };
+        let text = text.trim();
+        if !text.is_empty() {
+            lines.push(format!("{}: {}", role, text));
+        }
+    }
+    let transcript = lines.join("\n\n");
+    if transcript.len() <= MAX_TRANSCRIPT_CHARS {
+        return transcript;
+    }
+    let mut start = transcript.len() - MAX_TRANSCRIPT_CHARS;
+    while !transcript.is_char_boundary(start) {
+        start += 1;
+    }
+    transcript[start..].to_string()
+}
+
+/// First paragraph of the CLI's answer, unquoted and length-capped.
+fn clean_summary(raw: &str) -> String {
+    let first = raw.trim().split("\n\n").next().unwrap_or("").replace('\n', " ");
+    let trimmed = first.trim().trim_matches('"').trim();
+    if trimmed.chars().count() <= MAX_SUMMARY_CHARS {
+        return trimmed.to_string();
+    }
+    let cut: String = trimmed.chars().take(MAX_SUMMARY_CHARS - 1).collect();
+    format!("{}…", cut.trim_end())
+}
+
+/// One-shot `claude -p` run with `prompt` on stdin. Runs in the
+/// temp dir so it doesn't land in any project's history.
+fn run_cli(prompt: &str) -> Result<String, String> {
+    let shell = crate::platform::user_shell();
+    let mut cmd = Command::new(&shell);
+    cmd.args(["-l", "-c", "claude -p --model haiku"])
+        .env("PATH", crate::platform::agent_path())
+        .current_dir(std::env::temp_dir())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null());
+    if let Some(dir) = crate::load_app_settings().unwrap_or_default().claude_config_dir {
+        if !dir.is_empty() {
+            cmd.env("CLAUDE_CONFIG_DIR", shellexpand::tilde(&dir).to_string());
+        }
+    }
+
+    let mut child = cmd.spawn().map_err(|e| format!("Failed to run claude: {}", e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(prompt.as_bytes()).map_err(|e| e.to_string())?;
+    }
+    let started = Instant::now();
+    loop {
+        match child.try_wait().map_err(|e| e.to_string())? {
+            Some(status) if status.success() => break,
+            Some(status) => return Err(format!("claude exited with {}", status)),
+            None if started.elapsed() > CLI_TIMEOUT => {
+                let _ = child.kill();
+                return Err("claude timed out".to_string());
+            }
+            None => std::thread::sleep(Duration::from_millis(250)),
+        }
+    }
+    let output = child.wait_with_output().map_err(|e| e.to_string())?;
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transcript_keeps_only_conversation_text() {
+        let messages = vec![
+            serde_json::json!({"type": "system", "subtype": "init"}),
+            serde_json::json!({"type": "user", "message": {"content": "Fix the login bug"}}),
+            serde_json::json!({"type": "assistant", "message": {"content": [
+                {"type": "text", "text": "Looking at auth.rs"},
+                {"type": "tool_use", "id": "t", "name": "Read", "input": {}}
+            ]}}),
+            serde_json::json!({"type": "user", "message": {"content": [
+                {"type": "tool_result", "tool_use_id": "t", "content": "..."}
+            ]}}),
+        ];
+        assert_eq!(
+            build_transcript(&messages),
+            "User: Fix the login bug\n\nAssistant: Looking at auth.rs"
+        );
+    }
+
+    #[test]
+    fn transcript_is_truncated_from_the_front() {
+        let long = "é".repeat(MAX_TRANSCRIPT_CHARS);
+        let messages = vec![
+            serde_json::json!({"type": "user", "message": {"content": long}}),
+            serde_json::json!({"type": "assistant", "message": {"content": "done"}}),
+        ];
+        let transcript = build_transcript(&messages);
+        assert!(transcript.len() <= MAX_TRANSCRIPT_CHARS);
+        assert!(transcript.ends_with("Assistant: done"));
+    }
+
+    #[test]
+    fn summary_is_first_paragraph_unquoted() {
+        assert_eq!(clean_summary("\"Fixing the login flow.\"\n\nExtra"), "Fixing the login flow.");
+        let long = "word ".repeat(100);
+        assert!(clean_summary(&long).chars().count() <= MAX_SUMMARY_CHARS);
+    }
+}
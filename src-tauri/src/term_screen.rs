@@ -0,0 +1,206 @@
+// Server-side view of what a PTY session's terminal shows, as plain text,
+// for callers that can't run xterm.js (the MCP terminal tools).
+//
+// This is a line-oriented emulation, not a full terminal: it follows carriage
+// returns, backspaces, tabs, line erases, screen clears and horizontal cursor
+// moves, which covers shells and line-based tools. Colors and other escape
+// sequences are dropped, and vertical cursor movement is ignored, so
+// full-screen programs (vim, htop) come out garbled.
+
+use std::collections::VecDeque;
+
+/// Scrollback kept per session
+const MAX_LINES: usize = 5000;
+
+#[derive(Debug, Clone, PartialEq)]
+enum State {
+    Ground,
+    Escape,
+    /// Escape sequence taking one more character (charset selection)
+    EscapeArg,
+    Csi(String),
+    Osc,
+    OscEscape,
+}
+
+#[derive(Debug)]
+pub struct Screen {
+    lines: VecDeque<Vec<char>>,
+    col: usize,
+    state: State,
+}
+
+impl Default for Screen {
+    fn default() -> Self {
+        Screen { lines: VecDeque::from([Vec::new()]), col: 0, state: State::Ground }
+    }
+}
+
+impl Screen {
+    fn line(&mut self) -> &mut Vec<char> {
+        self.lines.back_mut().expect("a screen always has a line")
+    }
+
+    fn put(&mut self, c: char) {
+        let col = self.col;
+        let line = self.line();
+        if line.len() <= col {
+            line.resize(col, ' ');
+            line.push(c);
+        } else {
+            line[col] = c;
+        }
+        self.col += 1;
+    }
+
+    fn newline(&mut self) {
+        self.lines.push_back(Vec::new());
+        if self.lines.len() > MAX_LINES {
+            self.lines.pop_front();
+        }
+        self.col = 0;
+    }
+
+    fn csi(&mut self, params: &str, command: char) {
+        let nums: Vec<usize> = params
+            .trim_start_matches('?')
+            .split(';')
+            .map(|p| p.parse().unwrap_or(0))
+            .collect();
+        let n = nums.first().copied().unwrap_or(0);
+        match command {
+            'K' => {
+                let col = self.col;
+                let line = self.line();
+                match n {
+                    0 => line.truncate(col),
+                    1 => line.iter_mut().take(col + 1).for_each(|c| *c = ' '),
+                    _ => line.clear(),
+                }
+            }
+            'J' if n >= 2 => *self = Screen::default(),
+            'C' => self.col += n.max(1),
+            'D' => self.col = self.col.saturating_sub(n.max(1)),
+            'G' => self.col = n.saturating_sub(1),
+            'H' | 'f' => self.col = nums.get(1).copied().unwrap_or(1).saturating_sub(1),
+            _ => {}
+        }
+    }
+
+    pub fn feed(&mut self, text: &str) {
+        for c in text.chars() {
+            match std::mem::replace(&mut self.state, State::Ground) {
+                State::Ground => match c {
+                    '\x1b' => self.state = State::Escape,
+                    '\r' => self.col = 0,
+                    '\n' => self.newline(),
+                    '\x08' => self.col = self.col.saturating_sub(1),
+                    '\t' => {
+                        let next = (self.col / 8 + 1) * 8;
+                        while self.col < next {
+                            self.put(' ');
+                        }
+                    }
+                    c if c.is_control() => {}
+                    c => self.put(c),
+                },
+                State::Escape => match c {
+                    '[' => self.state = State::Csi(String::new()),
+                    ']' => self.state = State::Osc,
+                    '(' | ')' | '*' | '+' | '#' => self.state = State::EscapeArg,
+                    _ => {}
+                },
+                State::EscapeArg => {}
+                State::Csi(mut params) => {
+                    if ('@'..='~').contains(&c) {
+                        self.csi(&params, c);
+                    } else {
+                        params.push(c);
+                        self.state = State::Csi(params);
+                    }
+                }
+                State::Osc => match c {
+                    '\x07' => {}
+                    '\x1b' => self.state = State::OscEscape,
+                    _ => self.state = State::Osc,
+                },
+                // ESC \ ends an OSC; anything else starts over as an escape
+                State::OscEscape => {
+                    if c != '\\' {
+                        self.state = State::Escape;
+                        self.feed(&c.to_string());
+                    }
+                }
+            }
+        }
+    }
+
+    /// The last `count` lines, without trailing blanks
+    pub fn tail(&self, count: usize) -> String {
+        let mut lines: Vec<String> = self
+            .lines
+            .iter()
+            .map(|line| line.iter().collect::<String>().trim_end().to_string())
+            .collect();
+        while lines.len() > 1 && lines.last().is_some_and(|l| l.is_empty()) {
+            lines.pop();
+        }
+        lines[lines.len().saturating_sub(count)..].join("\n")
+    }
+}
+
+/// Terminal output from a session's PTY
+pub fn feed(session_id: &str, text: &str) {
+    crate::state::get().term_screens.lock().entry(session_id.to_string()).or_default().feed(text);
+}
+
+/// The last `count` lines a session's terminal shows; None if it never had output
+pub fn tail(session_id: &str, count: usize) -> Option<String> {
+    crate::state::get().term_screens.lock().get(session_id).map(|screen| screen.tail(count))
+}
+
+pub fn forget(session_id: &str) {
+    crate::state::get().term_screens.lock().remove(session_id);
+}
+
+/// Render output on its own, as a fresh terminal would show it
+pub fn render(text: &str) -> String {
+    let mut screen = Screen::default();
+    screen.feed(text);
+    screen.tail(MAX_LINES)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follows_line_editing_and_drops_escapes() {
+        // Colored prompt with a title, a typo erased the way shells echo it,
+        // and a progress bar redrawn in place
+        let output = "\x1b]0;~/dev\x07\x1b[1;32m$\x1b[0m lsx\x08 \x08\r\n\
+                      10%\r50%\r100%\r\n\
+                      a\tb\r\n\
+                      \x1b(Bdone\x1b[K";
+        assert_eq!(render(output), "$ ls\n100%\na       b\ndone");
+
+        // Clearing the line and rewriting part of it
+        assert_eq!(render("old text\r\x1b[2Knew"), "new");
+        assert_eq!(render("abcdef\x1b[3D\x1b[K"), "abc");
+    }
+
+    #[test]
+    fn clear_screen_and_tail() {
+        let mut screen = Screen::default();
+        screen.feed("one\r\ntwo\r\n\x1b[H\x1b[2J\x1b[3Jthree\r\nfour\r\n$ ");
+        assert_eq!(screen.tail(10), "three\nfour\n$");
+        assert_eq!(screen.tail(2), "four\n$");
+
+        let mut long = Screen::default();
+        for i in 0..MAX_LINES + 10 {
+            long.feed(&format!("{}\r\n", i));
+        }
+        assert_eq!(long.lines.len(), MAX_LINES);
+        assert_eq!(long.tail(1), (MAX_LINES + 9).to_string());
+    }
+}
@@ -0,0 +1,362 @@
+// The web server in-process, for tests of the HTTP API and of the mobile
+// WebSocket protocol remote clients depend on.
+//
+// One server per test run, serving web_router() on a loopback port against
+// the shared state, whose data directory is a scratch one under test (see
+// platform::data_dir). Sessions get fake backends: registered like a running
+// JSON process, with the test holding the process's stdin and standing in
+// for its stdout (emit).
+//
+// A device is paired before the server takes a request, so auth is enforced
+// from the start. Tests run in parallel against the same server and
+// database, so each only looks at the sessions it created.
+//
+// Modules whose tests need the shared state rather than an in-memory
+// connection (running sessions, say) use database(), session_row() and
+// fake_json_session() from here without starting the server.
+
+use crate::{state, DeviceScope, PairedDevice, PROTOCOL_VERSION};
+use futures::{SinkExt, StreamExt};
+use once_cell::sync::OnceCell;
+use serde_json::{json, Value};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::Message;
+
+type Ws = tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+struct TestServer {
+    addr: SocketAddr,
+    /// Token of a device paired with admin scope
+    token: String,
+    client: reqwest::Client,
+}
+
+static SERVER: OnceCell<TestServer> = OnceCell::new();
+
+impl TestServer {
+    /// The shared server, started on first use
+    fn get() -> &'static TestServer {
+        SERVER.get_or_init(TestServer::start)
+    }
+
+    fn start() -> TestServer {
+        database();
+        crate::load_paired_devices();
+        let token = pair_device("Test harness", DeviceScope::Admin);
+        let mobile_web_dir = crate::platform::data_dir().join("mobile-web-dist");
+        let router = crate::web_router(state::get().clone(), &mobile_web_dir, &crate::AppSettings::default());
+        let listener = run(tokio::net::TcpListener::bind("127.0.0.1:0")).expect("Failed to bind test server");
+        let addr = listener.local_addr().expect("Test server has no address");
+        state::spawn(async move {
+            axum::serve(listener, router.into_make_service_with_connect_info::<SocketAddr>()).await
+        });
+        TestServer { addr, token, client: reqwest::Client::new() }
+    }
+
+    /// Call /api`path`, returning the status and the JSON body (Null if none)
+    async fn request(&self, method: &str, path: &str, token: Option<&str>, body: Option<Value>) -> (u16, Value) {
+        let method = reqwest::Method::from_bytes(method.as_bytes()).expect("Bad method");
+        let mut request = self.client.request(method, format!("http://{}/api{}", self.addr, path));
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+        let response = request.send().await.expect("Request failed");
+        let status = response.status().as_u16();
+        (status, response.json().await.unwrap_or(Value::Null))
+    }
+
+    async fn create_session(&self, name: &str) -> String {
+        let (status, session) = self
+            .request("POST", "/sessions", Some(&self.token), Some(json!({ "name": name, "agent_type": "claude-json" })))
+            .await;
+        assert_eq!(status, 200, "{}", session);
+        session["id"].as_str().expect("No session id").to_string()
+    }
+
+    async fn connect_mobile(&self) -> Ws {
+        let (ws, _) = tokio_tungstenite::connect_async(format!("ws://{}/api/ws/mobile", self.addr))
+            .await
+            .expect("WebSocket handshake failed");
+        ws
+    }
+}
+
+/// Run a test's async part on the shared runtime
+fn run<F: Future>(future: F) -> F::Output {
+    state::get().runtime().block_on(future)
+}
+
+/// Pair a device the way api_pair does once the desktop approves; returns its token
+fn pair_device(name: &str, scope: DeviceScope) -> String {
+    let now = chrono::Utc::now().to_rfc3339();
+    let (token, _) = crate::issue_token(PairedDevice {
+        id: crate::generate_token(),
+        name: name.to_string(),
+        paired_at: now.clone(),
+        last_seen: now,
+        expires_at: None,
+        scope,
+        notifications: Default::default(),
+    });
+    token
+}
+
+/// Migrate the scratch database, once per run
+pub(crate) fn database() {
+    static MIGRATED: std::sync::Once = std::sync::Once::new();
+    MIGRATED.call_once(crate::run_db_migrations);
+}
+
+/// A new (stopped) claude-json session, straight into the database
+pub(crate) fn session_row(name: &str) -> String {
+    database();
+    let id = uuid::Uuid::new_v4().to_string();
+    state::get()
+        .db()
+        .lock()
+        .execute(
+            "INSERT INTO sessions (id, name, agent_type, command, working_dir, created_at)
+             VALUES (?1, ?2, 'claude-json', 'claude', '~', ?3)",
+            rusqlite::params![id, name, chrono::Utc::now().to_rfc3339()],
+        )
+        .expect("Failed to insert session");
+    id
+}
+
+/// Register a fake running JSON session. What's written to its process
+/// arrives on the returned receiver.
+pub(crate) fn fake_json_session(session_id: &str) -> tokio::sync::mpsc::Receiver<String> {
+    let (stdin, written) = tokio::sync::mpsc::channel(16);
+    state::get().json_processes.lock().insert(session_id.to_string(), crate::JsonProcess { stdin, child_id: 0 });
+    state::get().json_broadcasters.lock().insert(session_id.to_string(), tokio::sync::broadcast::channel(16).0);
+    written
+}
+
+/// A message from a fake session's agent, delivered as its stdout reader would
+fn emit(session_id: &str, message: Value) {
+    let message: crate::ClaudeJsonMessage = serde_json::from_value(message).expect("Not an agent message");
+    crate::broadcast_chat_message(session_id, &message);
+}
+
+async fn send(ws: &mut Ws, message: Value) {
+    ws.send(Message::Text(message.to_string())).await.expect("WebSocket send failed");
+}
+
+/// The next message of type `kind` (about `session_id`, if given), skipping
+/// others: other tests' events and presence come through as well
+async fn next_of_type(ws: &mut Ws, kind: &str, session_id: Option<&str>) -> Value {
+    let wanted = async {
+        while let Some(frame) = ws.next().await {
+            let Message::Text(text) = frame.expect("WebSocket error") else { continue };
+            let message: Value = serde_json::from_str(&text).expect("Message isn't JSON");
+            if message["type"] == kind && session_id.is_none_or(|id| message["sessionId"] == id) {
+                return message;
+            }
+        }
+        panic!("WebSocket closed waiting for {}", kind);
+    };
+    tokio::time::timeout(Duration::from_secs(5), wanted)
+        .await
+        .unwrap_or_else(|_| panic!("No {} message", kind))
+}
+
+#[test]
+fn api_requires_a_paired_device_with_enough_scope() {
+    let server = TestServer::get();
+    run(async {
+        assert_eq!(server.request("GET", "/health", None, None).await.0, 200);
+        assert_eq!(server.request("GET", "/sessions", None, None).await.0, 401);
+        assert_eq!(server.request("GET", "/sessions", Some("not-a-token"), None).await.0, 401);
+        assert_eq!(server.request("GET", "/sessions", Some(&server.token), None).await.0, 200);
+        assert_eq!(server.request("GET", "/v1/sessions", Some(&server.token), None).await.0, 200);
+
+        let (status, check) = server.request("GET", "/auth/check", Some("not-a-token"), None).await;
+        assert_eq!((status, &check["authenticated"]), (401, &json!(false)));
+        let (status, check) = server.request("GET", "/auth/check", Some(&server.token), None).await;
+        assert_eq!((status, &check["authenticated"]), (200, &json!(true)));
+
+        // A read-only device can look but not change anything
+        let viewer = pair_device("Viewer", DeviceScope::ReadOnly);
+        assert_eq!(server.request("GET", "/sessions", Some(&viewer), None).await.0, 200);
+        let (status, _) = server.request("POST", "/sessions", Some(&viewer), Some(json!({ "name": "Nope" }))).await;
+        assert_eq!(status, 403);
+    });
+}
+
+#[test]
+fn loopback_requests_need_the_local_token() {
+    let server = TestServer::get();
+    run(async {
+        let search = |local: Option<&str>| {
+            let mut request = server.client.get(format!("http://{}/api/search/messages?q=hello", server.addr));
+            if let Some(local) = local {
+                request = request.header("x-agent-hub-local", local);
+            }
+            async move { request.send().await.expect("Request failed").status().as_u16() }
+        };
+        // Tunnelled and proxied requests come from loopback too
+        assert_eq!(search(None).await, 401);
+        assert_eq!(search(Some("not-the-token")).await, 401);
+        assert_eq!(search(Some(crate::LOCAL_TOKEN.as_str())).await, 200);
+        assert_eq!(server.request("GET", "/auth/qr", None, None).await.0, 403);
+    });
+}
+
+#[test]
+fn pairing_needs_the_code_and_desktop_approval() {
+    let server = TestServer::get();
+    run(async {
+        let (status, requested) =
+            server.request("POST", "/auth/request-pairing", None, Some(json!({ "device_name": "Test phone" }))).await;
+        assert_eq!(status, 200);
+        let pairing_id = requested["pairing_id"].as_str().expect("No pairing id").to_string();
+        // Shown on the desktop
+        let code = state::get().pairing_requests.lock()[&pairing_id].code.clone();
+        let wrong = if code == "000000" { "111111" } else { "000000" };
+
+        let attempt = |code: &str| json!({ "pairing_id": pairing_id, "code": code, "device_name": "Test phone" });
+        assert_eq!(server.request("POST", "/auth/pair", None, Some(attempt(wrong))).await.0, 401);
+        let (status, pending) = server.request("POST", "/auth/pair", None, Some(attempt(&code))).await;
+        assert_eq!((status, &pending["status"]), (202, &json!("pending_approval")));
+
+        assert!(crate::approve_pairing(pairing_id.clone(), "everything".to_string()).is_err());
+        crate::approve_pairing(pairing_id.clone(), "interact".to_string()).unwrap();
+        let (status, paired) = server.request("POST", "/auth/pair", None, Some(attempt(&code))).await;
+        assert_eq!(status, 200);
+        let token = paired["token"].as_str().expect("No token");
+        assert_eq!(server.request("GET", "/sessions", Some(token), None).await.0, 200);
+        // With the scope the desktop picked, not admin
+        assert_eq!(crate::device_for_token(token).map(|d| d.scope), Some(DeviceScope::Interact));
+
+        // The request is used up
+        assert_eq!(server.request("POST", "/auth/pair", None, Some(attempt(&code))).await.0, 401);
+    });
+}
+
+#[test]
+fn sessions_can_be_created_renamed_and_deleted() {
+    let server = TestServer::get();
+    let token = Some(server.token.as_str());
+    run(async {
+        let session_id = server.create_session("CRUD test").await;
+        let listed = |sessions: Value| sessions.as_array().unwrap().iter().find(|s| s["id"] == session_id.as_str()).cloned();
+
+        let (_, sessions) = server.request("GET", "/sessions", token, None).await;
+        let session = listed(sessions).expect("New session isn't listed");
+        assert_eq!(session["name"], "CRUD test");
+        assert_eq!(session["running"], false);
+
+        let path = format!("/sessions/{}", session_id);
+        let (status, renamed) = server.request("PATCH", &path, token, Some(json!({ "name": "Renamed" }))).await;
+        assert_eq!((status, &renamed["name"]), (200, &json!("Renamed")));
+        assert_eq!(server.request("PATCH", &path, token, Some(json!({ "name": " " }))).await.0, 400);
+        let (_, sessions) = server.request("GET", "/sessions", token, None).await;
+        assert_eq!(listed(sessions).unwrap()["name"], "Renamed");
+
+        let (status, deleted) = server.request("DELETE", &path, token, None).await;
+        assert_eq!((status, &deleted["status"]), (200, &json!("deleted")));
+        let (_, sessions) = server.request("GET", "/sessions", token, None).await;
+        assert!(listed(sessions).is_none());
+        assert_eq!(server.request("DELETE", &path, token, None).await.0, 404);
+        assert_eq!(server.request("PATCH", &path, token, Some(json!({ "name": "Gone" }))).await.0, 404);
+    });
+}
+
+#[test]
+fn mobile_client_authenticates_subscribes_and_chats() {
+    let server = TestServer::get();
+    run(async {
+        let session_id = server.create_session("Mobile test").await;
+        let mut written = fake_json_session(&session_id);
+        let id = Some(session_id.as_str());
+        let mut ws = server.connect_mobile().await;
+
+        // Nothing but auth until authenticated
+        send(&mut ws, json!({ "type": "subscribe", "sessionId": session_id })).await;
+        assert_eq!(next_of_type(&mut ws, "error", None).await["message"], "Not authenticated");
+        send(&mut ws, json!({ "type": "auth", "token": "not-a-token" })).await;
+        next_of_type(&mut ws, "auth_error", None).await;
+
+        send(&mut ws, json!({ "type": "auth", "token": server.token, "protocolVersion": PROTOCOL_VERSION })).await;
+        let auth = next_of_type(&mut ws, "auth_success", None).await;
+        assert_eq!(auth["protocolVersion"], PROTOCOL_VERSION);
+        assert_eq!(auth["serverProtocolVersion"], PROTOCOL_VERSION);
+        assert_eq!(auth["scope"], "admin");
+        let list = next_of_type(&mut ws, "session_list", None).await;
+        let listed = list["sessions"].as_array().unwrap().iter().find(|s| s["id"] == session_id.as_str());
+        assert_eq!(listed.expect("Session isn't in the list")["running"], true);
+
+        // Older clients get the protocol they asked for
+        let mut old = server.connect_mobile().await;
+        send(&mut old, json!({ "type": "auth", "token": server.token })).await;
+        assert_eq!(next_of_type(&mut old, "auth_success", None).await["protocolVersion"], 1);
+
+        send(&mut ws, json!({ "type": "subscribe", "sessionId": session_id })).await;
+        let status = next_of_type(&mut ws, "session_status", id).await;
+        assert_eq!(status["status"]["running"], true);
+
+        // The agent's output reaches subscribers, numbered
+        emit(&session_id, json!({
+            "type": "assistant",
+            "message": { "role": "assistant", "content": [{ "type": "text", "text": "Hello from the agent" }] }
+        }));
+        let chat = next_of_type(&mut ws, "chat_message", id).await;
+        assert_eq!(chat["message"]["message"]["content"][0]["text"], "Hello from the agent");
+        assert!(chat["seq"].is_u64());
+
+        // And what the client sends reaches the agent
+        let prompt = json!({ "type": "user", "message": { "role": "user", "content": "Hi from the phone" } });
+        send(&mut ws, json!({ "type": "send_message", "sessionId": session_id, "content": prompt })).await;
+        let line = tokio::time::timeout(Duration::from_secs(5), written.recv())
+            .await
+            .expect("Nothing written to the session")
+            .unwrap();
+        assert!(line.ends_with('\n'));
+        assert_eq!(serde_json::from_str::<Value>(&line).unwrap(), prompt);
+
+        state::get().json_processes.lock().remove(&session_id);
+        state::get().json_broadcasters.lock().remove(&session_id);
+    });
+}
+
+#[test]
+fn chat_history_is_saved_by_appending_and_reads_back_whole() {
+    let server = TestServer::get();
+    let token = Some(server.token.as_str());
+    run(async {
+        let session_id = server.create_session("History test").await;
+        let said = |text: &str| {
+            json!({ "type": "assistant", "message": { "role": "assistant", "content": [{ "type": "text", "text": text }] } })
+        };
+
+        crate::append_session_message(&session_id, said("one"));
+        crate::save_session_messages_to_db(&session_id);
+        crate::append_session_message(&session_id, said("two"));
+        crate::append_session_message(&session_id, said("three"));
+        crate::save_session_messages_to_db(&session_id);
+
+        // The second save went on the end of the first
+        let appended: i64 = state::get()
+            .db()
+            .lock()
+            .query_row("SELECT appended_bytes FROM terminal_buffers WHERE session_id = ?1", [&session_id], |r| r.get(0))
+            .unwrap();
+        assert!(appended > 0);
+
+        let (_, body) = server.request("GET", &format!("/sessions/{}/buffer", session_id), token, None).await;
+        let saved: Vec<Value> = serde_json::from_str(body["buffer"].as_str().unwrap()).unwrap();
+        assert_eq!(saved, vec![said("one"), said("two"), said("three")]);
+
+        // As after a restart
+        state::get().session_messages.lock().remove(&session_id);
+        state::get().session_messages_loaded.lock().remove(&session_id);
+        state::get().session_messages_saved.lock().remove(&session_id);
+        crate::ensure_session_messages_loaded(&session_id);
+        assert_eq!(state::get().session_messages.lock()[&session_id].len(), 3);
+    });
+}
@@ -0,0 +1,194 @@
+// HTTPS for the web server.
+//
+// With `tls_enabled` set, the server terminates TLS itself using the
+// certificate at `tls_cert_path` / `tls_key_path`, or a self-signed one
+// generated (via openssl) into <data dir>/tls/ on first use. Clients pin the
+// certificate by its SHA-256 fingerprint, published at GET /api/cert.
+//
+// Plain HTTP is still accepted on the same port from loopback, so the local
+// MCP bridge and the desktop webview keep working; remote plain-HTTP
+// connections get a 400 telling them to use https.
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::{self, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+
+/// First byte of a TLS ClientHello record.
+const TLS_HANDSHAKE: u8 = 0x16;
+const SELF_SIGNED_DAYS: &str = "825";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CertInfo {
+    /// SHA-256 of the leaf certificate (DER), colon-separated hex.
+    pub fingerprint_sha256: String,
+    pub self_signed: bool,
+    pub cert_path: String,
+}
+
+/// Certificate in use; None while serving plain HTTP.
+static ACTIVE: Lazy<Mutex<Option<CertInfo>>> = Lazy::new(|| Mutex::new(None));
+
+pub fn active_cert() -> Option<CertInfo> {
+    ACTIVE.lock().clone()
+}
+
+fn tls_dir() -> PathBuf {
+    crate::platform::data_dir()
+        .join("tls")
+}
+
+/// Generate a self-signed certificate unless one already exists.
+fn ensure_self_signed(dir: &Path) -> Result<(PathBuf, PathBuf), String> {
+    let cert = dir.join("cert.pem");
+    let key = dir.join("key.pem");
+    if cert.exists() && key.exists() {
+        return Ok((cert, key));
+    }
+    std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+
+    let mut san = "subjectAltName=DNS:localhost,IP:127.0.0.1".to_string();
+    if let Some(host) = hostname() {
+        san.push_str(&format!(",DNS:{}", host));
+    }
+    if let Ok(ip) = local_ip_address::local_ip() {
+        san.push_str(&format!(",IP:{}", ip));
+    }
+    let output = std::process::Command::new("openssl")
+        .args(["req", "-x509", "-newkey", "rsa:2048", "-nodes", "-sha256", "-days", SELF_SIGNED_DAYS])
+        .arg("-subj")
+        .arg("/CN=Agent Hub")
+        .arg("-addext")
+        .arg(&san)
+        .arg("-keyout")
+        .arg(&key)
+        .arg("-out")
+        .arg(&cert)
+        .output()
+        .map_err(|e| format!("Failed to run openssl: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("openssl failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let _ = std::fs::set_permissions(&key, std::fs::Permissions::from_mode(0o600));
+    }
+    Ok((cert, key))
+}
+
+fn hostname() -> Option<String> {
+    let mut buf = [0u8; 256];
+    let ok = unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) } == 0;
+    let len = buf.iter().position(|&b| b == 0)?;
+    let host = std::str::from_utf8(&buf[..len]).ok()?;
+    (ok && !host.is_empty()).then(|| host.to_string())
+}
+
+fn fingerprint(der: &[u8]) -> String {
+    Sha256::digest(der).iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(":")
+}
+
+/// Build the acceptor from the configured (or self-signed) certificate and
+/// record it as active.
+pub fn acceptor(cert_path: Option<&str>, key_path: Option<&str>) -> Result<TlsAcceptor, String> {
+    let (cert_file, key_file, self_signed) = match (cert_path, key_path) {
+        (Some(c), Some(k)) => (
+            PathBuf::from(shellexpand::tilde(c).to_string()),
+            PathBuf::from(shellexpand::tilde(k).to_string()),
+            false,
+        ),
+        (None, None) => {
+            let (c, k) = ensure_self_signed(&tls_dir())?;
+            (c, k, true)
+        }
+        _ => return Err("Both a certificate and a key path are required".to_string()),
+    };
+
+    let certs: Vec<CertificateDer<'static>> = CertificateDer::pem_file_iter(&cert_file)
+        .map_err(|e| format!("Failed to read {}: {}", cert_file.display(), e))?
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("Invalid certificate {}: {}", cert_file.display(), e))?;
+    let leaf = certs.first().ok_or_else(|| format!("No certificate in {}", cert_file.display()))?;
+    let info = CertInfo {
+        fingerprint_sha256: fingerprint(leaf),
+        self_signed,
+        cert_path: cert_file.display().to_string(),
+    };
+    let key = PrivateKeyDer::from_pem_file(&key_file)
+        .map_err(|e| format!("Invalid private key {}: {}", key_file.display(), e))?;
+
+    let config = ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| format!("Certificate and key don't match: {}", e))?;
+
+    *ACTIVE.lock() = Some(info);
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Serve `app` over TLS (plus plain HTTP from loopback) until the listener fails.
+pub async fn serve(listener: tokio::net::TcpListener, app: axum::Router, acceptor: TlsAcceptor) {
+    use tower::ServiceExt;
+
+    loop {
+        let (stream, addr) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                tracing::error!("accept failed: {}", e);
+                continue;
+            }
+        };
+        let app = app.clone();
+        let acceptor = acceptor.clone();
+        tokio::spawn(async move {
+            let service = hyper::service::service_fn(move |mut req: hyper::Request<hyper::body::Incoming>| {
+                req.extensions_mut().insert(axum::extract::ConnectInfo::<SocketAddr>(addr));
+                app.clone().oneshot(req)
+            });
+            let http = hyper::server::conn::http1::Builder::new();
+
+            let mut first = [0u8; 1];
+            let is_tls = matches!(stream.peek(&mut first).await, Ok(1) if first[0] == TLS_HANDSHAKE);
+            if is_tls {
+                let Ok(tls) = acceptor.accept(stream).await else { return };
+                let io = hyper_util::rt::TokioIo::new(tls);
+                let _ = http.serve_connection(io, service).with_upgrades().await;
+            } else if addr.ip().is_loopback() {
+                let io = hyper_util::rt::TokioIo::new(stream);
+                let _ = http.serve_connection(io, service).with_upgrades().await;
+            } else {
+                let mut stream = stream;
+                let body = "This server requires HTTPS.\n";
+                let response = format!(
+                    "HTTP/1.1 400 Bad Request\r\ncontent-type: text/plain\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fingerprint_is_colon_separated_sha256() {
+        let fp = fingerprint(b"abc");
+        assert_eq!(fp.len(), 32 * 3 - 1);
+        assert!(fp.starts_with("BA:78:16:BF"));
+    }
+}
@@ -0,0 +1,140 @@
+// Speech-to-text for voice prompts from the mobile client.
+//
+// Transcription is delegated to a user-configured command
+// (`transcribe_command` app setting), typically whisper.cpp:
+//
+//     whisper-cli -m ~/models/ggml-base.en.bin -nt -np -f {file}
+//
+// `{file}` is replaced with the (shell-quoted) path of the uploaded audio,
+// written to a temp file whose extension matches the upload's content type.
+// The command runs through the login shell so PATH matches the user's
+// terminal, and its stdout is the transcript.
+
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(180);
+
+/// Transcribe an audio blob with the configured command.
+pub fn transcribe(audio: &[u8], content_type: Option<&str>) -> Result<String, String> {
+    let template = crate::load_app_settings()
+        .unwrap_or_default()
+        .transcribe_command
+        .filter(|c| !c.trim().is_empty())
+        .ok_or_else(|| "No transcription command configured (Settings > Transcription command)".to_string())?;
+
+    let path = std::env::temp_dir().join(format!(
+        "agent-hub-audio-{}.{}",
+        uuid::Uuid::new_v4(),
+        extension_for(content_type)
+    ));
+    std::fs::write(&path, audio).map_err(|e| format!("Failed to save audio: {}", e))?;
+    let result = run_command(&build_command(&template, &path.to_string_lossy()));
+    let _ = std::fs::remove_file(&path);
+    Ok(clean_transcript(&result?))
+}
+
+/// File extension for an upload's MIME type; transcribers sniff the
+/// container from it. Parameters like `;codecs=opus` are ignored.
+fn extension_for(content_type: Option<&str>) -> &'static str {
+    let mime = content_type
+        .and_then(|c| c.split(';').next())
+        .map(|c| c.trim().to_ascii_lowercase())
+        .unwrap_or_default();
+    match mime.as_str() {
+        "audio/wav" | "audio/x-wav" | "audio/wave" => "wav",
+        "audio/mpeg" | "audio/mp3" => "mp3",
+        "audio/mp4" | "audio/m4a" | "audio/x-m4a" | "audio/aac" => "m4a",
+        "audio/ogg" | "audio/opus" => "ogg",
+        "audio/flac" | "audio/x-flac" => "flac",
+        _ => "webm",
+    }
+}
+
+/// Substitute `{file}` in the template, or append the path if the template
+/// doesn't mention it.
+fn build_command(template: &str, path: &str) -> String {
+    let quoted = format!("'{}'", path.replace('\'', "'\\''"));
+    let template = shellexpand::tilde(template).to_string();
+    if template.contains("{file}") {
+        template.replace("{file}", &quoted)
+    } else {
+        format!("{} {}", template, quoted)
+    }
+}
+
+/// Join the transcriber's lines (whisper prints one per segment) into a
+/// single prompt.
+fn clean_transcript(raw: &str) -> String {
+    raw.lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn run_command(command: &str) -> Result<String, String> {
+    use std::os::unix::process::CommandExt;
+    let shell = crate::platform::user_shell();
+    let child = Command::new(&shell)
+        .args(["-l", "-c", command])
+        .env("PATH", crate::platform::agent_path())
+        // Own process group, so a timeout kills the transcriber and not just
+        // the shell around it
+        .process_group(0)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to run transcription command: {}", e))?;
+    let pid = child.id();
+
+    // Read both pipes while it runs, or a chatty transcriber fills one and
+    // stalls until the timeout
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = tx.send(child.wait_with_output());
+    });
+    let output = match rx.recv_timeout(COMMAND_TIMEOUT) {
+        Ok(output) => output.map_err(|e| e.to_string())?,
+        Err(_) => {
+            unsafe {
+                libc::killpg(pid as i32, libc::SIGKILL);
+            }
+            return Err("Transcription timed out".to_string());
+        }
+    };
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let last = stderr.lines().rev().find(|l| !l.trim().is_empty()).unwrap_or("").trim();
+        return Err(format!("Transcription command failed ({}): {}", output.status, last));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extension_ignores_codec_parameters() {
+        assert_eq!(extension_for(Some("audio/webm;codecs=opus")), "webm");
+        assert_eq!(extension_for(Some("audio/mp4")), "m4a");
+        assert_eq!(extension_for(Some("Audio/WAV")), "wav");
+        assert_eq!(extension_for(None), "webm");
+    }
+
+    #[test]
+    fn file_placeholder_is_quoted() {
+        assert_eq!(
+            build_command("whisper-cli -nt -f {file}", "/tmp/it's.wav"),
+            "whisper-cli -nt -f '/tmp/it'\\''s.wav'"
+        );
+        assert_eq!(build_command("transcribe", "/tmp/a.wav"), "transcribe '/tmp/a.wav'");
+    }
+
+    #[test]
+    fn transcript_lines_are_joined() {
+        assert_eq!(clean_transcript("\n Fix the login bug\n  and add a test \n\n"), "Fix the login bug and add a test");
+    }
+}
@@ -0,0 +1,210 @@
+// Menu bar / system tray status item.
+//
+// Shows how many sessions are running and working (next to the icon on
+// macOS, in the tooltip everywhere), how many need attention (attention.rs),
+// and a menu of the running sessions - clicking one brings the window up on
+// it - with New Session, Show and Quit. refresh() is called wherever session
+// status, processing state or attention changes; calls are coalesced and the
+// rebuild runs off the caller's thread, so it's safe to call while holding
+// the session locks it reads.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tauri::menu::{Menu, MenuItem, PredefinedMenuItem};
+use tauri::tray::TrayIconBuilder;
+use tauri::{AppHandle, Emitter, Manager};
+
+const TRAY_ID: &str = "main";
+const SESSION_ITEM_PREFIX: &str = "session:";
+
+static REFRESH_QUEUED: AtomicBool = AtomicBool::new(false);
+
+/// A session listed in the menu
+struct Entry {
+    id: String,
+    name: String,
+    processing: bool,
+    attention: bool,
+}
+
+/// Everything the tray shows
+#[derive(Default)]
+struct Summary {
+    sessions: Vec<Entry>,
+    running: usize,
+    processing: usize,
+    attention: usize,
+}
+
+impl Summary {
+    fn current() -> Summary {
+        let sessions = crate::load_sessions().unwrap_or_default();
+        let mut summary = Summary { attention: crate::attention::count(), ..Default::default() };
+        for session in sessions {
+            let running = crate::is_session_running(&session.id);
+            let attention = crate::attention::is_flagged(&session.id);
+            if !running && !attention {
+                continue;
+            }
+            let processing = running && crate::is_session_processing(&session.id);
+            summary.running += running as usize;
+            summary.processing += processing as usize;
+            summary.sessions.push(Entry { id: session.id, name: session.name, processing, attention });
+        }
+        summary
+    }
+
+    /// "3 running, 1 working"
+    fn status_line(&self) -> String {
+        match (self.running, self.processing) {
+            (0, _) => "No sessions running".to_string(),
+            (running, 0) => format!("{} running", running),
+            (running, processing) => format!("{} running, {} working", running, processing),
+        }
+    }
+
+    fn attention_line(&self) -> Option<String> {
+        match self.attention {
+            0 => None,
+            1 => Some("1 session needs attention".to_string()),
+            n => Some(format!("{} sessions need attention", n)),
+        }
+    }
+
+    /// Text beside the menu bar icon: the running count, and the attention
+    /// count when there is one. Empty when idle so it's just the icon.
+    fn title(&self) -> String {
+        match (self.running, self.attention) {
+            (0, 0) => String::new(),
+            (running, 0) => running.to_string(),
+            (running, attention) => format!("{} \u{26A0}{}", running, attention),
+        }
+    }
+
+    fn tooltip(&self) -> String {
+        match self.attention_line() {
+            Some(attention) => format!("{}: {}; {}", crate::APP_NAME, self.status_line(), attention.to_lowercase()),
+            None => format!("{}: {}", crate::APP_NAME, self.status_line()),
+        }
+    }
+}
+
+impl Entry {
+    fn label(&self) -> String {
+        let marker = if self.attention {
+            "\u{26A0}"
+        } else if self.processing {
+            "\u{25CF}"
+        } else {
+            "\u{25CB}"
+        };
+        format!("{} {}", marker, self.name)
+    }
+}
+
+fn build_menu(app: &AppHandle, summary: &Summary) -> tauri::Result<Menu<tauri::Wry>> {
+    let menu = Menu::new(app)?;
+    menu.append(&MenuItem::with_id(app, "tray_status", summary.status_line(), false, None::<&str>)?)?;
+    if let Some(line) = summary.attention_line() {
+        menu.append(&MenuItem::with_id(app, "tray_attention", line, false, None::<&str>)?)?;
+    }
+    if !summary.sessions.is_empty() {
+        menu.append(&PredefinedMenuItem::separator(app)?)?;
+        for entry in &summary.sessions {
+            let id = format!("{}{}", SESSION_ITEM_PREFIX, entry.id);
+            menu.append(&MenuItem::with_id(app, id, entry.label(), true, None::<&str>)?)?;
+        }
+    }
+    menu.append(&PredefinedMenuItem::separator(app)?)?;
+    menu.append(&MenuItem::with_id(app, "tray_new_session", "New Session", true, None::<&str>)?)?;
+    menu.append(&MenuItem::with_id(app, "tray_show", format!("Show {}", crate::APP_NAME), true, None::<&str>)?)?;
+    menu.append(&PredefinedMenuItem::separator(app)?)?;
+    menu.append(&MenuItem::with_id(app, "tray_quit", "Quit", true, None::<&str>)?)?;
+    Ok(menu)
+}
+
+/// Add the tray icon; called once from setup
+pub fn create(app: &tauri::App) -> tauri::Result<()> {
+    let handle = app.handle();
+    let summary = Summary::current();
+    let mut builder = TrayIconBuilder::with_id(TRAY_ID)
+        .menu(&build_menu(handle, &summary)?)
+        .tooltip(summary.tooltip())
+        .on_menu_event(|app, event| on_menu_event(app, event.id().as_ref()));
+    if let Some(icon) = app.default_window_icon() {
+        builder = builder.icon(icon.clone());
+    }
+    let tray = builder.build(app)?;
+    let _ = tray.set_title(Some(summary.title()));
+    Ok(())
+}
+
+fn on_menu_event(app: &AppHandle, id: &str) {
+    if let Some(session_id) = id.strip_prefix(SESSION_ITEM_PREFIX) {
+        show_window(app);
+        let _ = app.emit("open-session", session_id);
+        return;
+    }
+    match id {
+        "tray_new_session" => {
+            show_window(app);
+            let _ = app.emit("menu-event", "new_session");
+        }
+        "tray_show" => show_window(app),
+        "tray_quit" => app.exit(0),
+        _ => {}
+    }
+}
+
+fn show_window(app: &AppHandle) {
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.unminimize();
+        let _ = window.show();
+        let _ = window.set_focus();
+    }
+}
+
+/// Rebuild the tray from current state, shortly and off this thread
+pub fn refresh() {
+    if REFRESH_QUEUED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    std::thread::spawn(|| {
+        std::thread::sleep(Duration::from_millis(250));
+        REFRESH_QUEUED.store(false, Ordering::SeqCst);
+        let Some(app) = crate::state::get().app_handle.lock().clone() else { return };
+        let Some(tray) = app.tray_by_id(TRAY_ID) else { return };
+        let summary = Summary::current();
+        match build_menu(&app, &summary) {
+            Ok(menu) => {
+                let _ = tray.set_menu(Some(menu));
+            }
+            Err(e) => tracing::warn!("{}", e),
+        }
+        let _ = tray.set_tooltip(Some(summary.tooltip()));
+        let _ = tray.set_title(Some(summary.title()));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_read_naturally() {
+        let idle = Summary::default();
+        assert_eq!(idle.status_line(), "No sessions running");
+        assert_eq!(idle.attention_line(), None);
+        assert_eq!(idle.title(), "");
+
+        let busy = Summary { running: 3, processing: 1, attention: 2, ..Default::default() };
+        assert_eq!(busy.status_line(), "3 running, 1 working");
+        assert_eq!(busy.attention_line().as_deref(), Some("2 sessions need attention"));
+        assert_eq!(busy.title(), "3 \u{26A0}2");
+        assert!(busy.tooltip().ends_with("3 running, 1 working; 2 sessions need attention"));
+
+        let quiet = Summary { running: 2, ..Default::default() };
+        assert_eq!(quiet.status_line(), "2 running");
+        assert_eq!(quiet.title(), "2");
+    }
+}
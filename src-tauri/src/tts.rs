@@ -0,0 +1,229 @@
+// Text-to-speech through the OS speech engine: `say` on macOS, espeak /
+// spd-say on Linux, System.Speech on Windows.
+//
+// Only one utterance plays at a time; a new one interrupts the previous.
+// Sessions can opt in to having their final `result` text spoken when a
+// turn completes (`tts_sessions` table).
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use rusqlite::{params, Connection};
+use std::io::Write;
+use std::process::{Child, Command, Stdio};
+
+static CURRENT: Lazy<Mutex<Option<Child>>> = Lazy::new(|| Mutex::new(None));
+
+/// Long results are cut off here rather than read out for minutes.
+const MAX_SPOKEN_CHARS: usize = 4_000;
+
+pub fn run_tts_migrations(conn: &Connection) {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS tts_sessions (
+            session_id TEXT PRIMARY KEY
+        )",
+        [],
+    )
+    .expect("Failed to create tts_sessions table");
+}
+
+pub fn delete_tts_for_session(conn: &Connection, session_id: &str) {
+    let _ = conn.execute("DELETE FROM tts_sessions WHERE session_id = ?1", params![session_id]);
+}
+
+// =====================================================================
+//  Speaking
+// =====================================================================
+
+/// Speak `text`, interrupting anything already playing. Returns once the
+/// speech process has started.
+pub fn speak_text(text: &str, voice: Option<&str>) -> Result<(), String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(());
+    }
+    stop_speaking();
+
+    let mut child = speech_command(voice)?
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("Failed to start speech: {}", e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes()).map_err(|e| e.to_string())?;
+    }
+    *CURRENT.lock() = Some(child);
+    Ok(())
+}
+
+pub fn stop_speaking() {
+    if let Some(mut child) = CURRENT.lock().take() {
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+}
+
+/// The platform speech command, reading the text from stdin.
+#[cfg(target_os = "macos")]
+fn speech_command(voice: Option<&str>) -> Result<Command, String> {
+    let mut cmd = Command::new("say");
+    if let Some(v) = voice.filter(|v| !v.is_empty()) {
+        cmd.args(["-v", v]);
+    }
+    cmd.args(["-f", "-"]);
+    Ok(cmd)
+}
+
+#[cfg(target_os = "windows")]
+fn speech_command(voice: Option<&str>) -> Result<Command, String> {
+    let select = voice
+        .filter(|v| !v.is_empty())
+        .map(|v| format!("$s.SelectVoice('{}');", v.replace('\'', "''")))
+        .unwrap_or_default();
+    let mut cmd = Command::new("powershell");
+    cmd.args([
+        "-NoProfile",
+        "-Command",
+        &format!(
+            "Add-Type -AssemblyName System.Speech; $s = New-Object System.Speech.Synthesis.SpeechSynthesizer; {} $s.Speak([Console]::In.ReadToEnd())",
+            select
+        ),
+    ]);
+    Ok(cmd)
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+fn speech_command(voice: Option<&str>) -> Result<Command, String> {
+    for bin in ["espeak-ng", "espeak"] {
+        if which(bin) {
+            let mut cmd = Command::new(bin);
+            if let Some(v) = voice.filter(|v| !v.is_empty()) {
+                cmd.args(["-v", v]);
+            }
+            cmd.arg("--stdin");
+            return Ok(cmd);
+        }
+    }
+    if which("spd-say") {
+        // spd-say has no stdin mode; `-e` pipes stdin through
+        let mut cmd = Command::new("spd-say");
+        if let Some(v) = voice.filter(|v| !v.is_empty()) {
+            cmd.args(["-y", v]);
+        }
+        cmd.args(["-w", "-e"]);
+        return Ok(cmd);
+    }
+    Err("No speech engine found (install espeak-ng or speech-dispatcher)".to_string())
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+fn which(bin: &str) -> bool {
+    Command::new("sh")
+        .args(["-c", &format!("command -v {} >/dev/null 2>&1", bin)])
+        .status()
+        .map(|s| s.success())
+        .unwrap_or(false)
+}
+
+// =====================================================================
+//  Auto-speak results
+// =====================================================================
+
+pub fn auto_speak_enabled(session_id: &str) -> bool {
+    let conn = crate::state::get().db().lock();
+    conn.query_row(
+        "SELECT COUNT(*) FROM tts_sessions WHERE session_id = ?1",
+        params![session_id],
+        |r| r.get::<_, i64>(0),
+    )
+    .map(|n| n > 0)
+    .unwrap_or(false)
+}
+
+pub fn set_auto_speak(session_id: &str, enabled: bool) -> Result<(), String> {
+    let conn = crate::state::get().db().lock();
+    if enabled {
+        conn.execute("INSERT OR IGNORE INTO tts_sessions (session_id) VALUES (?1)", params![session_id])
+    } else {
+        conn.execute("DELETE FROM tts_sessions WHERE session_id = ?1", params![session_id])
+    }
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Called when a turn completes; speaks the result for opted-in sessions.
+pub fn on_turn_complete(session_id: &str, result: Option<&str>, is_error: bool) {
+    let Some(text) = result.filter(|_| !is_error) else { return };
+    if !auto_speak_enabled(session_id) {
+        return;
+    }
+    let voice = crate::load_app_settings().unwrap_or_default().tts_voice;
+    if let Err(e) = speak_text(&speakable(text), voice.as_deref()) {
+        tracing::warn!("{}", e);
+    }
+}
+
+/// Strip the markdown that sounds bad read aloud: code blocks are replaced
+/// by a short marker, emphasis/heading/bullet characters and link targets
+/// are dropped.
+fn speakable(markdown: &str) -> String {
+    let mut out = Vec::new();
+    let mut in_code = false;
+    for line in markdown.lines() {
+        if line.trim_start().starts_with("```") {
+            if !in_code {
+                out.push("Code block.".to_string());
+            }
+            in_code = !in_code;
+            continue;
+        }
+        if in_code {
+            continue;
+        }
+        let line = line.trim_start_matches(|c: char| c == '#' || c == '>' || c.is_whitespace());
+        let line = line.strip_prefix("- ").or_else(|| line.strip_prefix("* ")).unwrap_or(line);
+        let mut text = String::with_capacity(line.len());
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '*' | '_' | '`' => {}
+                // [label](url) -> label
+                ']' if chars.peek() == Some(&'(') => {
+                    for c in chars.by_ref() {
+                        if c == ')' {
+                            break;
+                        }
+                    }
+                }
+                '[' => {}
+                _ => text.push(c),
+            }
+        }
+        if !text.trim().is_empty() {
+            out.push(text.trim().to_string());
+        }
+    }
+    let spoken = out.join("\n");
+    if spoken.chars().count() <= MAX_SPOKEN_CHARS {
+        spoken
+    } else {
+        spoken.chars().take(MAX_SPOKEN_CHARS).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_markdown_syntax() {
+        let md = "## Done\n\n- Fixed **the bug** in `auth.rs`\n- See [the docs](https://example.com)";
+        assert_eq!(speakable(md), "Done\nFixed the bug in auth.rs\nSee the docs");
+    }
+
+    #[test]
+    fn replaces_code_blocks() {
+        let md = "Here:\n```rust\nfn main() {}\n```\nThat's it.";
+        assert_eq!(speakable(md), "Here:\nCode block.\nThat's it.");
+    }
+}
@@ -0,0 +1,233 @@
+// Remote access from outside the LAN.
+//
+// `tunnel_mode` picks how the web server is exposed:
+//   - "tailscale": `tailscale funnel` publishes the port at
+//     https://<machine>.<tailnet>.ts.net (requires Funnel to be enabled for
+//     the tailnet).
+//   - "ssh": a reverse tunnel (`ssh -R`) to a host the user controls. That
+//     host needs `GatewayPorts` enabled (or its own reverse proxy) for the
+//     forwarded port to be reachable; `tunnel_public_url` overrides the
+//     guessed http://<host>:<remote port>.
+//
+// Either way the tunnel connects to the server over loopback, so it works
+// whether or not the server itself speaks HTTPS.
+//
+// The status is active from before the tunnel command runs, so a tunnel
+// that's forwarding but has no URL yet (ssh in its first seconds) is still
+// reported, and stop() tears down whatever start() ran: the ssh process,
+// and `funnel off` whenever a funnel was requested, however that went.
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::process::{Child, Command, Stdio};
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TunnelStatus {
+    /// "tailscale" or "ssh"; None when no tunnel is configured.
+    pub mode: Option<String>,
+    /// Whether a tunnel may be forwarding traffic, URL or not
+    pub active: bool,
+    pub public_url: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Default)]
+struct State {
+    status: TunnelStatus,
+    ssh: Option<Child>,
+    /// `tailscale funnel` has been run since the last `funnel off`
+    funnel: bool,
+}
+
+static STATE: Lazy<Mutex<State>> = Lazy::new(|| Mutex::new(State::default()));
+
+pub struct TunnelConfig<'a> {
+    pub mode: Option<&'a str>,
+    pub ssh_target: Option<&'a str>,
+    pub ssh_remote_port: Option<u16>,
+    pub public_url: Option<&'a str>,
+}
+
+/// Current tunnel state. Notices an SSH tunnel that has died since it started.
+pub fn status() -> TunnelStatus {
+    let mut state = STATE.lock();
+    let exited = state.ssh.as_mut().and_then(|c| c.try_wait().ok().flatten());
+    if let Some(code) = exited {
+        state.ssh = None;
+        state.status.active = false;
+        state.status.public_url = None;
+        state.status.error = Some(format!("ssh tunnel exited ({})", code));
+    }
+    state.status.clone()
+}
+
+/// Bring up the configured tunnel to `local_port`, replacing any previous one.
+/// Blocks while the tunnel command runs; call off the async runtime.
+pub fn start(local_port: u16, config: &TunnelConfig) {
+    stop();
+    let Some(mode) = config.mode.filter(|m| !m.is_empty()) else { return };
+    STATE.lock().status = TunnelStatus { mode: Some(mode.to_string()), active: true, ..Default::default() };
+    let result = match mode {
+        "tailscale" => start_tailscale(local_port),
+        "ssh" => start_ssh(local_port, config),
+        other => Err(format!("Unknown tunnel mode '{}'", other)),
+    };
+    if let Err(e) = &result {
+        tracing::warn!("{}", e);
+    }
+    let mut state = STATE.lock();
+    // stop() ran meanwhile and tore it down
+    if state.status.mode.is_none() {
+        return;
+    }
+    state.status = TunnelStatus {
+        mode: Some(mode.to_string()),
+        // Failures tear down what they started
+        active: result.is_ok(),
+        public_url: result.as_ref().ok().cloned(),
+        error: result.err(),
+    };
+}
+
+pub fn stop() {
+    let mut state = STATE.lock();
+    if let Some(mut child) = state.ssh.take() {
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+    if state.funnel {
+        funnel_off();
+        state.funnel = false;
+    }
+    state.status = TunnelStatus::default();
+}
+
+fn funnel_off() {
+    if let Err(e) = Command::new("tailscale").args(["funnel", "--https=443", "off"]).output() {
+        tracing::warn!("Failed to turn tailscale funnel off: {}", e);
+    }
+}
+
+fn start_tailscale(local_port: u16) -> Result<String, String> {
+    // Before running it: a funnel can be up even if the command then fails
+    STATE.lock().funnel = true;
+    let result = run_funnel(local_port);
+    if result.is_err() {
+        funnel_off();
+        STATE.lock().funnel = false;
+    }
+    result
+}
+
+fn run_funnel(local_port: u16) -> Result<String, String> {
+    let output = Command::new("tailscale")
+        .args(["funnel", "--bg", &local_port.to_string()])
+        .output()
+        .map_err(|e| format!("Failed to run tailscale: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("tailscale funnel failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    let status = Command::new("tailscale")
+        .args(["status", "--json"])
+        .output()
+        .map_err(|e| format!("Failed to run tailscale: {}", e))?;
+    let dns_name = parse_dns_name(&String::from_utf8_lossy(&status.stdout))
+        .ok_or_else(|| "Couldn't determine this machine's Tailscale DNS name".to_string())?;
+    Ok(format!("https://{}", dns_name))
+}
+
+/// This machine's MagicDNS name from `tailscale status --json`.
+fn parse_dns_name(status_json: &str) -> Option<String> {
+    let status: serde_json::Value = serde_json::from_str(status_json).ok()?;
+    let name = status["Self"]["DNSName"].as_str()?.trim_end_matches('.');
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+fn ssh_args(local_port: u16, target: &str, remote_port: u16) -> Vec<String> {
+    vec![
+        "-N".into(),
+        "-T".into(),
+        "-o".into(),
+        "BatchMode=yes".into(),
+        "-o".into(),
+        "ExitOnForwardFailure=yes".into(),
+        "-o".into(),
+        "ServerAliveInterval=30".into(),
+        "-R".into(),
+        format!("0.0.0.0:{}:127.0.0.1:{}", remote_port, local_port),
+        target.to_string(),
+    ]
+}
+
+/// http://<host>:<remote port> for an ssh target like `user@host` or `host:22`.
+fn default_ssh_url(target: &str, remote_port: u16) -> String {
+    let host = target.rsplit('@').next().unwrap_or(target);
+    let host = host.split(':').next().unwrap_or(host);
+    format!("http://{}:{}", host, remote_port)
+}
+
+fn start_ssh(local_port: u16, config: &TunnelConfig) -> Result<String, String> {
+    let target = config
+        .ssh_target
+        .filter(|t| !t.is_empty())
+        .ok_or_else(|| "SSH tunnel needs a target (user@host)".to_string())?;
+    let remote_port = config.ssh_remote_port.unwrap_or(local_port);
+    let mut child = Command::new("ssh")
+        .args(ssh_args(local_port, target, remote_port))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to run ssh: {}", e))?;
+    let stderr = child.stderr.take();
+    // Held in the state from the start, so stop() kills it even while the
+    // forwarding is still being checked
+    STATE.lock().ssh = Some(child);
+
+    // Forwarding failures (auth, port in use) end the process almost at once
+    std::thread::sleep(std::time::Duration::from_secs(3));
+    let exited = {
+        let mut state = STATE.lock();
+        let code = state.ssh.as_mut().and_then(|c| c.try_wait().ok().flatten());
+        if code.is_some() {
+            state.ssh = None;
+        }
+        code
+    };
+    if let Some(code) = exited {
+        let mut message = String::new();
+        if let Some(mut pipe) = stderr {
+            use std::io::Read;
+            let _ = pipe.read_to_string(&mut message);
+        }
+        return Err(format!("ssh tunnel exited ({}): {}", code, message.trim()));
+    }
+
+    Ok(config
+        .public_url
+        .filter(|u| !u.is_empty())
+        .map(|u| u.trim_end_matches('/').to_string())
+        .unwrap_or_else(|| default_ssh_url(target, remote_port)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dns_name_comes_from_self() {
+        let json = r#"{"Self": {"DNSName": "studio.tail1234.ts.net.", "HostName": "studio"}, "Peer": {}}"#;
+        assert_eq!(parse_dns_name(json).as_deref(), Some("studio.tail1234.ts.net"));
+        assert_eq!(parse_dns_name(r#"{"Self": {"DNSName": ""}}"#), None);
+    }
+
+    #[test]
+    fn ssh_forwards_remote_port_to_loopback() {
+        let args = ssh_args(3030, "me@example.com", 8443);
+        assert!(args.windows(2).any(|w| w[0] == "-R" && w[1] == "0.0.0.0:8443:127.0.0.1:3030"));
+        assert_eq!(args.last().map(String::as_str), Some("me@example.com"));
+        assert_eq!(default_ssh_url("me@example.com", 8443), "http://example.com:8443");
+        assert_eq!(default_ssh_url("example.com:2222", 8443), "http://example.com:8443");
+    }
+}
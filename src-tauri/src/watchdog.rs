@@ -0,0 +1,253 @@
+// Hang detection for claude-json sessions.
+//
+// A session is "processing" from the moment a user prompt is written to its
+// stdin until the turn's `result` arrives. If it stays processing with no
+// stdout for longer than its timeout, it is reported once as hung
+// (`session_hung`) and its policy applied:
+//
+//   notify     report only (default)
+//   interrupt  send SIGINT, as the Stop button does
+//   restart    kill the process and resume the conversation in a new one
+//
+// Policies and per-session timeouts live in `watchdog_sessions`; sessions
+// without a row use the `hang_timeout_mins` app setting.
+
+use crate::events::SessionEvent;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tauri::Emitter;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+const POLICIES: &[&str] = &["notify", "interrupt", "restart"];
+
+pub(crate) struct Activity {
+    processing: bool,
+    last_output: Instant,
+    hung: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WatchdogConfig {
+    pub policy: String,
+    /// Minutes without output before the session counts as hung.
+    pub timeout_mins: u32,
+}
+
+pub fn run_watchdog_migrations(conn: &Connection) {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS watchdog_sessions (
+            session_id TEXT PRIMARY KEY,
+            policy TEXT NOT NULL,
+            timeout_mins INTEGER
+        )",
+        [],
+    )
+    .expect("Failed to create watchdog_sessions table");
+}
+
+pub fn delete_watchdog_for_session(conn: &Connection, session_id: &str) {
+    let _ = conn.execute("DELETE FROM watchdog_sessions WHERE session_id = ?1", params![session_id]);
+}
+
+// =====================================================================
+//  Configuration
+// =====================================================================
+
+pub fn get_config(session_id: &str) -> WatchdogConfig {
+    let default_timeout = crate::load_app_settings().unwrap_or_default().hang_timeout_mins;
+    let row: Option<(String, Option<u32>)> = {
+        let conn = crate::state::get().db().lock();
+        conn.query_row(
+            "SELECT policy, timeout_mins FROM watchdog_sessions WHERE session_id = ?1",
+            params![session_id],
+            |r| Ok((r.get(0)?, r.get(1)?)),
+        )
+        .optional()
+        .unwrap_or(None)
+    };
+    match row {
+        Some((policy, timeout)) => WatchdogConfig { policy, timeout_mins: timeout.unwrap_or(default_timeout) },
+        None => WatchdogConfig { policy: "notify".to_string(), timeout_mins: default_timeout },
+    }
+}
+
+/// Set a session's policy; `timeout_mins` None falls back to the app setting.
+pub fn set_config(session_id: &str, policy: &str, timeout_mins: Option<u32>) -> Result<(), String> {
+    if !POLICIES.contains(&policy) {
+        return Err(format!("Unknown policy '{}' (expected one of {})", policy, POLICIES.join(", ")));
+    }
+    if timeout_mins == Some(0) {
+        return Err("timeout_mins must be at least 1".to_string());
+    }
+    let conn = crate::state::get().db().lock();
+    conn.execute(
+        "INSERT OR REPLACE INTO watchdog_sessions (session_id, policy, timeout_mins) VALUES (?1, ?2, ?3)",
+        params![session_id, policy, timeout_mins],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// =====================================================================
+//  Activity tracking
+// =====================================================================
+
+/// A user prompt was written to the session's stdin.
+pub fn on_prompt(session_id: &str) {
+    crate::state::get().watchdog_activity.lock().insert(
+        session_id.to_string(),
+        Activity { processing: true, last_output: Instant::now(), hung: false },
+    );
+}
+
+/// A line arrived on the session's stdout.
+pub fn on_output(session_id: &str, is_result: bool) {
+    let recovered = {
+        let mut all = crate::state::get().watchdog_activity.lock();
+        let activity = all.entry(session_id.to_string()).or_insert(Activity {
+            processing: false,
+            last_output: Instant::now(),
+            hung: false,
+        });
+        activity.last_output = Instant::now();
+        if is_result {
+            activity.processing = false;
+        }
+        std::mem::replace(&mut activity.hung, false)
+    };
+    if recovered {
+        emit(SessionEvent::SessionRecovered { session_id: session_id.to_string() });
+    }
+}
+
+/// The process exited or the session was deleted.
+pub fn forget(session_id: &str) {
+    crate::state::get().watchdog_activity.lock().remove(session_id);
+}
+
+/// Sessions processing for longer than `timeout` without output; marks them
+/// hung so each stall is reported once. Returns (session, silent for).
+fn take_newly_hung(
+    all: &mut HashMap<String, Activity>,
+    now: Instant,
+    timeout_for: impl Fn(&str) -> Duration,
+) -> Vec<(String, Duration)> {
+    let mut hung = Vec::new();
+    for (session_id, activity) in all.iter_mut() {
+        if !activity.processing || activity.hung {
+            continue;
+        }
+        let silent = now.duration_since(activity.last_output);
+        if silent >= timeout_for(session_id) {
+            activity.hung = true;
+            hung.push((session_id.clone(), silent));
+        }
+    }
+    hung
+}
+
+// =====================================================================
+//  Watchdog thread
+// =====================================================================
+
+/// Start the watchdog thread. Call once at startup.
+pub fn start() {
+    std::thread::spawn(|| loop {
+        std::thread::sleep(CHECK_INTERVAL);
+        // Read configs before taking the activity lock (they hit the DB)
+        let processing: Vec<String> = crate::state::get().watchdog_activity
+            .lock()
+            .iter()
+            .filter(|(_, a)| a.processing && !a.hung)
+            .map(|(id, _)| id.clone())
+            .collect();
+        let configs: HashMap<String, WatchdogConfig> =
+            processing.into_iter().map(|id| (id.clone(), get_config(&id))).collect();
+
+        let hung = take_newly_hung(&mut crate::state::get().watchdog_activity.lock(), Instant::now(), |id| {
+            let mins = configs.get(id).map(|c| c.timeout_mins).unwrap_or(u32::MAX);
+            Duration::from_secs(u64::from(mins) * 60)
+        });
+        for (session_id, silent) in hung {
+            let policy = configs.get(&session_id).map(|c| c.policy.clone()).unwrap_or_default();
+            handle_hung(&session_id, silent, &policy);
+        }
+    });
+}
+
+fn handle_hung(session_id: &str, silent: Duration, policy: &str) {
+    tracing::warn!("{} silent for {}s (policy: {})", session_id, silent.as_secs(), policy);
+    emit(SessionEvent::SessionHung {
+        session_id: session_id.to_string(),
+        silent_secs: silent.as_secs(),
+        policy: policy.to_string(),
+    });
+    crate::attention::flag(session_id);
+    crate::push::on_hung(session_id, silent.as_secs());
+    crate::desktop_notify::on_hung(session_id, silent.as_secs());
+
+    match policy {
+        "interrupt" => {
+            let _ = crate::interrupt_json_process(session_id.to_string());
+        }
+        "restart" => {
+            let _ = crate::kill_json_process(session_id.to_string());
+            forget(session_id);
+            // Let the old process exit and clean up before resuming in a new one
+            std::thread::sleep(Duration::from_secs(2));
+            if let Err(e) = crate::ensure_json_session_running(session_id) {
+                tracing::error!("failed to restart {}: {}", session_id, e);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Send a watchdog event to the desktop, the status stream and mobile.
+fn emit(event: SessionEvent) {
+    let payload = event.data();
+    if let Some(app) = crate::state::get().app_handle.lock().as_ref() {
+        let _ = app.emit(&event.name().replace('_', "-"), payload.clone());
+    }
+    let msg = serde_json::json!({
+        "type": event.name(),
+        "sessionId": event.session_id(),
+        "silentSecs": payload.get("silent_secs"),
+        "policy": payload.get("policy"),
+    })
+    .to_string();
+    crate::broadcast_session_event(event);
+    crate::broadcast_to_mobile_clients(&msg);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn activity(processing: bool, silent_secs: u64, now: Instant) -> Activity {
+        Activity { processing, last_output: now - Duration::from_secs(silent_secs), hung: false }
+    }
+
+    #[test]
+    fn only_silent_processing_sessions_are_hung() {
+        let now = Instant::now() + Duration::from_secs(3600);
+        let mut all = HashMap::new();
+        all.insert("stalled".to_string(), activity(true, 700, now));
+        all.insert("busy".to_string(), activity(true, 30, now));
+        all.insert("idle".to_string(), activity(false, 3000, now));
+        let hung = take_newly_hung(&mut all, now, |_| Duration::from_secs(600));
+        assert_eq!(hung.len(), 1);
+        assert_eq!(hung[0].0, "stalled");
+    }
+
+    #[test]
+    fn a_stall_is_reported_once() {
+        let now = Instant::now() + Duration::from_secs(3600);
+        let mut all = HashMap::new();
+        all.insert("s".to_string(), activity(true, 700, now));
+        assert_eq!(take_newly_hung(&mut all, now, |_| Duration::from_secs(600)).len(), 1);
+        assert!(take_newly_hung(&mut all, now, |_| Duration::from_secs(600)).is_empty());
+    }
+}
@@ -0,0 +1,361 @@
+// Outgoing webhooks. Each webhook is a URL, a signing secret and an optional
+// list of the events it wants (all of them when empty):
+//
+//   session_created  session_started  session_stopped  result  error
+//
+// Deliveries are JSON POSTs signed with HMAC-SHA256 of the body under the
+// webhook's secret, sent as `X-Agent-Hub-Signature: sha256=<hex>` alongside
+// `X-Agent-Hub-Event` and `X-Agent-Hub-Delivery`. A delivery that fails (no
+// response or a non-2xx status) is retried with backoff; every attempt is
+// kept in webhook_deliveries, trimmed to the most recent per webhook.
+
+use ring::hmac;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+pub const EVENTS: &[&str] = &["session_created", "session_started", "session_stopped", "result", "error"];
+
+/// Wait before each retry; one attempt more than there are delays
+const RETRY_DELAYS: &[Duration] = &[Duration::from_secs(5), Duration::from_secs(30), Duration::from_secs(120)];
+const HISTORY_PER_WEBHOOK: i64 = 50;
+
+// =====================================================================
+//  Schema
+// =====================================================================
+
+pub fn run_webhook_migrations(conn: &Connection) {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS webhooks (
+            id         TEXT PRIMARY KEY,
+            url        TEXT NOT NULL,
+            secret     TEXT NOT NULL,
+            events     TEXT NOT NULL DEFAULT '[]',
+            created_at TEXT NOT NULL
+        )",
+        [],
+    )
+    .expect("Failed to create webhooks table");
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS webhook_deliveries (
+            id           TEXT NOT NULL,
+            webhook_id   TEXT NOT NULL,
+            event        TEXT NOT NULL,
+            session_id   TEXT,
+            attempt      INTEGER NOT NULL,
+            status       INTEGER,
+            error        TEXT,
+            delivered_at TEXT NOT NULL
+        )",
+        [],
+    )
+    .expect("Failed to create webhook_deliveries table");
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_webhook ON webhook_deliveries(webhook_id, delivered_at)",
+        [],
+    )
+    .expect("Failed to create webhook_deliveries index");
+}
+
+// =====================================================================
+//  CRUD
+// =====================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Webhook {
+    pub id: String,
+    pub url: String,
+    pub secret: String,
+    /// Events delivered; empty for all
+    pub events: Vec<String>,
+    pub created_at: String,
+}
+
+impl Webhook {
+    /// The webhook as shown over the HTTP API, without its secret
+    pub fn public_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "id": self.id,
+            "url": self.url,
+            "events": self.events,
+            "created_at": self.created_at,
+        })
+    }
+}
+
+const COLUMNS: &str = "id, url, secret, events, created_at";
+
+fn row_to_webhook(row: &rusqlite::Row) -> rusqlite::Result<Webhook> {
+    let events: String = row.get(3)?;
+    Ok(Webhook {
+        id: row.get(0)?,
+        url: row.get(1)?,
+        secret: row.get(2)?,
+        events: serde_json::from_str(&events).unwrap_or_default(),
+        created_at: row.get(4)?,
+    })
+}
+
+pub fn list_webhooks() -> Result<Vec<Webhook>, String> {
+    let conn = crate::state::get().db().lock();
+    let mut stmt = conn
+        .prepare(&format!("SELECT {} FROM webhooks ORDER BY created_at ASC", COLUMNS))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], row_to_webhook)
+        .map_err(|e| e.to_string())?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(rows)
+}
+
+/// Create a webhook, or replace the one with `id`. A secret is generated
+/// when none is given.
+pub fn save_webhook(
+    id: Option<String>,
+    url: String,
+    secret: Option<String>,
+    events: Vec<String>,
+) -> Result<Webhook, String> {
+    let url = url.trim().to_string();
+    if !(url.starts_with("https://") || url.starts_with("http://")) {
+        return Err("Webhook URL must start with http:// or https://".to_string());
+    }
+    if let Some(unknown) = events.iter().find(|e| !EVENTS.contains(&e.as_str())) {
+        return Err(format!("Unknown webhook event '{}'", unknown));
+    }
+    let conn = crate::state::get().db().lock();
+    let existing = id.as_ref().and_then(|id| {
+        conn.query_row(&format!("SELECT {} FROM webhooks WHERE id = ?1", COLUMNS), params![id], row_to_webhook)
+            .ok()
+    });
+    let webhook = Webhook {
+        id: id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string()),
+        url,
+        secret: secret
+            .filter(|s| !s.trim().is_empty())
+            .or_else(|| existing.as_ref().map(|w| w.secret.clone()))
+            .unwrap_or_else(crate::generate_token),
+        events,
+        created_at: existing.map(|w| w.created_at).unwrap_or_else(|| chrono::Utc::now().to_rfc3339()),
+    };
+    let events = serde_json::to_string(&webhook.events).map_err(|e| e.to_string())?;
+    conn.execute(
+        &format!("INSERT OR REPLACE INTO webhooks ({}) VALUES (?1, ?2, ?3, ?4, ?5)", COLUMNS),
+        params![webhook.id, webhook.url, webhook.secret, events, webhook.created_at],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(webhook)
+}
+
+pub fn delete_webhook(id: &str) -> Result<(), String> {
+    let conn = crate::state::get().db().lock();
+    conn.execute("DELETE FROM webhooks WHERE id = ?1", params![id])
+        .map_err(|e| e.to_string())?;
+    conn.execute("DELETE FROM webhook_deliveries WHERE webhook_id = ?1", params![id])
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// =====================================================================
+//  Delivery history
+// =====================================================================
+
+/// One attempt at delivering an event
+#[derive(Debug, Clone, Serialize)]
+pub struct Delivery {
+    /// Shared by the attempts at the same delivery
+    pub id: String,
+    pub webhook_id: String,
+    pub event: String,
+    pub session_id: Option<String>,
+    pub attempt: u32,
+    /// HTTP status, when the endpoint answered
+    pub status: Option<u16>,
+    pub error: Option<String>,
+    pub delivered_at: String,
+}
+
+/// Attempts for a webhook, newest first
+pub fn list_deliveries(webhook_id: &str) -> Result<Vec<Delivery>, String> {
+    let conn = crate::state::get().db().lock();
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, webhook_id, event, session_id, attempt, status, error, delivered_at
+             FROM webhook_deliveries WHERE webhook_id = ?1 ORDER BY delivered_at DESC, attempt DESC",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![webhook_id], |row| {
+            Ok(Delivery {
+                id: row.get(0)?,
+                webhook_id: row.get(1)?,
+                event: row.get(2)?,
+                session_id: row.get(3)?,
+                attempt: row.get(4)?,
+                status: row.get(5)?,
+                error: row.get(6)?,
+                delivered_at: row.get(7)?,
+            })
+        })
+        .map_err(|e| e.to_string())?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(rows)
+}
+
+fn record(delivery: &Delivery) {
+    let conn = crate::state::get().db().lock();
+    let saved = conn.execute(
+        "INSERT INTO webhook_deliveries (id, webhook_id, event, session_id, attempt, status, error, delivered_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            delivery.id,
+            delivery.webhook_id,
+            delivery.event,
+            delivery.session_id,
+            delivery.attempt,
+            delivery.status,
+            delivery.error,
+            delivery.delivered_at
+        ],
+    );
+    if let Err(e) = saved {
+        tracing::error!("failed to record delivery: {}", e);
+        return;
+    }
+    let _ = conn.execute(
+        "DELETE FROM webhook_deliveries WHERE webhook_id = ?1 AND rowid NOT IN (
+            SELECT rowid FROM webhook_deliveries WHERE webhook_id = ?1
+            ORDER BY delivered_at DESC LIMIT ?2
+        )",
+        params![delivery.webhook_id, HISTORY_PER_WEBHOOK],
+    );
+}
+
+// =====================================================================
+//  Dispatch
+// =====================================================================
+
+fn wants(webhook: &Webhook, event: &str) -> bool {
+    webhook.events.is_empty() || webhook.events.iter().any(|e| e == event)
+}
+
+/// Hex HMAC-SHA256 of `body` under `secret`
+pub fn signature(secret: &str, body: &[u8]) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    hmac::sign(&key, body).as_ref().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn payload(event: &str, session_id: &str, data: serde_json::Value) -> serde_json::Value {
+    let session_name = crate::load_sessions()
+        .ok()
+        .and_then(|sessions| sessions.into_iter().find(|s| s.id == session_id))
+        .map(|s| s.name);
+    serde_json::json!({
+        "event": event,
+        "session_id": session_id,
+        "session_name": session_name,
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+        "data": data,
+    })
+}
+
+/// Send `event` to every webhook that wants it, in the background
+pub fn dispatch(event: &str, session_id: &str, data: serde_json::Value) {
+    let webhooks: Vec<Webhook> = match list_webhooks() {
+        Ok(all) => all.into_iter().filter(|w| wants(w, event)).collect(),
+        Err(e) => {
+            tracing::error!("failed to load webhooks: {}", e);
+            return;
+        }
+    };
+    if webhooks.is_empty() {
+        return;
+    }
+    let body = payload(event, session_id, data).to_string();
+    let event = event.to_string();
+    let session_id = session_id.to_string();
+    tauri::async_runtime::spawn(async move {
+        let client = reqwest::Client::new();
+        for webhook in webhooks {
+            let (client, body, event, session_id) = (client.clone(), body.clone(), event.clone(), session_id.clone());
+            tauri::async_runtime::spawn(async move {
+                deliver(&client, &webhook, &event, &session_id, &body).await;
+            });
+        }
+    });
+}
+
+async fn deliver(client: &reqwest::Client, webhook: &Webhook, event: &str, session_id: &str, body: &str) {
+    let delivery_id = uuid::Uuid::new_v4().to_string();
+    let signature = format!("sha256={}", signature(&webhook.secret, body.as_bytes()));
+    for attempt in 1..=RETRY_DELAYS.len() as u32 + 1 {
+        if attempt > 1 {
+            tokio::time::sleep(RETRY_DELAYS[attempt as usize - 2]).await;
+        }
+        let outcome = client
+            .post(&webhook.url)
+            .header("Content-Type", "application/json")
+            .header("X-Agent-Hub-Event", event)
+            .header("X-Agent-Hub-Delivery", &delivery_id)
+            .header("X-Agent-Hub-Signature", &signature)
+            .body(body.to_string())
+            .timeout(Duration::from_secs(15))
+            .send()
+            .await;
+        let (status, error) = match outcome {
+            Ok(resp) if resp.status().is_success() => (Some(resp.status().as_u16()), None),
+            Ok(resp) => (Some(resp.status().as_u16()), Some(format!("endpoint returned {}", resp.status()))),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        let failed = error.is_some();
+        record(&Delivery {
+            id: delivery_id.clone(),
+            webhook_id: webhook.id.clone(),
+            event: event.to_string(),
+            session_id: Some(session_id.to_string()),
+            attempt,
+            status,
+            error,
+            delivered_at: chrono::Utc::now().to_rfc3339(),
+        });
+        if !failed {
+            return;
+        }
+    }
+    tracing::error!("giving up on {} for {}", event, webhook.url);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn webhook(events: &[&str]) -> Webhook {
+        Webhook {
+            id: "w1".into(),
+            url: "https://example.com/hook".into(),
+            secret: "shh".into(),
+            events: events.iter().map(|e| e.to_string()).collect(),
+            created_at: String::new(),
+        }
+    }
+
+    #[test]
+    fn an_empty_filter_takes_every_event() {
+        assert!(EVENTS.iter().all(|e| wants(&webhook(&[]), e)));
+        let filtered = webhook(&["result", "error"]);
+        assert!(wants(&filtered, "error"));
+        assert!(!wants(&filtered, "session_started"));
+        assert!(webhook(&[]).public_json().get("secret").is_none());
+    }
+
+    #[test]
+    fn signatures_are_hex_hmac_sha256() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            signature("Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+}
@@ -0,0 +1,448 @@
+// Workspaces: a level above folders, e.g. "Client A" and "Personal".
+//
+// Each workspace has a default working directory for new sessions, env vars
+// its sessions start with (a session's own vars win on conflict), an
+// optional monthly budget, and whether its sessions are shown to paired
+// devices. Sessions and folders carry a workspace_id; NULL means no
+// workspace, and those are listed under every workspace. The active
+// workspace (settings `active_workspace_id`) is what the sidebar shows and
+// where the app and remote clients create new sessions and folders.
+//
+// Spend is the total_cost_usd reported with each claude-json turn, summed per
+// calendar month. Once a workspace has spent its budget, prompts to its
+// sessions are refused until the next month or until the budget is raised.
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Workspace {
+    /// Empty when creating one
+    #[serde(default)]
+    pub id: String,
+    pub name: String,
+    /// Where new sessions in this workspace start (None = the app default)
+    #[serde(default)]
+    pub default_working_dir: Option<String>,
+    /// JSON object of env vars, the same shape as a session's env_vars
+    #[serde(default)]
+    pub env_vars: Option<String>,
+    /// Monthly spend limit in USD (None = unlimited)
+    #[serde(default)]
+    pub budget_usd: Option<f64>,
+    /// Whether paired devices can see this workspace's sessions
+    #[serde(default = "crate::default_true")]
+    pub remote_access: bool,
+    #[serde(default)]
+    pub sort_order: i32,
+    /// Spent this calendar month (read-only)
+    #[serde(default)]
+    pub spent_usd: f64,
+}
+
+// =====================================================================
+//  Schema
+// =====================================================================
+
+pub fn run_workspace_migrations(conn: &Connection) {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS workspaces (
+            id                  TEXT PRIMARY KEY,
+            name                TEXT NOT NULL,
+            default_working_dir TEXT,
+            env_vars            TEXT,
+            budget_usd          REAL,
+            remote_access       INTEGER NOT NULL DEFAULT 1,
+            sort_order          INTEGER NOT NULL DEFAULT 0
+        )",
+        [],
+    )
+    .expect("Failed to create workspaces table");
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS workspace_spend (
+            workspace_id TEXT NOT NULL,
+            month        TEXT NOT NULL,
+            cost_usd     REAL NOT NULL DEFAULT 0,
+            PRIMARY KEY (workspace_id, month)
+        )",
+        [],
+    )
+    .expect("Failed to create workspace_spend table");
+
+    let _ = conn.execute("ALTER TABLE sessions ADD COLUMN workspace_id TEXT", []);
+    let _ = conn.execute("ALTER TABLE folders ADD COLUMN workspace_id TEXT", []);
+}
+
+// =====================================================================
+//  Registry
+// =====================================================================
+
+/// "2026-10": the key spend is summed under
+fn current_month() -> String {
+    chrono::Utc::now().format("%Y-%m").to_string()
+}
+
+fn parse_env_vars(json: Option<&str>) -> HashMap<String, String> {
+    json.and_then(|j| serde_json::from_str(j).ok()).unwrap_or_default()
+}
+
+fn validate(workspace: &Workspace) -> Result<(), String> {
+    if workspace.name.trim().is_empty() {
+        return Err("A workspace needs a name".to_string());
+    }
+    if let Some(json) = workspace.env_vars.as_deref().filter(|j| !j.trim().is_empty()) {
+        serde_json::from_str::<HashMap<String, String>>(json)
+            .map_err(|_| "Workspace env vars must be a JSON object of strings".to_string())?;
+    }
+    if workspace.budget_usd.is_some_and(|b| !b.is_finite() || b < 0.0) {
+        return Err("A budget can't be negative".to_string());
+    }
+    Ok(())
+}
+
+fn list_in(conn: &Connection, month: &str) -> Result<Vec<Workspace>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT w.id, w.name, w.default_working_dir, w.env_vars, w.budget_usd, w.remote_access, w.sort_order,
+                    COALESCE(s.cost_usd, 0)
+             FROM workspaces w
+             LEFT JOIN workspace_spend s ON s.workspace_id = w.id AND s.month = ?1
+             ORDER BY w.sort_order, w.name",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![month], |row| {
+            Ok(Workspace {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                default_working_dir: row.get(2)?,
+                env_vars: row.get(3)?,
+                budget_usd: row.get(4)?,
+                remote_access: row.get(5)?,
+                sort_order: row.get(6)?,
+                spent_usd: row.get(7)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+}
+
+fn save_in(conn: &Connection, workspace: &Workspace) -> Result<(), String> {
+    conn.execute(
+        "INSERT OR REPLACE INTO workspaces (id, name, default_working_dir, env_vars, budget_usd, remote_access, sort_order)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            workspace.id,
+            workspace.name.trim(),
+            workspace.default_working_dir.as_deref().map(str::trim).filter(|d| !d.is_empty()),
+            workspace.env_vars.as_deref().filter(|j| !j.trim().is_empty()),
+            workspace.budget_usd,
+            workspace.remote_access,
+            workspace.sort_order,
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn delete_in(conn: &Connection, id: &str) -> Result<bool, String> {
+    conn.execute("UPDATE sessions SET workspace_id = NULL WHERE workspace_id = ?1", params![id])
+        .map_err(|e| e.to_string())?;
+    conn.execute("UPDATE folders SET workspace_id = NULL WHERE workspace_id = ?1", params![id])
+        .map_err(|e| e.to_string())?;
+    conn.execute("DELETE FROM workspace_spend WHERE workspace_id = ?1", params![id])
+        .map_err(|e| e.to_string())?;
+    conn.execute("DELETE FROM workspaces WHERE id = ?1", params![id])
+        .map(|n| n > 0)
+        .map_err(|e| e.to_string())
+}
+
+/// Every workspace in sidebar order, with this month's spend
+pub fn list() -> Result<Vec<Workspace>, String> {
+    list_in(&crate::state::get().db().lock(), &current_month())
+}
+
+pub fn get(id: &str) -> Option<Workspace> {
+    list().ok()?.into_iter().find(|w| w.id == id)
+}
+
+/// Create (empty id) or update a workspace
+pub fn save(mut workspace: Workspace) -> Result<Workspace, String> {
+    validate(&workspace)?;
+    {
+        let conn = crate::state::get().db().lock();
+        if workspace.id.is_empty() {
+            workspace.id = uuid::Uuid::new_v4().to_string();
+            workspace.sort_order = conn
+                .query_row("SELECT COALESCE(MAX(sort_order) + 1, 0) FROM workspaces", [], |r| r.get(0))
+                .unwrap_or(0);
+        }
+        save_in(&conn, &workspace)?;
+    }
+    get(&workspace.id).ok_or_else(|| "Workspace not found".to_string())
+}
+
+/// Remove a workspace; its sessions and folders are kept, in no workspace
+pub fn delete(id: &str) -> Result<(), String> {
+    if !delete_in(&crate::state::get().db().lock(), id)? {
+        return Err("Workspace not found".to_string());
+    }
+    if active_id().as_deref() == Some(id) {
+        set_active(None)?;
+    }
+    Ok(())
+}
+
+/// The workspace new sessions and folders go into
+pub fn active_id() -> Option<String> {
+    crate::load_app_settings().ok()?.active_workspace_id
+}
+
+pub fn set_active(id: Option<String>) -> Result<(), String> {
+    if let Some(id) = &id {
+        if get(id).is_none() {
+            return Err("Workspace not found".to_string());
+        }
+    }
+    let mut settings = crate::load_app_settings()?;
+    if settings.active_workspace_id == id {
+        return Ok(());
+    }
+    settings.active_workspace_id = id;
+    crate::write_app_settings(&settings)
+}
+
+/// Put a session in a workspace (None = none). It leaves its folder when
+/// that folder belongs to another workspace; folders in none are shared.
+pub fn move_session(session_id: &str, workspace_id: Option<&str>) -> Result<(), String> {
+    let conn = crate::state::get().db().lock();
+    let updated = conn
+        .execute(
+            "UPDATE sessions SET workspace_id = ?1,
+                folder_id = CASE WHEN COALESCE((SELECT workspace_id FROM folders WHERE id = sessions.folder_id), ?1) IS ?1
+                                 THEN folder_id ELSE NULL END
+             WHERE id = ?2",
+            params![workspace_id, session_id],
+        )
+        .map_err(|e| e.to_string())?;
+    if updated == 0 {
+        return Err("Session not found".to_string());
+    }
+    Ok(())
+}
+
+/// Put a folder, and the sessions in it, in a workspace (None = none)
+pub fn move_folder(folder_id: &str, workspace_id: Option<&str>) -> Result<(), String> {
+    let conn = crate::state::get().db().lock();
+    let updated = conn
+        .execute("UPDATE folders SET workspace_id = ?1 WHERE id = ?2", params![workspace_id, folder_id])
+        .map_err(|e| e.to_string())?;
+    if updated == 0 {
+        return Err("Folder not found".to_string());
+    }
+    conn.execute("UPDATE sessions SET workspace_id = ?1 WHERE folder_id = ?2", params![workspace_id, folder_id])
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// The workspace a folder is in, for sessions dropped into it
+pub fn of_folder(folder_id: &str) -> Option<String> {
+    crate::state::get().db()
+        .lock()
+        .query_row("SELECT workspace_id FROM folders WHERE id = ?1", params![folder_id], |r| r.get(0))
+        .optional()
+        .ok()
+        .flatten()
+        .flatten()
+}
+
+fn of_session_in(conn: &Connection, session_id: &str, month: &str) -> Option<Workspace> {
+    let workspace_id: String = conn
+        .query_row("SELECT workspace_id FROM sessions WHERE id = ?1", params![session_id], |r| r.get(0))
+        .ok()?;
+    list_in(conn, month).ok()?.into_iter().find(|w| w.id == workspace_id)
+}
+
+fn of_session(session_id: &str) -> Option<Workspace> {
+    of_session_in(&crate::state::get().db().lock(), session_id, &current_month())
+}
+
+// =====================================================================
+//  Environment, spend and visibility
+// =====================================================================
+
+/// Workspace env vars overlaid with the session's own, as a JSON object
+fn merge_env_vars(workspace: Option<&str>, session: Option<&str>) -> Option<String> {
+    let mut merged = parse_env_vars(workspace);
+    if merged.is_empty() {
+        return session.map(str::to_string);
+    }
+    merged.extend(parse_env_vars(session));
+    serde_json::to_string(&merged).ok()
+}
+
+/// The env vars a session starts with
+pub fn env_vars_for(session_id: &str, env_vars: Option<String>) -> Option<String> {
+    match of_session(session_id) {
+        Some(workspace) => merge_env_vars(workspace.env_vars.as_deref(), env_vars.as_deref()),
+        None => env_vars,
+    }
+}
+
+/// Add a turn's cost to its workspace's spend for this month
+pub fn record_cost(session_id: &str, cost_usd: f64) {
+    if cost_usd <= 0.0 {
+        return;
+    }
+    let conn = crate::state::get().db().lock();
+    let _ = conn.execute(
+        "INSERT INTO workspace_spend (workspace_id, month, cost_usd)
+         SELECT workspace_id, ?2, ?3 FROM sessions WHERE id = ?1 AND workspace_id IS NOT NULL
+         ON CONFLICT(workspace_id, month) DO UPDATE SET cost_usd = cost_usd + excluded.cost_usd",
+        params![session_id, current_month(), cost_usd],
+    );
+}
+
+fn budget_error(workspace: &Workspace) -> Option<String> {
+    let budget = workspace.budget_usd?;
+    (workspace.spent_usd >= budget).then(|| {
+        format!(
+            "Workspace '{}' has used its ${:.2} budget for this month (${:.2} spent)",
+            workspace.name, budget, workspace.spent_usd
+        )
+    })
+}
+
+/// Refuse new prompts to a session whose workspace is over budget
+pub fn check_budget(session_id: &str) -> Result<(), String> {
+    match of_session(session_id).as_ref().and_then(budget_error) {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
+
+/// Sessions kept off paired devices, by their own setting or their workspace's
+pub fn remote_hidden_sessions(conn: &Connection) -> HashSet<String> {
+    conn.prepare(
+        "SELECT s.id FROM sessions s LEFT JOIN workspaces w ON w.id = s.workspace_id
+         WHERE s.remote_access = 0 OR w.remote_access = 0",
+    )
+    .and_then(|mut stmt| stmt.query_map([], |row| row.get(0))?.collect())
+    .unwrap_or_default()
+}
+
+/// Whether a session's workspace is kept off paired devices
+pub fn hides_session(conn: &Connection, session_id: &str) -> bool {
+    conn.query_row(
+        "SELECT w.remote_access = 0 FROM sessions s JOIN workspaces w ON w.id = s.workspace_id WHERE s.id = ?1",
+        params![session_id],
+        |row| row.get(0),
+    )
+    .unwrap_or(false)
+}
+
+/// Workspaces kept off paired devices
+pub fn remote_hidden_workspaces() -> HashSet<String> {
+    crate::state::get().db()
+        .lock()
+        .prepare("SELECT id FROM workspaces WHERE remote_access = 0")
+        .and_then(|mut stmt| stmt.query_map([], |row| row.get(0))?.collect())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE sessions (id TEXT PRIMARY KEY, remote_access INTEGER NOT NULL DEFAULT 1, folder_id TEXT);
+             CREATE TABLE folders (id TEXT PRIMARY KEY);",
+        )
+        .unwrap();
+        run_workspace_migrations(&conn);
+        conn
+    }
+
+    fn workspace(id: &str, name: &str) -> Workspace {
+        Workspace {
+            id: id.to_string(),
+            name: name.to_string(),
+            default_working_dir: None,
+            env_vars: None,
+            budget_usd: None,
+            remote_access: true,
+            sort_order: 0,
+            spent_usd: 0.0,
+        }
+    }
+
+    #[test]
+    fn session_env_vars_win_over_the_workspace() {
+        let merged = merge_env_vars(Some(r#"{"AWS_PROFILE":"client-a","REGION":"eu"}"#), Some(r#"{"REGION":"us"}"#));
+        let merged = parse_env_vars(merged.as_deref());
+        assert_eq!(merged["AWS_PROFILE"], "client-a");
+        assert_eq!(merged["REGION"], "us");
+
+        assert_eq!(merge_env_vars(None, Some(r#"{"A":"1"}"#)).as_deref(), Some(r#"{"A":"1"}"#));
+        assert_eq!(merge_env_vars(None, None), None);
+    }
+
+    #[test]
+    fn budgets_are_checked_against_this_months_spend() {
+        let conn = setup();
+        let mut client = workspace("a", "Client A");
+        client.budget_usd = Some(1.0);
+        save_in(&conn, &client).unwrap();
+        conn.execute("INSERT INTO sessions (id, workspace_id) VALUES ('s1', 'a')", []).unwrap();
+        conn.execute(
+            "INSERT INTO workspace_spend (workspace_id, month, cost_usd) VALUES ('a', '2026-09', 5.0), ('a', '2026-10', 0.4)",
+            [],
+        )
+        .unwrap();
+
+        let listed = of_session_in(&conn, "s1", "2026-10").unwrap();
+        assert_eq!(listed.spent_usd, 0.4);
+        assert_eq!(budget_error(&listed), None);
+        let last_month = of_session_in(&conn, "s1", "2026-09").unwrap();
+        assert!(budget_error(&last_month).unwrap().contains("$1.00 budget"));
+        assert!(of_session_in(&conn, "unknown", "2026-10").is_none());
+    }
+
+    #[test]
+    fn hidden_workspaces_hide_their_sessions() {
+        let conn = setup();
+        let mut private = workspace("p", "Personal");
+        private.remote_access = false;
+        save_in(&conn, &private).unwrap();
+        save_in(&conn, &workspace("w", "Work")).unwrap();
+        conn.execute_batch(
+            "INSERT INTO sessions (id, workspace_id) VALUES ('in-private', 'p'), ('in-work', 'w'), ('loose', NULL);
+             INSERT INTO sessions (id, remote_access) VALUES ('hidden-itself', 0);",
+        )
+        .unwrap();
+
+        let hidden = remote_hidden_sessions(&conn);
+        assert_eq!(hidden, HashSet::from(["in-private".to_string(), "hidden-itself".to_string()]));
+
+        // Deleting the workspace keeps its sessions, back in view
+        assert!(delete_in(&conn, "p").unwrap());
+        assert_eq!(remote_hidden_sessions(&conn), HashSet::from(["hidden-itself".to_string()]));
+        assert!(!delete_in(&conn, "p").unwrap());
+    }
+
+    #[test]
+    fn workspaces_are_validated() {
+        assert!(validate(&workspace("", "Client A")).is_ok());
+        assert!(validate(&workspace("", "  ")).is_err());
+        let mut bad_env = workspace("", "A");
+        bad_env.env_vars = Some("KEY=value".to_string());
+        assert!(validate(&bad_env).is_err());
+        let mut negative = workspace("", "A");
+        negative.budget_usd = Some(-1.0);
+        assert!(validate(&negative).is_err());
+    }
+}
@@ -0,0 +1,143 @@
+// Binary encoding of the busiest mobile WebSocket messages (protocol 9).
+//
+// A client that asks for `encoding: "cbor"` in auth gets chat messages,
+// history, reply previews and terminal output as CBOR (RFC 8949) in binary
+// frames, with terminal output as raw bytes rather than base64. Everything
+// else, and everything the client sends, stays JSON text. WebSocket frames
+// already delimit messages, so there's no length prefix. A frame may still be
+// gzipped on top (protocol 6); gzip's magic bytes can't start a CBOR map.
+
+/// Messages worth encoding; the rest are small and infrequent
+const BULK_TYPES: &[&str] = &["chat_message", "chat_history", "agent_delta", "terminal_output"];
+
+fn head(out: &mut Vec<u8>, major: u8, n: u64) {
+    let major = major << 5;
+    if n < 24 {
+        out.push(major | n as u8);
+    } else if n <= u8::MAX as u64 {
+        out.extend([major | 24, n as u8]);
+    } else if n <= u16::MAX as u64 {
+        out.push(major | 25);
+        out.extend((n as u16).to_be_bytes());
+    } else if n <= u32::MAX as u64 {
+        out.push(major | 26);
+        out.extend((n as u32).to_be_bytes());
+    } else {
+        out.push(major | 27);
+        out.extend(n.to_be_bytes());
+    }
+}
+
+fn encode(value: &serde_json::Value, out: &mut Vec<u8>) {
+    use serde_json::Value;
+    match value {
+        Value::Null => out.push(0xf6),
+        Value::Bool(b) => out.push(if *b { 0xf5 } else { 0xf4 }),
+        Value::Number(n) => {
+            if let Some(u) = n.as_u64() {
+                head(out, 0, u);
+            } else if let Some(i) = n.as_i64() {
+                head(out, 1, (-1 - i) as u64);
+            } else {
+                out.push(0xfb);
+                out.extend(n.as_f64().unwrap_or(f64::NAN).to_be_bytes());
+            }
+        }
+        Value::String(s) => {
+            head(out, 3, s.len() as u64);
+            out.extend(s.as_bytes());
+        }
+        Value::Array(items) => {
+            head(out, 4, items.len() as u64);
+            for item in items {
+                encode(item, out);
+            }
+        }
+        Value::Object(map) => {
+            head(out, 5, map.len() as u64);
+            for (key, item) in map {
+                head(out, 3, key.len() as u64);
+                out.extend(key.as_bytes());
+                encode(item, out);
+            }
+        }
+    }
+}
+
+/// The message as a CBOR frame, if it's one of the bulk types
+pub fn encode_message(text: &str) -> Option<Vec<u8>> {
+    use base64::Engine;
+
+    let mut message: serde_json::Value = serde_json::from_str(text).ok()?;
+    let kind = message["type"].as_str()?;
+    if !BULK_TYPES.contains(&kind) {
+        return None;
+    }
+    let raw = (kind == "terminal_output")
+        .then(|| base64::engine::general_purpose::STANDARD.decode(message["data"].as_str()?).ok())
+        .flatten();
+    let mut out = Vec::with_capacity(text.len());
+    match raw {
+        // Terminal output as a byte string in place of its base64
+        Some(data) => {
+            let map = message.as_object_mut()?;
+            map.remove("data");
+            head(&mut out, 5, map.len() as u64 + 1);
+            for (key, item) in map.iter() {
+                head(&mut out, 3, key.len() as u64);
+                out.extend(key.as_bytes());
+                encode(item, &mut out);
+            }
+            head(&mut out, 3, 4);
+            out.extend(b"data");
+            head(&mut out, 2, data.len() as u64);
+            out.extend(data);
+        }
+        None => encode(&message, &mut out),
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cbor(value: serde_json::Value) -> String {
+        let mut out = Vec::new();
+        encode(&value, &mut out);
+        out.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn values_encode_as_rfc_8949_examples() {
+        use serde_json::json;
+        assert_eq!(cbor(json!(0)), "00");
+        assert_eq!(cbor(json!(23)), "17");
+        assert_eq!(cbor(json!(24)), "1818");
+        assert_eq!(cbor(json!(1000)), "1903e8");
+        assert_eq!(cbor(json!(1000000)), "1a000f4240");
+        assert_eq!(cbor(json!(1000000000000u64)), "1b000000e8d4a51000");
+        assert_eq!(cbor(json!(-1)), "20");
+        assert_eq!(cbor(json!(-1000)), "3903e7");
+        assert_eq!(cbor(json!(1.1)), "fb3ff199999999999a");
+        assert_eq!(cbor(json!(null)), "f6");
+        assert_eq!(cbor(json!(true)), "f5");
+        assert_eq!(cbor(json!("\u{fc}")), "62c3bc");
+        assert_eq!(cbor(json!([1, [2, 3]])), "8201820203");
+        assert_eq!(cbor(json!({ "a": 1, "b": [2, 3] })), "a26161016162820203");
+    }
+
+    #[test]
+    fn only_bulk_messages_are_encoded() {
+        let output = serde_json::json!({ "type": "terminal_output", "sessionId": "s", "data": "aGk=" }).to_string();
+        let frame = encode_message(&output).unwrap();
+        // {"sessionId": "s", "type": "terminal_output", "data": h'6869'}
+        assert_eq!(frame[0], 0xa3);
+        assert!(frame.ends_with(&[0x64, b'd', b'a', b't', b'a', 0x42, b'h', b'i']));
+
+        let chat = serde_json::json!({ "type": "chat_message", "sessionId": "s", "seq": 1, "message": {} }).to_string();
+        assert_eq!(encode_message(&chat).unwrap()[0], 0xa4);
+        assert!(encode_message(r#"{"type":"session_list","sessions":[]}"#).is_none());
+        assert!(encode_message("not json").is_none());
+    }
+}
@@ -183,8 +183,12 @@ interface Session {
   webglAddon?: WebglAddon;
   sortOrder: number;
   outputByteCount?: number; // Track bytes for periodic texture atlas clearing
+  unsavedOutput?: string; // Output since the buffer was last saved, for append_terminal_buffer
   folderId?: string;
   envVars?: string; // JSON string of env var key-value pairs
+  parentId?: string; // Session that spawned this one (agent sub-sessions)
+  remoteAccess?: boolean; // false keeps the session off paired devices
+  workspaceId?: string;
 }
 
 interface SessionData {
@@ -198,6 +202,9 @@ interface SessionData {
   sort_order: number;
   folder_id: string | null;
   env_vars: string | null;
+  parent_id?: string | null;
+  remote_access?: boolean;
+  workspace_id?: string | null;
 }
 
 interface Folder {
@@ -205,6 +212,7 @@ interface Folder {
   name: string;
   sortOrder: number;
   collapsed: boolean;
+  workspaceId?: string;
 }
 
 interface FolderData {
@@ -212,6 +220,28 @@ interface FolderData {
   name: string;
   sort_order: number;
   collapsed: boolean;
+  workspace_id?: string | null;
+}
+
+// Workspace above folders (see list_workspaces / switch_workspace)
+interface Workspace {
+  id: string;
+  name: string;
+  default_working_dir: string | null;
+  env_vars: string | null;
+  budget_usd: number | null;
+  remote_access: boolean;
+  sort_order: number;
+  spent_usd: number;
+}
+
+// One line of the setup checklist (see run_diagnostics)
+interface DiagnosticCheck {
+  id: string;
+  label: string;
+  status: "ok" | "warning" | "error";
+  detail: string;
+  fix?: string;
 }
 
 type SortOption = "custom" | "name" | "date" | "agent";
@@ -363,6 +393,38 @@ interface AppSettings {
   default_model?: string | null;
   claude_config_dir?: string | null;
   claude_search_dirs?: string[];
+  context_warning_pct?: number;
+  auto_summaries_enabled?: boolean;
+  tts_voice?: string | null;
+  transcribe_command?: string | null;
+  redaction_enabled?: boolean;
+  tls_enabled?: boolean;
+  tls_cert_path?: string | null;
+  tls_key_path?: string | null;
+  tunnel_mode?: "tailscale" | "ssh" | null;
+  tunnel_ssh_target?: string | null;
+  tunnel_ssh_remote_port?: number | null;
+  tunnel_public_url?: string | null;
+  agent_commands?: Record<string, string>;
+  // Overrides of the built-in rate limits; only editable in config.json
+  rate_limits?: Record<string, { max: number; window_secs: number }>;
+  web_server_enabled?: boolean;
+  web_bind_address?: string | null;
+  cors_allowed_origins?: string[];
+  ip_allowlist?: string[];
+  slack_webhook_url?: string | null;
+  discord_webhook_url?: string | null;
+  // action -> accelerator; see GLOBAL_SHORTCUT_INPUTS
+  global_shortcuts?: Record<string, string>;
+  // Menu item shortcuts overriding the defaults, action -> accelerator ("" = none)
+  shortcuts?: Record<string, string>;
+  // Active settings profile (see list_profiles / switch_profile)
+  profile?: string;
+  // Workspace the sidebar shows (null = all)
+  active_workspace_id?: string | null;
+  hang_timeout_mins?: number;
+  auto_restart_max_attempts?: number;
+  token_lifetime_days?: number;
 }
 
 // Recently closed session for undo functionality
@@ -379,8 +441,11 @@ interface RecentlyClosedSession {
 // State
 const sessions: Map<string, Session> = new Map();
 const folders: Map<string, Folder> = new Map();
+let workspaces: Workspace[] = [];
 const chatSessions: Map<string, ChatSession> = new Map();
 let activeSessionId: string | null = null;
+// Session to open as soon as remote-session-created adds it
+let pendingOpenSessionId: string | null = null;
 let searchQuery = "";
 let currentSort: SortOption = "custom";
 let draggedSessionId: string | null = null;
@@ -391,7 +456,7 @@ let appSettings: AppSettings = {
   font_size: 13,
   font_family: "Menlo, Monaco, 'Courier New', monospace",
   theme: "system",
-  default_working_dir: "~/dev/pplsi",
+  default_working_dir: "~",
   default_agent_type: "claude",
   notifications_enabled: false,
   bell_notifications_enabled: true,
@@ -401,6 +466,19 @@ let appSettings: AppSettings = {
   show_active_sessions_group: true,
   claude_config_dir: null,
   claude_search_dirs: ["~/.claude"],
+  context_warning_pct: 80,
+  auto_summaries_enabled: true,
+  redaction_enabled: true,
+  tls_enabled: false,
+  tls_cert_path: null,
+  tls_key_path: null,
+  tunnel_mode: null,
+  tunnel_ssh_target: null,
+  tunnel_ssh_remote_port: null,
+  tunnel_public_url: null,
+  hang_timeout_mins: 10,
+  auto_restart_max_attempts: 3,
+  token_lifetime_days: 30,
 };
 let sidebarResizeHandle: HTMLElement;
 let sidebarEl: HTMLElement;
@@ -461,8 +539,102 @@ function applyDefaultModel(command: string): string {
   return command.replace("claude ", `claude --model '${appSettings.default_model}' `);
 }
 
+// Command for a new session: the user's template from settings (where
+// {model} marks the model flag) or the built-in default. Mirrors
+// agent_commands.rs on the backend.
+function agentCommand(agentType: string): string {
+  const template = appSettings.agent_commands?.[agentType]?.trim() || AGENT_COMMANDS[agentType] || "";
+  if (!template.includes("{model}")) return applyDefaultModel(template);
+  if (!appSettings.default_model) return template.replace(" {model}", "").replace("{model}", "");
+  return template.replace("{model}", `--model '${appSettings.default_model}'`);
+}
+
+// Agent command overrides as "agent_type=command" lines for the settings textarea
+function agentCommandsToText(commands: Record<string, string> | undefined): string {
+  return Object.entries(commands || {}).map(([k, v]) => `${k}=${v}`).join("\n");
+}
+
+function textToAgentCommands(text: string): Record<string, string> {
+  const commands: Record<string, string> = {};
+  for (const line of text.split("\n")) {
+    const idx = line.indexOf("=");
+    if (idx <= 0) continue;
+    const key = line.slice(0, idx).trim();
+    const value = line.slice(idx + 1).trim();
+    if (key && value) commands[key] = value;
+  }
+  return commands;
+}
+
 // Default working directory
-const DEFAULT_WORKING_DIR = "~/dev/pplsi";
+const DEFAULT_WORKING_DIR = "~";
+
+// The active workspace's directory for new sessions, else the default
+function defaultWorkingDir(): string {
+  return activeWorkspace()?.default_working_dir || DEFAULT_WORKING_DIR;
+}
+
+// Global shortcut actions (hotkeys.rs) and their settings inputs
+const GLOBAL_SHORTCUT_INPUTS: Record<string, string> = {
+  new_session_here: "settings-hotkey-new-session-here",
+  toggle_window: "settings-hotkey-toggle-window",
+  interrupt_active: "settings-hotkey-interrupt-active",
+};
+
+// A menu action (see get_shortcuts) and its accelerators
+interface MenuShortcut {
+  action: string;
+  label: string;
+  accelerator: string;
+  default: string;
+}
+
+// Menu shortcuts shown in the settings modal, for saveSettings
+let menuShortcuts: MenuShortcut[] = [];
+
+// Whether a menu action still has its built-in shortcut, so the keydown
+// handler duplicating it should run
+function usesDefaultShortcut(action: string): boolean {
+  return appSettings.shortcuts?.[action] === undefined;
+}
+
+async function renderMenuShortcutInputs(): Promise<void> {
+  const container = document.getElementById("settings-menu-shortcuts")!;
+  container.innerHTML = "";
+  try {
+    menuShortcuts = await invoke<MenuShortcut[]>("get_shortcuts");
+  } catch (err) {
+    console.error("Failed to load menu shortcuts:", err);
+    menuShortcuts = [];
+  }
+  for (const shortcut of menuShortcuts) {
+    const group = document.createElement("div");
+    group.className = "form-group";
+    const label = document.createElement("label");
+    label.htmlFor = `settings-shortcut-${shortcut.action}`;
+    label.textContent = shortcut.label;
+    const input = document.createElement("input");
+    input.type = "text";
+    input.id = `settings-shortcut-${shortcut.action}`;
+    input.value = shortcut.accelerator;
+    input.placeholder = shortcut.default || "None";
+    group.append(label, input);
+    container.appendChild(group);
+  }
+}
+
+// Overrides from the settings modal: only fields that differ from the default
+function collectMenuShortcuts(): Record<string, string> {
+  const overrides: Record<string, string> = {};
+  for (const shortcut of menuShortcuts) {
+    const input = document.getElementById(`settings-shortcut-${shortcut.action}`) as HTMLInputElement | null;
+    const value = input?.value.trim() ?? shortcut.accelerator;
+    if (value !== shortcut.default) {
+      overrides[shortcut.action] = value;
+    }
+  }
+  return overrides;
+}
 
 // Read-aloud state (per session)
 interface ReadAloudState {
@@ -992,6 +1164,10 @@ document.addEventListener("DOMContentLoaded", async () => {
     }
   });
 
+  document.getElementById("workspace-select")!.addEventListener("change", (e) => {
+    switchWorkspace((e.target as HTMLSelectElement).value || null);
+  });
+
   sortSelect.addEventListener("change", () => {
     currentSort = sortSelect.value as SortOption;
     // Update session list class for drag handle visibility
@@ -1079,6 +1255,14 @@ document.addEventListener("DOMContentLoaded", async () => {
   document.getElementById("settings-save")!.addEventListener("click", saveSettings);
   document.getElementById("settings-check-update")!.addEventListener("click", checkForUpdates);
   document.getElementById("settings-rebuild-index")!.addEventListener("click", rebuildSearchIndex);
+  document.getElementById("settings-switch-profile")!.addEventListener("click", switchProfile);
+  document.getElementById("settings-workspace")!.addEventListener("change", fillWorkspaceForm);
+  document.getElementById("settings-save-workspace")!.addEventListener("click", saveWorkspaceFromSettings);
+  document.getElementById("settings-delete-workspace")!.addEventListener("click", deleteWorkspaceFromSettings);
+  document.getElementById("settings-show-logs")!.addEventListener("click", showRecentLogs);
+  document.getElementById("settings-run-diagnostics")!.addEventListener("click", () =>
+    renderDiagnostics(document.getElementById("settings-diagnostics")!));
+  document.getElementById("settings-pair-qr")!.addEventListener("click", showPairingQr);
   document.getElementById("settings-import-orphans")!.addEventListener("click", importOrphanJsonls);
   void subscribeToSearchProgress();
   settingsModal.addEventListener("click", (e) => {
@@ -1259,6 +1443,11 @@ document.addEventListener("DOMContentLoaded", async () => {
     const currentData = pendingWrites.get(sessionId) || "";
     pendingWrites.set(sessionId, currentData + data);
 
+    const outputSession = sessions.get(sessionId);
+    if (outputSession?.terminal) {
+      outputSession.unsavedOutput = (outputSession.unsavedOutput || "") + data;
+    }
+
     // Update activity state (for spinner indicator)
     updateActivityState(sessionId);
 
@@ -1484,6 +1673,7 @@ document.addEventListener("DOMContentLoaded", async () => {
   });
 
   await listen<{ session_id: string; exit_code?: number }>("json-process-exit", async (event) => {
+    startingJsonSessions.delete(event.payload.session_id); // Stopped before it started
     const session = sessions.get(event.payload.session_id);
     if (session) {
       session.isRunning = false;
@@ -1563,7 +1753,16 @@ document.addEventListener("DOMContentLoaded", async () => {
     }
   });
 
+  await listen<{ session_id: string }>("session-starting", (event) => {
+    const chatSession = chatSessions.get(event.payload.session_id);
+    if (chatSession) {
+      chatSession.statusEl.textContent = "Starting...";
+      chatSession.statusEl.className = "chat-status";
+    }
+  });
+
   await listen<{ session_id: string; error: string }>("json-process-error", (event) => {
+    startingJsonSessions.delete(event.payload.session_id);
     const chatSession = chatSessions.get(event.payload.session_id);
     if (chatSession) {
       chatSession.statusEl.textContent = `Error: ${event.payload.error}`;
@@ -1576,7 +1775,46 @@ document.addEventListener("DOMContentLoaded", async () => {
     }
   });
 
-  // Listen for remote client disconnect (restore desktop terminal size)
+  // An agent asked the user something through its Agent Hub MCP tools; the
+  // backend raises the notification (see desktop_notify)
+  await listen<AgentAsk>("session-ask", (event) => {
+    showAskCard(event.payload);
+  });
+
+  // Answered here or on another device, or the agent stopped waiting
+  await listen<{ session_id: string; ask_id: string }>("session-ask-settled", (event) => {
+    document.querySelector(`.chat-ask[data-ask-id="${event.payload.ask_id}"]`)?.remove();
+  });
+
+  await listen<{ session_id: string; message: string }>("session-attention", (event) => {
+    addChatMessage(event.payload.session_id, { type: "system", result: event.payload.message });
+  });
+
+  // A backend notification was most likely clicked, or an
+  // agenthub://session/<id> link opened: show its session
+  await listen<string>("open-session", async (event) => {
+    if (sessions.has(event.payload)) {
+      await switchToSession(event.payload);
+    } else {
+      // Just created in the backend (global shortcut); opened once it's added
+      pendingOpenSessionId = event.payload;
+    }
+  });
+
+  // agenthub://new?dir=&agent=&name=
+  await listen<{ agent_type: Session["agentType"] | null; name: string | null; working_dir: string | null }>(
+    "deep-link-new-session",
+    (event) => {
+      // Links can come from anywhere, so they only fill in the dialog; the user creates the session
+      const agentType = event.payload.agent_type || (appSettings.default_agent_type as Session["agentType"]);
+      showNewSessionModal(agentType, { workingDir: event.payload.working_dir || undefined, name: event.payload.name || undefined });
+    },
+  );
+
+  // agenthub://pair
+  await listen("deep-link-pair", () => showPairingQr());
+
+
   await listen<string>("remote-client-disconnected", async (event) => {
     const sessionId = event.payload;
     const session = sessions.get(sessionId);
@@ -1627,6 +1865,55 @@ document.addEventListener("DOMContentLoaded", async () => {
     }
   });
 
+  // Listen for remote session edits (PATCH /api/sessions/:id)
+  await listen<{ session: SessionData }>("remote-session-updated", (event) => {
+    const updated = event.payload.session;
+    const session = sessions.get(updated.id);
+    if (!session) return;
+    session.name = updated.name;
+    session.workingDir = updated.working_dir;
+    session.sortOrder = updated.sort_order;
+    session.folderId = updated.folder_id || undefined;
+    renderSessionList();
+  });
+
+  // Listen for remote session deletion (DELETE /api/sessions/:id). The backend
+  // has already stopped and deleted it; closeSession tears down the UI and
+  // records it under recently closed.
+  await listen<string>("remote-session-deleted", async (event) => {
+    if (sessions.has(event.payload)) {
+      await closeSession(event.payload);
+    }
+  });
+
+  // Settings changed from a remote client
+  await listen("remote-settings-updated", async () => {
+    await loadAppSettings();
+    renderSessionList();
+  });
+
+  // Folders created, renamed, reordered or deleted through the web API
+  await listen<{ folder: FolderData }>("remote-folder-updated", (event) => {
+    const data = event.payload.folder;
+    folders.set(data.id, {
+      id: data.id,
+      name: data.name,
+      sortOrder: data.sort_order,
+      collapsed: data.collapsed,
+    });
+    renderSessionList();
+  });
+
+  await listen<string>("remote-folder-deleted", (event) => {
+    folders.delete(event.payload);
+    for (const session of sessions.values()) {
+      if (session.folderId === event.payload) {
+        session.folderId = undefined;
+      }
+    }
+    renderSessionList();
+  });
+
   // Listen for remote session creation (from mobile web)
   await listen<{ session: { id: string; name: string; agent_type: string; working_dir: string } }>("remote-session-created", async (event) => {
     const { session: remoteSession } = event.payload;
@@ -1650,12 +1937,21 @@ document.addEventListener("DOMContentLoaded", async () => {
           claudeSessionId,
           hasBeenStarted: false,
           sortOrder: newSessionData.sort_order || minSortOrder - 1,
+          folderId: newSessionData.folder_id || undefined,
           envVars: newSessionData.env_vars || undefined,
+          parentId: newSessionData.parent_id || undefined,
+          remoteAccess: newSessionData.remote_access,
+          workspaceId: newSessionData.workspace_id || undefined,
         };
         sessions.set(session.id, session);
       }
     }
     renderSessionList();
+    if (pendingOpenSessionId === remoteSession.id) {
+      pendingOpenSessionId = null;
+      await switchToSession(remoteSession.id);
+      return;
+    }
 
     // Send notification
     if (appSettings.notifications_enabled) {
@@ -1689,6 +1985,13 @@ document.addEventListener("DOMContentLoaded", async () => {
     );
   });
 
+  // A device entered the right code; it gets a token only once approved here
+  await listen<{ pairing_id: string; device_name: string }>("pairing-approval-requested", async (event) => {
+    const { pairing_id, device_name } = event.payload;
+    showPairingApproval(pairing_id, device_name);
+    await showNotification("Approve Device?", `"${device_name}" entered the pairing code and is waiting for approval`);
+  });
+
   // Listen for successful pairing (hide modal)
   await listen("device-paired", () => {
     hidePairingModal();
@@ -1709,16 +2012,11 @@ document.addEventListener("DOMContentLoaded", async () => {
     } catch (e) {
       result = { error: e instanceof Error ? e.message : String(e) };
     }
-    // Send result back via HTTP
+    // Send result back to the waiting request
     try {
-      const port = await invoke<number | null>("get_web_server_port") || 3857;
-      await fetch(`http://localhost:${port}/api/mcp/result`, {
-        method: "POST",
-        headers: { "Content-Type": "application/json" },
-        body: JSON.stringify({
-          request_id,
-          result: typeof result === "string" ? result : JSON.stringify(result),
-        }),
+      await invoke("mcp_callback", {
+        requestId: request_id,
+        result: typeof result === "string" ? result : JSON.stringify(result),
       });
     } catch (e) {
       console.error("Failed to send MCP result:", e);
@@ -1727,6 +2025,8 @@ document.addEventListener("DOMContentLoaded", async () => {
 
   // Pairing modal dismiss button
   document.getElementById("pairing-dismiss")!.addEventListener("click", hidePairingModal);
+  document.getElementById("pairing-approve")!.addEventListener("click", () => decidePairing(true));
+  document.getElementById("pairing-deny")!.addEventListener("click", () => decidePairing(false));
 
   // Handle window resize
   window.addEventListener("resize", () => {
@@ -1748,7 +2048,7 @@ document.addEventListener("DOMContentLoaded", async () => {
   // This must use capture phase (true) and stopImmediatePropagation to prevent the key
   // from reaching xterm.js, which would otherwise send it to Claude Code's PTY
   document.addEventListener("keydown", (e) => {
-    if (e.ctrlKey && e.key === "Tab") {
+    if (e.ctrlKey && e.key === "Tab" && usesDefaultShortcut(e.shiftKey ? "prev_session" : "next_session")) {
       e.preventDefault();
       e.stopPropagation();
       e.stopImmediatePropagation();
@@ -1759,30 +2059,30 @@ document.addEventListener("DOMContentLoaded", async () => {
   // Keyboard shortcuts (bubbling phase)
   document.addEventListener("keydown", (e) => {
     // Cmd+Shift+T or Ctrl+Shift+T to reopen recently closed session
-    if ((e.metaKey || e.ctrlKey) && e.shiftKey && e.key === "t") {
+    if ((e.metaKey || e.ctrlKey) && e.shiftKey && e.key === "t" && usesDefaultShortcut("reopen_session")) {
       e.preventDefault();
       reopenLastClosedSession();
       return;
     }
     // Cmd+T or Ctrl+T for new session
-    if ((e.metaKey || e.ctrlKey) && e.key === "t") {
+    if ((e.metaKey || e.ctrlKey) && e.key === "t" && usesDefaultShortcut("new_session")) {
       e.preventDefault();
       createQuickSession();
     }
     // Cmd+I to edit/rename current session
-    if ((e.metaKey || e.ctrlKey) && e.key === "i") {
+    if ((e.metaKey || e.ctrlKey) && e.key === "i" && usesDefaultShortcut("rename_session")) {
       e.preventDefault();
       if (activeSessionId) {
         showEditSessionModal(activeSessionId);
       }
     }
     // Cmd+, for settings
-    if ((e.metaKey || e.ctrlKey) && e.key === ",") {
+    if ((e.metaKey || e.ctrlKey) && e.key === "," && usesDefaultShortcut("settings")) {
       e.preventDefault();
       showSettingsModal();
     }
     // Cmd+W to close current session (but not when typing in an input)
-    if ((e.metaKey || e.ctrlKey) && e.key === "w") {
+    if ((e.metaKey || e.ctrlKey) && e.key === "w" && usesDefaultShortcut("close_session")) {
       const activeEl = document.activeElement;
       const isTyping = activeEl instanceof HTMLInputElement || activeEl instanceof HTMLTextAreaElement;
       if (!isTyping) {
@@ -1794,7 +2094,7 @@ document.addEventListener("DOMContentLoaded", async () => {
       // Let the chat input handle its own Ctrl+W for word deletion
     }
     // Cmd+B to toggle sidebar
-    if ((e.metaKey || e.ctrlKey) && e.key === "b") {
+    if ((e.metaKey || e.ctrlKey) && e.key === "b" && usesDefaultShortcut("toggle_sidebar")) {
       e.preventDefault();
       toggleSidebar();
     }
@@ -1847,7 +2147,8 @@ document.addEventListener("DOMContentLoaded", async () => {
         }
       }
     }
-    // Cmd+1-9,0 to switch to sessions (like iTerm2)
+    // Cmd+1-9,0 to switch to sessions (like iTerm2); on macOS the Window
+    // menu's session items take Cmd+1-9 first
     if ((e.metaKey || e.ctrlKey) && /^[0-9]$/.test(e.key)) {
       e.preventDefault();
       const sessionArray = getFilteredAndSortedSessions();
@@ -1890,9 +2191,15 @@ document.addEventListener("DOMContentLoaded", async () => {
     saveAllTerminalBuffers();
   });
 
+  // Quitting waits for this (or a few seconds) before stopping the agents
+  listen("app-shutting-down", async () => {
+    await saveAllTerminalBuffers();
+    invoke("shutdown_ready").catch(() => {});
+  });
+
   // Also save periodically (every 30 seconds) to prevent data loss
   setInterval(() => {
-    saveAllTerminalBuffers();
+    saveAllTerminalBuffers(true);
   }, 30000);
 
   // Mouse-based drag and drop for session and folder reordering
@@ -2073,12 +2380,19 @@ document.addEventListener("DOMContentLoaded", async () => {
   // Load saved sessions and folders from database
   await loadSavedSessions();
   await loadFolders();
+  await loadWorkspaces();
   await loadRecentlyClosed();
 
   // Initial render
   renderSessionListImmediate();
   updateView();
 
+  // Onboarding: the empty state lists whatever setup is still missing
+  void renderDiagnostics(document.getElementById("onboarding-checklist")!, true);
+
+  // Sessions are loaded, so links the app was opened with can be handled
+  invoke("deep_links_ready").catch((err) => console.error("Failed to handle deep links:", err));
+
   // Check for updates in the background after a short delay
   setTimeout(async () => {
     try {
@@ -2118,6 +2432,9 @@ async function loadSavedSessions() {
         sortOrder: data.sort_order,
         folderId: data.folder_id || undefined,
         envVars: data.env_vars || undefined,
+        parentId: data.parent_id || undefined,
+        remoteAccess: data.remote_access,
+        workspaceId: data.workspace_id || undefined,
       };
       sessions.set(session.id, session);
     }
@@ -2135,6 +2452,7 @@ async function loadFolders() {
         name: data.name,
         sortOrder: data.sort_order,
         collapsed: data.collapsed,
+        workspaceId: data.workspace_id || undefined,
       };
       folders.set(folder.id, folder);
     }
@@ -2156,6 +2474,8 @@ async function saveSessionToDb(session: Session) {
       sort_order: session.sortOrder,
       folder_id: session.folderId || null,
       env_vars: session.envVars || null,
+      parent_id: session.parentId || null,
+      workspace_id: session.workspaceId || null,
     };
     await invoke("save_session", { session: data });
   } catch (err) {
@@ -2170,6 +2490,7 @@ async function saveFolderToDb(folder: Folder) {
       name: folder.name,
       sort_order: folder.sortOrder,
       collapsed: folder.collapsed,
+      workspace_id: folder.workspaceId || null,
     };
     await invoke("save_folder", { folder: data });
   } catch (err) {
@@ -2246,13 +2567,14 @@ async function createQuickSession() {
     id: crypto.randomUUID(),
     name: `Claude ${sessions.size + 1}`,
     agentType: "claude-json",
-    command: applyDefaultModel(AGENT_COMMANDS["claude-json"]),
-    workingDir: DEFAULT_WORKING_DIR,
+    command: agentCommand("claude-json"),
+    workingDir: defaultWorkingDir(),
     createdAt: new Date(),
     isRunning: false,
     claudeSessionId, // Pre-generated Claude session ID
     hasBeenStarted: false,
     sortOrder: minSortOrder - 1,
+    workspaceId: appSettings.active_workspace_id || undefined,
   };
 
   sessions.set(session.id, session);
@@ -2275,13 +2597,14 @@ async function createQuickSessionWithAgent(agentType: Session["agentType"]) {
     id: crypto.randomUUID(),
     name: `${agentLabel} ${sessions.size + 1}`,
     agentType,
-    command: applyDefaultModel(AGENT_COMMANDS[agentType]),
-    workingDir: DEFAULT_WORKING_DIR,
+    command: agentCommand(agentType),
+    workingDir: defaultWorkingDir(),
     createdAt: new Date(),
     isRunning: false,
     claudeSessionId,
     hasBeenStarted: false,
     sortOrder: minSortOrder - 1,
+    workspaceId: appSettings.active_workspace_id || undefined,
   };
 
   sessions.set(session.id, session);
@@ -2315,13 +2638,14 @@ async function createSessionWithDirectory(agentType: Session["agentType"], name:
     id: crypto.randomUUID(),
     name,
     agentType,
-    command: applyDefaultModel(AGENT_COMMANDS[agentType]),
+    command: agentCommand(agentType),
     workingDir: expandedDir,
     createdAt: new Date(),
     isRunning: false,
     claudeSessionId,
     hasBeenStarted: false,
     sortOrder: minSortOrder - 1,
+    workspaceId: appSettings.active_workspace_id || undefined,
   };
 
   sessions.set(session.id, session);
@@ -2357,12 +2681,15 @@ function showEditSessionModal(sessionId: string) {
   sessionNameInput.select();
 }
 
-function showNewSessionModal(agentType: Session["agentType"] = "claude", options?: { workingDir?: string; focusWorkingDir?: boolean }) {
+function showNewSessionModal(
+  agentType: Session["agentType"] = "claude",
+  options?: { workingDir?: string; name?: string; focusWorkingDir?: boolean },
+) {
   editingSessionId = null;
-  sessionNameInput.value = "";
+  sessionNameInput.value = options?.name || "";
   agentTypeSelect.value = agentType;
   customCommandInput.value = "";
-  workingDirInput.value = options?.workingDir || DEFAULT_WORKING_DIR;
+  workingDirInput.value = options?.workingDir || defaultWorkingDir();
   envVarsInput.value = "";
   customCommandGroup.style.display = agentType === "custom" ? "block" : "none";
 
@@ -2388,15 +2715,15 @@ function hideNewSessionModal() {
 }
 
 async function saveSessionFromModal() {
-  const workingDir = workingDirInput.value.trim() || DEFAULT_WORKING_DIR;
+  const workingDir = workingDirInput.value.trim() || defaultWorkingDir();
   // If no name provided, use the directory name (last component of the path)
   const dirName = workingDir.split("/").filter(Boolean).pop() || "Project";
   const name = sessionNameInput.value.trim() || dirName;
   const agentType = agentTypeSelect.value as Session["agentType"];
-  let command = applyDefaultModel(AGENT_COMMANDS[agentType]);
+  let command = agentCommand(agentType);
 
   if (agentType === "custom") {
-    command = customCommandInput.value.trim() || "/bin/zsh";
+    command = customCommandInput.value.trim() || "$SHELL";
   }
 
   if (editingSessionId) {
@@ -2456,13 +2783,14 @@ async function saveSessionFromModal() {
       name,
       agentType,
       command,
-      workingDir: workingDirInput.value.trim() || DEFAULT_WORKING_DIR,
+      workingDir: workingDirInput.value.trim() || defaultWorkingDir(),
       createdAt: new Date(),
       isRunning: false,
       claudeSessionId,
       hasBeenStarted: false,
       sortOrder: minSortOrder - 1,
       envVars: envVarsToJson(envVarsInput.value),
+      workspaceId: appSettings.active_workspace_id || undefined,
     };
 
     sessions.set(session.id, session);
@@ -2584,6 +2912,7 @@ async function switchToSession(sessionId: string) {
   }
 
   activeSessionId = sessionId;
+  invoke("set_active_session", { sessionId }).catch(() => {});
   const session = sessions.get(sessionId);
   if (!session) return;
 
@@ -3069,8 +3398,10 @@ async function startJsonProcess(session: Session) {
       resumeSession: shouldResume,
       envVars: session.envVars || null,
     });
-    // Note: isRunning will be set by the json-process-started event
+    // Returns once the start is under way: isRunning (and clearing the
+    // starting flag) is left to the json-process-started / -error events
   } catch (err) {
+    startingJsonSessions.delete(session.id);
     chatSession.statusEl.textContent = `Error: ${err}`;
     chatSession.statusEl.className = "chat-status error";
     addChatMessage(session.id, {
@@ -3078,8 +3409,6 @@ async function startJsonProcess(session: Session) {
       subtype: "error",
       result: `Failed to start process: ${err}`,
     });
-  } finally {
-    startingJsonSessions.delete(session.id);
   }
 }
 
@@ -3281,7 +3610,7 @@ function cycleSessions(direction: "next" | "prev"): void {
 // Returns sessions in visual order - when folders are active, unfiled sessions come first,
 // then sessions grouped by folder in folder sort order.
 function getFilteredAndSortedSessions(): Session[] {
-  let filteredSessions = Array.from(sessions.values());
+  let filteredSessions = Array.from(sessions.values()).filter(inActiveWorkspace);
 
   if (searchQuery) {
     filteredSessions = filteredSessions.filter(session => {
@@ -3732,12 +4061,27 @@ function scheduleRenderSessionList() {
 /**
  * Render the session list immediately (use scheduleRenderSessionList for coalescing).
  */
+// Sessions last sent to the Window menu, so unchanged lists aren't resent
+let sessionsMenuKey = "";
+
+// List sessions in the Window menu in sidebar order (Cmd+1..9)
+function syncSessionsMenu(sortedSessions: Session[]) {
+  const entries = sortedSessions.map((s) => ({ id: s.id, name: s.name }));
+  const key = JSON.stringify(entries);
+  if (key === sessionsMenuKey) return;
+  sessionsMenuKey = key;
+  invoke("update_sessions_menu", { sessions: entries }).catch((err) => {
+    console.error("Failed to update sessions menu:", err);
+  });
+}
+
 function renderSessionListImmediate() {
   perfStart("renderSessionList");
   sessionListEl.innerHTML = "";
   activeSessionsEl.innerHTML = "";
 
   const sortedSessions = getFilteredAndSortedSessions();
+  syncSessionsMenu(sortedSessions);
 
   // Show "no results" message if search has no matches
   if (sortedSessions.length === 0 && searchQuery) {
@@ -3751,7 +4095,7 @@ function renderSessionListImmediate() {
   // Render "Active Sessions" docked group above the scrollable list
   if (appSettings.show_active_sessions_group) {
     const runningSessions = Array.from(sessions.values())
-      .filter(s => s.isRunning)
+      .filter(s => s.isRunning && inActiveWorkspace(s))
       .sort((a, b) => a.sortOrder - b.sortOrder);
     if (runningSessions.length > 0) {
       const activeHeaderEl = document.createElement("div");
@@ -3785,7 +4129,9 @@ function renderSessionListImmediate() {
     }
 
     // Render each folder with its sessions
-    const sortedFolders = Array.from(folders.values()).sort((a, b) => a.sortOrder - b.sortOrder);
+    const sortedFolders = Array.from(folders.values())
+      .filter(inActiveWorkspace)
+      .sort((a, b) => a.sortOrder - b.sortOrder);
     for (const folder of sortedFolders) {
       const folderSessions = sortedSessions.filter(s => s.folderId === folder.id);
 
@@ -3890,7 +4236,7 @@ function createSessionItem(session: Session, index: number): HTMLElement {
     <div class="drag-handle" title="Drag to reorder">⋮⋮</div>
     <div class="status ${statusClass}"></div>
     <div class="details">
-      <div class="name">${escapeHtml(session.name)}</div>
+      <div class="name">${escapeHtml(session.name)}${session.remoteAccess !== false ? "" : ` <span class="remote-hidden-badge" title="Hidden from remote devices">🔒</span>`}</div>
       ${agentBadgeHtml}
     </div>
     ${shortcutHtml}
@@ -4127,6 +4473,9 @@ function showSessionContextMenu(x: number, y: number, sessionId: string) {
     moveItem.addEventListener("mouseleave", () => { submenu.style.display = "none"; });
   }
 
+  addWorkspaceSubmenu(menu, sessions.get(sessionId)?.workspaceId, (workspaceId) =>
+    moveSessionToWorkspace(sessionId, workspaceId));
+
   // Edit session (opens full edit modal with env vars, agent type, etc.)
   addMenuItem(menu, "Edit Session", () => showEditSessionModal(sessionId));
 
@@ -4137,12 +4486,84 @@ function showSessionContextMenu(x: number, y: number, sessionId: string) {
     if (nameEl) startRenaming(sessionId, nameEl);
   });
 
+  // Remote visibility
+  const remoteAccess = sessions.get(sessionId)?.remoteAccess !== false;
+  addMenuItem(menu, remoteAccess ? "Hide from Remote Devices" : "Show on Remote Devices", async () => {
+    try {
+      await invoke("set_session_remote_access", { sessionId, enabled: !remoteAccess });
+      const session = sessions.get(sessionId);
+      if (session) session.remoteAccess = !remoteAccess;
+      renderSessionListImmediate();
+    } catch (err) {
+      console.error("Failed to change remote access:", err);
+    }
+  });
+
+  // Slack / Discord results, once a webhook URL is set
+  if (appSettings.slack_webhook_url || appSettings.discord_webhook_url) {
+    let chatNotify = false;
+    const chatItem = addMenuItem(menu, "Post Results to Slack/Discord", async () => {
+      try {
+        await invoke("set_session_chat_notify", { sessionId, enabled: !chatNotify });
+      } catch (err) {
+        console.error("Failed to change chat notifications:", err);
+      }
+    });
+    invoke<boolean>("get_session_chat_notify", { sessionId }).then((enabled) => {
+      chatNotify = enabled;
+      if (enabled) chatItem.textContent = "Stop Posting Results to Slack/Discord";
+    });
+  }
+
+  // Guest share links
+  addMenuItem(menu, "Copy Share Link (View Only)", () => shareSession(sessionId, true));
+  addMenuItem(menu, "Copy Share Link (Can Type)", () => shareSession(sessionId, false));
+  addMenuItem(menu, "Revoke Share Links", () => revokeShareLinks(sessionId));
+
   addMenuDivider(menu);
 
   // Close session
   addMenuItem(menu, "Close Session", () => closeSession(sessionId));
 }
 
+interface ShareLink {
+  id: string;
+  session_id: string;
+  read_only: boolean;
+  expires_at: number;
+  used_at: number | null;
+  url: string;
+}
+
+// Single-use link for someone who isn't paired; valid for an hour
+async function shareSession(sessionId: string, readOnly: boolean) {
+  if (!readOnly && !confirm("Anyone who opens this link can send input to the session. Create it?")) return;
+  try {
+    const link = await invoke<ShareLink>("create_share_link", { sessionId, ttlSecs: 3600, readOnly });
+    await navigator.clipboard.writeText(link.url);
+    alert(`Share link copied. It works once and expires in an hour:\n\n${link.url}`);
+  } catch (err) {
+    alert(`Failed to create share link: ${err}`);
+  }
+}
+
+async function revokeShareLinks(sessionId: string) {
+  try {
+    const links = await invoke<ShareLink[]>("list_share_links", { sessionId });
+    if (links.length === 0) {
+      alert("This session has no active share links.");
+      return;
+    }
+    const inUse = links.filter((l) => l.used_at !== null).length;
+    if (!confirm(`Revoke ${links.length} share link(s)? ${inUse} currently in use will be disconnected.`)) return;
+    for (const link of links) {
+      await invoke("revoke_share_link", { id: link.id });
+    }
+  } catch (err) {
+    alert(`Failed to revoke share links: ${err}`);
+  }
+}
+
 function showFolderContextMenu(x: number, y: number, folderId: string) {
   const menu = createContextMenu(x, y);
 
@@ -4153,6 +4574,9 @@ function showFolderContextMenu(x: number, y: number, folderId: string) {
     if (nameEl) startRenamingFolder(folderId, nameEl);
   });
 
+  addWorkspaceSubmenu(menu, folders.get(folderId)?.workspaceId, (workspaceId) =>
+    moveFolderToWorkspace(folderId, workspaceId));
+
   addMenuDivider(menu);
 
   // Delete folder
@@ -4185,6 +4609,7 @@ async function createFolder(name?: string) {
     name: folderName,
     sortOrder: maxOrder + 1,
     collapsed: false,
+    workspaceId: appSettings.active_workspace_id || undefined,
   };
   folders.set(folderId, folder);
   await saveFolderToDb(folder);
@@ -4198,6 +4623,225 @@ async function createFolder(name?: string) {
   });
 }
 
+// --- Workspaces ---
+
+function activeWorkspace(): Workspace | undefined {
+  return workspaces.find(w => w.id === appSettings.active_workspace_id);
+}
+
+// Sessions and folders in no workspace are listed under every workspace
+function inActiveWorkspace(item: { workspaceId?: string }): boolean {
+  const active = activeWorkspace();
+  return !active || !item.workspaceId || item.workspaceId === active.id;
+}
+
+async function loadWorkspaces() {
+  try {
+    workspaces = await invoke<Workspace[]>("list_workspaces");
+  } catch (err) {
+    console.error("Failed to load workspaces:", err);
+  }
+  renderWorkspaceSelect();
+}
+
+// Sidebar picker; hidden until there's a workspace to pick
+function renderWorkspaceSelect() {
+  const select = document.getElementById("workspace-select") as HTMLSelectElement;
+  select.innerHTML = "";
+  select.hidden = workspaces.length === 0;
+  select.appendChild(new Option("All Workspaces", ""));
+  for (const workspace of workspaces) {
+    select.appendChild(new Option(workspace.name, workspace.id));
+  }
+  select.value = activeWorkspace()?.id || "";
+}
+
+async function switchWorkspace(workspaceId: string | null) {
+  try {
+    await invoke("switch_workspace", { workspaceId });
+  } catch (err) {
+    console.error("Failed to switch workspace:", err);
+    return;
+  }
+  appSettings.active_workspace_id = workspaceId;
+  renderWorkspaceSelect();
+  renderSessionList();
+}
+
+async function moveSessionToWorkspace(sessionId: string, workspaceId: string | null) {
+  const session = sessions.get(sessionId);
+  if (!session) return;
+  try {
+    await invoke("set_session_workspace", { sessionId, workspaceId });
+  } catch (err) {
+    console.error("Failed to move session to workspace:", err);
+    return;
+  }
+  session.workspaceId = workspaceId || undefined;
+  // It leaves a folder that belongs to another workspace
+  const folder = session.folderId ? folders.get(session.folderId) : undefined;
+  if (folder?.workspaceId && folder.workspaceId !== session.workspaceId) {
+    session.folderId = undefined;
+  }
+  renderSessionList();
+}
+
+async function moveFolderToWorkspace(folderId: string, workspaceId: string | null) {
+  const folder = folders.get(folderId);
+  if (!folder) return;
+  try {
+    await invoke("set_folder_workspace", { folderId, workspaceId });
+  } catch (err) {
+    console.error("Failed to move folder to workspace:", err);
+    return;
+  }
+  folder.workspaceId = workspaceId || undefined;
+  for (const session of sessions.values()) {
+    if (session.folderId === folderId) session.workspaceId = folder.workspaceId;
+  }
+  renderSessionList();
+}
+
+// "Move to Workspace" submenu for the session and folder context menus
+function addWorkspaceSubmenu(menu: HTMLElement, current: string | undefined, onPick: (workspaceId: string | null) => void) {
+  if (workspaces.length === 0) return;
+  const moveItem = document.createElement("div");
+  moveItem.className = "context-menu-item context-menu-submenu-trigger";
+  moveItem.textContent = "Move to Workspace ▸";
+  menu.appendChild(moveItem);
+
+  const submenu = document.createElement("div");
+  submenu.className = "context-menu context-menu-submenu";
+  submenu.style.display = "none";
+  moveItem.appendChild(submenu);
+
+  addMenuItem(submenu, current ? "No Workspace" : "✓ No Workspace", () => onPick(null));
+  addMenuDivider(submenu);
+  for (const workspace of workspaces) {
+    const label = workspace.id === current ? `✓ ${workspace.name}` : workspace.name;
+    addMenuItem(submenu, label, () => onPick(workspace.id));
+  }
+
+  moveItem.addEventListener("mouseenter", () => { submenu.style.display = "block"; });
+  moveItem.addEventListener("mouseleave", () => { submenu.style.display = "none"; });
+}
+
+// Settings modal: pick a workspace to edit, or "New Workspace…"
+function renderWorkspaceSettings(selectedId = "") {
+  const select = document.getElementById("settings-workspace") as HTMLSelectElement;
+  select.innerHTML = "";
+  select.appendChild(new Option("New Workspace…", ""));
+  for (const workspace of workspaces) {
+    select.appendChild(new Option(workspace.name, workspace.id));
+  }
+  select.value = workspaces.some(w => w.id === selectedId) ? selectedId : "";
+  fillWorkspaceForm();
+}
+
+function fillWorkspaceForm() {
+  const id = (document.getElementById("settings-workspace") as HTMLSelectElement).value;
+  const workspace = workspaces.find(w => w.id === id);
+  (document.getElementById("settings-workspace-name") as HTMLInputElement).value = workspace?.name || "";
+  (document.getElementById("settings-workspace-dir") as HTMLInputElement).value = workspace?.default_working_dir || "";
+  (document.getElementById("settings-workspace-env") as HTMLTextAreaElement).value = envVarsToText(workspace?.env_vars || undefined);
+  (document.getElementById("settings-workspace-budget") as HTMLInputElement).value =
+    workspace?.budget_usd != null ? String(workspace.budget_usd) : "";
+  (document.getElementById("settings-workspace-remote") as HTMLInputElement).checked = workspace?.remote_access ?? true;
+  (document.getElementById("settings-workspace-spent") as HTMLElement).textContent = workspace
+    ? `$${workspace.spent_usd.toFixed(2)} spent this month. Prompts are refused once the budget is reached.`
+    : "Prompts are refused once the budget is reached.";
+  (document.getElementById("settings-delete-workspace") as HTMLButtonElement).disabled = !workspace;
+}
+
+async function saveWorkspaceFromSettings() {
+  const id = (document.getElementById("settings-workspace") as HTMLSelectElement).value;
+  const existing = workspaces.find(w => w.id === id);
+  const budget = (document.getElementById("settings-workspace-budget") as HTMLInputElement).value.trim();
+  const workspace = {
+    id,
+    name: (document.getElementById("settings-workspace-name") as HTMLInputElement).value.trim(),
+    default_working_dir: (document.getElementById("settings-workspace-dir") as HTMLInputElement).value.trim() || null,
+    env_vars: envVarsToJson((document.getElementById("settings-workspace-env") as HTMLTextAreaElement).value) || null,
+    budget_usd: budget ? Number(budget) : null,
+    remote_access: (document.getElementById("settings-workspace-remote") as HTMLInputElement).checked,
+    sort_order: existing?.sort_order ?? 0,
+  };
+  try {
+    const saved = await invoke<Workspace>("save_workspace", { workspace });
+    await loadWorkspaces();
+    renderWorkspaceSettings(saved.id);
+    renderSessionList();
+  } catch (err) {
+    alert(`${err}`);
+  }
+}
+
+async function deleteWorkspaceFromSettings() {
+  const id = (document.getElementById("settings-workspace") as HTMLSelectElement).value;
+  const workspace = workspaces.find(w => w.id === id);
+  if (!workspace || !confirm(`Delete workspace "${workspace.name}"? Its sessions and folders are kept.`)) return;
+  try {
+    await invoke("delete_workspace", { workspaceId: id });
+  } catch (err) {
+    alert(`${err}`);
+    return;
+  }
+  for (const item of [...sessions.values(), ...folders.values()]) {
+    if (item.workspaceId === id) item.workspaceId = undefined;
+  }
+  if (appSettings.active_workspace_id === id) appSettings.active_workspace_id = null;
+  await loadWorkspaces();
+  renderWorkspaceSettings();
+  renderSessionList();
+}
+
+interface LogLine {
+  timestamp: string;
+  level: string;
+  target: string;
+  message: string;
+}
+
+async function showRecentLogs() {
+  const level = (document.getElementById("settings-log-level") as HTMLSelectElement).value;
+  const output = document.getElementById("settings-logs")!;
+  try {
+    const lines = await invoke<LogLine[]>("get_recent_logs", { level: level || null, limit: 200 });
+    output.textContent = lines.length
+      ? lines.map(l => `${l.timestamp} ${l.level.padStart(5)} ${l.target}: ${l.message}`).join("\n")
+      : "No log lines.";
+  } catch (err) {
+    output.textContent = `${err}`;
+  }
+  output.hidden = false;
+  output.scrollTop = output.scrollHeight;
+}
+
+// Run the setup checks into `list`. With onlyProblems the list stays hidden
+// when everything passed, and otherwise shows just what needs fixing.
+async function renderDiagnostics(list: HTMLElement, onlyProblems = false) {
+  list.innerHTML = onlyProblems ? "" : `<li class="diagnostic-item">Checking…</li>`;
+  let checks: DiagnosticCheck[];
+  try {
+    checks = await invoke<DiagnosticCheck[]>("run_diagnostics");
+  } catch (err) {
+    console.error("Failed to run setup checks:", err);
+    list.innerHTML = onlyProblems ? "" : `<li class="diagnostic-item error">${escapeHtml(String(err))}</li>`;
+    return;
+  }
+  if (onlyProblems) checks = checks.filter(c => c.status !== "ok");
+  list.innerHTML = checks.map(c => `
+    <li class="diagnostic-item ${c.status}">
+      <span class="diagnostic-icon">${c.status === "ok" ? "✓" : c.status === "warning" ? "!" : "✕"}</span>
+      <div>
+        <div class="diagnostic-label">${escapeHtml(c.label)}</div>
+        <div class="diagnostic-detail">${escapeHtml(c.detail)}</div>
+        ${c.fix ? `<code class="diagnostic-fix">${escapeHtml(c.fix)}</code>` : ""}
+      </div>
+    </li>`).join("");
+  list.hidden = checks.length === 0;
+}
+
 function updateView() {
   const hasActiveSession = activeSessionId !== null;
   const activeSession = activeSessionId ? sessions.get(activeSessionId) : null;
@@ -7087,6 +7731,7 @@ async function saveTerminalBuffer(session: Session): Promise<void> {
   }
 
   try {
+    session.unsavedOutput = "";
     // Serialize the entire terminal buffer including scrollback
     const bufferContent = session.serializeAddon.serialize({
       scrollback: session.terminal.options.scrollback || 10000,
@@ -7103,6 +7748,33 @@ async function saveTerminalBuffer(session: Session): Promise<void> {
   }
 }
 
+/**
+ * Append the terminal's output since its last save to the saved buffer,
+ * rather than serializing and saving the whole scrollback again. The backend
+ * says when enough has been appended that it should be saved whole, which
+ * trims it to the scrollback.
+ */
+async function appendTerminalOutput(session: Session): Promise<void> {
+  const chunk = session.unsavedOutput;
+  if (!chunk) {
+    return;
+  }
+  session.unsavedOutput = "";
+
+  try {
+    const rewrite = await invoke<boolean>("append_terminal_buffer", {
+      sessionId: session.id,
+      chunk,
+    });
+    if (rewrite) {
+      await saveTerminalBuffer(session);
+    }
+  } catch (err) {
+    session.unsavedOutput = chunk + (session.unsavedOutput || "");
+    console.error("Failed to append terminal output:", err);
+  }
+}
+
 /**
  * Delete terminal buffer from database when session is deleted.
  */
@@ -7117,14 +7789,16 @@ async function deleteTerminalBuffer(sessionId: string): Promise<void> {
 /**
  * Save all active terminal buffers before app closes.
  * Call this on beforeunload or similar cleanup events.
+ * With `incremental` (the periodic save), terminals append their new output
+ * instead of saving their whole scrollback.
  */
-async function saveAllTerminalBuffers(): Promise<void> {
+async function saveAllTerminalBuffers(incremental = false): Promise<void> {
   const savePromises: Promise<void>[] = [];
 
   // Save xterm terminal buffers
   for (const session of sessions.values()) {
     if (session.terminal && session.serializeAddon) {
-      savePromises.push(saveTerminalBuffer(session));
+      savePromises.push(incremental ? appendTerminalOutput(session) : saveTerminalBuffer(session));
     }
   }
 
@@ -7148,16 +7822,59 @@ async function showSettingsModal(): Promise<void> {
   settingsDefaultWorkingDirInput.value = appSettings.default_working_dir;
   settingsDefaultAgentSelect.value = appSettings.default_agent_type;
   settingsDefaultModelSelect.value = appSettings.default_model || "";
+  (document.getElementById("settings-agent-commands") as HTMLTextAreaElement).value =
+    agentCommandsToText(appSettings.agent_commands);
   settingsNotificationsCheckbox.checked = appSettings.notifications_enabled;
   settingsBellNotificationsCheckbox.checked = appSettings.bell_notifications_enabled ?? true;
   settingsBounceDockCheckbox.checked = appSettings.bounce_dock_on_bell ?? true;
   settingsReadAloudCheckbox.checked = appSettings.read_aloud_enabled ?? false;
   settingsActiveSessionsGroupCheckbox.checked = appSettings.show_active_sessions_group ?? true;
   settingsRendererSelect.value = appSettings.renderer || "webgl";
-  settingsRemotePinInput.value = appSettings.remote_pin || "";
+  // Only a hash is stored; the field sets a new PIN
+  settingsRemotePinInput.value = "";
+  settingsRemotePinInput.placeholder = appSettings.remote_pin ? "PIN set (enter a new one to change)" : "Not set";
+  (document.getElementById("settings-remote-pin-clear") as HTMLInputElement).checked = false;
   (document.getElementById("settings-claude-config-dir") as HTMLInputElement).value = appSettings.claude_config_dir || "";
   (document.getElementById("settings-claude-search-dirs") as HTMLTextAreaElement).value =
     (appSettings.claude_search_dirs || ["~/.claude"]).join("\n");
+  (document.getElementById("settings-context-warning-pct") as HTMLInputElement).value =
+    String(appSettings.context_warning_pct ?? 80);
+  (document.getElementById("settings-hang-timeout-mins") as HTMLInputElement).value =
+    String(appSettings.hang_timeout_mins ?? 10);
+  (document.getElementById("settings-auto-restart-attempts") as HTMLInputElement).value =
+    String(appSettings.auto_restart_max_attempts ?? 3);
+  (document.getElementById("settings-token-lifetime-days") as HTMLInputElement).value =
+    String(appSettings.token_lifetime_days ?? 30);
+  (document.getElementById("settings-auto-summaries") as HTMLInputElement).checked =
+    appSettings.auto_summaries_enabled ?? true;
+  (document.getElementById("settings-redaction-enabled") as HTMLInputElement).checked =
+    appSettings.redaction_enabled ?? true;
+  (document.getElementById("settings-tts-voice") as HTMLInputElement).value = appSettings.tts_voice || "";
+  for (const [action, inputId] of Object.entries(GLOBAL_SHORTCUT_INPUTS)) {
+    (document.getElementById(inputId) as HTMLInputElement).value = appSettings.global_shortcuts?.[action] || "";
+  }
+  await renderMenuShortcutInputs();
+  await renderProfileSelect();
+  renderWorkspaceSettings(appSettings.active_workspace_id || "");
+  (document.getElementById("settings-slack-webhook-url") as HTMLInputElement).value = appSettings.slack_webhook_url || "";
+  (document.getElementById("settings-discord-webhook-url") as HTMLInputElement).value =
+    appSettings.discord_webhook_url || "";
+  (document.getElementById("settings-transcribe-command") as HTMLInputElement).value = appSettings.transcribe_command || "";
+  (document.getElementById("settings-web-server-enabled") as HTMLInputElement).checked =
+    appSettings.web_server_enabled ?? true;
+  (document.getElementById("settings-web-bind-address") as HTMLInputElement).value = appSettings.web_bind_address || "";
+  (document.getElementById("settings-cors-origins") as HTMLTextAreaElement).value =
+    (appSettings.cors_allowed_origins ?? []).join("\n");
+  (document.getElementById("settings-ip-allowlist") as HTMLTextAreaElement).value =
+    (appSettings.ip_allowlist ?? []).join("\n");
+  (document.getElementById("settings-tls-enabled") as HTMLInputElement).checked = appSettings.tls_enabled ?? false;
+  (document.getElementById("settings-tls-cert-path") as HTMLInputElement).value = appSettings.tls_cert_path || "";
+  (document.getElementById("settings-tls-key-path") as HTMLInputElement).value = appSettings.tls_key_path || "";
+  (document.getElementById("settings-tunnel-mode") as HTMLSelectElement).value = appSettings.tunnel_mode || "";
+  (document.getElementById("settings-tunnel-ssh-target") as HTMLInputElement).value = appSettings.tunnel_ssh_target || "";
+  (document.getElementById("settings-tunnel-ssh-remote-port") as HTMLInputElement).value =
+    appSettings.tunnel_ssh_remote_port ? String(appSettings.tunnel_ssh_remote_port) : "";
+  (document.getElementById("settings-tunnel-public-url") as HTMLInputElement).value = appSettings.tunnel_public_url || "";
 
   // Show app version
   try {
@@ -7176,6 +7893,10 @@ async function showSettingsModal(): Promise<void> {
   // Populate search index stats
   await populateSearchIndexStats();
 
+  // Populate paired devices
+  await populatePairedDevices();
+  await populateAuditLog();
+
   // Populate scheduled jobs list
   await loadScheduledJobs();
 
@@ -7222,6 +7943,104 @@ async function populateSearchIndexStats(): Promise<void> {
   }
 }
 
+interface PairedDevice {
+  id: string;
+  name: string;
+  paired_at: string;
+  last_seen: string;
+  expires_at: string | null;
+  scope: "read_only" | "interact" | "admin";
+}
+
+async function populatePairedDevices(): Promise<void> {
+  const listEl = document.getElementById("settings-paired-devices");
+  if (!listEl) return;
+  try {
+    const devices = await invoke<PairedDevice[]>("list_paired_devices");
+    if (devices.length === 0) {
+      listEl.innerHTML = `<div class="form-hint">No devices paired.</div>`;
+      return;
+    }
+    listEl.innerHTML = devices
+      .map(
+        (d) => `
+      <div class="paired-device-row">
+        <div>
+          <div>${escapeHtml(d.name)}</div>
+          <div class="form-hint">Last seen ${new Date(d.last_seen).toLocaleString()}</div>
+        </div>
+        <select data-scope-device-id="${escapeHtml(d.id)}">
+          <option value="read_only"${d.scope === "read_only" ? " selected" : ""}>Watch only</option>
+          <option value="interact"${d.scope === "interact" ? " selected" : ""}>Use sessions</option>
+          <option value="admin"${d.scope === "admin" ? " selected" : ""}>Full access</option>
+        </select>
+        <button type="button" class="secondary-btn" data-device-id="${escapeHtml(d.id)}">Revoke</button>
+      </div>`
+      )
+      .join("");
+    listEl.querySelectorAll<HTMLButtonElement>("button[data-device-id]").forEach((btn) => {
+      btn.addEventListener("click", async () => {
+        try {
+          await invoke("revoke_device", { deviceId: btn.dataset.deviceId });
+        } catch (err) {
+          alert(`Couldn't revoke device: ${err}`);
+        }
+        await populatePairedDevices();
+      });
+    });
+    listEl.querySelectorAll<HTMLSelectElement>("select[data-scope-device-id]").forEach((select) => {
+      select.addEventListener("change", async () => {
+        try {
+          await invoke("set_device_scope", { deviceId: select.dataset.scopeDeviceId, scope: select.value });
+        } catch (err) {
+          alert(`Couldn't change device access: ${err}`);
+          await populatePairedDevices();
+        }
+      });
+    });
+  } catch (err) {
+    listEl.textContent = `Error: ${err}`;
+  }
+}
+
+interface AuditEntry {
+  id: number;
+  ts: string;
+  device_id: string | null;
+  device_name: string | null;
+  action: string;
+  session_id: string | null;
+  source_ip: string | null;
+}
+
+async function populateAuditLog(): Promise<void> {
+  const listEl = document.getElementById("settings-audit-log");
+  if (!listEl) return;
+  try {
+    const entries = await invoke<AuditEntry[]>("get_audit_log", { limit: 50 });
+    if (entries.length === 0) {
+      listEl.innerHTML = `<div class="form-hint">No remote activity yet.</div>`;
+      return;
+    }
+    listEl.innerHTML = entries
+      .map((e) => {
+        const who = e.device_name ?? "This computer";
+        const session = e.session_id ? sessions.get(e.session_id)?.name ?? e.session_id : "";
+        return `
+      <div class="audit-log-row">
+        <span class="audit-log-time">${new Date(e.ts).toLocaleString()}</span>
+        <span>${escapeHtml(who)}</span>
+        <code>${escapeHtml(e.action)}</code>
+        <span>${escapeHtml(session)}</span>
+        <span class="form-hint">${escapeHtml(e.source_ip ?? "")}</span>
+      </div>`;
+      })
+      .join("");
+  } catch (err) {
+    listEl.textContent = `Error: ${err}`;
+  }
+}
+
 function hideSettingsModal(): void {
   settingsModal.classList.remove("visible");
 }
@@ -7239,7 +8058,12 @@ async function populateWebInterfaceUrl(): Promise<void> {
       if (port) break;
       await new Promise((resolve) => setTimeout(resolve, 500));
     }
-    const ips = await invoke<string[]>("get_local_ips");
+    // A loopback-only server can't be reached at the LAN addresses
+    const bindAddress = appSettings.web_bind_address?.trim() || "";
+    const loopbackOnly = ["127.0.0.1", "::1", "localhost"].includes(bindAddress);
+    const ips = loopbackOnly ? [] : await invoke<string[]>("get_local_ips");
+    const cert = await invoke<{ fingerprint_sha256: string; self_signed: boolean } | null>("get_web_server_cert");
+    const scheme = cert ? "https" : "http";
 
     if (!port) {
       urlContainer.innerHTML = `<span class="loading-text">Web server not running</span>`;
@@ -7249,7 +8073,7 @@ async function populateWebInterfaceUrl(): Promise<void> {
     // Build URL list
     let html = "";
     for (const ip of ips) {
-      const url = `http://${ip}:${port}`;
+      const url = `${scheme}://${ip}:${port}`;
       html += `<a href="${url}" target="_blank" data-url="${url}">${url}</a>`;
     }
 
@@ -7259,6 +8083,21 @@ async function populateWebInterfaceUrl(): Promise<void> {
       html = `<a href="${url}" target="_blank" data-url="${url}">${url}</a>`;
     }
 
+    const tunnel = await invoke<{ mode: string | null; active: boolean; public_url: string | null; error: string | null }>(
+      "get_tunnel_status"
+    );
+    if (tunnel.public_url) {
+      html += `<a href="${tunnel.public_url}" target="_blank" data-url="${tunnel.public_url}">${tunnel.public_url}</a>`;
+    } else if (tunnel.error) {
+      html += `<span class="form-hint">Tunnel unavailable: ${escapeHtml(tunnel.error)}</span>`;
+    } else if (tunnel.active) {
+      html += `<span class="form-hint">Tunnel starting…</span>`;
+    }
+
+    if (cert) {
+      html += `<span class="form-hint">Certificate SHA-256${cert.self_signed ? " (self-signed)" : ""}: <code>${cert.fingerprint_sha256}</code></span>`;
+    }
+
     urlContainer.innerHTML = html;
 
     // Add click handlers to open in external browser
@@ -7286,12 +8125,13 @@ async function saveSettings(): Promise<void> {
     default_working_dir: settingsDefaultWorkingDirInput.value || DEFAULT_WORKING_DIR,
     default_agent_type: settingsDefaultAgentSelect.value || "claude",
     default_model: settingsDefaultModelSelect.value || null,
+    agent_commands: textToAgentCommands((document.getElementById("settings-agent-commands") as HTMLTextAreaElement).value),
     notifications_enabled: settingsNotificationsCheckbox.checked,
     bell_notifications_enabled: settingsBellNotificationsCheckbox.checked,
     bounce_dock_on_bell: settingsBounceDockCheckbox.checked,
     read_aloud_enabled: settingsReadAloudCheckbox.checked,
     renderer: settingsRendererSelect.value as "webgl" | "dom",
-    remote_pin: settingsRemotePinInput.value || null,
+    remote_pin: appSettings.remote_pin ?? null,
     show_active_sessions_group: settingsActiveSessionsGroupCheckbox.checked,
     claude_config_dir: (document.getElementById("settings-claude-config-dir") as HTMLInputElement).value || null,
     claude_search_dirs: (() => {
@@ -7299,12 +8139,78 @@ async function saveSettings(): Promise<void> {
       const lines = raw.split("\n").map((s) => s.trim()).filter((s) => s.length > 0);
       return lines.length > 0 ? lines : ["~/.claude"];
     })(),
+    context_warning_pct:
+      parseInt((document.getElementById("settings-context-warning-pct") as HTMLInputElement).value) || 80,
+    hang_timeout_mins:
+      parseInt((document.getElementById("settings-hang-timeout-mins") as HTMLInputElement).value) || 10,
+    auto_restart_max_attempts:
+      parseInt((document.getElementById("settings-auto-restart-attempts") as HTMLInputElement).value) || 0,
+    token_lifetime_days:
+      parseInt((document.getElementById("settings-token-lifetime-days") as HTMLInputElement).value) || 0,
+    auto_summaries_enabled: (document.getElementById("settings-auto-summaries") as HTMLInputElement).checked,
+    redaction_enabled: (document.getElementById("settings-redaction-enabled") as HTMLInputElement).checked,
+    tts_voice: (document.getElementById("settings-tts-voice") as HTMLInputElement).value.trim() || null,
+    slack_webhook_url: (document.getElementById("settings-slack-webhook-url") as HTMLInputElement).value.trim() || null,
+    discord_webhook_url:
+      (document.getElementById("settings-discord-webhook-url") as HTMLInputElement).value.trim() || null,
+    global_shortcuts: Object.fromEntries(
+      Object.entries(GLOBAL_SHORTCUT_INPUTS)
+        .map(([action, inputId]) => [action, (document.getElementById(inputId) as HTMLInputElement).value.trim()])
+        .filter(([, accelerator]) => accelerator),
+    ),
+    shortcuts: menuShortcuts.length > 0 ? collectMenuShortcuts() : appSettings.shortcuts,
+    profile: appSettings.profile,
+    active_workspace_id: appSettings.active_workspace_id,
+    transcribe_command: (document.getElementById("settings-transcribe-command") as HTMLInputElement).value.trim() || null,
+    tls_enabled: (document.getElementById("settings-tls-enabled") as HTMLInputElement).checked,
+    tls_cert_path: (document.getElementById("settings-tls-cert-path") as HTMLInputElement).value.trim() || null,
+    tls_key_path: (document.getElementById("settings-tls-key-path") as HTMLInputElement).value.trim() || null,
+    tunnel_mode:
+      ((document.getElementById("settings-tunnel-mode") as HTMLSelectElement).value as "tailscale" | "ssh") || null,
+    tunnel_ssh_target: (document.getElementById("settings-tunnel-ssh-target") as HTMLInputElement).value.trim() || null,
+    tunnel_ssh_remote_port:
+      parseInt((document.getElementById("settings-tunnel-ssh-remote-port") as HTMLInputElement).value) || null,
+    tunnel_public_url: (document.getElementById("settings-tunnel-public-url") as HTMLInputElement).value.trim() || null,
+    rate_limits: appSettings.rate_limits,
+    web_server_enabled: (document.getElementById("settings-web-server-enabled") as HTMLInputElement).checked,
+    web_bind_address: (document.getElementById("settings-web-bind-address") as HTMLInputElement).value.trim() || null,
+    cors_allowed_origins: (document.getElementById("settings-cors-origins") as HTMLTextAreaElement).value
+      .split("\n")
+      .map((origin) => origin.trim())
+      .filter(Boolean),
+    ip_allowlist: (document.getElementById("settings-ip-allowlist") as HTMLTextAreaElement).value
+      .split("\n")
+      .map((entry) => entry.trim())
+      .filter(Boolean),
   };
 
+  const newPin = settingsRemotePinInput.value.trim();
+  const clearPin = (document.getElementById("settings-remote-pin-clear") as HTMLInputElement).checked;
+  if (newPin || clearPin) {
+    try {
+      await invoke("set_remote_pin", { pin: clearPin ? null : newPin });
+    } catch (err) {
+      // Weak PIN: leave the modal open so it can be corrected
+      alert(`${err}`);
+      return;
+    }
+  }
+
   try {
     await invoke("save_app_settings", { settings: appSettings });
+    appSettings = await invoke<AppSettings>("load_app_settings");
   } catch (err) {
     console.error("Failed to save app settings:", err);
+    // Invalid listen address or origin: leave the modal open so it can be corrected
+    alert(`${err}`);
+    appSettings = await invoke<AppSettings>("load_app_settings");
+    return;
+  }
+
+  try {
+    await invoke("register_hotkeys");
+  } catch (err) {
+    alert(`${err}`);
   }
 
   // Apply theme immediately
@@ -7338,6 +8244,43 @@ async function saveSettings(): Promise<void> {
 }
 
 // Update checking
+async function renderProfileSelect(): Promise<void> {
+  const select = document.getElementById("settings-profile") as HTMLSelectElement;
+  select.innerHTML = "";
+  (document.getElementById("settings-new-profile") as HTMLInputElement).value = "";
+  try {
+    const { active, profiles } = await invoke<{ active: string; profiles: string[] }>("list_profiles");
+    for (const name of profiles) {
+      const option = document.createElement("option");
+      option.value = name;
+      option.textContent = name;
+      option.selected = name === active;
+      select.appendChild(option);
+    }
+  } catch (err) {
+    console.error("Failed to list profiles:", err);
+  }
+}
+
+// Switch to the typed new profile, or the one picked in the list; the
+// backend applies it live and the modal is refilled from the new settings
+async function switchProfile(): Promise<void> {
+  const name =
+    (document.getElementById("settings-new-profile") as HTMLInputElement).value.trim() ||
+    (document.getElementById("settings-profile") as HTMLSelectElement).value;
+  if (!name || name === appSettings.profile) return;
+  try {
+    await invoke("switch_profile", { name });
+  } catch (err) {
+    alert(`${err}`);
+    return;
+  }
+  await loadAppSettings();
+  renderWorkspaceSelect();
+  renderSessionList();
+  await showSettingsModal();
+}
+
 async function rebuildSearchIndex(): Promise<void> {
   const status = document.getElementById("search-index-status");
   const btn = document.getElementById("settings-rebuild-index") as HTMLButtonElement;
@@ -7795,9 +8738,11 @@ async function resumeClaudeSession(claudeSessionId: string, project: string): Pr
 
 let pairingExpiryInterval: number | null = null;
 
-function showPairingModal(code: string, deviceName?: string): void {
+function showPairingModal(code: string, deviceName?: string, qrSvg?: string): void {
   const modal = document.getElementById("pairing-modal")!;
   const codeValue = document.getElementById("pairing-code-value")!;
+  const qr = document.getElementById("pairing-qr")!;
+  const instructions = modal.querySelector(".pairing-instructions") as HTMLElement;
   const deviceNameEl = modal.querySelector(".pairing-device-name") as HTMLElement;
   const expiresTime = document.getElementById("pairing-expires-time")!;
 
@@ -7812,6 +8757,15 @@ function showPairingModal(code: string, deviceName?: string): void {
     deviceNameEl.style.display = "none";
   }
 
+  // QR pairing: the SVG is generated by the backend, not user input
+  qr.innerHTML = qrSvg ?? "";
+  qr.hidden = !qrSvg;
+  document.getElementById("pairing-approval")!.hidden = true;
+  pendingApprovalId = null;
+  instructions.textContent = qrSvg
+    ? "Scan with your phone's camera, or enter this code on the device"
+    : "Enter this code on your mobile device to pair";
+
   // Start countdown (5 minutes)
   let secondsLeft = 300;
   if (pairingExpiryInterval) {
@@ -7833,9 +8787,109 @@ function showPairingModal(code: string, deviceName?: string): void {
   modal.classList.add("visible");
 }
 
+// A question or approval request from an agent (see the backend's asks module)
+interface AgentAsk {
+  id: string;
+  session_id: string;
+  kind: "question" | "approval";
+  prompt: string;
+  options: string[];
+}
+
+// Card above the chat input with the ask and ways to answer it; removed on
+// session-ask-settled
+function showAskCard(ask: AgentAsk): void {
+  const chatSession = chatSessions.get(ask.session_id);
+  if (!chatSession) return;
+  const card = document.createElement("div");
+  card.className = "chat-ask";
+  card.dataset.askId = ask.id;
+  const prompt = document.createElement("div");
+  prompt.className = "chat-ask-prompt";
+  prompt.textContent = ask.prompt;
+  const actions = document.createElement("div");
+  actions.className = "chat-ask-actions";
+  card.append(prompt, actions);
+
+  const answer = async (text: string | null, approved: boolean | null) => {
+    try {
+      await invoke("answer_question", { askId: ask.id, text, approved });
+    } catch (err) {
+      alert(`${err}`);
+    }
+  };
+  const button = (label: string, onClick: () => void) => {
+    const btn = document.createElement("button");
+    btn.textContent = label;
+    btn.addEventListener("click", onClick);
+    actions.appendChild(btn);
+  };
+
+  if (ask.kind === "approval") {
+    button("Approve", () => answer(null, true));
+    button("Deny", () => answer(null, false));
+  } else {
+    for (const option of ask.options) {
+      button(option, () => answer(option, null));
+    }
+    const input = document.createElement("input");
+    input.placeholder = "Answer...";
+    input.addEventListener("keydown", (e) => {
+      if (e.key === "Enter" && input.value.trim()) answer(input.value.trim(), null);
+    });
+    actions.appendChild(input);
+  }
+  chatSession.attachmentsEl.before(card);
+}
+
+let pendingApprovalId: string | null = null;
+
+function showPairingApproval(pairingId: string, deviceName: string): void {
+  const modal = document.getElementById("pairing-modal")!;
+  pendingApprovalId = pairingId;
+  document.getElementById("pairing-approval-text")!.textContent =
+    `"${deviceName}" entered the code. Allow it to access this computer?`;
+  // Least access unless the user picks more
+  (document.getElementById("pairing-approval-scope") as HTMLSelectElement).value = "read_only";
+  document.getElementById("pairing-approval")!.hidden = false;
+  modal.classList.add("visible");
+}
+
+async function decidePairing(approve: boolean): Promise<void> {
+  if (!pendingApprovalId) return;
+  const pairingId = pendingApprovalId;
+  try {
+    if (approve) {
+      const scope = (document.getElementById("pairing-approval-scope") as HTMLSelectElement).value;
+      await invoke("approve_pairing", { pairingId, scope });
+    } else {
+      await invoke("deny_pairing", { pairingId });
+    }
+  } catch (err) {
+    alert(`${err}`);
+  }
+  // On approval the modal closes when the device collects its token (device-paired)
+  if (!approve) {
+    hidePairingModal();
+  } else {
+    document.getElementById("pairing-approval")!.hidden = true;
+  }
+}
+
+async function showPairingQr(): Promise<void> {
+  try {
+    const qr = await invoke<{ code: string; svg: string }>("generate_pairing_qr");
+    showPairingModal(qr.code, undefined, qr.svg);
+  } catch (err) {
+    console.error("Failed to generate pairing QR code:", err);
+    alert(`Couldn't create a pairing QR code: ${err}`);
+  }
+}
+
 function hidePairingModal(): void {
   const modal = document.getElementById("pairing-modal")!;
   modal.classList.remove("visible");
+  pendingApprovalId = null;
 
   if (pairingExpiryInterval) {
     clearInterval(pairingExpiryInterval);